@@ -0,0 +1,102 @@
+//! Reading ABI encoded data and matching text signatures to their hashes.
+//!
+//! A function is identified in calldata by a 4 byte selector and an event in
+//! a log by a 32 byte topic, both taken from the keccak hash of the text
+//! signature (e.g., "Transfer(address,address,uint256)"). Signatures from
+//! outside sources are checked by hashing them again, as different
+//! signatures can share a selector.
+use ethereum_types::{H160, H256, U256};
+use tiny_keccak::{Hasher, Keccak};
+
+/// Keccak-256 hash of some bytes.
+pub fn keccak256(bytes: &[u8]) -> [u8; 32] {
+    let mut hash = [0u8; 32];
+    let mut keccak = Keccak::v256();
+    keccak.update(bytes);
+    keccak.finalize(&mut hash);
+    hash
+}
+
+/// Keccak hash of an event signature, as found in the first log topic.
+pub fn topic_of(signature: &str) -> H256 {
+    H256::from(keccak256(signature.as_bytes()))
+}
+
+/// First four bytes of the hash of a function signature.
+pub fn selector_of(signature: &str) -> [u8; 4] {
+    let mut selector = [0; 4];
+    selector.copy_from_slice(&topic_of(signature).as_bytes()[..4]);
+    selector
+}
+
+/// Whether a text signature hashes to a selector or topic (hex, with or
+/// without "0x").
+///
+/// Anything shorter than a selector never matches.
+pub fn matches_signature(signature: &str, hash: &str) -> bool {
+    let Ok(hash) = hex::decode(hash.trim_start_matches("0x")) else {
+        return false;
+    };
+    hash.len() >= 4 && topic_of(signature).as_bytes().starts_with(&hash)
+}
+
+/// Reads the nth 32 byte word of ABI encoded data as an address.
+pub fn word_address(data: &[u8], n: usize) -> Option<H160> {
+    data.get(n * 32 + 12..(n + 1) * 32).map(H160::from_slice)
+}
+
+/// Reads an address from a 32 byte topic (left padded).
+pub fn topic_address(topic: &H256) -> H160 {
+    H160::from_slice(&topic.as_bytes()[12..])
+}
+
+/// An address in a left padded 32 byte word (e.g., an indexed address).
+///
+/// Small numbers also look like this, so the first four bytes of the address
+/// must not all be zero.
+pub fn padded_address(word: &[u8]) -> Option<H160> {
+    if word.len() != 32 {
+        return None;
+    }
+    let (padding, address) = word.split_at(12);
+    let plausible = padding.iter().all(|b| *b == 0) && address[..4].iter().any(|b| *b != 0);
+    plausible.then(|| H160::from_slice(address))
+}
+
+/// Reads the nth 32 byte word of log data as an integer.
+pub fn data_word(data: &[u8], n: usize) -> Option<U256> {
+    data.get(n * 32..(n + 1) * 32).map(U256::from_big_endian)
+}
+
+/// Reads the nth argument of ABI encoded data as a dynamic byte array.
+pub fn dynamic_bytes(data: &[u8], n: usize) -> Option<&[u8]> {
+    let offset = data_word(data, n)?;
+    if offset > U256::from(data.len()) {
+        return None;
+    }
+    let offset = offset.as_usize();
+    let length = U256::from_big_endian(data.get(offset..offset + 32)?);
+    if length > U256::from(data.len()) {
+        return None;
+    }
+    data.get(offset + 32..offset + 32 + length.as_usize())
+}
+
+#[test]
+fn signature_matching() {
+    let transfer = "Transfer(address,address,uint256)";
+    let topic = "0xddf252ad1be2c89b69c2b068fc378daa952ba7f163c4a11628f55a4df523b3ef";
+    assert_eq!(format!("{:?}", topic_of(transfer)), topic);
+    assert!(matches_signature(transfer, topic));
+    assert!(matches_signature(transfer, "ddf252ad"));
+    assert!(!matches_signature(
+        "Approval(address,address,uint256)",
+        topic
+    ));
+    assert!(!matches_signature(transfer, "dd"));
+    assert!(!matches_signature(transfer, "0xnothex"));
+    assert_eq!(
+        selector_of("transfer(address,uint256)"),
+        [0xa9, 0x05, 0x9c, 0xbb]
+    );
+}
@@ -0,0 +1,362 @@
+//! Alerts in watch mode: rules checked against each new decoded transaction,
+//! and the notifiers that are told when one matches.
+//!
+//! Rules and notifiers are set in the settings file (see `settings`):
+//!
+//! ```toml
+//! # Any Approval event with Self as the owner (the first indexed topic).
+//! [[alerts.rules]]
+//! kind = "event"
+//! event = "Approval"
+//! self_topic = 1
+//! # Any transaction from Self that sends more than 1 ether.
+//! [[alerts.rules]]
+//! kind = "outbound_value"
+//! min_ether = "1"
+//! # Any call from Self to a contract without a label or nametag.
+//! [[alerts.rules]]
+//! kind = "unlabelled_contract"
+//!
+//! # Where alerts go (stdout if none is set, see `notify`).
+//! [[alerts.notifiers]]
+//! kind = "desktop"
+//! ```
+use std::{collections::BTreeMap, fmt::Display};
+
+use anyhow::{anyhow, bail, Result};
+use log::warn;
+use serde::{Deserialize, Serialize};
+use web3::types::{H160, H256, U256};
+
+use crate::{
+    data::{LoggedEvent, TxInfo},
+    notify::NotifierKind,
+    semantics::ether_amount,
+};
+
+/// Decimals of ether.
+const ETHER_DECIMALS: usize = 18;
+
+/// Rules and notifiers of the settings file.
+#[derive(Debug, Default, Clone, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct AlertSettings {
+    pub rules: Vec<AlertRule>,
+    pub notifiers: Vec<NotifierKind>,
+}
+
+/// What makes a new transaction worth an alert.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum AlertRule {
+    /// An event with this name (e.g., "Approval") or text signature, with
+    /// Self as an indexed topic (1 to 3) if set.
+    Event {
+        event: String,
+        self_topic: Option<usize>,
+    },
+    /// A transaction from Self that sends more than this much ether (e.g.,
+    /// "0.5").
+    OutboundValue { min_ether: String },
+    /// A call from Self to a contract with no label or nametag. A
+    /// transaction with calldata that is not a text message is taken to be
+    /// a call to a contract.
+    UnlabelledContract,
+}
+
+/// A rule that matched a transaction.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct Alert {
+    /// The rule, as text.
+    pub rule: String,
+    pub block: u32,
+    pub index: u32,
+    pub hash: Option<H256>,
+    /// What matched (e.g., the event, or the contract called).
+    pub detail: String,
+    /// Values for message templates (see `notify`): the event name, contract
+    /// and decoded arguments, or the recipient and value sent.
+    pub fields: BTreeMap<String, String>,
+}
+
+/// Checked rules, with the notifiers to tell.
+#[derive(Debug, Default, Clone)]
+pub struct AlertRules {
+    rules: Vec<AlertRule>,
+    /// Minimum ether of each rule, in wei, if it has one.
+    thresholds: Vec<Option<U256>>,
+    notifiers: Vec<NotifierKind>,
+}
+
+impl AlertRules {
+    /// Checks the rules and notifiers of the settings (e.g., the amounts and
+    /// URLs). Without a notifier, alerts are printed.
+    pub fn new(settings: &AlertSettings) -> Result<Self> {
+        let mut thresholds = vec![];
+        for rule in &settings.rules {
+            thresholds.push(match rule {
+                AlertRule::OutboundValue { min_ether } => Some(parse_ether(min_ether)?),
+                AlertRule::Event {
+                    self_topic: Some(position),
+                    ..
+                } if !(1..=3).contains(position) => {
+                    bail!("Alert rule self_topic must be 1, 2 or 3, not {}", position)
+                }
+                _ => None,
+            });
+        }
+        for notifier in &settings.notifiers {
+            notifier.notifier()?;
+        }
+        let notifiers = match settings.notifiers.is_empty() {
+            true => vec![NotifierKind::Stdout],
+            false => settings.notifiers.clone(),
+        };
+        Ok(AlertRules {
+            rules: settings.rules.clone(),
+            thresholds,
+            notifiers,
+        })
+    }
+    pub fn is_empty(&self) -> bool {
+        self.rules.is_empty()
+    }
+    /// The alerts for a transaction of the owner, with the labels and
+    /// nametags of each address from `names`.
+    pub fn check(
+        &self,
+        tx: &TxInfo,
+        owner: &H160,
+        names: impl Fn(&H160) -> Vec<String>,
+    ) -> Vec<Alert> {
+        let mut alerts = vec![];
+        let desc = tx.description.as_ref();
+        let sent = desc.is_some_and(|d| d.from == Some(*owner));
+        let owner_topic = H256::from(*owner);
+        let address = |address: &H160| format!("0x{}", hex::encode(address));
+        for (rule, threshold) in self.rules.iter().zip(&self.thresholds) {
+            let matched = match rule {
+                AlertRule::Event { event, self_topic } => tx
+                    .events
+                    .iter()
+                    .flatten()
+                    .filter(|e| e.name.as_deref().is_some_and(|n| event_matches(n, event)))
+                    .find(|e| self_topic.is_none_or(|p| e.raw.topics.get(p) == Some(&owner_topic)))
+                    .map(|e| (e.to_string(), event_fields(e))),
+                AlertRule::OutboundValue { .. } => desc
+                    .filter(|d| sent && threshold.is_some_and(|min| d.value > min))
+                    .map(|d| {
+                        let value = ether_amount(d.value).to_string();
+                        let mut fields = BTreeMap::from([(String::from("value"), value.clone())]);
+                        if let Some(to) = &d.to {
+                            fields.insert(String::from("to"), address(to));
+                        }
+                        (format!("{} sent", value), fields)
+                    }),
+                AlertRule::UnlabelledContract => desc
+                    .filter(|d| sent && !d.input.0.is_empty() && tx.message.is_none())
+                    .and_then(|d| d.to)
+                    .filter(|to| names(to).is_empty())
+                    .map(|to| {
+                        let fields = BTreeMap::from([(String::from("to"), address(&to))]);
+                        (format!("Called {}", address(&to)), fields)
+                    }),
+            };
+            if let Some((detail, fields)) = matched {
+                alerts.push(Alert {
+                    rule: rule.to_string(),
+                    block: tx.location.block,
+                    index: tx.location.index,
+                    hash: desc.map(|d| d.hash),
+                    detail,
+                    fields,
+                });
+            }
+        }
+        alerts
+    }
+    /// Tells each notifier of an alert. Failures are logged.
+    pub async fn notify(&self, alert: &Alert) {
+        for kind in &self.notifiers {
+            let result = match kind.notifier() {
+                Ok(notifier) => notifier.notify(alert).await,
+                Err(e) => Err(e),
+            };
+            if let Err(e) = result {
+                warn!("Could not send alert to {:?} ({:#})", kind, e);
+            }
+        }
+    }
+}
+
+impl Alert {
+    /// A value for a message template: "rule", "block", "index", "hash",
+    /// "detail" or one of the fields.
+    pub fn field(&self, name: &str) -> Option<String> {
+        match name {
+            "rule" => Some(self.rule.clone()),
+            "block" => Some(self.block.to_string()),
+            "index" => Some(self.index.to_string()),
+            "hash" => self.hash.map(|h| format!("0x{}", hex::encode(h))),
+            "detail" => Some(self.detail.clone()),
+            _ => self.fields.get(name).cloned(),
+        }
+    }
+}
+
+/// The name and contract of an event, and its decoded arguments by name and
+/// by position ("arg0", "arg1", ...).
+fn event_fields(event: &LoggedEvent) -> BTreeMap<String, String> {
+    let mut fields = BTreeMap::new();
+    if let Some(name) = &event.name {
+        let short = name.split('(').next().unwrap_or(name);
+        fields.insert(String::from("event"), short.to_string());
+    }
+    let contract = format!("0x{}", hex::encode(event.raw.address));
+    fields.insert(String::from("contract"), contract);
+    for (i, param) in event
+        .decoded()
+        .into_iter()
+        .flat_map(|d| d.params)
+        .enumerate()
+    {
+        if !param.name.is_empty() {
+            fields.insert(param.name.clone(), param.value.clone());
+        }
+        fields.insert(format!("arg{}", i), param.value);
+    }
+    fields
+}
+
+/// Whether an event name (e.g., "Approval(address,address,uint256)")
+/// is that of a rule, given as a name or a full signature.
+fn event_matches(name: &str, rule: &str) -> bool {
+    match rule.contains('(') {
+        true => name == rule,
+        false => name.split('(').next() == Some(rule),
+    }
+}
+
+/// An amount of ether as wei, e.g., "1.5".
+fn parse_ether(text: &str) -> Result<U256> {
+    let invalid = || anyhow!("Invalid ether amount {:?}", text);
+    let (whole, fraction) = text.trim().split_once('.').unwrap_or((text.trim(), ""));
+    let digits = |s: &str| s.chars().all(|c| c.is_ascii_digit());
+    if fraction.len() > ETHER_DECIMALS || !digits(whole) || !digits(fraction) {
+        return Err(invalid());
+    }
+    if whole.is_empty() && fraction.is_empty() {
+        return Err(invalid());
+    }
+    let wei = format!("{}{:0<width$}", whole, fraction, width = ETHER_DECIMALS);
+    U256::from_str_radix(&wei, 10).map_err(|_| invalid())
+}
+
+impl Display for AlertRule {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            AlertRule::Event {
+                event,
+                self_topic: Some(position),
+            } => write!(f, "{} event with Self as topic {}", event, position),
+            AlertRule::Event { event, .. } => write!(f, "{} event", event),
+            AlertRule::OutboundValue { min_ether } => {
+                write!(f, "Transaction sending over {} ether", min_ether)
+            }
+            AlertRule::UnlabelledContract => write!(f, "Call to an unlabelled contract"),
+        }
+    }
+}
+
+impl Display for Alert {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Alert: {} (block {}", self.rule, self.block)?;
+        if let Some(hash) = self.hash {
+            write!(f, ", transaction 0x{}", hex::encode(hash))?;
+        }
+        write!(f, ")\n\t{}", self.detail)
+    }
+}
+
+#[test]
+fn rules_matched_against_transactions() {
+    use min_know::specs::address_appearance_index::AAIAppearanceTx;
+    use web3::types::{Bytes, Transaction};
+
+    use crate::semantics::test_event;
+
+    let settings: AlertSettings = toml::from_str(
+        r#"
+        [[rules]]
+        kind = "event"
+        event = "Approval"
+        self_topic = 1
+        [[rules]]
+        kind = "outbound_value"
+        min_ether = "1.5"
+        [[rules]]
+        kind = "unlabelled_contract"
+        "#,
+    )
+    .unwrap();
+    let rules = AlertRules::new(&settings).unwrap();
+    assert_eq!(rules.notifiers, [NotifierKind::Stdout]);
+    let owner = H160::repeat_byte(0xaa);
+    let (token, labelled) = (H160::repeat_byte(0xcc), H160::repeat_byte(0xdd));
+    let tx = |to: H160, ether: u64, input: &str| TxInfo {
+        location: AAIAppearanceTx {
+            block: 10,
+            index: 0,
+        },
+        description: Some(Transaction {
+            from: Some(owner),
+            to: Some(to),
+            value: U256::exp10(ETHER_DECIMALS) * ether,
+            input: Bytes(hex::decode(input).unwrap()),
+            ..Default::default()
+        }),
+        ..Default::default()
+    };
+    let names = |address: &H160| match *address == labelled {
+        true => vec![String::from("Uniswap")],
+        false => vec![],
+    };
+    let signature = "Approval(address,address,uint256)";
+    let mut approve = tx(token, 0, "095ea7b3");
+    let mut event = test_event(
+        &format!("0x{}", hex::encode(token)),
+        signature,
+        &[owner.into()],
+        vec![],
+    );
+    event.name = Some(String::from(signature));
+    approve.events = Some(vec![event]);
+
+    let matched = |tx: &TxInfo| -> Vec<String> {
+        rules
+            .check(tx, &owner, names)
+            .into_iter()
+            .map(|a| a.rule)
+            .collect()
+    };
+    assert_eq!(
+        matched(&approve),
+        [
+            "Approval event with Self as topic 1",
+            "Call to an unlabelled contract"
+        ]
+    );
+    assert_eq!(
+        matched(&tx(labelled, 2, "")),
+        ["Transaction sending over 1.5 ether"]
+    );
+    assert!(matched(&tx(labelled, 1, "095ea7b3")).is_empty());
+    let alert = &rules.check(&approve, &owner, names)[0];
+    assert_eq!(alert.field("event").as_deref(), Some("Approval"));
+    assert_eq!(alert.field("block").as_deref(), Some("10"));
+
+    assert_eq!(parse_ether("0.000000000000000001").unwrap(), U256::one());
+    assert!(parse_ether("1.2.3").is_err());
+    let bad_topic = "[[rules]]\nkind = \"event\"\nevent = \"Transfer\"\nself_topic = 4";
+    assert!(AlertRules::new(&toml::from_str(bad_topic).unwrap()).is_err());
+}
@@ -0,0 +1,489 @@
+//! Consistency checks across the whole history of an address.
+//!
+//! These flag things a user may want to look at more closely: transactions
+//! that seem to be missing from the index, and patterns common in spam and
+//! address poisoning. They are warnings, not errors.
+//!
+//! ## Lookalike addresses
+//! Wallets often shorten addresses to "0x1234…abcd". Poisoning attacks create
+//! an address with the same leading and trailing characters as a genuine
+//! counterparty and make it appear in the history, hoping that the user later
+//! copies it as a recipient. Counterparties are grouped by their shortened
+//! form, and in each group the genuine address is taken to be the owner, else
+//! the one the owner sent to most, else the one seen most often.
+//!
+//! ## ABIs that do not match the logs
+//! ABIs from Sourcify partial matches or from third parties may not belong to
+//! the deployed bytecode. Every event signature seen from a contract is
+//! checked against the events declared in its ABI, and an ABI that does not
+//! declare some of them is flagged. Full matches are not checked, as events
+//! that a proxy forwards from its implementation would be flagged wrongly.
+use std::{
+    collections::{BTreeMap, BTreeSet, HashMap, HashSet},
+    fmt::Display,
+};
+
+use min_know::specs::address_appearance_index::AAIAppearanceTx;
+use web3::types::{H160, H256, U256};
+
+use crate::{
+    abi::{data_word, topic_address, topic_of},
+    chains::MAINNET,
+    data::{TxInfo, VerificationStatus},
+    decode::event_signatures,
+    semantics::{ether_amount, short_address, TRANSFER},
+};
+
+/// Incoming ether below this (0.0001 ether) is treated as dust.
+const DUST_WEI: u64 = 100_000_000_000_000;
+
+/// Number of leading and of trailing hex characters compared for lookalikes.
+const LOOKALIKE_CHARS: usize = 4;
+
+/// Something unusual in a history.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Anomaly {
+    /// Nonces of transactions sent by the address skip these values (inclusive),
+    /// so those transactions are missing from the index.
+    NonceGap {
+        chain: String,
+        first: U256,
+        last: U256,
+    },
+    /// A zero value transaction from the address to itself (often used to
+    /// cancel a pending transaction).
+    SelfTransfer { location: AAIAppearanceTx },
+    /// A tiny amount of ether sent to the address by someone else.
+    Dust {
+        location: AAIAppearanceTx,
+        from: H160,
+        value: U256,
+    },
+    /// A token transfer out of the address that the address did not send.
+    ///
+    /// Zero value transfers like this are used to plant a lookalike address
+    /// in the history, hoping that it is later copied as a recipient.
+    ZeroValueTransferFrom {
+        location: AAIAppearanceTx,
+        token: H160,
+        to: H160,
+    },
+    /// A counterparty that shares its leading and trailing characters with
+    /// a genuine counterparty (or the address itself).
+    Lookalike {
+        address: H160,
+        resembles: H160,
+        /// Where the lookalike first appears.
+        location: AAIAppearanceTx,
+    },
+    /// A contract whose ABI does not declare events it has emitted, so the
+    /// ABI may be wrong.
+    UnexplainedEvents {
+        contract: H160,
+        verification: VerificationStatus,
+        /// Signature topics seen from the contract but missing from the ABI.
+        topics: Vec<H256>,
+    },
+}
+
+/// How an address appears in the history.
+#[derive(Debug, Default)]
+struct Contact {
+    /// Transactions or token transfers sent to it by the owner.
+    sent_to: usize,
+    /// Appearances as sender or recipient.
+    seen: usize,
+    first_seen: Option<AAIAppearanceTx>,
+}
+
+/// Checks the history of an address for anomalies.
+///
+/// Only transactions with data are checked.
+pub fn detect(owner: &H160, transactions: &[TxInfo]) -> Vec<Anomaly> {
+    let mut anomalies = nonce_gaps(owner, transactions);
+    anomalies.extend(lookalikes(owner, transactions));
+    anomalies.extend(unexplained_events(transactions));
+    let transfer = topic_of(TRANSFER);
+    for tx in transactions {
+        let Some(desc) = &tx.description else {
+            continue;
+        };
+        let location = tx.location.clone();
+        let sent = desc.from == Some(*owner);
+        if sent && desc.to == Some(*owner) && desc.value.is_zero() {
+            anomalies.push(Anomaly::SelfTransfer { location });
+            continue;
+        }
+        if !sent
+            && desc.to == Some(*owner)
+            && !desc.value.is_zero()
+            && desc.value < U256::from(DUST_WEI)
+        {
+            anomalies.push(Anomaly::Dust {
+                location: location.clone(),
+                from: desc.from.unwrap_or_default(),
+                value: desc.value,
+            });
+        }
+        if sent {
+            continue;
+        }
+        for event in tx.events.iter().flatten() {
+            let topics = &event.raw.topics;
+            if topics.len() != 3 || topics[0] != transfer {
+                continue;
+            }
+            let zero = data_word(&event.raw.data.0, 0).is_some_and(|v| v.is_zero());
+            if zero && topic_address(&topics[1]) == *owner {
+                anomalies.push(Anomaly::ZeroValueTransferFrom {
+                    location: location.clone(),
+                    token: event.raw.address,
+                    to: topic_address(&topics[2]),
+                });
+            }
+        }
+    }
+    anomalies
+}
+
+/// Finds nonces missing between the lowest (expected to be 0) and highest
+/// nonce of transactions sent by the address.
+///
+/// Only checked if data was fetched for every transaction. Each chain has its
+/// own nonces.
+fn nonce_gaps(owner: &H160, transactions: &[TxInfo]) -> Vec<Anomaly> {
+    if transactions.iter().any(|tx| tx.description.is_none()) {
+        return vec![];
+    }
+    let mut chains: BTreeMap<&str, BTreeSet<U256>> = BTreeMap::new();
+    for tx in transactions {
+        let Some(desc) = tx.description.as_ref().filter(|d| d.from == Some(*owner)) else {
+            continue;
+        };
+        chains.entry(&tx.chain).or_default().insert(desc.nonce);
+    }
+    let mut gaps = vec![];
+    for (chain, nonces) in chains {
+        let mut expected = U256::zero();
+        for nonce in nonces {
+            if nonce > expected {
+                gaps.push(Anomaly::NonceGap {
+                    chain: chain.to_string(),
+                    first: expected,
+                    last: nonce - 1,
+                });
+            }
+            expected = nonce + 1;
+        }
+    }
+    gaps
+}
+
+/// Finds counterparties that look like another counterparty when shortened.
+fn lookalikes(owner: &H160, transactions: &[TxInfo]) -> Vec<Anomaly> {
+    let transfer = topic_of(TRANSFER);
+    let mut contacts: HashMap<H160, Contact> = HashMap::new();
+    let mut note = |from: Option<H160>, to: Option<H160>, location: &AAIAppearanceTx| {
+        for address in [from, to].into_iter().flatten() {
+            let contact = contacts.entry(address).or_default();
+            contact.seen += 1;
+            contact.first_seen.get_or_insert_with(|| location.clone());
+        }
+        if let (Some(from), Some(to)) = (from, to) {
+            if from == *owner {
+                contacts.entry(to).or_default().sent_to += 1;
+            }
+        }
+    };
+    for tx in transactions {
+        if let Some(desc) = &tx.description {
+            note(desc.from, desc.to, &tx.location);
+        }
+        for event in tx.events.iter().flatten() {
+            let topics = &event.raw.topics;
+            if topics.len() >= 3 && topics[0] == transfer {
+                let (from, to) = (topic_address(&topics[1]), topic_address(&topics[2]));
+                note(Some(from), Some(to), &tx.location);
+            }
+        }
+    }
+    let mut groups: HashMap<(String, String), Vec<(&H160, &Contact)>> = HashMap::new();
+    for (address, contact) in &contacts {
+        let a = hex::encode(address);
+        let key = (
+            a[..LOOKALIKE_CHARS].to_string(),
+            a[a.len() - LOOKALIKE_CHARS..].to_string(),
+        );
+        groups.entry(key).or_default().push((address, contact));
+    }
+    let mut found = vec![];
+    for mut group in groups.into_values().filter(|g| g.len() > 1) {
+        group.sort_by_key(|(address, c)| {
+            (
+                *address != owner,
+                std::cmp::Reverse(c.sent_to),
+                std::cmp::Reverse(c.seen),
+                c.first_seen.as_ref().map(|l| (l.block, l.index)),
+            )
+        });
+        let genuine = *group[0].0;
+        for (address, contact) in &group[1..] {
+            found.push(Anomaly::Lookalike {
+                address: **address,
+                resembles: genuine,
+                location: contact.first_seen.clone().unwrap_or_default(),
+            });
+        }
+    }
+    found.sort_by_key(|a| match a {
+        Anomaly::Lookalike { location, .. } => (location.block, location.index),
+        _ => (0, 0),
+    });
+    found
+}
+
+/// Finds contracts with an ABI that does not declare events they emitted.
+///
+/// ABIs that cannot be read as JSON are not checked.
+fn unexplained_events(transactions: &[TxInfo]) -> Vec<Anomaly> {
+    let mut checked: HashMap<H160, (VerificationStatus, Option<HashSet<H256>>)> = HashMap::new();
+    let mut unexplained: HashMap<H160, BTreeSet<H256>> = HashMap::new();
+    for event in transactions
+        .iter()
+        .flat_map(|tx| tx.events.iter().flatten())
+    {
+        let contract = &event.contract;
+        let (Some(abi), Some(topic)) = (&contract.abi, event.raw.topics.first()) else {
+            continue;
+        };
+        if contract.verification == VerificationStatus::SourcifyFullMatch {
+            continue;
+        }
+        let (_, declared) = checked.entry(event.raw.address).or_insert_with(|| {
+            let topics = event_signatures(abi).map(|s| s.iter().map(|s| topic_of(s)).collect());
+            (contract.verification, topics)
+        });
+        if declared.as_ref().is_some_and(|d| !d.contains(topic)) {
+            unexplained
+                .entry(event.raw.address)
+                .or_default()
+                .insert(*topic);
+        }
+    }
+    let mut found: Vec<Anomaly> = unexplained
+        .into_iter()
+        .map(|(contract, topics)| Anomaly::UnexplainedEvents {
+            contract,
+            verification: checked[&contract].0,
+            topics: topics.into_iter().collect(),
+        })
+        .collect();
+    found.sort_by_key(|a| match a {
+        Anomaly::UnexplainedEvents { contract, .. } => *contract,
+        _ => H160::zero(),
+    });
+    found
+}
+
+impl Display for Anomaly {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let at = |l: &AAIAppearanceTx| format!("block {} index {}", l.block, l.index);
+        match self {
+            Anomaly::NonceGap { chain, first, last } => {
+                match first == last {
+                    true => write!(f, "Sent transaction with nonce {} is missing", first)?,
+                    false => write!(f, "Sent transactions with nonces {}-{} are missing", first, last)?,
+                }
+                match chain.is_empty() || chain == MAINNET {
+                    true => Ok(()),
+                    false => write!(f, " on {}", chain),
+                }
+            }
+            Anomaly::SelfTransfer { location } => {
+                write!(f, "Zero value transfer to self at {}", at(location))
+            }
+            Anomaly::Dust {
+                location,
+                from,
+                value,
+            } => write!(
+                f,
+                "Dust ({} wei, {}) received from {} at {}",
+                value,
+                ether_amount(*value),
+                short_address(from),
+                at(location)
+            ),
+            Anomaly::ZeroValueTransferFrom {
+                location,
+                token,
+                to,
+            } => write!(
+                f,
+                "Possible address poisoning: zero value {} transfer to {} sent by someone else at {}",
+                short_address(token),
+                short_address(to),
+                at(location)
+            ),
+            Anomaly::Lookalike {
+                address,
+                resembles,
+                location,
+            } => write!(
+                f,
+                "Lookalike address 0x{} resembles 0x{} (first seen at {})",
+                hex::encode(address),
+                hex::encode(resembles),
+                at(location)
+            ),
+            Anomaly::UnexplainedEvents {
+                contract,
+                verification,
+                topics,
+            } => write!(
+                f,
+                "Possibly wrong ABI for 0x{} ({}): {} emitted event signature(s) not in the ABI",
+                hex::encode(contract),
+                verification,
+                topics.len()
+            ),
+        }
+    }
+}
+
+#[test]
+fn nonce_gaps_and_poisoning() {
+    use web3::types::Transaction;
+
+    use crate::semantics::test_event;
+
+    let owner = H160::repeat_byte(0xaa);
+    let sent = |nonce: u64| TxInfo {
+        description: Some(Transaction {
+            from: Some(owner),
+            to: Some(H160::repeat_byte(0xbb)),
+            nonce: nonce.into(),
+            ..Default::default()
+        }),
+        ..Default::default()
+    };
+    let mut padded_owner = [0; 32];
+    padded_owner[12..].copy_from_slice(owner.as_bytes());
+    let mut padded_fake = [0; 32];
+    padded_fake[12..].copy_from_slice(H160::repeat_byte(0xbe).as_bytes());
+    let poisoned = TxInfo {
+        location: AAIAppearanceTx { block: 9, index: 1 },
+        description: Some(Transaction {
+            from: Some(H160::repeat_byte(0xcc)),
+            to: Some(H160::repeat_byte(0xdd)),
+            ..Default::default()
+        }),
+        events: Some(vec![test_event(
+            &"dd".repeat(20),
+            TRANSFER,
+            &[padded_owner.into(), padded_fake.into()],
+            vec![0; 32],
+        )]),
+        ..Default::default()
+    };
+
+    let anomalies = detect(&owner, &[sent(0), sent(1), sent(4), sent(6), poisoned]);
+    assert_eq!(
+        anomalies[..2],
+        [
+            Anomaly::NonceGap {
+                chain: String::new(),
+                first: 2.into(),
+                last: 3.into()
+            },
+            Anomaly::NonceGap {
+                chain: String::new(),
+                first: 5.into(),
+                last: 5.into()
+            },
+        ]
+    );
+    assert!(matches!(
+        anomalies[2],
+        Anomaly::ZeroValueTransferFrom { to, .. } if to == H160::repeat_byte(0xbe)
+    ));
+    assert_eq!(anomalies.len(), 3);
+}
+
+#[test]
+fn lookalike_counterparty() {
+    use web3::types::Transaction;
+
+    use crate::semantics::test_event;
+
+    let owner = H160::repeat_byte(0xaa);
+    let genuine: H160 = format!("1234{}abcd", "00".repeat(16)).parse().unwrap();
+    let fake: H160 = format!("1234{}abcd", "ff".repeat(16)).parse().unwrap();
+    let sent = TxInfo {
+        description: Some(Transaction {
+            from: Some(owner),
+            to: Some(genuine),
+            ..Default::default()
+        }),
+        ..Default::default()
+    };
+    let mut padded_owner = [0; 32];
+    padded_owner[12..].copy_from_slice(owner.as_bytes());
+    let mut padded_fake = [0; 32];
+    padded_fake[12..].copy_from_slice(fake.as_bytes());
+    let poisoned = TxInfo {
+        location: AAIAppearanceTx { block: 9, index: 1 },
+        events: Some(vec![test_event(
+            &"dd".repeat(20),
+            TRANSFER,
+            &[padded_owner.into(), padded_fake.into()],
+            vec![0; 32],
+        )]),
+        ..Default::default()
+    };
+    assert_eq!(
+        lookalikes(&owner, &[sent.clone(), sent, poisoned]),
+        vec![Anomaly::Lookalike {
+            address: fake,
+            resembles: genuine,
+            location: AAIAppearanceTx { block: 9, index: 1 },
+        }]
+    );
+}
+
+#[test]
+fn abi_missing_emitted_events() {
+    use crate::semantics::{test_event, APPROVAL};
+
+    let abi = r#"[{"type":"event","name":"Transfer","inputs":[
+        {"type":"address","indexed":true},{"type":"address","indexed":true},
+        {"type":"uint256","indexed":false}]}]"#;
+    let event = |signature: &str, verification| {
+        let mut e = test_event(&"11".repeat(20), signature, &[], vec![]);
+        e.contract.abi = Some(abi.to_string());
+        e.contract.verification = verification;
+        e
+    };
+    let partial = VerificationStatus::SourcifyPartialMatch;
+    let tx = TxInfo {
+        events: Some(vec![
+            event(TRANSFER, partial),
+            event(APPROVAL, partial),
+            event(APPROVAL, partial),
+        ]),
+        ..Default::default()
+    };
+    assert_eq!(
+        unexplained_events(&[tx]),
+        vec![Anomaly::UnexplainedEvents {
+            contract: H160::repeat_byte(0x11),
+            verification: partial,
+            topics: vec![topic_of(APPROVAL)],
+        }]
+    );
+    let full = TxInfo {
+        events: Some(vec![event(APPROVAL, VerificationStatus::SourcifyFullMatch)]),
+        ..Default::default()
+    };
+    assert!(unexplained_events(&[full]).is_empty());
+}
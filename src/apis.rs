@@ -10,20 +10,79 @@ Some ideas for both would be to have sourcify and 4byte both publish
 annual immutable "editions" where volumes of their data could
 be downloaded and pinned more readily, without CIDs changing. This
 might improve data availability on IPFS by allowing more participants.
+
+Each request reveals what is being looked up to the host. Requests are
+tallied as they are made (see `usage`), so that a run can report which
+hosts were contacted and what they were asked.
 */
-use std::str::FromStr;
+use std::{
+    collections::BTreeMap,
+    fmt::Display,
+    fs,
+    io::ErrorKind,
+    str::FromStr,
+    sync::{Mutex, OnceLock, PoisonError},
+};
 
-use anyhow::{bail, Result};
-use reqwest::{header::CONTENT_TYPE, StatusCode, Url};
+use anyhow::{anyhow, bail, Context, Result};
+use log::warn;
+use reqwest::{header::CONTENT_TYPE, Certificate, Client, Proxy, StatusCode, Url};
 use serde::{Deserialize, Serialize};
 use serde_json::Value;
 use web3::types::H160;
 
-use crate::parsing::{as_checksummed, summary_of_abi_from_json};
+use crate::{
+    abi::matches_signature,
+    chains::MAINNET_CHAIN_ID,
+    data::VerificationStatus,
+    parsing::as_checksummed,
+    settings::{HttpSettings, SourcifySettings},
+};
 
+/// Event signatures at 4byte.directory, by topic.
 const FOURBYTE: &str = "https://www.4byte.directory/api/v1/event-signatures/";
-const SOURCIFY_FULL: &str = "https://repo.sourcify.dev/contracts/full_match/1/";
-const SOURCIFY_PARTIAL: &str = "https://repo.sourcify.dev/contracts/partial_match/1/";
+/// Function signatures at 4byte.directory, by selector.
+const FOURBYTE_FUNCTIONS: &str = "https://www.4byte.directory/api/v1/signatures/";
+/// The public Sourcify repository (the "contracts" directory).
+pub const SOURCIFY_REPOSITORY: &str = "https://repo.sourcify.dev/contracts/";
+/// SOCKS port of a local Tor daemon. Host names are resolved through Tor.
+pub const TOR_PROXY: &str = "socks5h://127.0.0.1:9050";
+
+/// A Sourcify repository: the public one, one run locally or a pinned
+/// snapshot (e.g., through an IPFS gateway, or a directory as a file:// URL).
+///
+/// Metadata is at "{full_match|partial_match}/{chain id}/{address}/metadata.json".
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SourcifyRepo {
+    /// URL of the directory holding full_match and partial_match.
+    pub base: Url,
+    /// Chain segment of the paths.
+    pub chain_id: u64,
+}
+
+/// Client shared by the requests to the APIs (see `configure`).
+static CLIENT: OnceLock<Client> = OnceLock::new();
+
+/// Requests made to the APIs during the run.
+static USAGE: Mutex<ApiUsage> = Mutex::new(ApiUsage {
+    hosts: BTreeMap::new(),
+});
+
+/// Requests made to external APIs, by host.
+#[derive(Debug, Default, Clone, PartialEq, Serialize)]
+pub struct ApiUsage {
+    pub hosts: BTreeMap<String, HostUsage>,
+}
+
+/// Requests made to one host.
+#[derive(Debug, Default, Clone, PartialEq, Serialize)]
+pub struct HostUsage {
+    pub requests: u32,
+    /// Bytes of the response bodies.
+    pub bytes: u64,
+    /// What the responses told (e.g., a signature, or that there was none).
+    pub learned: Vec<String>,
+}
 
 #[derive(Serialize, Deserialize, Debug)]
 /// Response for a match query on event signatures at 4byte.directory.
@@ -44,6 +103,81 @@ pub struct FourByteResponse {
     bytes_signature: String,
 }
 
+/// Sets up the client for the requests to the APIs, e.g., with a proxy.
+///
+/// Must be called before the first request, which otherwise sets up a client
+/// with the defaults.
+pub fn configure(settings: &HttpSettings) -> Result<()> {
+    if CLIENT.set(http_client(settings)?).is_err() {
+        warn!("The API client was already in use, its settings are unchanged");
+    }
+    Ok(())
+}
+
+/// The client for requests to the APIs. Clones share connections.
+pub fn client() -> Client {
+    CLIENT.get_or_init(Client::new).clone()
+}
+
+/// The requests made to the APIs so far in this run.
+pub fn usage() -> ApiUsage {
+    USAGE.lock().unwrap_or_else(PoisonError::into_inner).clone()
+}
+
+/// Counts a request to an external host, with the bytes of its response (0
+/// if it failed). Local files are not counted.
+pub fn record_request(url: &Url, bytes: usize) {
+    let mut usage = USAGE.lock().unwrap_or_else(PoisonError::into_inner);
+    usage.record(url, bytes);
+}
+
+/// Notes what a request to an external host told.
+pub fn record_learned(url: &Url, learned: impl Into<String>) {
+    let mut usage = USAGE.lock().unwrap_or_else(PoisonError::into_inner);
+    usage.learned(url, learned.into());
+}
+
+fn http_client(settings: &HttpSettings) -> Result<Client> {
+    let mut builder = Client::builder();
+    match (proxy_url(settings)?, settings.no_proxy) {
+        (Some(_), true) => bail!("Set either a proxy (or tor) or no_proxy, not both"),
+        (Some(proxy), false) => {
+            let proxy = Proxy::all(proxy).with_context(|| format!("Invalid proxy {}", proxy))?;
+            builder = builder.proxy(proxy);
+        }
+        (None, true) => builder = builder.no_proxy(),
+        (None, false) => {}
+    }
+    for path in &settings.ca_certificates {
+        let pem = fs::read(path).with_context(|| format!("Could not read certificate {}", path))?;
+        let certificate =
+            Certificate::from_pem(&pem).with_context(|| format!("Invalid certificate {}", path))?;
+        builder = builder.add_root_certificate(certificate);
+    }
+    if settings.accept_invalid_certs {
+        warn!("Certificates of the APIs are not checked");
+        builder = builder.danger_accept_invalid_certs(true);
+    }
+    Ok(builder.build()?)
+}
+
+/// The proxy in the settings, or the Tor proxy if enabled.
+fn proxy_url(settings: &HttpSettings) -> Result<Option<&str>> {
+    let proxy = match (settings.proxy.as_deref(), settings.tor) {
+        (Some(_), true) => bail!("Set either a proxy or tor, not both"),
+        (None, true) => TOR_PROXY,
+        (Some(proxy), false) => proxy,
+        (None, false) => return Ok(None),
+    };
+    if proxy.starts_with("socks5://") {
+        warn!(
+            "Host names are resolved locally with {}, use socks5h:// to resolve them through the proxy",
+            proxy
+        );
+    }
+    Ok(Some(proxy))
+}
+
 /// Returns the first match from 4byte api for an event/topic hash.
 ///
 /// Example endpoint:
@@ -54,60 +188,279 @@ pub struct FourByteResponse {
 /// Each decoded candidate response is hashed and compared to the full 32 byte signature
 /// (present in the transaction log).
 pub async fn method_from_fourbyte_api(topic: &str) -> Result<Option<String>> {
-    let hex_sig = format!("0x{}", topic);
-    let url = Url::from_str(FOURBYTE)?;
-    let client = reqwest::Client::new();
-    let response: FourBytePage = client
-        .get(url)
-        .query(&[("hex_signature", hex_sig)])
+    fourbyte_lookup(FOURBYTE, topic).await
+}
+
+/// Returns the match from 4byte api for a 4 byte function selector.
+///
+/// Example endpoint:
+///
+/// https://www.4byte.directory/api/v1/signatures/?hex_signature=0xa9059cbb
+///
+/// ## Selector collisions
+/// Different signatures can share a selector. The one submitted first is
+/// used, as later ones are often made to collide on purpose.
+pub async fn function_from_fourbyte_api(selector: &str) -> Result<Option<String>> {
+    fourbyte_lookup(FOURBYTE_FUNCTIONS, selector).await
+}
+
+/// Looks up a hash at a 4byte.directory endpoint, returning the earliest
+/// signature that hashes to it.
+async fn fourbyte_lookup(endpoint: &str, hash: &str) -> Result<Option<String>> {
+    let hex_sig = format!("0x{}", hash);
+    let url = Url::from_str(endpoint)?;
+    let client = client();
+    let response = client
+        .get(url.clone())
+        .query(&[("hex_signature", &hex_sig)])
         .header(CONTENT_TYPE, "application/json")
         .send()
-        .await?
-        .json()
-        .await?;
+        .await;
+    let body = match response {
+        Ok(response) => response.bytes().await,
+        Err(e) => Err(e),
+    };
+    record_request(&url, body.as_ref().map_or(0, |b| b.len()));
+    let response: FourBytePage = serde_json::from_slice(&body?)?;
     // Hash to check each decoded response.
-    for r in response.results {
-        let target = hex::encode(topic);
-        let candidate_full_hash = r.hex_signature.trim_start_matches("0x");
-        if candidate_full_hash == target {
-            return Ok(Some(r.text_signature));
+    let matching = response
+        .results
+        .into_iter()
+        .filter(|r| matches_signature(&r.text_signature, hash))
+        .min_by_key(|r| r.id);
+    let learned = match &matching {
+        Some(r) => format!("{} is {}", hex_sig, r.text_signature),
+        None => format!("{} has no signature", hex_sig),
+    };
+    record_learned(&url, learned);
+    Ok(matching.map(|r| r.text_signature))
+}
+
+/// Returns the Sourcify metadata JSON for a given contract address.
+///
+/// The verification status records whether the match was full or partial.
+pub async fn metadata_from_sourcify_api(
+    address: &H160,
+    repo: &SourcifyRepo,
+) -> Result<Option<(Value, VerificationStatus)>> {
+    let statuses = [
+        VerificationStatus::SourcifyFullMatch,
+        // May not match on full
+        VerificationStatus::SourcifyPartialMatch,
+    ];
+    let contract = as_checksummed(address);
+    for status in statuses {
+        let url = repo.metadata_url(address, status)?;
+        if let Some(metadata) = fetch_json(&url).await? {
+            record_learned(&url, format!("{} is verified ({})", contract, status));
+            return Ok(Some((metadata, status)));
         }
     }
+    let url = repo.metadata_url(address, VerificationStatus::SourcifyFullMatch)?;
+    record_learned(&url, format!("{} is not verified", contract));
     Ok(None)
 }
 
-/// Returns the sourcify url target for a given contract address.
-pub async fn abi_from_sourcify_api(address: &H160) -> Result<Option<String>> {
-    let client = reqwest::Client::new();
-    let a = format!("{}/{}", as_checksummed(address), "metadata.json");
-
-    let url = Url::from_str(SOURCIFY_FULL)?.join(&a)?;
-    let response = client
-        .get(url)
+/// A JSON document, or None if there is none at the URL. file:// URLs are
+/// read from disk.
+async fn fetch_json(url: &Url) -> Result<Option<Value>> {
+    if url.scheme() == "file" {
+        let path = url
+            .to_file_path()
+            .map_err(|_| anyhow!("Invalid file URL {}", url))?;
+        return match fs::read(&path) {
+            Ok(bytes) => Ok(Some(serde_json::from_slice(&bytes)?)),
+            Err(e) if e.kind() == ErrorKind::NotFound => Ok(None),
+            Err(e) => Err(e).with_context(|| format!("Could not read {}", path.display())),
+        };
+    }
+    let response = client()
+        .get(url.clone())
         .header(CONTENT_TYPE, "application/json")
         .send()
         .await;
-    let Ok(r) = response else {bail!("The request failed for {}", a)};
-    if let StatusCode::OK = r.status() {
-        let v: Value = r.json().await?;
-        let contract_summary = summary_of_abi_from_json(v).unwrap();
-        return Ok(Some(contract_summary));
+    let Ok(r) = response else {
+        record_request(url, 0);
+        bail!("The request failed for {}", url)
+    };
+    let status = r.status();
+    let body = r.bytes().await;
+    record_request(url, body.as_ref().map_or(0, |b| b.len()));
+    match status {
+        StatusCode::OK => Ok(Some(serde_json::from_slice(&body?)?)),
+        _ => Ok(None),
     }
+}
 
-    // May not match on full
-    let url = Url::from_str(SOURCIFY_PARTIAL)?.join(&a)?;
-    let response = client
-        .get(url)
-        .header(CONTENT_TYPE, "application/json")
-        .send()
-        .await;
-    let Ok(r) = response else {bail!("The request failed for {}", a)};
-    if let StatusCode::OK = r.status() {
-        let v: Value = r.json().await?;
-        let contract_summary = summary_of_abi_from_json(v).unwrap();
-        Ok(Some(contract_summary))
-    } else {
-        // println!("Status code: {} for request for partial match", r.status());
-        Ok(None)
+impl ApiUsage {
+    fn record(&mut self, url: &Url, bytes: usize) {
+        if let Some(host) = external_host(url) {
+            let usage = self.hosts.entry(host).or_default();
+            usage.requests += 1;
+            usage.bytes += bytes as u64;
+        }
     }
+    fn learned(&mut self, url: &Url, learned: String) {
+        if let Some(host) = external_host(url) {
+            self.hosts.entry(host).or_default().learned.push(learned);
+        }
+    }
+    pub fn requests(&self) -> u32 {
+        self.hosts.values().map(|h| h.requests).sum()
+    }
+    pub fn bytes(&self) -> u64 {
+        self.hosts.values().map(|h| h.bytes).sum()
+    }
+}
+
+/// Host of a URL, unless it is a local file.
+fn external_host(url: &Url) -> Option<String> {
+    match url.scheme() {
+        "file" => None,
+        _ => url.host_str().map(String::from),
+    }
+}
+
+impl Display for ApiUsage {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "External APIs: {} requests to {} hosts, {} bytes received",
+            self.requests(),
+            self.hosts.len(),
+            self.bytes()
+        )?;
+        for (host, usage) in &self.hosts {
+            write!(
+                f,
+                "\n  {}: {} requests, {} bytes",
+                host, usage.requests, usage.bytes
+            )?;
+            for learned in &usage.learned {
+                write!(f, "\n    {}", learned)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+impl SourcifyRepo {
+    /// A repository at a URL (http(s) or file), for a chain.
+    pub fn new(base: &str, chain_id: u64) -> Result<Self> {
+        // Joined paths would replace the last segment without the slash.
+        let base = match base.ends_with('/') {
+            true => base.to_string(),
+            false => format!("{}/", base),
+        };
+        let base = Url::parse(&base).with_context(|| format!("Invalid Sourcify URL {}", base))?;
+        Ok(SourcifyRepo { base, chain_id })
+    }
+    /// The repository in the settings, otherwise the public one. The chain
+    /// id in the settings, if any, takes precedence.
+    pub fn from_settings(settings: &SourcifySettings, chain_id: u64) -> Result<Self> {
+        let base = settings
+            .repository
+            .as_deref()
+            .unwrap_or(SOURCIFY_REPOSITORY);
+        SourcifyRepo::new(base, settings.chain_id.unwrap_or(chain_id))
+    }
+    /// The same repository for another chain.
+    pub fn on_chain(&self, chain_id: u64) -> Self {
+        SourcifyRepo {
+            base: self.base.clone(),
+            chain_id,
+        }
+    }
+    /// Location of the metadata of a contract with a full or partial match.
+    pub fn metadata_url(&self, address: &H160, status: VerificationStatus) -> Result<Url> {
+        let matched = match status {
+            VerificationStatus::SourcifyPartialMatch => "partial_match",
+            _ => "full_match",
+        };
+        let path = format!(
+            "{}/{}/{}/metadata.json",
+            matched,
+            self.chain_id,
+            as_checksummed(address)
+        );
+        Ok(self.base.join(&path)?)
+    }
+}
+
+impl Default for SourcifyRepo {
+    fn default() -> Self {
+        SourcifyRepo {
+            base: Url::parse(SOURCIFY_REPOSITORY).expect("Valid URL"),
+            chain_id: MAINNET_CHAIN_ID,
+        }
+    }
+}
+
+#[tokio::test]
+async fn sourcify_repo_paths() {
+    let public = SourcifyRepo::default();
+    assert_eq!(public.base.as_str(), SOURCIFY_REPOSITORY);
+    assert_eq!(public.chain_id, MAINNET_CHAIN_ID);
+
+    let dir = std::env::temp_dir().join(format!("psr-sourcify-{}", std::process::id()));
+    let settings = SourcifySettings {
+        repository: Some(format!("file://{}", dir.display())),
+        chain_id: None,
+    };
+    let local = SourcifyRepo::from_settings(&settings, 10).unwrap();
+    assert_eq!(local.chain_id, 10);
+    assert!(local.base.as_str().ends_with('/'));
+    assert_eq!(local.on_chain(8453).chain_id, 8453);
+
+    let url = local.base.join("full_match/10/0xAb/metadata.json").unwrap();
+    assert_eq!(fetch_json(&url).await.unwrap(), None);
+    let path = url.to_file_path().unwrap();
+    fs::create_dir_all(path.parent().unwrap()).unwrap();
+    fs::write(&path, r#"{"output":{"abi":[]}}"#).unwrap();
+    let metadata = fetch_json(&url).await.unwrap().unwrap();
+    assert_eq!(metadata["output"]["abi"], serde_json::json!([]));
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn tor_or_socks_proxy() {
+    let mut settings = HttpSettings {
+        tor: true,
+        ..Default::default()
+    };
+    assert_eq!(proxy_url(&settings).unwrap(), Some(TOR_PROXY));
+    assert!(http_client(&settings).is_ok());
+    settings.proxy = Some(String::from("socks5h://127.0.0.1:1080"));
+    assert!(proxy_url(&settings).is_err());
+    settings.tor = false;
+    assert_eq!(
+        proxy_url(&settings).unwrap(),
+        Some("socks5h://127.0.0.1:1080")
+    );
+    assert!(http_client(&settings).is_ok());
+    assert_eq!(proxy_url(&HttpSettings::default()).unwrap(), None);
+}
+
+#[test]
+fn requests_tallied_by_host() {
+    let mut usage = ApiUsage::default();
+    let fourbyte = Url::from_str(FOURBYTE).unwrap();
+    usage.record(&fourbyte, 120);
+    usage.learned(
+        &fourbyte,
+        String::from("0xa9059cbb is transfer(address,uint256)"),
+    );
+    usage.record(&fourbyte, 0);
+    let local = Url::parse("file:///data/sourcify/contracts/").unwrap();
+    usage.record(&local, 500);
+    usage.learned(&local, String::from("not shown"));
+    assert_eq!(usage.requests(), 2);
+    assert_eq!(usage.bytes(), 120);
+    assert_eq!(usage.hosts.len(), 1);
+    assert_eq!(
+        usage.to_string(),
+        "External APIs: 2 requests to 1 hosts, 120 bytes received\n  \
+        www.4byte.directory: 2 requests, 120 bytes\n    \
+        0xa9059cbb is transfer(address,uint256)"
+    );
 }
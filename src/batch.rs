@@ -0,0 +1,448 @@
+//! Runs the pipeline for many addresses, e.g., a cohort of wallets for research.
+//!
+//! Addresses are read one per line from a file, or from standard input with
+//! "-". Blank lines and lines starting with `#` are skipped, and only the
+//! first field of a CSV line is read, so a column of addresses from another
+//! tool can be used as is.
+//!
+//! Appearances of every address are looked up in the index together (see
+//! `find_many`). The pipelines of several addresses then run at once, each
+//! in its own task so that a failure stays with its address. Tasks take
+//! turns with a few caches, so lookups resolved for one address are reused
+//! for later ones. Addresses that failed
+//! (e.g., a node was unreachable) are retried after the others finish.
+//! Histories are written to one file per address as they finish, or to one
+//! combined file at the end.
+use std::{
+    fmt::Display,
+    fs,
+    io::{self, Read},
+    path::{Path, PathBuf},
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc, Mutex,
+    },
+    time::Duration,
+};
+
+use anyhow::{anyhow, Context, Result};
+use clap::ValueEnum;
+use log::warn;
+use serde_json::{json, Value};
+use tokio::sync::Semaphore;
+
+use crate::{
+    cache::Cache,
+    chains::MAINNET,
+    export::{self, transactions_csv, transactions_json},
+    history::{AddressHistory, Config, Mode},
+    outcome::Outcome,
+    redact::Pseudonyms,
+    spam::SpamFilter,
+};
+
+/// Wait before retrying failed addresses, multiplied by the attempt.
+const RETRY_DELAY: Duration = Duration::from_secs(2);
+
+/// Format of the files written for a batch.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum BatchFormat {
+    /// Transactions with their events (see `export::to_json`).
+    #[default]
+    Json,
+    /// One row per transaction (see `export::to_csv`).
+    Csv,
+}
+
+/// Settings for a batch.
+#[derive(Debug, Clone)]
+pub struct BatchOptions {
+    pub format: BatchFormat,
+    /// Directory for one file per address.
+    pub out_dir: PathBuf,
+    /// One file for every address, in place of `out_dir`.
+    pub combined: Option<PathBuf>,
+    pub mode: Mode,
+    pub spam: SpamFilter,
+    /// Most addresses processed at once.
+    pub jobs: usize,
+    /// Most times an address that failed is run again.
+    pub retries: usize,
+}
+
+/// How the pipeline ended for one address.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AddressReport {
+    pub address: String,
+    pub outcome: Outcome,
+    pub transactions: usize,
+    /// Error that stopped the pipeline, if any.
+    pub error: Option<String>,
+    /// Number of times the pipeline was run.
+    pub attempts: usize,
+}
+
+/// How the pipeline ended for each address of a batch, in input order.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct BatchReport {
+    pub addresses: Vec<AddressReport>,
+}
+
+/// History of one address for a combined file.
+#[derive(Debug)]
+enum Output {
+    Json(Value),
+    Csv(String),
+}
+
+/// Result of the pipeline for one address.
+#[derive(Debug)]
+struct Finished {
+    report: AddressReport,
+    output: Option<Output>,
+    /// Whether running the pipeline again may succeed.
+    retryable: bool,
+}
+
+impl BatchReport {
+    /// Success if every address succeeded (see `Outcome::combine`).
+    pub fn outcome(&self) -> Outcome {
+        let outcomes: Vec<Outcome> = self.addresses.iter().map(|a| a.outcome).collect();
+        Outcome::combine(&outcomes)
+    }
+}
+
+/// Reads addresses from a file, or from standard input for "-".
+pub fn read_addresses(input: &Path) -> Result<Vec<String>> {
+    let text = match input == Path::new("-") {
+        true => {
+            let mut text = String::new();
+            io::stdin().read_to_string(&mut text)?;
+            text
+        }
+        false => fs::read_to_string(input)
+            .with_context(|| format!("Could not read addresses {}", input.display()))?,
+    };
+    Ok(parse_addresses(&text))
+}
+
+fn parse_addresses(text: &str) -> Vec<String> {
+    text.lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty() && !line.starts_with('#'))
+        .filter_map(|line| line.split([',', '\t', ' ']).next())
+        // A header row.
+        .filter(|field| !field.eq_ignore_ascii_case("address"))
+        .map(|field| field.to_string())
+        .collect()
+}
+
+/// Runs the pipelines of up to `jobs` addresses at once, writing each
+/// history as it finishes and printing progress.
+///
+/// Each pipeline has its own task, so an address that fails (or panics) is
+/// reported and the others continue. Addresses that failed are then run
+/// again, up to `retries` times. Lookups resolved during the batch are kept
+/// in the store.
+pub async fn run(
+    addresses: &[String],
+    config: Config,
+    options: &BatchOptions,
+) -> Result<BatchReport> {
+    if options.combined.is_none() {
+        fs::create_dir_all(&options.out_dir)?;
+    }
+    let mut cache = Cache::default();
+    if config.chain == MAINNET {
+        match config.store.resolved() {
+            Ok(resolved) => cache.add_resolved(resolved),
+            Err(e) => warn!("Could not reuse resolved lookups ({})", e),
+        }
+    }
+    let jobs = options.jobs.max(1);
+    let caches = Arc::new(Mutex::new(vec![cache; jobs]));
+    let options = Arc::new(options.clone());
+    let mut finished: Vec<Option<Finished>> = addresses.iter().map(|_| None).collect();
+    let mut pending: Vec<usize> = (0..addresses.len()).collect();
+    for attempt in 1.. {
+        let round: Vec<String> = pending.iter().map(|&i| addresses[i].clone()).collect();
+        let results = run_round(&round, &config, &options, &caches).await;
+        let mut retry = vec![];
+        for (i, mut result) in pending.into_iter().zip(results) {
+            result.report.attempts = attempt;
+            if attempt <= options.retries && result.retryable {
+                retry.push(i);
+            }
+            finished[i] = Some(result);
+        }
+        if retry.is_empty() || config.cancel.is_cancelled() {
+            break;
+        }
+        println!("Retrying {} failed addresses", retry.len());
+        tokio::time::sleep(RETRY_DELAY * attempt as u32).await;
+        pending = retry;
+    }
+
+    let finished: Vec<Finished> = finished.into_iter().flatten().collect();
+    if let Some(path) = &options.combined {
+        let outputs = finished.iter().filter_map(|f| f.output.as_ref());
+        match options.format {
+            BatchFormat::Json => {
+                let values: Vec<&Value> = outputs.filter_map(Output::json).collect();
+                serde_json::to_writer_pretty(fs::File::create(path)?, &values)?
+            }
+            BatchFormat::Csv => {
+                let rows: String = outputs.filter_map(Output::csv).collect();
+                fs::write(path, format!("{}\n{}", export::CSV_HEADER, rows))?
+            }
+        }
+    }
+    for cache in caches.lock().unwrap().iter() {
+        if let Err(e) = config.store.put_resolved(cache.resolved()) {
+            warn!("Could not keep resolved lookups ({})", e);
+        }
+    }
+    Ok(BatchReport {
+        addresses: finished.into_iter().map(|f| f.report).collect(),
+    })
+}
+
+/// Runs the pipelines for some addresses concurrently, with results in the
+/// same order.
+async fn run_round(
+    addresses: &[String],
+    config: &Config,
+    options: &Arc<BatchOptions>,
+    caches: &Arc<Mutex<Vec<Cache>>>,
+) -> Vec<Finished> {
+    let jobs = Arc::new(Semaphore::new(options.jobs.max(1)));
+    let done = Arc::new(AtomicUsize::new(0));
+    let total = addresses.len();
+    let histories = AddressHistory::of_addresses(addresses, config);
+    let tasks: Vec<_> = addresses
+        .iter()
+        .zip(histories)
+        .map(|(address, history)| {
+            let address = address.clone();
+            let (jobs, done) = (jobs.clone(), done.clone());
+            let (options, caches) = (options.clone(), caches.clone());
+            tokio::spawn(async move {
+                let _permit = jobs.acquire_owned().await;
+                // A cache is missing only if a task panicked while holding it.
+                let cache = caches.lock().unwrap().pop().unwrap_or_default();
+                let (finished, cache) = process(address, history, cache, &options).await;
+                caches.lock().unwrap().push(cache);
+                let n = done.fetch_add(1, Ordering::Relaxed) + 1;
+                println!("[{}/{}] {}", n, total, finished.report);
+                finished
+            })
+        })
+        .collect();
+    let mut results = vec![];
+    for (address, task) in addresses.iter().zip(tasks) {
+        let result = match task.await {
+            Ok(finished) => finished,
+            Err(e) => Finished {
+                report: AddressReport::failed(address, &anyhow!("Pipeline stopped ({})", e)),
+                output: None,
+                retryable: true,
+            },
+        };
+        results.push(result);
+    }
+    results
+}
+
+/// Runs the pipeline for one address using a cache, which is returned for
+/// the next address.
+async fn process(
+    address: String,
+    history: Result<AddressHistory>,
+    cache: Cache,
+    options: &BatchOptions,
+) -> (Finished, Cache) {
+    let mut history = match history {
+        Ok(history) => history,
+        Err(e) => {
+            // The address is invalid or missing from the index, every time.
+            let finished = Finished {
+                report: AddressReport::failed(&address, &e),
+                output: None,
+                retryable: false,
+            };
+            return (finished, cache);
+        }
+    };
+    history.cache = cache;
+    let result = history.run_from_appearances(options.mode).await.map(|_| ());
+    let cache = std::mem::take(&mut history.cache);
+    let result = result.and_then(|_| {
+        if options.spam == SpamFilter::Hide {
+            history.hide_spam();
+        }
+        write(&history, options)
+    });
+    let finished = match result {
+        Ok(output) => Finished {
+            report: AddressReport {
+                address,
+                outcome: Outcome::from_history(history.transactions.len(), &history.failures),
+                transactions: history.transactions.len(),
+                error: None,
+                attempts: 1,
+            },
+            output,
+            retryable: false,
+        },
+        Err(e) => {
+            let report = AddressReport::failed(&address, &e);
+            let retryable = matches!(report.outcome, Outcome::Failure | Outcome::RpcUnreachable);
+            Finished {
+                report,
+                output: None,
+                retryable,
+            }
+        }
+    };
+    (finished, cache)
+}
+
+/// Writes a history to its own file, or returns it for the combined file.
+fn write(history: &AddressHistory, options: &BatchOptions) -> Result<Option<Output>> {
+    let pseudonyms = Pseudonyms::default();
+    if options.combined.is_some() {
+        let output = match options.format {
+            BatchFormat::Json => Output::Json(json!({
+                "address": history.address,
+                "transactions": transactions_json(history, &pseudonyms),
+            })),
+            BatchFormat::Csv => Output::Csv(transactions_csv(history, &pseudonyms)),
+        };
+        return Ok(Some(output));
+    }
+    let path = options.out_dir.join(&history.address);
+    match options.format {
+        BatchFormat::Json => export::to_json(history, &path.with_extension("json"), &pseudonyms)?,
+        BatchFormat::Csv => export::to_csv(history, &path.with_extension("csv"), &pseudonyms)?,
+    }
+    Ok(None)
+}
+
+impl AddressReport {
+    fn failed(address: &str, error: &anyhow::Error) -> Self {
+        AddressReport {
+            address: address.to_string(),
+            outcome: Outcome::from_error(error),
+            transactions: 0,
+            error: Some(format!("{:#}", error)),
+            attempts: 1,
+        }
+    }
+}
+
+impl Output {
+    fn json(&self) -> Option<&Value> {
+        match self {
+            Output::Json(value) => Some(value),
+            Output::Csv(_) => None,
+        }
+    }
+    fn csv(&self) -> Option<&str> {
+        match self {
+            Output::Csv(rows) => Some(rows),
+            Output::Json(_) => None,
+        }
+    }
+}
+
+impl Display for AddressReport {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}: ", self.address)?;
+        match (&self.error, self.outcome) {
+            (Some(e), _) => write!(f, "failed ({})", e),
+            (None, Outcome::IndexMiss) => write!(f, "no transactions found"),
+            (None, Outcome::PartialSuccess) => {
+                write!(f, "{} transactions, some data missing", self.transactions)
+            }
+            (None, _) => write!(f, "{} transactions", self.transactions),
+        }
+    }
+}
+
+impl Display for BatchReport {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let count = |outcome: Outcome| {
+            self.addresses
+                .iter()
+                .filter(|a| a.outcome == outcome)
+                .count()
+        };
+        let failed: Vec<&AddressReport> = self
+            .addresses
+            .iter()
+            .filter(|a| a.error.is_some())
+            .collect();
+        let retried = self.addresses.iter().filter(|a| a.attempts > 1).count();
+        write!(
+            f,
+            "Processed {} addresses: {} succeeded, {} missing some data, {} without \
+            transactions, {} failed ({} retried)",
+            self.addresses.len(),
+            count(Outcome::Success),
+            count(Outcome::PartialSuccess),
+            self.addresses.len()
+                - failed.len()
+                - count(Outcome::Success)
+                - count(Outcome::PartialSuccess),
+            failed.len(),
+            retried
+        )?;
+        for report in failed {
+            write!(f, "\n{} (attempts: {})", report, report.attempts)?;
+        }
+        Ok(())
+    }
+}
+
+#[test]
+fn addresses_from_text() {
+    let text = "address,label\n\
+        0x00000000000000000000000000000000000000aa,friend\n\n\
+        # Exchanges\n  0x00000000000000000000000000000000000000bb\n";
+    assert_eq!(
+        parse_addresses(text),
+        vec![
+            "0x00000000000000000000000000000000000000aa",
+            "0x00000000000000000000000000000000000000bb"
+        ]
+    );
+}
+
+#[test]
+fn report_lists_failures() {
+    let report = |address: &str, outcome, error: Option<&str>, attempts| AddressReport {
+        address: address.to_string(),
+        outcome,
+        transactions: usize::from(error.is_none()),
+        error: error.map(String::from),
+        attempts,
+    };
+    let batch = BatchReport {
+        addresses: vec![
+            report("0xaa", Outcome::Success, None, 1),
+            report("0xbb", Outcome::IndexMiss, None, 1),
+            report(
+                "0xcc",
+                Outcome::RpcUnreachable,
+                Some("connection refused"),
+                3,
+            ),
+        ],
+    };
+    assert_eq!(batch.outcome(), Outcome::PartialSuccess);
+    assert_eq!(
+        batch.to_string(),
+        "Processed 3 addresses: 1 succeeded, 0 missing some data, 1 without transactions, \
+        1 failed (1 retried)\n0xcc: failed (connection refused) (attempts: 3)"
+    );
+}
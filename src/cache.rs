@@ -1,16 +1,28 @@
-use std::collections::HashMap;
+use std::collections::{BTreeMap, HashMap};
 
 use anyhow::Result;
-use heimdall::decompile::DecompileBuilder;
-use log::{debug, error, warn};
-use web3::types::H160;
+use log::{debug, warn};
+use serde::{Deserialize, Serialize};
+use web3::types::{H160, H256};
 
 use crate::{
-    apis::{abi_from_sourcify_api, method_from_fourbyte_api},
+    apis::{function_from_fourbyte_api, metadata_from_sourcify_api, method_from_fourbyte_api},
+    data::{DecompileStatus, Deployer, VerificationStatus, Warning, WarningKind},
+    decode::{CallShape, DecodedCall},
+    decompile::{DecompileFailure, DecompilePool, Finished},
     history::{address_nametags, sig_to_text, Config, Mode, VisitNote},
+    interfaces::{self, Erc},
+    parsing::abi_from_metadata,
+    provider::{Capability, Provider},
+    signatures::{common_signature, NameSource},
+    store::Resolved,
 };
 
-#[derive(Debug, Default, Clone, PartialEq)]
+/// ABI recorded for a decompiled contract.
+const DECOMPILED_ABI: &str = "TODO: Pull decompiled-ABI from file";
+
+#[derive(Debug, Default, Clone, PartialEq, Deserialize, Serialize)]
+#[serde(default)]
 /// A store of things that have been obtained externally, that may arise more than once.
 ///
 /// Each value has a bool
@@ -19,29 +31,113 @@ pub struct Cache {
     ///
     /// 4 byte signatures "abcd1234" -> "Withdraw()"
     pub signatures: HashMap<String, (VisitNote, String)>,
+    /// Maps signatures that were looked up to where they were found.
+    pub signature_sources: HashMap<String, NameSource>,
     /// Maps addresses to text names and tags.
     ///
     /// 20 byte addresses "abcd...1234" -> ("SomeContractName", "Special tag")
     pub nametags: HashMap<String, (VisitNote, Vec<String>)>,
-    /// Maps addresses to JSON encoded text ABIs.
+    /// Maps addresses to JSON encoded text ABIs and how they were obtained.
+    ///
+    /// 20 byte addresses "abcd...1234" -> ("{...}", SourcifyFullMatch)
+    pub abis: HashMap<String, (VisitNote, (String, VerificationStatus))>,
+    /// Maps selector and contract to the signature and argument types of a function.
+    ///
+    /// "a9059cbb:abcd...1234" -> ("transfer(address,uint256)", [Address, Uint])
+    pub calls: HashMap<String, (VisitNote, CallShape)>,
+    /// Maps addresses to the standard interfaces they implement.
+    ///
+    /// 20 byte addresses "abcd...1234" -> [Erc20]
+    pub interfaces: HashMap<String, (VisitNote, Vec<Erc>)>,
+    /// Maps addresses to the name and symbol read from the contract.
+    ///
+    /// 20 byte addresses "abcd...1234" -> ["Wrapped Ether", "WETH"]
+    pub onchain_names: HashMap<String, (VisitNote, Vec<String>)>,
+    /// Maps token contracts to the decimals read from the contract.
+    ///
+    /// 20 byte addresses "abcd...1234" -> 6
+    pub token_decimals: HashMap<String, (VisitNote, Option<u32>)>,
+    /// Maps contracts to who deployed them, and in which transaction.
     ///
-    /// 20 byte addresses "abcd...1234" -> ("{...}")
-    pub abis: HashMap<String, (VisitNote, String)>,
+    /// 20 byte addresses "abcd...1234" -> (factory, deploying transaction)
+    pub deployers: HashMap<String, (VisitNote, Option<(H160, H256)>)>,
+    /// Maps addresses to why their bytecode could not be decompiled.
+    ///
+    /// 20 byte addresses "abcd...1234" -> Timeout
+    pub decompile_failures: HashMap<String, DecompileFailure>,
+    /// Maps address and block to the code of the contract at that block.
+    ///
+    /// "abcd...1234:15000000" -> [0x60, 0x80, ...]
+    #[serde(skip)]
+    pub codes_at: HashMap<String, (VisitNote, Vec<u8>)>,
+    /// Lookups that failed since the warnings were last taken (see
+    /// `take_warnings`).
+    #[serde(skip)]
+    pub warnings: Vec<Warning>,
 }
 
 impl Cache {
+    /// Successful lookups, to keep for later runs.
+    pub fn resolved(&self) -> Resolved {
+        Resolved {
+            signatures: successes(&self.signatures),
+            nametags: successes(&self.nametags),
+            signature_sources: self
+                .signature_sources
+                .iter()
+                .filter(|(sig, _)| {
+                    matches!(
+                        self.signatures.get(*sig),
+                        Some((VisitNote::PriorSuccess, _))
+                    )
+                })
+                .map(|(sig, source)| (sig.to_owned(), *source))
+                .collect(),
+        }
+    }
+    /// Adds lookups from earlier runs, keeping those already made.
+    pub fn add_resolved(&mut self, resolved: Resolved) {
+        for (key, value) in resolved.signatures {
+            self.signatures
+                .entry(key)
+                .or_insert((VisitNote::PriorSuccess, value));
+        }
+        for (key, source) in resolved.signature_sources {
+            self.signature_sources.entry(key).or_insert(source);
+        }
+        for (key, value) in resolved.nametags {
+            self.nametags
+                .entry(key)
+                .or_insert((VisitNote::PriorSuccess, value));
+        }
+    }
+    /// Warnings recorded since they were last taken, oldest first.
+    pub fn take_warnings(&mut self) -> Vec<Warning> {
+        std::mem::take(&mut self.warnings)
+    }
+    fn warn(
+        &mut self,
+        kind: WarningKind,
+        subject: impl std::fmt::Display,
+        reason: impl std::fmt::Display,
+    ) {
+        let warning = Warning::new(kind, subject, reason);
+        debug!("{}", warning);
+        self.warnings.push(warning);
+    }
     /// Attempt to look up abi if not in cache.
     pub async fn try_abi(
         &mut self,
         address: &H160,
         mode: &Mode,
         bytecode: &[u8],
-    ) -> Option<String> {
+        config: &Config,
+    ) -> Option<(String, VerificationStatus)> {
         let address_string = hex::encode(address);
         let address_string = address_string.trim_start_matches("0x");
         match self.abis.get(address_string) {
             Some((VisitNote::PriorSuccess, abi)) => {
-                debug!("Using cached ABI: {} {}", address_string, abi);
+                debug!("Using cached ABI: {} {}", address_string, abi.0);
                 return Some(abi.to_owned());
             }
             Some((VisitNote::PriorFailure, _)) => {
@@ -51,18 +147,23 @@ impl Cache {
                 );
                 return None;
             }
+            _ if config.decompiler.is_pending(address) => return None,
             _ => {}
         }
 
-        let abi_result = get_abi(address, mode, bytecode).await;
+        let abi_result = get_abi(address, mode, bytecode, config).await;
 
         let abi = match abi_result {
             Ok(a) => a,
             Err(e) => {
-                error!("Couldn't get ABI for address: {} ({})", &address_string, e);
+                let subject = format!("0x{}", address_string);
+                self.warn(WarningKind::Abi, subject, format!("{:#}", e));
                 self.abis.insert(
                     address_string.to_owned(),
-                    (VisitNote::PriorFailure, String::from("")),
+                    (
+                        VisitNote::PriorFailure,
+                        (String::from(""), VerificationStatus::Unknown),
+                    ),
                 );
                 return None;
             }
@@ -76,40 +177,124 @@ impl Cache {
                 );
                 Some(a)
             }
+            None if config.decompiler.is_pending(address) => {
+                debug!("Decompiling {} in the background", &address_string);
+                None
+            }
             None => {
-                error!("No ABI found for address: {}", &address_string);
+                match bytecode.is_empty() {
+                    // E.g., a destructed contract.
+                    true => debug!("No ABI or code for address: {}", &address_string),
+                    false => {
+                        let subject = format!("0x{}", address_string);
+                        self.warn(WarningKind::Abi, subject, "not found")
+                    }
+                }
                 self.abis.insert(
                     address_string.to_owned(),
-                    (VisitNote::PriorFailure, String::from("")),
+                    (
+                        VisitNote::PriorFailure,
+                        (String::from(""), VerificationStatus::Unknown),
+                    ),
                 );
                 None
             }
         }
     }
 
-    /// Attempt to look up a signature if not in cache.
+    /// Records finished decompilations.
+    ///
+    /// Returns the contracts that now have a decompiled ABI.
+    pub fn add_decompiled(&mut self, finished: Vec<Finished>) -> Vec<H160> {
+        let mut decompiled = vec![];
+        for done in finished {
+            for address in done.addresses {
+                let abi = match &done.result {
+                    Ok(()) => {
+                        decompiled.push(address);
+                        (
+                            VisitNote::PriorSuccess,
+                            (
+                                String::from(DECOMPILED_ABI),
+                                VerificationStatus::DecompiledOnly,
+                            ),
+                        )
+                    }
+                    Err(e) => {
+                        self.warn(
+                            WarningKind::Decompile,
+                            format!("0x{}", hex::encode(address)),
+                            e,
+                        );
+                        self.decompile_failures
+                            .insert(hex::encode(address), e.clone());
+                        (
+                            VisitNote::PriorFailure,
+                            (String::from(""), VerificationStatus::Unknown),
+                        )
+                    }
+                };
+                self.abis.insert(hex::encode(address), abi);
+            }
+        }
+        decompiled
+    }
+
+    /// How far decompiling the contract has got.
+    pub fn decompile_status(&self, address: &H160, pool: &DecompilePool) -> DecompileStatus {
+        let key = hex::encode(address);
+        match self.abis.get(&key) {
+            Some((VisitNote::PriorSuccess, (_, VerificationStatus::DecompiledOnly))) => {
+                return DecompileStatus::Decompiled
+            }
+            _ if pool.is_pending(address) => return DecompileStatus::Pending,
+            _ => {}
+        }
+        match self.decompile_failures.get(&key) {
+            Some(failure) => DecompileStatus::Failed(failure.clone()),
+            None => DecompileStatus::NotAttempted,
+        }
+    }
+
+    /// Attempt to look up a signature if not built in or in cache.
+    ///
+    /// Function selectors (4 bytes) and event topics (32 bytes) are looked up
+    /// at their own 4byte.directory endpoints. A signature missing from the
+    /// local database is looked up again when APIs are used.
     pub async fn try_sig(&mut self, sig: &str, mode: &Mode, config: &Config) -> Option<String> {
+        if let Some(text) = common_signature(sig) {
+            debug!("Using built in signature: {} {}", sig, text);
+            return Some(text.to_string());
+        }
         match self.signatures.get(sig) {
             Some((VisitNote::PriorSuccess, value)) => {
                 debug!("Using cached signature: {} {}", sig, value);
                 return Some(value.to_owned());
             }
-            Some((VisitNote::PriorFailure, _)) => {
+            Some((VisitNote::PriorFailure, _))
+                if !(*mode == Mode::UseApis
+                    && self.signature_sources.get(sig) == Some(&NameSource::Database)) =>
+            {
                 debug!("(skipping) Prior text fetch failure for signature: {}", sig);
                 return None;
             }
             _ => {}
         }
 
-        let text_result = match mode {
-            Mode::AvoidApis => sig_to_text(sig, config),
-            Mode::UseApis => method_from_fourbyte_api(sig).await,
+        let (text_result, source) = match mode {
+            Mode::AvoidApis => (sig_to_text(sig, config), NameSource::Database),
+            Mode::UseApis if sig.len() == 8 => {
+                (function_from_fourbyte_api(sig).await, NameSource::FourByte)
+            }
+            Mode::UseApis => (method_from_fourbyte_api(sig).await, NameSource::FourByte),
         };
+        // Where a failed lookup was made, to try the APIs later.
+        self.signature_sources.insert(sig.to_owned(), source);
 
         let text = match text_result {
             Ok(t) => t,
             Err(e) => {
-                error!("Couldn't get text for signature: {} ({})", &sig, e);
+                self.warn(WarningKind::Signature, sig, format!("{:#}", e));
                 self.signatures
                     .insert(sig.to_owned(), (VisitNote::PriorFailure, String::from("")));
                 return None;
@@ -123,15 +308,214 @@ impl Cache {
                 Some(t)
             }
             None => {
-                error!("No text found for signature: {}", &sig);
+                self.warn(WarningKind::Signature, sig, "not found");
                 self.signatures
                     .insert(sig.to_owned(), (VisitNote::PriorFailure, String::from("")));
                 None
             }
         }
     }
-    /// Attempt to look up nametags if not in cache.
+    /// Where the text signature of a selector or topic was found, if it was.
+    pub fn signature_source(&self, sig: &str) -> Option<NameSource> {
+        if common_signature(sig).is_some() {
+            return Some(NameSource::BuiltIn);
+        }
+        match self.signatures.get(sig) {
+            Some((VisitNote::PriorSuccess, _)) => Some(
+                self.signature_sources
+                    .get(sig)
+                    .copied()
+                    .unwrap_or(NameSource::Earlier),
+            ),
+            _ => None,
+        }
+    }
+    /// True if a verified ABI has been found for the address.
+    pub fn is_verified(&self, address: &H160) -> bool {
+        self.verified_abi(address).is_some()
+    }
+    /// The ABI of the address, if one has been found and it is verified.
+    pub fn verified_abi(&self, address: &H160) -> Option<&str> {
+        match self.abis.get(&hex::encode(address)) {
+            Some((
+                VisitNote::PriorSuccess,
+                (
+                    abi,
+                    VerificationStatus::SourcifyFullMatch
+                    | VerificationStatus::SourcifyPartialMatch,
+                ),
+            )) => Some(abi),
+            _ => None,
+        }
+    }
+    /// Decodes calldata without an ABI, reusing the shape from earlier calls.
+    ///
+    /// A shape guessed without a signature is kept as a failure, so the
+    /// signature is looked up again (e.g., by a later run with APIs).
+    pub async fn try_call(
+        &mut self,
+        contract: &H160,
+        calldata: &[u8],
+        mode: &Mode,
+        config: &Config,
+    ) -> Option<DecodedCall> {
+        let selector = hex::encode(calldata.get(..4)?);
+        let key = format!("{}:{}", selector, hex::encode(contract));
+        let mut call = match self.calls.get(&key) {
+            Some((VisitNote::PriorSuccess, shape)) => {
+                debug!("Using cached call shape: {}", key);
+                shape.decode(calldata)?
+            }
+            _ => {
+                let signature = self.try_sig(&selector, mode, config).await;
+                let note = match signature {
+                    Some(_) => VisitNote::PriorSuccess,
+                    None => VisitNote::PriorFailure,
+                };
+                let shape = CallShape::new(signature, calldata);
+                let call = shape.decode(calldata);
+                self.calls.insert(key, (note, shape));
+                call?
+            }
+        };
+        call.source = self.signature_source(&selector);
+        Some(call)
+    }
+    /// Fetches the code of a contract at a block if not in cache. None if
+    /// the node could not give it.
+    pub async fn try_code_at(
+        &mut self,
+        address: &H160,
+        block: u64,
+        provider: &Provider,
+    ) -> Option<Vec<u8>> {
+        let key = format!("{}:{}", hex::encode(address), block);
+        match self.codes_at.get(&key) {
+            Some((VisitNote::PriorSuccess, code)) => return Some(code.to_owned()),
+            Some((VisitNote::PriorFailure, _)) => return None,
+            _ => {}
+        }
+        match provider.code_at(*address, block).await {
+            Ok((code, _)) => {
+                self.codes_at
+                    .insert(key, (VisitNote::PriorSuccess, code.0.to_owned()));
+                Some(code.0)
+            }
+            Err(e) => {
+                debug!("No code for {:?} at block {} ({})", address, block, e);
+                self.codes_at.insert(key, (VisitNote::PriorFailure, vec![]));
+                None
+            }
+        }
+    }
+    /// Detects the interfaces of a contract if not in cache.
+    pub async fn try_interfaces(
+        &mut self,
+        address: &H160,
+        bytecode: &[u8],
+        abi: Option<&str>,
+        provider: &Provider,
+        lookups: bool,
+    ) -> Vec<Erc> {
+        let addr_hex = hex::encode(address);
+        if let Some((VisitNote::PriorSuccess, value)) = self.interfaces.get(&addr_hex) {
+            debug!("Using cached interfaces: {} {:?}", address, value);
+            return value.to_owned();
+        }
+        let found = interfaces::detect(address, bytecode, abi, provider, lookups).await;
+        self.interfaces
+            .insert(addr_hex, (VisitNote::PriorSuccess, found.to_owned()));
+        found
+    }
+    /// Asks the node who deployed a contract, if `Config::deployer_lookups`
+    /// is set, and names the deployer from the nametags.
+    pub async fn try_deployer(&mut self, address: &H160, config: &Config) -> Option<Deployer> {
+        if !config.deployer_lookups || !config.provider.supports(Capability::Creators) {
+            return None;
+        }
+        let addr_hex = hex::encode(address);
+        let creation = match self.deployers.get(&addr_hex) {
+            Some((_, creation)) => *creation,
+            None => {
+                let creation = match config.provider.contract_creator(*address).await {
+                    Ok((creation, _)) => creation,
+                    Err(e) => {
+                        debug!("No deployer for {:?} ({})", address, e);
+                        None
+                    }
+                };
+                let note = match creation {
+                    Some(_) => VisitNote::PriorSuccess,
+                    None => VisitNote::PriorFailure,
+                };
+                self.deployers.insert(addr_hex, (note, creation));
+                creation
+            }
+        };
+        let (deployer, transaction) = creation?;
+        let tags = self.try_nametags(&deployer, config).unwrap_or_default();
+        Some(Deployer {
+            address: deployer,
+            transaction,
+            nametags: tags.into_iter().filter(|t| !t.is_empty()).collect(),
+        })
+    }
+    /// Looks up nametags, with labels supplied by the user first.
     pub fn try_nametags(&mut self, address: &H160, config: &Config) -> Option<Vec<String>> {
+        let tags = self.try_todd_nametags(address, config);
+        config.labels.merge(address, tags)
+    }
+    /// Looks up nametags, then calls name() and symbol() on the contract if
+    /// there are none and `Config::onchain_names` is set.
+    pub async fn try_nametags_or_onchain(
+        &mut self,
+        address: &H160,
+        config: &Config,
+    ) -> Option<Vec<String>> {
+        let tags = self.try_nametags(address, config);
+        let labelled = tags.iter().flatten().any(|t| !t.is_empty());
+        if labelled || !config.onchain_names {
+            return tags;
+        }
+        let addr_hex = hex::encode(address);
+        let names = match self.onchain_names.get(&addr_hex) {
+            Some((_, names)) => names.to_owned(),
+            None => {
+                let names = interfaces::onchain_names(address, &config.provider).await;
+                let note = match names.is_empty() {
+                    true => VisitNote::PriorFailure,
+                    false => VisitNote::PriorSuccess,
+                };
+                self.onchain_names
+                    .insert(addr_hex, (note, names.to_owned()));
+                names
+            }
+        };
+        match names.is_empty() {
+            true => tags,
+            false => Some(names),
+        }
+    }
+    /// Calls decimals() on a token contract if not in cache.
+    pub async fn try_decimals(&mut self, address: &H160, config: &Config) -> Option<u32> {
+        let addr_hex = hex::encode(address);
+        if let Some((_, decimals)) = self.token_decimals.get(&addr_hex) {
+            return *decimals;
+        }
+        let decimals = interfaces::onchain_decimals(address, &config.provider).await;
+        let note = match decimals {
+            Some(_) => VisitNote::PriorSuccess,
+            None => VisitNote::PriorFailure,
+        };
+        self.token_decimals.insert(addr_hex, (note, decimals));
+        decimals
+    }
+    /// Decimals of a token read earlier with `try_decimals`.
+    pub fn known_decimals(&self, address: &H160) -> Option<u32> {
+        self.token_decimals.get(&hex::encode(address))?.1
+    }
+    /// Attempt to look up nametags in the TODD database if not in cache.
+    fn try_todd_nametags(&mut self, address: &H160, config: &Config) -> Option<Vec<String>> {
         let addr_hex = hex::encode(address);
         match self.nametags.get(&addr_hex) {
             Some((VisitNote::PriorSuccess, value)) => {
@@ -155,7 +539,11 @@ impl Cache {
                 Some(n)
             }
             Err(e) => {
-                error!("Couldn't get nametag for address: {} ({})", &address, e);
+                self.warn(
+                    WarningKind::Nametags,
+                    format!("0x{}", addr_hex),
+                    format!("{:#}", e),
+                );
                 self.nametags.insert(
                     addr_hex.to_owned(),
                     (VisitNote::PriorFailure, vec![String::from("")]),
@@ -169,23 +557,54 @@ impl Cache {
 /// Gets the ABI for a contract.
 ///
 /// This may take two forms:
-/// - `Mode::UseApis` First tries Sourcify then Heimdall (which relies on third party API for
-/// four byte signatures)
+/// - `Mode::UseApis` First tries Sourcify (`Config::sourcify`) then Heimdall (which relies on third party API for
+///   four byte signatures)
 /// - `Mode::AvoidApis`
-pub async fn get_abi(address: &H160, mode: &Mode, bytecode: &[u8]) -> Result<Option<String>> {
+///
+/// The ABI is returned with the status describing where it came from.
+///
+/// ABIs from Sourcify (with the metadata) and decompiled output are kept in
+/// the artifact store and reused. Bytecode that has not been decompiled is
+/// queued with the decompiler pool, and no ABI is returned until it finishes.
+pub async fn get_abi(
+    address: &H160,
+    mode: &Mode,
+    bytecode: &[u8],
+    config: &Config,
+) -> Result<Option<(String, VerificationStatus)>> {
+    let (store, decompiler) = (&config.store, &config.decompiler);
+    if let Some(stored) = store.abi(address) {
+        return Ok(Some(stored));
+    }
     Ok(match mode {
         Mode::UseApis => {
-            let abi = abi_from_sourcify_api(address).await?;
+            let metadata = metadata_from_sourcify_api(address, &config.sourcify).await?;
             // If no ABI is found at the API, decompile.
-            match abi {
-                Some(x) => Some(x),
+            match metadata {
+                Some((metadata, status)) => {
+                    if let Err(e) = store.put_metadata(address, metadata.to_string().as_bytes()) {
+                        warn!("Could not store metadata for {:?} ({})", address, e);
+                    }
+                    let abi = abi_from_metadata(metadata)?;
+                    if let Err(e) = store.put_abi(address, &abi, status) {
+                        warn!("Could not store ABI for {:?} ({})", address, e);
+                    }
+                    Some((abi, status))
+                }
+                None if bytecode.is_empty() => None,
                 None => {
-                    let bytecode_string = hex::encode(bytecode);
-                    DecompileBuilder::new(&bytecode_string)
-                        .output(&format!("decompiled/{}", address))
-                        .decompile();
-                    warn!("Did not check if decompilation fails.");
-                    Some(String::from("TODO: Pull decompiled-ABI from file"))
+                    let output = store.decompiled_dir(bytecode);
+                    if output.exists() {
+                        debug!("Using stored decompilation for {}", address);
+                        store.touch_decompiled(bytecode);
+                        Some((
+                            String::from(DECOMPILED_ABI),
+                            VerificationStatus::DecompiledOnly,
+                        ))
+                    } else {
+                        decompiler.submit(*address, bytecode, output);
+                        None
+                    }
                 }
             }
         }
@@ -194,7 +613,58 @@ pub async fn get_abi(address: &H160, mode: &Mode, bytecode: &[u8]) -> Result<Opt
                 "ABI not fetched for address {}. Pending integration with TODD-ABI (IPFS) database.",
                 address
             );
-            Some(String::from("TODO, get TODD-ABIs"))
+            Some((
+                String::from("TODO, get TODD-ABIs"),
+                VerificationStatus::Unknown,
+            ))
         }
     })
 }
+
+/// Values of successful lookups by key.
+fn successes<T: Clone>(lookups: &HashMap<String, (VisitNote, T)>) -> BTreeMap<String, T> {
+    lookups
+        .iter()
+        .filter(|(_, (note, _))| *note == VisitNote::PriorSuccess)
+        .map(|(key, (_, value))| (key.to_owned(), value.to_owned()))
+        .collect()
+}
+
+#[test]
+fn signature_sources_kept() {
+    let mut cache = Cache::default();
+    let found = |text: &str| (VisitNote::PriorSuccess, text.to_string());
+    cache
+        .signatures
+        .insert(String::from("12345678"), found("f()"));
+    cache
+        .signatures
+        .insert(String::from("87654321"), found("g()"));
+    cache
+        .signature_sources
+        .insert(String::from("87654321"), NameSource::FourByte);
+    assert_eq!(
+        cache.signature_source("a9059cbb"),
+        Some(NameSource::BuiltIn)
+    );
+    assert_eq!(
+        cache.signature_source("12345678"),
+        Some(NameSource::Earlier)
+    );
+    assert_eq!(
+        cache.signature_source("87654321"),
+        Some(NameSource::FourByte)
+    );
+    assert_eq!(cache.signature_source("00000000"), None);
+
+    let mut later = Cache::default();
+    later.add_resolved(cache.resolved());
+    assert_eq!(
+        later.signature_source("87654321"),
+        Some(NameSource::FourByte)
+    );
+    assert_eq!(
+        NameSource::FourByte.to_string(),
+        "4byte.directory, confidence 40"
+    );
+}
@@ -0,0 +1,60 @@
+//! Stopping a run early (Ctrl-C) without losing what was already fetched.
+//!
+//! The first Ctrl-C cancels the run. Each stage stops before its next
+//! request and keeps the transactions it already processed. The rest of the
+//! run goes on as usual with the partial history: it is saved, resolved
+//! lookups are written to the store, and it is shown. The exit code is then
+//! 130. A second Ctrl-C exits at once.
+use log::warn;
+use tokio_util::sync::CancellationToken;
+
+use crate::outcome::Outcome;
+
+/// Shared flag that a run should stop. Clones cancel together.
+#[derive(Debug, Default, Clone)]
+pub struct Cancellation(CancellationToken);
+
+impl Cancellation {
+    /// Cancels the run, and every clone of this.
+    pub fn cancel(&self) {
+        self.0.cancel()
+    }
+    pub fn is_cancelled(&self) -> bool {
+        self.0.is_cancelled()
+    }
+    /// Completes once the run is cancelled.
+    pub async fn cancelled(&self) {
+        self.0.cancelled().await
+    }
+    /// Cancels on the first Ctrl-C and exits on the second.
+    pub fn on_ctrl_c(&self) {
+        let cancellation = self.clone();
+        tokio::spawn(async move {
+            if tokio::signal::ctrl_c().await.is_err() {
+                return;
+            }
+            warn!("Stopping after the current request. Press Ctrl-C again to quit now.");
+            cancellation.cancel();
+            if tokio::signal::ctrl_c().await.is_ok() {
+                std::process::exit(Outcome::Cancelled as i32);
+            }
+        });
+    }
+}
+
+impl PartialEq for Cancellation {
+    fn eq(&self, other: &Self) -> bool {
+        self.is_cancelled() == other.is_cancelled()
+    }
+}
+
+#[tokio::test]
+async fn clones_cancelled_together() {
+    let cancellation = Cancellation::default();
+    let stage = cancellation.clone();
+    assert!(!stage.is_cancelled());
+    cancellation.cancel();
+    assert!(stage.is_cancelled());
+    // Completes at once for a cancelled run.
+    stage.cancelled().await;
+}
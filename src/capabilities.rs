@@ -0,0 +1,306 @@
+//! Detects which RPC methods a node supports before the pipeline runs.
+//!
+//! Each capability is probed with a harmless request. A "method not found"
+//! response means the capability is missing, while any other response
+//! (including a complaint about the parameters) means the method exists.
+//! Stages that need a missing capability are then skipped, or substituted
+//! where another method can provide the same data.
+use std::{collections::HashSet, fmt::Display};
+
+use anyhow::Result;
+use async_trait::async_trait;
+use log::warn;
+use serde_json::{json, Value};
+use web3::{
+    api::BaseFilter,
+    transports::Http,
+    types::{
+        Block, BlockId, BlockNumber, Bytes, CallRequest, Filter, Log, Transaction, TransactionId,
+        TransactionReceipt, H160, H256, U256,
+    },
+    Web3,
+};
+
+use crate::provider::{unsupported, Capability, ChainDataProvider, Provider, Sourced};
+
+/// Capabilities in the order they are reported.
+const ALL: [Capability; 10] = [
+    Capability::Transactions,
+    Capability::Receipts,
+    Capability::BlockReceipts,
+    Capability::Blocks,
+    Capability::ContractCode,
+    Capability::Logs,
+    Capability::PendingTransactions,
+    Capability::Traces,
+    Capability::Calls,
+    Capability::Creators,
+];
+
+/// JSON-RPC error codes for methods that are absent or disabled (EIP-1474).
+const METHOD_NOT_FOUND: i64 = -32601;
+const METHOD_NOT_SUPPORTED: i64 = -32004;
+
+/// Outcome of probing one capability.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Support {
+    Available,
+    Missing,
+    /// The probe failed for another reason (e.g., the node is unreachable).
+    Unknown(String),
+}
+
+/// Which capabilities a node offers.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CapabilityReport {
+    /// Name of the provider that was probed.
+    pub provider: String,
+    /// Response to web3_clientVersion.
+    pub client: Option<String>,
+    pub results: Vec<(Capability, Support)>,
+}
+
+/// A provider restricted to the capabilities that were found.
+///
+/// Transactions are read from block bodies if the node lacks
+/// eth_getTransactionByBlockNumberAndIndex.
+pub struct ProbedProvider {
+    inner: Provider,
+    available: HashSet<Capability>,
+}
+
+/// Probes each capability the provider claims to support.
+pub async fn probe(provider: &Provider) -> CapabilityReport {
+    let client = match provider.request("web3_clientVersion", vec![]).await {
+        Ok((Value::String(version), _)) => Some(version),
+        Ok(_) => None,
+        Err(e) => {
+            warn!("Could not get the client version ({})", e);
+            None
+        }
+    };
+    let mut results = vec![];
+    for capability in ALL {
+        let support = match provider.supports(capability) {
+            true => probe_capability(provider, capability).await,
+            false => Support::Missing,
+        };
+        results.push((capability, support));
+    }
+    CapabilityReport {
+        provider: provider.name().to_owned(),
+        client,
+        results,
+    }
+}
+
+/// Sends the requests that a capability relies on.
+async fn probe_capability(provider: &Provider, capability: Capability) -> Support {
+    let zero_hash = json!(H256::zero());
+    let requests = match capability {
+        Capability::Transactions => vec![(
+            "eth_getTransactionByBlockNumberAndIndex",
+            vec![json!("0x0"), json!("0x0")],
+        )],
+        Capability::Receipts => vec![("eth_getTransactionReceipt", vec![zero_hash])],
+        Capability::BlockReceipts => vec![("eth_getBlockReceipts", vec![json!("0x0")])],
+        Capability::Blocks => vec![("eth_getBlockByNumber", vec![json!("0x0"), json!(false)])],
+        Capability::ContractCode => {
+            vec![("eth_getCode", vec![json!(H160::zero()), json!("latest")])]
+        }
+        Capability::Logs => vec![
+            ("eth_blockNumber", vec![]),
+            (
+                "eth_getLogs",
+                vec![json!({"fromBlock": "latest", "toBlock": "latest"})],
+            ),
+        ],
+        Capability::PendingTransactions => vec![("eth_newPendingTransactionFilter", vec![])],
+        Capability::Traces => vec![("trace_transaction", vec![zero_hash])],
+        Capability::Calls => vec![(
+            "eth_call",
+            vec![json!({"to": H160::zero(), "data": "0x"}), json!("latest")],
+        )],
+        Capability::Creators => vec![("ots_getContractCreator", vec![json!(H160::zero())])],
+    };
+    for (method, params) in requests {
+        match provider.request(method, params).await {
+            Ok((response, _)) => {
+                if capability == Capability::PendingTransactions {
+                    // Remove the probe filter rather than waiting for it to expire.
+                    let _ = provider
+                        .request("eth_uninstallFilter", vec![response])
+                        .await;
+                }
+            }
+            Err(e) if is_missing_method(&e) => return Support::Missing,
+            // The node answered (e.g., rejecting the params), so the method exists.
+            Err(e) if matches!(e.downcast_ref::<web3::Error>(), Some(web3::Error::Rpc(_))) => {}
+            Err(e) => return Support::Unknown(e.to_string()),
+        }
+    }
+    Support::Available
+}
+
+/// True if the node reported that the method does not exist or is disabled.
+fn is_missing_method(error: &anyhow::Error) -> bool {
+    match error.downcast_ref::<web3::Error>() {
+        Some(web3::Error::Rpc(e)) => missing_method_response(e.code.code(), &e.message),
+        _ => false,
+    }
+}
+
+/// Nodes differ in how they report unavailable methods.
+fn missing_method_response(code: i64, message: &str) -> bool {
+    let message = message.to_lowercase();
+    code == METHOD_NOT_FOUND
+        || code == METHOD_NOT_SUPPORTED
+        || message.contains("not supported")
+        || message.contains("does not exist")
+}
+
+impl CapabilityReport {
+    fn is_available(&self, capability: Capability) -> bool {
+        self.results
+            .iter()
+            .any(|(c, s)| *c == capability && *s == Support::Available)
+    }
+    /// Restricts the provider to the available capabilities.
+    pub fn configure(&self, provider: Provider) -> Provider {
+        let available = self
+            .results
+            .iter()
+            .filter(|(_, s)| *s == Support::Available)
+            .map(|(c, _)| *c)
+            .collect();
+        Provider::new(ProbedProvider {
+            inner: provider,
+            available,
+        })
+    }
+    /// How the pipeline changes without a capability.
+    fn effect(&self, capability: Capability) -> &'static str {
+        match capability {
+            Capability::Transactions if self.is_available(Capability::Blocks) => {
+                "transactions read from block bodies"
+            }
+            Capability::Transactions => "transaction data skipped",
+            Capability::Receipts => "receipts, events and gas skipped",
+            Capability::BlockReceipts => "receipts fetched one by one",
+            Capability::Blocks => "timestamps skipped",
+            Capability::ContractCode => "contract metadata and decompilation skipped",
+            Capability::Logs => "watch mode unavailable",
+            Capability::PendingTransactions => "pending alerts unavailable",
+            Capability::Traces => "internal calls unavailable",
+            Capability::Calls => "interfaces detected from bytecode only",
+            Capability::Creators => "deployers not looked up",
+        }
+    }
+}
+
+impl Display for CapabilityReport {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{} capabilities ({})",
+            self.provider,
+            self.client.as_deref().unwrap_or("unknown client")
+        )?;
+        for (capability, support) in &self.results {
+            let status = match support {
+                Support::Available => String::from("available"),
+                Support::Missing => format!("missing, {}", self.effect(*capability)),
+                Support::Unknown(e) => {
+                    format!("unknown ({}), {}", e, self.effect(*capability))
+                }
+            };
+            write!(f, "\n\t{}: {}", capability, status)?;
+        }
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl ChainDataProvider for ProbedProvider {
+    fn name(&self) -> &str {
+        self.inner.name()
+    }
+    fn supports(&self, capability: Capability) -> bool {
+        match capability {
+            Capability::Transactions => {
+                self.available.contains(&capability) || self.available.contains(&Capability::Blocks)
+            }
+            _ => self.available.contains(&capability),
+        }
+    }
+    async fn request(&self, method: &str, params: Vec<Value>) -> Result<Sourced<Value>> {
+        self.inner.request(method, params).await
+    }
+    async fn transaction(&self, id: TransactionId) -> Result<Sourced<Option<Transaction>>> {
+        if self.available.contains(&Capability::Transactions) {
+            return self.inner.transaction(id).await;
+        }
+        // Substitute: find the transaction in the block body.
+        let TransactionId::Block(BlockId::Number(BlockNumber::Number(number)), index) = id else {
+            return Err(unsupported(self.name(), Capability::Transactions));
+        };
+        let (block, url) = self.block_with_txs(number.as_u64()).await?;
+        let tx = block.and_then(|b| b.transactions.into_iter().nth(index.as_usize()));
+        Ok((tx, url))
+    }
+    async fn receipt(&self, hash: H256) -> Result<Sourced<Option<TransactionReceipt>>> {
+        self.inner.receipt(hash).await
+    }
+    async fn block_receipts(
+        &self,
+        number: u64,
+    ) -> Result<Sourced<Option<Vec<TransactionReceipt>>>> {
+        self.inner.block_receipts(number).await
+    }
+    async fn block(&self, number: u64) -> Result<Sourced<Option<Block<H256>>>> {
+        self.inner.block(number).await
+    }
+    async fn block_with_txs(&self, number: u64) -> Result<Sourced<Option<Block<Transaction>>>> {
+        self.inner.block_with_txs(number).await
+    }
+    async fn code(&self, address: H160) -> Result<Sourced<Bytes>> {
+        self.inner.code(address).await
+    }
+    async fn code_at(&self, address: H160, block: u64) -> Result<Sourced<Bytes>> {
+        self.inner.code_at(address, block).await
+    }
+    async fn contract_call(&self, to: H160, data: Vec<u8>) -> Result<Sourced<Bytes>> {
+        self.inner.contract_call(to, data).await
+    }
+    async fn simulate_call(&self, request: CallRequest) -> Result<Sourced<Bytes>> {
+        self.inner.simulate_call(request).await
+    }
+    async fn estimate_gas(&self, request: CallRequest) -> Result<Sourced<U256>> {
+        self.inner.estimate_gas(request).await
+    }
+    async fn contract_creator(&self, address: H160) -> Result<Sourced<Option<(H160, H256)>>> {
+        self.inner.contract_creator(address).await
+    }
+    async fn head_block(&self) -> Result<Sourced<u64>> {
+        self.inner.head_block().await
+    }
+    async fn logs(&self, filter: Filter) -> Result<Sourced<Vec<Log>>> {
+        self.inner.logs(filter).await
+    }
+    async fn pending_transactions_filter(
+        &self,
+    ) -> Result<Sourced<(Web3<Http>, BaseFilter<Http, H256>)>> {
+        self.inner.pending_transactions_filter().await
+    }
+}
+
+#[test]
+fn missing_method_detection() {
+    assert!(missing_method_response(-32601, "Method not found"));
+    assert!(missing_method_response(
+        -32000,
+        "the method trace_transaction does not exist/is not available"
+    ));
+    assert!(!missing_method_response(-32602, "invalid argument 0"));
+    assert!(!is_missing_method(&anyhow::anyhow!("timed out")));
+}
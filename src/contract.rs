@@ -2,80 +2,280 @@
 //!
 //! For example
 //! contract runtime bytecode contains source code metadata that can be decoded.
-use std::collections::HashMap;
+//!
+//! ## Metadata trailer
+//! Compilers append CBOR encoded metadata to the runtime bytecode, usually
+//! followed by its length as 2 bytes:
+//!
+//! `<runtime bytecode><CBOR map><length>`
+//!
+//! The map varies by compiler version:
+//! - solc 0.4.7 to 0.5.8: `{"bzzr0": <swarm hash>}`
+//! - solc 0.5.9 to 0.5.x: adds `"solc": <version bytes>`
+//! - solc 0.5.11 to 0.6.1: `"bzzr1"` instead of `"bzzr0"`
+//! - solc 0.6.2 and later: `"ipfs"` by default
+//! - `"experimental": true` if experimental features were enabled
+//! - prerelease solc builds store the full version as text
+//! - vyper 0.3.4 and later: `{"vyper": [major, minor, patch]}`
+//!
+//! Some bytecode has no length suffix (e.g., it was truncated or post
+//! processed), and some has no metadata at all (e.g., minimal proxies or
+//! contracts compiled with metadata disabled).
+use std::fmt::Display;
 
 use anyhow::{anyhow, bail, Result};
-use cbor::{Cbor, Decoder};
-use log::error;
 use serde::{Deserialize, Serialize};
 
-/// Returns the IPFS CID extracted from the on-chain runtime bytecode of a
+/// Bytes from the end of the bytecode searched for metadata without a length suffix.
+const SEARCH_WINDOW: usize = 128;
+
+/// The metadata trailer of runtime bytecode.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Trailer {
+    /// Metadata found after the runtime code.
+    Metadata(BytecodeMetadata),
+    /// No recognised metadata.
+    Absent,
+}
+
+/// Information decoded from the metadata trailer.
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct BytecodeMetadata {
+    /// Where the source code metadata can be found.
+    pub source: Option<MetadataSource>,
+    pub compiler: Option<Compiler>,
+    /// True if experimental compiler features were used.
+    pub experimental: bool,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
+#[non_exhaustive]
+pub enum MetadataSource {
+    Ipfs(String),
+    Swarm(String),
+}
+
+/// Compiler and version recorded in the metadata.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum Compiler {
+    Solc(String),
+    Vyper(String),
+}
+
+/// A CBOR data item, limited to the types used in metadata.
+#[derive(Debug, Clone, PartialEq)]
+enum Item {
+    Uint(u64),
+    Bytes(Vec<u8>),
+    Text(String),
+    Array(Vec<Item>),
+    Bool(bool),
+    Null,
+}
+
+/// Reads the metadata trailer from the on-chain runtime bytecode of a
 /// contract.
 ///
 /// For more information, see:
 /// - https://docs.sourcify.dev/blog/verify-contracts-perfectly/
 /// - https://docs.soliditylang.org/en/latest/metadata.html
 ///
-/// Note that other resources are available inside the metadata, such as
-/// the swarm hash (not currently fetched).
-pub fn cid_from_runtime_bytecode(runtime_bytecode: &[u8]) -> Result<Option<MetadataSource>> {
-    let metadata = read_metadata(runtime_bytecode)?;
-    cid_from_metadata(metadata)
+/// The length suffix is used if it points to valid metadata. Otherwise the
+/// end of the bytecode is searched for metadata without a suffix.
+pub fn read_trailer(runtime_bytecode: &[u8]) -> Trailer {
+    let with_suffix = read_metadata(runtime_bytecode).and_then(parse_metadata);
+    if let Ok(metadata) = with_suffix {
+        return Trailer::Metadata(metadata);
+    }
+    let len = runtime_bytecode.len();
+    for start in (len.saturating_sub(SEARCH_WINDOW)..len).rev() {
+        // Major type 5 (map) with 1 to 5 entries.
+        if !(0xa1..=0xa5).contains(&runtime_bytecode[start]) {
+            continue;
+        }
+        if let Ok(metadata) = parse_metadata(&runtime_bytecode[start..]) {
+            return Trailer::Metadata(metadata);
+        }
+    }
+    Trailer::Absent
 }
 
-#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
-pub enum MetadataSource {
-    Ipfs(String),
-    Swarm(String),
+/// Returns the constructor arguments from the input of a contract creation
+/// transaction.
+///
+/// The input is `<init code><runtime bytecode with metadata trailer><arguments>`.
+/// The arguments start after the last metadata trailer that is followed by a
+/// whole number of 32 byte words. Returns None if no trailer is found.
+pub fn constructor_args(input: &[u8]) -> Option<&[u8]> {
+    (2..=input.len()).rev().find_map(|end| {
+        if !(input.len() - end).is_multiple_of(32) {
+            return None;
+        }
+        let trailer = read_metadata(&input[..end]).ok()?;
+        if trailer.is_empty() {
+            return None;
+        }
+        parse_metadata(trailer).ok()?;
+        Some(&input[end..])
+    })
 }
 
-/// Decodes the IPFS CID from the CBOR-encoded metadata bytes.
+/// Decodes the CBOR-encoded metadata bytes.
 ///
 /// The runtime bytecode must first have the contract code and metadata-length bytes
-/// removed prior to being passed here.
-fn cid_from_metadata(metadata: &[u8]) -> Result<Option<MetadataSource>> {
-    let mut d = Decoder::from_bytes(metadata);
-    let cbor = d
-        .items()
-        .next()
-        .ok_or_else(|| anyhow!("Couldn't decode contract metadata CBOR."))??;
-    match cbor {
-        Cbor::Map(m) => determine_source(m),
-        _ => Ok(None),
+/// removed prior to being passed here. The bytes must be exactly one map with
+/// at least one known key.
+fn parse_metadata(metadata: &[u8]) -> Result<BytecodeMetadata> {
+    let mut reader = Reader {
+        data: metadata,
+        pos: 0,
+    };
+    let (major, entries) = reader.head()?;
+    if major != 5 {
+        bail!("Metadata is not a CBOR map.")
     }
+    let mut result = BytecodeMetadata::default();
+    let mut known = false;
+    for _ in 0..entries {
+        let Item::Text(key) = reader.item()? else {
+            bail!("Metadata key is not text.")
+        };
+        let value = reader.item()?;
+        known |= match (key.as_str(), value) {
+            ("ipfs", Item::Bytes(b)) => {
+                result.source = Some(MetadataSource::Ipfs(bs58::encode(b).into_string()));
+                true
+            }
+            ("bzzr0" | "bzzr1", Item::Bytes(b)) => {
+                result.source = Some(MetadataSource::Swarm(hex::encode(b)));
+                true
+            }
+            ("solc", Item::Bytes(b)) if b.len() == 3 => {
+                let version = format!("{}.{}.{}", b[0], b[1], b[2]);
+                result.compiler = Some(Compiler::Solc(version));
+                true
+            }
+            ("solc", Item::Text(version)) => {
+                result.compiler = Some(Compiler::Solc(version));
+                true
+            }
+            ("vyper", Item::Array(parts)) => {
+                let parts: Vec<String> = parts
+                    .iter()
+                    .map(|p| match p {
+                        Item::Uint(n) => n.to_string(),
+                        _ => String::from("?"),
+                    })
+                    .collect();
+                result.compiler = Some(Compiler::Vyper(parts.join(".")));
+                true
+            }
+            ("experimental", Item::Bool(b)) => {
+                result.experimental = b;
+                true
+            }
+            _ => false,
+        };
+    }
+    if reader.pos != metadata.len() {
+        bail!("Unexpected bytes after the metadata map.")
+    }
+    if !known {
+        bail!("Metadata map has no known keys.")
+    }
+    Ok(result)
 }
 
-/// Looks for known sources (Swarm, IFPS) in the CBOR hashmap.
-///
-/// Known keys have the following form: "ipfs" or "bzzr0" or "bzzr1".
-fn determine_source(m: HashMap<String, Cbor>) -> Result<Option<MetadataSource>> {
-    for key in m.keys() {
-        let Some(source) = m.get(key) else { continue };
-        // Key exists
-        if key.starts_with("ipfs") {
-            if let Cbor::Bytes(b) = source {
-                let bytes = &b.0;
-                let cid = bs58::encode(bytes).into_string();
-                return Ok(Some(MetadataSource::Ipfs(cid)));
+/// Reads CBOR data items from a byte slice.
+struct Reader<'a> {
+    data: &'a [u8],
+    pos: usize,
+}
+
+impl Reader<'_> {
+    fn take(&mut self, n: usize) -> Result<&[u8]> {
+        let end = self
+            .pos
+            .checked_add(n)
+            .filter(|end| *end <= self.data.len())
+            .ok_or_else(|| anyhow!("CBOR item is longer than the metadata."))?;
+        let bytes = &self.data[self.pos..end];
+        self.pos = end;
+        Ok(bytes)
+    }
+    /// Reads the major type and argument of the next item.
+    fn head(&mut self) -> Result<(u8, u64)> {
+        let initial = self.take(1)?[0];
+        let (major, info) = (initial >> 5, initial & 0x1f);
+        let argument = match info {
+            0..=23 => info as u64,
+            24..=27 => {
+                let size = 1 << (info - 24);
+                self.take(size)?
+                    .iter()
+                    .fold(0, |acc, b| (acc << 8) | *b as u64)
+            }
+            _ => bail!("Unsupported CBOR length encoding {}.", info),
+        };
+        Ok((major, argument))
+    }
+    fn item(&mut self) -> Result<Item> {
+        let (major, argument) = self.head()?;
+        let len = usize::try_from(argument)?;
+        Ok(match (major, argument) {
+            (0, n) => Item::Uint(n),
+            (2, _) => Item::Bytes(self.take(len)?.to_vec()),
+            (3, _) => Item::Text(String::from_utf8(self.take(len)?.to_vec())?),
+            (4, _) => {
+                if len > self.data.len() {
+                    bail!("CBOR array is longer than the metadata.")
+                }
+                Item::Array((0..len).map(|_| self.item()).collect::<Result<_>>()?)
             }
+            (7, 20) => Item::Bool(false),
+            (7, 21) => Item::Bool(true),
+            (7, 22) => Item::Null,
+            _ => bail!("Unsupported CBOR item (major type {}).", major),
+        })
+    }
+}
+
+impl Display for MetadataSource {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            MetadataSource::Ipfs(cid) => write!(f, "ipfs://{}", cid),
+            MetadataSource::Swarm(hash) => write!(f, "bzz://{}", hash),
         }
+    }
+}
 
-        if key.starts_with("bzz") {
-            if let Cbor::Bytes(b) = source {
-                let bytes = &b.0;
-                let cid = hex::encode(bytes);
-                return Ok(Some(MetadataSource::Swarm(cid)));
-            }
+impl Display for Compiler {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Compiler::Solc(version) => write!(f, "solc {}", version),
+            Compiler::Vyper(version) => write!(f, "vyper {}", version),
         }
     }
-    Ok(None)
+}
+
+impl BytecodeMetadata {
+    /// Compiler and version, noting if experimental features were used.
+    pub fn compiler_string(&self) -> Option<String> {
+        let compiler = self.compiler.as_ref()?;
+        Some(match self.experimental {
+            true => format!("{} (experimental)", compiler),
+            false => compiler.to_string(),
+        })
+    }
 }
 
 #[test]
 fn cid_extraction_1() {
     let test_metadata = "a2646970667358221220c019e4614043d8adc295c3046ba5142c603ab309adeef171f330c51c38f1498964736f6c6343000804";
     let bytes = hex::decode(test_metadata).unwrap();
-    let cid = cid_from_metadata(&bytes).unwrap().unwrap();
+    let cid = parse_metadata(&bytes).unwrap().source.unwrap();
     let expected = MetadataSource::Ipfs(String::from(
         "QmbGXtNqvZYEcbjK6xELyBQGEmzqXPDqyJNoQYjJPrST9S",
     ));
@@ -87,13 +287,109 @@ fn cid_extraction_2() {
     let test_metadata =
         "a165627a7a72305820deb4c2ccab3c2fdca32ab3f46728389c2fe2c165d5fafa07661e4e004f6c344a";
     let bytes = hex::decode(test_metadata).unwrap();
-    let cid = cid_from_metadata(&bytes).unwrap().unwrap();
+    let cid = parse_metadata(&bytes).unwrap().source.unwrap();
     let expected = MetadataSource::Swarm(String::from(
         "deb4c2ccab3c2fdca32ab3f46728389c2fe2c165d5fafa07661e4e004f6c344a",
     ));
     assert_eq!(cid, expected);
 }
 
+/// Trailers in the form emitted by each compiler variant, after some runtime code.
+///
+/// (name, trailer hex, expected compiler, experimental, has source)
+#[cfg(test)]
+const TRAILER_CORPUS: [(&str, &str, Option<&str>, bool, bool); 7] = [
+    (
+        "solc 0.8.4 ipfs",
+        "a2646970667358221220c019e4614043d8adc295c3046ba5142c603ab309adeef171f330c51c38f1498964736f6c63430008040033",
+        Some("solc 0.8.4"),
+        false,
+        true,
+    ),
+    (
+        "solc 0.4.x bzzr0",
+        "a165627a7a72305820deb4c2ccab3c2fdca32ab3f46728389c2fe2c165d5fafa07661e4e004f6c344a0029",
+        None,
+        false,
+        true,
+    ),
+    (
+        "solc 0.5.16 bzzr1",
+        "a265627a7a72315820abababababababababababababababababababababababababababababababab64736f6c63430005100032",
+        Some("solc 0.5.16"),
+        false,
+        true,
+    ),
+    (
+        "solc 0.6.6 experimental",
+        "a3646970667358221220c019e4614043d8adc295c3046ba5142c603ab309adeef171f330c51c38f149896c6578706572696d656e74616cf564736f6c63430006060041",
+        Some("solc 0.6.6"),
+        true,
+        true,
+    ),
+    (
+        "solc prerelease",
+        "a2646970667358221220c019e4614043d8adc295c3046ba5142c603ab309adeef171f330c51c38f1498964736f6c637818302e382e31382d646576656c6f702e323032322e31312e380049",
+        Some("solc 0.8.18-develop.2022.11.8"),
+        false,
+        true,
+    ),
+    (
+        "vyper 0.3.10",
+        "a16576797065728300030a000b",
+        Some("vyper 0.3.10"),
+        false,
+        false,
+    ),
+    (
+        "missing length suffix",
+        "a2646970667358221220c019e4614043d8adc295c3046ba5142c603ab309adeef171f330c51c38f1498964736f6c6343000804",
+        Some("solc 0.8.4"),
+        false,
+        true,
+    ),
+];
+
+#[test]
+fn trailer_corpus() {
+    let code = "6080604052348015600f57600080fd5b50";
+    for (name, trailer, compiler, experimental, has_source) in TRAILER_CORPUS {
+        let bytecode = hex::decode(format!("{}fe{}", code, trailer)).unwrap();
+        let Trailer::Metadata(metadata) = read_trailer(&bytecode) else {
+            panic!("No metadata found for {}", name)
+        };
+        assert_eq!(
+            metadata.compiler.map(|c| c.to_string()).as_deref(),
+            compiler,
+            "{}",
+            name
+        );
+        assert_eq!(metadata.experimental, experimental, "{}", name);
+        assert_eq!(metadata.source.is_some(), has_source, "{}", name);
+    }
+    // EIP-1167 minimal proxy, which has no metadata.
+    let proxy = hex::decode(format!(
+        "363d3d373d3d3d363d73{}5af43d82803e903d91602b57fd5bf3",
+        "be".repeat(20)
+    ))
+    .unwrap();
+    assert_eq!(read_trailer(&proxy), Trailer::Absent);
+    assert_eq!(read_trailer(&[]), Trailer::Absent);
+}
+
+#[test]
+fn constructor_args_after_trailer() {
+    let (_, trailer, ..) = TRAILER_CORPUS[0];
+    let mut input = hex::decode(format!("6080604052fe{}", trailer)).unwrap();
+    let mut arg = [0; 32];
+    arg[31] = 7;
+    input.extend(arg);
+    assert_eq!(constructor_args(&input), Some(&arg[..]));
+    let no_args = &input[..input.len() - 32];
+    assert_eq!(constructor_args(no_args), Some(&[][..]));
+    assert_eq!(constructor_args(&[0x60, 0x80]), None);
+}
+
 /// Pulls the contract metadata from runtime bytecode.
 ///
 /// Uses the final 2 bytes as the length of the metadata.
@@ -111,17 +407,13 @@ fn read_metadata(code: &[u8]) -> Result<&[u8]> {
     let len_as_slice: [u8; 2] = metadata_len_bytes.try_into()?;
     let metadata_len = u16::from_be_bytes(len_as_slice) as usize;
     let size_of_non_runtime_bytes = suffix_len + metadata_len;
-    let start = match code_len.checked_sub(size_of_non_runtime_bytes) {
-        Some(position) => position,
-        None => {
-            let msg = format!(
-                "Bytecode has unexpected form, underflow in calculation.
+    let Some(start) = code_len.checked_sub(size_of_non_runtime_bytes) else {
+        bail!(
+            "Bytecode has unexpected form, underflow in calculation.
     Total code len: {}. Non-runtime len: {}",
-                code_len, size_of_non_runtime_bytes
-            );
-            error!("{}", msg);
-            bail!(msg)
-        }
+            code_len,
+            size_of_non_runtime_bytes
+        )
     };
     let end = code_len - suffix_len;
     let m = &code[start..end];
@@ -0,0 +1,140 @@
+//! Adds data learned from APIs to the local TODD databases (opt-in).
+//!
+//! When APIs are used, signature texts from 4byte.directory and ABIs from
+//! Sourcify may be found that the local databases do not have. These are
+//! appended to raw data files in a contribution directory, and the
+//! signatures database is then extended (min-know) so that later runs do not
+//! need the APIs.
+//!
+//! Raw data layout:
+//! - `signatures.csv`: `hex_signature,text_signature` per line.
+//! - `abis/<address>.json`: a verified ABI. Kept for the TODD-ABI database,
+//!   which is pending.
+//!
+//! Nametags are not obtained from any API, so there is nothing to contribute.
+use std::{
+    collections::HashSet,
+    fs::{self, OpenOptions},
+    io::Write,
+    path::Path,
+};
+
+use anyhow::{Context, Result};
+use log::info;
+
+use crate::{
+    cache::Cache,
+    data::VerificationStatus,
+    history::{sig_to_text, Config, VisitNote},
+};
+
+const SIGNATURES_FILE: &str = "signatures.csv";
+const ABI_DIR: &str = "abis";
+
+/// Data found via APIs that is absent from the local databases.
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct Contribution {
+    /// (hex signature, text signature)
+    pub signatures: Vec<(String, String)>,
+    /// (address, JSON ABI)
+    pub abis: Vec<(String, String)>,
+}
+
+impl Contribution {
+    /// Collects cached API results that the local databases do not have.
+    pub fn learned(cache: &Cache, config: &Config) -> Self {
+        let mut signatures: Vec<(String, String)> = cache
+            .signatures
+            .iter()
+            .filter(|(_, (note, _))| *note == VisitNote::PriorSuccess)
+            .filter(|(sig, _)| !matches!(sig_to_text(sig, config), Ok(Some(_))))
+            .map(|(sig, (_, text))| (sig.to_owned(), text.to_owned()))
+            .collect();
+        signatures.sort();
+        let mut abis: Vec<(String, String)> = cache
+            .abis
+            .iter()
+            .filter(|(_, (note, (_, status)))| {
+                *note == VisitNote::PriorSuccess
+                    && matches!(
+                        status,
+                        VerificationStatus::SourcifyFullMatch
+                            | VerificationStatus::SourcifyPartialMatch
+                    )
+            })
+            .map(|(address, (_, (abi, _)))| (address.to_owned(), abi.to_owned()))
+            .collect();
+        abis.sort();
+        Contribution { signatures, abis }
+    }
+    pub fn is_empty(&self) -> bool {
+        self.signatures.is_empty() && self.abis.is_empty()
+    }
+    /// Appends the data to the raw files in a directory.
+    ///
+    /// Signatures already in the file are skipped. Returns the number of
+    /// entries written.
+    pub fn write(&self, dir: &Path) -> Result<usize> {
+        fs::create_dir_all(dir.join(ABI_DIR))
+            .with_context(|| format!("Could not create {}", dir.display()))?;
+        let path = dir.join(SIGNATURES_FILE);
+        let existing: HashSet<String> = fs::read_to_string(&path)
+            .unwrap_or_default()
+            .lines()
+            .filter_map(|line| line.split(',').next())
+            .map(str::to_owned)
+            .collect();
+        let mut file = OpenOptions::new().create(true).append(true).open(&path)?;
+        let mut written = 0;
+        for (sig, text) in &self.signatures {
+            if !existing.contains(sig) {
+                writeln!(file, "{},{}", sig, text)?;
+                written += 1;
+            }
+        }
+        for (address, abi) in &self.abis {
+            let path = dir.join(ABI_DIR).join(format!("{}.json", address));
+            if !path.exists() {
+                fs::write(path, abi)?;
+                written += 1;
+            }
+        }
+        Ok(written)
+    }
+}
+
+/// Writes learned data to the contribution directory and extends the local
+/// signatures database from it.
+pub fn contribute(cache: &Cache, config: &mut Config, dir: &Path) -> Result<Contribution> {
+    let contribution = Contribution::learned(cache, config);
+    if contribution.is_empty() {
+        info!("Nothing to contribute to the local databases");
+        return Ok(contribution);
+    }
+    let written = contribution.write(dir)?;
+    info!("Wrote {} new entries to {}", written, dir.display());
+    if written > 0 && !contribution.signatures.is_empty() {
+        config
+            .signatures_db
+            .extend()
+            .context("Could not extend the signatures database")?;
+    }
+    Ok(contribution)
+}
+
+#[test]
+fn contribution_is_appended_once() {
+    let dir = std::env::temp_dir().join(format!("psr-contribute-{}", std::process::id()));
+    let contribution = Contribution {
+        signatures: vec![(
+            String::from("ddf252ad"),
+            String::from("Transfer(address,address,uint256)"),
+        )],
+        abis: vec![("ab".repeat(20), String::from("[]"))],
+    };
+    assert_eq!(contribution.write(&dir).unwrap(), 2);
+    assert_eq!(contribution.write(&dir).unwrap(), 0);
+    let text = fs::read_to_string(dir.join(SIGNATURES_FILE)).unwrap();
+    assert_eq!(text, "ddf252ad,Transfer(address,address,uint256)\n");
+    fs::remove_dir_all(dir).unwrap();
+}
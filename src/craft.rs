@@ -0,0 +1,281 @@
+//! Unsigned transactions for planned calls, to be signed and sent from an
+//! external wallet. No keys are held here.
+//!
+//! A transaction is written as:
+//! - JSON: the fields of `eth_sendTransaction` (hex quantities).
+//! - An EIP-681 link, e.g., "ethereum:0x...@1/transfer?address=0x...&uint256=1".
+//! - Raw RLP: the unsigned EIP-1559 (type 2) payload that a wallet signs.
+//!
+//! The chain id comes from the node. The nonce (if the sender is known), gas
+//! and fees are filled in from the node where possible. Fees are the
+//! priority fee the node suggests plus twice the base fee of the latest
+//! block, so the transaction stays valid while the base fee rises.
+use clap::ValueEnum;
+use log::warn;
+use rlp::RlpStream;
+use serde_json::{json, Map, Value};
+use web3::types::{H160, U256};
+
+use anyhow::{anyhow, bail, Result};
+
+use crate::{
+    cache::Cache,
+    decode::{encode_call, signature_types},
+    history::{Config, Mode},
+    provider::Provider,
+    simulate::{resolve_function, PlannedCall},
+    verify::trimmed,
+};
+
+/// EIP-2718 type of an EIP-1559 transaction.
+const EIP1559_TYPE: u8 = 2;
+
+/// How an unsigned transaction is written.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum CraftFormat {
+    /// Fields of eth_sendTransaction.
+    #[default]
+    Json,
+    /// EIP-681 payment request link.
+    Eip681,
+    /// Unsigned EIP-1559 payload (hex).
+    Rlp,
+}
+
+/// A transaction ready to be signed, with the fields that could be filled.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct UnsignedTransaction {
+    pub chain_id: u64,
+    pub from: Option<H160>,
+    pub to: H160,
+    pub value: U256,
+    /// Text signature of the function called.
+    pub signature: String,
+    /// Arguments as given (see `encode_call`).
+    pub args: Vec<String>,
+    pub data: Vec<u8>,
+    pub nonce: Option<U256>,
+    pub gas: Option<U256>,
+    pub max_fee_per_gas: Option<U256>,
+    pub max_priority_fee_per_gas: Option<U256>,
+}
+
+/// Encodes the calldata of a planned call and fills in the transaction
+/// fields from the node.
+pub async fn craft_transaction(
+    call: &PlannedCall,
+    mode: &Mode,
+    config: &Config,
+    cache: &mut Cache,
+) -> Result<UnsignedTransaction> {
+    let signature = resolve_function(call, mode, config, cache).await?;
+    let data = encode_call(&signature, &call.args)?;
+    let provider = &config.provider;
+    let chain_id = quantity(provider, "eth_chainId", vec![])
+        .await?
+        .ok_or_else(|| anyhow!("The node did not give a chain id"))?
+        .as_u64();
+    let nonce = match call.from {
+        Some(from) => {
+            let params = vec![json!(from), json!("pending")];
+            optional(quantity(provider, "eth_getTransactionCount", params).await)
+        }
+        None => None,
+    };
+    let gas = optional(
+        provider
+            .estimate_gas(call.request(data.clone()))
+            .await
+            .map(|(gas, _)| Some(gas)),
+    );
+    let priority = optional(quantity(provider, "eth_maxPriorityFeePerGas", vec![]).await);
+    let base_fee = optional(base_fee(provider).await);
+    Ok(UnsignedTransaction {
+        chain_id,
+        from: call.from,
+        to: call.to,
+        value: call.value,
+        signature,
+        args: call.args.clone(),
+        data,
+        nonce,
+        gas,
+        max_fee_per_gas: priority.zip(base_fee).map(|(p, b)| p + b * 2),
+        max_priority_fee_per_gas: priority,
+    })
+}
+
+/// A quantity (hex) returned by a node method, if any.
+async fn quantity(provider: &Provider, method: &str, params: Vec<Value>) -> Result<Option<U256>> {
+    let (value, _) = provider.request(method, params).await?;
+    match value {
+        Value::Null => Ok(None),
+        Value::String(hex) => {
+            let digits = hex.trim_start_matches("0x");
+            Ok(Some(U256::from_str_radix(digits, 16)?))
+        }
+        other => bail!("Expected a quantity from {}, got {}", method, other),
+    }
+}
+
+/// Base fee of the latest block.
+async fn base_fee(provider: &Provider) -> Result<Option<U256>> {
+    let (head, _) = provider.head_block().await?;
+    let (block, _) = provider.block(head).await?;
+    Ok(block.and_then(|b| b.base_fee_per_gas))
+}
+
+/// A field that is left for the wallet to fill if the node could not give it.
+fn optional(result: Result<Option<U256>>) -> Option<U256> {
+    result.unwrap_or_else(|e| {
+        warn!("Left out of the transaction ({})", e);
+        None
+    })
+}
+
+impl UnsignedTransaction {
+    /// Writes the transaction in a format.
+    pub fn export(&self, format: CraftFormat) -> Result<String> {
+        match format {
+            CraftFormat::Json => Ok(serde_json::to_string_pretty(&self.to_json())?),
+            CraftFormat::Eip681 => self.eip681(),
+            CraftFormat::Rlp => Ok(format!("0x{}", hex::encode(self.rlp()?))),
+        }
+    }
+    /// Fields of eth_sendTransaction. Fields that are not known are left out.
+    pub fn to_json(&self) -> Value {
+        let hex = |value: U256| json!(format!("{:#x}", value));
+        let mut fields = Map::new();
+        fields.insert(String::from("type"), hex(U256::from(EIP1559_TYPE)));
+        fields.insert(String::from("chainId"), hex(U256::from(self.chain_id)));
+        if let Some(from) = self.from {
+            fields.insert(String::from("from"), json!(from));
+        }
+        fields.insert(String::from("to"), json!(self.to));
+        fields.insert(String::from("value"), hex(self.value));
+        let data = format!("0x{}", hex::encode(&self.data));
+        fields.insert(String::from("data"), json!(data));
+        let optional = [
+            ("nonce", self.nonce),
+            ("gas", self.gas),
+            ("maxFeePerGas", self.max_fee_per_gas),
+            ("maxPriorityFeePerGas", self.max_priority_fee_per_gas),
+        ];
+        for (name, value) in optional {
+            if let Some(value) = value {
+                fields.insert(String::from(name), hex(value));
+            }
+        }
+        Value::Object(fields)
+    }
+    /// EIP-681 link that calls the function with the arguments.
+    pub fn eip681(&self) -> Result<String> {
+        let name = &self.signature[..self.signature.find('(').unwrap_or_default()];
+        let types = signature_types(&self.signature)
+            .ok_or_else(|| anyhow!("Unsupported signature {}", self.signature))?;
+        let mut params: Vec<String> = types
+            .iter()
+            .zip(&self.args)
+            .map(|(kind, arg)| format!("{}={}", kind, percent_encode(arg)))
+            .collect();
+        if !self.value.is_zero() {
+            params.push(format!("value={}", self.value));
+        }
+        if let Some(gas) = self.gas {
+            params.push(format!("gasLimit={}", gas));
+        }
+        let mut link = format!(
+            "ethereum:0x{}@{}/{}",
+            hex::encode(self.to),
+            self.chain_id,
+            name
+        );
+        if !params.is_empty() {
+            link.push('?');
+            link.push_str(&params.join("&"));
+        }
+        Ok(link)
+    }
+    /// Unsigned EIP-1559 payload: the type, then the RLP list of fields
+    /// with an empty access list.
+    pub fn rlp(&self) -> Result<Vec<u8>> {
+        let (Some(nonce), Some(gas), Some(max_fee), Some(priority)) = (
+            self.nonce,
+            self.gas,
+            self.max_fee_per_gas,
+            self.max_priority_fee_per_gas,
+        ) else {
+            bail!("Raw transactions need a nonce (give the sender), gas and fees from the node");
+        };
+        let mut stream = RlpStream::new_list(9);
+        stream.append(&self.chain_id);
+        stream.append(&trimmed(nonce).as_slice());
+        stream.append(&trimmed(priority).as_slice());
+        stream.append(&trimmed(max_fee).as_slice());
+        stream.append(&trimmed(gas).as_slice());
+        stream.append(&self.to.as_bytes());
+        stream.append(&trimmed(self.value).as_slice());
+        stream.append(&self.data);
+        stream.begin_list(0);
+        let mut payload = vec![EIP1559_TYPE];
+        payload.extend_from_slice(&stream.out());
+        Ok(payload)
+    }
+}
+
+/// Escapes characters that are not allowed in a URL query value.
+fn percent_encode(text: &str) -> String {
+    text.bytes()
+        .map(|b| match b {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'.' | b'_' | b'~' => {
+                (b as char).to_string()
+            }
+            _ => format!("%{:02X}", b),
+        })
+        .collect()
+}
+
+#[test]
+fn unsigned_transaction_formats() {
+    let to = H160::repeat_byte(0x11);
+    let args = vec![format!("0x{}", "22".repeat(20)), String::from("1000")];
+    let mut tx = UnsignedTransaction {
+        chain_id: 1,
+        from: None,
+        to,
+        value: U256::zero(),
+        signature: String::from("transfer(address,uint256)"),
+        data: encode_call("transfer(address,uint256)", &args).unwrap(),
+        args,
+        nonce: None,
+        gas: Some(U256::from(50_000)),
+        max_fee_per_gas: None,
+        max_priority_fee_per_gas: None,
+    };
+    let json = tx.to_json();
+    assert_eq!(json["chainId"], "0x1");
+    assert_eq!(json["gas"], "0xc350");
+    assert!(json["data"].as_str().unwrap().starts_with("0xa9059cbb"));
+    assert!(json.get("nonce").is_none());
+    assert_eq!(
+        tx.eip681().unwrap(),
+        format!(
+            "ethereum:0x{}@1/transfer?address=0x{}&uint256=1000&gasLimit=50000",
+            "11".repeat(20),
+            "22".repeat(20)
+        )
+    );
+    assert!(tx.rlp().is_err());
+
+    tx.nonce = Some(U256::from(7));
+    tx.max_fee_per_gas = Some(U256::from(30_000_000_000u64));
+    tx.max_priority_fee_per_gas = Some(U256::from(1_000_000_000u64));
+    let payload = tx.rlp().unwrap();
+    assert_eq!(payload[0], EIP1559_TYPE);
+    let fields = rlp::Rlp::new(&payload[1..]);
+    assert_eq!(fields.item_count().unwrap(), 9);
+    assert_eq!(fields.val_at::<u64>(1).unwrap(), 7);
+    assert_eq!(fields.val_at::<Vec<u8>>(5).unwrap(), to.as_bytes());
+    assert_eq!(fields.val_at::<Vec<u8>>(7).unwrap(), tx.data);
+    assert_eq!(percent_encode("a b&c"), "a%20b%26c");
+}
@@ -1,37 +1,142 @@
-use std::{fmt::Display, path::PathBuf};
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    fmt::Display,
+    path::PathBuf,
+    sync::Arc,
+};
 
 use min_know::specs::address_appearance_index::AAIAppearanceTx;
 use serde::{Deserialize, Serialize};
-use web3::types::{Transaction, TransactionReceipt};
+use web3::types::{Log, Transaction, TransactionReceipt, H160, H256, U256};
 
-use crate::contract::MetadataSource;
+use crate::{
+    contract::MetadataSource,
+    decode::{decode_event, DecodedCall, DecodedEvent},
+    decompile::DecompileFailure,
+    fees::BlobInfo,
+    inspect::{hexdump, text_preview},
+    interfaces::Erc,
+    prices::FiatValue,
+    semantics::Action,
+    signatures::NameSource,
+    verify::Verification,
+};
+
+/// Bytes of log data in the hexdump of an event that was not decoded.
+const HEXDUMP_BYTES: usize = 128;
 
 /// Information about a particular logged event.
 #[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
 pub struct LoggedEvent {
-    /// Unmodified Transaction.log.
-    pub raw: web3::types::Log,
+    /// Unmodified Transaction.log. Shared, so that clones of the event (e.g.,
+    /// in merged histories and exports) do not copy the log data.
+    pub raw: Arc<web3::types::Log>,
     /// The signature of the first topic (raw event name).
     pub topic_zero: String,
     /// Address of the contract that emitted the event.
     pub contract: Contract,
     /// Decoded 4 byte log signature.
     pub name: Option<String>,
+    /// Where the name came from, and so how far to trust it.
+    #[serde(default)]
+    pub name_source: Option<NameSource>,
     /// Associated names or tags for the emitting contract.
     pub nametags: Option<Vec<String>>,
+    /// Names or tags of the addresses in the indexed topics, by topic
+    /// position (1 to 3).
+    #[serde(default)]
+    pub topic_nametags: BTreeMap<usize, Vec<String>>,
+    /// Whether the event is likely from an unsolicited airdrop (see `spam`).
+    #[serde(default)]
+    pub spam: bool,
+    /// Position of the log in its block, if the node gave it.
+    #[serde(default)]
+    pub log_index: Option<u64>,
+    /// Position of the transaction in its block.
+    #[serde(default)]
+    pub transaction_index: Option<u32>,
+    /// Lookups that failed while examining the event.
+    #[serde(default)]
+    pub warnings: Vec<Warning>,
 }
 
 /// Information about a particular transaction.
-#[derive(Debug, Default, Clone, PartialEq)]
+///
+/// Stored between runs (see `store`), so fields added later need a default.
+#[derive(Debug, Default, Clone, PartialEq, Deserialize, Serialize)]
+#[serde(default)]
 pub struct TxInfo {
     /// A block number and index.
+    #[serde(with = "Appearance")]
     pub location: AAIAppearanceTx,
+    /// Name of the chain the transaction is on (see `chains`).
+    pub chain: String,
     /// Data from eth_getTransactionByBlockNumberAndIndex.
     pub description: Option<Transaction>,
     /// Receipt from eth_getTransactionReceipt.
     pub receipt: Option<TransactionReceipt>,
+    /// Unix timestamp of the block, from eth_getBlockByNumber.
+    pub timestamp: Option<u64>,
+    /// Base fee per gas of the block (London and later), from eth_getBlockByNumber.
+    pub base_fee: Option<U256>,
+    /// Miner or fee recipient of the block, from eth_getBlockByNumber.
+    pub miner: Option<H160>,
+    /// Nametags of the miner or fee recipient.
+    pub miner_nametags: Option<Vec<String>>,
+    /// Latest block of the chain when the block was fetched, for the
+    /// confirmations.
+    pub head_block: Option<u64>,
     /// Events extracted from the Transaction.
     pub events: Option<Vec<LoggedEvent>>,
+    /// Function and arguments decoded from the calldata.
+    pub call: Option<DecodedCall>,
+    /// Constructor arguments, for a transaction that creates a contract.
+    pub constructor: Option<DecodedCall>,
+    /// Text sent as the input of a transaction to an account (not a
+    /// contract), e.g., a note attached to an ether transfer.
+    pub message: Option<String>,
+    /// Blobs carried by the transaction (EIP-4844).
+    pub blob: Option<BlobInfo>,
+    /// Human readable interpretation of the transaction.
+    pub action: Option<Action>,
+    /// RPC endpoints that provided the transaction, receipt and block data.
+    pub providers: BTreeSet<String>,
+    /// Whether the receipt was checked against the block header.
+    pub verification: Option<Verification>,
+    /// Value of the ether and tokens moved, if priced (see `prices`).
+    pub fiat: Option<FiatValue>,
+    /// Lookups that failed while decoding the calldata.
+    pub warnings: Vec<Warning>,
+    /// The logs held by the events and the logs bloom were dropped from the
+    /// receipt (see `Config::light`).
+    pub pruned: bool,
+}
+
+impl TxInfo {
+    /// Blocks from the block of the transaction to the latest block when it
+    /// was fetched, counting both.
+    pub fn confirmations(&self) -> Option<u64> {
+        let head = self.head_block?;
+        Some((head + 1).saturating_sub(self.location.block.into()))
+    }
+    /// Logs of the receipt, including those kept by the events once the
+    /// receipt was pruned, in order.
+    pub fn logs(&self) -> Vec<&Log> {
+        let mut logs: Vec<&Log> = self.receipt.iter().flat_map(|r| &r.logs).collect();
+        if self.pruned {
+            logs.extend(self.events.iter().flatten().map(|e| &*e.raw));
+            logs.sort_by_key(|log| log.log_index);
+        }
+        logs
+    }
+}
+
+/// Serialization of an appearance from the index.
+#[derive(Deserialize, Serialize)]
+#[serde(remote = "AAIAppearanceTx")]
+pub(crate) struct Appearance {
+    block: u32,
+    index: u32,
 }
 
 #[derive(Debug, Default, Clone, PartialEq, Eq, Deserialize, Serialize)]
@@ -48,10 +153,153 @@ pub struct Contract {
     pub abi: Option<String>,
     /// Flag for whether the contract data is from the source or is decompiled.
     pub decompiled: bool,
+    /// Whether the bytecode was decompiled for want of a verified ABI.
+    #[serde(default)]
+    pub decompile: DecompileStatus,
+    /// Functions found by decompiling the bytecode.
+    #[serde(default)]
+    pub functions: Vec<RecoveredFunction>,
+    /// How the ABI was obtained, and so how much a decoded name can be trusted.
+    pub verification: VerificationStatus,
+    /// Standard interfaces the contract implements.
+    pub interfaces: Vec<Erc>,
+    /// Compiler recorded in the bytecode metadata (e.g., "solc 0.8.4").
+    pub compiler: Option<String>,
+    /// Code of the contract at the block of the event.
+    #[serde(default)]
+    pub code_at_emission: CodeStatus,
+    /// Code of the contract at the latest block.
+    #[serde(default)]
+    pub code_current: CodeStatus,
+    /// The contract emitted events but has no code now (e.g., after a
+    /// selfdestruct).
+    #[serde(default)]
+    pub destructed: bool,
+    /// Who deployed the contract, if looked up (see `Config::deployer_lookups`).
+    #[serde(default)]
+    pub deployer: Option<Deployer>,
+}
+
+/// The account or factory contract that deployed a contract.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
+pub struct Deployer {
+    pub address: H160,
+    /// The deploying transaction.
+    pub transaction: H256,
+    /// Names or tags of the deployer.
+    pub nametags: Vec<String>,
+}
+
+/// Whether a contract had code at a block.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
+pub enum CodeStatus {
+    /// The code was not fetched.
+    #[default]
+    NotChecked,
+    /// The node could not give the code (e.g., a node that does not keep
+    /// the state of old blocks).
+    Unavailable,
+    /// Code was deployed.
+    Deployed,
+    /// Code was deployed, but not the code at the block of the event (e.g.,
+    /// the contract was destroyed and created again).
+    Changed,
+    /// No code was deployed (e.g., the contract was destroyed).
+    Empty,
+}
+
+/// The origin of the ABI for a contract, from most to least trustworthy.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
+pub enum VerificationStatus {
+    /// Source verified by Sourcify, including the metadata hash.
+    SourcifyFullMatch,
+    /// Source verified by Sourcify, but the metadata hash differs.
+    SourcifyPartialMatch,
+    /// Only the metadata link (IPFS/Swarm) in the bytecode is known.
+    MetadataOnly,
+    /// ABI recovered by decompiling the bytecode.
+    DecompiledOnly,
+    /// Nothing is known about the origin of the contract.
+    #[default]
+    Unknown,
+}
+
+/// A function found by decompiling a contract.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
+pub struct RecoveredFunction {
+    /// 4 byte selector as hex, without "0x".
+    pub selector: String,
+    /// Text signature from the signature sources (e.g., "transfer(address,uint256)").
+    pub signature: Option<String>,
+}
+
+/// A lookup that failed without stopping the history, e.g., an ABI that
+/// could not be found. The item is shown without the missing information.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
+pub struct Warning {
+    pub kind: WarningKind,
+    /// What was looked up: an address or a selector (hex).
+    pub subject: String,
+    /// Why the lookup failed.
+    pub reason: String,
+}
+
+/// What a failed lookup was for.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Deserialize, Serialize)]
+pub enum WarningKind {
+    Abi,
+    Decompile,
+    Signature,
+    Nametags,
+}
+
+/// Progress of decompiling a contract.
+#[derive(Debug, Default, Clone, PartialEq, Eq, Deserialize, Serialize)]
+pub enum DecompileStatus {
+    /// Not needed, or not tried (e.g., without APIs).
+    #[default]
+    NotAttempted,
+    /// Queued or running when the contract was examined.
+    Pending,
+    Decompiled,
+    /// Calls to the contract are decoded heuristically instead.
+    Failed(DecompileFailure),
+}
+
+impl VerificationStatus {
+    /// Upgrades an unknown status if the bytecode contained a metadata link.
+    pub fn or_metadata(self, metadata: &Option<MetadataSource>) -> Self {
+        match (self, metadata) {
+            (VerificationStatus::Unknown, Some(_)) => VerificationStatus::MetadataOnly,
+            (status, _) => status,
+        }
+    }
+}
+
+impl Warning {
+    pub fn new(kind: WarningKind, subject: impl Display, reason: impl Display) -> Self {
+        Warning {
+            kind,
+            subject: subject.to_string(),
+            reason: reason.to_string(),
+        }
+    }
+}
+
+impl WarningKind {
+    /// Name of the lookup, e.g., for counts.
+    pub fn lookup(&self) -> &'static str {
+        match self {
+            WarningKind::Abi => "ABI",
+            WarningKind::Decompile => "decompile",
+            WarningKind::Signature => "signature",
+            WarningKind::Nametags => "nametag",
+        }
+    }
 }
 
 impl LoggedEvent {
-    fn nametag_string(&self) -> String {
+    pub fn nametag_string(&self) -> String {
         let mut nametags = String::new();
         match &self.nametags {
             Some(tags) => {
@@ -78,27 +326,84 @@ impl LoggedEvent {
         }
         let sig = format!(" event ({})", self.topic_zero);
         event.push_str(&sig);
+        if let Some(source) = self.name_source {
+            event.push_str(&format!(" (name from {})", source));
+        }
         event.to_owned()
     }
+    /// Arguments decoded with the ABI of the contract, if the event is in it.
+    /// Names of the contract are the candidates for hashed indexed values.
+    pub fn decoded(&self) -> Option<DecodedEvent> {
+        let abi = self.contract.abi.as_deref()?;
+        let candidates = self.nametags.as_deref().unwrap_or_default();
+        decode_event(abi, &self.raw.topics, &self.raw.data.0, candidates)
+    }
     fn topics_string(&self) -> String {
         let mut t = format!("{}", self.raw.topics.len());
         for (i, topic) in self.raw.topics.iter().enumerate() {
             t.push_str(&format!(", topic {} {}", i + 1, topic));
+            if let Some(tags) = self.topic_tags(i) {
+                t.push_str(&format!(" {}", tags));
+            }
         }
         t
     }
+    /// Names of the address in a topic, as "|name|tag|", if it has any.
+    pub fn topic_tags(&self, position: usize) -> Option<String> {
+        let tags = self.topic_nametags.get(&position)?;
+        Some(format!("|{}|", tags.join("|")))
+    }
 }
 
 impl Display for LoggedEvent {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         write!(f, "{}", self.event_string())?;
-        write!(f, "\n\t\t{} contract", self.nametag_string())?;
+        write!(
+            f,
+            "\n\t\t{} contract ({}",
+            self.nametag_string(),
+            self.contract.verification
+        )?;
+        for erc in &self.contract.interfaces {
+            write!(f, ", {}", erc)?;
+        }
+        if let Some(compiler) = &self.contract.compiler {
+            write!(f, ", {}", compiler)?;
+        }
+        if let DecompileStatus::Failed(reason) = &self.contract.decompile {
+            write!(f, ", not decompiled: {}", reason)?;
+        }
+        if self.contract.destructed {
+            write!(f, ", contract no longer deployed")?;
+        }
+        if let Some(deployer) = &self.contract.deployer {
+            write!(f, ", deployed by {}", deployer)?;
+        }
+        write!(f, ")")?;
         write!(f, "\n\t\t\tTopic values: {}", self.topics_string())?;
-        write!(f, "\n\t\t\tData: {} bytes.", self.raw.data.0.len())?;
+        let data = &self.raw.data.0;
+        write!(f, "\n\t\t\tData: {} bytes.", data.len())?;
+        if !data.is_empty() && self.decoded().is_none() {
+            for line in hexdump(data, HEXDUMP_BYTES) {
+                write!(f, "\n\t\t\t\t{}", line)?;
+            }
+            if let Some(text) = text_preview(data) {
+                write!(f, "\n\t\t\tText: {:?}", text)?;
+            }
+        }
         write!(f, "")
     }
 }
 
+impl Display for Deployer {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        if !self.nametags.is_empty() {
+            write!(f, "|{}| ", self.nametags.join("|"))?;
+        }
+        write!(f, "0x{}", hex::encode(self.address))
+    }
+}
+
 impl Display for Contract {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         let abi = match &self.abi {
@@ -107,8 +412,61 @@ impl Display for Contract {
         };
         write!(
             f,
-            "contract address {}, (abi sample: '{}', decomplied status: {})",
-            self.address, abi, self.decompiled
+            "contract address {}, (abi sample: '{}', decomplied status: {}, verification: {})",
+            self.address, abi, self.decompile, self.verification
         )
     }
 }
+
+impl Display for RecoveredFunction {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match &self.signature {
+            Some(signature) => write!(f, "{}", signature),
+            None => write!(f, "0x{}", self.selector),
+        }
+    }
+}
+
+impl Display for DecompileStatus {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            DecompileStatus::NotAttempted => write!(f, "not attempted"),
+            DecompileStatus::Pending => write!(f, "pending"),
+            DecompileStatus::Decompiled => write!(f, "decompiled"),
+            DecompileStatus::Failed(reason) => {
+                write!(f, "failed, {}, decoded heuristically", reason)
+            }
+        }
+    }
+}
+
+impl Display for Warning {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{} {}: {}", self.kind, self.subject, self.reason)
+    }
+}
+
+impl Display for WarningKind {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let s = match self {
+            WarningKind::Abi => "ABI of",
+            WarningKind::Decompile => "Decompiling",
+            WarningKind::Signature => "Signature",
+            WarningKind::Nametags => "Nametags of",
+        };
+        write!(f, "{}", s)
+    }
+}
+
+impl Display for VerificationStatus {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let s = match self {
+            VerificationStatus::SourcifyFullMatch => "sourcify full match",
+            VerificationStatus::SourcifyPartialMatch => "sourcify partial match",
+            VerificationStatus::MetadataOnly => "metadata only",
+            VerificationStatus::DecompiledOnly => "decompiled only",
+            VerificationStatus::Unknown => "unverified",
+        };
+        write!(f, "{}", s)
+    }
+}
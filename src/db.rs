@@ -0,0 +1,273 @@
+//! Maintenance of the local TODD databases (min-know), so that their data can
+//! be kept fresh from this tool.
+//!
+//! - Status: each database is checked against its manifest.
+//! - Extend: new volumes are made from the raw data of each database (e.g.,
+//!   newer appearance index chunks), then its manifest is written again.
+//!
+//! The files added or changed by an extension are reported for databases in
+//! a custom directory. min-know does not expose where the default and sample
+//! data are kept.
+use std::{
+    collections::BTreeMap,
+    fmt::Display,
+    fs,
+    path::{Path, PathBuf},
+};
+
+use anyhow::Result;
+use clap::ValueEnum;
+use min_know::{
+    config::{
+        address_appearance_index::Network,
+        choices::{DataKind, DirNature},
+    },
+    database::types::Todd,
+    specs::{
+        address_appearance_index::AAISpec, nametags::NameTagsSpec, signatures::SignaturesSpec,
+    },
+};
+
+/// A local TODD database.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, ValueEnum)]
+pub enum Database {
+    /// Address appearances (the transactions of each address).
+    Appearances,
+    /// Text signatures of functions and events.
+    Signatures,
+    /// Names and tags of addresses.
+    Nametags,
+}
+
+/// How a database compares with its manifest.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DatabaseStatus {
+    pub database: Database,
+    /// Directory of the database, if custom.
+    pub directory: Option<PathBuf>,
+    /// Why the database is incomplete, if it is.
+    pub problem: Option<String>,
+}
+
+/// What extending a database did.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Extension {
+    pub database: Database,
+    /// Why the database could not be extended, if it could not.
+    pub error: Option<String>,
+    /// Files added and changed, if the directory is known.
+    pub changes: Option<Changes>,
+    /// Status after the extension.
+    pub status: DatabaseStatus,
+}
+
+/// Files that differ between two snapshots of a directory.
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct Changes {
+    pub added: Vec<PathBuf>,
+    pub changed: Vec<PathBuf>,
+}
+
+/// Length and modification time of each file, by path relative to the
+/// directory.
+type Snapshot = BTreeMap<PathBuf, (u64, Option<std::time::SystemTime>)>;
+
+impl Database {
+    pub const ALL: [Database; 3] = [
+        Database::Appearances,
+        Database::Signatures,
+        Database::Nametags,
+    ];
+
+    /// Kind of data, for min-know.
+    pub fn kind(&self) -> DataKind {
+        match self {
+            Database::Appearances => DataKind::AddressAppearanceIndex(Network::default()),
+            Database::Signatures => DataKind::Signatures,
+            Database::Nametags => DataKind::NameTags,
+        }
+    }
+    /// Checks the data against the manifest.
+    fn check(&self, directories: &DirNature) -> Result<()> {
+        let kind = self.kind();
+        let directories = directories.clone();
+        match self {
+            Database::Appearances => Todd::<AAISpec>::init(kind, directories)?.check_completeness(),
+            Database::Signatures => {
+                Todd::<SignaturesSpec>::init(kind, directories)?.check_completeness()
+            }
+            Database::Nametags => {
+                Todd::<NameTagsSpec>::init(kind, directories)?.check_completeness()
+            }
+        }
+    }
+    /// Adds volumes from new raw data and writes the manifest again.
+    fn extend(&self, directories: &DirNature) -> Result<()> {
+        let kind = self.kind();
+        let directories = directories.clone();
+        match self {
+            Database::Appearances => {
+                let mut db = Todd::<AAISpec>::init(kind, directories)?;
+                db.extend()?;
+                db.generate_manifest()
+            }
+            Database::Signatures => {
+                let mut db = Todd::<SignaturesSpec>::init(kind, directories)?;
+                db.extend()?;
+                db.generate_manifest()
+            }
+            Database::Nametags => {
+                let mut db = Todd::<NameTagsSpec>::init(kind, directories)?;
+                db.extend()?;
+                db.generate_manifest()
+            }
+        }
+    }
+}
+
+/// Checks databases, each in its directories, against their manifests.
+pub fn status(databases: &[(Database, DirNature)]) -> Vec<DatabaseStatus> {
+    databases
+        .iter()
+        .map(|(database, directories)| DatabaseStatus {
+            database: *database,
+            directory: custom_directory(directories),
+            problem: database.check(directories).err().map(|e| e.to_string()),
+        })
+        .collect()
+}
+
+/// Extends databases, each in its directories, one at a time. A database
+/// that cannot be extended does not stop the others.
+pub fn extend(databases: &[(Database, DirNature)]) -> Vec<Extension> {
+    databases
+        .iter()
+        .map(|(database, directories)| {
+            let directory = custom_directory(directories);
+            let before = directory.as_deref().map(snapshot);
+            let error = database.extend(directories).err().map(|e| e.to_string());
+            let changes = before
+                .zip(directory.as_deref())
+                .map(|(before, dir)| changes(&before, &snapshot(dir)));
+            Extension {
+                database: *database,
+                error,
+                changes,
+                status: status(&[(*database, directories.clone())]).remove(0),
+            }
+        })
+        .collect()
+}
+
+fn custom_directory(directories: &DirNature) -> Option<PathBuf> {
+    match directories {
+        DirNature::Custom(path) => Some(path.clone()),
+        DirNature::Sample | DirNature::Default => None,
+    }
+}
+
+/// Files under a directory. Unreadable entries are left out.
+fn snapshot(dir: &Path) -> Snapshot {
+    let mut files = Snapshot::new();
+    let mut pending = vec![dir.to_path_buf()];
+    while let Some(current) = pending.pop() {
+        let Ok(entries) = fs::read_dir(&current) else {
+            continue;
+        };
+        for entry in entries.flatten() {
+            let path = entry.path();
+            let Ok(metadata) = entry.metadata() else {
+                continue;
+            };
+            if metadata.is_dir() {
+                pending.push(path);
+            } else if let Ok(relative) = path.strip_prefix(dir) {
+                let modified = metadata.modified().ok();
+                files.insert(relative.to_path_buf(), (metadata.len(), modified));
+            }
+        }
+    }
+    files
+}
+
+fn changes(before: &Snapshot, after: &Snapshot) -> Changes {
+    let mut changes = Changes::default();
+    for (path, file) in after {
+        match before.get(path) {
+            None => changes.added.push(path.clone()),
+            Some(old) if old != file => changes.changed.push(path.clone()),
+            Some(_) => {}
+        }
+    }
+    changes
+}
+
+impl Display for Database {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let name = match self {
+            Database::Appearances => "appearances",
+            Database::Signatures => "signatures",
+            Database::Nametags => "nametags",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+impl Display for DatabaseStatus {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.database)?;
+        if let Some(dir) = &self.directory {
+            write!(f, " ({})", dir.display())?;
+        }
+        match &self.problem {
+            None => write!(f, ": complete"),
+            Some(problem) => write!(f, ": incomplete ({})", problem),
+        }
+    }
+}
+
+impl Display for Extension {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match &self.error {
+            None => write!(f, "Extended {}", self.database)?,
+            Some(e) => write!(f, "Could not extend {} ({})", self.database, e)?,
+        }
+        match &self.changes {
+            Some(changes) => write!(
+                f,
+                ": {} files added, {} changed",
+                changes.added.len(),
+                changes.changed.len()
+            )?,
+            None => write!(f, ": changes not listed for the default and sample data")?,
+        }
+        for path in self.changes.iter().flat_map(|c| &c.added) {
+            write!(f, "\n\tAdded {}", path.display())?;
+        }
+        write!(f, "\n\tNow {}", self.status)
+    }
+}
+
+#[test]
+fn changed_files_listed() {
+    let dir = std::env::temp_dir().join(format!("psr-db-{}", std::process::id()));
+    fs::create_dir_all(dir.join("chapter_0x00")).unwrap();
+    fs::write(dir.join("manifest.json"), "{}").unwrap();
+    fs::write(dir.join("chapter_0x00/volume_0.ssz"), "a").unwrap();
+    let before = snapshot(&dir);
+    assert_eq!(before.len(), 2);
+
+    fs::write(dir.join("manifest.json"), r#"{"volumes":2}"#).unwrap();
+    fs::write(dir.join("chapter_0x00/volume_1.ssz"), "b").unwrap();
+    let found = changes(&before, &snapshot(&dir));
+    assert_eq!(
+        found.added,
+        vec![PathBuf::from("chapter_0x00/volume_1.ssz")]
+    );
+    assert_eq!(found.changed, vec![PathBuf::from("manifest.json")]);
+    fs::remove_dir_all(&dir).unwrap();
+
+    let custom = DirNature::Custom(dir.clone());
+    assert_eq!(custom_directory(&custom), Some(dir));
+    assert_eq!(custom_directory(&DirNature::Sample), None);
+}
@@ -0,0 +1,774 @@
+//! Decodes transaction calldata for contracts without a verified ABI.
+//!
+//! The 4 byte selector is resolved to a text signature where possible (from
+//! the local database, or the function signatures at 4byte.directory), and
+//! each argument is read using the types in that signature. Without a usable
+//! signature, the type of each 32 byte word is guessed from its shape. Either
+//! way the result is a guess and is labelled as heuristic.
+//!
+//! Events are decoded with the ABI of their contract (see `decode_event`).
+//! Indexed arguments of a dynamic type (string, bytes, arrays and tuples) are
+//! not in the log: the topic is the keccak hash of the value. These are shown
+//! as hashes, or as the candidate value that hashes to the topic.
+use std::fmt::Display;
+
+use anyhow::{anyhow, bail, Context, Result};
+use ethereum_types::{H160, H256, U256};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+use crate::{
+    abi::{data_word, dynamic_bytes, keccak256, selector_of, topic_of},
+    signatures::NameSource,
+};
+
+/// Calldata decoded into a function and arguments.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
+#[non_exhaustive]
+pub struct DecodedCall {
+    /// 4 byte selector, hex without "0x".
+    pub selector: String,
+    /// Text signature (e.g., "transfer(address,uint256)"), if known.
+    pub signature: Option<String>,
+    pub params: Vec<DecodedParam>,
+    /// True if the types were inferred rather than read from a verified ABI.
+    pub heuristic: bool,
+    /// Where the signature came from, if looked up.
+    #[serde(default)]
+    pub source: Option<NameSource>,
+}
+
+/// A single decoded argument.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
+#[non_exhaustive]
+pub struct DecodedParam {
+    pub kind: ParamKind,
+    pub value: String,
+}
+
+/// Type of an argument, as declared in a signature or guessed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
+#[non_exhaustive]
+pub enum ParamKind {
+    Address,
+    Uint,
+    Int,
+    Bool,
+    /// bytes1 to bytes32, left aligned.
+    FixedBytes,
+    String,
+    /// Dynamic bytes, or any other dynamic type.
+    Bytes,
+    /// Raw 32 byte word of unknown or unsupported type (e.g., a tuple).
+    Word,
+    /// Keccak hash of an indexed argument of a dynamic type, in place of the
+    /// value (see `decode_event`).
+    IndexedHash,
+}
+
+/// An event decoded using the ABI of its contract.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
+#[non_exhaustive]
+pub struct DecodedEvent {
+    /// Text signature, e.g., "Transfer(address,address,uint256)".
+    pub signature: String,
+    /// Arguments in the order of the ABI.
+    pub params: Vec<EventParam>,
+}
+
+/// A decoded argument of an event.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
+#[non_exhaustive]
+pub struct EventParam {
+    /// Name in the ABI, which may be empty.
+    pub name: String,
+    /// Whether the argument is a topic rather than in the log data.
+    pub indexed: bool,
+    pub kind: ParamKind,
+    pub value: String,
+}
+
+/// The part of a decoded call that is the same for every call to a function.
+///
+/// Cached by selector and contract so that guesses are made once.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
+#[non_exhaustive]
+pub struct CallShape {
+    pub signature: Option<String>,
+    pub kinds: Vec<ParamKind>,
+}
+
+impl CallShape {
+    /// Reads the types from a signature, or guesses them from the calldata.
+    pub fn new(signature: Option<String>, calldata: &[u8]) -> Self {
+        let args = calldata.get(4..).unwrap_or_default();
+        let kinds = signature
+            .as_deref()
+            .and_then(kinds_from_signature)
+            .unwrap_or_else(|| guess_kinds(args));
+        CallShape { signature, kinds }
+    }
+    /// Decodes calldata using the shape.
+    pub fn decode(&self, calldata: &[u8]) -> Option<DecodedCall> {
+        let selector = hex::encode(calldata.get(..4)?);
+        Some(DecodedCall {
+            selector,
+            signature: self.signature.clone(),
+            params: self.decode_args(&calldata[4..]),
+            heuristic: true,
+            source: None,
+        })
+    }
+    /// Decodes ABI encoded arguments (without a selector).
+    fn decode_args(&self, args: &[u8]) -> Vec<DecodedParam> {
+        self.kinds
+            .iter()
+            .enumerate()
+            .map_while(|(n, kind)| {
+                Some(DecodedParam {
+                    kind: *kind,
+                    value: read_param(args, n, *kind)?,
+                })
+            })
+            .collect()
+    }
+}
+
+/// Decodes constructor arguments, using the constructor in a JSON ABI if
+/// available and guessing the types otherwise.
+pub fn decode_constructor(args: &[u8], abi: Option<&str>) -> DecodedCall {
+    let declared = abi.and_then(constructor_signature);
+    let heuristic = declared.is_none();
+    let kinds = declared
+        .as_deref()
+        .and_then(kinds_from_signature)
+        .unwrap_or_else(|| guess_kinds(args));
+    let signature = declared.unwrap_or_else(|| {
+        let kinds: Vec<String> = kinds.iter().map(|k| k.to_string()).collect();
+        format!("constructor({})", kinds.join(","))
+    });
+    let shape = CallShape {
+        signature: Some(signature),
+        kinds,
+    };
+    DecodedCall {
+        selector: String::new(),
+        signature: shape.signature.clone(),
+        params: shape.decode_args(args),
+        heuristic,
+        source: None,
+    }
+}
+
+/// Decodes an event using the entry for topic zero in a JSON ABI.
+///
+/// The value of an indexed argument of a dynamic type is replaced by its
+/// hash. If one of the candidates (e.g., names known for the contract) hashes
+/// to the topic, it is shown instead. Candidates are compared to strings as
+/// text and to bytes as hex, while arrays and tuples are left as hashes.
+///
+/// Returns None if the event is not in the ABI, or if the topics or data do
+/// not fit its arguments (e.g., an ERC-721 Transfer with an ERC-20 ABI).
+pub fn decode_event(
+    abi: &str,
+    topics: &[H256],
+    data: &[u8],
+    candidates: &[String],
+) -> Option<DecodedEvent> {
+    let Ok(Value::Array(items)) = serde_json::from_str::<Value>(abi) else {
+        return None;
+    };
+    let topic = topics.first()?;
+    let (signature, inputs) = items
+        .iter()
+        .filter(|item| item["type"] == "event" && item["anonymous"] != true)
+        .find_map(|item| {
+            let signature = abi_signature(item["name"].as_str()?, &item["inputs"])?;
+            let inputs = item["inputs"].as_array()?;
+            (topic_of(&signature) == *topic).then_some((signature, inputs))
+        })?;
+    let indexed = inputs.iter().filter(|i| i["indexed"] == true).count();
+    if indexed + 1 != topics.len() {
+        return None;
+    }
+    let types = inputs
+        .iter()
+        .map(canonical_type)
+        .collect::<Option<Vec<String>>>()?;
+    let (mut topic_n, mut data_n) = (1, 0);
+    let mut params = vec![];
+    for (input, t) in inputs.iter().zip(&types) {
+        let indexed = input["indexed"] == true;
+        let kind = kind_of(t);
+        let (kind, value) = match indexed {
+            true if is_dynamic(t) => {
+                let hash = topics[topic_n];
+                (ParamKind::IndexedHash, hashed_value(t, &hash, candidates))
+            }
+            true => (kind, read_param(topics[topic_n].as_bytes(), 0, kind)?),
+            false => (kind, read_param(data, data_n, kind)?),
+        };
+        match indexed {
+            true => topic_n += 1,
+            false => data_n += 1,
+        }
+        let name = input["name"].as_str().unwrap_or_default().to_string();
+        params.push(EventParam {
+            name,
+            indexed,
+            kind,
+            value,
+        });
+    }
+    Some(DecodedEvent { signature, params })
+}
+
+/// Whether a type is dynamic, and so hashed when indexed.
+fn is_dynamic(t: &str) -> bool {
+    t == "string" || t == "bytes" || t.ends_with(']') || t.starts_with('(')
+}
+
+/// The candidate that hashes to an indexed topic, or the topic as hex.
+fn hashed_value(t: &str, hash: &H256, candidates: &[String]) -> String {
+    let found = candidates.iter().find(|c| {
+        let bytes = match t {
+            "string" => c.as_bytes().to_vec(),
+            "bytes" => match c.strip_prefix("0x").map(hex::decode) {
+                Some(Ok(bytes)) => bytes,
+                _ => return false,
+            },
+            _ => return false,
+        };
+        keccak256(&bytes) == hash.0
+    });
+    match (found, t) {
+        (Some(c), "string") => format!("{:?} (hash matched)", c),
+        (Some(c), _) => format!("{} (hash matched)", c),
+        (None, _) => format!("0x{}", hex::encode(hash)),
+    }
+}
+
+/// Decodes the data returned by a call to a function, using the outputs of
+/// the function in a JSON ABI if available and guessing the types otherwise.
+pub fn decode_return(signature: &str, data: &[u8], abi: Option<&str>) -> DecodedCall {
+    let declared = abi.and_then(|abi| output_types(abi, signature));
+    let kinds = declared
+        .as_deref()
+        .and_then(kinds_from_signature)
+        .unwrap_or_else(|| guess_kinds(data));
+    let shape = CallShape {
+        signature: Some(signature.to_string()),
+        kinds,
+    };
+    DecodedCall {
+        selector: hex::encode(selector_of(signature)),
+        signature: shape.signature.clone(),
+        params: shape.decode_args(data),
+        heuristic: declared.is_none(),
+        source: None,
+    }
+}
+
+/// ABI encodes a call from a text signature such as "transfer(address,uint256)"
+/// and one value per argument.
+///
+/// Values are written as they are shown once decoded: addresses and bytes as
+/// hex, integers in decimal (or hex with "0x") and booleans as true or false.
+/// Arrays and tuples are not supported.
+pub fn encode_call(signature: &str, args: &[String]) -> Result<Vec<u8>> {
+    let types =
+        signature_types(signature).ok_or_else(|| anyhow!("Unsupported signature {}", signature))?;
+    if types.len() != args.len() {
+        bail!(
+            "{} takes {} arguments, got {}",
+            signature,
+            types.len(),
+            args.len()
+        );
+    }
+    let mut head = vec![];
+    let mut tail = vec![];
+    for (kind, arg) in types.iter().zip(args) {
+        let context = || format!("Invalid {} argument {}", kind, arg);
+        match *kind {
+            "string" => tail_bytes(arg.as_bytes(), types.len(), &mut head, &mut tail),
+            "bytes" => {
+                let bytes = hex::decode(arg.trim_start_matches("0x")).with_context(context)?;
+                tail_bytes(&bytes, types.len(), &mut head, &mut tail);
+            }
+            _ => head.extend(encode_word(kind, arg).with_context(context)?),
+        }
+    }
+    let mut calldata = selector_of(signature).to_vec();
+    calldata.extend(head);
+    calldata.extend(tail);
+    Ok(calldata)
+}
+
+/// Adds a dynamic argument: its offset to the head, and its length and
+/// padded bytes to the tail.
+fn tail_bytes(bytes: &[u8], args: usize, head: &mut Vec<u8>, tail: &mut Vec<u8>) {
+    head.extend(word(U256::from(args * 32 + tail.len())));
+    tail.extend(word(U256::from(bytes.len())));
+    tail.extend(bytes);
+    tail.resize(tail.len().div_ceil(32) * 32, 0);
+}
+
+/// Encodes a value of a static type as a 32 byte word.
+fn encode_word(kind: &str, value: &str) -> Result<[u8; 32]> {
+    let bits = |prefix: &str| -> Result<usize> {
+        match kind.strip_prefix(prefix).unwrap_or_default() {
+            "" => Ok(256),
+            n => Ok(n.parse()?),
+        }
+    };
+    Ok(match kind {
+        "address" => {
+            let address: H160 = value.trim_start_matches("0x").parse()?;
+            let mut w = [0; 32];
+            w[12..].copy_from_slice(address.as_bytes());
+            w
+        }
+        "bool" => word(U256::from(value.parse::<bool>()? as u8)),
+        k if k.starts_with("uint") => {
+            let n = parse_integer(value)?;
+            if n.bits() > bits("uint")? {
+                bail!("Out of range");
+            }
+            word(n)
+        }
+        k if k.starts_with("int") => {
+            let (negative, digits) = match value.strip_prefix('-') {
+                Some(digits) => (true, digits),
+                None => (false, value),
+            };
+            let n = parse_integer(digits)?;
+            if n.bits() >= bits("int")? {
+                bail!("Out of range");
+            }
+            match negative {
+                true => word((!n).overflowing_add(U256::one()).0),
+                false => word(n),
+            }
+        }
+        k if k.starts_with("bytes") => {
+            let bytes = hex::decode(value.trim_start_matches("0x"))?;
+            if bytes.len() > bits("bytes")? {
+                bail!("Longer than {}", kind);
+            }
+            let mut w = [0; 32];
+            w[..bytes.len()].copy_from_slice(&bytes);
+            w
+        }
+        _ => bail!("Encoding {} is not supported", kind),
+    })
+}
+
+/// An integer in decimal, or in hex with "0x".
+fn parse_integer(value: &str) -> Result<U256> {
+    match value.strip_prefix("0x") {
+        Some(hex) => Ok(U256::from_str_radix(hex, 16)?),
+        None => Ok(U256::from_dec_str(value)?),
+    }
+}
+
+fn word(value: U256) -> [u8; 32] {
+    let mut w = [0; 32];
+    value.to_big_endian(&mut w);
+    w
+}
+
+/// Output types of a function in a JSON ABI, e.g., "(uint256,address)".
+fn output_types(abi: &str, signature: &str) -> Option<String> {
+    let Ok(Value::Array(items)) = serde_json::from_str::<Value>(abi) else {
+        return None;
+    };
+    let function = items.iter().find(|item| {
+        item["type"] == "function"
+            && item["name"]
+                .as_str()
+                .and_then(|name| abi_signature(name, &item["inputs"]))
+                .is_some_and(|s| s == signature)
+    })?;
+    abi_signature("", &function["outputs"])
+}
+
+/// Signature of the constructor in a JSON ABI, e.g., "constructor(address,uint256)".
+///
+/// An ABI without a constructor has one with no arguments.
+fn constructor_signature(abi: &str) -> Option<String> {
+    let Ok(Value::Array(items)) = serde_json::from_str::<Value>(abi) else {
+        return None;
+    };
+    let Some(constructor) = items.iter().find(|item| item["type"] == "constructor") else {
+        return Some(String::from("constructor()"));
+    };
+    abi_signature("constructor", &constructor["inputs"])
+}
+
+/// Signatures of the events in a JSON ABI, e.g., "Transfer(address,address,uint256)".
+///
+/// Anonymous events are left out, as they have no signature topic.
+pub fn event_signatures(abi: &str) -> Option<Vec<String>> {
+    signatures(abi, |item| {
+        item["type"] == "event" && item["anonymous"] != true
+    })
+}
+
+/// Signatures of the functions in a JSON ABI, e.g., "transfer(address,uint256)".
+pub fn function_signatures(abi: &str) -> Option<Vec<String>> {
+    signatures(abi, |item| item["type"] == "function")
+}
+
+/// Signatures of the items in a JSON ABI that are included.
+fn signatures(abi: &str, include: fn(&Value) -> bool) -> Option<Vec<String>> {
+    let Ok(Value::Array(items)) = serde_json::from_str::<Value>(abi) else {
+        return None;
+    };
+    items
+        .iter()
+        .filter(|item| include(item))
+        .map(|item| abi_signature(item["name"].as_str()?, &item["inputs"]))
+        .collect()
+}
+
+/// Builds a text signature from the inputs of an ABI item.
+fn abi_signature(name: &str, inputs: &Value) -> Option<String> {
+    let types = inputs
+        .as_array()?
+        .iter()
+        .map(canonical_type)
+        .collect::<Option<Vec<String>>>()?;
+    Some(format!("{}({})", name, types.join(",")))
+}
+
+/// Type of an ABI parameter as used in signatures, with tuples expanded
+/// (e.g., "(address,uint256)[]").
+fn canonical_type(param: &Value) -> Option<String> {
+    let kind = param["type"].as_str()?;
+    let Some(suffix) = kind.strip_prefix("tuple") else {
+        return Some(kind.to_string());
+    };
+    let components = param["components"]
+        .as_array()?
+        .iter()
+        .map(canonical_type)
+        .collect::<Option<Vec<String>>>()?;
+    Some(format!("({}){}", components.join(","), suffix))
+}
+
+/// Parses the argument types of a text signature such as "transfer(address,uint256)".
+///
+/// Returns None if the signature is malformed. Nested types are not split and
+/// are read as raw words.
+fn kinds_from_signature(signature: &str) -> Option<Vec<ParamKind>> {
+    let kinds = signature_types(signature)?
+        .into_iter()
+        .map(kind_of)
+        .collect();
+    Some(kinds)
+}
+
+/// How an argument of a type is read (e.g., "uint128" as an integer).
+fn kind_of(t: &str) -> ParamKind {
+    match t {
+        "address" => ParamKind::Address,
+        "bool" => ParamKind::Bool,
+        "string" => ParamKind::String,
+        "bytes" => ParamKind::Bytes,
+        t if t.ends_with(']') => ParamKind::Bytes,
+        t if t.starts_with("uint") => ParamKind::Uint,
+        t if t.starts_with("int") => ParamKind::Int,
+        t if t.starts_with("bytes") => ParamKind::FixedBytes,
+        _ => ParamKind::Word,
+    }
+}
+
+/// Argument types of a text signature, or None if it is malformed or has
+/// nested types.
+pub fn signature_types(signature: &str) -> Option<Vec<&str>> {
+    let start = signature.find('(')?;
+    let inner = signature.get(start + 1..)?.strip_suffix(')')?;
+    if inner.is_empty() {
+        return Some(vec![]);
+    }
+    if inner.contains('(') {
+        return None;
+    }
+    Some(inner.split(',').map(str::trim).collect())
+}
+
+/// Guesses the type of each argument word.
+///
+/// A word that points to a length-prefixed region later in the data is taken
+/// to be a dynamic argument. The head of the arguments ends where the first
+/// dynamic region starts.
+fn guess_kinds(args: &[u8]) -> Vec<ParamKind> {
+    let mut head_end = args.len() / 32;
+    let mut kinds = vec![];
+    let mut n = 0;
+    while n < head_end {
+        let Some(word) = args.get(n * 32..(n + 1) * 32) else {
+            break;
+        };
+        let value = U256::from_big_endian(word);
+        let kind = if let Some(offset) = dynamic_offset(args, n) {
+            head_end = head_end.min(offset / 32);
+            ParamKind::Bytes
+        } else if word[..12].iter().all(|b| *b == 0) && word[12..16].iter().any(|b| *b != 0) {
+            ParamKind::Address
+        } else if value <= U256::one() {
+            ParamKind::Bool
+        } else if word[0] != 0 && word[31] == 0 {
+            ParamKind::FixedBytes
+        } else {
+            ParamKind::Uint
+        };
+        kinds.push(kind);
+        n += 1;
+    }
+    kinds
+}
+
+/// Offset of a dynamic region if the nth word looks like a pointer to one.
+fn dynamic_offset(args: &[u8], n: usize) -> Option<usize> {
+    let offset = data_word(args, n)?;
+    if offset.is_zero() || offset % 32 != U256::zero() || offset >= U256::from(args.len()) {
+        return None;
+    }
+    let offset = offset.as_usize();
+    if offset <= n * 32 {
+        return None;
+    }
+    dynamic_bytes(args, n)?;
+    Some(offset)
+}
+
+/// Reads the nth argument as the given type.
+fn read_param(args: &[u8], n: usize, kind: ParamKind) -> Option<String> {
+    let word = args.get(n * 32..(n + 1) * 32)?;
+    let value = U256::from_big_endian(word);
+    Some(match kind {
+        ParamKind::Address => format!("0x{}", hex::encode(H160::from_slice(&word[12..]))),
+        ParamKind::Uint => value.to_string(),
+        ParamKind::Int if word[0] & 0x80 != 0 => {
+            format!("-{}", (!value).overflowing_add(U256::one()).0)
+        }
+        ParamKind::Int => value.to_string(),
+        ParamKind::Bool => (!value.is_zero()).to_string(),
+        ParamKind::FixedBytes | ParamKind::Word | ParamKind::IndexedHash => {
+            format!("0x{}", hex::encode(word))
+        }
+        ParamKind::String => {
+            let bytes = dynamic_bytes(args, n)?;
+            format!("{:?}", String::from_utf8_lossy(bytes))
+        }
+        ParamKind::Bytes => format!("0x{}", hex::encode(dynamic_bytes(args, n)?)),
+    })
+}
+
+impl Display for ParamKind {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let name = match self {
+            ParamKind::Address => "address",
+            ParamKind::Uint => "uint",
+            ParamKind::Int => "int",
+            ParamKind::Bool => "bool",
+            ParamKind::FixedBytes => "bytes32",
+            ParamKind::String => "string",
+            ParamKind::Bytes => "bytes",
+            ParamKind::Word => "word",
+            ParamKind::IndexedHash => "indexed hash",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+impl Display for DecodedEvent {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.signature)?;
+        for (i, p) in self.params.iter().enumerate() {
+            write!(f, "\n\t\t{}. {} {}: {}", i, p.kind, p.name, p.value)?;
+        }
+        Ok(())
+    }
+}
+
+impl Display for DecodedCall {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match &self.signature {
+            Some(s) => write!(f, "{}", s)?,
+            None => write!(f, "function {}", self.selector)?,
+        }
+        if self.heuristic {
+            write!(f, " (heuristic)")?;
+        }
+        if let Some(source) = self.source {
+            write!(f, " (name from {})", source)?;
+        }
+        for (i, p) in self.params.iter().enumerate() {
+            write!(f, "\n\t\t{}. {}: {}", i, p.kind, p.value)?;
+        }
+        Ok(())
+    }
+}
+
+#[test]
+fn guess_transfer_calldata() {
+    let mut calldata = hex::decode("a9059cbb").unwrap();
+    calldata.extend([0; 12]);
+    calldata.extend([0xab; 20]);
+    let mut amount = [0; 32];
+    U256::exp10(18).to_big_endian(&mut amount);
+    calldata.extend(amount);
+
+    let guessed = CallShape::new(None, &calldata);
+    assert_eq!(guessed.kinds, vec![ParamKind::Address, ParamKind::Uint]);
+    let call = guessed.decode(&calldata).unwrap();
+    assert_eq!(call.selector, "a9059cbb");
+    assert_eq!(call.params[0].value, format!("0x{}", "ab".repeat(20)));
+    assert_eq!(call.params[1].value, "1000000000000000000");
+
+    let declared = CallShape::new(Some(String::from("transfer(address,uint256)")), &calldata);
+    assert_eq!(declared.kinds, guessed.kinds);
+}
+
+#[test]
+fn guess_dynamic_argument() {
+    // f(uint256, string) with 7 and "hello".
+    let mut calldata = hex::decode("12345678").unwrap();
+    for word in [U256::from(7), U256::from(64), U256::from(5)] {
+        let mut w = [0; 32];
+        word.to_big_endian(&mut w);
+        calldata.extend(w);
+    }
+    let mut text = [0; 32];
+    text[..5].copy_from_slice(b"hello");
+    calldata.extend(text);
+
+    let guessed = CallShape::new(None, &calldata);
+    assert_eq!(guessed.kinds, vec![ParamKind::Uint, ParamKind::Bytes]);
+    let declared = CallShape::new(Some(String::from("f(uint256,string)")), &calldata);
+    let call = declared.decode(&calldata).unwrap();
+    assert_eq!(call.params[1].value, "\"hello\"");
+}
+
+#[test]
+fn constructor_arguments() {
+    let mut args = vec![0; 12];
+    args.extend([0xcd; 20]);
+    let mut supply = [0; 32];
+    U256::from(1000).to_big_endian(&mut supply);
+    args.extend(supply);
+
+    let abi = r#"[{"type":"constructor","inputs":[{"type":"address"},{"type":"uint256"}]}]"#;
+    let declared = decode_constructor(&args, Some(abi));
+    assert!(!declared.heuristic);
+    assert_eq!(
+        declared.signature.as_deref(),
+        Some("constructor(address,uint256)")
+    );
+    assert_eq!(declared.params[1].value, "1000");
+    let guessed = decode_constructor(&args, None);
+    assert!(guessed.heuristic);
+    assert_eq!(
+        guessed.signature.as_deref(),
+        Some("constructor(address,uint)")
+    );
+}
+
+#[test]
+fn abi_event_signatures() {
+    let abi = r#"[
+        {"type":"event","name":"Transfer","anonymous":false,"inputs":[
+            {"type":"address","indexed":true},{"type":"address","indexed":true},
+            {"type":"uint256","indexed":false}]},
+        {"type":"event","name":"Hidden","anonymous":true,"inputs":[]},
+        {"type":"event","name":"Swapped","inputs":[{"type":"tuple[]","components":[
+            {"type":"address"},{"type":"uint128"}]}]},
+        {"type":"function","name":"transfer","inputs":[]}
+    ]"#;
+    assert_eq!(
+        event_signatures(abi).unwrap(),
+        [
+            "Transfer(address,address,uint256)",
+            "Swapped((address,uint128)[])"
+        ]
+    );
+    assert_eq!(event_signatures("Contract: summary"), None);
+    assert_eq!(function_signatures(abi).unwrap(), ["transfer()"]);
+}
+
+#[test]
+fn encode_and_return() {
+    let args = [format!("0x{}", "ab".repeat(20)), String::from("1000")];
+    let calldata = encode_call("transfer(address,uint256)", &args).unwrap();
+    let call = CallShape::new(Some(String::from("transfer(address,uint256)")), &calldata)
+        .decode(&calldata)
+        .unwrap();
+    assert_eq!(call.selector, "a9059cbb");
+    assert_eq!(call.params[0].value, args[0]);
+    assert_eq!(call.params[1].value, "1000");
+
+    let args = [
+        String::from("-2"),
+        String::from("hello"),
+        String::from("true"),
+    ];
+    let calldata = encode_call("f(int8,string,bool)", &args).unwrap();
+    let call = CallShape::new(Some(String::from("f(int8,string,bool)")), &calldata)
+        .decode(&calldata)
+        .unwrap();
+    let values: Vec<&str> = call.params.iter().map(|p| p.value.as_str()).collect();
+    assert_eq!(values, ["-2", "\"hello\"", "true"]);
+    assert_eq!(calldata.len(), 4 + 5 * 32);
+
+    assert!(encode_call("f(uint8)", &[String::from("256")]).is_err());
+    assert!(encode_call("f(uint256[])", &[String::from("1")]).is_err());
+    assert!(encode_call("f(address)", &[]).is_err());
+
+    let abi = r#"[{"type":"function","name":"balanceOf","inputs":[{"type":"address"}],
+        "outputs":[{"type":"uint256"}]}]"#;
+    let data = word(U256::from(42));
+    let returned = decode_return("balanceOf(address)", &data, Some(abi));
+    assert!(!returned.heuristic);
+    assert_eq!(returned.params[0].kind, ParamKind::Uint);
+    assert_eq!(returned.params[0].value, "42");
+    assert!(decode_return("balanceOf(address)", &data, None).heuristic);
+}
+
+#[test]
+fn indexed_dynamic_values_hashed() {
+    let abi = r#"[{"type":"event","name":"Registered","inputs":[
+        {"name":"name","type":"string","indexed":true},
+        {"name":"owner","type":"address","indexed":true},
+        {"name":"tags","type":"uint256[]","indexed":true},
+        {"name":"cost","type":"uint256","indexed":false}]}]"#;
+    let mut owner = [0; 32];
+    owner[12..].copy_from_slice(&[0xab; 20]);
+    let topics = [
+        topic_of("Registered(string,address,uint256[],uint256)"),
+        H256(keccak256(b"vitalik")),
+        H256(owner),
+        H256([0x11; 32]),
+    ];
+    let data = word(U256::from(5));
+    let event = decode_event(abi, &topics, &data, &[]).unwrap();
+    let values: Vec<&str> = event.params.iter().map(|p| p.value.as_str()).collect();
+    assert_eq!(
+        values[0],
+        format!("0x{}", hex::encode(keccak256(b"vitalik")))
+    );
+    assert_eq!(values[1], format!("0x{}", "ab".repeat(20)));
+    assert_eq!(values[3], "5");
+    assert_eq!(event.params[0].kind, ParamKind::IndexedHash);
+    assert_eq!(event.params[2].kind, ParamKind::IndexedHash);
+    assert!(event.params[2].indexed && !event.params[3].indexed);
+
+    let candidates = [String::from("nick"), String::from("vitalik")];
+    let event = decode_event(abi, &topics, &data, &candidates).unwrap();
+    assert_eq!(event.params[0].value, "\"vitalik\" (hash matched)");
+    // Fewer topics than indexed arguments: a different event.
+    assert_eq!(decode_event(abi, &topics[..3], &data, &[]), None);
+    assert_eq!(decode_event(abi, &topics, &[], &[]), None);
+}
@@ -0,0 +1,536 @@
+//! Decompilation of contracts without a verified ABI, alongside decoding.
+//!
+//! Decompiling is CPU bound and takes far longer than the requests made while
+//! decoding logs. Bytecode is queued with a `DecompilePool`, which runs at
+//! most one decompilation per worker on the blocking thread pool, so that
+//! decoding continues while the queue is worked through. Each distinct
+//! bytecode is decompiled once, however many contracts share it.
+//!
+//! Finished decompilations are collected with `completed` (without waiting)
+//! or `wait`, and merged into the cache by the caller.
+//!
+//! The heimdall library does not report its version, so before the first
+//! decompilation a small known contract is decompiled to find what it
+//! produces. If it produces nothing, no contracts are decompiled. Failures
+//! are classified (see `DecompileFailure`), and contracts that could not be
+//! decompiled are decoded heuristically instead.
+use std::{
+    any::Any,
+    collections::HashMap,
+    fmt::Display,
+    fs,
+    path::{Path, PathBuf},
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc, Mutex,
+    },
+    time::Duration,
+};
+
+use anyhow::{bail, Result};
+use heimdall::decompile::DecompileBuilder;
+use log::{debug, warn};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use tokio::sync::{
+    mpsc::{unbounded_channel, UnboundedReceiver, UnboundedSender},
+    Mutex as AsyncMutex, OnceCell, Semaphore,
+};
+use web3::{
+    signing::keccak256,
+    types::{H160, H256},
+};
+
+use crate::abi::selector_of;
+
+/// Longest a decompilation may take before it is abandoned.
+pub const TIMEOUT: Duration = Duration::from_secs(300);
+
+/// Runtime bytecode that any working decompiler handles
+/// (PUSH1 0x80 PUSH1 0x40 MSTORE PUSH1 0 DUP1 REVERT).
+const PROBE_BYTECODE: [u8; 9] = [0x60, 0x80, 0x60, 0x40, 0x52, 0x60, 0x00, 0x80, 0xfd];
+
+/// Decompiles bytecode into an output directory, unless told the
+/// decompilation was abandoned.
+type Decompile = fn(&[u8], &Path, &AtomicBool) -> Result<()>;
+
+/// Finds what the decompiler produces.
+type Probe = fn() -> Support;
+
+/// Shared handle to a queue of decompilations with a limited number of workers.
+#[derive(Clone)]
+pub struct DecompilePool(Arc<Pool>);
+
+struct Pool {
+    workers: usize,
+    permits: Arc<Semaphore>,
+    decompile: Decompile,
+    timeout: Duration,
+    probe: Probe,
+    /// What the decompiler produces, once probed.
+    support: OnceCell<Support>,
+    /// Contracts waiting for the decompilation of their bytecode, by bytecode hash.
+    waiting: Mutex<HashMap<H256, Vec<H160>>>,
+    sender: UnboundedSender<(H256, Outcome)>,
+    receiver: AsyncMutex<UnboundedReceiver<(H256, Outcome)>>,
+}
+
+type Outcome = Result<(), DecompileFailure>;
+
+/// A decompilation that finished.
+#[derive(Debug)]
+pub struct Finished {
+    /// Contracts with the decompiled bytecode.
+    pub addresses: Vec<H160>,
+    pub result: Outcome,
+}
+
+/// What the decompiler wrote for a known contract.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+struct Support {
+    /// An ABI ("abi.json").
+    abi: bool,
+    /// Source code ("decompiled.sol" or "decompiled.yul").
+    source: bool,
+}
+
+/// Why bytecode could not be decompiled.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
+pub enum DecompileFailure {
+    /// The decompiler produced nothing for a known contract.
+    Unavailable,
+    /// The decompiler took longer than allowed.
+    Timeout,
+    /// The bytecode has an opcode the decompiler does not handle.
+    UnsupportedOpcode(String),
+    /// The decompiler panicked.
+    Panicked(String),
+    /// Any other error (e.g., no output was written).
+    Error(String),
+}
+
+/// Number of CPUs available to the process.
+pub fn default_workers() -> usize {
+    std::thread::available_parallelism()
+        .map(|n| n.get())
+        .unwrap_or(1)
+}
+
+impl DecompilePool {
+    /// Pool that decompiles with heimdall, abandoning decompilations that
+    /// take longer than the timeout.
+    pub fn new(workers: usize, timeout: Duration) -> Self {
+        DecompilePool::with_decompiler(workers, timeout, heimdall_decompile, heimdall_support)
+    }
+    fn with_decompiler(
+        workers: usize,
+        timeout: Duration,
+        decompile: Decompile,
+        probe: Probe,
+    ) -> Self {
+        let workers = workers.max(1);
+        let (sender, receiver) = unbounded_channel();
+        DecompilePool(Arc::new(Pool {
+            workers,
+            permits: Arc::new(Semaphore::new(workers)),
+            decompile,
+            timeout,
+            probe,
+            support: OnceCell::new(),
+            waiting: Mutex::new(HashMap::new()),
+            sender,
+            receiver: AsyncMutex::new(receiver),
+        }))
+    }
+    /// Whether the bytecode of the contract is queued or being decompiled.
+    pub fn is_pending(&self, address: &H160) -> bool {
+        self.waiting().values().any(|a| a.contains(address))
+    }
+    /// Queues the bytecode of a contract for decompilation into a directory.
+    ///
+    /// Bytecode that is already queued is not decompiled again.
+    pub fn submit(&self, address: H160, bytecode: &[u8], output: PathBuf) {
+        let hash = H256::from(keccak256(bytecode));
+        let mut waiting = self.waiting();
+        if let Some(addresses) = waiting.get_mut(&hash) {
+            if !addresses.contains(&address) {
+                addresses.push(address);
+            }
+            return;
+        }
+        waiting.insert(hash, vec![address]);
+        debug!("Queued decompilation of 0x{}", hex::encode(address));
+        let pool = self.0.clone();
+        let bytecode = bytecode.to_vec();
+        tokio::spawn(async move {
+            let result = pool.run(bytecode, output).await;
+            let _ = pool.sender.send((hash, result));
+        });
+    }
+    /// Decompilations that finished since the last call, without waiting.
+    pub fn completed(&self) -> Vec<Finished> {
+        let Ok(mut receiver) = self.0.receiver.try_lock() else {
+            return vec![];
+        };
+        let mut finished = vec![];
+        while let Ok((hash, result)) = receiver.try_recv() {
+            finished.push(self.finish(hash, result));
+        }
+        finished
+    }
+    /// Waits for every queued decompilation to finish.
+    pub async fn wait(&self) -> Vec<Finished> {
+        let mut receiver = self.0.receiver.lock().await;
+        let mut finished = vec![];
+        while !self.waiting().is_empty() {
+            let Some((hash, result)) = receiver.recv().await else {
+                break;
+            };
+            finished.push(self.finish(hash, result));
+        }
+        finished
+    }
+    fn finish(&self, hash: H256, result: Outcome) -> Finished {
+        Finished {
+            addresses: self.waiting().remove(&hash).unwrap_or_default(),
+            result,
+        }
+    }
+    fn waiting(&self) -> std::sync::MutexGuard<'_, HashMap<H256, Vec<H160>>> {
+        self.0
+            .waiting
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+}
+
+impl Pool {
+    /// Decompiles once a worker is free, if the decompiler works at all.
+    async fn run(&self, bytecode: Vec<u8>, output: PathBuf) -> Outcome {
+        let permit = self
+            .permits
+            .clone()
+            .acquire_owned()
+            .await
+            .map_err(|e| DecompileFailure::Error(e.to_string()))?;
+        let probe = self.probe;
+        let support = self
+            .support
+            .get_or_init(|| async move {
+                let support = tokio::task::spawn_blocking(probe)
+                    .await
+                    .unwrap_or_default();
+                match support.abi || support.source {
+                    true => debug!("Decompiler output: {:?}", support),
+                    false => warn!("The decompiler does not work, so contracts without an ABI are decoded heuristically"),
+                }
+                support
+            })
+            .await;
+        if !(support.abi || support.source) {
+            return Err(DecompileFailure::Unavailable);
+        }
+        let decompile = self.decompile;
+        let abandoned = Arc::new(AtomicBool::new(false));
+        let flag = abandoned.clone();
+        let task = tokio::task::spawn_blocking(move || {
+            // A worker stays busy until the decompiler returns, even after
+            // the decompilation is abandoned.
+            let _permit = permit;
+            decompile(&bytecode, &output, &flag)
+        });
+        match tokio::time::timeout(self.timeout, task).await {
+            Ok(Ok(result)) => result.map_err(|e| DecompileFailure::classify(e.to_string(), false)),
+            Ok(Err(e)) if e.is_panic() => Err(DecompileFailure::classify(
+                panic_message(e.into_panic()),
+                true,
+            )),
+            Ok(Err(e)) => Err(DecompileFailure::Error(format!(
+                "Decompiler stopped ({})",
+                e
+            ))),
+            Err(_) => {
+                abandoned.store(true, Ordering::SeqCst);
+                Err(DecompileFailure::Timeout)
+            }
+        }
+    }
+}
+
+impl DecompileFailure {
+    /// Classifies an error or panic message from the decompiler.
+    fn classify(message: String, panicked: bool) -> Self {
+        if message.to_ascii_lowercase().contains("opcode") {
+            return DecompileFailure::UnsupportedOpcode(message);
+        }
+        match panicked {
+            true => DecompileFailure::Panicked(message),
+            false => DecompileFailure::Error(message),
+        }
+    }
+}
+
+/// Text of a panic, if it has any.
+pub fn panic_message(payload: Box<dyn Any + Send>) -> String {
+    match payload.downcast::<String>() {
+        Ok(message) => *message,
+        Err(payload) => payload
+            .downcast_ref::<&str>()
+            .map(|s| s.to_string())
+            .unwrap_or_default(),
+    }
+}
+
+/// Selectors of the functions in the ABI written by the decompiler, in order.
+///
+/// Functions that heimdall could not name are called "Unresolved_<selector>".
+/// The selectors of others are hashed from their name and input types.
+/// Returns None if there is no ABI in the output directory.
+pub fn decompiled_selectors(output: &Path) -> Option<Vec<[u8; 4]>> {
+    let text = fs::read_to_string(output.join("abi.json")).ok()?;
+    let Ok(Value::Array(items)) = serde_json::from_str(&text) else {
+        return None;
+    };
+    let selectors = items
+        .iter()
+        .filter(|item| item["type"] == "function")
+        .filter_map(|item| {
+            let name = item["name"].as_str()?;
+            if let Some(selector) = name.strip_prefix("Unresolved_") {
+                return hex::decode(selector).ok()?.try_into().ok();
+            }
+            let types = item["inputs"]
+                .as_array()?
+                .iter()
+                .map(|input| input["type"].as_str())
+                .collect::<Option<Vec<&str>>>()?;
+            Some(selector_of(&format!("{}({})", name, types.join(","))))
+        })
+        .collect();
+    Some(selectors)
+}
+
+/// Decompiles a known contract with heimdall to find what it writes.
+fn heimdall_support() -> Support {
+    let output = std::env::temp_dir().join(format!("psr-decompile-probe-{}", std::process::id()));
+    DecompileBuilder::new(&hex::encode(PROBE_BYTECODE))
+        .output(&output.to_string_lossy())
+        .decompile();
+    let support = Support {
+        abi: output.join("abi.json").exists(),
+        source: output.join("decompiled.sol").exists() || output.join("decompiled.yul").exists(),
+    };
+    let _ = fs::remove_dir_all(&output);
+    support
+}
+
+/// Decompiles with heimdall, into a temporary directory that is renamed to
+/// the output directory once complete.
+///
+/// An interrupted run therefore leaves no partial output that a later run
+/// would take as complete.
+fn heimdall_decompile(bytecode: &[u8], output: &Path, abandoned: &AtomicBool) -> Result<()> {
+    if output.exists() {
+        return Ok(());
+    }
+    let partial = output.with_extension("partial");
+    if partial.exists() {
+        fs::remove_dir_all(&partial)?;
+    }
+    DecompileBuilder::new(&hex::encode(bytecode))
+        .output(&partial.to_string_lossy())
+        .decompile();
+    complete(&partial, output, abandoned)
+}
+
+/// Moves finished decompiler output into place.
+///
+/// Output of a decompilation that was abandoned is discarded, as is output
+/// for bytecode that was decompiled in the meantime.
+fn complete(partial: &Path, output: &Path, abandoned: &AtomicBool) -> Result<()> {
+    if !partial.exists() {
+        bail!("Decompiler produced no output");
+    }
+    if abandoned.load(Ordering::SeqCst) {
+        fs::remove_dir_all(partial)?;
+        bail!("Decompilation abandoned");
+    }
+    if output.exists() {
+        fs::remove_dir_all(partial)?;
+        return Ok(());
+    }
+    fs::rename(partial, output)?;
+    Ok(())
+}
+
+impl Default for DecompilePool {
+    fn default() -> Self {
+        DecompilePool::new(default_workers(), TIMEOUT)
+    }
+}
+
+impl Display for DecompileFailure {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            DecompileFailure::Unavailable => write!(f, "decompiler unavailable"),
+            DecompileFailure::Timeout => write!(f, "timed out"),
+            DecompileFailure::UnsupportedOpcode(m) => write!(f, "unsupported opcode ({})", m),
+            DecompileFailure::Panicked(m) => write!(f, "decompiler panicked ({})", m),
+            DecompileFailure::Error(m) => write!(f, "{}", m),
+        }
+    }
+}
+
+impl std::fmt::Debug for DecompilePool {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("DecompilePool")
+            .field("workers", &self.0.workers)
+            .finish()
+    }
+}
+
+impl PartialEq for DecompilePool {
+    fn eq(&self, other: &Self) -> bool {
+        Arc::ptr_eq(&self.0, &other.0)
+    }
+}
+
+#[tokio::test]
+async fn decompiles_each_bytecode_once() {
+    use std::sync::atomic::AtomicUsize;
+
+    static RUNS: AtomicUsize = AtomicUsize::new(0);
+    static RUNNING: AtomicUsize = AtomicUsize::new(0);
+    static MOST_RUNNING: AtomicUsize = AtomicUsize::new(0);
+    fn slow(bytecode: &[u8], _: &Path, _: &AtomicBool) -> Result<()> {
+        RUNS.fetch_add(1, Ordering::SeqCst);
+        let running = RUNNING.fetch_add(1, Ordering::SeqCst) + 1;
+        MOST_RUNNING.fetch_max(running, Ordering::SeqCst);
+        std::thread::sleep(std::time::Duration::from_millis(20));
+        RUNNING.fetch_sub(1, Ordering::SeqCst);
+        match bytecode {
+            [0xfe] => bail!("Invalid bytecode"),
+            _ => Ok(()),
+        }
+    }
+
+    let works = || Support {
+        abi: true,
+        source: true,
+    };
+    let pool = DecompilePool::with_decompiler(2, TIMEOUT, slow, works);
+    let output = PathBuf::from("unused");
+    let (a, b) = (H160::repeat_byte(1), H160::repeat_byte(2));
+    pool.submit(a, &[0x60, 0x80], output.clone());
+    pool.submit(b, &[0x60, 0x80], output.clone());
+    for n in 0..4u8 {
+        pool.submit(H160::repeat_byte(0x10 + n), &[0x60, n], output.clone());
+    }
+    pool.submit(H160::repeat_byte(0xff), &[0xfe], output);
+    assert!(pool.is_pending(&b));
+
+    let mut finished = pool.wait().await;
+    assert_eq!(RUNS.load(Ordering::SeqCst), 6);
+    assert!(MOST_RUNNING.load(Ordering::SeqCst) <= 2);
+    assert!(!pool.is_pending(&b));
+    assert_eq!(finished.iter().filter(|f| f.result.is_err()).count(), 1);
+    finished.retain(|f| f.addresses.contains(&a));
+    assert_eq!(finished[0].addresses, [a, b]);
+    assert!(pool.completed().is_empty());
+}
+
+#[tokio::test]
+async fn failures_classified() {
+    fn failing(bytecode: &[u8], _: &Path, _: &AtomicBool) -> Result<()> {
+        match bytecode {
+            [0x01] => panic!("Unknown opcode 0x0c"),
+            [0x02] => panic!("index out of bounds"),
+            [0x03] => std::thread::sleep(Duration::from_millis(500)),
+            _ => bail!("Decompiler produced no output"),
+        }
+        Ok(())
+    }
+    let works = || Support {
+        abi: true,
+        source: true,
+    };
+    let pool = DecompilePool::with_decompiler(4, TIMEOUT, failing, works);
+    let output = PathBuf::from("unused");
+    for n in [1, 2, 4u8] {
+        pool.submit(H160::repeat_byte(n), &[n], output.clone());
+    }
+    let mut finished = pool.wait().await;
+    finished.sort_by_key(|f| f.addresses[0]);
+    let results: Vec<Outcome> = finished.into_iter().map(|f| f.result).collect();
+    assert_eq!(
+        results,
+        vec![
+            Err(DecompileFailure::UnsupportedOpcode(String::from(
+                "Unknown opcode 0x0c"
+            ))),
+            Err(DecompileFailure::Panicked(String::from(
+                "index out of bounds"
+            ))),
+            Err(DecompileFailure::Error(String::from(
+                "Decompiler produced no output"
+            ))),
+        ]
+    );
+
+    let pool = DecompilePool::with_decompiler(1, Duration::from_millis(100), failing, works);
+    pool.submit(H160::zero(), &[0x03], output.clone());
+    let finished = pool.wait().await;
+    assert_eq!(finished[0].result, Err(DecompileFailure::Timeout));
+
+    // Nothing is decompiled if the decompiler does not work.
+    let pool = DecompilePool::with_decompiler(1, TIMEOUT, failing, Support::default);
+    pool.submit(H160::zero(), &[0x03], output);
+    let finished = pool.wait().await;
+    assert_eq!(finished[0].result, Err(DecompileFailure::Unavailable));
+}
+
+#[test]
+fn output_completed_once() {
+    let dir = std::env::temp_dir().join(format!("psr-decompile-complete-{}", std::process::id()));
+    let (partial, output) = (dir.join("aa.partial"), dir.join("aa"));
+    let write = |dir: &Path, text: &str| {
+        fs::create_dir_all(dir).unwrap();
+        fs::write(dir.join("abi.json"), text).unwrap();
+    };
+    let (running, abandoned) = (AtomicBool::new(false), AtomicBool::new(true));
+
+    assert!(complete(&partial, &output, &running).is_err());
+    write(&partial, "late");
+    assert!(complete(&partial, &output, &abandoned).is_err());
+    assert!(!partial.exists() && !output.exists());
+
+    write(&partial, "first");
+    complete(&partial, &output, &running).unwrap();
+    // Output that already exists is kept.
+    write(&partial, "second");
+    complete(&partial, &output, &running).unwrap();
+    let text = fs::read_to_string(output.join("abi.json")).unwrap();
+    let partial_left = partial.exists();
+    fs::remove_dir_all(&dir).unwrap();
+    assert_eq!(text, "first");
+    assert!(!partial_left);
+}
+
+#[test]
+fn selectors_from_decompiled_abi() {
+    let output = std::env::temp_dir().join(format!("psr-decompiled-{}", std::process::id()));
+    fs::create_dir_all(&output).unwrap();
+    assert_eq!(decompiled_selectors(&output), None);
+    let abi = r#"[
+        {"type": "function", "name": "transfer", "inputs": [{"name": "arg0", "type": "address"}, {"name": "arg1", "type": "uint256"}]},
+        {"type": "event", "name": "Transfer", "inputs": []},
+        {"type": "function", "name": "Unresolved_12345678", "inputs": []}
+    ]"#;
+    fs::write(output.join("abi.json"), abi).unwrap();
+    let selectors = decompiled_selectors(&output);
+    fs::remove_dir_all(&output).unwrap();
+    assert_eq!(
+        selectors,
+        Some(vec![[0xa9, 0x05, 0x9c, 0xbb], [0x12, 0x34, 0x56, 0x78]])
+    );
+}
@@ -0,0 +1,247 @@
+//! Changes between two saved histories of an address (see `--save-history`),
+//! e.g., to monitor an address, or to see what a change to the pipeline did
+//! to its output.
+//!
+//! Transactions are matched by chain, block and index. For those in both,
+//! the diff lists the functions and events named only in the newer history
+//! and the interpretations (action, or spam) that changed. Contracts whose
+//! ABI is only known in the newer history are listed once.
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    fmt::Display,
+    path::Path,
+};
+
+use anyhow::{bail, Result};
+use serde::Serialize;
+use web3::types::H160;
+
+use crate::{chains::MAINNET, data::TxInfo, history::saved_transactions};
+
+/// What changed from one history of an address to another.
+#[derive(Debug, Default, Clone, PartialEq, Eq, Serialize)]
+pub struct HistoryDiff {
+    pub address: String,
+    /// Transactions only in the newer history.
+    pub added: Vec<TxRef>,
+    /// Transactions only in the older history.
+    pub removed: Vec<TxRef>,
+    /// Functions and events named only in the newer history.
+    pub names: Vec<NewName>,
+    /// Contracts with an ABI only in the newer history.
+    pub abis: BTreeSet<H160>,
+    /// Transactions interpreted differently.
+    pub interpretations: Vec<Reinterpreted>,
+}
+
+/// A transaction, by where it is.
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Serialize)]
+pub struct TxRef {
+    pub chain: String,
+    pub block: u32,
+    pub index: u32,
+}
+
+/// A function or event name found in the newer history.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct NewName {
+    pub tx: TxRef,
+    /// "function" or "event".
+    pub kind: String,
+    pub name: String,
+}
+
+/// The interpretation of a transaction in each history.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct Reinterpreted {
+    pub tx: TxRef,
+    pub old: Option<String>,
+    pub new: Option<String>,
+}
+
+impl HistoryDiff {
+    /// Compares two files written by `AddressHistory::save`, which must be
+    /// for the same address.
+    pub fn of_files(old: &Path, new: &Path) -> Result<Self> {
+        let (old_address, old_txs) = saved_transactions(old)?;
+        let (new_address, new_txs) = saved_transactions(new)?;
+        if !old_address.eq_ignore_ascii_case(&new_address) {
+            bail!(
+                "The histories are of different addresses ({} and {})",
+                old_address,
+                new_address
+            );
+        }
+        Ok(HistoryDiff::new(new_address, &old_txs, &new_txs))
+    }
+    pub fn new(address: String, old: &[TxInfo], new: &[TxInfo]) -> Self {
+        let mut diff = HistoryDiff {
+            address,
+            abis: &with_abi(new) - &with_abi(old),
+            ..Default::default()
+        };
+        let old: BTreeMap<TxRef, &TxInfo> = old.iter().map(|tx| (TxRef::of(tx), tx)).collect();
+        let new: BTreeMap<TxRef, &TxInfo> = new.iter().map(|tx| (TxRef::of(tx), tx)).collect();
+        diff.removed = old
+            .keys()
+            .filter(|r| !new.contains_key(r))
+            .cloned()
+            .collect();
+        for (tx_ref, tx) in &new {
+            let Some(before) = old.get(tx_ref) else {
+                diff.added.push(tx_ref.clone());
+                continue;
+            };
+            let function = |tx: &TxInfo| tx.call.as_ref().and_then(|c| c.signature.clone());
+            if let (None, Some(name)) = (function(before), function(tx)) {
+                diff.names.push(tx_ref.named("function", name));
+            }
+            let events = tx.events.iter().flatten();
+            for (i, event) in events.enumerate() {
+                let old_name = before
+                    .events
+                    .as_ref()
+                    .and_then(|e| e.get(i))
+                    .filter(|e| e.raw == event.raw)
+                    .and_then(|e| e.name.as_ref());
+                if let (None, Some(name)) = (old_name, &event.name) {
+                    diff.names.push(tx_ref.named("event", name.clone()));
+                }
+            }
+            let (old, new) = (interpretation(before), interpretation(tx));
+            if old != new {
+                let tx = tx_ref.clone();
+                diff.interpretations.push(Reinterpreted { tx, old, new });
+            }
+        }
+        diff
+    }
+    pub fn is_empty(&self) -> bool {
+        self.added.is_empty()
+            && self.removed.is_empty()
+            && self.names.is_empty()
+            && self.abis.is_empty()
+            && self.interpretations.is_empty()
+    }
+}
+
+impl TxRef {
+    fn of(tx: &TxInfo) -> Self {
+        TxRef {
+            chain: tx.chain.clone(),
+            block: tx.location.block,
+            index: tx.location.index,
+        }
+    }
+    fn named(&self, kind: &str, name: String) -> NewName {
+        NewName {
+            tx: self.clone(),
+            kind: kind.to_string(),
+            name,
+        }
+    }
+}
+
+/// Contracts that emitted an event and have an ABI.
+fn with_abi(transactions: &[TxInfo]) -> BTreeSet<H160> {
+    transactions
+        .iter()
+        .flat_map(|tx| tx.events.iter().flatten())
+        .filter(|event| event.contract.abi.is_some())
+        .map(|event| event.raw.address)
+        .collect()
+}
+
+/// The action of a transaction, and whether it has spam events.
+fn interpretation(tx: &TxInfo) -> Option<String> {
+    let spam = tx.events.iter().flatten().any(|e| e.spam);
+    match (&tx.action, spam) {
+        (Some(action), false) => Some(action.to_string()),
+        (Some(action), true) => Some(format!("{} (spam)", action)),
+        (None, true) => Some(String::from("(spam)")),
+        (None, false) => None,
+    }
+}
+
+impl Display for TxRef {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}:{}", self.block, self.index)?;
+        if self.chain != MAINNET && !self.chain.is_empty() {
+            write!(f, " ({})", self.chain)?;
+        }
+        Ok(())
+    }
+}
+
+impl Display for HistoryDiff {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Changes to the history of {}", self.address)?;
+        if self.is_empty() {
+            return write!(f, "\n\tNone");
+        }
+        for tx in &self.added {
+            write!(f, "\n\t+ Transaction {}", tx)?;
+        }
+        for tx in &self.removed {
+            write!(f, "\n\t- Transaction {}", tx)?;
+        }
+        for name in &self.names {
+            write!(f, "\n\tNamed {} {} in {}", name.kind, name.name, name.tx)?;
+        }
+        for contract in &self.abis {
+            write!(f, "\n\tABI found for 0x{}", hex::encode(contract))?;
+        }
+        let text = |interpretation: &Option<String>| match interpretation {
+            Some(text) => text.to_owned(),
+            None => String::from("None"),
+        };
+        for change in &self.interpretations {
+            write!(
+                f,
+                "\n\tReinterpreted {}: {} -> {}",
+                change.tx,
+                text(&change.old),
+                text(&change.new)
+            )?;
+        }
+        Ok(())
+    }
+}
+
+#[test]
+fn changes_between_runs() {
+    use min_know::specs::address_appearance_index::AAIAppearanceTx;
+
+    use crate::{decode::CallShape, semantics::test_event};
+
+    let tx = |block: u32| TxInfo {
+        location: AAIAppearanceTx { block, index: 0 },
+        chain: String::from(MAINNET),
+        ..Default::default()
+    };
+    let contract = "11".repeat(20);
+    let old = vec![tx(10), tx(20)];
+    let mut swap = tx(20);
+    let calldata = hex::decode(format!("a9059cbb{:0>64}{:0>64}", contract, "2a")).unwrap();
+    let signature = String::from("transfer(address,uint256)");
+    swap.call = CallShape::new(Some(signature), &calldata).decode(&calldata);
+    let mut event = test_event(&contract, "Swap()", &[], vec![]);
+    event.name = Some(String::from("Swap()"));
+    event.contract.abi = Some(String::from("[]"));
+    event.spam = true;
+    swap.events = Some(vec![event]);
+    let new = vec![swap, tx(30)];
+
+    let diff = HistoryDiff::new(String::from("0xab"), &old, &new);
+    let at = |block| TxRef::of(&tx(block));
+    assert_eq!(diff.added, vec![at(30)]);
+    assert_eq!(diff.removed, vec![at(10)]);
+    let names: Vec<&str> = diff.names.iter().map(|n| n.name.as_str()).collect();
+    assert_eq!(names, ["transfer(address,uint256)", "Swap()"]);
+    assert_eq!(diff.abis.len(), 1);
+    assert_eq!(diff.interpretations[0].new.as_deref(), Some("(spam)"));
+    let text = diff.to_string();
+    assert!(text.contains("\n\tNamed event Swap() in 20:0"));
+    assert!(text.ends_with("\n\tReinterpreted 20:0: None -> (spam)"));
+    assert!(HistoryDiff::new(String::new(), &new, &new).is_empty());
+}
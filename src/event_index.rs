@@ -0,0 +1,85 @@
+//! Reverse index from event signature (topic zero) to where those events
+//! occur in a history.
+//!
+//! Built once after logs are decoded, so that filtering by event (e.g., all
+//! Transfer events) does not need to scan every transaction again.
+use std::{collections::HashMap, fs::File, path::Path};
+
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+use web3::types::H256;
+
+use crate::data::TxInfo;
+
+/// Position of an event in a history.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct EventLocation {
+    /// Position of the transaction in the history.
+    pub tx: usize,
+    /// Position of the event in the transaction.
+    pub event: usize,
+}
+
+/// Event locations by topic zero, in history order.
+#[derive(Debug, Default, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct EventIndex(HashMap<H256, Vec<EventLocation>>);
+
+impl EventIndex {
+    pub fn new(transactions: &[TxInfo]) -> Self {
+        let mut index = EventIndex::default();
+        index.extend(0, transactions);
+        index
+    }
+    /// Adds transactions that start at position `offset` in the history.
+    pub fn extend(&mut self, offset: usize, transactions: &[TxInfo]) {
+        for (i, tx) in transactions.iter().enumerate() {
+            for (event, e) in tx.events.iter().flatten().enumerate() {
+                let Some(topic) = e.raw.topics.first() else {
+                    continue;
+                };
+                self.0.entry(*topic).or_default().push(EventLocation {
+                    tx: offset + i,
+                    event,
+                });
+            }
+        }
+    }
+    /// Locations of events with the given topic zero.
+    pub fn get(&self, topic: &H256) -> &[EventLocation] {
+        self.0.get(topic).map(|l| l.as_slice()).unwrap_or_default()
+    }
+    /// Writes the index as JSON.
+    pub fn save(&self, path: &Path) -> Result<()> {
+        serde_json::to_writer(File::create(path)?, self)?;
+        Ok(())
+    }
+}
+
+#[test]
+fn index_by_topic() {
+    use crate::{
+        abi::topic_of,
+        semantics::{test_event, APPROVAL, TRANSFER},
+    };
+
+    let contract = "11".repeat(20);
+    let tx = |signatures: &[&str]| TxInfo {
+        events: Some(
+            signatures
+                .iter()
+                .map(|s| test_event(&contract, s, &[], vec![]))
+                .collect(),
+        ),
+        ..Default::default()
+    };
+    let mut index = EventIndex::new(&[tx(&[APPROVAL, TRANSFER]), tx(&[])]);
+    index.extend(2, &[tx(&[TRANSFER])]);
+    assert_eq!(
+        index.get(&topic_of(TRANSFER)),
+        [
+            EventLocation { tx: 0, event: 1 },
+            EventLocation { tx: 2, event: 0 }
+        ]
+    );
+    assert!(index.get(&H256::zero()).is_empty());
+}
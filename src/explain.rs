@@ -0,0 +1,93 @@
+//! One call that explains an address: sets up the config, runs the usual
+//! pipeline and returns a report that serializes to JSON, for programs that
+//! do not need the stages themselves (see `pipeline`).
+//!
+//! Like the pipeline, this needs node, index and cache access, so is only
+//! in the library with the `node` feature. It is re-exported as
+//! `psr::explain_address`.
+use anyhow::Result;
+use min_know::config::choices::DirNature;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+use crate::{
+    chains::MAINNET_CHAIN_ID,
+    export::{failures_json, transactions_json, warnings_json},
+    history::{AddressHistory, Config, Mode},
+    redact::Pseudonyms,
+};
+
+/// Where `explain_address` gets its data.
+#[derive(Debug, Clone)]
+pub struct ExplainOptions {
+    /// RPC endpoints of a mainnet node, tried in order.
+    pub rpc_urls: Vec<String>,
+    /// Directories of the databases.
+    pub directories: DirNature,
+    /// Whether Sourcify and 4byte are asked for what local data lacks.
+    pub mode: Mode,
+}
+
+impl Default for ExplainOptions {
+    /// A local node, the sample databases and no APIs.
+    fn default() -> Self {
+        ExplainOptions {
+            rpc_urls: vec![String::from("http://localhost:8545")],
+            directories: DirNature::Sample,
+            mode: Mode::AvoidApis,
+        }
+    }
+}
+
+/// The decoded history of an address, as served by `psr_getHistory`.
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+pub struct Report {
+    pub address: String,
+    /// Transactions, with the fields of the JSON export.
+    pub transactions: Vec<Value>,
+    /// Stages that failed, by transaction.
+    pub failures: Vec<Value>,
+    /// Lookups that failed.
+    pub warnings: Vec<String>,
+}
+
+impl Report {
+    pub fn new(history: &AddressHistory) -> Self {
+        let pseudonyms = Pseudonyms::default();
+        Report {
+            address: history.address.clone(),
+            transactions: transactions_json(history, &pseudonyms),
+            failures: failures_json(&history.failures),
+            warnings: warnings_json(&history.warnings, &pseudonyms),
+        }
+    }
+}
+
+/// Checks the node and databases, then builds and decodes the history of
+/// an address.
+pub async fn explain_address(address: &str, options: ExplainOptions) -> Result<Report> {
+    let validated = Config::builder()
+        .directories(options.directories)
+        .rpc_urls(options.rpc_urls)
+        .chain_id(MAINNET_CHAIN_ID)
+        .validate()
+        .await?;
+    let mut history = AddressHistory::new(address, validated.config)?;
+    history.run(options.mode).await?;
+    Ok(Report::new(&history))
+}
+
+#[test]
+fn report_as_served() {
+    let report = Report {
+        address: String::from("0xab"),
+        transactions: vec![],
+        failures: vec![],
+        warnings: vec![String::from("4byte lookup failed")],
+    };
+    let value = serde_json::to_value(&report).unwrap();
+    let keys: Vec<&String> = value.as_object().unwrap().keys().collect();
+    assert_eq!(keys, ["address", "failures", "transactions", "warnings"]);
+    assert_eq!(serde_json::from_value::<Report>(value).unwrap(), report);
+    assert_eq!(ExplainOptions::default().mode, Mode::AvoidApis);
+}
@@ -0,0 +1,73 @@
+//! Links to a hosted block explorer (EIP-3091).
+//!
+//! Explorers that follow EIP-3091 share the same paths below a base URL
+//! (e.g., `https://etherscan.io/tx/0x...`), so one template per network is
+//! enough to link transactions, blocks, addresses and tokens.
+use std::str::FromStr;
+
+use anyhow::{bail, Result};
+use serde::{Deserialize, Serialize};
+use web3::types::{H160, H256};
+
+/// Known networks and their default explorer.
+const NETWORKS: [(&str, &str); 3] = [
+    ("mainnet", "https://etherscan.io"),
+    ("sepolia", "https://sepolia.etherscan.io"),
+    ("holesky", "https://holesky.etherscan.io"),
+];
+
+/// Base URL of an EIP-3091 block explorer.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Explorer {
+    base: String,
+}
+
+impl Explorer {
+    pub fn tx(&self, hash: &H256) -> String {
+        format!("{}/tx/0x{}", self.base, hex::encode(hash))
+    }
+    pub fn block(&self, number: u64) -> String {
+        format!("{}/block/{}", self.base, number)
+    }
+    pub fn address(&self, address: &H160) -> String {
+        format!("{}/address/0x{}", self.base, hex::encode(address))
+    }
+    pub fn token(&self, address: &H160) -> String {
+        format!("{}/token/0x{}", self.base, hex::encode(address))
+    }
+}
+
+impl FromStr for Explorer {
+    type Err = anyhow::Error;
+
+    /// Reads a network name (e.g., "mainnet") or a base URL.
+    fn from_str(s: &str) -> Result<Self> {
+        let base = match NETWORKS.iter().find(|(name, _)| *name == s) {
+            Some((_, url)) => url,
+            None if s.starts_with("http://") || s.starts_with("https://") => s,
+            None => bail!(
+                "Unknown explorer {}. Use a base URL or one of: {}",
+                s,
+                NETWORKS.map(|(name, _)| name).join(", ")
+            ),
+        };
+        Ok(Explorer {
+            base: base.trim_end_matches('/').to_string(),
+        })
+    }
+}
+
+#[test]
+fn explorer_links() {
+    let mainnet: Explorer = "mainnet".parse().unwrap();
+    assert_eq!(
+        mainnet.block(17_000_000),
+        "https://etherscan.io/block/17000000"
+    );
+    let custom: Explorer = "https://explorer.example/".parse().unwrap();
+    assert_eq!(
+        custom.token(&H160::repeat_byte(0x11)),
+        format!("https://explorer.example/token/0x{}", "11".repeat(20))
+    );
+    assert!("goerly".parse::<Explorer>().is_err());
+}
@@ -0,0 +1,716 @@
+//! Writes a decoded history to formats that other tools can consume.
+//!
+//! Exports are built from data already held in the `AddressHistory`, so the
+//! pipeline does not need to be re-run to analyse the results. Each export
+//! takes the pseudonyms to show in place of addresses and transaction hashes
+//! (none by default, see `redact`). With pseudonyms, blocks and times are
+//! coarsened and transaction indices left out (0 in the SQLite
+//! `transactions` table, where the column cannot be null).
+use std::{collections::HashMap, fs, fs::File, path::Path, sync::Arc};
+
+use anyhow::Result;
+use arrow::{
+    array::{
+        ArrayRef, BooleanArray, Float64Array, ListBuilder, StringArray, StringBuilder, UInt32Array,
+        UInt64Array,
+    },
+    datatypes::{Field, Schema},
+    record_batch::RecordBatch,
+};
+use parquet::{arrow::ArrowWriter, basic::Compression, file::properties::WriterProperties};
+use rusqlite::{params, Connection};
+use serde_json::{json, Value};
+use web3::types::H160;
+
+use crate::{
+    data::{Contract, LoggedEvent, TxInfo, Warning},
+    flows::FlowGraph,
+    history::AddressHistory,
+    outcome::StageFailure,
+    redact::Pseudonyms,
+    tax::csv_field,
+    verify::Verification,
+};
+
+/// Columns of the CSV export.
+pub const CSV_HEADER: &str = "address,chain,block,tx_index,hash,from,to,value_wei,status,gas_used,\
+timestamp,action,events";
+
+/// Normalized tables for the SQLite export.
+///
+/// Events reference transactions by hash and contracts by address.
+const SQLITE_SCHEMA: &str = "
+CREATE TABLE IF NOT EXISTS transactions (
+    hash TEXT PRIMARY KEY,
+    block INTEGER NOT NULL,
+    tx_index INTEGER NOT NULL,
+    sender TEXT,
+    recipient TEXT,
+    value_wei TEXT,
+    gas_used TEXT,
+    status INTEGER,
+    timestamp INTEGER,
+    action TEXT,
+    chain TEXT,
+    cumulative_gas_used TEXT,
+    blob_count INTEGER,
+    blob_gas_used TEXT,
+    blob_gas_price TEXT,
+    blob_hashes TEXT,
+    fiat_currency TEXT,
+    value_fiat REAL,
+    miner TEXT,
+    confirmations INTEGER
+);
+CREATE TABLE IF NOT EXISTS contracts (
+    address TEXT PRIMARY KEY,
+    verification TEXT NOT NULL,
+    metadata_link TEXT,
+    abi TEXT
+);
+CREATE TABLE IF NOT EXISTS events (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    tx_hash TEXT NOT NULL REFERENCES transactions(hash),
+    tx_index INTEGER,
+    log_index INTEGER,
+    contract TEXT NOT NULL REFERENCES contracts(address),
+    topic0 TEXT,
+    name TEXT,
+    topic1 TEXT,
+    topic2 TEXT,
+    topic3 TEXT,
+    data TEXT,
+    value_fiat REAL,
+    spam INTEGER
+);
+CREATE TABLE IF NOT EXISTS nametags (
+    address TEXT NOT NULL,
+    tag TEXT NOT NULL,
+    PRIMARY KEY (address, tag)
+);
+";
+
+/// Writes transactions, events, contracts and nametags to a SQLite database.
+///
+/// Rows for the same transaction hash or contract are replaced, so exporting
+/// into an existing database updates it.
+pub fn to_sqlite(history: &AddressHistory, path: &Path, pseudonyms: &Pseudonyms) -> Result<()> {
+    write_sqlite(&history.transactions, path, pseudonyms)
+}
+
+/// Writes the transactions to a SQLite database (see `to_sqlite`).
+fn write_sqlite(transactions: &[TxInfo], path: &Path, pseudonyms: &Pseudonyms) -> Result<()> {
+    let mut conn = Connection::open(path)?;
+    conn.execute_batch(SQLITE_SCHEMA)?;
+    add_missing_columns(&conn)?;
+    let db = conn.transaction()?;
+
+    // Events reference their contract, so contracts are written first. They
+    // are updated in place, as replacing a row would orphan the events of
+    // earlier exports.
+    let contracts: HashMap<&str, &Contract> = transactions
+        .iter()
+        .flat_map(|tx| tx.events.iter().flatten())
+        .map(|e| (e.contract.address.as_str(), &e.contract))
+        .collect();
+    for (address, contract) in contracts {
+        db.execute(
+            "INSERT INTO contracts (address, verification, metadata_link, abi)
+            VALUES (?1, ?2, ?3, ?4)
+            ON CONFLICT (address) DO UPDATE SET verification = ?2, metadata_link = ?3, abi = ?4",
+            params![
+                pseudonyms.text(&format!("0x{}", address)),
+                contract.verification.to_string(),
+                contract
+                    .source_code_metadata_link
+                    .as_ref()
+                    .map(|m| m.to_string()),
+                contract.abi,
+            ],
+        )?;
+    }
+    let mut nametags: Vec<(&str, &str)> = vec![];
+    for tx in transactions {
+        let Some(desc) = &tx.description else {
+            continue;
+        };
+        let tx_hash = pseudonyms.tx(&desc.hash);
+        let receipt = tx.receipt.as_ref();
+        let blob = tx.blob.as_ref();
+        db.execute("DELETE FROM events WHERE tx_hash = ?1", params![tx_hash])?;
+        db.execute(
+            "INSERT OR REPLACE INTO transactions
+            (hash, block, tx_index, sender, recipient, value_wei, gas_used, status, timestamp, action,
+            chain, cumulative_gas_used, blob_count, blob_gas_used, blob_gas_price, blob_hashes,
+            fiat_currency, value_fiat, miner, confirmations)
+            VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12, ?13, ?14, ?15, ?16, ?17,
+            ?18, ?19, ?20)",
+            params![
+                tx_hash,
+                pseudonyms.block(tx.location.block),
+                // The column predates redaction and cannot be null.
+                pseudonyms.locating(tx.location.index).unwrap_or_default(),
+                desc.from.map(|a| pseudonyms.address(&a)),
+                desc.to.map(|a| pseudonyms.address(&a)),
+                desc.value.to_string(),
+                receipt.and_then(|r| r.gas_used).map(|g| g.to_string()),
+                receipt.and_then(|r| r.status).map(|s| s.as_u64()),
+                tx.timestamp.map(|t| pseudonyms.timestamp(t)),
+                tx.action.as_ref().map(|a| pseudonyms.text(&a.to_string())),
+                tx.chain,
+                receipt.map(|r| r.cumulative_gas_used.to_string()),
+                blob.map(|b| b.versioned_hashes.len()),
+                blob.and_then(|b| b.blob_gas_used).map(|g| g.to_string()),
+                blob.and_then(|b| b.blob_gas_price).map(|p| p.to_string()),
+                blob.and_then(|b| pseudonyms.locating(json!(b.versioned_hashes).to_string())),
+                tx.fiat.as_ref().map(|f| &f.currency),
+                tx.fiat.as_ref().and_then(|f| f.value),
+                tx.miner.map(|a| pseudonyms.address(&a)),
+                tx.confirmations().and_then(|c| pseudonyms.locating(c)),
+            ],
+        )?;
+        for (i, event) in tx.events.iter().flatten().enumerate() {
+            let topic = |n: usize| {
+                event
+                    .raw
+                    .topics
+                    .get(n)
+                    .map(|t| pseudonyms.text(&format!("0x{}", hex::encode(t))))
+            };
+            db.execute(
+                "INSERT INTO events
+                (tx_hash, tx_index, log_index, contract, topic0, name, topic1, topic2, topic3, data,
+                value_fiat, spam)
+                VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12)",
+                params![
+                    tx_hash,
+                    pseudonyms.locating(event.transaction_index.unwrap_or(tx.location.index)),
+                    event.log_index,
+                    pseudonyms.address(&event.raw.address),
+                    topic(0),
+                    event.name,
+                    topic(1),
+                    topic(2),
+                    topic(3),
+                    pseudonyms.text(&format!("0x{}", hex::encode(&event.raw.data.0))),
+                    event_value(tx, i),
+                    event.spam,
+                ],
+            )?;
+            // Tags would name the address behind a pseudonym.
+            if pseudonyms.pseudonym(&event.raw.address).is_some() {
+                continue;
+            }
+            for tag in event.nametags.iter().flatten() {
+                if !tag.is_empty() {
+                    nametags.push((&event.contract.address, tag));
+                }
+            }
+        }
+    }
+    for (address, tag) in nametags {
+        db.execute(
+            "INSERT OR IGNORE INTO nametags (address, tag) VALUES (?1, ?2)",
+            params![format!("0x{}", address), tag],
+        )?;
+    }
+    db.commit()?;
+    Ok(())
+}
+
+/// Columns added after the first version of the schema, by table.
+const ADDED_COLUMNS: [(&str, &str, &str); 13] = [
+    ("transactions", "chain", "TEXT"),
+    ("transactions", "blob_count", "INTEGER"),
+    ("transactions", "blob_gas_used", "TEXT"),
+    ("transactions", "blob_gas_price", "TEXT"),
+    ("transactions", "blob_hashes", "TEXT"),
+    ("transactions", "fiat_currency", "TEXT"),
+    ("transactions", "value_fiat", "REAL"),
+    ("events", "value_fiat", "REAL"),
+    ("events", "spam", "INTEGER"),
+    ("transactions", "cumulative_gas_used", "TEXT"),
+    ("events", "tx_index", "INTEGER"),
+    ("transactions", "miner", "TEXT"),
+    ("transactions", "confirmations", "INTEGER"),
+];
+
+/// Adds columns to tables created before the columns existed.
+fn add_missing_columns(conn: &Connection) -> Result<()> {
+    for (table, name, kind) in ADDED_COLUMNS {
+        let mut columns =
+            conn.prepare(&format!("SELECT name FROM pragma_table_info('{}')", table))?;
+        let names = columns
+            .query_map([], |row| row.get::<_, String>(0))?
+            .collect::<Result<Vec<String>, _>>()?;
+        if !names.iter().any(|n| n == name) {
+            conn.execute(
+                &format!("ALTER TABLE {} ADD COLUMN {} {}", table, name, kind),
+                [],
+            )?;
+        }
+    }
+    Ok(())
+}
+
+/// Fiat value of the nth event of a transaction, if priced.
+fn event_value(tx: &TxInfo, n: usize) -> Option<f64> {
+    tx.fiat
+        .as_ref()
+        .and_then(|f| f.events.get(n).copied().flatten())
+}
+
+/// Writes one row per event to a Parquet file (snappy compressed).
+///
+/// Columns: chain, block, tx_index, tx_hash, log_index, contract, topic0,
+/// name, params (JSON), nametags (list), value_fiat (token transfers, if
+/// priced), spam (likely an unsolicited airdrop), and the status and
+/// cumulative gas used from the receipt of the transaction.
+pub fn to_parquet(history: &AddressHistory, path: &Path, pseudonyms: &Pseudonyms) -> Result<()> {
+    write_parquet(&history.transactions, path, pseudonyms)
+}
+
+/// Writes the events of the transactions to a Parquet file (see `to_parquet`).
+fn write_parquet(transactions: &[TxInfo], path: &Path, pseudonyms: &Pseudonyms) -> Result<()> {
+    let mut chains = vec![];
+    let mut blocks = vec![];
+    let mut tx_indices = vec![];
+    let mut tx_hashes = vec![];
+    let mut log_indices = vec![];
+    let mut contracts = vec![];
+    let mut topic_zeros = vec![];
+    let mut names = vec![];
+    let mut params = vec![];
+    let mut nametags = ListBuilder::new(StringBuilder::new());
+    let mut values = vec![];
+    let mut spam = vec![];
+    let mut statuses = vec![];
+    let mut cumulative_gas = vec![];
+
+    for tx in transactions {
+        let tx_hash = tx.description.as_ref().map(|d| pseudonyms.tx(&d.hash));
+        let receipt = tx.receipt.as_ref();
+        for (n, event) in tx.events.iter().flatten().enumerate() {
+            chains.push(tx.chain.clone());
+            blocks.push(pseudonyms.block(tx.location.block));
+            tx_indices
+                .push(pseudonyms.locating(event.transaction_index.unwrap_or(tx.location.index)));
+            tx_hashes.push(tx_hash.clone());
+            log_indices.push(event.log_index);
+            contracts.push(pseudonyms.address(&event.raw.address));
+            topic_zeros.push(
+                event
+                    .raw
+                    .topics
+                    .first()
+                    .map(|t| format!("0x{}", hex::encode(t))),
+            );
+            names.push(event.name.clone());
+            params.push(pseudonyms.text(&params_json(event)));
+            match pseudonyms.pseudonym(&event.raw.address) {
+                Some(_) => nametags.append(false),
+                None => {
+                    for tag in event.nametags.iter().flatten() {
+                        nametags.values().append_value(tag);
+                    }
+                    nametags.append(event.nametags.is_some());
+                }
+            }
+            values.push(event_value(tx, n));
+            spam.push(event.spam);
+            statuses.push(receipt.and_then(|r| r.status).map(|s| s.as_u64()));
+            cumulative_gas.push(receipt.map(|r| r.cumulative_gas_used.to_string()));
+        }
+    }
+
+    let columns: Vec<(&str, ArrayRef)> = vec![
+        ("chain", Arc::new(StringArray::from(chains))),
+        ("block", Arc::new(UInt32Array::from(blocks))),
+        ("tx_index", Arc::new(UInt32Array::from(tx_indices))),
+        ("tx_hash", Arc::new(StringArray::from(tx_hashes))),
+        ("log_index", Arc::new(UInt64Array::from(log_indices))),
+        ("contract", Arc::new(StringArray::from(contracts))),
+        ("topic0", Arc::new(StringArray::from(topic_zeros))),
+        ("name", Arc::new(StringArray::from(names))),
+        ("params", Arc::new(StringArray::from(params))),
+        ("nametags", Arc::new(nametags.finish())),
+        ("value_fiat", Arc::new(Float64Array::from(values))),
+        ("spam", Arc::new(BooleanArray::from(spam))),
+        ("status", Arc::new(UInt64Array::from(statuses))),
+        (
+            "cumulative_gas_used",
+            Arc::new(StringArray::from(cumulative_gas)),
+        ),
+    ];
+    let fields: Vec<Field> = columns
+        .iter()
+        .map(|(name, array)| Field::new(*name, array.data_type().clone(), true))
+        .collect();
+    let schema = Arc::new(Schema::new(fields));
+    let batch = RecordBatch::try_new(
+        schema.clone(),
+        columns.into_iter().map(|(_, array)| array).collect(),
+    )?;
+
+    let properties = WriterProperties::builder()
+        .set_compression(Compression::SNAPPY)
+        .build();
+    let mut writer = ArrowWriter::try_new(File::create(path)?, schema, Some(properties))?;
+    writer.write(&batch)?;
+    writer.close()?;
+    Ok(())
+}
+
+/// Writes the transactions and their events as a JSON array.
+pub fn to_json(history: &AddressHistory, path: &Path, pseudonyms: &Pseudonyms) -> Result<()> {
+    let transactions = transactions_json(history, pseudonyms);
+    serde_json::to_writer_pretty(File::create(path)?, &transactions)?;
+    Ok(())
+}
+
+/// Writes one row per transaction to a CSV file (see `transactions_csv`).
+pub fn to_csv(history: &AddressHistory, path: &Path, pseudonyms: &Pseudonyms) -> Result<()> {
+    let mut csv = format!("{}\n", CSV_HEADER);
+    csv.push_str(&transactions_csv(history, pseudonyms));
+    fs::write(path, csv)?;
+    Ok(())
+}
+
+/// Rows of the CSV export, without the header: the address explored, then
+/// the chain, block, index, hash, sender, recipient, value, receipt status,
+/// gas used, timestamp, action and number of events of each transaction.
+pub fn transactions_csv(history: &AddressHistory, pseudonyms: &Pseudonyms) -> String {
+    let owner = history
+        .address
+        .trim_start_matches("0x")
+        .parse()
+        .map(|a| pseudonyms.address(&a))
+        .unwrap_or_else(|_| history.address.clone());
+    let mut csv = String::new();
+    for tx in &history.transactions {
+        let desc = tx.description.as_ref();
+        let receipt = tx.receipt.as_ref();
+        let text = |value: Option<String>| csv_field(&value.unwrap_or_default());
+        let fields = [
+            owner.clone(),
+            csv_field(&tx.chain),
+            pseudonyms.block(tx.location.block).to_string(),
+            text(
+                pseudonyms
+                    .locating(tx.location.index)
+                    .map(|i| i.to_string()),
+            ),
+            text(desc.map(|d| pseudonyms.tx(&d.hash))),
+            text(desc.and_then(|d| d.from).map(|a| pseudonyms.address(&a))),
+            text(desc.and_then(|d| d.to).map(|a| pseudonyms.address(&a))),
+            text(desc.map(|d| d.value.to_string())),
+            text(receipt.and_then(|r| r.status).map(|s| s.to_string())),
+            text(receipt.and_then(|r| r.gas_used).map(|g| g.to_string())),
+            text(tx.timestamp.map(|t| pseudonyms.timestamp(t).to_string())),
+            text(tx.action.as_ref().map(|a| pseudonyms.text(&a.to_string()))),
+            text(tx.events.as_ref().map(|e| e.len().to_string())),
+        ];
+        csv.push_str(&fields.join(","));
+        csv.push('\n');
+    }
+    csv
+}
+
+/// Writes the value flows between addresses as a Graphviz DOT graph.
+pub fn to_dot(history: &AddressHistory, path: &Path, pseudonyms: &Pseudonyms) -> Result<()> {
+    let mut graph = FlowGraph::new(history);
+    graph.redact(pseudonyms);
+    fs::write(path, pseudonyms.text(&graph.to_dot()))?;
+    Ok(())
+}
+
+/// Writes the value flows between addresses as GraphML (e.g., for Gephi).
+pub fn to_graphml(history: &AddressHistory, path: &Path, pseudonyms: &Pseudonyms) -> Result<()> {
+    let mut graph = FlowGraph::new(history);
+    graph.redact(pseudonyms);
+    fs::write(path, pseudonyms.text(&graph.to_graphml()))?;
+    Ok(())
+}
+
+/// The transactions and their events as JSON values.
+///
+/// If an explorer is configured, transactions, addresses and event emitting
+/// contracts on the main chain are given a "url". Contracts with a token
+/// interface link to the token page. Links are left out when pseudonyms are
+/// used, as they would reveal the address.
+pub fn transactions_json(history: &AddressHistory, pseudonyms: &Pseudonyms) -> Vec<Value> {
+    let mut transactions = vec![];
+    for tx in &history.transactions {
+        let explorer = history
+            .config
+            .explorer
+            .as_ref()
+            .filter(|_| tx.chain == history.config.chain && !pseudonyms.is_active());
+        let address = |a: H160| {
+            json!({
+                "address": pseudonyms.address(&a),
+                "url": explorer.map(|e| e.address(&a)),
+            })
+        };
+        let desc = tx.description.as_ref();
+        let receipt = tx.receipt.as_ref();
+        let events: Vec<Value> = tx
+            .events
+            .iter()
+            .flatten()
+            .enumerate()
+            .map(|(n, event)| {
+                let contract = &event.raw.address;
+                let url = explorer.map(|e| match event.contract.interfaces.is_empty() {
+                    true => e.address(contract),
+                    false => e.token(contract),
+                });
+                json!({
+                    "contract": pseudonyms.address(contract),
+                    "url": url,
+                    "name": event.name,
+                    "name_source": event.name_source,
+                    "name_confidence": event.name_source.map(|s| s.confidence()),
+                    "params": serde_json::from_str::<Value>(&pseudonyms.text(&params_json(event))).ok(),
+                    "value_fiat": event_value(tx, n),
+                    "spam": event.spam,
+                    "log_index": event.log_index,
+                    "transaction_index": pseudonyms.locating(event.transaction_index.unwrap_or(tx.location.index)),
+                    "warnings": warnings_json(&event.warnings, pseudonyms),
+                })
+            })
+            .collect();
+        transactions.push(json!({
+            "chain": tx.chain,
+            "block": pseudonyms.block(tx.location.block),
+            "index": pseudonyms.locating(tx.location.index),
+            "block_url": explorer.map(|e| e.block(tx.location.block.into())),
+            "confirmations": tx.confirmations().and_then(|c| pseudonyms.locating(c)),
+            "miner": tx.miner.map(address),
+            "miner_nametags": tx.miner_nametags,
+            "hash": desc.map(|d| pseudonyms.tx(&d.hash)),
+            "url": desc.and_then(|d| explorer.map(|e| e.tx(&d.hash))),
+            "from": desc.and_then(|d| d.from).map(address),
+            "to": desc.and_then(|d| d.to).map(address),
+            "value_wei": desc.map(|d| d.value.to_string()),
+            "fiat": tx.fiat.as_ref().map(|f| json!({"currency": f.currency, "value": f.value})),
+            "timestamp": tx.timestamp.map(|t| pseudonyms.timestamp(t)),
+            "status": receipt.and_then(|r| r.status).map(|s| s.as_u64()),
+            "gas_used": receipt.and_then(|r| r.gas_used).map(|g| g.to_string()),
+            "cumulative_gas_used": receipt.map(|r| r.cumulative_gas_used.to_string()),
+            "action": tx.action.as_ref().map(|a| pseudonyms.text(&a.to_string())),
+            "receipt_verified": tx.verification
+                .as_ref()
+                .map(|v| matches!(v, Verification::Verified { .. })),
+            "blob": tx.blob.as_ref().map(|b| json!({
+                "versioned_hashes": pseudonyms.locating(&b.versioned_hashes),
+                "max_fee_per_blob_gas": b.max_fee_per_blob_gas.map(|v| v.to_string()),
+                "blob_gas_used": b.blob_gas_used.map(|v| v.to_string()),
+                "blob_gas_price": b.blob_gas_price.map(|v| v.to_string()),
+            })),
+            "events": events,
+            "warnings": warnings_json(&tx.warnings, pseudonyms),
+        }));
+    }
+    transactions
+}
+
+/// Stages that failed, with the transaction they failed for.
+pub fn failures_json(failures: &[StageFailure]) -> Vec<Value> {
+    failures
+        .iter()
+        .map(|f| {
+            json!({
+                "block": f.location.block,
+                "index": f.location.index,
+                "stage": f.stage.to_string(),
+                "error": f.error,
+            })
+        })
+        .collect()
+}
+
+/// Failed lookups as text, with addresses replaced by pseudonyms if used.
+pub fn warnings_json(warnings: &[Warning], pseudonyms: &Pseudonyms) -> Vec<String> {
+    warnings
+        .iter()
+        .map(|w| pseudonyms.text(&w.to_string()))
+        .collect()
+}
+
+/// Parameters of an event as JSON.
+///
+/// Decoded with the ABI if the contract has one with the event. Otherwise the
+/// indexed topics and 32 byte data words are listed in order.
+fn params_json(event: &LoggedEvent) -> String {
+    if let Some(decoded) = event.decoded() {
+        return json!({ "signature": decoded.signature, "params": decoded.params }).to_string();
+    }
+    let topics: Vec<String> = event
+        .raw
+        .topics
+        .iter()
+        .skip(1)
+        .map(|t| format!("0x{}", hex::encode(t)))
+        .collect();
+    let words: Vec<String> = event
+        .raw
+        .data
+        .0
+        .chunks(32)
+        .map(|w| format!("0x{}", hex::encode(w)))
+        .collect();
+    json!({ "topics": topics, "data": words }).to_string()
+}
+
+#[cfg(test)]
+fn exported_transaction(events: usize) -> TxInfo {
+    use web3::types::{H256, U256};
+
+    let mut amount = [0u8; 32];
+    U256::from(1_000_000).to_big_endian(&mut amount);
+    let owner = H256::from(H160::repeat_byte(0x84));
+    let events = (0..events)
+        .map(|n| {
+            let mut event = crate::semantics::test_event(
+                "dac17f958d2ee523a2206206994597c13d831ec7",
+                crate::semantics::TRANSFER,
+                &[owner, H256::from(H160::repeat_byte(0x11))],
+                amount.to_vec(),
+            );
+            event.log_index = Some(n as u64);
+            event.name = Some(String::from("Transfer"));
+            event.nametags = Some(vec![String::from("Tether")]);
+            event
+        })
+        .collect();
+    TxInfo {
+        location: min_know::specs::address_appearance_index::AAIAppearanceTx {
+            block: 17_000_000,
+            index: 4,
+        },
+        description: Some(web3::types::Transaction {
+            hash: H256::repeat_byte(0xab),
+            ..Default::default()
+        }),
+        events: Some(events),
+        ..Default::default()
+    }
+}
+
+#[test]
+fn sqlite_events_read_back() {
+    let path = std::env::temp_dir().join(format!("psr-export-{}.sqlite", std::process::id()));
+    let _ = fs::remove_file(&path);
+    let pseudonyms = Pseudonyms::default();
+    write_sqlite(&[exported_transaction(2)], &path, &pseudonyms).unwrap();
+    let conn = Connection::open(&path).unwrap();
+    let events = |conn: &Connection| {
+        let mut query = conn
+            .prepare("SELECT tx_hash, log_index, contract, name, topic1 FROM events ORDER BY id")
+            .unwrap();
+        query
+            .query_map([], |row| {
+                Ok((
+                    row.get::<_, String>(0)?,
+                    row.get::<_, u64>(1)?,
+                    row.get::<_, String>(2)?,
+                    row.get::<_, String>(3)?,
+                    row.get::<_, String>(4)?,
+                ))
+            })
+            .unwrap()
+            .collect::<Result<Vec<_>, _>>()
+            .unwrap()
+    };
+    let hash = format!("0x{}", "ab".repeat(32));
+    let rows = events(&conn);
+    assert_eq!(rows.len(), 2);
+    assert_eq!(rows[1].0, hash);
+    assert_eq!(rows[1].1, 1);
+    assert_eq!(rows[1].2, "0xdac17f958d2ee523a2206206994597c13d831ec7");
+    assert_eq!(rows[1].3, "Transfer");
+    assert_eq!(
+        rows[1].4,
+        format!("0x{}{}", "00".repeat(12), "84".repeat(20))
+    );
+    let tag: String = conn
+        .query_row("SELECT tag FROM nametags", [], |row| row.get(0))
+        .unwrap();
+    assert_eq!(tag, "Tether");
+
+    // Exporting again replaces the events of the transaction.
+    write_sqlite(&[exported_transaction(1)], &path, &pseudonyms).unwrap();
+    let rows = events(&conn);
+    assert_eq!(rows.len(), 1);
+    assert_eq!(rows[0].0, hash);
+    let transactions: u64 = conn
+        .query_row("SELECT COUNT(*) FROM transactions", [], |row| row.get(0))
+        .unwrap();
+    assert_eq!(transactions, 1);
+    fs::remove_file(&path).unwrap();
+}
+
+#[test]
+fn parquet_events_read_back() {
+    use arrow::array::{Array, ListArray};
+    use parquet::arrow::arrow_reader::ParquetRecordBatchReaderBuilder;
+
+    let path = std::env::temp_dir().join(format!("psr-export-{}.parquet", std::process::id()));
+    let mut untagged = exported_transaction(1);
+    untagged.location.index = 5;
+    for event in untagged.events.iter_mut().flatten() {
+        event.nametags = None;
+    }
+    let transactions = [exported_transaction(2), untagged];
+    write_parquet(&transactions, &path, &Pseudonyms::default()).unwrap();
+    let reader = ParquetRecordBatchReaderBuilder::try_new(File::open(&path).unwrap())
+        .unwrap()
+        .build()
+        .unwrap();
+    let batches = reader.collect::<Result<Vec<_>, _>>().unwrap();
+    assert_eq!(batches.len(), 1);
+    let batch = &batches[0];
+    assert_eq!(batch.num_rows(), 3);
+    let strings = |name: &str| {
+        batch
+            .column_by_name(name)
+            .unwrap()
+            .as_any()
+            .downcast_ref::<StringArray>()
+            .unwrap()
+            .clone()
+    };
+    assert_eq!(
+        strings("contract").value(0),
+        "0xdac17f958d2ee523a2206206994597c13d831ec7"
+    );
+    assert_eq!(strings("name").value(1), "Transfer");
+    let params: Value = serde_json::from_str(strings("params").value(0)).unwrap();
+    assert_eq!(
+        params["topics"][0],
+        format!("0x{}{}", "00".repeat(12), "84".repeat(20))
+    );
+    assert_eq!(params["data"][0], format!("0x{}0f4240", "00".repeat(29)));
+    let tx_indices = batch
+        .column_by_name("tx_index")
+        .unwrap()
+        .as_any()
+        .downcast_ref::<UInt32Array>()
+        .unwrap()
+        .clone();
+    assert_eq!(tx_indices.values(), &[4, 4, 5]);
+    let nametags = batch
+        .column_by_name("nametags")
+        .unwrap()
+        .as_any()
+        .downcast_ref::<ListArray>()
+        .unwrap()
+        .clone();
+    let tags = nametags.value(1);
+    let tags = tags.as_any().downcast_ref::<StringArray>().unwrap();
+    assert_eq!(tags.len(), 1);
+    assert_eq!(tags.value(0), "Tether");
+    assert!(nametags.is_null(2));
+    fs::remove_file(&path).unwrap();
+}
@@ -0,0 +1,292 @@
+//! Transaction types and how the fee of a transaction was split.
+//!
+//! Since London (EIP-1559) the fee is the gas used times the effective gas
+//! price. The base fee part is burned and the rest is a tip to the block
+//! producer. Before London the whole fee was paid to the miner.
+//!
+//! Blob transactions (EIP-4844) also pay for blob gas, which is burned in
+//! full. The blob fields are not part of the web3 transaction and receipt
+//! types, so they are read from the raw JSON-RPC responses.
+use std::fmt::Display;
+
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use web3::types::{H256, U256};
+
+use crate::{
+    data::TxInfo,
+    provider::{Provider, Sourced},
+    semantics::{ether_amount, Amount},
+};
+
+/// Fees below this (0.001 ether) are shown in gwei.
+const SMALL_FEE: u64 = 1_000_000_000_000_000;
+
+/// Envelope type of a transaction (EIP-2718).
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum TxType {
+    Legacy,
+    /// EIP-2930.
+    AccessList,
+    /// EIP-1559.
+    DynamicFee,
+    /// EIP-4844.
+    Blob,
+    Other(u64),
+}
+
+/// Blobs carried by a type 3 transaction (EIP-4844).
+#[derive(Debug, Default, Clone, PartialEq, Eq, Deserialize, Serialize)]
+pub struct BlobInfo {
+    /// One versioned hash (commitment) per blob.
+    pub versioned_hashes: Vec<H256>,
+    /// Maximum price per blob gas the sender would pay.
+    pub max_fee_per_blob_gas: Option<U256>,
+    /// From the receipt.
+    pub blob_gas_used: Option<U256>,
+    /// Price paid per blob gas, from the receipt.
+    pub blob_gas_price: Option<U256>,
+}
+
+/// The fee paid by a transaction and how it was split.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FeeBreakdown {
+    pub tx_type: TxType,
+    pub gas_used: U256,
+    /// Price paid per gas.
+    pub effective_price: U256,
+    /// Maximum price per gas the sender would pay (EIP-1559 and later).
+    pub max_fee: Option<U256>,
+    /// Maximum tip per gas the sender offered (EIP-1559 and later).
+    pub max_priority_fee: Option<U256>,
+    /// Wei burned as base fee, if the base fee of the block is known.
+    pub burned: Option<U256>,
+    /// Wei paid to the block producer, if the base fee of the block is known.
+    pub tip: Option<U256>,
+    /// Blobs and the blob fee, for blob transactions.
+    pub blob: Option<BlobInfo>,
+}
+
+impl TxType {
+    /// Reads the type of a transaction. Transactions without a type are legacy.
+    pub fn of(tx: &TxInfo) -> Option<Self> {
+        let desc = tx.description.as_ref()?;
+        Some(match desc.transaction_type.map(|t| t.as_u64()) {
+            None | Some(0) => TxType::Legacy,
+            Some(1) => TxType::AccessList,
+            Some(2) => TxType::DynamicFee,
+            Some(3) => TxType::Blob,
+            Some(n) => TxType::Other(n),
+        })
+    }
+}
+
+impl FeeBreakdown {
+    /// Computes the fee split. Requires the transaction data and receipt.
+    pub fn new(tx: &TxInfo) -> Option<Self> {
+        let desc = tx.description.as_ref()?;
+        let receipt = tx.receipt.as_ref()?;
+        let tx_type = TxType::of(tx)?;
+        let gas_used = receipt.gas_used?;
+        let effective_price = receipt.effective_gas_price.or(desc.gas_price)?;
+        // Before London blocks have no base fee and the miner received everything.
+        let pre_london = tx.timestamp.is_some() && tx.base_fee.is_none();
+        let base_fee = match pre_london {
+            true => Some(U256::zero()),
+            false => tx.base_fee,
+        };
+        let burned = base_fee.map(|b| b.min(effective_price) * gas_used);
+        let tip = base_fee.map(|b| effective_price.saturating_sub(b) * gas_used);
+        Some(FeeBreakdown {
+            tx_type,
+            gas_used,
+            effective_price,
+            max_fee: desc.max_fee_per_gas,
+            max_priority_fee: desc.max_priority_fee_per_gas,
+            burned,
+            tip,
+            blob: tx.blob.clone(),
+        })
+    }
+    /// Total fee in wei, excluding any blob fee.
+    pub fn total(&self) -> U256 {
+        self.gas_used * self.effective_price
+    }
+}
+
+impl BlobInfo {
+    /// Reads the blob fields from a raw transaction and receipt.
+    pub fn from_json(tx: &Value, receipt: &Value) -> Self {
+        let quantity = |v: &Value| v.as_str().and_then(|s| U256::from_str_radix(s, 16).ok());
+        BlobInfo {
+            versioned_hashes: tx["blobVersionedHashes"]
+                .as_array()
+                .into_iter()
+                .flatten()
+                .filter_map(|h| h.as_str()?.parse().ok())
+                .collect(),
+            max_fee_per_blob_gas: quantity(&tx["maxFeePerBlobGas"]),
+            blob_gas_used: quantity(&receipt["blobGasUsed"]),
+            blob_gas_price: quantity(&receipt["blobGasPrice"]),
+        }
+    }
+    /// Wei burned for blob gas, if the receipt had the blob fields.
+    pub fn fee(&self) -> Option<U256> {
+        Some(self.blob_gas_used? * self.blob_gas_price?)
+    }
+}
+
+/// Fetches the blob fields of a transaction (eth_getTransactionByHash and
+/// eth_getTransactionReceipt).
+pub async fn get_blob_info(provider: &Provider, hash: H256) -> Result<Sourced<BlobInfo>> {
+    let (tx, url) = provider
+        .request("eth_getTransactionByHash", vec![json!(hash)])
+        .await?;
+    let (receipt, _) = provider
+        .request("eth_getTransactionReceipt", vec![json!(hash)])
+        .await?;
+    Ok((BlobInfo::from_json(&tx, &receipt), url))
+}
+
+/// Amount for a gas price in wei.
+fn gwei(value: U256) -> Amount {
+    Amount {
+        value,
+        decimals: 9,
+        symbol: String::from("gwei"),
+    }
+}
+
+/// Amount in wei, for values that would round to zero gwei.
+fn wei(value: U256) -> Amount {
+    Amount {
+        value,
+        decimals: 0,
+        symbol: String::from("wei"),
+    }
+}
+
+/// Amount for a fee, in gwei (or wei) if it would round to zero ether.
+fn fee(value: U256) -> Amount {
+    match value {
+        v if v < U256::exp10(9) => wei(v),
+        v if v < U256::from(SMALL_FEE) => gwei(v),
+        v => ether_amount(v),
+    }
+}
+
+impl Display for TxType {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            TxType::Legacy => write!(f, "Legacy"),
+            TxType::AccessList => write!(f, "Access list (EIP-2930)"),
+            TxType::DynamicFee => write!(f, "EIP-1559"),
+            TxType::Blob => write!(f, "Blob (EIP-4844)"),
+            TxType::Other(n) => write!(f, "Type {}", n),
+        }
+    }
+}
+
+impl Display for FeeBreakdown {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Type: {}", self.tx_type)?;
+        if let (Some(max), Some(priority)) = (self.max_fee, self.max_priority_fee) {
+            write!(
+                f,
+                " (max fee {}, max priority fee {})",
+                gwei(max),
+                gwei(priority)
+            )?;
+        }
+        write!(
+            f,
+            "\n\tFee: {} at {}",
+            fee(self.total()),
+            gwei(self.effective_price)
+        )?;
+        if let (Some(burned), Some(tip)) = (self.burned, self.tip) {
+            write!(f, " (burned {}, tip {})", fee(burned), fee(tip))?;
+        }
+        if let Some(blob) = &self.blob {
+            write!(f, "\n\t{}", blob)?;
+        }
+        Ok(())
+    }
+}
+
+impl Display for BlobInfo {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Blobs: {}", self.versioned_hashes.len())?;
+        if let (Some(used), Some(price)) = (self.blob_gas_used, self.blob_gas_price) {
+            write!(
+                f,
+                " (blob gas {} at {}, burned {})",
+                used,
+                match price < U256::exp10(9) {
+                    true => wei(price),
+                    false => gwei(price),
+                },
+                fee(used * price)
+            )?;
+        }
+        for hash in &self.versioned_hashes {
+            write!(f, "\n\t\tVersioned hash: 0x{}", hex::encode(hash))?;
+        }
+        Ok(())
+    }
+}
+
+#[test]
+fn dynamic_fee_split() {
+    use web3::types::{Transaction, TransactionReceipt, U64};
+
+    let gwei = U256::exp10(9);
+    let tx = TxInfo {
+        description: Some(Transaction {
+            transaction_type: Some(U64::from(2)),
+            max_fee_per_gas: Some(gwei * 30),
+            max_priority_fee_per_gas: Some(gwei * 2),
+            ..Default::default()
+        }),
+        receipt: Some(TransactionReceipt {
+            gas_used: Some(U256::from(21_000)),
+            effective_gas_price: Some(gwei * 12),
+            ..Default::default()
+        }),
+        timestamp: Some(1_700_000_000),
+        base_fee: Some(gwei * 10),
+        ..Default::default()
+    };
+    let fees = FeeBreakdown::new(&tx).unwrap();
+    assert_eq!(fees.tx_type, TxType::DynamicFee);
+    assert_eq!(fees.burned, Some(gwei * 210_000));
+    assert_eq!(fees.tip, Some(gwei * 42_000));
+    assert_eq!(
+        fees.to_string(),
+        "Type: EIP-1559 (max fee 30 gwei, max priority fee 2 gwei)\
+        \n\tFee: 252000 gwei at 12 gwei (burned 210000 gwei, tip 42000 gwei)"
+    );
+}
+
+#[test]
+fn blob_fields() {
+    let hash = format!("0x01{}", "ab".repeat(31));
+    let tx = json!({
+        "type": "0x3",
+        "blobVersionedHashes": [hash],
+        "maxFeePerBlobGas": "0x3b9aca00",
+    });
+    let receipt = json!({ "blobGasUsed": "0x20000", "blobGasPrice": "0x1" });
+    let blob = BlobInfo::from_json(&tx, &receipt);
+    assert_eq!(blob.versioned_hashes.len(), 1);
+    assert_eq!(blob.max_fee_per_blob_gas, Some(U256::exp10(9)));
+    assert_eq!(blob.fee(), Some(U256::from(131_072)));
+    assert_eq!(
+        blob.to_string(),
+        format!(
+            "Blobs: 1 (blob gas 131072 at 1 wei, burned 131072 wei)\n\t\tVersioned hash: {}",
+            hash
+        )
+    );
+}
@@ -0,0 +1,372 @@
+//! Graph of value flows between addresses, for Graphviz (DOT) or Gephi
+//! (GraphML).
+//!
+//! Nodes are the addresses that sent or received value, named with user
+//! labels and nametags where known. Edges are all transfers of one asset
+//! from one address to another, added together:
+//! - Ether sent with a successful transaction (internal transfers are not
+//!   visible without traces).
+//! - ERC-20 `Transfer` events (the amount in the data) and ERC-721
+//!   `Transfer` events (one token each).
+use std::collections::{BTreeMap, BTreeSet};
+
+use web3::types::{H160, U256};
+
+use crate::{
+    abi::{data_word, topic_address, topic_of},
+    data::TxInfo,
+    history::{AddressHistory, VisitNote},
+    redact::Pseudonyms,
+    semantics::{ether_amount, short_address, Amount, TRANSFER},
+};
+
+/// Value transferred, aggregated by sender, recipient and asset.
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct FlowGraph {
+    /// The address whose history the graph is from.
+    pub owner: H160,
+    /// Names of each address in the graph (empty if unknown).
+    pub nodes: BTreeMap<H160, Vec<String>>,
+    pub edges: BTreeMap<(H160, H160, Asset), Flow>,
+    /// First name of each token contract with a name, used as its symbol.
+    pub tokens: BTreeMap<H160, String>,
+    /// Decimals of each token contract whose decimals were read.
+    pub decimals: BTreeMap<H160, u32>,
+}
+
+/// What was transferred.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Asset {
+    Ether,
+    /// A fungible token contract (ERC-20).
+    Token(H160),
+    /// A non-fungible token contract (ERC-721), counted in tokens.
+    Nft(H160),
+}
+
+/// Transfers of one asset from one address to another.
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct Flow {
+    /// Sum of the raw amounts (wei, token base units or number of NFTs).
+    pub total: U256,
+    /// Number of transfers.
+    pub transfers: usize,
+}
+
+impl FlowGraph {
+    /// Builds the graph from the transactions, naming addresses with the
+    /// user labels and the nametags already looked up. Token amounts use the
+    /// decimals already read.
+    pub fn new(history: &AddressHistory) -> Self {
+        let names = |address: &H160| {
+            let tags = match history.cache.nametags.get(&hex::encode(address)) {
+                Some((VisitNote::PriorSuccess, tags)) => Some(tags.clone()),
+                _ => None,
+            };
+            history.config.labels.merge(address, tags)
+        };
+        let owner = history
+            .address
+            .trim_start_matches("0x")
+            .parse()
+            .unwrap_or_default();
+        let decimals = |token: &H160| history.cache.known_decimals(token);
+        FlowGraph::of_transactions(owner, &history.transactions, names, decimals)
+    }
+    fn of_transactions(
+        owner: H160,
+        transactions: &[TxInfo],
+        names: impl Fn(&H160) -> Option<Vec<String>>,
+        decimals: impl Fn(&H160) -> Option<u32>,
+    ) -> Self {
+        let mut graph = FlowGraph {
+            owner,
+            ..Default::default()
+        };
+        let transfer = topic_of(TRANSFER);
+        for tx in transactions {
+            let failed = tx
+                .receipt
+                .as_ref()
+                .and_then(|r| r.status)
+                .is_some_and(|s| s.is_zero());
+            if failed {
+                continue;
+            }
+            if let Some(desc) = &tx.description {
+                if let (Some(from), Some(to)) = (desc.from, desc.to) {
+                    if !desc.value.is_zero() {
+                        graph.add(from, to, Asset::Ether, desc.value);
+                    }
+                }
+            }
+            for event in tx.events.iter().flatten() {
+                let topics = &event.raw.topics;
+                if topics.first() != Some(&transfer) {
+                    continue;
+                }
+                let token = event.raw.address;
+                let (asset, amount) = match topics.len() {
+                    3 => match data_word(&event.raw.data.0, 0) {
+                        Some(amount) => (Asset::Token(token), amount),
+                        None => continue,
+                    },
+                    4 => (Asset::Nft(token), U256::one()),
+                    _ => continue,
+                };
+                graph.add(
+                    topic_address(&topics[1]),
+                    topic_address(&topics[2]),
+                    asset,
+                    amount,
+                );
+            }
+        }
+        for (address, tags) in graph.nodes.iter_mut() {
+            *tags = known_names(names(address));
+        }
+        let tokens: BTreeSet<H160> = graph
+            .edges
+            .keys()
+            .filter_map(|(_, _, asset)| match asset {
+                Asset::Ether => None,
+                Asset::Token(token) | Asset::Nft(token) => Some(*token),
+            })
+            .collect();
+        for token in tokens {
+            if let Some(name) = known_names(names(&token)).into_iter().next() {
+                graph.tokens.insert(token, name);
+            }
+            if let Some(decimals) = decimals(&token) {
+                graph.decimals.insert(token, decimals);
+            }
+        }
+        graph
+    }
+    /// Names addresses that have a pseudonym with only the pseudonym, so
+    /// that their labels and nametags are not shown.
+    pub fn redact(&mut self, pseudonyms: &Pseudonyms) {
+        for (address, names) in self.nodes.iter_mut() {
+            if let Some(pseudonym) = pseudonyms.pseudonym(address) {
+                *names = vec![pseudonym.to_string()];
+            }
+        }
+    }
+    fn add(&mut self, from: H160, to: H160, asset: Asset, amount: U256) {
+        self.nodes.entry(from).or_default();
+        self.nodes.entry(to).or_default();
+        let flow = self.edges.entry((from, to, asset)).or_default();
+        flow.total = flow.total.saturating_add(amount);
+        flow.transfers += 1;
+    }
+    /// Short name for a node: "Self", its first name, or a short address.
+    fn node_label(&self, address: &H160) -> String {
+        if *address == self.owner {
+            return String::from("Self");
+        }
+        match self.nodes.get(address).and_then(|n| n.first()) {
+            Some(name) => name.clone(),
+            None => short_address(address),
+        }
+    }
+    /// Name of a token contract, or a short address.
+    fn token_label(&self, token: &H160) -> String {
+        self.tokens
+            .get(token)
+            .cloned()
+            .unwrap_or_else(|| short_address(token))
+    }
+    /// The total of a flow with the symbol of the asset, e.g., "1.5 ETH".
+    ///
+    /// Only the symbol is given for tokens with unknown decimals.
+    fn amount(&self, asset: &Asset, flow: &Flow) -> String {
+        match asset {
+            Asset::Ether => ether_amount(flow.total).to_string(),
+            Asset::Token(token) => match self.decimals.get(token) {
+                Some(decimals) => Amount {
+                    value: flow.total,
+                    decimals: *decimals,
+                    symbol: self.token_label(token),
+                }
+                .to_string(),
+                None => self.token_label(token),
+            },
+            Asset::Nft(token) => Amount {
+                value: flow.total,
+                decimals: 0,
+                symbol: format!("NFT {}", self.token_label(token)),
+            }
+            .to_string(),
+        }
+    }
+    /// Graphviz DOT, with an edge per asset labelled with the total.
+    pub fn to_dot(&self) -> String {
+        let mut dot = String::from("digraph flows {\n    rankdir=LR;\n    node [shape=box];\n");
+        for (address, names) in &self.nodes {
+            let mut label = format!("{}\\n0x{}", self.node_label(address), hex::encode(address));
+            if names.len() > 1 {
+                label.push_str(&format!("\\n{}", names[1..].join(", ")));
+            }
+            let style = match *address == self.owner {
+                true => ", style=bold",
+                false => "",
+            };
+            dot.push_str(&format!(
+                "    \"0x{}\" [label=\"{}\"{}];\n",
+                hex::encode(address),
+                dot_escape(&label),
+                style
+            ));
+        }
+        for ((from, to, asset), flow) in &self.edges {
+            let mut label = self.amount(asset, flow);
+            if flow.transfers > 1 {
+                label.push_str(&format!(" ({} transfers)", flow.transfers));
+            }
+            dot.push_str(&format!(
+                "    \"0x{}\" -> \"0x{}\" [label=\"{}\"];\n",
+                hex::encode(from),
+                hex::encode(to),
+                dot_escape(&label)
+            ));
+        }
+        dot.push_str("}\n");
+        dot
+    }
+    /// GraphML, with names, assets and amounts as attributes.
+    pub fn to_graphml(&self) -> String {
+        let mut xml = String::from(
+            r#"<?xml version="1.0" encoding="UTF-8"?>
+<graphml xmlns="http://graphml.graphdrawing.org/xmlns">
+  <key id="label" for="node" attr.name="label" attr.type="string"/>
+  <key id="names" for="node" attr.name="names" attr.type="string"/>
+  <key id="owner" for="node" attr.name="owner" attr.type="boolean"/>
+  <key id="asset" for="edge" attr.name="asset" attr.type="string"/>
+  <key id="amount" for="edge" attr.name="amount" attr.type="string"/>
+  <key id="raw_amount" for="edge" attr.name="raw_amount" attr.type="string"/>
+  <key id="transfers" for="edge" attr.name="transfers" attr.type="int"/>
+  <graph id="flows" edgedefault="directed">
+"#,
+        );
+        for (address, names) in &self.nodes {
+            xml.push_str(&format!(
+                "    <node id=\"0x{}\">\n      <data key=\"label\">{}</data>\n      <data key=\"names\">{}</data>\n      <data key=\"owner\">{}</data>\n    </node>\n",
+                hex::encode(address),
+                xml_escape(&self.node_label(address)),
+                xml_escape(&names.join(", ")),
+                *address == self.owner
+            ));
+        }
+        for ((from, to, asset), flow) in &self.edges {
+            let asset_name = match asset {
+                Asset::Ether => String::from("ETH"),
+                Asset::Token(token) | Asset::Nft(token) => format!("0x{}", hex::encode(token)),
+            };
+            xml.push_str(&format!(
+                "    <edge source=\"0x{}\" target=\"0x{}\">\n      <data key=\"asset\">{}</data>\n      <data key=\"amount\">{}</data>\n      <data key=\"raw_amount\">{}</data>\n      <data key=\"transfers\">{}</data>\n    </edge>\n",
+                hex::encode(from),
+                hex::encode(to),
+                asset_name,
+                xml_escape(&self.amount(asset, flow)),
+                flow.total,
+                flow.transfers
+            ));
+        }
+        xml.push_str("  </graph>\n</graphml>\n");
+        xml
+    }
+}
+
+/// Names without empty entries.
+fn known_names(names: Option<Vec<String>>) -> Vec<String> {
+    let names = names.unwrap_or_default().into_iter();
+    names.filter(|n| !n.is_empty()).collect()
+}
+
+fn dot_escape(text: &str) -> String {
+    text.replace('"', "\\\"")
+}
+
+fn xml_escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+#[test]
+fn transfers_added_by_asset() {
+    use web3::types::{Transaction, TransactionReceipt, H256};
+
+    use crate::semantics::test_event;
+
+    let owner = H160::repeat_byte(0xaa);
+    let friend = H160::repeat_byte(0xbb);
+    let token = "0x1111111111111111111111111111111111111111";
+    let nft = "0x2222222222222222222222222222222222222222";
+    let topic = |address: H160| H256::from(address);
+    let tx = |value: u64, status: u64, events| TxInfo {
+        description: Some(Transaction {
+            from: Some(owner),
+            to: Some(friend),
+            value: U256::from(value) * U256::exp10(18),
+            ..Default::default()
+        }),
+        receipt: Some(TransactionReceipt {
+            status: Some(status.into()),
+            ..Default::default()
+        }),
+        events: Some(events),
+        ..Default::default()
+    };
+    let mut amount = [0u8; 32];
+    U256::from(2_500_000u64).to_big_endian(&mut amount);
+    let transfer = test_event(
+        token,
+        TRANSFER,
+        &[topic(friend), topic(owner)],
+        amount.to_vec(),
+    );
+    let nft_transfer = test_event(
+        nft,
+        TRANSFER,
+        &[topic(owner), topic(friend), H256::from_low_u64_be(7)],
+        vec![],
+    );
+    let transactions = vec![
+        tx(1, 1, vec![transfer.clone()]),
+        tx(2, 1, vec![transfer, nft_transfer.clone()]),
+        // Failed, so nothing moved.
+        tx(5, 0, vec![nft_transfer]),
+    ];
+    let names = |address: &H160| match hex::encode(address).as_str() {
+        "1111111111111111111111111111111111111111" => Some(vec![String::from("USDC")]),
+        "bbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbb" => {
+            Some(vec![String::from("Friend"), String::from("Tag \"b\"")])
+        }
+        _ => None,
+    };
+    let decimals = |address: &H160| Some(6).filter(|_| *address == token.parse().unwrap());
+    let graph = FlowGraph::of_transactions(owner, &transactions, names, decimals);
+    assert_eq!(graph.nodes.len(), 2);
+    assert_eq!(graph.edges.len(), 3);
+    let ether = &graph.edges[&(owner, friend, Asset::Ether)];
+    assert_eq!((ether.total, ether.transfers), (U256::exp10(18) * 3, 2));
+    let usdc = &graph.edges[&(friend, owner, Asset::Token(token.parse().unwrap()))];
+    assert_eq!((usdc.total, usdc.transfers), (U256::from(5_000_000u64), 2));
+    let nfts = &graph.edges[&(owner, friend, Asset::Nft(nft.parse().unwrap()))];
+    assert_eq!(nfts.transfers, 1);
+
+    let dot = graph.to_dot();
+    assert!(dot.contains("[label=\"Friend\\n0xbbbb"));
+    assert!(dot.contains("Tag \\\"b\\\"\"];"));
+    assert!(dot.contains("[label=\"3 ETH (2 transfers)\"]"));
+    assert!(dot.contains("[label=\"5 USDC (2 transfers)\"]"));
+    assert!(dot.contains("[label=\"1 NFT 0x2222"));
+    // Without the decimals of the token only its symbol is shown.
+    let unknown = FlowGraph::of_transactions(owner, &transactions, names, |_| None);
+    assert!(unknown.to_dot().contains("[label=\"USDC (2 transfers)\"]"));
+    let graphml = graph.to_graphml();
+    assert!(graphml.contains("<data key=\"names\">Friend, Tag &quot;b&quot;</data>"));
+    assert_eq!(graphml.matches("<edge ").count(), 3);
+}
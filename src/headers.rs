@@ -0,0 +1,268 @@
+//! Verification of block headers from a source other than the node.
+//!
+//! Receipts are checked against the receipts root of their block header (see
+//! `verify`), but the header comes from the same node. A header is checked
+//! in two steps:
+//! 1. The header fields are hashed, so the receipts root is bound to the
+//!    block hash the node reported.
+//! 2. The block hash is checked by a `HeaderVerifier`, a source of canonical
+//!    block hashes that does not depend on the node.
+//!
+//! Backends:
+//! - `TrustedHashes`: a file of block hashes, e.g., exported from the
+//!   pre-merge header accumulator of the Portal Network.
+//! - `TrustedNode`: a node that verifies headers itself, e.g., a beacon
+//!   light client that serves the execution JSON-RPC API.
+use std::{collections::HashMap, fmt::Display, fs, ops::Deref, path::Path, sync::Arc};
+
+use anyhow::{anyhow, bail, Context, Result};
+use async_trait::async_trait;
+use rlp::RlpStream;
+use serde_json::Value;
+use web3::{signing::keccak256, types::H256};
+
+use crate::{provider::Provider, rpc::RpcPool};
+
+/// Header fields in the order they are encoded, with whether the field is
+/// an integer. Fields added by later forks are only present after the fork.
+const HEADER_FIELDS: [(&str, bool); 21] = [
+    ("parentHash", false),
+    ("sha3Uncles", false),
+    ("miner", false),
+    ("stateRoot", false),
+    ("transactionsRoot", false),
+    ("receiptsRoot", false),
+    ("logsBloom", false),
+    ("difficulty", true),
+    ("number", true),
+    ("gasLimit", true),
+    ("gasUsed", true),
+    ("timestamp", true),
+    ("extraData", false),
+    ("mixHash", false),
+    ("nonce", false),
+    // London
+    ("baseFeePerGas", true),
+    // Shanghai
+    ("withdrawalsRoot", false),
+    // Cancun
+    ("blobGasUsed", true),
+    ("excessBlobGas", true),
+    ("parentBeaconBlockRoot", false),
+    // Prague
+    ("requestsHash", false),
+];
+
+/// A source of canonical block hashes.
+#[async_trait]
+pub trait HeaderVerifier: Send + Sync {
+    /// Short name used in logs and output.
+    fn name(&self) -> &str;
+    /// Checks that the block hash is canonical for the block number.
+    async fn verify(&self, number: u64, hash: H256) -> Result<()>;
+}
+
+/// Shared handle to the header verifier used by the pipeline.
+#[derive(Clone)]
+pub struct Verifier(Arc<dyn HeaderVerifier>);
+
+/// Block hashes read from a file.
+///
+/// Each line is `number,hash`. Blank lines and lines starting with `#` are
+/// skipped.
+pub struct TrustedHashes {
+    name: String,
+    hashes: HashMap<u64, H256>,
+}
+
+/// A node whose block hashes are trusted (e.g., a light client).
+pub struct TrustedNode {
+    name: String,
+    provider: Provider,
+}
+
+impl Verifier {
+    pub fn new(verifier: impl HeaderVerifier + 'static) -> Self {
+        Verifier(Arc::new(verifier))
+    }
+    /// A trusted node for an http(s) URL, otherwise a file of block hashes.
+    pub fn from_source(source: &str) -> Result<Self> {
+        match source.starts_with("http://") || source.starts_with("https://") {
+            true => Ok(Verifier::new(TrustedNode::new(source)?)),
+            false => Ok(Verifier::new(TrustedHashes::load(Path::new(source))?)),
+        }
+    }
+}
+
+impl TrustedHashes {
+    pub fn load(path: &Path) -> Result<Self> {
+        let text = fs::read_to_string(path)
+            .with_context(|| format!("Could not read block hashes {}", path.display()))?;
+        let hashes = parse_hashes(&text)
+            .with_context(|| format!("Invalid block hashes file {}", path.display()))?;
+        Ok(TrustedHashes {
+            name: path.display().to_string(),
+            hashes,
+        })
+    }
+}
+
+impl TrustedNode {
+    pub fn new(url: &str) -> Result<Self> {
+        Ok(TrustedNode {
+            name: url.to_string(),
+            provider: Provider::new(RpcPool::new(vec![url.to_string()])?),
+        })
+    }
+}
+
+#[async_trait]
+impl HeaderVerifier for TrustedHashes {
+    fn name(&self) -> &str {
+        &self.name
+    }
+    async fn verify(&self, number: u64, hash: H256) -> Result<()> {
+        match self.hashes.get(&number) {
+            Some(trusted) if *trusted == hash => Ok(()),
+            Some(trusted) => bail!("Block {} has hash {:?}, not {:?}", number, trusted, hash),
+            None => bail!("Block {} is not in the list of hashes", number),
+        }
+    }
+}
+
+#[async_trait]
+impl HeaderVerifier for TrustedNode {
+    fn name(&self) -> &str {
+        &self.name
+    }
+    async fn verify(&self, number: u64, hash: H256) -> Result<()> {
+        let (block, _) = self.provider.block(number).await?;
+        let trusted = block
+            .and_then(|b| b.hash)
+            .ok_or_else(|| anyhow!("No block {}", number))?;
+        if trusted != hash {
+            bail!("Block {} has hash {:?}, not {:?}", number, trusted, hash);
+        }
+        Ok(())
+    }
+}
+
+/// Reads `number,hash` lines.
+fn parse_hashes(text: &str) -> Result<HashMap<u64, H256>> {
+    let mut hashes = HashMap::new();
+    for (n, line) in text.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let (number, hash) = line
+            .split_once(',')
+            .ok_or_else(|| anyhow!("Line {}: expected number,hash", n + 1))?;
+        let number = number
+            .trim()
+            .parse()
+            .with_context(|| format!("Line {}", n + 1))?;
+        let hash = hash
+            .trim()
+            .parse()
+            .with_context(|| format!("Line {}", n + 1))?;
+        hashes.insert(number, hash);
+    }
+    Ok(hashes)
+}
+
+/// Hash of a header from the JSON returned by eth_getBlockByNumber.
+pub fn header_hash(header: &Value) -> Result<H256> {
+    let mut fields = vec![];
+    for (name, is_integer) in HEADER_FIELDS {
+        let Some(value) = header.get(name).and_then(|v| v.as_str()) else {
+            continue;
+        };
+        let digits = value.trim_start_matches("0x");
+        let digits = match digits.len() % 2 {
+            1 => format!("0{}", digits),
+            _ => digits.to_string(),
+        };
+        let mut bytes =
+            hex::decode(digits).with_context(|| format!("Header field {} is not hex", name))?;
+        if is_integer {
+            let start = bytes.iter().position(|b| *b != 0).unwrap_or(bytes.len());
+            bytes.drain(..start);
+        }
+        fields.push(bytes);
+    }
+    if fields.len() < 15 {
+        bail!("Header is missing fields");
+    }
+    let mut stream = RlpStream::new_list(fields.len());
+    for field in &fields {
+        stream.append(&field.as_slice());
+    }
+    Ok(H256::from(keccak256(&stream.out())))
+}
+
+impl Deref for Verifier {
+    type Target = dyn HeaderVerifier;
+
+    fn deref(&self) -> &Self::Target {
+        self.0.as_ref()
+    }
+}
+
+impl std::fmt::Debug for Verifier {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_tuple("Verifier").field(&self.name()).finish()
+    }
+}
+
+impl PartialEq for Verifier {
+    fn eq(&self, other: &Self) -> bool {
+        Arc::ptr_eq(&self.0, &other.0)
+    }
+}
+
+impl Display for Verifier {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.name())
+    }
+}
+
+#[test]
+fn genesis_header_hash() {
+    let zero = format!("0x{}", "00".repeat(32));
+    let empty_root = "0x56e81f171bcc55a6ff8345e692c0f86e5b48e01b996cadc001622fb5e363b421";
+    let header = serde_json::json!({
+        "parentHash": zero,
+        "sha3Uncles": "0x1dcc4de8dec75d7aab85b567b6ccd41ad312451b948a7413f0a142fd40d49347",
+        "miner": format!("0x{}", "00".repeat(20)),
+        "stateRoot": "0xd7f8974fb5ac78d9ac099b9ad5018bedc2ce0a72dad1827a1709da30580f0544",
+        "transactionsRoot": empty_root,
+        "receiptsRoot": empty_root,
+        "logsBloom": format!("0x{}", "00".repeat(256)),
+        "difficulty": "0x400000000",
+        "number": "0x0",
+        "gasLimit": "0x1388",
+        "gasUsed": "0x0",
+        "timestamp": "0x0",
+        "extraData": "0x11bbe8db4e347b4e8c937c1c8370e4b5ed33adb3db69cbdb7a38e1e50b1b82fa",
+        "mixHash": zero,
+        "nonce": "0x0000000000000042",
+    });
+    assert_eq!(
+        hex::encode(header_hash(&header).unwrap()),
+        "d4e56740f876aef8c010b86a40d5f56745a118d0906a34e69aec8c0db1cb8fa3"
+    );
+}
+
+#[tokio::test]
+async fn hashes_from_file() {
+    let hash = H256::repeat_byte(0xab);
+    let verifier = TrustedHashes {
+        name: String::from("test"),
+        hashes: parse_hashes(&format!("# number,hash\n\n15537393,{:?}\n", hash)).unwrap(),
+    };
+    assert!(verifier.verify(15537393, hash).await.is_ok());
+    assert!(verifier.verify(15537393, H256::zero()).await.is_err());
+    assert!(verifier.verify(1, hash).await.is_err());
+    assert!(parse_hashes("15537393").is_err());
+}
@@ -1,12 +1,17 @@
-use std::{fmt::Display, path::PathBuf};
+use std::{
+    collections::{hash_map::Entry, BTreeMap, BTreeSet, HashMap},
+    fmt::Display,
+    fs::{self, File},
+    path::{Path, PathBuf},
+    sync::Arc,
+    thread,
+};
 
-use anyhow::{anyhow, Result};
-use log::debug;
+use anyhow::{anyhow, bail, Context, Result};
+use clap::ValueEnum;
+use log::{debug, info, warn};
 use min_know::{
-    config::{
-        address_appearance_index::Network,
-        choices::{DataKind, DirNature},
-    },
+    config::choices::DirNature,
     database::types::Todd,
     specs::{
         address_appearance_index::{AAIAppearanceTx, AAISpec},
@@ -16,28 +21,66 @@ use min_know::{
 };
 
 use serde::{Deserialize, Serialize};
-use web3::{
-    transports::Http,
-    types::{BlockNumber, Log, H160},
-    Web3,
-};
+use web3::types::{Log, TransactionId, TransactionReceipt, H160, H256, U256};
 
 use crate::{
+    abi::{padded_address, topic_of},
+    apis::SourcifyRepo,
     cache::Cache,
-    contract::cid_from_runtime_bytecode,
-    data::{Contract, LoggedEvent, TxInfo},
-    parsing::h160_to_string,
+    cancel::Cancellation,
+    capabilities,
+    chains::MAINNET,
+    contract::{constructor_args, read_trailer, Trailer},
+    data::{
+        CodeStatus, Contract, DecompileStatus, LoggedEvent, RecoveredFunction, TxInfo,
+        VerificationStatus, Warning,
+    },
+    db::Database,
+    decode::{decode_constructor, event_signatures, DecodedCall},
+    decompile::{decompiled_selectors, default_workers, panic_message, DecompilePool, Finished},
+    event_index::EventIndex,
+    explorer::Explorer,
+    fees::{get_blob_info, TxType},
+    headers::Verifier,
+    inspect::message_text,
+    labels::UserLabels,
+    outcome::{tolerate, IndexMiss, Stage, StageFailure},
+    parsing::{h160_to_string, validate_address, AddressError},
+    pipeline::{Pipeline, StageKind},
+    portal::PortalNode,
+    prices::{value_of, Prices},
+    profile::ContractProfile,
+    provider::{unsupported, Capability, Provider},
+    render::{BlockView, FormatOptions, HistoryView},
+    rpc::{RpcPool, Unreachable},
+    semantics::{RecognizerRegistry, TRANSFER},
+    signatures::NameSource,
+    spam,
+    stats::HistoryStats,
+    store::{ArtifactStore, StoredHistory},
+    verify::{check_logs_bloom, encode_receipt, verified_block_receipts, Verification},
 };
 
 /// Selected mode of operation. APIs are used as temporary stop-gaps.
 ///
 /// Available APIs: Sourcify and 4byte.directory.
-#[allow(dead_code)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum Mode {
     AvoidApis,
     UseApis,
 }
 
+/// Block at which the code of a contract is examined.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, ValueEnum)]
+pub enum CodeBlock {
+    /// The block of the event, and the latest block if the node does not
+    /// have the code at that block.
+    #[default]
+    Emission,
+    /// The latest block only.
+    Latest,
+}
+
 #[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct Config {
     /// Database that contains the indexed transaction appearances.
@@ -46,25 +89,139 @@ pub struct Config {
     pub signatures_db: Todd<SignaturesSpec>,
     /// Database that contains the indexed transaction appearances.
     pub nametags_db: Todd<NameTagsSpec>,
-    /// RPC URL of local node.
-    pub rpc_url: &'static str,
+    /// Source of chain data (e.g., RPC nodes or a Portal node).
+    #[serde(skip)]
+    pub provider: Provider,
+    /// Directory for bytecode and decompiled contracts kept between runs.
+    pub store: ArtifactStore,
+    /// Address labels supplied by the user, which take precedence over nametags.
+    pub labels: UserLabels,
+    /// Block explorer to link to, if any.
+    pub explorer: Option<Explorer>,
+    /// Where verified contracts are looked up.
+    #[serde(skip)]
+    pub sourcify: SourcifyRepo,
+    /// Recognizers used to interpret transactions.
+    #[serde(skip)]
+    pub recognizers: RecognizerRegistry,
+    /// Name of the chain that the provider and appearances index serve.
+    pub chain: String,
+    /// Chain id of `chain`, if known (e.g., for EIP-1191 checksums).
+    pub chain_id: Option<u64>,
+    /// Recompute the logs bloom of each receipt and flag mismatches.
+    pub verify_blooms: bool,
+    /// Check each receipt against the receipts root of its block header.
+    pub verify_receipts: bool,
+    /// Source of canonical block hashes, to check the block headers.
+    #[serde(skip)]
+    pub header_verifier: Option<Verifier>,
+    /// Workers that decompile contracts without a verified ABI.
+    #[serde(skip)]
+    pub decompiler: DecompilePool,
+    /// Keep decoded transactions in the store and only fetch those in later
+    /// blocks on the next run (see `run_incremental`).
+    pub incremental: bool,
+    /// Query ERC-165 for a curated list of interfaces and the EIP-1820
+    /// registry for each contract (see `interfaces`).
+    pub interface_lookups: bool,
+    /// Drop the bytecode of each contract once its events are decoded, to
+    /// keep large histories small. It stays in the artifact store.
+    pub drop_bytecode: bool,
+    /// Drop the logs and logs bloom of each receipt once the history is
+    /// decoded and verified, to run large histories with little memory. The
+    /// events keep the logs they were decoded from.
+    pub light: bool,
+    /// Call name() and symbol() on contracts without a nametag, to label
+    /// them (e.g., tokens) without local data.
+    pub onchain_names: bool,
+    /// Ask the node who deployed each contract that emitted an event (see
+    /// `Capability::Creators`).
+    pub deployer_lookups: bool,
+    /// Block at which contract code is fetched for events.
+    pub code_at: CodeBlock,
+    /// Stages that a run is limited to, in order (see `pipeline`). None runs
+    /// them all.
+    pub stages: Option<Vec<StageKind>>,
+    /// Set when the run should stop early (see `cancel`).
+    #[serde(skip)]
+    pub cancel: Cancellation,
 }
 
 /// Represents historical activity data for a single address.
 #[derive(Debug, Clone, PartialEq)]
 pub struct AddressHistory {
-    /// Address that a user wants to explore.
-    pub address: &'static str,
+    /// Address that a user wants to explore, "0x" prefixed lowercase hex.
+    pub address: String,
     /// Holds information for all transactions relevant to the address.
     pub transactions: Vec<TxInfo>,
     /// Settings and configurations.
     pub config: Config,
     /// A Cache of things looked up.
     pub cache: Cache,
+    /// Stages that failed for individual transactions.
+    pub failures: Vec<StageFailure>,
+    /// Lookups that failed, for every transaction and event (also kept on
+    /// each of them) and for contracts decompiled afterwards.
+    pub warnings: Vec<Warning>,
+    /// Where each kind of event occurs, built when logs are decoded.
+    pub events: EventIndex,
+    /// Where the appearances came from in the index, once looked up.
+    pub sources: Option<AppearanceSources>,
+}
+
+/// Volumes of the appearance index that held appearances of an address.
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct AppearanceSources {
+    /// Chapter of the index (the first two hex characters of the address).
+    pub chapter: String,
+    /// Volumes with at least one appearance.
+    pub volumes: usize,
+    /// Appearances after duplicates were dropped.
+    pub appearances: usize,
+    /// Appearances found in more than one volume (e.g., a volume published
+    /// again), counted once.
+    pub duplicates: usize,
+}
+
+/// State of a history written by `AddressHistory::save`.
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+struct SavedHistory {
+    /// Version of the program that saved the history.
+    version: String,
+    address: String,
+    chain: String,
+    transactions: Vec<TxInfo>,
+    cache: Cache,
+    failures: Vec<StageFailure>,
+    #[serde(default)]
+    warnings: Vec<Warning>,
+}
+
+/// Reads a history written by `AddressHistory::save`.
+fn read_saved(path: &Path) -> Result<SavedHistory> {
+    let text = fs::read_to_string(path)
+        .with_context(|| format!("Could not read history {}", path.display()))?;
+    let saved: SavedHistory = serde_json::from_str(&text)
+        .with_context(|| format!("Invalid history {}", path.display()))?;
+    if saved.version != env!("CARGO_PKG_VERSION") {
+        warn!(
+            "History {} was saved by version {}",
+            path.display(),
+            saved.version
+        );
+    }
+    Ok(saved)
+}
+
+/// The address and transactions of a history written by
+/// `AddressHistory::save`, without a config (e.g., to compare runs).
+pub fn saved_transactions(path: &Path) -> Result<(String, Vec<TxInfo>)> {
+    let saved = read_saved(path)?;
+    Ok((saved.address, saved.transactions))
 }
 
 /// A resource may have been looked up before. This stores the result of that attempt.
-#[derive(Debug, Default, Clone, PartialEq)]
+#[derive(Debug, Default, Clone, PartialEq, Deserialize, Serialize)]
 pub enum VisitNote {
     #[default]
     NotVisited,
@@ -72,82 +229,502 @@ pub enum VisitNote {
     PriorFailure,
 }
 
+/// Builds a `Config` with a directory for each TODD database, e.g.,
+/// appearances on an external drive and the other databases in the default
+/// location.
+///
+/// Databases without a directory of their own use the shared directories
+/// (the sample data unless set). `validate` checks the RPC URLs, the node and
+/// the databases before anything is fetched.
+#[derive(Debug, Clone, Default)]
+pub struct ConfigBuilder {
+    directories: Option<DirNature>,
+    appearances: Option<DirNature>,
+    signatures: Option<DirNature>,
+    nametags: Option<DirNature>,
+    provider: Provider,
+    store: ArtifactStore,
+    /// Endpoints to make the provider from, in place of `provider`.
+    rpc_urls: Vec<String>,
+    /// Treat the first RPC URL as a Portal Network node.
+    portal: bool,
+    /// Chain id that the node must serve.
+    chain_id: Option<u64>,
+    /// Do not contact the node when validating.
+    offline: bool,
+}
+
+/// A config whose RPC URLs, node and databases were checked (see
+/// `ConfigBuilder::validate`).
+#[derive(Debug)]
+pub struct ValidatedConfig {
+    /// Config with the provider restricted to the capabilities found.
+    pub config: Config,
+    /// Chain id reported by the node, if known.
+    pub chain_id: Option<u64>,
+    /// Endpoints made from the RPC URLs, to report their health.
+    pub pool: Option<RpcPool>,
+}
+
+/// Something that would make the pipeline fail.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ConfigProblem {
+    /// An RPC URL that is not an http(s) URL, and why.
+    InvalidUrl(String, String),
+    /// No RPC endpoint answered.
+    Unreachable(String),
+    /// The node serves another chain.
+    WrongChain { expected: u64, found: u64 },
+    /// A database that could not be opened, and why.
+    Database(Database, String),
+}
+
+/// All the problems found when validating a config.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ConfigProblems(pub Vec<ConfigProblem>);
+
+impl ConfigBuilder {
+    /// Sample, Default or Custom directories for all databases.
+    pub fn directories(mut self, directories: DirNature) -> Self {
+        self.directories = Some(directories);
+        self
+    }
+    pub fn appearances_dir(mut self, dir: impl Into<PathBuf>) -> Self {
+        self.appearances = Some(DirNature::Custom(dir.into()));
+        self
+    }
+    pub fn signatures_dir(mut self, dir: impl Into<PathBuf>) -> Self {
+        self.signatures = Some(DirNature::Custom(dir.into()));
+        self
+    }
+    pub fn nametags_dir(mut self, dir: impl Into<PathBuf>) -> Self {
+        self.nametags = Some(DirNature::Custom(dir.into()));
+        self
+    }
+    pub fn provider(mut self, provider: Provider) -> Self {
+        self.provider = provider;
+        self
+    }
+    pub fn store(mut self, store: ArtifactStore) -> Self {
+        self.store = store;
+        self
+    }
+    /// RPC endpoints, tried in order. Replaces the provider when validated.
+    pub fn rpc_urls(mut self, urls: Vec<String>) -> Self {
+        self.rpc_urls = urls;
+        self
+    }
+    /// Treat the first RPC endpoint as a Portal Network node.
+    pub fn portal(mut self, portal: bool) -> Self {
+        self.portal = portal;
+        self
+    }
+    /// Chain id that the node must serve (e.g., 1 for the mainnet index).
+    pub fn chain_id(mut self, chain_id: u64) -> Self {
+        self.chain_id = Some(chain_id);
+        self
+    }
+    /// Validate without contacting the node (e.g., for a loaded history).
+    pub fn offline(mut self) -> Self {
+        self.offline = true;
+        self
+    }
+    /// Directories of a database.
+    pub fn directory(&self, database: Database) -> DirNature {
+        let own = match database {
+            Database::Appearances => &self.appearances,
+            Database::Signatures => &self.signatures,
+            Database::Nametags => &self.nametags,
+        };
+        own.as_ref()
+            .or(self.directories.as_ref())
+            .cloned()
+            .unwrap_or(DirNature::Sample)
+    }
+    /// Sets up the TODD databases, without checks.
+    pub fn build(self) -> Result<Config> {
+        let open = |database: Database| (database.kind(), self.directory(database));
+        let (kind, dir) = open(Database::Appearances);
+        let appearances_db = Todd::init(kind, dir)?;
+        let (kind, dir) = open(Database::Signatures);
+        let signatures_db = Todd::init(kind, dir)?;
+        let (kind, dir) = open(Database::Nametags);
+        let nametags_db = Todd::init(kind, dir)?;
+        Ok(self.assemble(appearances_db, signatures_db, nametags_db))
+    }
+    /// Checks the RPC URLs, the node (connectivity, chain id and
+    /// capabilities) and the databases, and sets up the config.
+    ///
+    /// Returns every problem found at once, rather than the first. An
+    /// unreachable node or missing appearances are marked as `Unreachable`
+    /// or `IndexMiss`.
+    pub async fn validate(mut self) -> Result<ValidatedConfig> {
+        let mut problems: Vec<ConfigProblem> =
+            self.rpc_urls.iter().filter_map(|u| check_url(u)).collect();
+        let mut pool = None;
+        if !self.rpc_urls.is_empty() && problems.is_empty() {
+            let rpc = RpcPool::new(self.rpc_urls.clone())?;
+            self.provider = match self.portal {
+                true => Provider::new(PortalNode::new(&rpc.urls[0])?),
+                false => Provider::new(rpc.clone()),
+            };
+            pool = Some(rpc);
+        }
+        let mut chain_id = None;
+        if !self.offline && problems.is_empty() {
+            match node_chain_id(&self.provider).await {
+                Ok(id) => chain_id = Some(id),
+                Err(e) if e.downcast_ref::<Unreachable>().is_some() => {
+                    problems.push(ConfigProblem::Unreachable(format!("{:#}", e)));
+                }
+                Err(e) => warn!("Could not get the chain id ({})", e),
+            }
+            if let Some(id) = chain_id {
+                info!("The node serves chain id {}", id);
+            }
+            if let (Some(expected), Some(found)) = (self.chain_id, chain_id) {
+                if expected != found {
+                    problems.push(ConfigProblem::WrongChain { expected, found });
+                }
+            }
+            if problems.is_empty() {
+                let report = capabilities::probe(&self.provider).await;
+                info!("{}", report);
+                self.provider = report.configure(self.provider.clone());
+            }
+        }
+        for database in Database::ALL {
+            if let DirNature::Custom(path) = self.directory(database) {
+                if !path.is_dir() {
+                    let reason = format!("{} is not a directory", path.display());
+                    problems.push(ConfigProblem::Database(database, reason));
+                }
+            }
+        }
+        if problems
+            .iter()
+            .any(|p| matches!(p, ConfigProblem::Database(..)))
+        {
+            return Err(ConfigProblems(problems).into_error());
+        }
+        let open = |database: Database| (database.kind(), self.directory(database));
+        let (kind, dir) = open(Database::Appearances);
+        let appearances_db = Todd::init(kind, dir);
+        let (kind, dir) = open(Database::Signatures);
+        let signatures_db = Todd::init(kind, dir);
+        let (kind, dir) = open(Database::Nametags);
+        let nametags_db = Todd::init(kind, dir);
+        let errors = [
+            (Database::Appearances, appearances_db.as_ref().err()),
+            (Database::Signatures, signatures_db.as_ref().err()),
+            (Database::Nametags, nametags_db.as_ref().err()),
+        ];
+        for (database, error) in errors {
+            if let Some(e) = error {
+                problems.push(ConfigProblem::Database(database, format!("{:#}", e)));
+            }
+        }
+        if !problems.is_empty() {
+            return Err(ConfigProblems(problems).into_error());
+        }
+        let mut config = self.assemble(appearances_db?, signatures_db?, nametags_db?);
+        config.chain_id = chain_id.or(config.chain_id);
+        Ok(ValidatedConfig {
+            config,
+            chain_id,
+            pool,
+        })
+    }
+    fn assemble(
+        self,
+        appearances_db: Todd<AAISpec>,
+        signatures_db: Todd<SignaturesSpec>,
+        nametags_db: Todd<NameTagsSpec>,
+    ) -> Config {
+        Config {
+            appearances_db,
+            signatures_db,
+            nametags_db,
+            provider: self.provider,
+            store: self.store,
+            labels: UserLabels::default(),
+            explorer: None,
+            recognizers: RecognizerRegistry::default(),
+            chain: String::from(MAINNET),
+            chain_id: self.chain_id,
+            verify_blooms: false,
+            verify_receipts: false,
+            header_verifier: None,
+            decompiler: DecompilePool::default(),
+            incremental: false,
+            interface_lookups: false,
+            drop_bytecode: false,
+            light: false,
+            onchain_names: false,
+            deployer_lookups: false,
+            code_at: CodeBlock::default(),
+            sourcify: SourcifyRepo::default(),
+            stages: None,
+            cancel: Cancellation::default(),
+        }
+    }
+}
+
+/// Chain id reported by the node (eth_chainId).
+pub async fn node_chain_id(provider: &Provider) -> Result<u64> {
+    let (value, _) = provider.request("eth_chainId", vec![]).await?;
+    let id = value
+        .as_str()
+        .ok_or_else(|| anyhow!("Expected a chain id, got {}", value))?;
+    Ok(u64::from_str_radix(id.trim_start_matches("0x"), 16)?)
+}
+
+/// A problem with an RPC URL, if any.
+fn check_url(url: &str) -> Option<ConfigProblem> {
+    let problem = |reason: String| Some(ConfigProblem::InvalidUrl(url.to_string(), reason));
+    match reqwest::Url::parse(url) {
+        Ok(parsed) if matches!(parsed.scheme(), "http" | "https") => None,
+        Ok(parsed) => problem(format!("unsupported scheme {}", parsed.scheme())),
+        Err(e) => problem(e.to_string()),
+    }
+}
+
+impl ConfigProblems {
+    /// An error with the problems, marked as `Unreachable` or `IndexMiss`
+    /// for the exit code.
+    fn into_error(self) -> anyhow::Error {
+        let unreachable = self
+            .0
+            .iter()
+            .any(|p| matches!(p, ConfigProblem::Unreachable(_)));
+        let no_index = self
+            .0
+            .iter()
+            .any(|p| matches!(p, ConfigProblem::Database(Database::Appearances, _)));
+        let error = anyhow::Error::new(self);
+        match (unreachable, no_index) {
+            (true, _) => error.context(Unreachable),
+            (false, true) => error.context(IndexMiss),
+            (false, false) => error,
+        }
+    }
+}
+
+impl std::ops::Deref for ValidatedConfig {
+    type Target = Config;
+
+    fn deref(&self) -> &Self::Target {
+        &self.config
+    }
+}
+
+impl Display for AppearanceSources {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{} appearances from {} volumes of chapter 0x{}",
+            self.appearances, self.volumes, self.chapter
+        )?;
+        match self.duplicates {
+            0 => Ok(()),
+            1 => write!(f, " (1 duplicate dropped)"),
+            n => write!(f, " ({} duplicates dropped)", n),
+        }
+    }
+}
+
+impl Display for ConfigProblem {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ConfigProblem::InvalidUrl(url, reason) => {
+                write!(f, "Invalid RPC URL {} ({})", url, reason)
+            }
+            ConfigProblem::Unreachable(reason) => {
+                write!(f, "The node could not be reached ({})", reason)
+            }
+            ConfigProblem::WrongChain { expected, found } => write!(
+                f,
+                "The node serves chain id {}, expected {}",
+                found, expected
+            ),
+            ConfigProblem::Database(database, reason) => {
+                write!(
+                    f,
+                    "The {} database could not be opened ({})",
+                    database, reason
+                )
+            }
+        }
+    }
+}
+
+impl Display for ConfigProblems {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Invalid configuration:")?;
+        for problem in &self.0 {
+            write!(f, "\n\t{}", problem)?;
+        }
+        Ok(())
+    }
+}
+
+impl std::error::Error for ConfigProblems {}
+
 impl Config {
     /// Sets up TODD databases with the option for Sample, Default or Custom directories.
-    pub fn new(directory_nature: DirNature, rpc_url: &'static str) -> Result<Self> {
-        Ok(Config {
-            appearances_db: Todd::init(
-                DataKind::AddressAppearanceIndex(Network::default()),
-                directory_nature.clone(),
-            )?,
-            signatures_db: Todd::init(DataKind::Signatures, directory_nature.clone())?,
-            nametags_db: Todd::init(DataKind::NameTags, directory_nature)?,
-            rpc_url,
-        })
+    pub fn new(
+        directory_nature: DirNature,
+        provider: Provider,
+        store: ArtifactStore,
+    ) -> Result<Self> {
+        Config::builder()
+            .directories(directory_nature)
+            .provider(provider)
+            .store(store)
+            .build()
+    }
+    /// Sets up TODD databases with a directory for each (see `ConfigBuilder`).
+    pub fn builder() -> ConfigBuilder {
+        ConfigBuilder::default()
     }
 }
 
 impl AddressHistory {
-    pub fn new(address: &'static str, config: Config) -> Self {
-        AddressHistory {
+    /// Validates the address and prepares an empty history for it.
+    pub fn new(address: &str, config: Config) -> Result<Self, AddressError> {
+        let address = validate_address(address, config.chain_id)?;
+        Ok(AddressHistory {
             address,
             transactions: vec![],
             config,
             cache: Cache::default(),
+            failures: vec![],
+            warnings: vec![],
+            events: EventIndex::default(),
+            sources: None,
+        })
+    }
+    /// Histories of several addresses, with their appearances looked up in
+    /// parallel (see `find_many`). The remaining stages are run with
+    /// `run_from_appearances`.
+    pub fn of_addresses(addresses: &[String], config: &Config) -> Vec<Result<Self>> {
+        let mut histories: Vec<Result<Self>> = addresses
+            .iter()
+            .map(|a| Ok(AddressHistory::new(a, config.clone())?))
+            .collect();
+        let valid: Vec<String> = histories
+            .iter()
+            .flatten()
+            .map(|h| h.address.clone())
+            .collect();
+        let mut found = find_many(&config.appearances_db, &valid).into_iter();
+        for history in histories.iter_mut() {
+            let Ok(h) = history else {
+                continue;
+            };
+            match found.next() {
+                Some(Ok((appearances, sources))) => h.add_appearances(appearances, sources),
+                Some(Err(e)) => *history = Err(e),
+                None => {}
+            }
         }
+        histories
+    }
+    /// Writes the decoded transactions, the cache and the failures to a file
+    /// (JSON), to be read with `load` in a later run.
+    pub fn save(&self, path: &Path) -> Result<()> {
+        let saved = SavedHistory {
+            version: env!("CARGO_PKG_VERSION").to_string(),
+            address: self.address.clone(),
+            chain: self.config.chain.clone(),
+            transactions: self.transactions.clone(),
+            cache: self.cache.clone(),
+            failures: self.failures.clone(),
+            warnings: self.warnings.clone(),
+        };
+        let file = File::create(path)
+            .with_context(|| format!("Could not create history {}", path.display()))?;
+        serde_json::to_writer(file, &saved)?;
+        Ok(())
+    }
+    /// A history written by `save`, without fetching anything. The config is
+    /// used by any stages run afterwards.
+    pub fn load(path: &Path, mut config: Config) -> Result<Self> {
+        let saved = read_saved(path)?;
+        config.chain = saved.chain;
+        let mut history = AddressHistory::new(&saved.address, config)?;
+        history.events = EventIndex::new(&saved.transactions);
+        history.transactions = saved.transactions;
+        history.cache = saved.cache;
+        history.failures = saved.failures;
+        history.warnings = saved.warnings;
+        Ok(history)
     }
     /// Find the appearances for this address.
     ///
     /// Uses an index of address appearances.
     pub fn get_transaction_ids(&mut self) -> Result<&mut Self> {
-        let values = self.config.appearances_db.find(self.address)?;
-        let mut appearances: Vec<AAIAppearanceTx> = vec![];
-        for record_value in values {
-            // Join together the SSZ vectors in to one Vector.
-            appearances.extend(record_value.value.to_vec());
-        }
+        let (appearances, sources) = find_appearances(&self.config.appearances_db, &self.address)?;
+        self.add_appearances(appearances, sources);
+        Ok(self)
+    }
+    fn add_appearances(&mut self, appearances: Vec<AAIAppearanceTx>, sources: AppearanceSources) {
+        info!("{}: {}", self.address, sources);
+        self.sources = Some(sources);
         for appearance in appearances {
             let info = TxInfo {
                 location: appearance,
-                description: None,
-                receipt: None,
-                events: None,
+                chain: self.config.chain.clone(),
+                ..Default::default()
             };
             self.transactions.push(info)
         }
-        Ok(self)
     }
     /// Get the basic transaction data from a node.
     ///
     /// Uses eth_getTransactionByBlockNumberAndIndex on local node.
     ///
-    /// Number of transactions to get data for can be capped.
+    /// Number of transactions to get data for can be capped, and those past
+    /// the cap are dropped.
     pub async fn get_transaction_data(&mut self, cap_num: Option<u32>) -> Result<&mut Self> {
-        let transport = Http::new(self.config.rpc_url)?;
-        let web3 = Web3::new(transport);
-        let mut txs_with_data = vec![];
-        for (i, tx) in self.transactions.iter().enumerate() {
-            if let Some(cap) = cap_num {
-                if i > cap as usize {
-                    break;
-                }
+        if !self.config.provider.supports(Capability::Transactions) {
+            let reason = unsupported(self.config.provider.name(), Capability::Transactions);
+            warn!("Skipping transaction data. {}", reason);
+            return Ok(self);
+        }
+        self.drop_past_cap(cap_num);
+        for tx in self.transactions.iter_mut() {
+            if self.config.cancel.is_cancelled() {
+                break;
             }
             // eth_getTransactionByBlockNumberAndIndex
-            let tx_data = web3
-                .eth()
-                .transaction(tx.location.as_web3_tx_id())
-                .await?
-                .ok_or_else(|| anyhow!("No data for this transaction id."))?;
-
-            let tx = TxInfo {
+            let id = tx.location.as_web3_tx_id();
+            let result = self
+                .config
+                .provider
+                .transaction(id)
+                .await
+                .and_then(|r| match r {
+                    (Some(data), provider) => Ok((data, provider)),
+                    (None, _) => Err(anyhow!("No data for this transaction id.")),
+                });
+            let result = tolerate(
+                result,
+                Stage::TransactionData,
+                &tx.location,
+                &mut self.failures,
+            )?;
+            let Some((tx_data, provider)) = result else {
+                continue;
+            };
+            *tx = TxInfo {
                 location: tx.location.clone(),
+                chain: std::mem::take(&mut tx.chain),
                 description: Some(tx_data),
-                receipt: None,
-                events: None,
+                providers: BTreeSet::from([provider]),
+                ..Default::default()
             };
-            txs_with_data.push(tx);
         }
-        self.transactions = txs_with_data;
         for t in &self.transactions {
             debug!("{:?}", t.description);
         }
@@ -155,193 +732,1131 @@ impl AddressHistory {
     }
     /// Get the receipts of transactions from a node.
     ///
-    /// Uses eth_getTransactionReceipt on local node.
+    /// Uses eth_getTransactionReceipt on local node. For blob transactions,
+    /// the blob fields are also read from the raw transaction and receipt.
     ///
-    /// Number of transactions to get receipts for can be capped.
+    /// Number of transactions to get receipts for can be capped, and those
+    /// past the cap are dropped.
     pub async fn get_receipts(&mut self, cap_num: Option<u32>) -> Result<&mut Self> {
-        let transport = Http::new(self.config.rpc_url)?;
-        let web3 = Web3::new(transport);
-        let mut txs_with_data: Vec<TxInfo> = vec![];
-        for (i, tx) in self.transactions.iter().enumerate() {
+        if !self.config.provider.supports(Capability::Receipts) {
+            let reason = unsupported(self.config.provider.name(), Capability::Receipts);
+            warn!("Skipping receipts and events. {}", reason);
+            return Ok(self);
+        }
+        self.drop_past_cap(cap_num);
+        let mut block_receipts = BlockReceipts::new(&self.config.provider, &self.transactions);
+        for tx in self.transactions.iter_mut() {
+            if self.config.cancel.is_cancelled() {
+                break;
+            }
+            let Some(description) = &tx.description else {
+                continue;
+            };
+            let hash = description.hash;
+            let result = match block_receipts.take(tx.location.block, hash).await {
+                Some(found) => Ok(found),
+                // eth_getTransactionReceipt
+                None => self
+                    .config
+                    .provider
+                    .receipt(hash)
+                    .await
+                    .and_then(|r| match r {
+                        (Some(receipt), provider) => Ok((receipt, provider)),
+                        (None, _) => Err(anyhow!("No receipt for this transaction hash.")),
+                    }),
+            };
+            let result = tolerate(result, Stage::Receipt, &tx.location, &mut self.failures)?;
+            let Some((tx_receipt, provider)) = result else {
+                continue;
+            };
+            tx.receipt = Some(tx_receipt);
+            tx.providers.insert(provider);
+            if TxType::of(tx) == Some(TxType::Blob) {
+                let result = get_blob_info(&self.config.provider, hash).await;
+                let result = tolerate(result, Stage::BlobData, &tx.location, &mut self.failures)?;
+                tx.blob = result.map(|(blob, _)| blob);
+            }
+        }
+        for t in &self.transactions {
+            debug!("{:?}", t.receipt);
+        }
+        Ok(self)
+    }
+    /// Get the timestamps, base fees and miners of the blocks containing the
+    /// transactions.
+    ///
+    /// Uses eth_getBlockByNumber on local node, once per block. The latest
+    /// block is also noted, for the confirmations of each transaction.
+    ///
+    /// Number of transactions to get timestamps for can be capped.
+    pub async fn get_timestamps(&mut self, cap_num: Option<u32>) -> Result<&mut Self> {
+        if !self.config.provider.supports(Capability::Blocks) {
+            let reason = unsupported(self.config.provider.name(), Capability::Blocks);
+            warn!("Skipping timestamps. {}", reason);
+            return Ok(self);
+        }
+        let head = match self.config.provider.supports(Capability::Logs) {
+            true => match self.config.provider.head_block().await {
+                Ok((head, _)) => Some(head),
+                Err(e) => {
+                    warn!("Could not get the latest block for confirmations ({})", e);
+                    None
+                }
+            },
+            false => None,
+        };
+        let mut blocks: HashMap<u32, (u64, Option<U256>, H160, String)> = HashMap::new();
+        for (i, tx) in self.transactions.iter_mut().enumerate() {
             if let Some(cap) = cap_num {
                 if i > cap as usize {
                     break;
                 }
             }
-            let Some(description) = &tx.description else {
-                continue
+            if self.config.cancel.is_cancelled() {
+                break;
+            }
+            let block = tx.location.block;
+            tx.head_block = head;
+            if let Some((ts, base_fee, miner, provider)) = blocks.get(&block) {
+                tx.timestamp = Some(*ts);
+                tx.base_fee = *base_fee;
+                tx.miner = Some(*miner);
+                tx.miner_nametags = self.cache.try_nametags(miner, &self.config);
+                tx.providers.insert(provider.to_owned());
+                continue;
+            }
+            // eth_getBlockByNumber
+            let result = self
+                .config
+                .provider
+                .block(block.into())
+                .await
+                .and_then(|r| match r {
+                    (Some(b), provider) => {
+                        Ok((b.timestamp.as_u64(), b.base_fee_per_gas, b.author, provider))
+                    }
+                    (None, _) => Err(anyhow!("No block data for block {}.", block)),
+                });
+            let result = tolerate(result, Stage::Timestamp, &tx.location, &mut self.failures)?;
+            let Some((ts, base_fee, miner, provider)) = result else {
+                continue;
             };
-            // eth_getTransactionReceipt
-            let tx_receipt = web3
-                .eth()
-                .transaction_receipt(description.hash)
-                .await?
-                .ok_or_else(|| anyhow!("No receipt for this transaction hash."))?;
-            let mut tx_new = tx.clone();
-            tx_new.receipt = Some(tx_receipt);
-            txs_with_data.push(tx_new);
-        }
-        self.transactions = txs_with_data;
-        for t in &self.transactions {
-            debug!("{:?}", t.receipt);
+            blocks.insert(block, (ts, base_fee, miner, provider.clone()));
+            tx.timestamp = Some(ts);
+            tx.base_fee = base_fee;
+            tx.miner = Some(miner);
+            tx.miner_nametags = self.cache.try_nametags(&miner, &self.config);
+            tx.providers.insert(provider);
         }
+        take_warnings(&mut self.cache, &mut self.warnings);
         Ok(self)
     }
+    /// Drops the transactions past the cap of a stage (the first `cap + 1`
+    /// are kept).
+    fn drop_past_cap(&mut self, cap_num: Option<u32>) {
+        if let Some(cap) = cap_num {
+            self.transactions.truncate(cap as usize + 1);
+        }
+    }
+    /// Summarises the activity in the history.
+    pub fn stats(&self) -> HistoryStats {
+        HistoryStats::new(self)
+    }
+    /// Summarises the interactions of the address with a contract.
+    pub fn contract_profile(&self, contract: &H160) -> ContractProfile {
+        ContractProfile::new(self, *contract)
+    }
+    /// Adds the history of the same address on another chain.
+    ///
+    /// Transactions are ordered by time, with those without a timestamp last.
+    pub fn merge(&mut self, other: AddressHistory) {
+        self.transactions.extend(other.transactions);
+        self.transactions
+            .sort_by_key(|tx| (tx.timestamp.is_none(), tx.timestamp));
+        self.failures.extend(other.failures);
+        self.warnings.extend(other.warnings);
+        self.events = EventIndex::new(&self.transactions);
+    }
+    /// Renders the history as text, limited and detailed as in `options`.
+    pub fn render<'a>(&'a self, options: &'a FormatOptions) -> HistoryView<'a> {
+        HistoryView {
+            history: self,
+            options,
+        }
+    }
     /// Decodes the event signatures of the logs for each transaction
     ///
     /// Every logged event originates from a contract. That contract
     /// is obtained with ethGetCode and useful information is stored
     /// alongside the event.
+    ///
+    /// Contracts without a verified ABI are decompiled in the background, and
+    /// their events are updated once decompilation finishes.
     pub async fn decode_logs(&mut self, cap_num: Option<u32>, mode: Mode) -> Result<&mut Self> {
-        let transport = Http::new(self.config.rpc_url)?;
-        let web3 = Web3::new(transport);
-        let mut txs_with_data: Vec<TxInfo> = vec![];
-        for (i, tx) in self.transactions.iter().enumerate() {
-            if let Some(cap) = cap_num {
-                if i > cap as usize {
-                    break;
-                }
+        let mut decompiled = vec![];
+        self.drop_past_cap(cap_num);
+        for tx in self.transactions.iter_mut() {
+            if self.config.cancel.is_cancelled() {
+                break;
+            }
+            let Some(receipt) = &tx.receipt else {
+                continue;
+            };
+            if tx.pruned {
+                // Decoded already. The receipt no longer has the logs.
+                continue;
             }
-            let Some(receipt) = &tx.receipt else {continue};
             let mut events: Vec<LoggedEvent> = vec![];
-            for log in receipt.logs.clone() {
-                let event = examine_log(&log, &mode, &web3, &self.config, &mut self.cache).await?;
-                let Some(e) = event else {continue};
+            let mut providers: BTreeSet<String> = BTreeSet::new();
+            for log in &receipt.logs {
+                let result = examine_log(log, &mode, &self.config, &mut self.cache).await;
+                let event = tolerate(result, Stage::Events, &tx.location, &mut self.failures)?;
+                let warnings = take_warnings(&mut self.cache, &mut self.warnings);
+                let Some(Some((mut e, provider))) = event else {
+                    continue;
+                };
+                e.warnings = warnings;
+                // Known from the appearance if the node left it out of the log.
+                e.transaction_index.get_or_insert(tx.location.index);
+                providers.extend(provider);
                 events.push(e)
             }
-            let mut tx_new = tx.clone();
-            tx_new.events = Some(events);
-            txs_with_data.push(tx_new);
+            tx.events = Some(events);
+            tx.providers.append(&mut providers);
+            let finished = self.config.decompiler.completed();
+            decompiled.extend(self.cache.add_decompiled(finished));
+        }
+        let finished = self.finished_decompiling().await;
+        decompiled.extend(self.cache.add_decompiled(finished));
+        self.mark_decompiled(&decompiled, &mode).await;
+        self.mark_decompile_failures();
+        take_warnings(&mut self.cache, &mut self.warnings);
+        if self.config.drop_bytecode {
+            drop_bytecode(&mut self.transactions);
         }
-        self.transactions = txs_with_data;
         for t in &self.transactions {
             debug!("{:?}", t.events);
         }
+        self.events = EventIndex::new(&self.transactions);
+        Ok(self)
+    }
+    /// Events with a text signature (e.g., "Transfer(address,address,uint256)")
+    /// or topic zero (hex), with the position of their transaction.
+    pub fn events_by_signature(&self, signature: &str) -> Result<Vec<(usize, &LoggedEvent)>> {
+        let topic = match signature.contains('(') {
+            true => topic_of(signature),
+            false => signature.trim_start_matches("0x").parse()?,
+        };
+        let events = self
+            .events
+            .get(&topic)
+            .iter()
+            .filter_map(|l| {
+                let event = self.transactions.get(l.tx)?.events.as_ref()?.get(l.event)?;
+                Some((l.tx, event))
+            })
+            .collect();
+        Ok(events)
+    }
+    /// Decodes the calldata of transactions to contracts without a verified ABI.
+    ///
+    /// Types are inferred heuristically (see `decode`) and cached by selector
+    /// and contract. For contract creation, the constructor arguments are
+    /// decoded instead, using the ABI of the new contract if it is verified.
+    pub async fn decode_calldata(&mut self, cap_num: Option<u32>, mode: Mode) -> &mut Self {
+        for (i, tx) in self.transactions.iter_mut().enumerate() {
+            if let Some(cap) = cap_num {
+                if i > cap as usize {
+                    break;
+                }
+            }
+            if self.config.cancel.is_cancelled() {
+                break;
+            }
+            let Some(desc) = &tx.description else {
+                continue;
+            };
+            if let (Some(to), Some(text)) = (desc.to, message_text(&desc.input.0)) {
+                if is_account(&to, &self.config).await {
+                    tx.message = Some(text);
+                    continue;
+                }
+            }
+            match desc.to {
+                None => {
+                    let contract = tx.receipt.as_ref().and_then(|r| r.contract_address);
+                    let input = &desc.input.0;
+                    let constructor = decode_constructor_args(
+                        input,
+                        contract,
+                        &mode,
+                        &self.config,
+                        &mut self.cache,
+                    );
+                    tx.constructor = constructor.await;
+                }
+                Some(to) if self.cache.is_verified(&to) => continue,
+                Some(to) => {
+                    tx.call = self
+                        .cache
+                        .try_call(&to, &desc.input.0, &mode, &self.config)
+                        .await;
+                }
+            }
+            tx.warnings = take_warnings(&mut self.cache, &mut self.warnings);
+        }
+        let finished = self.finished_decompiling().await;
+        self.cache.add_decompiled(finished);
+        take_warnings(&mut self.cache, &mut self.warnings);
+        self
+    }
+    /// Waits for the contracts being decompiled, or only takes those already
+    /// done if the run was cancelled.
+    async fn finished_decompiling(&self) -> Vec<Finished> {
+        match self.cancelled() {
+            true => self.config.decompiler.completed(),
+            false => self.config.decompiler.wait().await,
+        }
+    }
+    /// Whether the run was cancelled, so that no more requests are made.
+    pub fn cancelled(&self) -> bool {
+        self.config.cancel.is_cancelled()
+    }
+    /// Updates the events of contracts whose decompilation finished after
+    /// the events were examined.
+    async fn mark_decompiled(&mut self, decompiled: &[H160], mode: &Mode) {
+        for tx in &mut self.transactions {
+            for event in tx.events.iter_mut().flatten() {
+                let contract = &mut event.contract;
+                if !decompiled.contains(&event.raw.address) || contract.abi.is_some() {
+                    continue;
+                }
+                let abi = self.cache.abis.get(&hex::encode(event.raw.address));
+                contract.abi = abi.map(|(_, (abi, _))| abi.to_owned());
+                contract.verification = VerificationStatus::DecompiledOnly;
+                contract.decompiled = true;
+                contract.decompile = DecompileStatus::Decompiled;
+                contract.functions =
+                    recover_functions(&contract.bytecode, mode, &self.config, &mut self.cache)
+                        .await;
+            }
+        }
+    }
+    /// Records why contracts that were being decompiled when their events
+    /// were examined could not be decompiled.
+    fn mark_decompile_failures(&mut self) {
+        for tx in &mut self.transactions {
+            for event in tx.events.iter_mut().flatten() {
+                let contract = &mut event.contract;
+                if contract.decompile != DecompileStatus::Pending {
+                    continue;
+                }
+                let status = self
+                    .cache
+                    .decompile_status(&event.raw.address, &self.config.decompiler);
+                if let DecompileStatus::Failed(_) = status {
+                    contract.decompile = status;
+                }
+            }
+        }
+    }
+    /// Checks that the logs bloom of each receipt matches its logs.
+    ///
+    /// A mismatch is recorded as a failure, as the node may have provided
+    /// altered logs.
+    pub fn verify_blooms(&mut self) -> &mut Self {
+        for tx in &self.transactions {
+            let Some(receipt) = tx.receipt.as_ref().filter(|_| !tx.pruned) else {
+                continue;
+            };
+            if let Err(e) = check_logs_bloom(receipt) {
+                warn!(
+                    "Transaction {}:{} may have altered logs ({})",
+                    tx.location.block, tx.location.index, e
+                );
+                self.failures.push(StageFailure {
+                    stage: Stage::Verification,
+                    location: tx.location.clone(),
+                    error: e.to_string(),
+                });
+            }
+        }
+        self
+    }
+    /// Checks each receipt against the receipts root of its block header and
+    /// marks the transaction verified or unverified.
+    ///
+    /// Every receipt in the block is fetched to rebuild the receipts trie.
+    /// An unverified receipt is recorded as a failure.
+    pub async fn verify_receipts(&mut self) -> Result<&mut Self> {
+        for capability in [Capability::Blocks, Capability::Receipts] {
+            if !self.config.provider.supports(capability) {
+                let reason = unsupported(self.config.provider.name(), capability);
+                warn!("Skipping receipt verification. {}", reason);
+                return Ok(self);
+            }
+        }
+        let header_source = self.config.header_verifier.as_ref().map(|v| v.to_string());
+        let mut blocks: HashMap<u32, Result<Vec<TransactionReceipt>, String>> = HashMap::new();
+        for tx in self.transactions.iter_mut() {
+            if self.config.cancel.is_cancelled() {
+                break;
+            }
+            let Some(receipt) = tx.receipt.as_ref().filter(|_| !tx.pruned) else {
+                continue;
+            };
+            let block = tx.location.block;
+            if let Entry::Vacant(entry) = blocks.entry(block) {
+                let result = verified_block_receipts(
+                    &self.config.provider,
+                    block.into(),
+                    self.config.header_verifier.as_ref(),
+                )
+                .await;
+                if let Err(e) = &result {
+                    if e.downcast_ref::<Unreachable>().is_some() {
+                        return result.map(|_| self);
+                    }
+                }
+                entry.insert(result.map_err(|e| format!("{:#}", e)));
+            }
+            let verification = match &blocks[&block] {
+                Ok(receipts) => {
+                    let included = receipts
+                        .get(tx.location.index as usize)
+                        .and_then(|r| encode_receipt(r).ok());
+                    match included.is_some() && included == encode_receipt(receipt).ok() {
+                        true => Verification::Verified {
+                            header: header_source.clone(),
+                        },
+                        false => Verification::Unverified(String::from(
+                            "Receipt differs from the one in the block",
+                        )),
+                    }
+                }
+                Err(e) => Verification::Unverified(e.to_owned()),
+            };
+            if let Verification::Unverified(reason) = &verification {
+                warn!(
+                    "Transaction {}:{} receipt is {}",
+                    tx.location.block, tx.location.index, verification
+                );
+                self.failures.push(StageFailure {
+                    stage: Stage::Verification,
+                    location: tx.location.clone(),
+                    error: reason.to_owned(),
+                });
+            }
+            tx.verification = Some(verification);
+        }
+        Ok(self)
+    }
+    /// Infers a headline action for each transaction from its events and calldata.
+    ///
+    /// Should be called after logs are decoded. Token amounts are only shown
+    /// for tokens whose decimals can be read.
+    pub async fn interpret(&mut self) -> &mut Self {
+        let decimals = self.token_decimals().await;
+        for tx in self.transactions.iter_mut() {
+            tx.action =
+                self.config
+                    .recognizers
+                    .describe(&self.address, tx, &self.config.labels, &decimals);
+        }
+        self
+    }
+    /// Flags events that are likely unsolicited airdrops.
+    ///
+    /// Should be called after logs are decoded.
+    pub fn detect_spam(&mut self) -> &mut Self {
+        spam::detect(&self.address, &mut self.transactions, &self.config.labels);
+        self
+    }
+    /// Removes events flagged as spam, and transactions from others that only
+    /// had spam.
+    pub fn hide_spam(&mut self) -> &mut Self {
+        spam::hide(&self.address, &mut self.transactions);
+        self.events = EventIndex::new(&self.transactions);
+        self
+    }
+    /// Values the ether and tokens moved by each transaction with the prices
+    /// on the day of its block.
+    ///
+    /// Should be called after logs are decoded.
+    pub async fn price(&mut self, prices: &Prices) -> Result<&mut Self> {
+        let decimals = self.token_decimals().await;
+        for tx in self.transactions.iter_mut() {
+            let result = value_of(tx, &**prices, &decimals, &self.config.labels).await;
+            let value = tolerate(result, Stage::Price, &tx.location, &mut self.failures)?;
+            tx.fiat = value.flatten();
+        }
+        Ok(self)
+    }
+    /// Decimals of the tokens transferred in the history, read from each
+    /// token contract. Tokens whose decimals could not be read are left out.
+    pub async fn token_decimals(&mut self) -> HashMap<H160, u32> {
+        let transfer = topic_of(TRANSFER);
+        let tokens: BTreeSet<H160> = self
+            .transactions
+            .iter()
+            .flat_map(|tx| tx.events.iter().flatten())
+            .filter(|e| e.raw.topics.len() == 3 && e.raw.topics[0] == transfer)
+            .map(|e| e.raw.address)
+            .collect();
+        let mut decimals = HashMap::new();
+        for token in tokens {
+            match self.cache.try_decimals(&token, &self.config).await {
+                Some(d) => {
+                    decimals.insert(token, d);
+                }
+                None => warn!(
+                    "Could not read the decimals of token {:?}, so its amounts are not shown or valued",
+                    token
+                ),
+            }
+        }
+        decimals
+    }
+    /// Runs every stage of the pipeline, then the verifications enabled in
+    /// the config. Only the selected stages are run if the config has them.
+    pub async fn run(&mut self, mode: Mode) -> Result<&mut Self> {
+        if let Some(stages) = self.config.stages.clone() {
+            Pipeline::selected(&stages, mode).run(self).await?;
+            return Ok(self);
+        }
+        if self.config.incremental {
+            return self.run_incremental(mode).await;
+        }
+        self.get_transaction_ids()?.run_from_appearances(mode).await
+    }
+    /// Runs the pipeline for appearances above the watermark stored in an
+    /// earlier run, then adds the transactions stored in that run.
+    ///
+    /// The transactions up to the new watermark (the highest block up to
+    /// which every transaction was processed) are stored for the next run.
+    pub async fn run_incremental(&mut self, mode: Mode) -> Result<&mut Self> {
+        let address: H160 = self.address.trim_start_matches("0x").parse()?;
+        let stored = match self.config.store.history(&address) {
+            Ok(stored) => stored.unwrap_or_default(),
+            Err(e) => {
+                warn!("Could not read the stored history, starting over ({})", e);
+                StoredHistory::default()
+            }
+        };
+        self.get_transaction_ids()?;
+        self.transactions
+            .retain(|tx| tx.location.block > stored.watermark);
+        info!(
+            "Reusing {} transactions up to block {}, {} appearances since",
+            stored.transactions.len(),
+            stored.watermark,
+            self.transactions.len()
+        );
+        self.run_from_appearances(mode).await?;
+        let new = std::mem::replace(&mut self.transactions, stored.transactions);
+        self.transactions.extend(new);
+        self.events = EventIndex::new(&self.transactions);
+        // Whether a token is spam depends on the whole history.
+        self.detect_spam();
+
+        if self.cancelled() {
+            // Later stages may have skipped transactions that have their data.
+            warn!("Cancelled, so the stored history is left as it was");
+            return Ok(self);
+        }
+        let watermark = watermark(&self.transactions, &self.failures, stored.watermark);
+        let update = StoredHistory {
+            watermark,
+            transactions: self
+                .transactions
+                .iter()
+                .filter(|tx| tx.location.block <= watermark)
+                .cloned()
+                .collect(),
+        };
+        if let Err(e) = self.config.store.put_history(&address, &update) {
+            warn!("Could not store the history ({})", e);
+        }
+        Ok(self)
+    }
+    /// Runs the stages after the appearances were looked up (e.g., by
+    /// `of_addresses`).
+    pub async fn run_from_appearances(&mut self, mode: Mode) -> Result<&mut Self> {
+        Pipeline::after_appearances(mode).run(self).await?;
+        Ok(self)
+    }
+    /// History of the sender of a transaction, holding only that transaction
+    /// (not yet decoded, see `decode_transaction`).
+    pub async fn of_transaction(hash: H256, config: Config) -> Result<Self> {
+        let (tx, provider) = config
+            .provider
+            .transaction(TransactionId::Hash(hash))
+            .await?;
+        let tx = tx.ok_or_else(|| anyhow!("No transaction {:?}", hash))?;
+        let (Some(block), Some(index)) = (tx.block_number, tx.transaction_index) else {
+            bail!("Transaction {:?} is not in a block yet", hash);
+        };
+        let sender = format!("0x{}", hex::encode(tx.from.unwrap_or_default()));
+        let chain = config.chain.clone();
+        let mut history = AddressHistory::new(&sender, config)?;
+        history.transactions = vec![TxInfo {
+            location: AAIAppearanceTx {
+                block: block.as_u32(),
+                index: index.as_u32(),
+            },
+            chain,
+            description: Some(tx),
+            providers: BTreeSet::from([provider]),
+            ..Default::default()
+        }];
+        Ok(history)
+    }
+    /// History of the fee recipient of a block, holding every transaction in
+    /// the block (not yet decoded, see `decode_transaction`).
+    pub async fn of_block(number: u64, config: Config) -> Result<Self> {
+        let (block, provider) = config.provider.block_with_txs(number).await?;
+        let block = block.ok_or_else(|| anyhow!("No block {}", number))?;
+        let miner = format!("0x{}", hex::encode(block.author));
+        let chain = config.chain.clone();
+        let mut history = AddressHistory::new(&miner, config)?;
+        history.transactions = block
+            .transactions
+            .into_iter()
+            .enumerate()
+            .map(|(i, tx)| TxInfo {
+                location: AAIAppearanceTx {
+                    block: number as u32,
+                    index: tx.transaction_index.map_or(i as u32, |i| i.as_u32()),
+                },
+                chain: chain.clone(),
+                description: Some(tx),
+                providers: BTreeSet::from([provider.clone()]),
+                ..Default::default()
+            })
+            .collect();
+        Ok(history)
+    }
+    /// The transactions of a block, one line each (see `of_block`).
+    pub fn render_block<'a>(&'a self, options: &'a FormatOptions) -> BlockView<'a> {
+        BlockView {
+            transactions: &self.transactions,
+            fee_recipient: &self.address,
+            warnings: &self.warnings,
+            options,
+        }
+    }
+    /// Drops the logs and logs bloom of each receipt whose logs were decoded
+    /// (see `Config::light`). Logs without an event (e.g., anonymous) stay.
+    pub fn prune_receipts(&mut self) -> &mut Self {
+        prune_receipts(&mut self.transactions);
+        self
+    }
+    /// Runs the stages after the transaction data, for a history that was
+    /// given its transactions (e.g., by `of_transaction`).
+    pub async fn decode_transaction(&mut self, mode: Mode) -> Result<&mut Self> {
+        Pipeline::after_transaction_data(mode).run(self).await?;
         Ok(self)
     }
 }
 
-impl Display for AddressHistory {
-    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        let a = self.address;
-        write!(
-            f,
-            "There are {} txs for address: {}",
-            self.transactions.len(),
-            a
-        )?;
-        for (i, tx) in self.transactions.iter().enumerate() {
-            write!(f, "\n\nTransaction {}:", i)?;
-            let Some(desc) = &tx.description else {continue};
-            let Some(receipt) = &tx.receipt else {continue};
-            let Some(events) = &tx.events else {continue};
-            write!(f, "\n\tSender: {}", nice_address(desc.from, a))?;
-            write!(f, "\n\tRecipient: {}", nice_address(receipt.to, a))?;
-            let calldata = hex::encode(&desc.input.0);
-            if !calldata.is_empty() {
-                write!(f, "\n\tCalldata: {} bytes", desc.input.0.len())?;
-            }
-            if let Some(c) = receipt.contract_address {
-                write!(f, "\n\tContract deployed: {}", nice_address(Some(c), a))?;
-            }
-            write!(f, "\n\tTx Hash: {}", hex::encode(desc.hash))?;
-            let milli_ether = desc.value / 1_000_000_000 / 1_000_000;
-            if !milli_ether.is_zero() {
-                write!(f, "\n\tEther sent: {} mETH", milli_ether)?;
-            }
-            let event_count = events.len();
-            write!(f, "\n\tEvents emitted: {}", event_count)?;
-            for (i, e) in events.iter().enumerate() {
-                if i > 10 {
-                    write!(
-                        f,
-                        "\n\t\tSkipping remaining {} events...",
-                        event_count - i - 1
-                    )?;
-                    break;
+/// Empties the bytecode of the contract of each event. A contract is copied
+/// for each of its events, so its bytecode can dominate a large history.
+fn drop_bytecode(transactions: &mut [TxInfo]) {
+    for event in transactions
+        .iter_mut()
+        .flat_map(|tx| tx.events.iter_mut().flatten())
+    {
+        event.contract.bytecode = Vec::new();
+    }
+}
+
+/// Drops the logs held by the events, and the logs bloom, from each receipt
+/// whose logs were decoded.
+fn prune_receipts(transactions: &mut [TxInfo]) {
+    for tx in transactions {
+        let (Some(receipt), Some(events)) = (&mut tx.receipt, &tx.events) else {
+            continue;
+        };
+        receipt
+            .logs
+            .retain(|log| !events.iter().any(|e| *e.raw == *log));
+        receipt.logs_bloom = Default::default();
+        tx.pruned = true;
+    }
+}
+
+/// The highest block up to which every transaction has its data and no
+/// failed stages, and at least `floor`.
+fn watermark(transactions: &[TxInfo], failures: &[StageFailure], floor: u32) -> u32 {
+    let failed: BTreeSet<u32> = failures.iter().map(|f| f.location.block).collect();
+    let mut watermark = floor;
+    for tx in transactions {
+        let block = tx.location.block;
+        if tx.description.is_none() || failed.contains(&block) {
+            // The block is fetched again on the next run.
+            return block.saturating_sub(1).max(floor);
+        }
+        watermark = watermark.max(block);
+    }
+    watermark
+}
+
+/// Appearances found for an address, with the volumes they came from.
+type Found = (Vec<AAIAppearanceTx>, AppearanceSources);
+
+/// Receipts of the blocks that hold more than one transaction of a history,
+/// fetched with one eth_getBlockReceipts request per block.
+///
+/// After the node fails to provide them (e.g., the method is missing), the
+/// receipts are fetched one by one instead.
+struct BlockReceipts<'a> {
+    provider: &'a Provider,
+    /// Blocks with more than one transaction.
+    busy: BTreeSet<u32>,
+    /// Receipts by transaction hash, with the endpoint that provided them.
+    fetched: HashMap<u32, (HashMap<H256, TransactionReceipt>, String)>,
+    enabled: bool,
+}
+
+impl<'a> BlockReceipts<'a> {
+    fn new(provider: &'a Provider, transactions: &[TxInfo]) -> Self {
+        let mut counts: HashMap<u32, usize> = HashMap::new();
+        for tx in transactions.iter().filter(|tx| tx.description.is_some()) {
+            *counts.entry(tx.location.block).or_default() += 1;
+        }
+        BlockReceipts {
+            provider,
+            busy: counts
+                .into_iter()
+                .filter(|(_, n)| *n > 1)
+                .map(|(b, _)| b)
+                .collect(),
+            fetched: HashMap::new(),
+            enabled: provider.supports(Capability::BlockReceipts),
+        }
+    }
+    /// The receipt of a transaction, if its block is busy and the node
+    /// provided the receipts of the block.
+    async fn take(&mut self, block: u32, hash: H256) -> Option<(TransactionReceipt, String)> {
+        if !self.enabled || !self.busy.contains(&block) {
+            return None;
+        }
+        if let Entry::Vacant(entry) = self.fetched.entry(block) {
+            match self.provider.block_receipts(block.into()).await {
+                Ok((Some(receipts), provider)) => {
+                    let receipts = receipts.into_iter().map(|r| (r.transaction_hash, r));
+                    entry.insert((receipts.collect(), provider));
+                }
+                Ok((None, _)) => return None,
+                Err(e) => {
+                    warn!(
+                        "Fetching receipts one by one. eth_getBlockReceipts failed ({})",
+                        e
+                    );
+                    self.enabled = false;
+                    return None;
                 }
-                write!(f, "\n\n\t\t{}. Event {}/{}", e, i, event_count)?;
             }
         }
-        write!(f, "")
+        let (receipts, provider) = self.fetched.get_mut(&block)?;
+        Some((receipts.remove(&hash)?, provider.clone()))
+    }
+}
+
+/// Looks up the appearances of an address in the index.
+fn find_appearances(db: &Todd<AAISpec>, address: &str) -> Result<Found> {
+    let values = db.find(address).context(IndexMiss)?;
+    let volumes = values.into_iter().map(|v| v.value.to_vec()).collect();
+    Ok(join_volumes(&chapter_of(address), volumes))
+}
+
+/// Joins the appearances from each volume of a chapter in to one list,
+/// ordered by block and index. Appearances in more than one volume (e.g.,
+/// overlapping or published again) are kept once.
+fn join_volumes(chapter: &str, volumes: Vec<Vec<AAIAppearanceTx>>) -> Found {
+    let mut sources = AppearanceSources {
+        chapter: chapter.to_string(),
+        ..Default::default()
+    };
+    let mut seen = BTreeSet::new();
+    let mut joined = vec![];
+    for volume in volumes.into_iter().filter(|v| !v.is_empty()) {
+        sources.volumes += 1;
+        for appearance in volume {
+            match seen.insert((appearance.block, appearance.index)) {
+                true => joined.push(appearance),
+                false => sources.duplicates += 1,
+            }
+        }
     }
+    joined.sort_by_key(|a| (a.block, a.index));
+    sources.appearances = joined.len();
+    (joined, sources)
 }
 
-/// Makes an address option nice to read and detects if it is the owner.
-fn nice_address(address: Option<H160>, owner_address: &str) -> String {
-    let owner_address = owner_address.trim_start_matches("0x");
-    match address {
-        Some(a) => {
-            let a = hex::encode(a);
-            if a == owner_address {
-                String::from("Self")
-            } else {
-                format!("0x{}", a)
+/// Looks up the appearances of many addresses, in the order given.
+///
+/// The index is split into chapters by the first two hex characters of an
+/// address. Chapters are looked up in parallel, one thread per CPU, with the
+/// addresses of a chapter looked up one after another on the same thread.
+///
+/// If a thread panics, the addresses it was given fail with the panic message.
+pub fn find_many(db: &Todd<AAISpec>, addresses: &[String]) -> Vec<Result<Found>> {
+    let chapters = chapters(addresses);
+    let workers = default_workers().min(chapters.len()).max(1);
+    let mut found: Vec<Option<Result<Found>>> = addresses.iter().map(|_| None).collect();
+    thread::scope(|scope| {
+        let threads: Vec<_> = (0..workers)
+            .map(|worker| {
+                let positions: Vec<usize> = chapters
+                    .iter()
+                    .skip(worker)
+                    .step_by(workers)
+                    .flatten()
+                    .copied()
+                    .collect();
+                let thread = scope.spawn({
+                    let positions = positions.clone();
+                    move || {
+                        positions
+                            .into_iter()
+                            .map(|i| (i, find_appearances(db, &addresses[i])))
+                            .collect::<Vec<_>>()
+                    }
+                });
+                (positions, thread)
+            })
+            .collect();
+        for (positions, thread) in threads {
+            match thread.join() {
+                Ok(results) => {
+                    for (i, result) in results {
+                        found[i] = Some(result);
+                    }
+                }
+                Err(payload) => {
+                    let message = panic_message(payload);
+                    for i in positions {
+                        let error = anyhow!("Index lookup panicked ({})", message);
+                        found[i] = Some(Err(error).context(IndexMiss));
+                    }
+                }
             }
         }
-        None => String::from("None"),
+    });
+    found
+        .into_iter()
+        .map(|r| r.unwrap_or_else(|| Err(anyhow!("Index lookup stopped")).context(IndexMiss)))
+        .collect()
+}
+
+/// Positions of addresses grouped by index chapter.
+fn chapters(addresses: &[String]) -> Vec<Vec<usize>> {
+    let mut chapters: BTreeMap<String, Vec<usize>> = BTreeMap::new();
+    for (i, address) in addresses.iter().enumerate() {
+        chapters.entry(chapter_of(address)).or_default().push(i);
     }
+    chapters.into_values().collect()
+}
+
+/// Chapter of the index that holds an address (its first two hex characters).
+fn chapter_of(address: &str) -> String {
+    let hex = address.trim_start_matches("0x").to_ascii_lowercase();
+    hex.get(..2).unwrap_or_default().to_string()
+}
+
+/// Moves the warnings recorded by the cache to those of a history, and
+/// returns them to be kept on the item they are about.
+fn take_warnings(cache: &mut Cache, warnings: &mut Vec<Warning>) -> Vec<Warning> {
+    let taken = cache.take_warnings();
+    warnings.extend(taken.iter().cloned());
+    taken
 }
 
 /// Extracts the information about a given log.
+///
+/// Also returns the endpoint that provided the contract code, if it was
+/// fetched rather than read from the artifact store.
 async fn examine_log(
     log: &Log,
     mode: &Mode,
-    web3: &Web3<Http>,
     config: &Config,
     cache: &mut Cache,
-) -> Result<Option<LoggedEvent>> {
-    let topic_zero = match log.topics.get(0) {
+) -> Result<Option<(LoggedEvent, Option<String>)>> {
+    let topic_zero = match log.topics.first() {
         Some(t) => {
             let s = hex::encode(t);
             s[..8].to_owned()
         }
         None => return Ok(None),
     };
-    let raw = log.clone();
+    let raw = Arc::new(log.clone());
+    let block = log.block_number.map(|n| n.as_u64());
+    let (contract, provider) = examine_contract(&log.address, block, mode, config, cache).await?;
+    let (name, name_source) = match verified_event_name(&contract, log) {
+        Some(name) => (Some(name), Some(NameSource::VerifiedAbi)),
+        None => {
+            let name = cache.try_sig(&topic_zero, mode, config).await;
+            (name, cache.signature_source(&topic_zero))
+        }
+    };
+    let nametags = cache.try_nametags_or_onchain(&log.address, config).await;
+    let mut topic_nametags = BTreeMap::new();
+    for (position, topic) in log.topics.iter().enumerate().skip(1) {
+        let Some(address) = padded_address(topic.as_bytes()) else {
+            continue;
+        };
+        let tags = cache.try_nametags(&address, config).unwrap_or_default();
+        let tags: Vec<String> = tags.into_iter().filter(|t| !t.is_empty()).collect();
+        if !tags.is_empty() {
+            topic_nametags.insert(position, tags);
+        }
+    }
+
+    let event: LoggedEvent = LoggedEvent {
+        raw,
+        contract,
+        topic_zero: topic_zero.to_owned(),
+        name,
+        name_source,
+        nametags,
+        topic_nametags,
+        spam: false,
+        log_index: log.log_index.map(|i| i.as_u64()),
+        transaction_index: log.transaction_index.map(|i| i.as_u32()),
+        warnings: vec![],
+    };
+    Ok(Some((event, provider)))
+}
 
+/// Signature of an event in the verified ABI of the contract that emitted it.
+fn verified_event_name(contract: &Contract, log: &Log) -> Option<String> {
+    let verified = matches!(
+        contract.verification,
+        VerificationStatus::SourcifyFullMatch | VerificationStatus::SourcifyPartialMatch
+    );
+    let topic = log.topics.first()?;
+    let abi = contract.abi.as_deref().filter(|_| verified)?;
+    event_signatures(abi)?
+        .into_iter()
+        .find(|signature| topic_of(signature) == *topic)
+}
+
+/// Gathers the bytecode, metadata, ABI and interfaces of a contract.
+///
+/// The code at the block of an event (if given) is examined, so that
+/// contracts since destroyed or replaced are seen as they were. The latest
+/// code is used if the node does not have it.
+///
+/// A contract without code that emitted an event is marked as destructed.
+/// Its code at the block of the event is looked up even if only the latest
+/// code is examined otherwise. Whether a contract is destructed is always
+/// decided from the latest code. Code stored in an earlier run is only used
+/// for the code at the event, if the node no longer has it, or if the node
+/// cannot give code at all.
+///
+/// Also returns the endpoint that provided the latest code, if fetched.
+pub async fn examine_contract(
+    contract: &H160,
+    block: Option<u64>,
+    mode: &Mode,
+    config: &Config,
+    cache: &mut Cache,
+) -> Result<(Contract, Option<String>)> {
     // eth_getCode
-    let bytecode = web3
-        .eth()
-        .code(log.address, Some(BlockNumber::Latest))
-        .await?
-        .0;
-
-    let cid = match cid_from_runtime_bytecode(bytecode.as_ref()) {
-        Ok(c) => c,
-        Err(e) => {
-            log::error!(
-                "The metadata CID was not able to be extracted from bytecode
-for contract 0x{}. ({})",
-                hex::encode(log.address),
-                e
-            );
-            None
+    let supported = config.provider.supports(Capability::ContractCode);
+    let (current, provider) = match supported {
+        true => {
+            let (code, provider) = config.provider.code(*contract).await?;
+            (Some(code.0), Some(provider))
+        }
+        false => (None, None),
+    };
+    let stored = config.store.bytecode(contract);
+    if let Some(code) = current.as_ref().filter(|code| !code.is_empty()) {
+        if stored.as_ref() != Some(code) {
+            if let Err(e) = config.store.put_bytecode(contract, code) {
+                warn!("Could not store bytecode for {:?} ({})", contract, e);
+            }
+        }
+    }
+    let destructed = is_destructed(block, current.as_ref());
+    let at_emission = match block {
+        Some(block) if supported && (config.code_at == CodeBlock::Emission || destructed) => {
+            let fetched = cache.try_code_at(contract, block, &config.provider).await;
+            Some(emission_code(fetched, stored.clone(), destructed))
+        }
+        _ => None,
+    };
+    if destructed && !matches!(&at_emission, Some(Some(code)) if !code.is_empty()) {
+        debug!(
+            "0x{} is no longer deployed and its earlier code is unavailable",
+            hex::encode(contract)
+        );
+    }
+    let (code_at_emission, code_current) = code_statuses(at_emission.as_ref(), current.as_ref());
+    let bytecode = match at_emission {
+        Some(Some(code)) if !code.is_empty() => code,
+        _ => current.or(stored).unwrap_or_default(),
+    };
+
+    let (cid, compiler) = match read_trailer(&bytecode) {
+        Trailer::Metadata(metadata) => (metadata.source.clone(), metadata.compiler_string()),
+        Trailer::Absent => {
+            debug!("No metadata in bytecode of 0x{}", hex::encode(contract));
+            (None, None)
         }
     };
-    let address = h160_to_string(&log.address);
 
-    let abi = cache.try_abi(&log.address, mode, &bytecode).await;
-    let sig_text = cache.try_sig(&topic_zero, mode, config).await;
-    let nametags = cache.try_nametags(&log.address, config);
+    let (abi, verification) = match cache.try_abi(contract, mode, &bytecode, config).await {
+        Some((abi, status)) => (Some(abi), status.or_metadata(&cid)),
+        None => (None, VerificationStatus::Unknown.or_metadata(&cid)),
+    };
+    let interfaces = cache
+        .try_interfaces(
+            contract,
+            &bytecode,
+            abi.as_deref(),
+            &config.provider,
+            config.interface_lookups,
+        )
+        .await;
+    let deployer = cache.try_deployer(contract, config).await;
+    let decompile = cache.decompile_status(contract, &config.decompiler);
+    let functions = match decompile {
+        DecompileStatus::Decompiled => recover_functions(&bytecode, mode, config, cache).await,
+        _ => vec![],
+    };
 
     let contract = Contract {
-        address: address.to_owned(),
+        address: h160_to_string(contract),
         source_code_metadata_link: cid,
         bytecode,
         source_code: PathBuf::from("TODO: Path to source code."),
         abi,
-        decompiled: false,
+        decompiled: verification == VerificationStatus::DecompiledOnly,
+        decompile,
+        functions,
+        verification,
+        interfaces,
+        compiler,
+        code_at_emission,
+        code_current,
+        destructed,
+        deployer,
     };
+    Ok((contract, provider))
+}
 
-    let event: LoggedEvent = LoggedEvent {
-        raw,
-        contract,
-        topic_zero: topic_zero.to_owned(),
-        name: sig_text,
-        nametags,
+/// Whether a contract that emitted an event has no code at the latest block.
+fn is_destructed(block: Option<u64>, current: Option<&Vec<u8>>) -> bool {
+    block.is_some() && current.is_some_and(|code| code.is_empty())
+}
+
+/// Code at the block of an event, from the node, or for a destructed contract
+/// the code stored in an earlier run if the node no longer has it.
+fn emission_code(
+    fetched: Option<Vec<u8>>,
+    stored: Option<Vec<u8>>,
+    destructed: bool,
+) -> Option<Vec<u8>> {
+    match fetched {
+        Some(code) if !code.is_empty() => Some(code),
+        fetched if destructed => stored.filter(|code| !code.is_empty()).or(fetched),
+        fetched => fetched,
+    }
+}
+
+/// Statuses of the code at the block of an event (if fetched, None if the
+/// node could not give it) and the latest code (None if unavailable).
+fn code_statuses(
+    at_emission: Option<&Option<Vec<u8>>>,
+    current: Option<&Vec<u8>>,
+) -> (CodeStatus, CodeStatus) {
+    let status = |code: Option<&Vec<u8>>| match code {
+        None => CodeStatus::Unavailable,
+        Some(code) if code.is_empty() => CodeStatus::Empty,
+        Some(_) => CodeStatus::Deployed,
+    };
+    let current_status = match (at_emission, current) {
+        (Some(Some(old)), Some(code)) if !old.is_empty() && !code.is_empty() && old != code => {
+            CodeStatus::Changed
+        }
+        _ => status(current),
+    };
+    let emission_status = at_emission.map_or(CodeStatus::NotChecked, |code| status(code.as_ref()));
+    (emission_status, current_status)
+}
+
+/// Functions of a decompiled contract, named from the signature sources
+/// where possible.
+async fn recover_functions(
+    bytecode: &[u8],
+    mode: &Mode,
+    config: &Config,
+    cache: &mut Cache,
+) -> Vec<RecoveredFunction> {
+    let output = config.store.decompiled_dir(bytecode);
+    let mut functions = vec![];
+    for selector in decompiled_selectors(&output).unwrap_or_default() {
+        let selector = hex::encode(selector);
+        let signature = cache.try_sig(&selector, mode, config).await;
+        functions.push(RecoveredFunction {
+            selector,
+            signature,
+        });
+    }
+    functions
+}
+
+/// Decodes the constructor arguments in the input of a contract creation.
+///
+/// With APIs, the ABI of the new contract is looked up if not already known.
+async fn decode_constructor_args(
+    input: &[u8],
+    contract: Option<H160>,
+    mode: &Mode,
+    config: &Config,
+    cache: &mut Cache,
+) -> Option<DecodedCall> {
+    let args = constructor_args(input)?;
+    if let (Some(address), Mode::UseApis) = (contract, mode) {
+        if !cache.is_verified(&address) {
+            let code = match config.store.bytecode(&address) {
+                Some(code) => code,
+                None => {
+                    let (code, _) = config.provider.code(address).await.ok()?;
+                    if let Err(e) = config.store.put_bytecode(&address, &code.0) {
+                        warn!("Could not store bytecode for {:?} ({})", address, e);
+                    }
+                    code.0
+                }
+            };
+            cache.try_abi(&address, mode, &code, config).await;
+        }
+    }
+    let abi = contract.and_then(|address| cache.verified_abi(&address));
+    Some(decode_constructor(args, abi))
+}
+
+/// Whether an address has no code (an account rather than a contract),
+/// from the stored bytecode or the node. False if this cannot be told.
+async fn is_account(address: &H160, config: &Config) -> bool {
+    let code = match config.store.bytecode(address) {
+        Some(code) => code,
+        None if config.provider.supports(Capability::ContractCode) => {
+            let Ok((code, _)) = config.provider.code(*address).await else {
+                return false;
+            };
+            if let Err(e) = config.store.put_bytecode(address, &code.0) {
+                warn!("Could not store bytecode for {:?} ({})", address, e);
+            }
+            code.0
+        }
+        None => return false,
     };
-    Ok(Some(event))
+    code.is_empty()
 }
 
 /// Uses TODD Signatures database to convert hex string to text string.
@@ -370,3 +1885,319 @@ pub fn address_nametags(address: &str, config: &Config) -> Result<Vec<String>> {
     }
     Ok(s)
 }
+
+#[test]
+fn addresses_grouped_by_chapter() {
+    let addresses: Vec<String> = ["0xab01", "0x12ff", "0xAB99", "0x1200", "0xcd"]
+        .iter()
+        .map(|a| a.to_string())
+        .collect();
+    assert_eq!(chapters(&addresses), vec![vec![1, 3], vec![0, 2], vec![4]]);
+}
+
+#[test]
+fn bytecode_dropped_and_logs_shared() {
+    use crate::semantics::test_event;
+
+    let mut event = test_event(&"11".repeat(20), "Ping()", &[], vec![]);
+    event.contract.bytecode = vec![0x60, 0x80];
+    let mut transactions = vec![TxInfo {
+        events: Some(vec![event]),
+        ..Default::default()
+    }];
+    let copy = transactions.clone();
+    drop_bytecode(&mut transactions);
+    let [before, after] = [&copy, &transactions].map(|txs| &txs[0].events.as_ref().unwrap()[0]);
+    assert_eq!(before.contract.bytecode.len(), 2);
+    assert!(after.contract.bytecode.is_empty());
+    assert!(Arc::ptr_eq(&before.raw, &after.raw));
+}
+
+#[test]
+fn decoded_logs_pruned() {
+    use crate::semantics::test_event;
+
+    let mut event = test_event(&"11".repeat(20), "Ping()", &[], vec![]);
+    Arc::make_mut(&mut event.raw).log_index = Some(1.into());
+    let anonymous = Log {
+        log_index: Some(0.into()),
+        topics: vec![],
+        ..(*event.raw).clone()
+    };
+    let mut transactions = vec![TxInfo {
+        receipt: Some(TransactionReceipt {
+            logs: vec![anonymous.clone(), (*event.raw).clone()],
+            logs_bloom: [0xff; 256].into(),
+            ..Default::default()
+        }),
+        events: Some(vec![event.clone()]),
+        ..Default::default()
+    }];
+    let logs: Vec<Log> = transactions[0].logs().into_iter().cloned().collect();
+    prune_receipts(&mut transactions);
+    let receipt = transactions[0].receipt.as_ref().unwrap();
+    // The log without an event stays in the receipt.
+    assert_eq!(receipt.logs, vec![anonymous]);
+    assert!(receipt.logs_bloom.is_zero());
+    assert!(transactions[0].pruned);
+    let kept: Vec<Log> = transactions[0].logs().into_iter().cloned().collect();
+    assert_eq!(kept, logs);
+}
+
+#[test]
+fn duplicate_appearances_dropped() {
+    let tx = |block, index| AAIAppearanceTx { block, index };
+    let volumes = vec![
+        vec![tx(10, 1), tx(12, 0)],
+        vec![],
+        // Published again with a later appearance.
+        vec![tx(12, 0), tx(15, 3)],
+    ];
+    let (joined, sources) = join_volumes("84", volumes);
+    assert_eq!(joined, vec![tx(10, 1), tx(12, 0), tx(15, 3)]);
+    assert_eq!(sources.volumes, 2);
+    assert_eq!(sources.duplicates, 1);
+    assert_eq!(
+        sources.to_string(),
+        "3 appearances from 2 volumes of chapter 0x84 (1 duplicate dropped)"
+    );
+    assert_eq!(chapter_of("0x84Be97"), "84");
+}
+
+#[test]
+fn watermark_below_incomplete_block() {
+    let tx = |block: u32, index: u32, fetched: bool| TxInfo {
+        location: AAIAppearanceTx { block, index },
+        description: fetched.then(Default::default),
+        ..Default::default()
+    };
+    let complete = [tx(10, 0, true), tx(12, 0, true), tx(12, 1, true)];
+    assert_eq!(watermark(&complete, &[], 5), 12);
+    // Block 12 is only partly fetched.
+    let partial = [tx(10, 0, true), tx(12, 0, true), tx(12, 1, false)];
+    assert_eq!(watermark(&partial, &[], 5), 11);
+    let failures = [StageFailure {
+        stage: Stage::Receipt,
+        location: AAIAppearanceTx {
+            block: 10,
+            index: 0,
+        },
+        error: String::from("No receipt"),
+    }];
+    assert_eq!(watermark(&complete, &failures, 5), 9);
+    // Never below the earlier watermark.
+    assert_eq!(watermark(&complete, &failures, 9), 9);
+    assert_eq!(watermark(&[], &[], 9), 9);
+}
+
+#[test]
+fn saved_history_round_trip() {
+    use crate::semantics::{Action, Amount};
+
+    let tx = TxInfo {
+        location: AAIAppearanceTx {
+            block: 15_000_000,
+            index: 3,
+        },
+        chain: String::from(MAINNET),
+        timestamp: Some(1_655_000_000),
+        action: Some(Action::Wrap {
+            amount: Amount {
+                value: U256::exp10(18),
+                decimals: 18,
+                symbol: String::from("ETH"),
+            },
+        }),
+        ..Default::default()
+    };
+    let mut cache = Cache::default();
+    cache.signatures.insert(
+        String::from("a9059cbb"),
+        (
+            VisitNote::PriorSuccess,
+            String::from("transfer(address,uint256)"),
+        ),
+    );
+    let saved = SavedHistory {
+        version: env!("CARGO_PKG_VERSION").to_string(),
+        address: format!("0x{}", "aa".repeat(20)),
+        chain: String::from(MAINNET),
+        failures: vec![StageFailure {
+            stage: Stage::Receipt,
+            location: tx.location.clone(),
+            error: String::from("No receipt"),
+        }],
+        transactions: vec![tx],
+        cache,
+        warnings: vec![Warning::new(
+            crate::data::WarningKind::Signature,
+            "12345678",
+            "not found",
+        )],
+    };
+    let text = serde_json::to_string(&saved).unwrap();
+    assert_eq!(serde_json::from_str::<SavedHistory>(&text).unwrap(), saved);
+}
+
+#[test]
+fn code_status_at_emission_and_now() {
+    let code = vec![0x60, 0x80];
+    let other = vec![0x60, 0x40];
+    let statuses = |at: Option<Option<Vec<u8>>>, current: Option<Vec<u8>>| {
+        code_statuses(at.as_ref(), current.as_ref())
+    };
+    assert_eq!(
+        statuses(None, Some(code.clone())),
+        (CodeStatus::NotChecked, CodeStatus::Deployed)
+    );
+    // Destroyed since the event.
+    assert_eq!(
+        statuses(Some(Some(code.clone())), Some(vec![])),
+        (CodeStatus::Deployed, CodeStatus::Empty)
+    );
+    // Created again with other code.
+    assert_eq!(
+        statuses(Some(Some(code.clone())), Some(other)),
+        (CodeStatus::Deployed, CodeStatus::Changed)
+    );
+    // A node without the state of old blocks.
+    assert_eq!(
+        statuses(Some(None), Some(code)),
+        (CodeStatus::Unavailable, CodeStatus::Deployed)
+    );
+    assert_eq!(statuses(None, None).1, CodeStatus::Unavailable);
+
+    let mut event = crate::semantics::test_event(
+        "0x1111111111111111111111111111111111111111",
+        "Transfer(address,address,uint256)",
+        &[],
+        vec![],
+    );
+    event.contract.destructed = true;
+    assert!(event.to_string().contains(", contract no longer deployed)"));
+}
+
+#[test]
+fn destruction_from_latest_code() {
+    let code = vec![0x60, 0x80];
+    // Code stored while deployed does not hide that the latest code is empty.
+    assert!(is_destructed(Some(15_000_000), Some(&vec![])));
+    assert!(!is_destructed(Some(15_000_000), Some(&code)));
+    assert!(!is_destructed(None, Some(&vec![])));
+    assert!(!is_destructed(Some(15_000_000), None));
+
+    let stored = Some(code.clone());
+    // The node has the old state.
+    assert_eq!(
+        emission_code(Some(vec![0x60, 0x40]), stored.clone(), true),
+        Some(vec![0x60, 0x40])
+    );
+    // It does not, so the code stored before the contract was destroyed is used.
+    assert_eq!(emission_code(None, stored.clone(), true), stored);
+    assert_eq!(emission_code(Some(vec![]), stored.clone(), true), stored);
+    assert_eq!(emission_code(None, None, true), None);
+    // Stored code may be newer than the event if the contract still exists.
+    assert_eq!(emission_code(None, stored, false), None);
+}
+
+#[test]
+fn database_directories() {
+    let builder = Config::builder()
+        .directories(DirNature::Default)
+        .appearances_dir("/mnt/external/appearances");
+    assert_eq!(
+        builder.directory(Database::Appearances),
+        DirNature::Custom(PathBuf::from("/mnt/external/appearances"))
+    );
+    assert_eq!(builder.directory(Database::Signatures), DirNature::Default);
+    let builder = Config::builder().nametags_dir("names");
+    assert_eq!(builder.directory(Database::Signatures), DirNature::Sample);
+    assert_eq!(
+        builder.directory(Database::Nametags),
+        DirNature::Custom(PathBuf::from("names"))
+    );
+}
+
+#[tokio::test]
+async fn config_problems_reported_together() {
+    assert_eq!(check_url("http://localhost:8545"), None);
+    assert!(matches!(
+        check_url("ws://localhost:8546"),
+        Some(ConfigProblem::InvalidUrl(_, _))
+    ));
+    let missing = std::env::temp_dir().join("psr-no-such-index");
+    let error = Config::builder()
+        .rpc_urls(vec![String::from("localhost:8545")])
+        .appearances_dir(&missing)
+        .offline()
+        .validate()
+        .await
+        .unwrap_err();
+    assert!(error.downcast_ref::<IndexMiss>().is_some());
+    let problems = error.downcast_ref::<ConfigProblems>().unwrap();
+    assert_eq!(problems.0.len(), 2);
+    assert!(matches!(problems.0[0], ConfigProblem::InvalidUrl(_, _)));
+    assert_eq!(
+        problems.0[1],
+        ConfigProblem::Database(
+            Database::Appearances,
+            format!("{} is not a directory", missing.display())
+        )
+    );
+}
+
+#[tokio::test]
+async fn busy_blocks_fetch_receipts_once() {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    use async_trait::async_trait;
+    use serde_json::Value;
+
+    use crate::provider::{ChainDataProvider, Sourced};
+
+    /// Serves the receipts of any block, as two transactions.
+    struct Node(Arc<AtomicUsize>);
+
+    #[async_trait]
+    impl ChainDataProvider for Node {
+        fn name(&self) -> &str {
+            "test node"
+        }
+        fn supports(&self, _capability: Capability) -> bool {
+            true
+        }
+        async fn request(&self, _method: &str, _params: Vec<Value>) -> Result<Sourced<Value>> {
+            bail!("no requests")
+        }
+        async fn block_receipts(
+            &self,
+            _number: u64,
+        ) -> Result<Sourced<Option<Vec<TransactionReceipt>>>> {
+            self.0.fetch_add(1, Ordering::SeqCst);
+            let receipt = |n: u64| TransactionReceipt {
+                transaction_hash: H256::from_low_u64_be(n),
+                ..Default::default()
+            };
+            Ok((Some(vec![receipt(1), receipt(2)]), String::from("node")))
+        }
+    }
+
+    let requests = Arc::new(AtomicUsize::new(0));
+    let provider = Provider::new(Node(requests.clone()));
+    let tx = |block, hash| TxInfo {
+        location: AAIAppearanceTx { block, index: 0 },
+        description: Some(web3::types::Transaction {
+            hash: H256::from_low_u64_be(hash),
+            ..Default::default()
+        }),
+        ..Default::default()
+    };
+    let transactions = [tx(10, 1), tx(10, 2), tx(11, 3)];
+    let mut receipts = BlockReceipts::new(&provider, &transactions);
+    let first = receipts.take(10, H256::from_low_u64_be(1)).await.unwrap();
+    assert_eq!(first.0.transaction_hash, H256::from_low_u64_be(1));
+    assert!(receipts.take(10, H256::from_low_u64_be(2)).await.is_some());
+    // A block with one transaction of the history uses eth_getTransactionReceipt.
+    assert!(receipts.take(11, H256::from_low_u64_be(3)).await.is_none());
+    assert_eq!(requests.load(Ordering::SeqCst), 1);
+}
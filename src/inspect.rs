@@ -0,0 +1,309 @@
+//! Raw data of a single transaction, to see why part of it did not decode.
+//!
+//! The transaction and receipt are printed as the node returned them. The
+//! calldata and the topics and data of each log are then split into 32 byte
+//! words, with what was decoded from each word beside it (e.g., the argument
+//! read from it, or the event name found for topic zero). Indexed values of
+//! a dynamic type are hashes in the topics, and are shown as such.
+//!
+//! Log data that could not be decoded is also shown in event summaries, as a
+//! hexdump (`hexdump`) and any text it holds (`text_preview`).
+use std::fmt::Display;
+
+use serde_json::Value;
+use web3::types::H256;
+
+use crate::{
+    abi::dynamic_bytes,
+    data::{LoggedEvent, TxInfo},
+    render::{FormatOptions, Role},
+};
+
+/// Bytes on each line of a hexdump.
+const HEXDUMP_WIDTH: usize = 16;
+/// Shortest run of printable characters taken for text.
+const MIN_TEXT_CHARS: usize = 4;
+
+/// Displays the raw data of a transaction beside what was decoded from it.
+pub struct RawView<'a> {
+    pub tx: &'a TxInfo,
+    pub options: &'a FormatOptions,
+}
+
+impl Display for RawView<'_> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let (tx, options) = (self.tx, self.options);
+        let heading = |text: &str| options.paint(Role::Heading, text);
+        if let Some(desc) = &tx.description {
+            write!(f, "{}\n{}", heading("Transaction"), pretty(desc))?;
+            let calldata = &desc.input.0;
+            let title = format!("Calldata ({} bytes)", calldata.len());
+            write!(f, "\n\n{}", heading(&title))?;
+            for line in calldata_lines(calldata, tx) {
+                write!(f, "\n{}", line)?;
+            }
+        }
+        match &tx.receipt {
+            Some(receipt) => {
+                // Logs are shown one by one below.
+                let count = tx.logs().len();
+                let mut receipt = serde_json::to_value(receipt).unwrap_or_default();
+                if let Some(logs) = receipt.get_mut("logs") {
+                    *logs = Value::from(format!("{} logs, see below", count));
+                }
+                write!(f, "\n\n{}\n{}", heading("Receipt"), pretty(&receipt))?;
+            }
+            None => write!(f, "\n\n{}\nNone", heading("Receipt"))?,
+        }
+        let events = tx.events.as_deref().unwrap_or_default();
+        for log in tx.logs() {
+            let event = events.iter().find(|e| *e.raw == *log);
+            let title = format!(
+                "Log {} from 0x{}",
+                log.log_index.unwrap_or_default(),
+                hex::encode(log.address)
+            );
+            write!(f, "\n\n{}", heading(&title))?;
+            if let Some(event) = event {
+                write!(f, " ({} contract)", event.contract.verification)?;
+            }
+            for line in log_lines(&log.topics, &log.data.0, event) {
+                write!(f, "\n{}", line)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+fn pretty(value: &impl serde::Serialize) -> String {
+    serde_json::to_string_pretty(value).unwrap_or_default()
+}
+
+/// The selector then each 32 byte word of calldata, beside the decoded
+/// function and arguments.
+fn calldata_lines(calldata: &[u8], tx: &TxInfo) -> Vec<String> {
+    let Some(selector) = calldata.get(..4) else {
+        return words(calldata, 0, &[]);
+    };
+    let call = tx.call.as_ref();
+    let signature = match call.and_then(|c| c.signature.as_deref()) {
+        Some(signature) => signature.to_string(),
+        None if call.is_some() => String::from("unknown selector"),
+        None => String::from("not decoded"),
+    };
+    let mut lines = vec![row("0000", &hex::encode(selector), &signature)];
+    let params: Vec<String> = call
+        .map(|c| {
+            c.params
+                .iter()
+                .enumerate()
+                .map(|(i, p)| format!("{}. {}: {}", i, p.kind, p.value))
+                .collect()
+        })
+        .unwrap_or_default();
+    lines.extend(words(&calldata[4..], 4, &params));
+    lines
+}
+
+/// Each topic and 32 byte word of log data, with the event name beside
+/// topic zero and the arguments beside the rest, if the ABI has the event.
+fn log_lines(topics: &[H256], data: &[u8], event: Option<&LoggedEvent>) -> Vec<String> {
+    let name = match event.map(|e| &e.name) {
+        Some(Some(name)) => name.to_string(),
+        Some(None) => String::from("no signature found"),
+        None => String::from("not decoded"),
+    };
+    let (mut indexed, mut unindexed) = (vec![name], vec![]);
+    if let Some(decoded) = event.and_then(|e| e.decoded()) {
+        for (i, p) in decoded.params.iter().enumerate() {
+            let text = format!("{}. {} {}: {}", i, p.kind, p.name, p.value);
+            match p.indexed {
+                true => indexed.push(text),
+                false => unindexed.push(text),
+            }
+        }
+    }
+    let mut lines: Vec<String> = topics
+        .iter()
+        .enumerate()
+        .map(|(i, topic)| {
+            let decoded = indexed.get(i).map_or("", |d| d.as_str());
+            let tags = event.and_then(|e| e.topic_tags(i));
+            let decoded = match tags {
+                Some(tags) => format!("{} {}", decoded, tags).trim_start().to_string(),
+                None => decoded.to_string(),
+            };
+            row(&format!("t{}", i), &hex::encode(topic), &decoded)
+        })
+        .collect();
+    lines.extend(words(data, 0, &unindexed));
+    lines
+}
+
+/// Splits bytes into 32 byte words, each with its offset (from `start`) and
+/// the decoded value at the same position, if any.
+fn words(data: &[u8], start: usize, decoded: &[String]) -> Vec<String> {
+    data.chunks(32)
+        .enumerate()
+        .map(|(n, word)| {
+            let offset = format!("{:04x}", start + n * 32);
+            let decoded = decoded.get(n).map_or("", |d| d.as_str());
+            row(&offset, &hex::encode(word), decoded)
+        })
+        .collect()
+}
+
+/// Lines of 16 bytes, each with its offset and the printable ASCII beside
+/// it, for up to `max` bytes. A last line counts the bytes left out.
+pub fn hexdump(data: &[u8], max: usize) -> Vec<String> {
+    let shown = &data[..data.len().min(max)];
+    let mut lines: Vec<String> = shown
+        .chunks(HEXDUMP_WIDTH)
+        .enumerate()
+        .map(|(n, chunk)| {
+            let hex: Vec<String> = chunk.iter().map(|b| format!("{:02x}", b)).collect();
+            let ascii: String = chunk
+                .iter()
+                .map(|b| match b {
+                    0x20..=0x7e => *b as char,
+                    _ => '.',
+                })
+                .collect();
+            format!(
+                "{:04x}  {:<width$}  |{}|",
+                n * HEXDUMP_WIDTH,
+                hex.join(" "),
+                ascii,
+                width = HEXDUMP_WIDTH * 3 - 1
+            )
+        })
+        .collect();
+    if data.len() > shown.len() {
+        lines.push(format!("... {} more bytes", data.len() - shown.len()));
+    }
+    lines
+}
+
+/// Text in log data: an ABI encoded string, otherwise the longest run of
+/// printable UTF-8 (at least 4 characters).
+pub fn text_preview(data: &[u8]) -> Option<String> {
+    let printable = |text: &str| !text.chars().any(|c| c.is_control());
+    if let Some(text) = dynamic_bytes(data, 0).and_then(|b| std::str::from_utf8(b).ok()) {
+        if !text.is_empty() && printable(text) {
+            return Some(text.to_string());
+        }
+    }
+    String::from_utf8_lossy(data)
+        .split(|c: char| c.is_control() || c == char::REPLACEMENT_CHARACTER)
+        .filter(|run| run.trim().chars().count() >= MIN_TEXT_CHARS)
+        .max_by_key(|run| run.chars().count())
+        .map(|run| run.trim().to_string())
+}
+
+/// Calldata that reads as a message: valid UTF-8 with no control characters
+/// other than line breaks and tabs (trailing zero bytes are ignored).
+pub fn message_text(input: &[u8]) -> Option<String> {
+    let text = std::str::from_utf8(input)
+        .ok()?
+        .trim_end_matches('\0')
+        .trim();
+    let plain = !text
+        .chars()
+        .any(|c| c.is_control() && !matches!(c, '\n' | '\r' | '\t'));
+    (plain && !text.is_empty()).then(|| text.to_string())
+}
+
+/// A line with the raw hex in a fixed width column, then the decoded value.
+fn row(position: &str, raw: &str, decoded: &str) -> String {
+    match decoded.is_empty() {
+        true => format!("  {:<4}  {}", position, raw),
+        false => format!("  {:<4}  {:<64}  | {}", position, raw, decoded),
+    }
+}
+
+#[test]
+fn calldata_words_beside_arguments() {
+    use crate::decode::CallShape;
+
+    let recipient = "11".repeat(20);
+    let calldata = hex::decode(format!("a9059cbb{:0>64}{:0>64}ff", recipient, "2a")).unwrap();
+    let signature = String::from("transfer(address,uint256)");
+    let tx = TxInfo {
+        call: CallShape::new(Some(signature), &calldata).decode(&calldata),
+        ..Default::default()
+    };
+    let lines = calldata_lines(&calldata, &tx);
+    assert_eq!(lines.len(), 4);
+    assert!(lines[0].starts_with("  0000  a9059cbb "));
+    assert!(lines[0].ends_with("| transfer(address,uint256)"));
+    assert!(lines[1].starts_with(&format!("  0004  {:0>64}  | 0. address: 0x", recipient)));
+    assert!(lines[2].starts_with("  0024  "));
+    assert!(lines[2].ends_with("| 1. uint: 42"));
+    // A partial last word has nothing decoded beside it.
+    assert_eq!(lines[3], "  0044  ff");
+
+    let lines = calldata_lines(&calldata, &TxInfo::default());
+    assert!(lines[0].ends_with("| not decoded"));
+    assert_eq!(lines[1], format!("  0004  {:0>64}", recipient));
+}
+
+#[test]
+fn topic_addresses_named() {
+    use web3::types::H160;
+
+    use crate::{abi::padded_address, semantics::test_event};
+
+    let exchange = H160::repeat_byte(0x71);
+    let topics = [H256::from(exchange), H256::from_low_u64_be(5)];
+    let mut event = test_event(
+        "0x00000000000000000000000000000000000000aa",
+        "Deposit(address,uint256)",
+        &topics,
+        vec![],
+    );
+    assert_eq!(padded_address(topics[0].as_bytes()), Some(exchange));
+    // A small number is not taken for an address.
+    assert_eq!(padded_address(topics[1].as_bytes()), None);
+    event
+        .topic_nametags
+        .insert(1, vec![String::from("Coinbase"), String::from("Exchange")]);
+    let lines = log_lines(&event.raw.topics, &[], Some(&event));
+    assert!(lines[1].ends_with("| |Coinbase|Exchange|"));
+    assert!(!lines[2].contains('|'));
+    assert!(event.to_string().contains(&format!(
+        "topic 2 {} |Coinbase|Exchange|",
+        H256::from(exchange)
+    )));
+}
+
+#[test]
+fn undecoded_data_dumped() {
+    let data = b"\x00\x01Hello, world!\xff\x00\x00gm".to_vec();
+    let lines = hexdump(&data, 128);
+    assert_eq!(lines.len(), 2);
+    assert_eq!(
+        lines[0],
+        "0000  00 01 48 65 6c 6c 6f 2c 20 77 6f 72 6c 64 21 ff  |..Hello, world!.|"
+    );
+    assert!(lines[1].starts_with("0010  00 00 67 6d  "));
+    assert!(lines[1].ends_with("  |..gm|"));
+    assert_eq!(hexdump(&[0; 40], 16).last().unwrap(), "... 24 more bytes");
+    assert_eq!(text_preview(&data).unwrap(), "Hello, world!");
+
+    // An ABI encoded string is read by its length.
+    let text = "ünïcode";
+    let encoded = format!("{:064x}{:064x}{:0<64}", 32, text.len(), hex::encode(text));
+    assert_eq!(text_preview(&hex::decode(encoded).unwrap()).unwrap(), text);
+    assert_eq!(text_preview(&[0, 1, 2, 0x41, 0x42]), None);
+}
+
+#[test]
+fn transfer_messages_read() {
+    let note = "gm, thanks for the coffee\n".as_bytes();
+    assert_eq!(message_text(note).unwrap(), "gm, thanks for the coffee");
+    assert_eq!(message_text(b"refund\0\0").unwrap(), "refund");
+    // Calldata of a function call, or invalid UTF-8, is not a message.
+    assert_eq!(message_text(&hex::decode("a9059cbb0000").unwrap()), None);
+    assert_eq!(message_text(&[0x68, 0x69, 0xff]), None);
+    assert_eq!(message_text(b" \n"), None);
+}
@@ -0,0 +1,454 @@
+//! Detects which token and vault standards (ERCs) a contract implements.
+//!
+//! Three sources are combined:
+//! - Function selectors in the ABI, if one is available.
+//! - Function selectors pushed in the bytecode dispatcher (PUSH4).
+//! - ERC-165 `supportsInterface` calls, which also work for proxies whose
+//!   own bytecode does not contain the selectors.
+//!
+//! With lookups enabled, ERC-165 is also queried for a curated list of
+//! extensions (e.g., NFT metadata and royalties), and the EIP-1820 registry
+//! for the interfaces a contract registered itself for (e.g., ERC-777).
+//! These only come from calls, so help most with unverified contracts.
+//!
+//! The name and symbol a contract gives itself (e.g., an ERC-20 token) can
+//! also be read with calls, to label contracts without a nametag, as can the
+//! decimals of a token.
+use std::{collections::HashSet, fmt::Display, time::Duration};
+
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use web3::{
+    signing::keccak256,
+    types::{H160, U256},
+};
+
+use crate::{
+    abi::dynamic_bytes,
+    provider::{Capability, Provider},
+};
+
+/// A standard contract interface.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Deserialize, Serialize)]
+pub enum Erc {
+    /// Fungible token.
+    Erc20,
+    /// Non-fungible token.
+    Erc721,
+    /// Multi token.
+    Erc1155,
+    /// Tokenized vault.
+    Erc4626,
+    /// Fungible token with operators and send hooks.
+    Erc777,
+    /// Standard interface detection.
+    Erc165,
+    /// Name, symbol and token URIs of an ERC-721 token.
+    Erc721Metadata,
+    /// Enumeration of ERC-721 tokens.
+    Erc721Enumerable,
+    /// Token URIs of an ERC-1155 token.
+    Erc1155MetadataUri,
+    /// NFT royalties.
+    Erc2981,
+    /// NFT metadata update events.
+    Erc4906,
+    /// Non-transferable (soulbound) NFT.
+    Erc5192,
+}
+
+/// PUSH4 opcode, used by the Solidity and Vyper dispatchers to compare selectors.
+const PUSH4: u8 = 0x63;
+
+/// Selector of supportsInterface(bytes4), which is also the ERC-165 interface id.
+const SUPPORTS_INTERFACE: [u8; 4] = [0x01, 0xff, 0xc9, 0xa7];
+
+/// Selectors of name() and symbol().
+const NAME: [u8; 4] = [0x06, 0xfd, 0xde, 0x03];
+const SYMBOL: [u8; 4] = [0x95, 0xd8, 0x9b, 0x41];
+
+/// Selector of decimals().
+const DECIMALS: [u8; 4] = [0x31, 0x3c, 0xe5, 0x67];
+
+/// Most decimals an amount can have (10^77 is the largest power of ten in
+/// a uint256).
+const MAX_DECIMALS: u32 = 77;
+
+/// Time allowed for each name(), symbol() or decimals() call.
+const NAME_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Longest name or symbol kept, so that long (e.g., spam) names do not fill
+/// the output.
+const MAX_NAME_CHARS: usize = 64;
+
+/// Interface id that an ERC-165 contract must not support.
+const INVALID_ID: [u8; 4] = [0xff; 4];
+
+/// EIP-1820 registry, deployed at the same address on each chain that has it.
+const REGISTRY: H160 = H160([
+    0x18, 0x20, 0xa4, 0xb7, 0x61, 0x8b, 0xde, 0x71, 0xdc, 0xe8, 0xcd, 0xc7, 0x3a, 0xab, 0x6c, 0x95,
+    0x90, 0x5f, 0xad, 0x24,
+]);
+
+/// Selector of getInterfaceImplementer(address,bytes32) on the EIP-1820 registry.
+const GET_INTERFACE_IMPLEMENTER: [u8; 4] = [0xaa, 0xbb, 0xb8, 0xca];
+
+/// Interface names that contracts register for themselves in the EIP-1820
+/// registry.
+const REGISTERED: [(&str, Erc); 2] = [("ERC777Token", Erc::Erc777), ("ERC20Token", Erc::Erc20)];
+
+impl Erc {
+    /// Standards detected from function selectors.
+    const STANDARDS: [Erc; 4] = [Erc::Erc20, Erc::Erc721, Erc::Erc1155, Erc::Erc4626];
+    /// Interfaces only detected with lookups, ERC-165 aside.
+    const CURATED: [Erc; 6] = [
+        Erc::Erc721Metadata,
+        Erc::Erc721Enumerable,
+        Erc::Erc1155MetadataUri,
+        Erc::Erc2981,
+        Erc::Erc4906,
+        Erc::Erc5192,
+    ];
+
+    /// Selectors of functions that must all be present.
+    fn required_selectors(&self) -> &'static [[u8; 4]] {
+        match self {
+            // totalSupply, balanceOf, transfer, transferFrom, approve, allowance
+            Erc::Erc20 => &[
+                [0x18, 0x16, 0x0d, 0xdd],
+                [0x70, 0xa0, 0x82, 0x31],
+                [0xa9, 0x05, 0x9c, 0xbb],
+                [0x23, 0xb8, 0x72, 0xdd],
+                [0x09, 0x5e, 0xa7, 0xb3],
+                [0xdd, 0x62, 0xed, 0x3e],
+            ],
+            // ownerOf, safeTransferFrom(address,address,uint256), setApprovalForAll, getApproved
+            Erc::Erc721 => &[
+                [0x63, 0x52, 0x21, 0x1e],
+                [0x42, 0x84, 0x2e, 0x0e],
+                [0xa2, 0x2c, 0xb4, 0x65],
+                [0x08, 0x18, 0x12, 0xfc],
+            ],
+            // safeTransferFrom(address,address,uint256,uint256,bytes), safeBatchTransferFrom,
+            // balanceOfBatch
+            Erc::Erc1155 => &[
+                [0xf2, 0x42, 0x43, 0x2a],
+                [0x2e, 0xb2, 0xc2, 0xd6],
+                [0x4e, 0x12, 0x73, 0xf4],
+            ],
+            // asset, totalAssets, convertToShares, convertToAssets, deposit, redeem
+            Erc::Erc4626 => &[
+                [0x38, 0xd5, 0x2e, 0x0f],
+                [0x01, 0xe1, 0xd1, 0x14],
+                [0xc6, 0xe6, 0xf5, 0x92],
+                [0x07, 0xa2, 0xd1, 0x3a],
+                [0x6e, 0x55, 0x3f, 0x65],
+                [0xba, 0x08, 0x76, 0x52],
+            ],
+            // Only detected with lookups.
+            _ => &[],
+        }
+    }
+    /// ERC-165 interface id, for standards that require ERC-165.
+    fn interface_id(&self) -> Option<[u8; 4]> {
+        match self {
+            Erc::Erc721 => Some([0x80, 0xac, 0x58, 0xcd]),
+            Erc::Erc1155 => Some([0xd9, 0xb6, 0x7a, 0x26]),
+            Erc::Erc165 => Some(SUPPORTS_INTERFACE),
+            Erc::Erc721Metadata => Some([0x5b, 0x5e, 0x13, 0x9f]),
+            Erc::Erc721Enumerable => Some([0x78, 0x0e, 0x9d, 0x63]),
+            Erc::Erc1155MetadataUri => Some([0x0e, 0x89, 0x34, 0x1c]),
+            Erc::Erc2981 => Some([0x2a, 0x55, 0x20, 0x5a]),
+            Erc::Erc4906 => Some([0x49, 0x06, 0x49, 0x06]),
+            Erc::Erc5192 => Some([0xb4, 0x5a, 0x3c, 0x0e]),
+            Erc::Erc20 | Erc::Erc4626 | Erc::Erc777 => None,
+        }
+    }
+}
+
+/// Determines the interfaces a contract implements, with the curated
+/// ERC-165 and EIP-1820 lookups if `lookups` is set.
+///
+/// ERC-165 and registry calls are only made if the provider supports eth_call.
+pub async fn detect(
+    address: &H160,
+    bytecode: &[u8],
+    abi: Option<&str>,
+    provider: &Provider,
+    lookups: bool,
+) -> Vec<Erc> {
+    let mut selectors = bytecode_selectors(bytecode);
+    if let Some(abi) = abi {
+        selectors.extend(abi_selectors(abi));
+    }
+    let mut found: Vec<Erc> = Erc::STANDARDS
+        .into_iter()
+        .filter(|erc| {
+            erc.required_selectors()
+                .iter()
+                .all(|s| selectors.contains(s))
+        })
+        .collect();
+
+    if !provider.supports(Capability::Calls) {
+        return found;
+    }
+    if implements_erc165(address, provider).await {
+        let mut candidates = Erc::STANDARDS.to_vec();
+        if lookups {
+            found.push(Erc::Erc165);
+            candidates.extend(Erc::CURATED);
+        }
+        for erc in candidates {
+            let Some(id) = erc.interface_id() else {
+                continue;
+            };
+            if !found.contains(&erc) && supports_interface(address, id, provider).await {
+                found.push(erc);
+            }
+        }
+    }
+    if lookups {
+        found.extend(registered_interfaces(address, provider).await);
+    }
+    found.sort();
+    found.dedup();
+    found
+}
+
+/// Selectors pushed onto the stack by PUSH4 instructions.
+///
+/// Data following other PUSH instructions is skipped so that it is not read
+/// as code.
+fn bytecode_selectors(bytecode: &[u8]) -> HashSet<[u8; 4]> {
+    let mut selectors = HashSet::new();
+    let mut i = 0;
+    while i < bytecode.len() {
+        let op = bytecode[i];
+        if op == PUSH4 {
+            if let Some(s) = bytecode.get(i + 1..i + 5) {
+                selectors.insert([s[0], s[1], s[2], s[3]]);
+            }
+        }
+        // PUSH1 (0x60) to PUSH32 (0x7f) are followed by 1 to 32 bytes of data.
+        if (0x60..=0x7f).contains(&op) {
+            i += (op - 0x5f) as usize;
+        }
+        i += 1;
+    }
+    selectors
+}
+
+/// Selectors of the functions in a JSON ABI. Returns none if the ABI is not JSON.
+fn abi_selectors(abi: &str) -> HashSet<[u8; 4]> {
+    let Ok(Value::Array(items)) = serde_json::from_str::<Value>(abi) else {
+        return HashSet::new();
+    };
+    items
+        .iter()
+        .filter(|item| item["type"] == "function")
+        .filter_map(|item| {
+            let name = item["name"].as_str()?;
+            let types = item["inputs"]
+                .as_array()?
+                .iter()
+                .map(|input| input["type"].as_str())
+                .collect::<Option<Vec<&str>>>()?;
+            let hash = keccak256(format!("{}({})", name, types.join(",")).as_bytes());
+            Some([hash[0], hash[1], hash[2], hash[3]])
+        })
+        .collect()
+}
+
+/// Name and symbol from calling name() and symbol() on a contract, e.g.,
+/// ["Wrapped Ether", "WETH"]. Calls that fail or time out are left out.
+///
+/// The values are chosen by the contract, so are not checked in any way.
+pub async fn onchain_names(address: &H160, provider: &Provider) -> Vec<String> {
+    if !provider.supports(Capability::Calls) {
+        return vec![];
+    }
+    let mut names = vec![];
+    for selector in [NAME, SYMBOL] {
+        let call = provider.contract_call(*address, selector.to_vec());
+        if let Ok(Ok((response, _))) = tokio::time::timeout(NAME_TIMEOUT, call).await {
+            names.extend(returned_text(&response.0));
+        }
+    }
+    names
+}
+
+/// Decimals from calling decimals() on a token contract. None if the call
+/// fails, times out or returns something other than a number of decimals.
+pub async fn onchain_decimals(address: &H160, provider: &Provider) -> Option<u32> {
+    if !provider.supports(Capability::Calls) {
+        return None;
+    }
+    let call = provider.contract_call(*address, DECIMALS.to_vec());
+    match tokio::time::timeout(NAME_TIMEOUT, call).await {
+        Ok(Ok((response, _))) => returned_decimals(&response.0),
+        _ => None,
+    }
+}
+
+/// Decimals returned as a uint8 (or any uint up to `MAX_DECIMALS`).
+fn returned_decimals(data: &[u8]) -> Option<u32> {
+    if data.len() != 32 {
+        return None;
+    }
+    let decimals = U256::from_big_endian(data);
+    (decimals <= U256::from(MAX_DECIMALS)).then(|| decimals.as_u32())
+}
+
+/// Text returned as a string, or as bytes32 by older tokens (e.g., MKR).
+fn returned_text(data: &[u8]) -> Option<String> {
+    let bytes = match data.len() {
+        32 => data.split(|b| *b == 0).next()?,
+        _ => dynamic_bytes(data, 0)?,
+    };
+    let text: String = String::from_utf8_lossy(bytes)
+        .chars()
+        .filter(|c| !c.is_control())
+        .take(MAX_NAME_CHARS)
+        .collect();
+    let text = text.trim();
+    (!text.is_empty()).then(|| text.to_string())
+}
+
+/// Checks that the contract answers supportsInterface as ERC-165 specifies.
+async fn implements_erc165(address: &H160, provider: &Provider) -> bool {
+    supports_interface(address, SUPPORTS_INTERFACE, provider).await
+        && !supports_interface(address, INVALID_ID, provider).await
+}
+
+/// Calls supportsInterface(bytes4) on the contract. Errors count as unsupported.
+async fn supports_interface(address: &H160, id: [u8; 4], provider: &Provider) -> bool {
+    let mut data = SUPPORTS_INTERFACE.to_vec();
+    data.extend(id);
+    data.extend([0; 28]);
+    match provider.contract_call(*address, data).await {
+        Ok((response, _)) => response.0.len() == 32 && response.0[31] == 1,
+        Err(_) => false,
+    }
+}
+
+/// Interfaces that the contract registered itself as implementing in the
+/// EIP-1820 registry. Errors (e.g., no registry on the chain) count as none.
+async fn registered_interfaces(address: &H160, provider: &Provider) -> Vec<Erc> {
+    let mut found = vec![];
+    for (name, erc) in REGISTERED {
+        let data = implementer_call(address, name);
+        if let Ok((response, _)) = provider.contract_call(REGISTRY, data).await {
+            if response.0.len() == 32 && response.0[12..] == address.0 {
+                found.push(erc);
+            }
+        }
+    }
+    found
+}
+
+/// Calldata of getInterfaceImplementer for an interface name.
+fn implementer_call(address: &H160, name: &str) -> Vec<u8> {
+    let mut data = GET_INTERFACE_IMPLEMENTER.to_vec();
+    data.extend([0; 12]);
+    data.extend(address.0);
+    data.extend(keccak256(name.as_bytes()));
+    data
+}
+
+impl Display for Erc {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let name = match self {
+            Erc::Erc20 => "ERC-20",
+            Erc::Erc721 => "ERC-721",
+            Erc::Erc1155 => "ERC-1155",
+            Erc::Erc4626 => "ERC-4626",
+            Erc::Erc777 => "ERC-777",
+            Erc::Erc165 => "ERC-165",
+            Erc::Erc721Metadata => "ERC-721 Metadata",
+            Erc::Erc721Enumerable => "ERC-721 Enumerable",
+            Erc::Erc1155MetadataUri => "ERC-1155 Metadata URI",
+            Erc::Erc2981 => "ERC-2981",
+            Erc::Erc4906 => "ERC-4906",
+            Erc::Erc5192 => "ERC-5192",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+#[test]
+fn erc20_from_bytecode_and_abi() {
+    // A dispatcher fragment: PUSH4 <selector> EQ for each ERC-20 function.
+    let mut bytecode = vec![0x60, 0x80, 0x60, 0x40, 0x52];
+    for selector in Erc::Erc20.required_selectors() {
+        bytecode.push(PUSH4);
+        bytecode.extend(selector);
+        bytecode.push(0x14);
+    }
+    let selectors = bytecode_selectors(&bytecode);
+    assert!(Erc::Erc20
+        .required_selectors()
+        .iter()
+        .all(|s| selectors.contains(s)));
+    // Selector bytes inside PUSH32 data are not code.
+    let mut hidden = vec![0x7f];
+    hidden.extend([PUSH4, 0xaa, 0xbb, 0xcc, 0xdd]);
+    hidden.extend([0; 27]);
+    assert!(bytecode_selectors(&hidden).is_empty());
+
+    let abi = r#"[{"type":"function","name":"transfer","inputs":[{"type":"address"},{"type":"uint256"}]}]"#;
+    assert!(abi_selectors(abi).contains(&[0xa9, 0x05, 0x9c, 0xbb]));
+    assert!(abi_selectors("TODO, get TODD-ABIs").is_empty());
+}
+
+#[test]
+fn lookup_calldata() {
+    let selector = |text: &str| keccak256(text.as_bytes())[..4].to_vec();
+    assert_eq!(
+        selector("getInterfaceImplementer(address,bytes32)"),
+        GET_INTERFACE_IMPLEMENTER
+    );
+    // Interfaces of one function have the selector as their id.
+    let royalties = Erc::Erc2981.interface_id().unwrap();
+    assert_eq!(selector("royaltyInfo(uint256,uint256)"), royalties);
+    assert!(Erc::CURATED.iter().all(|erc| erc.interface_id().is_some()));
+    assert!(Erc::Erc777.required_selectors().is_empty());
+
+    let token = H160::repeat_byte(0x11);
+    let data = implementer_call(&token, "ERC777Token");
+    assert_eq!(data.len(), 4 + 32 + 32);
+    assert_eq!(&data[16..36], token.as_bytes());
+    assert_eq!(data[36..], keccak256(b"ERC777Token"));
+    assert_eq!(
+        format!("{:?}", REGISTRY),
+        "0x1820a4b7618bde71dce8cdc73aab6c95905fad24"
+    );
+}
+
+#[test]
+fn names_from_string_or_bytes32() {
+    assert_eq!(keccak256(b"name()")[..4], NAME);
+    assert_eq!(keccak256(b"symbol()")[..4], SYMBOL);
+    let mut maker = [0; 32];
+    maker[..3].copy_from_slice(b"MKR");
+    assert_eq!(returned_text(&maker).as_deref(), Some("MKR"));
+
+    let mut string = [0; 96];
+    string[31] = 0x20;
+    string[63] = 13;
+    string[64..77].copy_from_slice(b"Wrapped\nEther");
+    assert_eq!(returned_text(&string).as_deref(), Some("WrappedEther"));
+    assert_eq!(returned_text(&[0; 32]), None);
+    assert_eq!(returned_text(&[]), None);
+}
+
+#[test]
+fn decimals_from_uint() {
+    assert_eq!(keccak256(b"decimals()")[..4], DECIMALS);
+    let mut word = [0; 32];
+    word[31] = 6;
+    assert_eq!(returned_decimals(&word), Some(6));
+    assert_eq!(returned_decimals(&word[..31]), None);
+    word[0] = 1;
+    assert_eq!(returned_decimals(&word), None);
+}
@@ -0,0 +1,141 @@
+//! Address labels supplied by the user (e.g., exchange deposit addresses,
+//! friends' wallets).
+//!
+//! User labels are merged with the TODD nametags database at lookup time and
+//! take precedence over it.
+//!
+//! Two file formats are accepted, chosen by extension:
+//! - `.json`: an object mapping addresses to a label or a list of labels.
+//! - Anything else is read as CSV: `address,label[,label...]` per line. Blank
+//!   lines, lines starting with `#` and a header starting with `address` are
+//!   skipped. Quoted fields are not supported.
+use std::{collections::HashMap, fs, path::Path};
+
+use anyhow::{anyhow, bail, Context, Result};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use web3::types::H160;
+
+use crate::parsing::validate_address;
+
+/// Labels by address (lowercase hex without "0x").
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct UserLabels(HashMap<String, Vec<String>>);
+
+impl UserLabels {
+    /// Reads label files. Labels for the same address are combined in file order.
+    pub fn load(paths: &[impl AsRef<Path>]) -> Result<Self> {
+        let mut labels = UserLabels::default();
+        for path in paths {
+            let path = path.as_ref();
+            let text = fs::read_to_string(path)
+                .with_context(|| format!("Could not read label file {}", path.display()))?;
+            let is_json = path.extension().is_some_and(|e| e == "json");
+            let parsed = match is_json {
+                true => labels.add_json(&text),
+                false => labels.add_csv(&text),
+            };
+            parsed.with_context(|| format!("Invalid label file {}", path.display()))?;
+        }
+        Ok(labels)
+    }
+    fn add_csv(&mut self, text: &str) -> Result<()> {
+        for (n, line) in text.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let mut fields = line.split(',').map(str::trim);
+            let address = fields.next().unwrap_or_default();
+            if n == 0 && address.eq_ignore_ascii_case("address") {
+                continue;
+            }
+            let labels: Vec<String> = fields
+                .filter(|f| !f.is_empty())
+                .map(str::to_owned)
+                .collect();
+            self.insert(address, labels)
+                .with_context(|| format!("Line {}", n + 1))?;
+        }
+        Ok(())
+    }
+    fn add_json(&mut self, text: &str) -> Result<()> {
+        let Value::Object(map) = serde_json::from_str(text)? else {
+            bail!("Expected an object mapping addresses to labels.")
+        };
+        for (address, value) in map {
+            let labels = match value {
+                Value::String(label) => vec![label],
+                Value::Array(items) => items
+                    .into_iter()
+                    .map(|item| match item {
+                        Value::String(label) => Ok(label),
+                        other => Err(anyhow!("Label is not a string: {}", other)),
+                    })
+                    .collect::<Result<Vec<String>>>()?,
+                other => bail!("Labels for {} are not a string or list: {}", address, other),
+            };
+            self.insert(&address, labels)?;
+        }
+        Ok(())
+    }
+    fn insert(&mut self, address: &str, labels: Vec<String>) -> Result<()> {
+        let address = validate_address(address, None)?;
+        let key = address.trim_start_matches("0x").to_owned();
+        self.0.entry(key).or_default().extend(labels);
+        Ok(())
+    }
+    /// Labels for an address, if any were supplied.
+    pub fn get(&self, address: &H160) -> Option<&[String]> {
+        self.0
+            .get(&hex::encode(address))
+            .map(|l| l.as_slice())
+            .filter(|l| !l.is_empty())
+    }
+    /// Each address (lowercase hex without "0x") with its labels.
+    pub fn iter(&self) -> impl Iterator<Item = (&String, &Vec<String>)> {
+        self.0.iter().filter(|(_, labels)| !labels.is_empty())
+    }
+    /// User labels followed by any other tags not already present.
+    pub fn merge(&self, address: &H160, tags: Option<Vec<String>>) -> Option<Vec<String>> {
+        let Some(user) = self.get(address) else {
+            return tags;
+        };
+        let mut merged = user.to_vec();
+        for tag in tags.into_iter().flatten() {
+            if !tag.is_empty() && !merged.contains(&tag) {
+                merged.push(tag);
+            }
+        }
+        Some(merged)
+    }
+}
+
+#[test]
+fn user_labels() {
+    let mut labels = UserLabels::default();
+    labels
+        .add_csv(
+            "address,label\n\
+            # Exchanges\n\
+            0x28C6c06298d514Db089934071355E5743bf21d60, Binance 14, exchange\n\n",
+        )
+        .unwrap();
+    labels
+        .add_json(r#"{"0x846be97d3bf1e3865f3caf55d749864d39e54cb9": "Alice"}"#)
+        .unwrap();
+    assert!(labels.add_csv("0x1234,too short").is_err());
+
+    let binance: H160 = "28c6c06298d514db089934071355e5743bf21d60".parse().unwrap();
+    let merged = labels.merge(
+        &binance,
+        Some(vec![String::from("exchange"), String::from("hot wallet")]),
+    );
+    assert_eq!(
+        merged.unwrap(),
+        vec!["Binance 14", "exchange", "hot wallet"]
+    );
+    let alice: H160 = "846be97d3bf1e3865f3caf55d749864d39e54cb9".parse().unwrap();
+    assert_eq!(labels.get(&alice), Some(&[String::from("Alice")][..]));
+    assert_eq!(labels.merge(&H160::zero(), None), None);
+}
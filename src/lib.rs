@@ -0,0 +1,193 @@
+//! Decoding core of the explorer, and with the `node` feature (the default)
+//! the node, API, index and cache access of the `psr` binary around it.
+//!
+//! The decoding core works on bytes and text that the caller already has,
+//! without network, file or database access, so it also builds for
+//! `wasm32-unknown-unknown` (without default features) for use in a browser
+//! or wallet extension.
+//!
+//! With the `node` feature, `explain_address` builds and decodes the history
+//! of an address in one call.
+//!
+//! ## Stability
+//! The public API is `prelude`, the `wasm` bindings and (with the `node`
+//! feature) `explain_address`, and follows semver. Structs and enums that
+//! are likely to gain fields or variants are `#[non_exhaustive]`, so adding
+//! them is not a breaking change.
+//!
+//! The other modules are public only so that the `psr` binary can build on
+//! them. They are hidden from the documentation and may change in any
+//! release. Those of the decoding core are:
+//! - `abi`: selectors, topics, signature matching and ABI words.
+//! - `contract`: the CBOR metadata trailer of runtime bytecode.
+//! - `decode`: calldata and constructor arguments, with or without an ABI.
+//! - `signatures`: built in text signatures of common functions and events.
+#[doc(hidden)]
+pub mod abi;
+#[cfg(feature = "node")]
+#[doc(hidden)]
+pub mod alerts;
+#[cfg(feature = "node")]
+#[doc(hidden)]
+pub mod anomalies;
+#[cfg(feature = "node")]
+#[doc(hidden)]
+pub mod apis;
+#[cfg(feature = "node")]
+#[doc(hidden)]
+pub mod batch;
+#[cfg(feature = "node")]
+#[doc(hidden)]
+pub mod cache;
+#[cfg(feature = "node")]
+#[doc(hidden)]
+pub mod cancel;
+#[cfg(feature = "node")]
+#[doc(hidden)]
+pub mod capabilities;
+#[cfg(feature = "node")]
+#[doc(hidden)]
+pub mod chains;
+#[doc(hidden)]
+pub mod contract;
+#[cfg(feature = "node")]
+#[doc(hidden)]
+pub mod contribute;
+#[cfg(feature = "node")]
+#[doc(hidden)]
+pub mod craft;
+#[cfg(feature = "node")]
+#[doc(hidden)]
+pub mod data;
+#[cfg(feature = "node")]
+#[doc(hidden)]
+pub mod db;
+#[doc(hidden)]
+pub mod decode;
+#[cfg(feature = "node")]
+#[doc(hidden)]
+pub mod decompile;
+#[cfg(feature = "node")]
+#[doc(hidden)]
+pub mod diff;
+#[cfg(feature = "node")]
+#[doc(hidden)]
+pub mod event_index;
+#[cfg(feature = "node")]
+#[doc(hidden)]
+pub mod explain;
+#[cfg(feature = "node")]
+#[doc(hidden)]
+pub mod explorer;
+#[cfg(feature = "node")]
+#[doc(hidden)]
+pub mod export;
+#[cfg(feature = "node")]
+#[doc(hidden)]
+pub mod fees;
+#[cfg(feature = "node")]
+#[doc(hidden)]
+pub mod flows;
+#[cfg(feature = "node")]
+#[doc(hidden)]
+pub mod headers;
+#[cfg(feature = "node")]
+#[doc(hidden)]
+pub mod history;
+#[cfg(feature = "node")]
+#[doc(hidden)]
+pub mod inspect;
+#[cfg(feature = "node")]
+#[doc(hidden)]
+pub mod interfaces;
+#[cfg(feature = "node")]
+#[doc(hidden)]
+pub mod labels;
+#[cfg(feature = "node")]
+#[doc(hidden)]
+pub mod notify;
+#[cfg(feature = "node")]
+#[doc(hidden)]
+pub mod outcome;
+#[cfg(feature = "node")]
+#[doc(hidden)]
+pub mod parsing;
+#[cfg(feature = "node")]
+#[doc(hidden)]
+pub mod pipeline;
+#[cfg(feature = "node")]
+#[doc(hidden)]
+pub mod portal;
+pub mod prelude;
+#[cfg(feature = "node")]
+#[doc(hidden)]
+pub mod prices;
+#[cfg(feature = "node")]
+#[doc(hidden)]
+pub mod privacy;
+#[cfg(feature = "node")]
+#[doc(hidden)]
+pub mod profile;
+#[cfg(feature = "node")]
+#[doc(hidden)]
+pub mod provider;
+#[cfg(feature = "node")]
+#[doc(hidden)]
+pub mod redact;
+#[cfg(feature = "node")]
+#[doc(hidden)]
+pub mod render;
+#[cfg(feature = "node")]
+#[doc(hidden)]
+pub mod rpc;
+#[cfg(feature = "node")]
+#[doc(hidden)]
+pub mod safe;
+#[cfg(feature = "node")]
+#[doc(hidden)]
+pub mod search;
+#[cfg(feature = "node")]
+#[doc(hidden)]
+pub mod semantics;
+#[cfg(feature = "node")]
+#[doc(hidden)]
+pub mod service;
+#[cfg(feature = "node")]
+#[doc(hidden)]
+pub mod settings;
+#[doc(hidden)]
+pub mod signatures;
+#[cfg(feature = "node")]
+#[doc(hidden)]
+pub mod simulate;
+#[cfg(feature = "node")]
+#[doc(hidden)]
+pub mod snapshot;
+#[cfg(feature = "node")]
+#[doc(hidden)]
+pub mod spam;
+#[cfg(feature = "node")]
+#[doc(hidden)]
+pub mod stats;
+#[cfg(feature = "node")]
+#[doc(hidden)]
+pub mod store;
+#[cfg(feature = "node")]
+#[doc(hidden)]
+pub mod tax;
+#[cfg(feature = "node")]
+#[doc(hidden)]
+pub mod timeline;
+#[cfg(feature = "node")]
+#[doc(hidden)]
+pub mod user_operation;
+#[cfg(feature = "node")]
+#[doc(hidden)]
+pub mod verify;
+pub mod wasm;
+#[cfg(feature = "node")]
+#[doc(hidden)]
+pub mod watch;
+
+#[cfg(feature = "node")]
+pub use explain::{explain_address, ExplainOptions, Report};
@@ -1,21 +1,541 @@
-mod apis;
-mod cache;
-mod contract;
-mod data;
-mod history;
-mod parsing;
+use std::{
+    env, fs,
+    path::{Path, PathBuf},
+    process::ExitCode,
+    time::Duration,
+};
 
-use std::env;
-
-use anyhow::Result;
-use history::Mode;
+use anyhow::{anyhow, Result};
+use clap::{Parser, Subcommand};
+use clap_verbosity_flag::{InfoLevel, Verbosity};
+use log::LevelFilter;
 use min_know::config::choices::DirNature;
-
-use crate::history::{AddressHistory, Config};
+use psr::{
+    alerts::AlertRules,
+    apis::{self, SourcifyRepo},
+    batch::{self, BatchFormat, BatchOptions},
+    cache::Cache,
+    cancel::Cancellation,
+    chains::{Chain, MAINNET, MAINNET_CHAIN_ID},
+    contribute,
+    craft::{craft_transaction, CraftFormat},
+    db::{self, Database},
+    decompile::{self, DecompilePool},
+    diff::HistoryDiff,
+    explorer::Explorer,
+    export,
+    headers::Verifier,
+    history::{AddressHistory, CodeBlock, Config, ConfigBuilder, Mode, ValidatedConfig},
+    inspect::RawView,
+    labels::UserLabels,
+    outcome::{ChainFailure, ChainSummary, Outcome, Summary},
+    pipeline::StageKind,
+    prices::Prices,
+    privacy::PaddedProvider,
+    redact::{Pseudonyms, Redaction},
+    render::{ColorChoice, Detail, FormatOptions},
+    search, service,
+    settings::Settings,
+    simulate::{try_call, PlannedCall},
+    snapshot,
+    spam::SpamFilter,
+    store::ArtifactStore,
+    tax::{LotMethod, TaxReport},
+    timeline::Timeline,
+    watch::{self, WatchOptions},
+};
 
 const PORTAL_NODE: &str = "http://localhost:8545";
 
-/// Uses index data and a theoretical local Ethereum portal node to
+/// A wallet explorer prototype.
+#[derive(Parser, Debug)]
+#[command(name = "psr", version, about)]
+struct Cli {
+    /// Address to explore.
+    #[arg(long, default_value = SAMPLE_ADDRESS[1])]
+    address: String,
+    /// Settings file (TOML), e.g., a proxy and certificates for the APIs.
+    #[arg(long)]
+    config: Option<PathBuf>,
+    /// RPC endpoint of a node. Repeat to add fallbacks, tried in order.
+    #[arg(long = "rpc-url", default_value = PORTAL_NODE)]
+    rpc_urls: Vec<String>,
+    /// Treat the first RPC endpoint as a Portal Network node (experimental).
+    ///
+    /// Receipts, events and watch mode are unavailable.
+    #[arg(long)]
+    portal: bool,
+    /// Chain id that the node must serve, checked before anything is fetched.
+    #[arg(long, default_value_t = MAINNET_CHAIN_ID)]
+    chain_id: u64,
+    /// Another chain to include, as name=rpc_url[,rpc_url...]@index_dir. Can be repeated.
+    ///
+    /// Activity on all chains is merged into one report, ordered by time.
+    #[arg(long = "chain")]
+    chains: Vec<Chain>,
+    /// Directory of the address appearances database (defaults to the
+    /// sample data).
+    #[arg(long)]
+    appearances_dir: Option<PathBuf>,
+    /// Directory of the signatures database (defaults to the sample data).
+    #[arg(long)]
+    signatures_dir: Option<PathBuf>,
+    /// Directory of the nametags database (defaults to the sample data).
+    #[arg(long)]
+    nametags_dir: Option<PathBuf>,
+    /// Directory for bytecode and decompiled contracts kept between runs.
+    #[arg(long, default_value = "cache")]
+    cache_dir: PathBuf,
+    /// Size limit of the cache directory in MiB. Least recently used entries are removed.
+    #[arg(long, default_value_t = 512)]
+    cache_max_mb: u64,
+    /// Keep the decoded history in the cache directory and, on later runs,
+    /// only fetch transactions in blocks after those already processed.
+    #[arg(long)]
+    incremental: bool,
+    /// Write the decoded history (transactions, lookups and failures) to
+    /// this file, to be read with --load-history.
+    #[arg(long)]
+    save_history: Option<PathBuf>,
+    /// Read a history written with --save-history instead of fetching it
+    /// from the node and index (e.g., to export it again).
+    #[arg(long, conflicts_with_all = ["incremental", "chains"])]
+    load_history: Option<PathBuf>,
+    /// Most contracts decompiled at once (defaults to the number of CPUs).
+    ///
+    /// Events are decoded while decompilation continues in the background.
+    #[arg(long, default_value_t = decompile::default_workers())]
+    decompile_workers: usize,
+    /// Seconds a contract may take to decompile before it is decoded
+    /// heuristically instead.
+    #[arg(long, default_value_t = decompile::TIMEOUT.as_secs())]
+    decompile_timeout: u64,
+    /// File of address labels (CSV or JSON), shown in place of nametags. Can be repeated.
+    #[arg(long)]
+    labels: Vec<PathBuf>,
+    /// Link transactions, addresses and tokens to a block explorer (EIP-3091).
+    ///
+    /// A network name (mainnet, sepolia, holesky) or the base URL of an explorer.
+    #[arg(long)]
+    explorer: Option<Explorer>,
+    /// Look up signatures and ABIs missing locally at 4byte.directory and Sourcify.
+    #[arg(long)]
+    use_apis: bool,
+    /// Send the requests to the APIs through a local Tor daemon (SOCKS5 on
+    /// port 9050). The node is reached directly.
+    #[arg(long)]
+    tor: bool,
+    /// Append signatures and ABIs found via APIs to raw data files in this
+    /// directory and extend the local signatures database with them.
+    #[arg(long, requires = "use_apis")]
+    contribute: Option<PathBuf>,
+    /// Write the requests made to external APIs (host, count, bytes and
+    /// what was learned) to this file as JSON. They are always printed at
+    /// the end of a run with --use-apis.
+    #[arg(long)]
+    api_report: Option<PathBuf>,
+    /// Most events shown per transaction (0 shows all).
+    #[arg(long, default_value_t = 10)]
+    max_events: usize,
+    /// Most transactions shown.
+    #[arg(long)]
+    max_txs: Option<usize>,
+    /// Number of transactions to skip before the first one shown.
+    #[arg(long, default_value_t = 0)]
+    skip_txs: usize,
+    /// How much is shown for each transaction.
+    #[arg(long, value_enum, default_value_t = Detail::Normal)]
+    detail: Detail,
+    /// Recompute the logs bloom of each receipt and flag receipts whose logs do not match.
+    #[arg(long)]
+    verify_blooms: bool,
+    /// Check each receipt against the receipts root of its block and mark
+    /// transactions as verified or unverified.
+    ///
+    /// Fetches every receipt of each block in the history.
+    #[arg(long)]
+    verify_receipts: bool,
+    /// Check block headers against a trusted source of block hashes when
+    /// verifying receipts.
+    ///
+    /// The URL of a node that verifies headers itself (e.g., a light client),
+    /// or a file of "number,hash" lines (e.g., from a header accumulator).
+    #[arg(long, requires = "verify_receipts")]
+    trusted_headers: Option<String>,
+    /// Ask each contract which interfaces it supports (ERC-165, for a
+    /// curated list) and look it up in the EIP-1820 registry.
+    ///
+    /// Makes a few eth_calls per contract.
+    #[arg(long)]
+    interface_lookups: bool,
+    /// Drop the bytecode of each contract once its events are decoded, for
+    /// histories with many events. It stays in the cache directory.
+    #[arg(long)]
+    drop_bytecode: bool,
+    /// Light mode, for machines with little memory: drops the bytecode (as
+    /// --drop-bytecode), then the logs and logs bloom of each receipt once
+    /// the history is decoded and verified.
+    #[arg(long)]
+    light: bool,
+    /// Label contracts without a nametag by calling their name() and
+    /// symbol() functions (e.g., tokens).
+    ///
+    /// The names are chosen by each contract, so may mislead.
+    #[arg(long)]
+    onchain_names: bool,
+    /// Show who deployed each contract that emitted an event (e.g., a
+    /// factory), named from the nametags.
+    ///
+    /// Needs a node with the Otterscan API (ots_getContractCreator).
+    #[arg(long)]
+    deployer_lookups: bool,
+    /// Privacy mode: pad each transaction, receipt, block and code lookup
+    /// with this many decoy lookups, so that the node sees more than what
+    /// the history needs.
+    ///
+    /// Best effort: a provider can still correlate requests, and the run
+    /// makes several times as many.
+    #[arg(long, default_value_t = 0)]
+    privacy_decoys: u32,
+    /// Block at which the code of each contract that emitted an event is
+    /// examined.
+    ///
+    /// At emission, contracts since destroyed or replaced are seen as they
+    /// were, which needs a node that keeps the state of old blocks.
+    #[arg(long, value_enum, default_value_t)]
+    code_at: CodeBlock,
+    /// Run only these stages, in this order (comma separated), e.g., to
+    /// stop before decoding. Defaults to all of them.
+    #[arg(
+        long,
+        value_enum,
+        value_delimiter = ',',
+        conflicts_with = "incremental"
+    )]
+    stages: Option<Vec<StageKind>>,
+    /// Highlight Self, labelled addresses, failures and large values.
+    #[arg(long, value_enum, default_value_t = ColorChoice::Auto)]
+    color: ColorChoice,
+    /// How token events that look like unsolicited airdrops are shown, in
+    /// the terminal and exports (hide leaves them out of both).
+    #[arg(long, value_enum, default_value_t = SpamFilter::Show)]
+    spam: SpamFilter,
+    /// Log level: info, raised by -v (debug) and lowered by -q. RUST_LOG
+    /// takes precedence if set.
+    #[command(flatten)]
+    verbosity: Verbosity<InfoLevel>,
+    #[command(subcommand)]
+    command: Option<Command>,
+}
+
+#[derive(Subcommand, Debug)]
+enum Command {
+    /// Print the decoded history to the terminal (default).
+    Show,
+    /// Decode the history and write it to a file.
+    Export {
+        /// Replace the explored address (owner), or it and every counterparty
+        /// (all), with pseudonyms so the export can be shared.
+        #[arg(long, value_enum)]
+        redact: Option<Redaction>,
+        /// Add the value of ether and token transfers on the day of each
+        /// transaction, with prices from a CSV file of date,asset,price lines
+        /// or an API URL with {asset}, {date} and {currency} placeholders.
+        #[arg(long)]
+        prices: Option<String>,
+        /// Currency of the prices.
+        #[arg(long, default_value = "USD", requires = "prices")]
+        currency: String,
+        #[command(subcommand)]
+        format: ExportFormat,
+    },
+    /// Print the history, then follow new blocks and decode new activity.
+    Watch {
+        /// Address to watch (overrides --address).
+        address: Option<String>,
+        /// Seconds between checks for new blocks.
+        #[arg(long, default_value_t = 12)]
+        interval: u64,
+        /// SQLite database to append new transactions to.
+        #[arg(long)]
+        sqlite: Option<PathBuf>,
+        /// Also flag pending mempool transactions that involve the address.
+        #[arg(long)]
+        pending: bool,
+    },
+    /// Print the number of transactions in each month as a bar chart.
+    Timeline {
+        /// Also write the timeline to this file as an HTML heatmap.
+        #[arg(long)]
+        html: Option<PathBuf>,
+    },
+    /// Write capital gains to a CSV file, matching disposals of ether and
+    /// tokens with earlier acquisitions.
+    Tax {
+        /// CSV file to write (Form 8949 columns).
+        path: PathBuf,
+        /// Prices on the day of each transaction, from a CSV file of
+        /// date,asset,price lines or an API URL with {asset}, {date} and
+        /// {currency} placeholders.
+        #[arg(long)]
+        prices: String,
+        /// Currency of the prices.
+        #[arg(long, default_value = "USD")]
+        currency: String,
+        /// Which acquisitions a disposal is matched with.
+        #[arg(long, value_enum, default_value_t = LotMethod::Fifo)]
+        method: LotMethod,
+    },
+    /// Print every event with a signature, e.g., "Transfer(address,address,uint256)".
+    Events {
+        /// Text signature, or topic zero as hex.
+        signature: String,
+        /// Also write the index of all event signatures to this file (JSON).
+        #[arg(long)]
+        save_index: Option<PathBuf>,
+    },
+    /// Print what the address did with a contract: first and last
+    /// interaction, calls, events, value exchanged and functions used.
+    Profile {
+        /// Address of the contract.
+        contract: String,
+    },
+    /// Answer JSON-RPC 2.0 requests (psr_getHistory, psr_decodeTx,
+    /// psr_contractInfo) from local tools, one JSON object per line.
+    Serve {
+        /// TCP address to listen on, or the path of a Unix socket.
+        #[arg(long, default_value = "127.0.0.1:8645")]
+        listen: String,
+    },
+    /// Work with text signatures of functions and events.
+    Sig {
+        #[command(subcommand)]
+        action: SigAction,
+    },
+    /// Find addresses whose nametags or labels contain some text, e.g., "uniswap".
+    ///
+    /// Searches user labels and nametags found in earlier runs.
+    Nametags {
+        /// Text to search for (case insensitive).
+        query: String,
+        /// Instead list transactions in the history of --address that involve
+        /// a matching address (each address is looked up in the database).
+        #[arg(long)]
+        history: bool,
+    },
+    /// Decode one transaction, e.g., to see why one of its events did not decode.
+    ///
+    /// Any transaction can be decoded, as it is fetched by hash rather than
+    /// found in the appearance index.
+    #[command(visible_alias = "decode-tx")]
+    Tx {
+        /// Transaction hash.
+        hash: String,
+        /// Also print the transaction, receipt and logs as the node returned
+        /// them, with calldata and log data split into words beside the
+        /// decoded values.
+        #[arg(long)]
+        raw: bool,
+    },
+    /// Decode every transaction in a block and list the action of each.
+    ///
+    /// Uses the same stages and lookups as a history, so also exercises them
+    /// on many contracts at once.
+    Block {
+        /// Block number.
+        number: u64,
+    },
+    /// Print the histories of several addresses, looking them up in the
+    /// index in parallel.
+    Portfolio {
+        /// Addresses to explore.
+        #[arg(required = true)]
+        addresses: Vec<String>,
+    },
+    /// Write the histories of many addresses to files, printing progress.
+    Batch {
+        /// File of addresses, one per line (or the first field of a CSV
+        /// line), or "-" to read standard input.
+        #[arg(long)]
+        input: PathBuf,
+        /// Format of the files written.
+        #[arg(long, value_enum, default_value_t = BatchFormat::Json)]
+        format: BatchFormat,
+        /// Directory for one file per address.
+        #[arg(long, default_value = "batch")]
+        out_dir: PathBuf,
+        /// Write every history to this one file instead.
+        #[arg(long)]
+        combined: Option<PathBuf>,
+        /// Most addresses processed at once.
+        #[arg(long, default_value_t = 4)]
+        jobs: usize,
+        /// Most times an address that failed (e.g., a node could not be
+        /// reached) is run again.
+        #[arg(long, default_value_t = 2)]
+        retries: usize,
+    },
+    /// Call a contract function at the latest block without sending a
+    /// transaction, printing the return values and the gas it would use.
+    Simulate {
+        /// Contract to call.
+        contract: String,
+        /// Function: a text signature, or the name or selector of a function
+        /// in the verified ABI or recovered by decompiling.
+        function: String,
+        /// Arguments: addresses and bytes as hex, integers in decimal.
+        #[arg(allow_hyphen_values = true)]
+        args: Vec<String>,
+        /// Sender of the call.
+        #[arg(long)]
+        from: Option<String>,
+        /// Ether sent with the call, in wei.
+        #[arg(long, default_value = "0")]
+        value: String,
+    },
+    /// Build an unsigned transaction calling a contract function, to sign
+    /// and send from an external wallet.
+    Craft {
+        /// Contract to call.
+        contract: String,
+        /// Function: a text signature, or the name or selector of a function
+        /// in the verified ABI or recovered by decompiling.
+        function: String,
+        /// Arguments: addresses and bytes as hex, integers in decimal.
+        #[arg(allow_hyphen_values = true)]
+        args: Vec<String>,
+        /// Sender, to fill in the nonce.
+        #[arg(long)]
+        from: Option<String>,
+        /// Ether sent with the transaction, in wei.
+        #[arg(long, default_value = "0")]
+        value: String,
+        /// How the transaction is written.
+        #[arg(long, value_enum, default_value_t)]
+        format: CraftFormat,
+        /// Write the transaction to this file instead of printing it.
+        #[arg(long)]
+        out: Option<PathBuf>,
+    },
+    /// Check and update the local databases (appearances, signatures and
+    /// nametags).
+    Db {
+        #[command(subcommand)]
+        action: DbAction,
+    },
+    /// Manage the cache directory.
+    Cache {
+        #[command(subcommand)]
+        action: CacheAction,
+    },
+    /// Compare two histories of an address saved with --save-history (e.g.,
+    /// before and after an upgrade): new and missing transactions, newly
+    /// resolved names and ABIs, and changed interpretations.
+    Diff {
+        /// The older history.
+        old: PathBuf,
+        /// The newer history.
+        new: PathBuf,
+        /// Print the changes as JSON.
+        #[arg(long)]
+        json: bool,
+    },
+    /// Download the Sourcify metadata and signatures that the history needs,
+    /// keep them locally and write them to a directory laid out as a
+    /// Sourcify repository.
+    Snapshot {
+        /// Directory to write the snapshot to.
+        out: PathBuf,
+        /// Pin the snapshot to an IPFS node with this API URL (e.g.,
+        /// http://127.0.0.1:5001).
+        #[arg(long)]
+        ipfs_api: Option<String>,
+    },
+}
+
+#[derive(Subcommand, Debug)]
+enum CacheAction {
+    /// Show the number of cached contracts and the size of the cache.
+    Stats,
+    /// Remove everything from the cache.
+    Clear,
+    /// Write the cache (bytecode, decompiled contracts, signatures, nametags
+    /// and ABIs) to a bundle file to share.
+    Export {
+        /// Bundle file to create (a tar archive compressed with zstd).
+        path: PathBuf,
+    },
+    /// Add the contents of a bundle file to the cache, keeping existing entries.
+    Import {
+        /// Bundle file to read.
+        path: PathBuf,
+    },
+}
+
+#[derive(Subcommand, Debug)]
+enum DbAction {
+    /// Check each database against its manifest.
+    Status {
+        /// Databases to check (defaults to all).
+        #[arg(long, value_enum)]
+        database: Vec<Database>,
+    },
+    /// Add volumes from new raw data to each database, write its manifest
+    /// again and list the files that changed.
+    Extend {
+        /// Databases to extend (defaults to all).
+        #[arg(long, value_enum)]
+        database: Vec<Database>,
+    },
+}
+
+#[derive(Subcommand, Debug)]
+enum SigAction {
+    /// Print the selectors (functions) and topics (events) of known
+    /// signatures that contain some text, e.g., "transfer(".
+    ///
+    /// Searches signatures found in earlier runs and stored ABIs.
+    Find {
+        /// Text to search for (case insensitive).
+        query: String,
+    },
+}
+
+#[derive(Subcommand, Debug)]
+enum ExportFormat {
+    /// Normalized SQLite database of transactions, events, contracts and nametags.
+    Sqlite {
+        /// Database file to create or update.
+        path: PathBuf,
+    },
+    /// Parquet table with one row per event.
+    Parquet {
+        /// File to write.
+        path: PathBuf,
+    },
+    /// JSON array of transactions with their events (and explorer links, if enabled).
+    Json {
+        /// File to write.
+        path: PathBuf,
+    },
+    /// CSV file with one row per transaction.
+    Csv {
+        /// File to write.
+        path: PathBuf,
+    },
+    /// Graphviz DOT graph of ether and token transfers between addresses.
+    Dot {
+        /// File to write.
+        path: PathBuf,
+    },
+    /// GraphML graph of ether and token transfers between addresses (e.g., for Gephi).
+    Graphml {
+        /// File to write.
+        path: PathBuf,
+    },
+}
+
+/// Uses index data and a local Ethereum node (full or portal) to
 /// decode information for a user.
 ///
 /// A transaction is inspected for logs, which contain event
@@ -26,29 +546,687 @@ const PORTAL_NODE: &str = "http://localhost:8545";
 ///
 /// Additionally, the contract code can be inspected and the metadata
 /// extracted, which may contain a link to the contract ABI.
+///
+/// The exit code reports how the run ended (see `Outcome`).
 #[tokio::main]
-async fn main() -> Result<()> {
-    // For full error backtraces with anyhow.
-    env::set_var("RUST_BACKTRACE", "full");
-    env::set_var("RUST_LOG", "info");
-    env_logger::init();
-
-    let config = Config::new(DirNature::Sample, PORTAL_NODE)?;
-    let mut history = AddressHistory::new(SAMPLE_ADDRESS[1], config);
-
-    history
-        .get_transaction_ids()?
-        .get_transaction_data(Some(1))
-        .await?
-        .get_receipts(Some(1))
-        .await?
-        .decode_logs(Some(1), Mode::AvoidApis)
-        .await?;
-
-    println!("{}", history);
+async fn main() -> ExitCode {
+    let cli = Cli::parse();
+    init_logger(&cli.verbosity);
+
+    let (use_apis, api_report) = (cli.use_apis, cli.api_report.clone());
+    let outcome = match run(cli).await {
+        Ok(outcome) => outcome,
+        Err(e) => {
+            eprintln!("Error: {:?}", e);
+            Outcome::from_error(&e)
+        }
+    };
+    if let Err(e) = report_api_usage(use_apis, api_report.as_deref()) {
+        eprintln!("Error: {:?}", e);
+    }
+    outcome.into()
+}
+
+/// Logs at the level of the -v and -q flags, unless RUST_LOG is set.
+///
+/// From -vv, errors also carry full backtraces (with anyhow), unless
+/// RUST_BACKTRACE is set.
+fn init_logger(verbosity: &Verbosity<InfoLevel>) {
+    let level = verbosity.log_level_filter();
+    if level >= LevelFilter::Trace && env::var_os("RUST_BACKTRACE").is_none() {
+        env::set_var("RUST_BACKTRACE", "full");
+    }
+    let env = env_logger::Env::default().default_filter_or(level.to_string());
+    env_logger::Builder::from_env(env).init();
+}
+
+/// Prints the requests made to external APIs during the run (if any were
+/// allowed or made), and writes them to a file if asked.
+fn report_api_usage(use_apis: bool, path: Option<&Path>) -> Result<()> {
+    let usage = apis::usage();
+    if use_apis || !usage.hosts.is_empty() {
+        eprintln!("\n{}", usage);
+    }
+    if let Some(path) = path {
+        fs::write(path, serde_json::to_string_pretty(&usage)?)?;
+        eprintln!("Wrote API usage to {}", path.display());
+    }
     Ok(())
 }
 
+/// Runs the pipeline and the selected command.
+async fn run(cli: Cli) -> Result<Outcome> {
+    let mut settings = match &cli.config {
+        Some(path) => Settings::load(path)?,
+        None => Settings::default(),
+    };
+    settings.http.tor |= cli.tor;
+    apis::configure(&settings.http)?;
+    let databases = database_dirs(&cli);
+    let store = ArtifactStore::new(cli.cache_dir.clone(), cli.cache_max_mb * 1024 * 1024);
+    if let Err(e) = store.migrate() {
+        log::warn!("Could not move stored artifacts to the new layout ({})", e);
+    }
+    if let Some(Command::Cache { action }) = &cli.command {
+        match action {
+            CacheAction::Stats => println!("{}\n{}", store.root.display(), store.stats()?),
+            CacheAction::Clear => println!("Removed {} entries", store.clear()?),
+            CacheAction::Export { path } => {
+                let count = store.export_bundle(path)?;
+                println!("Exported {} entries to {}", count, path.display())
+            }
+            CacheAction::Import { path } => {
+                let count = store.import_bundle(path)?;
+                println!("Imported {} entries from {}", count, path.display())
+            }
+        }
+        return Ok(Outcome::Success);
+    }
+    if let Some(Command::Diff { old, new, json }) = &cli.command {
+        let diff = HistoryDiff::of_files(old, new)?;
+        match json {
+            true => println!("{}", serde_json::to_string_pretty(&diff)?),
+            false => println!("{}", diff),
+        }
+        return Ok(Outcome::Success);
+    }
+    if let Some(Command::Sig {
+        action: SigAction::Find { query },
+    }) = &cli.command
+    {
+        let found = search::search_signatures(query, &store.resolved()?, &store.abis()?);
+        if found.is_empty() {
+            println!("No known signatures containing \"{}\"", query);
+        }
+        for m in found {
+            println!("{}", m);
+        }
+        return Ok(Outcome::Success);
+    }
+    if let Some(Command::Db { action }) = &cli.command {
+        let (DbAction::Status { database } | DbAction::Extend { database }) = action;
+        let selected = match database.is_empty() {
+            true => Database::ALL.to_vec(),
+            false => database.clone(),
+        };
+        let selected: Vec<(Database, DirNature)> = selected
+            .into_iter()
+            .map(|d| (d, databases.directory(d)))
+            .collect();
+        let complete = match action {
+            DbAction::Status { .. } => {
+                let statuses = db::status(&selected);
+                for status in &statuses {
+                    println!("{}", status);
+                }
+                statuses.iter().all(|s| s.problem.is_none())
+            }
+            DbAction::Extend { .. } => {
+                let extensions = db::extend(&selected);
+                for extension in &extensions {
+                    println!("{}", extension);
+                }
+                extensions.iter().all(|e| e.error.is_none())
+            }
+        };
+        return Ok(match complete {
+            true => Outcome::Success,
+            false => Outcome::Failure,
+        });
+    }
+    if let Some(Command::Nametags {
+        query,
+        history: false,
+    }) = &cli.command
+    {
+        let labels = UserLabels::load(&cli.labels)?;
+        let found = search::search_names(query, &labels, &store.resolved()?);
+        if found.is_empty() {
+            println!("No known addresses with a name containing \"{}\"", query);
+        }
+        for m in found {
+            println!("0x{}: {}", hex::encode(m.address), m.names.join(", "));
+        }
+        return Ok(Outcome::Success);
+    }
+    let address = match &cli.command {
+        Some(Command::Watch {
+            address: Some(a), ..
+        }) => a.to_owned(),
+        _ => cli.address.clone(),
+    };
+    let labels = UserLabels::load(&cli.labels)?;
+    let header_verifier = match &cli.trusted_headers {
+        Some(source) => Some(Verifier::from_source(source)?),
+        None => None,
+    };
+    let decompiler = DecompilePool::new(
+        cli.decompile_workers,
+        Duration::from_secs(cli.decompile_timeout),
+    );
+    let shared = Shared {
+        labels,
+        decompiler,
+        cancel: Cancellation::default(),
+    };
+    let mut builder = databases
+        .clone()
+        .rpc_urls(cli.rpc_urls.clone())
+        .portal(cli.portal)
+        .chain_id(cli.chain_id)
+        .store(store.clone());
+    // A loaded history needs no node, unless a command fetches more.
+    if cli.load_history.is_some() {
+        builder = builder.offline();
+    }
+    let validated = chain_config(builder, &cli, &shared).await?;
+    let pool = validated.pool.clone().unwrap_or_default();
+    let chain_id = validated.chain_id.unwrap_or(cli.chain_id);
+    let sourcify = SourcifyRepo::from_settings(&settings.sourcify, chain_id)?;
+    let mut config = validated.config;
+    config.sourcify = sourcify.clone();
+    config.header_verifier = header_verifier;
+    // The service is stopped by the first Ctrl-C, as before.
+    let cancel = shared.cancel.clone();
+    if !matches!(cli.command, Some(Command::Serve { .. })) {
+        cancel.on_ctrl_c();
+    }
+    let format = FormatOptions {
+        max_events: Some(cli.max_events).filter(|n| *n > 0),
+        max_txs: cli.max_txs,
+        skip_txs: cli.skip_txs,
+        detail: cli.detail,
+        color: cli.color.enabled(),
+        spam: cli.spam,
+    };
+    let mode = match cli.use_apis {
+        true => Mode::UseApis,
+        false => Mode::AvoidApis,
+    };
+    if let Some(Command::Serve { listen }) = &cli.command {
+        service::serve(listen, config, mode).await?;
+        return Ok(Outcome::Success);
+    }
+    if let Some(Command::Tx { hash, raw }) = &cli.command {
+        let hash = hash
+            .trim_start_matches("0x")
+            .parse()
+            .map_err(|_| anyhow!("Invalid transaction hash {}", hash))?;
+        let mut history = AddressHistory::of_transaction(hash, config).await?;
+        reuse_resolved(&mut history);
+        history.decode_transaction(mode).await?;
+        if format.spam == SpamFilter::Hide {
+            history.hide_spam();
+        }
+        if let Err(e) = store.put_resolved(history.cache.resolved()) {
+            log::warn!("Could not keep resolved lookups ({})", e);
+        }
+        if *raw {
+            let tx = &history.transactions[0];
+            let options = &format;
+            println!("{}\n", RawView { tx, options });
+        }
+        println!("{}", history.render(&format));
+        if !history.failures.is_empty() {
+            eprintln!("\n{}", Summary(&history.failures));
+        }
+        if history.cancelled() {
+            return Ok(Outcome::Cancelled);
+        }
+        return Ok(Outcome::from_history(1, &history.failures));
+    }
+    if let Some(Command::Block { number }) = &cli.command {
+        let mut history = AddressHistory::of_block(*number, config).await?;
+        reuse_resolved(&mut history);
+        history.decode_transaction(mode).await?;
+        if let Err(e) = store.put_resolved(history.cache.resolved()) {
+            log::warn!("Could not keep resolved lookups ({})", e);
+        }
+        println!("{}", history.render_block(&format));
+        if !history.failures.is_empty() {
+            eprintln!("\n{}", Summary(&history.failures));
+        }
+        if history.cancelled() {
+            return Ok(Outcome::Cancelled);
+        }
+        // An empty block is not a miss.
+        return Ok(Outcome::from_history(1, &history.failures));
+    }
+    if let Some(Command::Simulate {
+        contract,
+        function,
+        args,
+        from,
+        value,
+    }) = &cli.command
+    {
+        let call = PlannedCall::parse(contract, function, args, from.as_deref(), value)?;
+        let mut cache = Cache::default();
+        // Lookups are stored by address only, so are reused on mainnet only.
+        if config.chain == MAINNET {
+            match store.resolved() {
+                Ok(resolved) => cache.add_resolved(resolved),
+                Err(e) => log::warn!("Could not reuse resolved lookups ({})", e),
+            }
+        }
+        let simulation = try_call(&call, &mode, &config, &mut cache).await?;
+        if let Err(e) = store.put_resolved(cache.resolved()) {
+            log::warn!("Could not keep resolved lookups ({})", e);
+        }
+        println!("{}", simulation);
+        return Ok(Outcome::Success);
+    }
+    if let Some(Command::Craft {
+        contract,
+        function,
+        args,
+        from,
+        value,
+        format,
+        out,
+    }) = &cli.command
+    {
+        let call = PlannedCall::parse(contract, function, args, from.as_deref(), value)?;
+        let mut cache = Cache::default();
+        // Lookups are stored by address only, so are reused on mainnet only.
+        if config.chain == MAINNET {
+            match store.resolved() {
+                Ok(resolved) => cache.add_resolved(resolved),
+                Err(e) => log::warn!("Could not reuse resolved lookups ({})", e),
+            }
+        }
+        let tx = craft_transaction(&call, &mode, &config, &mut cache).await?;
+        if let Err(e) = store.put_resolved(cache.resolved()) {
+            log::warn!("Could not keep resolved lookups ({})", e);
+        }
+        let exported = tx.export(*format)?;
+        match out {
+            Some(path) => {
+                fs::write(path, exported)?;
+                println!("Wrote unsigned transaction to {}", path.display());
+            }
+            None => println!("{}", exported),
+        }
+        return Ok(Outcome::Success);
+    }
+    if let Some(Command::Portfolio { addresses }) = &cli.command {
+        let histories = AddressHistory::of_addresses(addresses, &config);
+        let mut outcomes = vec![];
+        // Lookups resolved for one address are reused for the next.
+        let mut cache = None;
+        for (address, history) in addresses.iter().zip(histories) {
+            let mut history = match history {
+                Ok(history) => history,
+                Err(e) => {
+                    eprintln!("{}: {:?}", address, e);
+                    outcomes.push(Outcome::from_error(&e));
+                    continue;
+                }
+            };
+            match cache.take() {
+                Some(c) => history.cache = c,
+                None => reuse_resolved(&mut history),
+            }
+            let result = history.run_from_appearances(mode).await.map(|_| ());
+            cache = Some(std::mem::take(&mut history.cache));
+            if let Err(e) = result {
+                eprintln!("{}: {:?}", address, e);
+                outcomes.push(Outcome::from_error(&e));
+                continue;
+            }
+            if format.spam == SpamFilter::Hide {
+                history.hide_spam();
+            }
+            println!("{}\n{}", address, history.render(&format));
+            if !history.failures.is_empty() {
+                eprintln!("\n{}", Summary(&history.failures));
+            }
+            let count = history.transactions.len();
+            outcomes.push(Outcome::from_history(count, &history.failures));
+            if history.cancelled() {
+                break;
+            }
+        }
+        if let Some(cache) = &cache {
+            if let Err(e) = store.put_resolved(cache.resolved()) {
+                log::warn!("Could not keep resolved lookups ({})", e);
+            }
+        }
+        if cancel.is_cancelled() {
+            return Ok(Outcome::Cancelled);
+        }
+        return Ok(Outcome::combine(&outcomes));
+    }
+    if let Some(Command::Batch {
+        input,
+        format: batch_format,
+        out_dir,
+        combined,
+        jobs,
+        retries,
+    }) = &cli.command
+    {
+        let addresses = batch::read_addresses(input)?;
+        let options = BatchOptions {
+            format: *batch_format,
+            out_dir: out_dir.clone(),
+            combined: combined.clone(),
+            mode,
+            spam: format.spam,
+            jobs: *jobs,
+            retries: *retries,
+        };
+        let report = batch::run(&addresses, config, &options).await?;
+        println!("{}", report);
+        match &options.combined {
+            Some(path) => println!("Wrote histories to {}", path.display()),
+            None => println!("Wrote histories to {}", options.out_dir.display()),
+        }
+        if cancel.is_cancelled() {
+            return Ok(Outcome::Cancelled);
+        }
+        return Ok(report.outcome());
+    }
+    let mut history = match &cli.load_history {
+        Some(path) => AddressHistory::load(path, config)?,
+        None => explore(&address, config, mode).await?,
+    };
+    let mut pools = vec![pool];
+    // A chain that fails is left out of the report, rather than stopping it.
+    let mut skipped = vec![];
+    for chain in cli.chains.iter().take_while(|_| !cancel.is_cancelled()) {
+        let builder = Config::builder()
+            .directories(chain.index.clone())
+            .rpc_urls(chain.rpc_urls.clone())
+            .store(store.on_chain(&chain.name));
+        let explored = async {
+            let validated = chain_config(builder, &cli, &shared).await?;
+            pools.extend(validated.pool);
+            let mut config = validated.config;
+            if let Some(id) = validated.chain_id {
+                config.sourcify = sourcify.on_chain(id);
+            }
+            config.chain = chain.name.clone();
+            if cli.trusted_headers.is_some() {
+                log::warn!(
+                    "{}: The trusted headers are for the main chain, so receipts are checked against the node's headers",
+                    chain.name
+                );
+            }
+            explore(&address, config, mode).await
+        };
+        match explored.await {
+            Ok(other) => history.merge(other),
+            Err(e) => {
+                log::error!("{}: Could not explore the chain ({:#})", chain.name, e);
+                skipped.push(ChainFailure {
+                    chain: chain.name.clone(),
+                    error: format!("{:#}", e),
+                });
+            }
+        }
+    }
+    if let Some(path) = &cli.save_history {
+        history.save(path)?;
+    }
+    if format.spam == SpamFilter::Hide {
+        history.hide_spam();
+    }
+
+    // Before contributing, so that the downloaded signatures are contributed.
+    if let Some(Command::Snapshot { out, ipfs_api }) = &cli.command {
+        let mut snapshot = snapshot::download(&mut history).await?;
+        snapshot.write(out)?;
+        if let Some(api) = ipfs_api {
+            snapshot.pin(out, api).await?;
+        }
+        println!("{}", snapshot);
+        println!("Wrote snapshot to {}", out.display());
+    }
+    if let Some(dir) = &cli.contribute {
+        contribute::contribute(&history.cache, &mut history.config, dir)?;
+    }
+    if let Err(e) = store.put_resolved(history.cache.resolved()) {
+        log::warn!("Could not keep resolved lookups ({})", e);
+    }
+    if let Err(e) = history.config.store.evict() {
+        log::warn!("Could not enforce the cache size limit ({})", e);
+    }
+    for (url, health) in pools.iter().flat_map(|p| p.health()) {
+        if health.failures > 0 {
+            log::warn!(
+                "RPC endpoint {} failed {} of {} requests",
+                url,
+                health.failures,
+                health.failures + health.successes
+            );
+        }
+    }
+
+    match cli.command.unwrap_or(Command::Show) {
+        Command::Show => println!("{}", history.render(&format)),
+        Command::Export {
+            redact,
+            prices,
+            currency,
+            format,
+        } => {
+            if let Some(source) = prices {
+                history
+                    .price(&Prices::from_source(&source, &currency)?)
+                    .await?;
+            }
+            let pseudonyms = match redact {
+                Some(redaction) => Pseudonyms::new(&history, redaction),
+                None => Pseudonyms::default(),
+            };
+            let path = match format {
+                ExportFormat::Sqlite { path } => {
+                    export::to_sqlite(&history, &path, &pseudonyms)?;
+                    path
+                }
+                ExportFormat::Parquet { path } => {
+                    export::to_parquet(&history, &path, &pseudonyms)?;
+                    path
+                }
+                ExportFormat::Json { path } => {
+                    export::to_json(&history, &path, &pseudonyms)?;
+                    path
+                }
+                ExportFormat::Csv { path } => {
+                    export::to_csv(&history, &path, &pseudonyms)?;
+                    path
+                }
+                ExportFormat::Dot { path } => {
+                    export::to_dot(&history, &path, &pseudonyms)?;
+                    path
+                }
+                ExportFormat::Graphml { path } => {
+                    export::to_graphml(&history, &path, &pseudonyms)?;
+                    path
+                }
+            };
+            println!("Exported history to {}", path.display());
+        }
+        Command::Watch {
+            interval,
+            sqlite,
+            pending,
+            ..
+        } => {
+            println!("{}", history.render(&format));
+            let options = WatchOptions {
+                interval: Duration::from_secs(interval),
+                mode,
+                sqlite,
+                pending,
+                format,
+                alerts: AlertRules::new(&settings.alerts)?,
+            };
+            watch::watch(&mut history, options).await?;
+            // Stopped by Ctrl-C, after the lookups above were written.
+            if let Err(e) = store.put_resolved(history.cache.resolved()) {
+                log::warn!("Could not keep resolved lookups ({})", e);
+            }
+        }
+        Command::Events {
+            signature,
+            save_index,
+        } => {
+            for (i, event) in history.events_by_signature(&signature)? {
+                println!("Transaction {}: {}", i, event);
+            }
+            if let Some(path) = save_index {
+                history.events.save(&path)?;
+            }
+        }
+        Command::Profile { contract } => {
+            let contract = contract
+                .trim_start_matches("0x")
+                .parse()
+                .map_err(|_| anyhow!("Invalid contract address {}", contract))?;
+            println!("{}", history.contract_profile(&contract));
+        }
+        Command::Timeline { html } => {
+            let timeline = Timeline::new(&history);
+            println!("{}", timeline);
+            if let Some(path) = html {
+                fs::write(&path, timeline.to_html(&history.address))?;
+                println!("Wrote timeline to {}", path.display());
+            }
+        }
+        Command::Tax {
+            path,
+            prices,
+            currency,
+            method,
+        } => {
+            history
+                .price(&Prices::from_source(&prices, &currency)?)
+                .await?;
+            let report = TaxReport::new(&history, method);
+            println!("{}", report);
+            fs::write(&path, report.to_csv())?;
+            println!(
+                "Wrote {} disposals to {}",
+                report.disposals.len(),
+                path.display()
+            );
+        }
+        Command::Nametags { query, .. } => {
+            let found = search::search_history(&query, &mut history);
+            if found.is_empty() {
+                println!("No transactions with an address named \"{}\"", query);
+            }
+            for i in found {
+                println!(
+                    "Transaction {}: 0x{} ({})",
+                    i.index,
+                    hex::encode(i.matched.address),
+                    i.matched.names.join(", ")
+                );
+            }
+        }
+        // Handled before the pipeline runs.
+        Command::Serve { .. }
+        | Command::Tx { .. }
+        | Command::Block { .. }
+        | Command::Portfolio { .. }
+        | Command::Batch { .. }
+        | Command::Simulate { .. }
+        | Command::Craft { .. }
+        | Command::Sig { .. }
+        | Command::Db { .. }
+        | Command::Diff { .. }
+        | Command::Cache { .. } => {}
+        // Handled before contributing.
+        Command::Snapshot { .. } => {}
+    }
+    if !history.failures.is_empty() {
+        eprintln!("\n{}", Summary(&history.failures));
+    }
+    if !skipped.is_empty() {
+        eprintln!("\n{}", ChainSummary(&skipped));
+    }
+    if cancel.is_cancelled() {
+        return Ok(Outcome::Cancelled);
+    }
+    let outcome = Outcome::from_history(history.transactions.len(), &history.failures);
+    Ok(outcome.without_chains(&skipped))
+}
+
+/// Directories of the TODD databases from the options.
+fn database_dirs(cli: &Cli) -> ConfigBuilder {
+    let mut builder = Config::builder().directories(DirNature::Sample);
+    if let Some(dir) = &cli.appearances_dir {
+        builder = builder.appearances_dir(dir);
+    }
+    if let Some(dir) = &cli.signatures_dir {
+        builder = builder.signatures_dir(dir);
+    }
+    if let Some(dir) = &cli.nametags_dir {
+        builder = builder.nametags_dir(dir);
+    }
+    builder
+}
+
+/// Settings made once from the options and shared by the config of every chain.
+struct Shared {
+    labels: UserLabels,
+    decompiler: DecompilePool,
+    cancel: Cancellation,
+}
+
+/// Validates the config of a chain and applies the options that are the same
+/// on every chain.
+///
+/// The node, index and store of the chain are set on the builder. The chain
+/// name, Sourcify repository and trusted headers differ between chains, so
+/// are left to the caller.
+async fn chain_config(
+    builder: ConfigBuilder,
+    cli: &Cli,
+    shared: &Shared,
+) -> Result<ValidatedConfig> {
+    let mut validated = builder.validate().await?;
+    let config = &mut validated.config;
+    config.provider = PaddedProvider::wrap(config.provider.clone(), cli.privacy_decoys);
+    config.labels = shared.labels.clone();
+    config.decompiler = shared.decompiler.clone();
+    config.explorer = cli.explorer.clone();
+    config.verify_blooms = cli.verify_blooms;
+    config.verify_receipts = cli.verify_receipts;
+    config.incremental = cli.incremental;
+    config.interface_lookups = cli.interface_lookups;
+    config.drop_bytecode = cli.drop_bytecode || cli.light;
+    config.light = cli.light;
+    config.onchain_names = cli.onchain_names;
+    config.deployer_lookups = cli.deployer_lookups;
+    config.code_at = cli.code_at;
+    config.stages = cli.stages.clone();
+    config.cancel = shared.cancel.clone();
+    Ok(validated)
+}
+
+/// Builds and decodes the history of an address on the chain of the config.
+async fn explore(address: &str, config: Config, mode: Mode) -> Result<AddressHistory> {
+    let mut history = AddressHistory::new(address, config)?;
+    reuse_resolved(&mut history);
+    history.run(mode).await?;
+    Ok(history)
+}
+
+/// Adds the lookups resolved in earlier runs to the cache of a history.
+fn reuse_resolved(history: &mut AddressHistory) {
+    // Lookups are stored by address only, so are reused on mainnet only.
+    if history.config.chain == MAINNET {
+        match history.config.store.resolved() {
+            Ok(resolved) => history.cache.add_resolved(resolved),
+            Err(e) => log::warn!("Could not reuse resolved lookups ({})", e),
+        }
+    }
+}
+
 const SAMPLE_ADDRESS: [&str; 10] = [
     "0xde0b295669a9fd93d5f28d9ec85e40f4cb697bae", // an EF wallet
     "0x846be97d3bf1e3865f3caf55d749864d39e54cb9",
@@ -0,0 +1,303 @@
+//! Where watch mode alerts are sent (see `alerts`): printed, a desktop
+//! notification, or a phone through a webhook, ntfy or Gotify.
+//!
+//! Notifiers are set in the settings file. The message of an alert can be
+//! a template, with the names of its fields in braces: "rule", "block",
+//! "index", "hash" and "detail", and those of the rule that matched (for an
+//! event "event", "contract" and each decoded argument by name and as
+//! "arg0", "arg1", ...; for a transaction "to" and "value").
+//!
+//! ```toml
+//! [[alerts.notifiers]]
+//! kind = "ntfy"
+//! url = "https://ntfy.sh/my-wallet-alerts"
+//! template = "{event} of {value} to {spender} (block {block})"
+//! [[alerts.notifiers]]
+//! kind = "gotify"
+//! url = "https://gotify.example.com"
+//! token = "AbCdEf123"
+//! # JSON of the alert, or the template as plain text.
+//! [[alerts.notifiers]]
+//! kind = "webhook"
+//! url = "https://hooks.example.com/psr"
+//! ```
+//!
+//! Requests go through the API client, so any proxy or Tor is used.
+//! Notifiers can also be written outside this module, by implementing
+//! `Notifier`.
+use anyhow::{anyhow, bail, Result};
+use async_trait::async_trait;
+use reqwest::{header::CONTENT_TYPE, RequestBuilder, Url};
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+
+use crate::{alerts::Alert, apis};
+
+/// Gotify priority of alerts (high enough to sound on a phone).
+const GOTIFY_PRIORITY: u8 = 8;
+
+/// A notifier provided by this module.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum NotifierKind {
+    /// Prints the alert.
+    Stdout,
+    /// Posts the alert as JSON to a URL, or the template as text.
+    Webhook {
+        url: String,
+        template: Option<String>,
+    },
+    /// Publishes to an ntfy topic (e.g., "https://ntfy.sh/topic"), with an
+    /// access token if the server needs one.
+    Ntfy {
+        url: String,
+        token: Option<String>,
+        template: Option<String>,
+    },
+    /// Sends a message to a Gotify server, with the token of an application.
+    Gotify {
+        url: String,
+        token: String,
+        template: Option<String>,
+    },
+    /// Shows a desktop notification (notify-send, or osascript on macOS).
+    Desktop { template: Option<String> },
+}
+
+/// Told of each alert.
+#[async_trait]
+pub trait Notifier: Send + Sync {
+    async fn notify(&self, alert: &Alert) -> Result<()>;
+}
+
+/// Prints alerts.
+pub struct Stdout;
+
+/// Posts alerts to a URL.
+pub struct Webhook {
+    pub url: Url,
+    pub template: Option<String>,
+}
+
+/// Publishes alerts to an ntfy topic.
+pub struct Ntfy {
+    pub url: Url,
+    pub token: Option<String>,
+    pub template: Option<String>,
+}
+
+/// Sends alerts to a Gotify server.
+pub struct Gotify {
+    /// The message endpoint of the server.
+    pub url: Url,
+    pub token: String,
+    pub template: Option<String>,
+}
+
+/// Shows alerts as desktop notifications.
+pub struct Desktop {
+    pub template: Option<String>,
+}
+
+impl NotifierKind {
+    /// The notifier, if its settings are valid.
+    pub fn notifier(&self) -> Result<Box<dyn Notifier>> {
+        let parse = |url: &str| Url::parse(url).map_err(|e| anyhow!("Invalid URL {} ({})", url, e));
+        Ok(match self.clone() {
+            NotifierKind::Stdout => Box::new(Stdout),
+            NotifierKind::Webhook { url, template } => Box::new(Webhook {
+                url: parse(&url)?,
+                template,
+            }),
+            NotifierKind::Ntfy {
+                url,
+                token,
+                template,
+            } => Box::new(Ntfy {
+                url: parse(&url)?,
+                token,
+                template,
+            }),
+            NotifierKind::Gotify {
+                url,
+                token,
+                template,
+            } => Box::new(Gotify {
+                url: parse(&format!("{}/message", url.trim_end_matches('/')))?,
+                token,
+                template,
+            }),
+            NotifierKind::Desktop { template } => Box::new(Desktop { template }),
+        })
+    }
+}
+
+/// The template with each "{field}" of the alert filled in. Unknown fields
+/// are left as they are.
+pub fn fill(template: &str, alert: &Alert) -> String {
+    let mut text = String::new();
+    let mut rest = template;
+    while let Some(start) = rest.find('{') {
+        let Some(length) = rest[start..].find('}') else {
+            break;
+        };
+        text.push_str(&rest[..start]);
+        let placeholder = &rest[start..=start + length];
+        let name = &placeholder[1..placeholder.len() - 1];
+        match alert.field(name) {
+            Some(value) => text.push_str(&value),
+            None => text.push_str(placeholder),
+        }
+        rest = &rest[start + length + 1..];
+    }
+    text.push_str(rest);
+    text
+}
+
+/// The text of an alert: the template filled in, or the detail of the alert.
+fn message(alert: &Alert, template: &Option<String>) -> String {
+    match template {
+        Some(template) => fill(template, alert),
+        None => alert.detail.clone(),
+    }
+}
+
+/// Sends a request, counting it in the API usage.
+async fn send(request: RequestBuilder, url: &Url) -> Result<()> {
+    let response = request.send().await;
+    apis::record_request(url, 0);
+    response?.error_for_status()?;
+    Ok(())
+}
+
+#[async_trait]
+impl Notifier for Stdout {
+    async fn notify(&self, alert: &Alert) -> Result<()> {
+        println!("\n{}", alert);
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl Notifier for Webhook {
+    async fn notify(&self, alert: &Alert) -> Result<()> {
+        let request = apis::client().post(self.url.clone());
+        let request = match &self.template {
+            Some(template) => request
+                .header(CONTENT_TYPE, "text/plain; charset=utf-8")
+                .body(fill(template, alert)),
+            None => request
+                .header(CONTENT_TYPE, "application/json")
+                .body(serde_json::to_string(alert)?),
+        };
+        send(request, &self.url).await
+    }
+}
+
+#[async_trait]
+impl Notifier for Ntfy {
+    async fn notify(&self, alert: &Alert) -> Result<()> {
+        let mut request = apis::client()
+            .post(self.url.clone())
+            .header("Title", format!("psr: {}", alert.rule))
+            .header("Tags", "rotating_light")
+            .body(message(alert, &self.template));
+        if let Some(token) = &self.token {
+            request = request.bearer_auth(token);
+        }
+        send(request, &self.url).await
+    }
+}
+
+#[async_trait]
+impl Notifier for Gotify {
+    async fn notify(&self, alert: &Alert) -> Result<()> {
+        let body = json!({
+            "title": format!("psr: {}", alert.rule),
+            "message": message(alert, &self.template),
+            "priority": GOTIFY_PRIORITY,
+        });
+        let request = apis::client()
+            .post(self.url.clone())
+            .header("X-Gotify-Key", &self.token)
+            .header(CONTENT_TYPE, "application/json")
+            .body(body.to_string());
+        send(request, &self.url).await
+    }
+}
+
+#[async_trait]
+impl Notifier for Desktop {
+    async fn notify(&self, alert: &Alert) -> Result<()> {
+        let (title, text) = (
+            format!("psr: {}", alert.rule),
+            message(alert, &self.template),
+        );
+        let mut command = match cfg!(target_os = "macos") {
+            true => {
+                let mut command = tokio::process::Command::new("osascript");
+                let script = format!("display notification {:?} with title {:?}", text, title);
+                command.arg("-e").arg(script);
+                command
+            }
+            false => {
+                let mut command = tokio::process::Command::new("notify-send");
+                command.arg(title).arg(text);
+                command
+            }
+        };
+        let status = command.status().await?;
+        if !status.success() {
+            bail!("The notification command failed ({})", status);
+        }
+        Ok(())
+    }
+}
+
+#[test]
+fn templates_filled_from_alerts() {
+    use std::collections::BTreeMap;
+
+    use web3::types::H256;
+
+    let alert = Alert {
+        rule: String::from("Approval event"),
+        block: 17_000_000,
+        index: 3,
+        hash: Some(H256::repeat_byte(0xab)),
+        detail: String::from("Approval(address,address,uint256)"),
+        fields: BTreeMap::from([
+            (String::from("event"), String::from("Approval")),
+            (String::from("spender"), String::from("0x1111")),
+        ]),
+    };
+    let template = "{event} to {spender} in {block}:{index} {unknown} {";
+    assert_eq!(
+        fill(template, &alert),
+        "Approval to 0x1111 in 17000000:3 {unknown} {"
+    );
+    assert!(fill("{hash}", &alert).starts_with("0xabab"));
+    assert_eq!(message(&alert, &None), alert.detail);
+
+    let settings = r#"
+        [[notifiers]]
+        kind = "ntfy"
+        url = "https://ntfy.sh/alerts"
+        [[notifiers]]
+        kind = "gotify"
+        url = "https://gotify.example.com/"
+        token = "secret"
+        template = "{detail}"
+        [[notifiers]]
+        kind = "desktop"
+    "#;
+    let settings: crate::alerts::AlertSettings = toml::from_str(settings).unwrap();
+    assert_eq!(settings.notifiers.len(), 3);
+    assert!(settings.notifiers.iter().all(|n| n.notifier().is_ok()));
+    let invalid = NotifierKind::Ntfy {
+        url: String::from("ntfy.sh/alerts"),
+        token: None,
+        template: None,
+    };
+    assert!(invalid.notifier().is_err());
+}
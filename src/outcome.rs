@@ -0,0 +1,232 @@
+//! How a run ended, for scripts that wrap the binary.
+//!
+//! Failures for individual transactions are recorded and the run continues,
+//! so that the rest of the history is still produced. Failures that affect
+//! every transaction (the index cannot be read, no node can be reached) stop
+//! the run. The exit code distinguishes these cases.
+use std::{fmt::Display, process::ExitCode};
+
+use anyhow::Result;
+use min_know::specs::address_appearance_index::AAIAppearanceTx;
+use serde::{Deserialize, Serialize};
+
+use crate::{data::Appearance, rpc::Unreachable};
+
+/// Process exit status.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Outcome {
+    /// Every stage completed for every transaction.
+    Success = 0,
+    /// An error not covered by another outcome (e.g., invalid arguments).
+    Failure = 1,
+    /// The address has no appearances in the index, or the index could not be read.
+    IndexMiss = 2,
+    /// No RPC endpoint could be reached.
+    RpcUnreachable = 3,
+    /// Output was produced, but some transactions are missing data (or
+    /// some chains are missing from a report of several).
+    PartialSuccess = 4,
+    /// Stopped early by Ctrl-C, with the output produced so far.
+    Cancelled = 130,
+}
+
+/// A chain left out of a report of several chains, and why.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ChainFailure {
+    /// Name of the chain (e.g., "optimism").
+    pub chain: String,
+    pub error: String,
+}
+
+/// Marker for errors reading the appearance index.
+#[derive(Debug, Clone, Copy)]
+pub struct IndexMiss;
+
+/// A pipeline stage that can fail for a single transaction.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
+pub enum Stage {
+    TransactionData,
+    Receipt,
+    Timestamp,
+    Events,
+    BlobData,
+    Verification,
+    Price,
+}
+
+/// A stage that failed for one transaction.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
+pub struct StageFailure {
+    pub stage: Stage,
+    #[serde(with = "Appearance")]
+    pub location: AAIAppearanceTx,
+    pub error: String,
+}
+
+impl Outcome {
+    /// Classifies an error that stopped the run.
+    pub fn from_error(error: &anyhow::Error) -> Self {
+        if error.downcast_ref::<Unreachable>().is_some() {
+            Outcome::RpcUnreachable
+        } else if error.downcast_ref::<IndexMiss>().is_some() {
+            Outcome::IndexMiss
+        } else {
+            Outcome::Failure
+        }
+    }
+    /// Outcome of a run that completed.
+    pub fn from_history(transaction_count: usize, failures: &[StageFailure]) -> Self {
+        if transaction_count == 0 {
+            Outcome::IndexMiss
+        } else if failures.is_empty() {
+            Outcome::Success
+        } else {
+            Outcome::PartialSuccess
+        }
+    }
+    /// Outcome of a report of several chains with some left out: a complete
+    /// run is only a partial success.
+    pub fn without_chains(self, skipped: &[ChainFailure]) -> Self {
+        match self {
+            Outcome::Success if !skipped.is_empty() => Outcome::PartialSuccess,
+            outcome => outcome,
+        }
+    }
+    /// Outcome of runs for several addresses: the shared outcome if all
+    /// agree, otherwise a partial success.
+    pub fn combine(outcomes: &[Outcome]) -> Self {
+        match outcomes.split_first() {
+            Some((first, rest)) if rest.iter().all(|o| o == first) => *first,
+            Some(_) => Outcome::PartialSuccess,
+            None => Outcome::Success,
+        }
+    }
+}
+
+impl From<Outcome> for ExitCode {
+    fn from(outcome: Outcome) -> Self {
+        ExitCode::from(outcome as u8)
+    }
+}
+
+/// Records a failed request for a transaction unless it is fatal.
+///
+/// Returns the value on success, None if the failure was recorded, or the
+/// error if no node could be reached (so every other request would fail too).
+pub fn tolerate<T>(
+    result: Result<T>,
+    stage: Stage,
+    location: &AAIAppearanceTx,
+    failures: &mut Vec<StageFailure>,
+) -> Result<Option<T>> {
+    match result {
+        Ok(value) => Ok(Some(value)),
+        Err(e) if e.downcast_ref::<Unreachable>().is_some() => Err(e),
+        Err(e) => {
+            log::error!(
+                "{} failed for transaction {}:{} ({:#})",
+                stage,
+                location.block,
+                location.index,
+                e
+            );
+            failures.push(StageFailure {
+                stage,
+                location: location.clone(),
+                error: format!("{:#}", e),
+            });
+            Ok(None)
+        }
+    }
+}
+
+/// Lists the failures, for the end of the output.
+pub struct Summary<'a>(pub &'a [StageFailure]);
+
+/// Lists the chains left out of a report, for the end of the output.
+pub struct ChainSummary<'a>(pub &'a [ChainFailure]);
+
+impl Display for Stage {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let name = match self {
+            Stage::TransactionData => "Transaction data",
+            Stage::Receipt => "Receipt",
+            Stage::Timestamp => "Timestamp",
+            Stage::Events => "Event decoding",
+            Stage::BlobData => "Blob data",
+            Stage::Verification => "Verification",
+            Stage::Price => "Price",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+impl Display for IndexMiss {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Could not read the address appearance index")
+    }
+}
+
+impl Display for Summary<'_> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Errors: {}", self.0.len())?;
+        for failure in self.0 {
+            write!(
+                f,
+                "\n\tBlock {} index {}: {} failed ({})",
+                failure.location.block, failure.location.index, failure.stage, failure.error
+            )?;
+        }
+        Ok(())
+    }
+}
+
+impl Display for ChainSummary<'_> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Chains left out: {}", self.0.len())?;
+        for failure in self.0 {
+            write!(f, "\n\t{} ({})", failure.chain, failure.error)?;
+        }
+        Ok(())
+    }
+}
+
+#[test]
+fn exit_codes() {
+    let unreachable = anyhow::anyhow!("connection refused").context(Unreachable);
+    assert_eq!(Outcome::from_error(&unreachable), Outcome::RpcUnreachable);
+    let index = anyhow::anyhow!("missing chapter").context(IndexMiss);
+    assert_eq!(Outcome::from_error(&index), Outcome::IndexMiss);
+    assert_eq!(Outcome::from_history(0, &[]), Outcome::IndexMiss);
+
+    let location = AAIAppearanceTx { block: 1, index: 2 };
+    let mut failures = vec![];
+    let recorded = tolerate::<()>(
+        Err(anyhow::anyhow!("No receipt")),
+        Stage::Receipt,
+        &location,
+        &mut failures,
+    );
+    assert!(matches!(recorded, Ok(None)));
+    assert!(tolerate::<()>(Err(unreachable), Stage::Receipt, &location, &mut failures).is_err());
+    assert_eq!(Outcome::from_history(1, &failures), Outcome::PartialSuccess);
+
+    let all = [Outcome::Success, Outcome::Success];
+    assert_eq!(Outcome::combine(&all), Outcome::Success);
+    let some = [Outcome::Success, Outcome::IndexMiss];
+    assert_eq!(Outcome::combine(&some), Outcome::PartialSuccess);
+
+    let skipped = [ChainFailure {
+        chain: String::from("optimism"),
+        error: String::from("No RPC endpoint could be reached"),
+    }];
+    assert_eq!(Outcome::Success.without_chains(&[]), Outcome::Success);
+    assert_eq!(
+        Outcome::Success.without_chains(&skipped),
+        Outcome::PartialSuccess
+    );
+    assert_eq!(
+        Outcome::IndexMiss.without_chains(&skipped),
+        Outcome::IndexMiss
+    );
+}
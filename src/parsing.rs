@@ -1,7 +1,17 @@
+use std::fmt::Display;
+
 use anyhow::{anyhow, Result};
 use eip55::checksum;
+use log::{debug, warn};
 use serde_json::Value;
-use web3::types::H160;
+use web3::{signing::keccak256, types::H160};
+
+/// Gets the JSON ABI from contract metadata, logging a summary of it.
+pub fn abi_from_metadata(metadata: Value) -> Result<String> {
+    let abi = metadata["output"]["abi"].to_string();
+    debug!("{}", summary_of_abi_from_json(metadata)?);
+    Ok(abi)
+}
 
 /// Gets a human readable summary of contract metadata.
 ///
@@ -46,6 +56,115 @@ pub fn h160_to_string(address: &H160) -> String {
     hex::encode(address)
 }
 
+/// Reasons a user-provided address cannot be used.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum AddressError {
+    /// Address is not 40 hex characters long (ignoring the "0x" prefix).
+    InvalidLength(usize),
+    /// Address contains characters that are not hex.
+    InvalidHex(String),
+}
+
+impl Display for AddressError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            AddressError::InvalidLength(len) => {
+                write!(f, "Address must have 40 hex characters, found {}", len)
+            }
+            AddressError::InvalidHex(a) => write!(f, "Address contains non-hex characters: {}", a),
+        }
+    }
+}
+
+impl std::error::Error for AddressError {}
+
+/// Checks that an address is well formed and returns it as "0x" prefixed lowercase hex.
+///
+/// Mixed-case addresses are checked against EIP-55 and, if a chain id is provided,
+/// EIP-1191. A failed checksum is only warned about because the address may
+/// still be intended.
+pub fn validate_address(address: &str, chain_id: Option<u64>) -> Result<String, AddressError> {
+    let stripped = address
+        .strip_prefix("0x")
+        .or_else(|| address.strip_prefix("0X"))
+        .unwrap_or(address);
+    if stripped.len() != 40 {
+        return Err(AddressError::InvalidLength(stripped.len()));
+    }
+    if !stripped.chars().all(|c| c.is_ascii_hexdigit()) {
+        return Err(AddressError::InvalidHex(address.to_string()));
+    }
+    let lower = stripped.to_ascii_lowercase();
+    let is_mixed_case = stripped != lower && stripped != stripped.to_ascii_uppercase();
+    if is_mixed_case {
+        let eip55_ok = stripped == checksum_with_chain(&lower, None);
+        let eip1191_ok = chain_id
+            .map(|id| stripped == checksum_with_chain(&lower, Some(id)))
+            .unwrap_or(false);
+        if !eip55_ok && !eip1191_ok {
+            warn!("Address {} has mixed case but fails checksum.", address);
+        }
+    }
+    Ok(format!("0x{}", lower))
+}
+
+/// Applies EIP-55 (or EIP-1191 if a chain id is provided) capitalisation.
+///
+/// Input: lowercase hex without the "0x" prefix. Output has no prefix.
+fn checksum_with_chain(lower: &str, chain_id: Option<u64>) -> String {
+    let preimage = match chain_id {
+        Some(id) => format!("{}0x{}", id, lower),
+        None => lower.to_string(),
+    };
+    let hash = hex::encode(keccak256(preimage.as_bytes()));
+    lower
+        .chars()
+        .zip(hash.chars())
+        .map(|(c, h)| match h.to_digit(16) {
+            Some(n) if n >= 8 => c.to_ascii_uppercase(),
+            _ => c,
+        })
+        .collect()
+}
+
+#[test]
+fn address_validation() {
+    let valid = "0x5aAeb6053F3E94C9b9A09f33669435E7Ef1BeAed";
+    assert_eq!(
+        validate_address(valid, None).unwrap(),
+        "0x5aaeb6053f3e94c9b9a09f33669435e7ef1beaed"
+    );
+    assert_eq!(
+        checksum_with_chain("5aaeb6053f3e94c9b9a09f33669435e7ef1beaed", None),
+        valid.trim_start_matches("0x")
+    );
+    assert_eq!(
+        validate_address("0x1234", None),
+        Err(AddressError::InvalidLength(4))
+    );
+    assert!(matches!(
+        validate_address("0xzzaeb6053f3e94c9b9a09f33669435e7ef1beaed", None),
+        Err(AddressError::InvalidHex(_))
+    ));
+    assert_eq!(
+        validate_address("0X5AAEB6053F3E94C9B9A09F33669435E7EF1BEAED", None).unwrap(),
+        "0x5aaeb6053f3e94c9b9a09f33669435e7ef1beaed"
+    );
+    assert!(matches!(
+        validate_address("0x0x5aaeb6053f3e94c9b9a09f33669435e7ef1bea", None),
+        Err(AddressError::InvalidHex(_))
+    ));
+}
+
+#[test]
+fn eip1191_checksum() {
+    // Test vector from EIP-1191 (RSK mainnet, chain id 30).
+    assert_eq!(
+        checksum_with_chain("5aaeb6053f3e94c9b9a09f33669435e7ef1beaed", Some(30)),
+        "5aaEB6053f3e94c9b9a09f33669435E7ef1bEAeD"
+    );
+}
+
 #[test]
 fn parse_metadata() {
     let metadata_str = r#"
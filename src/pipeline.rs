@@ -0,0 +1,299 @@
+//! The steps that build a history, as stages that can be run in any order.
+//!
+//! Each stage fills in part of the transactions of a history (e.g., the
+//! receipts, or the decoded events). A `Pipeline` runs a list of stages in
+//! order. The usual list is `Pipeline::after_appearances`, and `--stages`
+//! selects another (e.g., to stop before decoding, or to run a stage alone
+//! on a loaded history).
+//!
+//! Stages can also be written outside this module, by implementing `Stage`.
+use std::fmt::Display;
+
+use anyhow::Result;
+use async_trait::async_trait;
+use clap::ValueEnum;
+use log::debug;
+use serde::{Deserialize, Serialize};
+
+use crate::history::{AddressHistory, Mode};
+
+/// Transactions processed by a run of the pipeline (see
+/// `get_transaction_data`). None processes all of them.
+const RUN_CAP: Option<u32> = Some(1);
+
+/// A step that adds to a history.
+#[async_trait]
+pub trait Stage: Send + Sync {
+    /// Short name used in logs.
+    fn name(&self) -> &str;
+    /// Adds to the history. An error stops the pipeline.
+    async fn apply(&self, history: &mut AddressHistory) -> Result<()>;
+}
+
+/// A stage provided by this module, by name.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, Serialize, ValueEnum)]
+pub enum StageKind {
+    /// Transactions of the address, from the appearance index.
+    Appearances,
+    /// Transaction data (eth_getTransactionByBlockNumberAndIndex).
+    TxData,
+    /// Receipts (eth_getTransactionReceipt).
+    Receipts,
+    /// Block timestamps and base fees (eth_getBlockByNumber).
+    Timestamps,
+    /// Event signatures, contracts and nametags.
+    DecodeLogs,
+    /// Functions and arguments of the calldata.
+    DecodeCalldata,
+    /// Actions and spam, from the decoded events and calldata.
+    Semantics,
+    /// Logs blooms and receipts roots, if enabled in the config.
+    Verify,
+    /// Logs dropped from the decoded receipts, if enabled in the config.
+    Prune,
+}
+
+/// Looks up the appearances of the address.
+pub struct Appearances;
+
+/// Gets the data of each transaction.
+pub struct TxData {
+    pub cap: Option<u32>,
+}
+
+/// Gets the receipt of each transaction.
+pub struct Receipts {
+    pub cap: Option<u32>,
+}
+
+/// Gets the timestamp and base fee of the block of each transaction.
+pub struct Timestamps {
+    pub cap: Option<u32>,
+}
+
+/// Decodes the events of each receipt.
+pub struct DecodeLogs {
+    pub cap: Option<u32>,
+    pub mode: Mode,
+}
+
+/// Decodes the calldata of each transaction.
+pub struct DecodeCalldata {
+    pub cap: Option<u32>,
+    pub mode: Mode,
+}
+
+/// Interprets each transaction and flags spam.
+pub struct Semantics;
+
+/// Checks the receipts, as enabled by `verify_blooms` and `verify_receipts`.
+pub struct Verify;
+
+/// Drops the decoded logs from the receipts, as enabled by `light`.
+pub struct Prune;
+
+#[async_trait]
+impl Stage for Appearances {
+    fn name(&self) -> &str {
+        "appearances"
+    }
+    async fn apply(&self, history: &mut AddressHistory) -> Result<()> {
+        history.get_transaction_ids()?;
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl Stage for TxData {
+    fn name(&self) -> &str {
+        "transaction data"
+    }
+    async fn apply(&self, history: &mut AddressHistory) -> Result<()> {
+        history.get_transaction_data(self.cap).await?;
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl Stage for Receipts {
+    fn name(&self) -> &str {
+        "receipts"
+    }
+    async fn apply(&self, history: &mut AddressHistory) -> Result<()> {
+        history.get_receipts(self.cap).await?;
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl Stage for Timestamps {
+    fn name(&self) -> &str {
+        "timestamps"
+    }
+    async fn apply(&self, history: &mut AddressHistory) -> Result<()> {
+        history.get_timestamps(self.cap).await?;
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl Stage for DecodeLogs {
+    fn name(&self) -> &str {
+        "decode logs"
+    }
+    async fn apply(&self, history: &mut AddressHistory) -> Result<()> {
+        history.decode_logs(self.cap, self.mode).await?;
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl Stage for DecodeCalldata {
+    fn name(&self) -> &str {
+        "decode calldata"
+    }
+    async fn apply(&self, history: &mut AddressHistory) -> Result<()> {
+        history.decode_calldata(self.cap, self.mode).await;
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl Stage for Semantics {
+    fn name(&self) -> &str {
+        "semantics"
+    }
+    async fn apply(&self, history: &mut AddressHistory) -> Result<()> {
+        history.interpret().await.detect_spam();
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl Stage for Verify {
+    fn name(&self) -> &str {
+        "verify"
+    }
+    async fn apply(&self, history: &mut AddressHistory) -> Result<()> {
+        if history.config.verify_blooms {
+            history.verify_blooms();
+        }
+        if history.config.verify_receipts {
+            history.verify_receipts().await?;
+        }
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl Stage for Prune {
+    fn name(&self) -> &str {
+        "prune"
+    }
+    async fn apply(&self, history: &mut AddressHistory) -> Result<()> {
+        if history.config.light {
+            history.prune_receipts();
+        }
+        Ok(())
+    }
+}
+
+impl StageKind {
+    /// Every stage, in the order of a full run.
+    pub const ALL: [StageKind; 9] = [
+        StageKind::Appearances,
+        StageKind::TxData,
+        StageKind::Receipts,
+        StageKind::Timestamps,
+        StageKind::DecodeLogs,
+        StageKind::DecodeCalldata,
+        StageKind::Semantics,
+        StageKind::Verify,
+        StageKind::Prune,
+    ];
+
+    /// The stage, processing up to `cap` transactions.
+    pub fn stage(&self, cap: Option<u32>, mode: Mode) -> Box<dyn Stage> {
+        match self {
+            StageKind::Appearances => Box::new(Appearances),
+            StageKind::TxData => Box::new(TxData { cap }),
+            StageKind::Receipts => Box::new(Receipts { cap }),
+            StageKind::Timestamps => Box::new(Timestamps { cap }),
+            StageKind::DecodeLogs => Box::new(DecodeLogs { cap, mode }),
+            StageKind::DecodeCalldata => Box::new(DecodeCalldata { cap, mode }),
+            StageKind::Semantics => Box::new(Semantics),
+            StageKind::Verify => Box::new(Verify),
+            StageKind::Prune => Box::new(Prune),
+        }
+    }
+}
+
+/// Stages run one after another on a history.
+#[derive(Default)]
+pub struct Pipeline {
+    stages: Vec<Box<dyn Stage>>,
+}
+
+impl Pipeline {
+    /// Adds a stage after the others, e.g., one written outside this crate.
+    pub fn stage(mut self, stage: impl Stage + 'static) -> Self {
+        self.stages.push(Box::new(stage));
+        self
+    }
+    /// Stages provided by this module, in the order given.
+    pub fn of(kinds: &[StageKind], cap: Option<u32>, mode: Mode) -> Self {
+        Pipeline {
+            stages: kinds.iter().map(|kind| kind.stage(cap, mode)).collect(),
+        }
+    }
+    /// The stages after the appearances were looked up (e.g., by
+    /// `of_addresses`).
+    pub fn after_appearances(mode: Mode) -> Self {
+        Pipeline::of(&StageKind::ALL[1..], RUN_CAP, mode)
+    }
+    /// The stages after the transaction data, for a history that was given
+    /// its transactions (e.g., by `of_transaction`). All are processed.
+    pub fn after_transaction_data(mode: Mode) -> Self {
+        Pipeline::of(&StageKind::ALL[2..], None, mode)
+    }
+    /// Stages selected by the user, processing as many transactions as a
+    /// full run.
+    pub fn selected(kinds: &[StageKind], mode: Mode) -> Self {
+        Pipeline::of(kinds, RUN_CAP, mode)
+    }
+    /// Names of the stages, in order.
+    pub fn names(&self) -> Vec<&str> {
+        self.stages.iter().map(|stage| stage.name()).collect()
+    }
+    /// Runs each stage in order, stopping at the first error.
+    pub async fn run(&self, history: &mut AddressHistory) -> Result<()> {
+        for stage in &self.stages {
+            debug!("Running stage: {}", stage.name());
+            stage.apply(history).await?;
+        }
+        Ok(())
+    }
+}
+
+impl Display for Pipeline {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.names().join(" -> "))
+    }
+}
+
+#[test]
+fn stages_run_in_order() {
+    let full = Pipeline::after_appearances(Mode::AvoidApis);
+    assert_eq!(full.names().len(), 8);
+    assert_eq!(full.names()[0], "transaction data");
+    assert_eq!(
+        Pipeline::after_transaction_data(Mode::AvoidApis).names()[0],
+        "receipts"
+    );
+
+    let kinds = [StageKind::Receipts, StageKind::Semantics];
+    let pipeline = Pipeline::selected(&kinds, Mode::UseApis).stage(Verify);
+    assert_eq!(pipeline.to_string(), "receipts -> semantics -> verify");
+    let parsed = StageKind::from_str("decode-logs", true).unwrap();
+    assert_eq!(parsed, StageKind::DecodeLogs);
+}
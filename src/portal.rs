@@ -0,0 +1,71 @@
+//! Experimental access to chain data via a Portal Network node (e.g., trin).
+//!
+//! A Portal node serves block headers and bodies from the history network
+//! over the `eth_getBlockByNumber` JSON-RPC method. Transactions are read out
+//! of block bodies. Receipts, contract code, logs and the mempool are not
+//! available, so the pipeline skips the stages that need them.
+use anyhow::{anyhow, Result};
+use async_trait::async_trait;
+use serde_json::Value;
+use web3::{
+    transports::Http,
+    types::{Block, BlockId, BlockNumber, Transaction, TransactionId, H256},
+    Transport, Web3,
+};
+
+use crate::provider::{unsupported, Capability, ChainDataProvider, Sourced};
+
+/// A Portal Network node that exposes the trin JSON-RPC subset.
+#[derive(Debug, Clone)]
+pub struct PortalNode {
+    url: String,
+    web3: Web3<Http>,
+}
+
+impl PortalNode {
+    pub fn new(url: &str) -> Result<Self> {
+        Ok(PortalNode {
+            url: url.to_owned(),
+            web3: Web3::new(Http::new(url)?),
+        })
+    }
+}
+
+#[async_trait]
+impl ChainDataProvider for PortalNode {
+    fn name(&self) -> &str {
+        "Portal node"
+    }
+    fn supports(&self, capability: Capability) -> bool {
+        matches!(capability, Capability::Transactions | Capability::Blocks)
+    }
+    async fn request(&self, method: &str, params: Vec<Value>) -> Result<Sourced<Value>> {
+        let response = self.web3.transport().execute(method, params).await?;
+        Ok((response, self.url.to_owned()))
+    }
+    /// Only transactions located by block number and index can be found.
+    async fn transaction(&self, id: TransactionId) -> Result<Sourced<Option<Transaction>>> {
+        let TransactionId::Block(BlockId::Number(BlockNumber::Number(number)), index) = id else {
+            return Err(unsupported(self.name(), Capability::Transactions)
+                .context("Transactions can only be found by block number and index."));
+        };
+        let (block, url) = self.block_with_txs(number.as_u64()).await?;
+        let tx = block.and_then(|b| b.transactions.into_iter().nth(index.as_usize()));
+        Ok((tx, url))
+    }
+    async fn block(&self, number: u64) -> Result<Sourced<Option<Block<H256>>>> {
+        let id = BlockId::Number(BlockNumber::Number(number.into()));
+        let block = self.web3.eth().block(id).await?;
+        Ok((block, self.url.to_owned()))
+    }
+    async fn block_with_txs(&self, number: u64) -> Result<Sourced<Option<Block<Transaction>>>> {
+        let id = BlockId::Number(BlockNumber::Number(number.into()));
+        let block = self
+            .web3
+            .eth()
+            .block_with_txs(id)
+            .await
+            .map_err(|e| anyhow!("Portal node could not provide block {} ({})", number, e))?;
+        Ok((block, self.url.to_owned()))
+    }
+}
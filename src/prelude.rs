@@ -0,0 +1,21 @@
+//! The stable API of the decoding core.
+//!
+//! ```
+//! use psr::prelude::*;
+//!
+//! let calldata = hex::decode(format!("a9059cbb{:0>64}{:0>64}", "11".repeat(20), "2a")).unwrap();
+//! let signature = String::from("transfer(address,uint256)");
+//! assert!(matches_signature(&signature, &hex::encode(&calldata[..4])));
+//! let call = CallShape::new(Some(signature), &calldata).decode(&calldata).unwrap();
+//! assert_eq!(call.params[1].value, "42");
+//! ```
+pub use crate::{
+    abi::{matches_signature, selector_of, topic_of},
+    contract::{
+        constructor_args, read_trailer, BytecodeMetadata, Compiler, MetadataSource, Trailer,
+    },
+    decode::{
+        decode_constructor, decode_return, encode_call, event_signatures, function_signatures,
+        CallShape, DecodedCall, DecodedParam, ParamKind,
+    },
+};
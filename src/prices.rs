@@ -0,0 +1,361 @@
+//! Values of transfers in a fiat currency, at the day of each transaction,
+//! for use in exports (e.g., for tax accounting).
+//!
+//! Prices come from a `PriceSource`:
+//! - `PriceFile`: a CSV file of daily prices.
+//! - `PriceApi`: a URL with `{asset}`, `{date}` and `{currency}` in place of
+//!   the asset, the day and the currency. The response is a number, or an
+//!   object with a "price" number. A 404 means no price is known.
+//!
+//! An asset is "ETH" or a token contract ("0x" prefixed hex). A token is
+//! also looked up by its symbol (the first label or nametag) if there is no
+//! price for its contract. Tokens whose decimals are not known (see
+//! `AddressHistory::token_decimals`) are not valued.
+use std::{
+    collections::HashMap,
+    fmt::Display,
+    fs,
+    ops::Deref,
+    path::Path,
+    sync::{Arc, Mutex},
+};
+
+use anyhow::{anyhow, bail, Context, Result};
+use async_trait::async_trait;
+use reqwest::{StatusCode, Url};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use web3::types::{H160, U256};
+
+use crate::{
+    abi::{data_word, topic_of},
+    apis,
+    data::TxInfo,
+    labels::UserLabels,
+    semantics::TRANSFER,
+    stats::date_from_unix,
+};
+
+/// A source of daily prices in one currency.
+#[async_trait]
+pub trait PriceSource: Send + Sync {
+    /// Short name used in logs and output.
+    fn name(&self) -> &str;
+    /// Currency of the prices (e.g., "USD").
+    fn currency(&self) -> &str;
+    /// Price of one unit of an asset on a day ("YYYY-MM-DD", UTC), if known.
+    async fn price(&self, asset: &str, date: &str) -> Result<Option<f64>>;
+}
+
+/// Shared handle to the price source used by the pipeline.
+#[derive(Clone)]
+pub struct Prices(Arc<dyn PriceSource>);
+
+/// Value of a transaction in a fiat currency.
+#[derive(Debug, Default, Clone, PartialEq, Deserialize, Serialize)]
+pub struct FiatValue {
+    pub currency: String,
+    /// Value of the ether sent, if priced.
+    pub value: Option<f64>,
+    /// Value of each event in order, for token transfers that were priced.
+    pub events: Vec<Option<f64>>,
+}
+
+/// Prices read from a file.
+///
+/// Each line is `date,asset,price` (e.g., `2024-01-31,ETH,2283.5`). Blank
+/// lines and lines starting with `#` are skipped.
+pub struct PriceFile {
+    name: String,
+    currency: String,
+    /// Price by lowercase asset and date.
+    prices: HashMap<(String, String), f64>,
+}
+
+/// Prices fetched from an API, each kept once fetched.
+pub struct PriceApi {
+    template: String,
+    currency: String,
+    client: reqwest::Client,
+    fetched: Mutex<HashMap<(String, String), Option<f64>>>,
+}
+
+impl Prices {
+    pub fn new(source: impl PriceSource + 'static) -> Self {
+        Prices(Arc::new(source))
+    }
+    /// An API for an http(s) URL, otherwise a file of prices.
+    pub fn from_source(source: &str, currency: &str) -> Result<Self> {
+        match source.starts_with("http://") || source.starts_with("https://") {
+            true => Ok(Prices::new(PriceApi::new(source, currency))),
+            false => Ok(Prices::new(PriceFile::load(Path::new(source), currency)?)),
+        }
+    }
+}
+
+impl PriceFile {
+    pub fn load(path: &Path, currency: &str) -> Result<Self> {
+        let text = fs::read_to_string(path)
+            .with_context(|| format!("Could not read prices {}", path.display()))?;
+        let prices = parse_prices(&text)
+            .with_context(|| format!("Invalid prices file {}", path.display()))?;
+        Ok(PriceFile {
+            name: path.display().to_string(),
+            currency: currency.to_string(),
+            prices,
+        })
+    }
+}
+
+impl PriceApi {
+    pub fn new(template: &str, currency: &str) -> Self {
+        PriceApi {
+            template: template.to_string(),
+            currency: currency.to_string(),
+            client: apis::client(),
+            fetched: Mutex::new(HashMap::new()),
+        }
+    }
+    async fn fetch(&self, asset: &str, date: &str) -> Result<Option<f64>> {
+        let url = self
+            .template
+            .replace("{asset}", asset)
+            .replace("{date}", date)
+            .replace("{currency}", &self.currency);
+        let url = Url::parse(&url).with_context(|| format!("Invalid price URL {}", url))?;
+        let response = match self.client.get(url.clone()).send().await {
+            Ok(response) => response,
+            Err(e) => {
+                apis::record_request(&url, 0);
+                return Err(e.into());
+            }
+        };
+        let status = response.status();
+        let bytes = response.bytes().await;
+        apis::record_request(&url, bytes.as_ref().map_or(0, |b| b.len()));
+        if status == StatusCode::NOT_FOUND {
+            apis::record_learned(&url, format!("no price of {} on {}", asset, date));
+            return Ok(None);
+        }
+        if !status.is_success() {
+            bail!("The price request failed ({}) for {}", status, url);
+        }
+        let body: Value = serde_json::from_slice(&bytes?)?;
+        apis::record_learned(&url, format!("price of {} on {}", asset, date));
+        body.as_f64()
+            .or_else(|| body.get("price").and_then(Value::as_f64))
+            .map(Some)
+            .ok_or_else(|| anyhow!("Expected a price from {}, got {}", url, body))
+    }
+}
+
+#[async_trait]
+impl PriceSource for PriceFile {
+    fn name(&self) -> &str {
+        &self.name
+    }
+    fn currency(&self) -> &str {
+        &self.currency
+    }
+    async fn price(&self, asset: &str, date: &str) -> Result<Option<f64>> {
+        let key = (asset.to_ascii_lowercase(), date.to_string());
+        Ok(self.prices.get(&key).copied())
+    }
+}
+
+#[async_trait]
+impl PriceSource for PriceApi {
+    fn name(&self) -> &str {
+        &self.template
+    }
+    fn currency(&self) -> &str {
+        &self.currency
+    }
+    async fn price(&self, asset: &str, date: &str) -> Result<Option<f64>> {
+        let key = (asset.to_string(), date.to_string());
+        if let Some(price) = self.fetched.lock().unwrap().get(&key) {
+            return Ok(*price);
+        }
+        let price = self.fetch(asset, date).await?;
+        self.fetched.lock().unwrap().insert(key, price);
+        Ok(price)
+    }
+}
+
+/// Value of the ether and tokens a transaction moved, at the day of its
+/// block. None without a timestamp.
+pub async fn value_of(
+    tx: &TxInfo,
+    source: &dyn PriceSource,
+    decimals: &HashMap<H160, u32>,
+    labels: &UserLabels,
+) -> Result<Option<FiatValue>> {
+    let (Some(timestamp), Some(desc)) = (tx.timestamp, &tx.description) else {
+        return Ok(None);
+    };
+    let date = date_from_unix(timestamp);
+    let value = match desc.value.is_zero() {
+        true => None,
+        false => {
+            let price = source.price("ETH", &date).await?;
+            price.map(|price| units(desc.value, 18) * price)
+        }
+    };
+    let transfer = topic_of(TRANSFER);
+    let mut events = vec![];
+    for event in tx.events.iter().flatten() {
+        let topics = &event.raw.topics;
+        let amount = match topics.len() == 3 && topics[0] == transfer {
+            true => data_word(&event.raw.data.0, 0),
+            false => None,
+        };
+        let token = event.raw.address;
+        let (Some(amount), Some(decimals)) = (amount, decimals.get(&token)) else {
+            events.push(None);
+            continue;
+        };
+        let symbol = labels
+            .merge(&token, event.nametags.clone())
+            .and_then(|names| names.into_iter().find(|n| !n.is_empty()));
+        let mut price = source
+            .price(&format!("0x{}", hex::encode(token)), &date)
+            .await?;
+        if let (None, Some(symbol)) = (price, &symbol) {
+            price = source.price(symbol, &date).await?;
+        }
+        events.push(price.map(|price| units(amount, *decimals) * price));
+    }
+    Ok(Some(FiatValue {
+        currency: source.currency().to_string(),
+        value,
+        events,
+    }))
+}
+
+/// An integer amount in whole units.
+pub fn units(amount: U256, decimals: u32) -> f64 {
+    let amount: f64 = amount.to_string().parse().unwrap_or_default();
+    amount / 10f64.powi(decimals as i32)
+}
+
+/// Reads `date,asset,price` lines.
+fn parse_prices(text: &str) -> Result<HashMap<(String, String), f64>> {
+    let mut prices = HashMap::new();
+    for (n, line) in text.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let fields: Vec<&str> = line.split(',').map(str::trim).collect();
+        let [date, asset, price] = fields[..] else {
+            return Err(anyhow!("Line {}: expected date,asset,price", n + 1));
+        };
+        let price = price.parse().with_context(|| format!("Line {}", n + 1))?;
+        prices.insert((asset.to_ascii_lowercase(), date.to_string()), price);
+    }
+    Ok(prices)
+}
+
+impl Deref for Prices {
+    type Target = dyn PriceSource;
+
+    fn deref(&self) -> &Self::Target {
+        self.0.as_ref()
+    }
+}
+
+impl std::fmt::Debug for Prices {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_tuple("Prices").field(&self.name()).finish()
+    }
+}
+
+impl PartialEq for Prices {
+    fn eq(&self, other: &Self) -> bool {
+        Arc::ptr_eq(&self.0, &other.0)
+    }
+}
+
+impl Display for Prices {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{} ({})", self.name(), self.currency())
+    }
+}
+
+#[tokio::test]
+async fn values_from_file() {
+    use web3::types::{Transaction, H256};
+
+    use crate::semantics::test_event;
+
+    let prices = PriceFile {
+        name: String::from("test"),
+        currency: String::from("EUR"),
+        prices: parse_prices(
+            "# date,asset,price\n\n2023-11-14,ETH,2000\n2023-11-14,usdc,0.9\n\
+            2023-11-14,0x2222222222222222222222222222222222222222,3.5\n",
+        )
+        .unwrap(),
+    };
+    assert!(parse_prices("2023-11-14,ETH").is_err());
+
+    let word = |value: U256| {
+        let mut word = [0u8; 32];
+        value.to_big_endian(&mut word);
+        word.to_vec()
+    };
+    let topics = [H256::zero(), H256::zero()];
+    let mut usdc = test_event(
+        "0x1111111111111111111111111111111111111111",
+        TRANSFER,
+        &topics,
+        word(U256::from(2_000_000u64)),
+    );
+    usdc.nametags = Some(vec![String::from("USDC")]);
+    let other = test_event(
+        "0x2222222222222222222222222222222222222222",
+        TRANSFER,
+        &topics,
+        word(U256::exp10(18) * 2),
+    );
+    let unknown = test_event(
+        "0x3333333333333333333333333333333333333333",
+        TRANSFER,
+        &topics,
+        word(U256::one()),
+    );
+    let tx = TxInfo {
+        // 2023-11-14 22:13:20 UTC
+        timestamp: Some(1_700_000_000),
+        description: Some(Transaction {
+            value: U256::exp10(17),
+            ..Default::default()
+        }),
+        events: Some(vec![usdc, other, unknown]),
+        ..Default::default()
+    };
+    let decimals = HashMap::from([(H160::repeat_byte(0x11), 6), (H160::repeat_byte(0x22), 18)]);
+    let fiat = value_of(&tx, &prices, &decimals, &UserLabels::default())
+        .await
+        .unwrap()
+        .unwrap();
+    assert_eq!(fiat.currency, "EUR");
+    assert_eq!(fiat.value, Some(200.0));
+    // 2 USDC (6 decimals) by symbol, then a token by its contract.
+    assert_eq!(fiat.events[0], Some(1.8));
+    assert_eq!(fiat.events[1], Some(7.0));
+    assert_eq!(fiat.events[2], None);
+    // Tokens with unknown decimals are not valued.
+    let fiat = value_of(&tx, &prices, &HashMap::new(), &UserLabels::default())
+        .await
+        .unwrap()
+        .unwrap();
+    assert_eq!(fiat.events, [None, None, None]);
+
+    let undated = TxInfo {
+        timestamp: None,
+        ..tx
+    };
+    let value = value_of(&undated, &prices, &decimals, &UserLabels::default()).await;
+    assert_eq!(value.unwrap(), None);
+}
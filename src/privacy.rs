@@ -0,0 +1,440 @@
+//! Privacy mode: decoy requests mixed in with those of a run, so that the
+//! node (e.g., a hosted RPC provider) cannot easily tell which transactions
+//! and addresses the user is interested in.
+//!
+//! Each transaction, receipt, block, code or deployer lookup is padded with a number of
+//! decoys of the same kind (see `--privacy-decoys`), some sent before it and
+//! the rest after:
+//! - Transactions (by hash, or by block and index, as the lookup was) and
+//!   receipts of other transactions seen in earlier blocks.
+//! - Blocks, and the receipts of blocks, near the last one looked up (or the
+//!   head block, before any block was).
+//! - Code of addresses seen in the decoy receipts.
+//!
+//! A decoy block is looked up first only if no transactions were seen yet,
+//! so decoy transactions and receipts are requested the same way as real
+//! ones.
+//!
+//! This is best effort. The decoys multiply the requests made, and a
+//! provider that sees every request can still correlate them (e.g., by
+//! timing, or because the real requests follow the appearances of one
+//! address). Running a local node is the only way to reveal nothing.
+use std::{
+    collections::hash_map::RandomState,
+    hash::{BuildHasher, Hasher},
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Mutex, PoisonError,
+    },
+};
+
+use anyhow::Result;
+use async_trait::async_trait;
+use log::debug;
+use serde_json::Value;
+use web3::{
+    api::BaseFilter,
+    transports::Http,
+    types::{
+        Block, BlockId, BlockNumber, Bytes, CallRequest, Filter, Log, Transaction, TransactionId,
+        TransactionReceipt, H160, H256, U256,
+    },
+    Web3,
+};
+
+use crate::provider::{Capability, ChainDataProvider, Provider, Sourced};
+
+/// Decoy blocks are at most this far from the last block looked up (twice
+/// as far below it near the head block).
+const SPREAD: u64 = 10_000;
+/// Addresses kept for decoy code lookups.
+const MAX_ADDRESSES: usize = 256;
+/// Transactions kept for decoy transaction and receipt lookups.
+const MAX_TRANSACTIONS: usize = 1024;
+
+/// A provider that pads each lookup with decoys.
+pub struct PaddedProvider {
+    inner: Provider,
+    /// Decoys per lookup.
+    decoys: u32,
+    /// Last block of a real lookup, near which decoys are made (0 if none yet).
+    last_block: AtomicU64,
+    /// Highest block known to exist, past which decoys are not made (0 until
+    /// the head block is first needed).
+    head: AtomicU64,
+    /// Addresses seen in decoy receipts.
+    addresses: Mutex<Vec<H160>>,
+    /// Block, index and hash of transactions seen in blocks.
+    transactions: Mutex<Vec<(u64, usize, H256)>>,
+}
+
+/// What a decoy looks up.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Decoy {
+    /// A transaction by block and index.
+    Transaction,
+    TransactionByHash,
+    Receipt,
+    BlockReceipts,
+    Block,
+    Code,
+}
+
+impl PaddedProvider {
+    /// Pads the lookups of a provider with a number of decoys each. Zero
+    /// keeps the provider as it is.
+    pub fn wrap(provider: Provider, decoys: u32) -> Provider {
+        if decoys == 0 {
+            return provider;
+        }
+        Provider::new(PaddedProvider {
+            inner: provider,
+            decoys,
+            last_block: AtomicU64::new(0),
+            head: AtomicU64::new(0),
+            addresses: Mutex::new(vec![]),
+            transactions: Mutex::new(vec![]),
+        })
+    }
+    /// Sends the decoys for a lookup, some before it and the rest after.
+    async fn padded<T>(
+        &self,
+        decoy: Decoy,
+        block: Option<u64>,
+        request: impl std::future::Future<Output = Result<T>> + Send,
+    ) -> Result<T> {
+        if let Some(block) = block {
+            self.last_block.store(block, Ordering::Relaxed);
+        }
+        let before = random() % (u64::from(self.decoys) + 1);
+        for _ in 0..before {
+            self.decoy(decoy).await;
+        }
+        let response = request.await;
+        for _ in before..u64::from(self.decoys) {
+            self.decoy(decoy).await;
+        }
+        response
+    }
+    /// Makes one decoy request. Failures are ignored.
+    async fn decoy(&self, decoy: Decoy) {
+        let result = match decoy {
+            Decoy::Transaction | Decoy::TransactionByHash | Decoy::Receipt => {
+                self.decoy_transaction(decoy).await
+            }
+            Decoy::BlockReceipts => match self.nearby_block().await {
+                Ok(number) => self.inner.block_receipts(number).await.map(|_| ()),
+                Err(e) => Err(e),
+            },
+            Decoy::Block => self.decoy_block().await,
+            Decoy::Code => self.decoy_code().await,
+        };
+        if let Err(e) = result {
+            debug!("Decoy {:?} lookup failed ({})", decoy, e);
+        }
+    }
+    /// Looks up a block near the last one looked up, remembering its
+    /// transactions.
+    async fn decoy_block(&self) -> Result<()> {
+        let number = self.nearby_block().await?;
+        if let (Some(block), _) = self.inner.block(number).await? {
+            self.remember_transactions(number, block.transactions.into_iter());
+        }
+        Ok(())
+    }
+    /// Looks up a transaction seen in an earlier block, or its receipt,
+    /// remembering the addresses in the receipt. A decoy block is looked up
+    /// first if no transactions were seen yet.
+    async fn decoy_transaction(&self, decoy: Decoy) -> Result<()> {
+        if pick(&self.transactions).is_none() {
+            self.decoy_block().await?;
+        }
+        let Some((number, index, hash)) = pick(&self.transactions) else {
+            return Ok(());
+        };
+        match decoy {
+            Decoy::Transaction => {
+                let id = TransactionId::Block(
+                    BlockId::Number(BlockNumber::Number(number.into())),
+                    index.into(),
+                );
+                self.inner.transaction(id).await?;
+            }
+            Decoy::TransactionByHash => {
+                self.inner.transaction(TransactionId::Hash(hash)).await?;
+            }
+            _ => {
+                if let (Some(receipt), _) = self.inner.receipt(hash).await? {
+                    let logged = receipt.logs.iter().map(|log| log.address);
+                    keep(
+                        &self.addresses,
+                        receipt.to.into_iter().chain(logged),
+                        MAX_ADDRESSES,
+                    );
+                }
+            }
+        }
+        Ok(())
+    }
+    /// Looks up the code of an address seen in a decoy receipt, or fetches
+    /// a decoy receipt if none was seen yet.
+    async fn decoy_code(&self) -> Result<()> {
+        match pick(&self.addresses) {
+            Some(address) => self.inner.code(address).await.map(|_| ()),
+            None => self.decoy_transaction(Decoy::Receipt).await,
+        }
+    }
+    /// A random block near the last one looked up, or near the head block
+    /// if none was. Decoys are never past the head block.
+    async fn nearby_block(&self) -> Result<u64> {
+        let last = self.last_block.load(Ordering::Relaxed);
+        let mut head = self.head.load(Ordering::Relaxed);
+        if head == 0 {
+            (head, _) = self.inner.head_block().await?;
+        }
+        // A real lookup past the head means the chain has grown since.
+        let head = head.max(last);
+        self.head.fetch_max(head, Ordering::Relaxed);
+        Ok(nearby(last, head, random()))
+    }
+    /// Remembers the transactions of a block for decoy lookups.
+    fn remember_transactions(&self, number: u64, hashes: impl Iterator<Item = H256>) {
+        let seen = hashes
+            .enumerate()
+            .map(|(index, hash)| (number, index, hash));
+        keep(&self.transactions, seen, MAX_TRANSACTIONS);
+    }
+}
+
+/// Adds items to those kept, replacing random ones once there are `max`.
+fn keep<T>(kept: &Mutex<Vec<T>>, seen: impl Iterator<Item = T>, max: usize) {
+    let mut kept = kept.lock().unwrap_or_else(PoisonError::into_inner);
+    for item in seen {
+        match kept.len() < max {
+            true => kept.push(item),
+            false => {
+                let slot = random() as usize % max;
+                kept[slot] = item;
+            }
+        }
+    }
+}
+
+/// A random item of those kept, if there are any.
+fn pick<T: Copy>(kept: &Mutex<Vec<T>>) -> Option<T> {
+    let kept = kept.lock().unwrap_or_else(PoisonError::into_inner);
+    match kept.is_empty() {
+        true => None,
+        false => Some(kept[random() as usize % kept.len()]),
+    }
+}
+
+/// A random number, from the randomly keyed hasher of the standard library.
+/// A block within `SPREAD` of the last block looked up (or of the head if
+/// none was). The range is moved down to end at the head, so that decoys are
+/// spread as evenly near the head as elsewhere.
+fn nearby(last: u64, head: u64, random: u64) -> u64 {
+    let centre = match last {
+        0 => head,
+        last => last,
+    };
+    let top = (centre + SPREAD).min(head).max(1);
+    let bottom = top.saturating_sub(2 * SPREAD).max(1);
+    bottom + random % (top - bottom + 1)
+}
+
+fn random() -> u64 {
+    RandomState::new().build_hasher().finish()
+}
+
+#[async_trait]
+impl ChainDataProvider for PaddedProvider {
+    fn name(&self) -> &str {
+        self.inner.name()
+    }
+    fn supports(&self, capability: Capability) -> bool {
+        self.inner.supports(capability)
+    }
+    async fn request(&self, method: &str, params: Vec<Value>) -> Result<Sourced<Value>> {
+        self.inner.request(method, params).await
+    }
+    async fn transaction(&self, id: TransactionId) -> Result<Sourced<Option<Transaction>>> {
+        let (decoy, block) = match &id {
+            TransactionId::Block(BlockId::Number(BlockNumber::Number(n)), _) => {
+                (Decoy::Transaction, Some(n.as_u64()))
+            }
+            TransactionId::Block(..) => (Decoy::Transaction, None),
+            TransactionId::Hash(_) => (Decoy::TransactionByHash, None),
+        };
+        let request = self.inner.transaction(id);
+        self.padded(decoy, block, request).await
+    }
+    async fn receipt(&self, hash: H256) -> Result<Sourced<Option<TransactionReceipt>>> {
+        self.padded(Decoy::Receipt, None, self.inner.receipt(hash))
+            .await
+    }
+    async fn block_receipts(
+        &self,
+        number: u64,
+    ) -> Result<Sourced<Option<Vec<TransactionReceipt>>>> {
+        let request = self.inner.block_receipts(number);
+        self.padded(Decoy::BlockReceipts, Some(number), request)
+            .await
+    }
+    async fn block(&self, number: u64) -> Result<Sourced<Option<Block<H256>>>> {
+        let response = self
+            .padded(Decoy::Block, Some(number), self.inner.block(number))
+            .await?;
+        if let (Some(block), _) = &response {
+            self.remember_transactions(number, block.transactions.iter().copied());
+        }
+        Ok(response)
+    }
+    async fn block_with_txs(&self, number: u64) -> Result<Sourced<Option<Block<Transaction>>>> {
+        let request = self.inner.block_with_txs(number);
+        let response = self.padded(Decoy::Block, Some(number), request).await?;
+        if let (Some(block), _) = &response {
+            self.remember_transactions(number, block.transactions.iter().map(|tx| tx.hash));
+        }
+        Ok(response)
+    }
+    async fn code(&self, address: H160) -> Result<Sourced<Bytes>> {
+        self.padded(Decoy::Code, None, self.inner.code(address))
+            .await
+    }
+    async fn code_at(&self, address: H160, block: u64) -> Result<Sourced<Bytes>> {
+        let request = self.inner.code_at(address, block);
+        self.padded(Decoy::Code, Some(block), request).await
+    }
+    async fn contract_call(&self, to: H160, data: Vec<u8>) -> Result<Sourced<Bytes>> {
+        self.inner.contract_call(to, data).await
+    }
+    async fn contract_creator(&self, address: H160) -> Result<Sourced<Option<(H160, H256)>>> {
+        let request = self.inner.contract_creator(address);
+        self.padded(Decoy::Code, None, request).await
+    }
+    async fn simulate_call(&self, request: CallRequest) -> Result<Sourced<Bytes>> {
+        self.inner.simulate_call(request).await
+    }
+    async fn estimate_gas(&self, request: CallRequest) -> Result<Sourced<U256>> {
+        self.inner.estimate_gas(request).await
+    }
+    async fn head_block(&self) -> Result<Sourced<u64>> {
+        self.inner.head_block().await
+    }
+    async fn logs(&self, filter: Filter) -> Result<Sourced<Vec<Log>>> {
+        self.inner.logs(filter).await
+    }
+    async fn pending_transactions_filter(
+        &self,
+    ) -> Result<Sourced<(Web3<Http>, BaseFilter<Http, H256>)>> {
+        self.inner.pending_transactions_filter().await
+    }
+}
+
+#[test]
+fn decoys_not_past_head() {
+    let head = 18_000_000;
+    for value in [0, 1, SPREAD, 2 * SPREAD, u64::MAX, random()] {
+        // Near the head, the range ends at the head.
+        for last in [0, head, head - 1] {
+            let block = nearby(last, head, value);
+            assert!((head - 2 * SPREAD..=head).contains(&block));
+        }
+        let block = nearby(15_000_000, head, value);
+        assert!((15_000_000 - SPREAD..=15_000_000 + SPREAD).contains(&block));
+        // Early blocks and an empty chain.
+        assert!((1..=2 * SPREAD).contains(&nearby(5, head, value)));
+        assert_eq!(nearby(0, 0, value), 1);
+    }
+}
+
+#[tokio::test]
+async fn lookups_padded_with_decoys() {
+    use std::sync::{atomic::AtomicUsize, Arc};
+
+    /// Serves blocks of two transactions, and counts receipts, blocks and
+    /// transactions looked up by hash.
+    struct Node {
+        receipts: Arc<AtomicUsize>,
+        blocks: Arc<AtomicUsize>,
+        by_hash: Arc<AtomicUsize>,
+    }
+
+    #[async_trait]
+    impl ChainDataProvider for Node {
+        fn name(&self) -> &str {
+            "test node"
+        }
+        fn supports(&self, _capability: Capability) -> bool {
+            true
+        }
+        async fn request(&self, _method: &str, _params: Vec<Value>) -> Result<Sourced<Value>> {
+            anyhow::bail!("no requests")
+        }
+        async fn transaction(&self, id: TransactionId) -> Result<Sourced<Option<Transaction>>> {
+            let TransactionId::Hash(hash) = id else {
+                anyhow::bail!("only by hash")
+            };
+            self.by_hash.fetch_add(1, Ordering::SeqCst);
+            let tx = Transaction {
+                hash,
+                ..Default::default()
+            };
+            Ok((Some(tx), String::from("node")))
+        }
+        async fn receipt(&self, hash: H256) -> Result<Sourced<Option<TransactionReceipt>>> {
+            self.receipts.fetch_add(1, Ordering::SeqCst);
+            let receipt = TransactionReceipt {
+                transaction_hash: hash,
+                to: Some(H160::from_low_u64_be(hash.to_low_u64_be())),
+                ..Default::default()
+            };
+            Ok((Some(receipt), String::from("node")))
+        }
+        async fn block(&self, number: u64) -> Result<Sourced<Option<Block<H256>>>> {
+            self.blocks.fetch_add(1, Ordering::SeqCst);
+            let block = Block {
+                number: Some(number.into()),
+                transactions: vec![H256::from_low_u64_be(7), H256::from_low_u64_be(8)],
+                ..Default::default()
+            };
+            Ok((Some(block), String::from("node")))
+        }
+        async fn head_block(&self) -> Result<Sourced<u64>> {
+            Ok((18_000_000, String::from("node")))
+        }
+    }
+
+    let count = || Arc::new(AtomicUsize::new(0));
+    let (receipts, blocks, by_hash) = (count(), count(), count());
+    let node = Provider::new(Node {
+        receipts: receipts.clone(),
+        blocks: blocks.clone(),
+        by_hash: by_hash.clone(),
+    });
+    assert_eq!(PaddedProvider::wrap(node.clone(), 0), node);
+    let padded = PaddedProvider::wrap(node, 3);
+
+    // Before any block lookup, decoys are near the head block. One decoy
+    // block is looked up for transactions, which all decoys then use.
+    let real = H256::from_low_u64_be(1);
+    let (receipt, _) = padded.receipt(real).await.unwrap();
+    assert_eq!(receipt.unwrap().transaction_hash, real);
+    assert_eq!(receipts.load(Ordering::SeqCst), 4);
+    assert_eq!(blocks.load(Ordering::SeqCst), 1);
+
+    let (tx, _) = padded.transaction(TransactionId::Hash(real)).await.unwrap();
+    assert_eq!(tx.unwrap().hash, real);
+    assert_eq!(by_hash.load(Ordering::SeqCst), 4);
+
+    let (block, _) = padded.block(15_000_000).await.unwrap();
+    assert_eq!(block.unwrap().number, Some(15_000_000.into()));
+    assert_eq!(blocks.load(Ordering::SeqCst), 5);
+
+    // Decoy receipts are not preceded by block lookups.
+    let (receipt, _) = padded.receipt(real).await.unwrap();
+    assert_eq!(receipt.unwrap().transaction_hash, real);
+    assert_eq!(receipts.load(Ordering::SeqCst), 8);
+    assert_eq!(blocks.load(Ordering::SeqCst), 5);
+}
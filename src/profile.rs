@@ -0,0 +1,283 @@
+//! What an address did with one contract across its history: when it first
+//! and last interacted, how often it called it, the events the contract
+//! emitted, the value exchanged and the functions used.
+//!
+//! A transaction is an interaction if the address called the contract, the
+//! contract emitted an event in it, or a token moved between the two.
+use std::{collections::BTreeMap, fmt::Display};
+
+use web3::types::{H160, U256};
+
+use crate::{
+    abi::{data_word, topic_address, topic_of},
+    data::TxInfo,
+    history::{AddressHistory, VisitNote},
+    semantics::{ether_amount, short_address, Amount, TRANSFER},
+    stats::date_from_unix,
+};
+
+/// The interactions of an address with a contract.
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct ContractProfile {
+    pub contract: H160,
+    /// Labels and nametags of the contract.
+    pub names: Vec<String>,
+    /// Block and timestamp (if fetched) of the first interaction.
+    pub first: Option<(u32, Option<u64>)>,
+    /// Block and timestamp (if fetched) of the last interaction.
+    pub last: Option<(u32, Option<u64>)>,
+    /// Transactions in which the contract took part.
+    pub interactions: usize,
+    /// Transactions the address sent to the contract.
+    pub calls: usize,
+    /// Events emitted by the contract.
+    pub events: usize,
+    /// Ether sent to the contract with successful calls (wei).
+    pub ether_sent: U256,
+    /// ERC-20 amounts sent to and received from the contract, by token.
+    pub tokens: BTreeMap<H160, (U256, U256)>,
+    /// Functions called (text signature, or selector), with their counts.
+    pub methods: BTreeMap<String, usize>,
+    /// Names of the tokens, by contract (first label or nametag).
+    pub token_names: BTreeMap<H160, String>,
+    /// Decimals of the tokens whose decimals were read, by contract.
+    pub token_decimals: BTreeMap<H160, u32>,
+}
+
+impl ContractProfile {
+    /// Summarises the transactions of the history with a contract, naming
+    /// it and the tokens with the labels and nametags already looked up.
+    /// Token amounts use the decimals already read.
+    pub fn new(history: &AddressHistory, contract: H160) -> Self {
+        let names = |address: &H160| {
+            let tags = match history.cache.nametags.get(&hex::encode(address)) {
+                Some((VisitNote::PriorSuccess, tags)) => Some(tags.clone()),
+                _ => None,
+            };
+            let names = history.config.labels.merge(address, tags);
+            names
+                .unwrap_or_default()
+                .into_iter()
+                .filter(|n| !n.is_empty())
+                .collect()
+        };
+        let owner = history
+            .address
+            .trim_start_matches("0x")
+            .parse()
+            .unwrap_or_default();
+        let decimals = |token: &H160| history.cache.known_decimals(token);
+        ContractProfile::of_transactions(owner, contract, &history.transactions, names, decimals)
+    }
+    fn of_transactions(
+        owner: H160,
+        contract: H160,
+        transactions: &[TxInfo],
+        names: impl Fn(&H160) -> Vec<String>,
+        decimals: impl Fn(&H160) -> Option<u32>,
+    ) -> Self {
+        let mut profile = ContractProfile {
+            contract,
+            names: names(&contract),
+            ..Default::default()
+        };
+        let transfer = topic_of(TRANSFER);
+        for tx in transactions {
+            let failed = tx
+                .receipt
+                .as_ref()
+                .and_then(|r| r.status)
+                .is_some_and(|s| s.is_zero());
+            let mut involved = false;
+            if let Some(desc) = tx.description.as_ref().filter(|d| d.to == Some(contract)) {
+                involved = true;
+                if desc.from == Some(owner) {
+                    profile.calls += 1;
+                    if !failed {
+                        profile.ether_sent += desc.value;
+                    }
+                    let method = match &tx.call {
+                        Some(call) => call.signature.clone().unwrap_or(call.selector.clone()),
+                        None if desc.input.0.len() >= 4 => hex::encode(&desc.input.0[..4]),
+                        None => String::from("(ether transfer)"),
+                    };
+                    *profile.methods.entry(method).or_default() += 1;
+                }
+            }
+            for event in tx.events.iter().flatten() {
+                let topics = &event.raw.topics;
+                if event.raw.address == contract {
+                    involved = true;
+                    profile.events += 1;
+                }
+                if topics.len() != 3 || topics[0] != transfer {
+                    continue;
+                }
+                let Some(amount) = data_word(&event.raw.data.0, 0) else {
+                    continue;
+                };
+                let (from, to) = (topic_address(&topics[1]), topic_address(&topics[2]));
+                let exchanged = profile.tokens.entry(event.raw.address);
+                match (from, to) {
+                    (f, t) if f == owner && t == contract => exchanged.or_default().0 += amount,
+                    (f, t) if f == contract && t == owner => exchanged.or_default().1 += amount,
+                    _ => continue,
+                }
+                involved = true;
+            }
+            if !involved {
+                continue;
+            }
+            profile.interactions += 1;
+            let at = (tx.location.block, tx.timestamp);
+            if profile.first.is_none_or(|(block, _)| at.0 < block) {
+                profile.first = Some(at);
+            }
+            if profile.last.is_none_or(|(block, _)| at.0 > block) {
+                profile.last = Some(at);
+            }
+        }
+        for token in profile.tokens.keys() {
+            if let Some(name) = names(token).into_iter().next() {
+                profile.token_names.insert(*token, name);
+            }
+            if let Some(decimals) = decimals(token) {
+                profile.token_decimals.insert(*token, decimals);
+            }
+        }
+        profile
+    }
+}
+
+impl Display for ContractProfile {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Contract 0x{}", hex::encode(self.contract))?;
+        if !self.names.is_empty() {
+            write!(f, " |{}|", self.names.join("|"))?;
+        }
+        let at = |at: Option<(u32, Option<u64>)>| match at {
+            Some((block, Some(ts))) => format!("block {} ({})", block, date_from_unix(ts)),
+            Some((block, None)) => format!("block {}", block),
+            None => String::from("None"),
+        };
+        write!(f, "\n\tFirst interaction: {}", at(self.first))?;
+        write!(f, "\n\tLast interaction: {}", at(self.last))?;
+        write!(
+            f,
+            "\n\tTransactions: {} ({} calls sent, {} events emitted)",
+            self.interactions, self.calls, self.events
+        )?;
+        write!(f, "\n\tEther sent: {}", ether_amount(self.ether_sent))?;
+        for (token, (sent, received)) in &self.tokens {
+            let symbol = match self.token_names.get(token) {
+                Some(name) => name.to_owned(),
+                None => short_address(token),
+            };
+            let Some(decimals) = self.token_decimals.get(token) else {
+                write!(f, "\n\t{} sent and received (decimals unknown)", symbol)?;
+                continue;
+            };
+            let amount = |value: U256| Amount {
+                value,
+                decimals: *decimals,
+                symbol: symbol.clone(),
+            };
+            write!(
+                f,
+                "\n\t{} sent, {} received",
+                amount(*sent),
+                amount(*received)
+            )?;
+        }
+        if !self.methods.is_empty() {
+            write!(f, "\n\tFunctions called:")?;
+            let mut methods: Vec<(&String, &usize)> = self.methods.iter().collect();
+            methods.sort_by(|a, b| b.1.cmp(a.1).then_with(|| a.0.cmp(b.0)));
+            for (method, count) in methods {
+                write!(f, "\n\t\t{} x{}", method, count)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+#[test]
+fn interactions_with_one_contract() {
+    use min_know::specs::address_appearance_index::AAIAppearanceTx;
+    use web3::types::{Bytes, Transaction, TransactionReceipt, H256};
+
+    use crate::semantics::test_event;
+
+    let owner = H160::repeat_byte(0xaa);
+    let router = H160::repeat_byte(0xbb);
+    let token = H160::repeat_byte(0xcc);
+    let word = |a: H160| H256::from(a);
+    let amount = |n: u64| {
+        let mut data = [0u8; 32];
+        U256::from(n).to_big_endian(&mut data);
+        data.to_vec()
+    };
+    let tx = |block: u32, to: H160, value: u64, input: &str, status: u64| TxInfo {
+        location: AAIAppearanceTx { block, index: 0 },
+        description: Some(Transaction {
+            from: Some(owner),
+            to: Some(to),
+            value: value.into(),
+            input: Bytes(hex::decode(input).unwrap()),
+            ..Default::default()
+        }),
+        receipt: Some(TransactionReceipt {
+            status: Some(status.into()),
+            ..Default::default()
+        }),
+        ..Default::default()
+    };
+    let token_hex = format!("0x{}", hex::encode(token));
+    let router_hex = format!("0x{}", hex::encode(router));
+    let mut swap = tx(20, router, 5, "7ff36ab5", 1);
+    swap.timestamp = Some(1_709_251_200);
+    swap.events = Some(vec![
+        test_event(&router_hex, "Swap()", &[], vec![]),
+        test_event(
+            &token_hex,
+            TRANSFER,
+            &[word(router), word(owner)],
+            amount(3_000_000),
+        ),
+    ]);
+    let mut approve = tx(10, token, 0, "095ea7b3", 1);
+    approve.events = Some(vec![test_event(
+        &token_hex,
+        TRANSFER,
+        &[word(owner), word(router)],
+        amount(2_500_000),
+    )]);
+    let failed = tx(30, router, 7, "7ff36ab5", 0);
+    let unrelated = tx(40, token, 0, "", 1);
+    let transactions = [swap, approve, failed, unrelated];
+    let names = |address: &H160| match *address == token {
+        true => vec![String::from("USDC")],
+        false => vec![],
+    };
+
+    let decimals = |address: &H160| Some(6).filter(|_| *address == token);
+    let profile = ContractProfile::of_transactions(owner, router, &transactions, names, decimals);
+    assert_eq!(profile.interactions, 3);
+    assert_eq!(profile.calls, 2);
+    assert_eq!(profile.events, 1);
+    assert_eq!(profile.first, Some((10, None)));
+    assert_eq!(profile.last, Some((30, None)));
+    // The failed call sent no ether.
+    assert_eq!(profile.ether_sent, U256::from(5));
+    let exchanged = (U256::from(2_500_000), U256::from(3_000_000));
+    assert_eq!(profile.tokens[&token], exchanged);
+    assert_eq!(profile.methods["7ff36ab5"], 2);
+    let text = profile.to_string();
+    assert!(text.contains("\n\t2.5 USDC sent, 3 USDC received"));
+    assert!(text.ends_with("\n\tFunctions called:\n\t\t7ff36ab5 x2"));
+    // Without the decimals of the token its amounts are left out.
+    let unknown = ContractProfile::of_transactions(owner, router, &transactions, names, |_| None);
+    assert!(unknown
+        .to_string()
+        .contains("\n\tUSDC sent and received (decimals unknown)"));
+}
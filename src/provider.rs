@@ -0,0 +1,189 @@
+//! Access to chain data, independent of the kind of node that serves it.
+//!
+//! A full node (or several, see `RpcPool`) supports every method used by the
+//! pipeline. A Portal Network node only serves a subset, so stages that need
+//! unsupported methods are skipped and the output is less detailed.
+use std::{fmt::Display, ops::Deref, sync::Arc};
+
+use anyhow::{anyhow, Result};
+use async_trait::async_trait;
+use serde_json::Value;
+use web3::{
+    api::BaseFilter,
+    transports::Http,
+    types::{
+        Block, Bytes, CallRequest, Filter, Log, Transaction, TransactionId, TransactionReceipt,
+        H160, H256, U256,
+    },
+    Web3,
+};
+
+use crate::rpc::RpcPool;
+
+/// A response and the endpoint URL that provided it.
+pub type Sourced<T> = (T, String);
+
+/// Groups of node methods used by the pipeline.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Capability {
+    /// eth_getTransactionByBlockNumberAndIndex (or equivalent).
+    Transactions,
+    /// eth_getTransactionReceipt.
+    Receipts,
+    /// eth_getBlockReceipts, for every receipt of a block in one request.
+    BlockReceipts,
+    /// eth_getBlockByNumber.
+    Blocks,
+    /// eth_getCode.
+    ContractCode,
+    /// eth_blockNumber and eth_getLogs, needed to follow new blocks.
+    Logs,
+    /// eth_newPendingTransactionFilter.
+    PendingTransactions,
+    /// trace_transaction, for calls made within a transaction.
+    Traces,
+    /// eth_call, for read-only contract functions.
+    Calls,
+    /// ots_getContractCreator (Otterscan API, e.g., Erigon and Reth), for
+    /// who deployed a contract.
+    Creators,
+}
+
+/// A source of chain data.
+///
+/// Methods default to returning an error, so an implementation only needs to
+/// provide what its node supports and report that via `supports`.
+#[async_trait]
+pub trait ChainDataProvider: Send + Sync {
+    /// Short name used in logs and output.
+    fn name(&self) -> &str;
+    /// Whether the node serves the methods for a capability.
+    fn supports(&self, capability: Capability) -> bool;
+    /// Sends an arbitrary JSON-RPC request (e.g., to probe for a method).
+    async fn request(&self, method: &str, params: Vec<Value>) -> Result<Sourced<Value>>;
+    /// A transaction by its location.
+    async fn transaction(&self, _id: TransactionId) -> Result<Sourced<Option<Transaction>>> {
+        Err(unsupported(self.name(), Capability::Transactions))
+    }
+    /// The receipt for a transaction hash.
+    async fn receipt(&self, _hash: H256) -> Result<Sourced<Option<TransactionReceipt>>> {
+        Err(unsupported(self.name(), Capability::Receipts))
+    }
+    /// Every receipt of a block, in the order of its transactions.
+    async fn block_receipts(
+        &self,
+        _number: u64,
+    ) -> Result<Sourced<Option<Vec<TransactionReceipt>>>> {
+        Err(unsupported(self.name(), Capability::BlockReceipts))
+    }
+    /// A block with transaction hashes only.
+    async fn block(&self, _number: u64) -> Result<Sourced<Option<Block<H256>>>> {
+        Err(unsupported(self.name(), Capability::Blocks))
+    }
+    /// A block with full transactions.
+    async fn block_with_txs(&self, _number: u64) -> Result<Sourced<Option<Block<Transaction>>>> {
+        Err(unsupported(self.name(), Capability::Blocks))
+    }
+    /// Runtime bytecode of a contract at the latest block.
+    async fn code(&self, _address: H160) -> Result<Sourced<Bytes>> {
+        Err(unsupported(self.name(), Capability::ContractCode))
+    }
+    /// Runtime bytecode of a contract at a block. Nodes that are not archive
+    /// nodes may not have the state of old blocks.
+    async fn code_at(&self, _address: H160, _block: u64) -> Result<Sourced<Bytes>> {
+        Err(unsupported(self.name(), Capability::ContractCode))
+    }
+    /// Result of calling a read-only contract function at the latest block.
+    async fn contract_call(&self, _to: H160, _data: Vec<u8>) -> Result<Sourced<Bytes>> {
+        Err(unsupported(self.name(), Capability::Calls))
+    }
+    /// Result of a call with a sender and ether value (eth_call) at the
+    /// latest block, without sending a transaction.
+    async fn simulate_call(&self, _request: CallRequest) -> Result<Sourced<Bytes>> {
+        Err(unsupported(self.name(), Capability::Calls))
+    }
+    /// Gas a transaction would use if sent at the latest block (eth_estimateGas).
+    async fn estimate_gas(&self, _request: CallRequest) -> Result<Sourced<U256>> {
+        Err(unsupported(self.name(), Capability::Calls))
+    }
+    /// Most recent block number.
+    /// The address that deployed a contract (an account or a factory) and
+    /// the deploying transaction. None if the address is not a contract.
+    async fn contract_creator(&self, _address: H160) -> Result<Sourced<Option<(H160, H256)>>> {
+        Err(unsupported(self.name(), Capability::Creators))
+    }
+    async fn head_block(&self) -> Result<Sourced<u64>> {
+        Err(unsupported(self.name(), Capability::Logs))
+    }
+    /// Logs matching a filter.
+    async fn logs(&self, _filter: Filter) -> Result<Sourced<Vec<Log>>> {
+        Err(unsupported(self.name(), Capability::Logs))
+    }
+    /// Filter for pending transactions, with the client that created it.
+    ///
+    /// Filters live on one node, so the same client must be used to poll it.
+    async fn pending_transactions_filter(
+        &self,
+    ) -> Result<Sourced<(Web3<Http>, BaseFilter<Http, H256>)>> {
+        Err(unsupported(self.name(), Capability::PendingTransactions))
+    }
+}
+
+/// Shared handle to the chain data provider used by the pipeline.
+#[derive(Clone)]
+pub struct Provider(Arc<dyn ChainDataProvider>);
+
+impl Provider {
+    pub fn new(provider: impl ChainDataProvider + 'static) -> Self {
+        Provider(Arc::new(provider))
+    }
+}
+
+impl Deref for Provider {
+    type Target = dyn ChainDataProvider;
+
+    fn deref(&self) -> &Self::Target {
+        self.0.as_ref()
+    }
+}
+
+impl Default for Provider {
+    fn default() -> Self {
+        Provider::new(RpcPool::default())
+    }
+}
+
+impl std::fmt::Debug for Provider {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_tuple("Provider").field(&self.name()).finish()
+    }
+}
+
+impl PartialEq for Provider {
+    fn eq(&self, other: &Self) -> bool {
+        Arc::ptr_eq(&self.0, &other.0)
+    }
+}
+
+impl Display for Capability {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let methods = match self {
+            Capability::Transactions => "eth_getTransactionByBlockNumberAndIndex",
+            Capability::Receipts => "eth_getTransactionReceipt",
+            Capability::BlockReceipts => "eth_getBlockReceipts",
+            Capability::Blocks => "eth_getBlockByNumber",
+            Capability::ContractCode => "eth_getCode",
+            Capability::Logs => "eth_blockNumber/eth_getLogs",
+            Capability::PendingTransactions => "eth_newPendingTransactionFilter",
+            Capability::Traces => "trace_transaction",
+            Capability::Calls => "eth_call",
+            Capability::Creators => "ots_getContractCreator",
+        };
+        write!(f, "{}", methods)
+    }
+}
+
+/// Error for a request the provider cannot serve.
+pub fn unsupported(name: &str, capability: Capability) -> anyhow::Error {
+    anyhow!("{} is not supported by {}.", capability, name)
+}
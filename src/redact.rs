@@ -0,0 +1,345 @@
+//! Pseudonyms in place of addresses, so that exports can be shared without
+//! revealing whose history they are.
+//!
+//! The address being explored becomes "Wallet-A". Optionally, every other
+//! address it dealt with becomes "Counterparty-1", "Counterparty-2" and so
+//! on, numbered in order of first appearance so the same history always gets
+//! the same names. Contracts that emitted events (tokens, exchanges) keep
+//! their address, as they are public and say what a transaction did.
+//!
+//! Addresses are also replaced where text (e.g., the action of a
+//! transaction) shows them in short form ("0xabcd…1234") or by a label
+//! supplied by the user.
+//!
+//! Transaction hashes would lead straight back to the address, so they
+//! become "Tx-1", "Tx-2" and so on. A block and index (or an exact time, or
+//! a blob hash) would too, so blocks are rounded down to a multiple of
+//! 10,000, times to the day, and indices, confirmations and blob hashes are
+//! left out. Amounts are kept, so a determined reader may still find a
+//! transaction on chain by searching the blocks around it.
+use std::collections::{HashMap, HashSet};
+
+use clap::ValueEnum;
+use web3::types::{H160, H256};
+
+use crate::{
+    abi::padded_address, data::TxInfo, history::AddressHistory, labels::UserLabels,
+    semantics::short_address,
+};
+
+/// Pseudonym of the address being explored.
+const OWNER: &str = "Wallet-A";
+/// Blocks are rounded down to a multiple of this.
+const BLOCK_ROUNDING: u32 = 10_000;
+/// Times are rounded down to the day.
+const TIME_ROUNDING: u64 = 86_400;
+
+/// Which addresses are replaced with pseudonyms.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum Redaction {
+    /// Only the address being explored.
+    Owner,
+    /// The address being explored and every counterparty.
+    All,
+}
+
+/// Pseudonyms of addresses and transactions (none by default).
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct Pseudonyms {
+    addresses: HashMap<H160, String>,
+    transactions: HashMap<H256, String>,
+    /// Short forms and labels of the addresses with pseudonyms, with the
+    /// pseudonym of each, longest first.
+    forms: Vec<(String, String)>,
+}
+
+impl Pseudonyms {
+    pub fn new(history: &AddressHistory, redaction: Redaction) -> Self {
+        let owner = history
+            .address
+            .trim_start_matches("0x")
+            .parse()
+            .unwrap_or_default();
+        let labels = &history.config.labels;
+        Pseudonyms::of_transactions(owner, &history.transactions, labels, redaction)
+    }
+    fn of_transactions(
+        owner: H160,
+        transactions: &[TxInfo],
+        labels: &UserLabels,
+        redaction: Redaction,
+    ) -> Self {
+        let mut names = Pseudonyms::default();
+        names.addresses.insert(owner, String::from(OWNER));
+        for (n, tx) in transactions.iter().enumerate() {
+            if let Some(desc) = &tx.description {
+                names
+                    .transactions
+                    .insert(desc.hash, format!("Tx-{}", n + 1));
+            }
+        }
+        if redaction == Redaction::All {
+            names.add_counterparties(transactions);
+        }
+        for (address, name) in &names.addresses {
+            names.forms.push((short_address(address), name.clone()));
+            for label in labels.get(address).unwrap_or_default() {
+                names.forms.push((label.clone(), name.clone()));
+            }
+        }
+        names
+            .forms
+            .sort_by(|a, b| b.0.len().cmp(&a.0.len()).then_with(|| a.0.cmp(&b.0)));
+        names
+    }
+    /// Numbers every address other than contracts that emitted events.
+    fn add_counterparties(&mut self, transactions: &[TxInfo]) {
+        let emitters: HashSet<H160> = transactions
+            .iter()
+            .flat_map(|tx| tx.events.iter().flatten())
+            .map(|e| e.raw.address)
+            .collect();
+        let mut count = 0;
+        for address in transactions.iter().flat_map(addresses_in) {
+            if emitters.contains(&address) || self.addresses.contains_key(&address) {
+                continue;
+            }
+            count += 1;
+            self.addresses
+                .insert(address, format!("Counterparty-{}", count));
+        }
+    }
+    /// Whether any pseudonyms are used.
+    pub fn is_active(&self) -> bool {
+        !self.addresses.is_empty()
+    }
+    pub fn pseudonym(&self, address: &H160) -> Option<&str> {
+        self.addresses.get(address).map(|n| n.as_str())
+    }
+    /// The pseudonym of an address, or the address as "0x" prefixed hex.
+    pub fn address(&self, address: &H160) -> String {
+        match self.pseudonym(address) {
+            Some(name) => name.to_string(),
+            None => format!("0x{}", hex::encode(address)),
+        }
+    }
+    /// The block of a transaction, rounded down if pseudonyms are used.
+    pub fn block(&self, block: u32) -> u32 {
+        match self.is_active() {
+            true => block - block % BLOCK_ROUNDING,
+            false => block,
+        }
+    }
+    /// A value that locates a transaction on chain (e.g., its index in the
+    /// block, its confirmations or its blob hashes), unless pseudonyms are
+    /// used.
+    pub fn locating<T>(&self, value: T) -> Option<T> {
+        (!self.is_active()).then_some(value)
+    }
+    /// A unix timestamp, rounded down to the day if pseudonyms are used.
+    pub fn timestamp(&self, timestamp: u64) -> u64 {
+        match self.is_active() {
+            true => timestamp - timestamp % TIME_ROUNDING,
+            false => timestamp,
+        }
+    }
+    /// The pseudonym of a transaction, or its hash as "0x" prefixed hex.
+    pub fn tx(&self, hash: &H256) -> String {
+        match self.transactions.get(hash) {
+            Some(name) => name.clone(),
+            None => format!("0x{}", hex::encode(hash)),
+        }
+    }
+    /// Replaces addresses in "0x" prefixed hex within some text, and the
+    /// short forms and labels of addresses with pseudonyms.
+    ///
+    /// An address is replaced on its own (20 bytes) or as a 32 byte word
+    /// (left padded). In longer data, each word holding an address is
+    /// replaced with the pseudonym in brackets.
+    pub fn text(&self, text: &str) -> String {
+        if !self.is_active() {
+            return text.to_string();
+        }
+        let mut text = text.to_string();
+        for (form, name) in &self.forms {
+            text = text.replace(form, name);
+        }
+        let mut result = String::with_capacity(text.len());
+        let mut rest = text.as_str();
+        while let Some(start) = rest.find("0x") {
+            result.push_str(&rest[..start]);
+            let digits = &rest[start + 2..];
+            let end = digits
+                .find(|c: char| !c.is_ascii_hexdigit())
+                .unwrap_or(digits.len());
+            result.push_str(&self.hex(&digits[..end]));
+            rest = &digits[end..];
+        }
+        result.push_str(rest);
+        result
+    }
+    /// Replaces addresses in hex digits (without "0x").
+    fn hex(&self, digits: &str) -> String {
+        let padded = |word: &str| {
+            let (padding, address) = word.split_at(24);
+            match padding.bytes().all(|b| b == b'0') {
+                true => self.lookup(address),
+                false => None,
+            }
+        };
+        match digits.len() {
+            40 => self.lookup(digits),
+            64 => padded(digits),
+            n if n > 0 && n % 64 == 0 => {
+                let words: Vec<String> = (0..n / 64)
+                    .map(|i| &digits[i * 64..(i + 1) * 64])
+                    .map(|w| padded(w).map_or_else(|| w.to_string(), |p| format!("[{}]", p)))
+                    .collect();
+                Some(format!("0x{}", words.concat()))
+            }
+            _ => None,
+        }
+        .unwrap_or_else(|| format!("0x{}", digits))
+    }
+    fn lookup(&self, digits: &str) -> Option<String> {
+        let address: H160 = digits.parse().ok()?;
+        self.pseudonym(&address).map(|p| p.to_string())
+    }
+}
+
+/// Addresses in a transaction, in order: the sender and recipient, then
+/// event topics and data words that hold an address.
+fn addresses_in(tx: &TxInfo) -> Vec<H160> {
+    let mut addresses = vec![];
+    if let Some(desc) = &tx.description {
+        addresses.extend(desc.from);
+        addresses.extend(desc.to);
+    }
+    for event in tx.events.iter().flatten() {
+        let words = event.raw.data.0.chunks_exact(32);
+        let topics = event.raw.topics.iter().skip(1).map(|t| t.as_bytes());
+        addresses.extend(topics.chain(words).filter_map(padded_address));
+    }
+    addresses
+}
+
+#[test]
+fn stable_pseudonyms() {
+    use web3::types::Transaction;
+
+    use crate::semantics::{test_event, TRANSFER};
+
+    let owner = H160::repeat_byte(0xaa);
+    let friend = H160::repeat_byte(0xbb);
+    let shop = H160::repeat_byte(0xcc);
+    let token = "0x1111111111111111111111111111111111111111";
+    let tx = |hash: u64, from: H160, to: H160, events| TxInfo {
+        description: Some(Transaction {
+            hash: H256::from_low_u64_be(hash),
+            from: Some(from),
+            to: Some(to),
+            ..Default::default()
+        }),
+        events: Some(events),
+        ..Default::default()
+    };
+    let transfer = test_event(
+        token,
+        TRANSFER,
+        &[H256::from(owner), H256::from(shop)],
+        vec![0; 32],
+    );
+    let transactions = vec![
+        tx(1, friend, owner, vec![]),
+        tx(2, owner, token.parse().unwrap(), vec![transfer]),
+    ];
+
+    let none = UserLabels::default();
+    let names = Pseudonyms::of_transactions(owner, &transactions, &none, Redaction::Owner);
+    assert_eq!(names.address(&owner), "Wallet-A");
+    assert_eq!(names.address(&friend), format!("0x{}", "bb".repeat(20)));
+    assert_eq!(names.tx(&H256::from_low_u64_be(2)), "Tx-2");
+
+    let names = Pseudonyms::of_transactions(owner, &transactions, &none, Redaction::All);
+    assert_eq!(names.address(&friend), "Counterparty-1");
+    assert_eq!(names.address(&shop), "Counterparty-2");
+    // Contracts that emitted events keep their address.
+    assert_eq!(names.address(&token.parse().unwrap()), token);
+
+    let text = format!(
+        "from 0x{} to 0x{:0>64}, data 0x{:0>64}{:0>64}",
+        "aa".repeat(20),
+        "cc".repeat(20),
+        "2a",
+        "bb".repeat(20)
+    );
+    assert_eq!(
+        names.text(&text),
+        format!(
+            "from Wallet-A to Counterparty-2, data 0x{:0>64}[Counterparty-1]",
+            "2a"
+        )
+    );
+    assert_eq!(Pseudonyms::default().text(&text), text);
+
+    // Nothing that locates a transaction on chain is kept.
+    assert_eq!(names.block(17_034_870), 17_030_000);
+    assert_eq!(names.locating(42), None);
+    assert_eq!(names.timestamp(1_700_000_000), 1_699_920_000);
+    let none = Pseudonyms::default();
+    assert_eq!(none.block(17_034_870), 17_034_870);
+    assert_eq!(none.locating(42), Some(42));
+}
+
+#[test]
+fn counterparties_in_actions() {
+    use web3::types::Transaction;
+
+    use crate::semantics::{test_event, RecognizerRegistry, TRANSFER};
+
+    let owner = H160::repeat_byte(0xaa);
+    let (friend, shop) = (H160::repeat_byte(0xbb), H160::repeat_byte(0xcc));
+    let path = std::env::temp_dir().join(format!("psr-redact-labels-{}.csv", std::process::id()));
+    std::fs::write(&path, format!("0x{},Alice's wallet\n", "bb".repeat(20))).unwrap();
+    let labels = UserLabels::load(&[&path]);
+    std::fs::remove_file(&path).unwrap();
+    let labels = labels.unwrap();
+
+    let transfer = |to: H160| {
+        test_event(
+            "0x1111111111111111111111111111111111111111",
+            TRANSFER,
+            &[H256::from(owner), H256::from(to)],
+            vec![0; 32],
+        )
+    };
+    let tx = |events| TxInfo {
+        description: Some(Transaction {
+            from: Some(owner),
+            ..Default::default()
+        }),
+        events: Some(events),
+        ..Default::default()
+    };
+    let transactions = vec![tx(vec![transfer(friend)]), tx(vec![transfer(shop)])];
+    let names = Pseudonyms::of_transactions(owner, &transactions, &labels, Redaction::All);
+    let action = |tx: &TxInfo| {
+        let owner = format!("0x{}", hex::encode(owner));
+        let action =
+            RecognizerRegistry::default().describe(&owner, tx, &labels, &Default::default());
+        action.unwrap().to_string()
+    };
+
+    // Labelled, then shown in short form.
+    let (labelled, short) = (action(&transactions[0]), action(&transactions[1]));
+    assert!(labelled.contains("Alice's wallet"));
+    assert!(short.contains(&short_address(&shop)));
+    assert_eq!(
+        names.text(&labelled),
+        labelled.replace("Alice's wallet", "Counterparty-1")
+    );
+    assert_eq!(
+        names.text(&short),
+        short.replace(&short_address(&shop), "Counterparty-2")
+    );
+}
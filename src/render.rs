@@ -0,0 +1,627 @@
+//! Text rendering of a history, shared by every front end.
+//!
+//! The same renderer is used for the full history and for single
+//! transactions (e.g., in watch mode), with `FormatOptions` deciding how much
+//! is shown.
+//!
+//! ## Color
+//! With color enabled, roles are highlighted so that long histories can be
+//! scanned: the address itself (Self), labelled addresses and contracts,
+//! failed transactions and large ether values each have their own color.
+use std::{
+    collections::{BTreeMap, HashSet},
+    env,
+    fmt::Display,
+    io::{stdout, IsTerminal},
+};
+
+use clap::ValueEnum;
+use web3::types::{H160, U256};
+
+use crate::{
+    chains::MAINNET,
+    data::{TxInfo, Warning, WarningKind},
+    explorer::Explorer,
+    fees::{FeeBreakdown, TxType},
+    history::AddressHistory,
+    labels::UserLabels,
+    spam::SpamFilter,
+    verify::Verification,
+};
+
+/// How much is shown for each transaction.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, ValueEnum)]
+pub enum Detail {
+    /// Only the headline of each transaction.
+    Brief,
+    /// Addresses, call, fees and events.
+    #[default]
+    Normal,
+    /// Also the raw calldata and event data.
+    Full,
+}
+
+/// Ether sent of at least this (1 ether) is highlighted.
+const LARGE_VALUE_WEI: u64 = 1_000_000_000_000_000_000;
+
+/// When to use color.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum ColorChoice {
+    /// If printing to a terminal and NO_COLOR is not set.
+    #[default]
+    Auto,
+    Always,
+    Never,
+}
+
+impl ColorChoice {
+    pub fn enabled(self) -> bool {
+        match self {
+            ColorChoice::Auto => stdout().is_terminal() && env::var_os("NO_COLOR").is_none(),
+            ColorChoice::Always => true,
+            ColorChoice::Never => false,
+        }
+    }
+}
+
+/// What a highlighted piece of text is.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Role {
+    Heading,
+    /// The address whose history is being explored.
+    Owner,
+    /// An address or contract with a user label or nametag.
+    Labelled,
+    Failed,
+    LargeValue,
+}
+
+impl Role {
+    /// ANSI select graphic rendition code.
+    fn code(self) -> &'static str {
+        match self {
+            Role::Heading => "1",
+            Role::Owner => "1;36",
+            Role::Labelled => "32",
+            Role::Failed => "1;31",
+            Role::LargeValue => "1;33",
+        }
+    }
+}
+
+/// Settings for rendering a history as text.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FormatOptions {
+    /// Most events shown per transaction (all if None).
+    pub max_events: Option<usize>,
+    /// Most transactions shown (all if None).
+    pub max_txs: Option<usize>,
+    /// Transactions skipped before the first one shown, for paging.
+    pub skip_txs: usize,
+    pub detail: Detail,
+    /// Use ANSI escape codes for emphasis.
+    pub color: bool,
+    /// How events flagged as spam are shown.
+    pub spam: SpamFilter,
+}
+
+impl Default for FormatOptions {
+    fn default() -> Self {
+        FormatOptions {
+            max_events: Some(10),
+            max_txs: None,
+            skip_txs: 0,
+            detail: Detail::default(),
+            color: false,
+            spam: SpamFilter::default(),
+        }
+    }
+}
+
+impl FormatOptions {
+    /// Wraps text in the ANSI color for the role, if color is enabled.
+    pub fn paint(&self, role: Role, text: &str) -> String {
+        match self.color {
+            true => format!("\x1b[{}m{}\x1b[0m", role.code(), text),
+            false => text.to_string(),
+        }
+    }
+}
+
+/// Displays a history with the given options (see `AddressHistory::render`).
+pub struct HistoryView<'a> {
+    pub history: &'a AddressHistory,
+    pub options: &'a FormatOptions,
+}
+
+impl Display for HistoryView<'_> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let (history, options) = (self.history, self.options);
+        let total = history.transactions.len();
+        write!(
+            f,
+            "There are {} txs for address: {}",
+            total, history.address
+        )?;
+        if let Some(sources) = &history.sources {
+            write!(f, "\nIndex: {}", sources)?;
+        }
+        write!(f, "{}", history.stats())?;
+        let shown = history
+            .transactions
+            .iter()
+            .enumerate()
+            .skip(options.skip_txs)
+            .take(options.max_txs.unwrap_or(usize::MAX));
+        let mut range = None;
+        for (i, tx) in shown {
+            // Explorer links are for the main chain only.
+            let explorer = history.config.explorer.as_ref();
+            let view = TxView {
+                index: i,
+                tx,
+                owner: &history.address,
+                labels: &history.config.labels,
+                explorer: explorer.filter(|_| tx.chain == history.config.chain),
+                options,
+            };
+            write!(f, "\n\n{}", view)?;
+            range = Some((range.map_or(i, |(first, _)| first), i));
+        }
+        match range {
+            Some((first, last)) if first > 0 || last + 1 < total => {
+                write!(
+                    f,
+                    "\n\nShowing transactions {}-{} of {}.",
+                    first, last, total
+                )?;
+                if last + 1 < total {
+                    write!(
+                        f,
+                        " Skipping remaining {} transactions...",
+                        total - last - 1
+                    )?;
+                }
+            }
+            None if total > 0 => write!(f, "\n\nNo transactions shown, {} in total.", total)?,
+            _ => {}
+        }
+        if !history.warnings.is_empty() {
+            write!(f, "\n\n{}", WarningSummary(&history.warnings))?;
+        }
+        Ok(())
+    }
+}
+
+impl Display for AddressHistory {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.render(&FormatOptions::default()))
+    }
+}
+
+/// Displays the transactions of a block, one line each (see
+/// `AddressHistory::of_block`).
+pub struct BlockView<'a> {
+    pub transactions: &'a [TxInfo],
+    pub fee_recipient: &'a str,
+    pub warnings: &'a [Warning],
+    pub options: &'a FormatOptions,
+}
+
+impl Display for BlockView<'_> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let (transactions, options) = (self.transactions, self.options);
+        let block = transactions.first().map(|tx| tx.location.block);
+        let title = match block {
+            Some(block) => format!("Block {}", block),
+            None => String::from("Block"),
+        };
+        write!(
+            f,
+            "{}: {} transactions, fee recipient {}",
+            options.paint(Role::Heading, &title),
+            transactions.len(),
+            self.fee_recipient
+        )?;
+        let (mut recognised, mut failed, mut spam) = (0, 0, 0);
+        for tx in transactions {
+            let events = tx.events.as_deref().unwrap_or_default();
+            let hash = tx.description.as_ref().map(|d| hex::encode(d.hash));
+            let hash = hash.as_deref().unwrap_or_default();
+            write!(
+                f,
+                "\n\t{}. 0x{}",
+                tx.location.index,
+                hash.get(..8).unwrap_or(hash)
+            )?;
+            match &tx.action {
+                Some(action) => {
+                    recognised += 1;
+                    write!(f, " {}", action)?;
+                }
+                None => write!(f, " ({} events)", events.len())?,
+            }
+            let receipt = tx.receipt.as_ref();
+            if receipt.and_then(|r| r.status).is_some_and(|s| s.is_zero()) {
+                failed += 1;
+                write!(f, " {}", options.paint(Role::Failed, "(failed)"))?;
+            }
+            spam += events.iter().filter(|e| e.spam).count();
+        }
+        write!(
+            f,
+            "\n{} with a recognised action, {} failed, {} likely spam events.",
+            recognised, failed, spam
+        )?;
+        if !self.warnings.is_empty() {
+            write!(f, "\n\n{}", WarningSummary(self.warnings))?;
+        }
+        Ok(())
+    }
+}
+
+/// Counts the failed lookups of each kind, for the end of the output.
+pub struct WarningSummary<'a>(pub &'a [Warning]);
+
+/// Displays a single transaction from the perspective of an address.
+pub struct TxView<'a> {
+    /// Position of the transaction in the history.
+    pub index: usize,
+    pub tx: &'a TxInfo,
+    /// The address whose history is being explored.
+    pub owner: &'a str,
+    /// Address labels supplied by the user.
+    pub labels: &'a UserLabels,
+    /// Block explorer to link to, if any.
+    pub explorer: Option<&'a Explorer>,
+    pub options: &'a FormatOptions,
+}
+
+impl Display for WarningSummary<'_> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let mut counts: BTreeMap<WarningKind, usize> = BTreeMap::new();
+        for warning in self.0 {
+            *counts.entry(warning.kind).or_default() += 1;
+        }
+        let counts: Vec<String> = counts
+            .iter()
+            .map(|(kind, count)| format!("{} {}", count, kind.lookup()))
+            .collect();
+        write!(
+            f,
+            "Warnings: {} lookups failed ({}). Items are shown without them.",
+            self.0.len(),
+            counts.join(", ")
+        )
+    }
+}
+
+impl Display for TxView<'_> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let (tx, a, l, options) = (self.tx, self.owner, self.labels, self.options);
+        let heading = format!("Transaction {}:", self.index);
+        write!(f, "{}", options.paint(Role::Heading, &heading))?;
+        if let Some(action) = &tx.action {
+            write!(f, " {}", action)?;
+        }
+        // Receipts may be unavailable from the provider.
+        let receipt = tx.receipt.as_ref();
+        if receipt.and_then(|r| r.status).is_some_and(|s| s.is_zero()) {
+            write!(f, " {}", options.paint(Role::Failed, "(failed)"))?;
+        }
+        if options.detail == Detail::Brief {
+            return Ok(());
+        }
+        let Some(desc) = &tx.description else {
+            return Ok(());
+        };
+        let nice = |address| nice_address(address, a, l, options);
+        if !tx.chain.is_empty() && tx.chain != MAINNET {
+            write!(f, "\n\tChain: {}", tx.chain)?;
+        }
+        write!(f, "\n\tSender: {}", nice(desc.from))?;
+        let recipient = receipt.map_or(desc.to, |r| r.to);
+        write!(f, "\n\tRecipient: {}", nice(recipient))?;
+        if !desc.input.0.is_empty() {
+            write!(f, "\n\tCalldata: {} bytes", desc.input.0.len())?;
+            if options.detail == Detail::Full {
+                write!(f, " 0x{}", hex::encode(&desc.input.0))?;
+            }
+        }
+        if let Some(call) = &tx.call {
+            write!(f, "\n\tCall: {}", call)?;
+        }
+        if let Some(message) = &tx.message {
+            write!(f, "\n\tMessage: {:?}", message)?;
+        }
+        if let Some(c) = receipt.and_then(|r| r.contract_address) {
+            write!(f, "\n\tContract deployed: {}", nice(Some(c)))?;
+        }
+        if let Some(constructor) = &tx.constructor {
+            write!(f, "\n\tConstructor: {}", constructor)?;
+        }
+        if let Some(fees) = FeeBreakdown::new(tx) {
+            write!(f, "\n\t{}", fees)?;
+        } else if let Some(tx_type) = TxType::of(tx) {
+            write!(f, "\n\tType: {}", tx_type)?;
+        }
+        write!(f, "\n\tTx Hash: {}", hex::encode(desc.hash))?;
+        write!(
+            f,
+            "\n\tBlock: {}, position {}",
+            tx.location.block, tx.location.index
+        )?;
+        if let Some(confirmations) = tx.confirmations() {
+            write!(f, " ({} confirmations)", confirmations)?;
+        }
+        if let Some(miner) = tx.miner {
+            write!(f, "\n\tFee recipient: {}", nice(Some(miner)))?;
+            let tags = tx.miner_nametags.iter().flatten().filter(|t| !t.is_empty());
+            let tags: Vec<&str> = tags.map(|t| t.as_str()).collect();
+            if !tags.is_empty() {
+                let tags = tags.join(", ");
+                write!(f, " ({})", options.paint(Role::Labelled, &tags))?;
+            }
+        }
+        if let Some(explorer) = self.explorer {
+            write!(f, "\n\tExplorer: {}", explorer.tx(&desc.hash))?;
+        }
+        if !tx.providers.is_empty() {
+            let providers: Vec<&str> = tx.providers.iter().map(|p| p.as_str()).collect();
+            write!(f, "\n\tData from: {}", providers.join(", "))?;
+        }
+        match &tx.verification {
+            Some(v @ Verification::Verified { .. }) => write!(f, "\n\tReceipt: {}", v)?,
+            Some(v) => {
+                let text = v.to_string();
+                write!(f, "\n\tReceipt: {}", options.paint(Role::Failed, &text))?
+            }
+            None => {}
+        }
+        let milli_ether = desc.value / 1_000_000_000 / 1_000_000;
+        if !milli_ether.is_zero() {
+            let mut sent = format!("{} mETH", milli_ether);
+            if desc.value >= U256::from(LARGE_VALUE_WEI) {
+                sent = options.paint(Role::LargeValue, &sent);
+            }
+            write!(f, "\n\tEther sent: {}", sent)?;
+        }
+        let Some(events) = &tx.events else {
+            return Ok(());
+        };
+        let event_count = events.len();
+        write!(f, "\n\tEvents emitted: {}", event_count)?;
+        let spam_count = events.iter().filter(|e| e.spam).count();
+        if spam_count > 0 && options.spam == SpamFilter::Collapse {
+            let text = format!("{} likely spam events not shown", spam_count);
+            write!(f, " ({})", options.paint(Role::Failed, &text))?;
+        }
+        let max_events = options.max_events.unwrap_or(usize::MAX);
+        let shown = events
+            .iter()
+            .enumerate()
+            .filter(|(_, e)| !e.spam || options.spam == SpamFilter::Show);
+        let mut listed = HashSet::new();
+        for (i, e) in shown.take(max_events) {
+            let mut event = e.to_string();
+            if e.nametags.as_ref().is_some_and(|t| !t.is_empty()) {
+                let tags = e.nametag_string();
+                event = event.replacen(&tags, &options.paint(Role::Labelled, &tags), 1);
+            }
+            if e.spam {
+                // After the event name, on its first line.
+                let mark = format!(" {}\n", options.paint(Role::Failed, "(likely spam)"));
+                event = event.replacen('\n', &mark, 1);
+            }
+            write!(f, "\n\n\t\t{}. Event {}/{}", event, i, event_count)?;
+            if options.detail == Detail::Full && !e.raw.data.0.is_empty() {
+                write!(f, "\n\t\t\tData hex: 0x{}", hex::encode(&e.raw.data.0))?;
+            }
+            let functions = &e.contract.functions;
+            if options.detail == Detail::Full
+                && !functions.is_empty()
+                && listed.insert(e.raw.address)
+            {
+                let names: Vec<String> = functions.iter().map(|f| f.to_string()).collect();
+                write!(
+                    f,
+                    "\n\t\t\tOther functions available on this contract: {}",
+                    names.join(", ")
+                )?;
+            }
+        }
+        let visible = match options.spam {
+            SpamFilter::Show => event_count,
+            SpamFilter::Collapse | SpamFilter::Hide => event_count - spam_count,
+        };
+        if visible > max_events {
+            write!(
+                f,
+                "\n\t\tSkipping remaining {} events...",
+                visible - max_events
+            )?;
+        }
+        Ok(())
+    }
+}
+
+/// Makes an address option nice to read and detects if it is the owner.
+///
+/// Addresses with a user label are shown as "label (0x...)".
+fn nice_address(
+    address: Option<H160>,
+    owner_address: &str,
+    labels: &UserLabels,
+    options: &FormatOptions,
+) -> String {
+    let owner_address = owner_address.trim_start_matches("0x");
+    match address {
+        Some(a) => {
+            let label = labels.get(&a).and_then(|l| l.first());
+            let a = hex::encode(a);
+            if a == owner_address {
+                options.paint(Role::Owner, "Self")
+            } else if let Some(label) = label {
+                format!("{} (0x{})", options.paint(Role::Labelled, label), a)
+            } else {
+                format!("0x{}", a)
+            }
+        }
+        None => String::from("None"),
+    }
+}
+
+#[test]
+fn event_limit_and_detail() {
+    use crate::semantics::{test_event, TRANSFER};
+
+    let contract = "11".repeat(20);
+    let tx = TxInfo {
+        description: Some(Default::default()),
+        events: Some(vec![test_event(&contract, TRANSFER, &[], vec![]); 3]),
+        ..Default::default()
+    };
+    let labels = UserLabels::default();
+    let mut options = FormatOptions {
+        max_events: Some(2),
+        ..Default::default()
+    };
+    let view = |options: &FormatOptions| {
+        TxView {
+            index: 7,
+            tx: &tx,
+            owner: "0xaa",
+            labels: &labels,
+            explorer: None,
+            options,
+        }
+        .to_string()
+    };
+    let limited = view(&options);
+    assert_eq!(limited.matches(". Event ").count(), 2);
+    assert!(limited.ends_with("Skipping remaining 1 events..."));
+    options.detail = Detail::Brief;
+    assert_eq!(view(&options), "Transaction 7:");
+}
+
+#[test]
+fn role_colors() {
+    use web3::types::{Transaction, TransactionReceipt};
+
+    let owner = H160::repeat_byte(0xaa);
+    let tx = TxInfo {
+        description: Some(Transaction {
+            from: Some(owner),
+            value: U256::exp10(18) * 2,
+            ..Default::default()
+        }),
+        receipt: Some(TransactionReceipt {
+            status: Some(0.into()),
+            ..Default::default()
+        }),
+        ..Default::default()
+    };
+    let labels = UserLabels::default();
+    let view = |color| {
+        TxView {
+            index: 0,
+            tx: &tx,
+            owner: &format!("0x{}", hex::encode(owner)),
+            labels: &labels,
+            explorer: None,
+            options: &FormatOptions {
+                color,
+                ..Default::default()
+            },
+        }
+        .to_string()
+    };
+    let plain = view(false);
+    assert!(plain.starts_with("Transaction 0: (failed)\n\tSender: Self"));
+    assert!(plain.contains("Ether sent: 2000 mETH"));
+    let colored = view(true);
+    assert!(colored.contains("\x1b[1;31m(failed)\x1b[0m"));
+    assert!(colored.contains("Sender: \x1b[1;36mSelf\x1b[0m"));
+    assert!(colored.contains("\x1b[1;33m2000 mETH\x1b[0m"));
+}
+
+#[test]
+fn block_position_and_confirmations() {
+    use min_know::specs::address_appearance_index::AAIAppearanceTx;
+    use web3::types::Transaction;
+
+    let tx = TxInfo {
+        location: AAIAppearanceTx {
+            block: 100,
+            index: 4,
+        },
+        description: Some(Transaction::default()),
+        miner: Some(H160::repeat_byte(0x95)),
+        miner_nametags: Some(vec![String::from("Builder")]),
+        head_block: Some(109),
+        ..Default::default()
+    };
+    let text = TxView {
+        index: 0,
+        tx: &tx,
+        owner: "0x00",
+        labels: &UserLabels::default(),
+        explorer: None,
+        options: &FormatOptions::default(),
+    }
+    .to_string();
+    assert!(text.contains("\n\tBlock: 100, position 4 (10 confirmations)"));
+    let miner = format!("\n\tFee recipient: 0x{} (Builder)", "95".repeat(20));
+    assert!(text.contains(&miner));
+}
+
+#[test]
+fn warnings_counted_by_kind() {
+    let warnings = [
+        Warning::new(WarningKind::Signature, "12345678", "not found"),
+        Warning::new(WarningKind::Abi, "0x11", "not found"),
+        Warning::new(WarningKind::Signature, "87654321", "not found"),
+    ];
+    assert_eq!(
+        WarningSummary(&warnings).to_string(),
+        "Warnings: 3 lookups failed (1 ABI, 2 signature). Items are shown without them."
+    );
+    assert_eq!(warnings[1].to_string(), "ABI of 0x11: not found");
+}
+
+#[test]
+fn block_transactions_one_line_each() {
+    use min_know::specs::address_appearance_index::AAIAppearanceTx;
+    use web3::types::{Transaction, TransactionReceipt, H256};
+
+    use crate::semantics::Action;
+
+    let tx = |index, action| TxInfo {
+        location: AAIAppearanceTx { block: 7, index },
+        description: Some(Transaction {
+            hash: H256::repeat_byte(0xab),
+            ..Default::default()
+        }),
+        receipt: Some(TransactionReceipt {
+            status: Some((index as u64).into()),
+            ..Default::default()
+        }),
+        action,
+        ..Default::default()
+    };
+    let deploy = Action::Deploy {
+        contract: String::from("0xc0"),
+    };
+    let transactions = vec![tx(0, None), tx(1, Some(deploy))];
+    let text = BlockView {
+        transactions: &transactions,
+        fee_recipient: "0x95",
+        warnings: &[],
+        options: &FormatOptions::default(),
+    }
+    .to_string();
+    assert!(text.starts_with("Block 7: 2 transactions, fee recipient 0x95"));
+    assert!(text.contains("\n\t0. 0xabababab (0 events) (failed)"));
+    assert!(text.contains("\n\t1. 0xabababab Deployed contract 0xc0"));
+    assert!(text.ends_with("1 with a recognised action, 1 failed, 0 likely spam events."));
+}
@@ -0,0 +1,348 @@
+//! Access to one or more Ethereum nodes over JSON-RPC.
+//!
+//! Requests are sent to the healthiest endpoint first. If an endpoint cannot
+//! be reached (connection error or timeout) the next one is tried, so a single
+//! flaky node does not stop a long run.
+use std::{
+    future::Future,
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+
+use anyhow::{anyhow, bail, Result};
+use async_trait::async_trait;
+use log::warn;
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use web3::{
+    api::BaseFilter,
+    transports::Http,
+    types::{
+        Block, BlockId, BlockNumber, Bytes, CallRequest, Filter, Log, Transaction, TransactionId,
+        TransactionReceipt, H160, H256, U256, U64,
+    },
+    Transport, Web3,
+};
+
+use crate::provider::{Capability, ChainDataProvider, Sourced};
+
+/// Time allowed for a single request before trying the next endpoint.
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// Record of how an endpoint has behaved during this run.
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct EndpointHealth {
+    /// Requests that were answered.
+    pub successes: u32,
+    /// Requests that failed to connect or timed out.
+    pub failures: u32,
+    /// Failures since the last success. Used to order endpoints.
+    pub consecutive_failures: u32,
+}
+
+/// Error context when no endpoint could answer a request.
+#[derive(Debug, Clone, Copy)]
+pub struct Unreachable;
+
+/// A set of RPC endpoints with failover.
+///
+/// Clones share health information.
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct RpcPool {
+    /// Endpoint URLs in order of preference.
+    pub urls: Vec<String>,
+    /// Clients for each URL.
+    #[serde(skip)]
+    clients: Vec<Web3<Http>>,
+    /// Health of each URL.
+    #[serde(skip)]
+    health: Arc<Mutex<Vec<EndpointHealth>>>,
+}
+
+impl RpcPool {
+    /// Creates clients for each endpoint URL.
+    pub fn new(urls: Vec<String>) -> Result<Self> {
+        if urls.is_empty() {
+            bail!("At least one RPC URL is required.")
+        }
+        let clients = urls
+            .iter()
+            .map(|url| Ok(Web3::new(Http::new(url)?)))
+            .collect::<Result<Vec<_>>>()?;
+        let health = vec![EndpointHealth::default(); urls.len()];
+        Ok(RpcPool {
+            urls,
+            clients,
+            health: Arc::new(Mutex::new(health)),
+        })
+    }
+    /// Endpoint indices, healthiest first (ties keep the configured order).
+    fn ordered(&self) -> Vec<usize> {
+        let health = self.health.lock().expect("RPC health lock poisoned");
+        let mut order: Vec<usize> = (0..self.urls.len()).collect();
+        order.sort_by_key(|i| health.get(*i).map(|h| h.consecutive_failures));
+        order
+    }
+    fn record(&self, index: usize, success: bool) {
+        let mut health = self.health.lock().expect("RPC health lock poisoned");
+        let Some(h) = health.get_mut(index) else {
+            return;
+        };
+        if success {
+            h.successes += 1;
+            h.consecutive_failures = 0;
+        } else {
+            h.failures += 1;
+            h.consecutive_failures += 1;
+        }
+    }
+    /// Client for the healthiest endpoint, for stateful requests (e.g., filters)
+    /// that must stay on one node.
+    pub fn primary(&self) -> Result<(Web3<Http>, String)> {
+        let index = *self
+            .ordered()
+            .first()
+            .ok_or_else(|| anyhow!("No RPC endpoints configured."))?;
+        let client = self
+            .clients
+            .get(index)
+            .ok_or_else(|| anyhow!("RPC clients not initialised."))?;
+        Ok((client.clone(), self.urls[index].to_owned()))
+    }
+    /// Health of each endpoint as (url, health).
+    pub fn health(&self) -> Vec<(String, EndpointHealth)> {
+        let health = self.health.lock().expect("RPC health lock poisoned");
+        self.urls
+            .iter()
+            .cloned()
+            .zip(health.iter().cloned())
+            .collect()
+    }
+    /// Performs a request, rotating through endpoints on connection errors.
+    ///
+    /// Returns the response and the URL of the endpoint that provided it.
+    /// Errors reported by a node (e.g., invalid params) are returned without
+    /// trying other endpoints.
+    pub async fn call<T, F, Fut>(&self, request: F) -> Result<(T, String)>
+    where
+        F: Fn(Web3<Http>) -> Fut,
+        Fut: Future<Output = web3::Result<T>>,
+    {
+        let mut last_error = anyhow!("RPC clients not initialised.");
+        for index in self.ordered() {
+            let Some(client) = self.clients.get(index) else {
+                continue;
+            };
+            let url = &self.urls[index];
+            match tokio::time::timeout(REQUEST_TIMEOUT, request(client.clone())).await {
+                Ok(Ok(response)) => {
+                    self.record(index, true);
+                    return Ok((response, url.to_owned()));
+                }
+                Ok(Err(e)) if !is_connection_error(&e) => {
+                    self.record(index, true);
+                    return Err(e.into());
+                }
+                Ok(Err(e)) => {
+                    warn!("RPC endpoint {} failed ({}), trying next.", url, e);
+                    last_error = e.into();
+                }
+                Err(_) => {
+                    warn!("RPC endpoint {} timed out, trying next.", url);
+                    last_error = anyhow!("Request to {} timed out", url);
+                }
+            }
+            self.record(index, false);
+        }
+        Err(last_error.context(Unreachable))
+    }
+}
+
+/// Response to ots_getContractCreator.
+#[derive(Deserialize)]
+struct ContractCreator {
+    /// The deploying transaction.
+    hash: H256,
+    creator: H160,
+}
+
+/// A full node supports every capability.
+#[async_trait]
+impl ChainDataProvider for RpcPool {
+    fn name(&self) -> &str {
+        "RPC node"
+    }
+    fn supports(&self, _capability: Capability) -> bool {
+        true
+    }
+    async fn request(&self, method: &str, params: Vec<Value>) -> Result<Sourced<Value>> {
+        self.call(|web3| {
+            let params = params.clone();
+            async move { web3.transport().execute(method, params).await }
+        })
+        .await
+    }
+    async fn transaction(&self, id: TransactionId) -> Result<Sourced<Option<Transaction>>> {
+        self.call(|web3| {
+            let id = id.clone();
+            async move { web3.eth().transaction(id).await }
+        })
+        .await
+    }
+    async fn receipt(&self, hash: H256) -> Result<Sourced<Option<TransactionReceipt>>> {
+        self.call(|web3| async move { web3.eth().transaction_receipt(hash).await })
+            .await
+    }
+    async fn block_receipts(
+        &self,
+        number: u64,
+    ) -> Result<Sourced<Option<Vec<TransactionReceipt>>>> {
+        let params = vec![json!(U64::from(number))];
+        let (response, url) = self.request("eth_getBlockReceipts", params).await?;
+        Ok((serde_json::from_value(response)?, url))
+    }
+    async fn block(&self, number: u64) -> Result<Sourced<Option<Block<H256>>>> {
+        let id = BlockId::Number(BlockNumber::Number(number.into()));
+        self.call(|web3| async move { web3.eth().block(id).await })
+            .await
+    }
+    async fn block_with_txs(&self, number: u64) -> Result<Sourced<Option<Block<Transaction>>>> {
+        let id = BlockId::Number(BlockNumber::Number(number.into()));
+        self.call(|web3| async move { web3.eth().block_with_txs(id).await })
+            .await
+    }
+    async fn code(&self, address: H160) -> Result<Sourced<Bytes>> {
+        self.call(|web3| async move { web3.eth().code(address, Some(BlockNumber::Latest)).await })
+            .await
+    }
+    async fn code_at(&self, address: H160, block: u64) -> Result<Sourced<Bytes>> {
+        let number = BlockNumber::Number(block.into());
+        self.call(|web3| async move { web3.eth().code(address, Some(number)).await })
+            .await
+    }
+    async fn contract_call(&self, to: H160, data: Vec<u8>) -> Result<Sourced<Bytes>> {
+        let request = CallRequest::builder().to(to).data(Bytes(data)).build();
+        self.call(|web3| {
+            let request = request.clone();
+            async move { web3.eth().call(request, None).await }
+        })
+        .await
+    }
+    async fn simulate_call(&self, request: CallRequest) -> Result<Sourced<Bytes>> {
+        let block = Some(BlockId::Number(BlockNumber::Latest));
+        self.call(|web3| {
+            let request = request.clone();
+            async move { web3.eth().call(request, block).await }
+        })
+        .await
+    }
+    async fn estimate_gas(&self, request: CallRequest) -> Result<Sourced<U256>> {
+        self.call(|web3| {
+            let request = request.clone();
+            async move { web3.eth().estimate_gas(request, None).await }
+        })
+        .await
+    }
+    async fn contract_creator(&self, address: H160) -> Result<Sourced<Option<(H160, H256)>>> {
+        let (response, url) = self
+            .request("ots_getContractCreator", vec![json!(address)])
+            .await?;
+        let creator: Option<ContractCreator> = serde_json::from_value(response)?;
+        Ok((creator.map(|c| (c.creator, c.hash)), url))
+    }
+    async fn head_block(&self) -> Result<Sourced<u64>> {
+        let (head, url) = self
+            .call(|web3| async move { web3.eth().block_number().await })
+            .await?;
+        Ok((head.as_u64(), url))
+    }
+    async fn logs(&self, filter: Filter) -> Result<Sourced<Vec<Log>>> {
+        self.call(|web3| {
+            let filter = filter.clone();
+            async move { web3.eth().logs(filter).await }
+        })
+        .await
+    }
+    async fn pending_transactions_filter(
+        &self,
+    ) -> Result<Sourced<(Web3<Http>, BaseFilter<Http, H256>)>> {
+        let (web3, url) = self.primary()?;
+        let filter = web3
+            .eth_filter()
+            .create_pending_transactions_filter()
+            .await?;
+        Ok(((web3, filter), url))
+    }
+}
+
+impl std::fmt::Display for Unreachable {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "All RPC endpoints failed")
+    }
+}
+
+impl PartialEq for RpcPool {
+    fn eq(&self, other: &Self) -> bool {
+        self.urls == other.urls
+    }
+}
+
+/// True for errors where another endpoint may succeed.
+fn is_connection_error(error: &web3::Error) -> bool {
+    matches!(
+        error,
+        web3::Error::Unreachable
+            | web3::Error::Transport(_)
+            | web3::Error::Io(_)
+            | web3::Error::InvalidResponse(_)
+    )
+}
+
+#[test]
+fn failing_endpoint_is_tried_last() {
+    let urls = vec![
+        String::from("http://localhost:8545"),
+        String::from("http://localhost:8546"),
+    ];
+    let pool = RpcPool::new(urls).unwrap();
+    assert_eq!(pool.ordered(), vec![0, 1]);
+    pool.record(0, false);
+    assert_eq!(pool.ordered(), vec![1, 0]);
+    pool.clone().record(1, false);
+    pool.record(1, false);
+    assert_eq!(pool.ordered(), vec![0, 1]);
+    pool.record(1, true);
+    assert_eq!(pool.ordered(), vec![1, 0]);
+    assert_eq!(pool.primary().unwrap().1, "http://localhost:8546");
+    assert!(RpcPool::default().primary().is_err());
+}
+
+#[test]
+fn contract_creator_response() {
+    use crate::{data::Deployer, semantics::test_event};
+
+    let response = json!({
+        "hash": format!("0x{}", "ab".repeat(32)),
+        "creator": "0x5c69bee701ef814a2b6a3edd4b1652cb9cc5aa6f"
+    });
+    let creator: Option<ContractCreator> = serde_json::from_value(response).unwrap();
+    let creator = creator.unwrap();
+    assert_eq!(creator.hash, H256::repeat_byte(0xab));
+    let none: Option<ContractCreator> = serde_json::from_value(Value::Null).unwrap();
+    assert!(none.is_none());
+
+    let mut event = test_event(
+        "0x00000000000000000000000000000000000000aa",
+        "Sync(uint112,uint112)",
+        &[],
+        vec![],
+    );
+    event.contract.deployer = Some(Deployer {
+        address: creator.creator,
+        transaction: creator.hash,
+        nametags: vec![String::from("Uniswap V2 Factory")],
+    });
+    assert!(event.to_string().contains(
+        ", deployed by |Uniswap V2 Factory| 0x5c69bee701ef814a2b6a3edd4b1652cb9cc5aa6f)"
+    ));
+}
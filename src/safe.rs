@@ -0,0 +1,208 @@
+//! Interprets transactions executed by a Safe (formerly Gnosis Safe) multisig.
+//!
+//! An owner of a Safe submits the signed Safe transaction with
+//! `execTransaction`, and the Safe then emits `ExecutionSuccess` or
+//! `ExecutionFailure`. A contract is taken to be a Safe if it emits one of
+//! these events. The call that the Safe made is read from the calldata,
+//! including each call in a MultiSend batch.
+//!
+//! The Safe nonce is only available from the `SafeMultiSigTransaction` event
+//! of the L2 version of the Safe, which records it with the other details.
+use web3::types::{H160, U256};
+
+use crate::{
+    abi::{data_word, dynamic_bytes, topic_of, word_address},
+    semantics::{describe_call, Action, TxContext},
+};
+
+/// Emitted by a Safe after the inner call succeeded.
+pub const SAFE_EXECUTION_SUCCESS: &str = "ExecutionSuccess(bytes32,uint256)";
+/// Emitted by a Safe after the inner call failed (the nonce is still used).
+pub const SAFE_EXECUTION_FAILURE: &str = "ExecutionFailure(bytes32,uint256)";
+/// Emitted by the L2 version of the Safe with the details of each Safe transaction.
+pub const SAFE_MULTISIG_TRANSACTION: &str = "SafeMultiSigTransaction(address,uint256,bytes,uint8,uint256,uint256,uint256,address,address,bytes,bytes)";
+
+/// execTransaction(address,uint256,bytes,uint8,uint256,uint256,uint256,address,address,bytes)
+const EXEC_TRANSACTION: [u8; 4] = [0x6a, 0x76, 0x12, 0x02];
+/// multiSend(bytes), called by a Safe with delegatecall to batch calls.
+const MULTI_SEND: [u8; 4] = [0x8d, 0x80, 0xff, 0x0a];
+
+/// A call made by a Safe.
+struct SafeCall<'a> {
+    to: H160,
+    value: U256,
+    data: &'a [u8],
+    /// True for delegatecall (operation 1).
+    delegate: bool,
+}
+
+/// Recognises a Safe transaction sent by the address (as an owner) or
+/// executed by the address (as the Safe).
+pub fn safe_rule(context: &TxContext) -> Option<Action> {
+    let success = topic_of(SAFE_EXECUTION_SUCCESS);
+    let failure = topic_of(SAFE_EXECUTION_FAILURE);
+    let execution = context.events().iter().find(|e| {
+        e.raw
+            .topics
+            .first()
+            .is_some_and(|t| *t == success || *t == failure)
+    })?;
+    let safe = execution.raw.address;
+    let desc = context.tx.description.as_ref()?;
+    let sent_by_owner = desc.from.is_some_and(|f| context.is_owner(&f));
+    if !sent_by_owner && !context.is_owner(&safe) {
+        return None;
+    }
+    let nonce = context
+        .events_with_signature(SAFE_MULTISIG_TRANSACTION)
+        .into_iter()
+        .find(|e| e.raw.address == safe)
+        .and_then(|e| data_word(dynamic_bytes(&e.raw.data.0, 10)?, 0));
+    let calls = match desc.to == Some(safe) {
+        true => exec_transaction(&desc.input.0)
+            .map(|call| describe_safe_call(context, &call))
+            .unwrap_or_default(),
+        false => vec![],
+    };
+    Some(Action::SafeTransaction {
+        safe: context.label(&safe),
+        nonce,
+        success: execution.raw.topics[0] == success,
+        calls,
+    })
+}
+
+/// Reads the call from execTransaction calldata.
+fn exec_transaction(calldata: &[u8]) -> Option<SafeCall<'_>> {
+    let args = calldata.strip_prefix(&EXEC_TRANSACTION)?;
+    Some(SafeCall {
+        to: word_address(args, 0)?,
+        value: data_word(args, 1)?,
+        data: dynamic_bytes(args, 2)?,
+        delegate: data_word(args, 3)? == U256::one(),
+    })
+}
+
+/// Reads the calls packed into multiSend calldata.
+///
+/// Each call is operation (1 byte), to (20), value (32), data length (32)
+/// and data.
+fn multi_send(calldata: &[u8]) -> Option<Vec<SafeCall<'_>>> {
+    let mut packed = dynamic_bytes(calldata.strip_prefix(&MULTI_SEND)?, 0)?;
+    let mut calls = vec![];
+    while !packed.is_empty() {
+        let header = packed.get(..85)?;
+        let length = U256::from_big_endian(&header[53..85]);
+        if length > U256::from(packed.len()) {
+            return None;
+        }
+        let end = 85 + length.as_usize();
+        calls.push(SafeCall {
+            to: H160::from_slice(&header[1..21]),
+            value: U256::from_big_endian(&header[21..53]),
+            data: packed.get(85..end)?,
+            delegate: header[0] == 1,
+        });
+        packed = &packed[end..];
+    }
+    Some(calls)
+}
+
+/// Describes a call, or each call in a batch, e.g., "transfer 10 ETH to 0xabcd…1234".
+fn describe_safe_call(context: &TxContext, call: &SafeCall) -> Vec<String> {
+    if !call.delegate {
+        return vec![describe_call(context, &call.to, call.value, call.data)];
+    }
+    if let Some(calls) = multi_send(call.data) {
+        return calls
+            .iter()
+            .flat_map(|c| describe_safe_call(context, c))
+            .collect();
+    }
+    let selector = call.data.get(..4).unwrap_or_default();
+    vec![format!(
+        "delegatecall {} on {}",
+        hex::encode(selector),
+        context.label(&call.to)
+    )]
+}
+
+#[test]
+fn exec_transaction_with_multi_send() {
+    use web3::types::{Bytes, Transaction, H256};
+
+    use crate::{
+        data::TxInfo,
+        labels::UserLabels,
+        semantics::{short_address, RecognizerRegistry},
+    };
+
+    let owner = H160::repeat_byte(0xaa);
+    let safe = H160::repeat_byte(0x5a);
+    let recipient = H160::repeat_byte(0xbb);
+    let token = H160::repeat_byte(0x70);
+    let word = |value: U256| {
+        let mut w = [0u8; 32];
+        value.to_big_endian(&mut w);
+        w.to_vec()
+    };
+    let address_word = |a: H160| H256::from(a).as_bytes().to_vec();
+    // Dynamic bytes argument at the end of the head.
+    let with_bytes = |mut head: Vec<u8>, tail: &[u8]| {
+        head.extend(word(tail.len().into()));
+        head.extend(tail);
+        head.resize(head.len() + (32 - tail.len() % 32) % 32, 0);
+        head
+    };
+    let mut erc20 = crate::semantics::ERC20_TRANSFER.to_vec();
+    erc20.extend(address_word(recipient));
+    erc20.extend(word(U256::exp10(18) * 3));
+    let mut packed = vec![0];
+    packed.extend(recipient.as_bytes());
+    packed.extend(word(U256::exp10(19)));
+    packed.extend(word(0.into()));
+    packed.push(0);
+    packed.extend(token.as_bytes());
+    packed.extend(word(0.into()));
+    packed.extend(word(erc20.len().into()));
+    packed.extend(&erc20);
+    let mut multi_send = MULTI_SEND.to_vec();
+    multi_send.extend(with_bytes(word(32.into()), &packed));
+    let mut head = address_word(H160::repeat_byte(0x40));
+    head.extend(word(0.into()));
+    head.extend(word((10 * 32).into()));
+    head.extend(word(1.into()));
+    head.extend(vec![0; 6 * 32]);
+    let mut input = EXEC_TRANSACTION.to_vec();
+    input.extend(with_bytes(head, &multi_send));
+
+    let tx = TxInfo {
+        description: Some(Transaction {
+            from: Some(owner),
+            to: Some(safe),
+            input: Bytes(input),
+            ..Default::default()
+        }),
+        events: Some(vec![crate::semantics::test_event(
+            &hex::encode(safe),
+            SAFE_EXECUTION_SUCCESS,
+            &[],
+            vec![0; 64],
+        )]),
+        ..Default::default()
+    };
+    let decimals = std::collections::HashMap::from([(token, 18)]);
+    let action = RecognizerRegistry::default()
+        .describe(&hex::encode(owner), &tx, &UserLabels::default(), &decimals)
+        .unwrap();
+    assert_eq!(
+        action.to_string(),
+        format!(
+            "Executed Safe tx on {}: transfer 10 ETH to {}; transfer 3 {} to {}",
+            short_address(&safe),
+            short_address(&recipient),
+            short_address(&token),
+            short_address(&recipient)
+        )
+    );
+}
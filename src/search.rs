@@ -0,0 +1,265 @@
+//! Search of names and signatures by text, e.g., "have I ever interacted
+//! with anything labelled uniswap?".
+//!
+//! ## Names
+//! The TODD nametags database is keyed by address and can only be read one
+//! address at a time. A search therefore covers two sets of addresses:
+//! - Without a history: addresses with a user label, and addresses whose
+//!   nametags were looked up in earlier runs (kept in the artifact store).
+//! - With a history: every address the history involves (sender, recipient,
+//!   deployed contract and contracts that emitted events), each looked up in
+//!   the database.
+//!
+//! Matching is a case insensitive substring of any name or tag.
+//!
+//! ## Signatures
+//! The TODD signatures database is keyed by selector in the same way. Text
+//! signatures are searched in the signatures resolved in earlier runs and the
+//! functions and events of stored ABIs. A complete signature (e.g.,
+//! "Transfer(address,address,uint256)") is also hashed directly. Matching is
+//! a case insensitive substring, with matches at the start listed first.
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    fmt::Display,
+};
+
+use web3::types::H160;
+
+use crate::{
+    abi::{selector_of, topic_of},
+    decode::{event_signatures, function_signatures},
+    history::AddressHistory,
+    labels::UserLabels,
+    store::Resolved,
+};
+
+/// An address with names that match a search.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct NameMatch {
+    pub address: H160,
+    /// All names and tags of the address (user labels first).
+    pub names: Vec<String>,
+}
+
+/// A transaction in a history that involves an address with a matching name.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Interaction {
+    /// Position of the transaction in the history.
+    pub index: usize,
+    pub matched: NameMatch,
+}
+
+/// A text signature that matches a search.
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
+pub struct SignatureMatch {
+    pub text: String,
+    pub kind: SignatureKind,
+}
+
+/// What a signature is known to be, which decides the hash that is useful.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum SignatureKind {
+    /// From an ABI function, identified by a 4 byte selector.
+    Function,
+    /// From an ABI event, identified by a 32 byte topic.
+    Event,
+    /// Resolved from a selector or given in full, so either.
+    Either,
+}
+
+/// Addresses with a user label or previously resolved nametag that matches.
+pub fn search_names(query: &str, labels: &UserLabels, resolved: &Resolved) -> Vec<NameMatch> {
+    let mut known: BTreeMap<H160, Option<Vec<String>>> = BTreeMap::new();
+    for (address, tags) in &resolved.nametags {
+        if let Ok(address) = address.parse() {
+            known.insert(address, Some(tags.clone()));
+        }
+    }
+    for (address, _) in labels.iter() {
+        if let Ok(address) = address.parse() {
+            known.entry(address).or_default();
+        }
+    }
+    known
+        .into_iter()
+        .filter_map(|(address, tags)| {
+            let names = labels.merge(&address, tags)?;
+            name_match(query, address, names)
+        })
+        .collect()
+}
+
+/// Transactions in the history that involve an address with a matching name.
+pub fn search_history(query: &str, history: &mut AddressHistory) -> Vec<Interaction> {
+    let AddressHistory {
+        transactions,
+        config,
+        cache,
+        ..
+    } = history;
+    let mut interactions = vec![];
+    for (index, tx) in transactions.iter().enumerate() {
+        let mut addresses = BTreeSet::new();
+        if let Some(desc) = &tx.description {
+            addresses.extend(desc.from);
+            addresses.extend(desc.to);
+        }
+        if let Some(receipt) = &tx.receipt {
+            addresses.extend(receipt.contract_address);
+        }
+        for event in tx.events.iter().flatten() {
+            addresses.insert(event.raw.address);
+        }
+        for address in addresses {
+            let Some(names) = cache.try_nametags(&address, config) else {
+                continue;
+            };
+            if let Some(matched) = name_match(query, address, names) {
+                interactions.push(Interaction { index, matched });
+            }
+        }
+    }
+    interactions
+}
+
+/// Signatures in resolved lookups and stored ABIs that contain the query.
+pub fn search_signatures(query: &str, resolved: &Resolved, abis: &[String]) -> Vec<SignatureMatch> {
+    let mut known: BTreeMap<String, SignatureKind> = BTreeMap::new();
+    let mut add = |text: &str, kind| {
+        known
+            .entry(text.to_string())
+            .and_modify(|k| {
+                if *k != kind {
+                    *k = SignatureKind::Either
+                }
+            })
+            .or_insert(kind);
+    };
+    for abi in abis {
+        for text in function_signatures(abi).unwrap_or_default() {
+            add(&text, SignatureKind::Function);
+        }
+        for text in event_signatures(abi).unwrap_or_default() {
+            add(&text, SignatureKind::Event);
+        }
+    }
+    for text in resolved.signatures.values() {
+        add(text, SignatureKind::Either);
+    }
+    if is_signature(query) {
+        add(query, SignatureKind::Either);
+    }
+    let lowercase = query.to_lowercase();
+    let mut found: Vec<SignatureMatch> = known
+        .into_iter()
+        .filter(|(text, _)| text.to_lowercase().contains(&lowercase))
+        .map(|(text, kind)| SignatureMatch { text, kind })
+        .collect();
+    found.sort_by_key(|m| {
+        (
+            !m.text.to_lowercase().starts_with(&lowercase),
+            m.text.clone(),
+        )
+    });
+    found
+}
+
+/// Whether text has the shape of a complete signature, e.g., "name(type,type)".
+fn is_signature(text: &str) -> bool {
+    let Some((name, rest)) = text.split_once('(') else {
+        return false;
+    };
+    !name.is_empty()
+        && name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_')
+        && rest.ends_with(')')
+        && !rest.contains(' ')
+}
+
+impl Display for SignatureMatch {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let selector = format!("0x{}", hex::encode(selector_of(&self.text)));
+        let topic = format!("{:?}", topic_of(&self.text));
+        match self.kind {
+            SignatureKind::Function => write!(f, "{}  {}", selector, self.text),
+            SignatureKind::Event => write!(f, "{}  {}", topic, self.text),
+            SignatureKind::Either => write!(f, "{}  {}  (topic {})", selector, self.text, topic),
+        }
+    }
+}
+
+fn name_match(query: &str, address: H160, names: Vec<String>) -> Option<NameMatch> {
+    let query = query.to_lowercase();
+    names
+        .iter()
+        .any(|name| name.to_lowercase().contains(&query))
+        .then_some(NameMatch { address, names })
+}
+
+#[test]
+fn names_from_labels_and_resolved() {
+    let labels: UserLabels = serde_json::from_value(serde_json::json!({
+        "11".repeat(20): ["My Uniswap LP"],
+        "22".repeat(20): ["Alice"],
+    }))
+    .unwrap();
+    let mut resolved = Resolved::default();
+    resolved.nametags.insert(
+        "33".repeat(20),
+        vec![String::from("Uniswap V2: Router 2"), String::from("dex")],
+    );
+    resolved
+        .nametags
+        .insert("22".repeat(20), vec![String::from("EOA")]);
+
+    let found = search_names("UNISWAP", &labels, &resolved);
+    let addresses: Vec<H160> = found.iter().map(|m| m.address).collect();
+    assert_eq!(
+        addresses,
+        [H160::repeat_byte(0x11), H160::repeat_byte(0x33)]
+    );
+    assert_eq!(found[1].names, ["Uniswap V2: Router 2", "dex"]);
+    // User labels come before the nametags of the same address.
+    let alice = search_names("eoa", &labels, &resolved);
+    assert_eq!(alice[0].names, ["Alice", "EOA"]);
+    assert!(search_names("curve", &labels, &resolved).is_empty());
+}
+
+#[test]
+fn signatures_from_abis_and_resolved() {
+    let abi = r#"[
+        {"type":"function","name":"transferFrom","inputs":[
+            {"type":"address"},{"type":"address"},{"type":"uint256"}]},
+        {"type":"function","name":"approve","inputs":[{"type":"address"},{"type":"uint256"}]},
+        {"type":"event","name":"Transfer","inputs":[
+            {"type":"address","indexed":true},{"type":"address","indexed":true},
+            {"type":"uint256","indexed":false}]}
+    ]"#;
+    let mut resolved = Resolved::default();
+    resolved.signatures.insert(
+        String::from("a9059cbb"),
+        String::from("transfer(address,uint256)"),
+    );
+    let found = search_signatures("transfer(", &resolved, &[abi.to_string()]);
+    let texts: Vec<&str> = found.iter().map(|m| m.text.as_str()).collect();
+    assert_eq!(
+        texts,
+        [
+            "Transfer(address,address,uint256)",
+            "transfer(address,uint256)"
+        ]
+    );
+    assert_eq!(found[0].kind, SignatureKind::Event);
+    assert!(found[0]
+        .to_string()
+        .starts_with("0xddf252ad1be2c89b69c2b068fc378daa952ba7f163c4a11628f55a4df523b3ef"));
+    assert!(found[1].to_string().starts_with("0xa9059cbb  transfer("));
+    let found = search_signatures("from", &resolved, &[abi.to_string()]);
+    assert_eq!(found[0].text, "transferFrom(address,address,uint256)");
+    assert_eq!(found[0].kind, SignatureKind::Function);
+    // A complete signature is hashed even if it is not known.
+    let found = search_signatures("deposit()", &resolved, &[]);
+    assert_eq!(
+        found[0].to_string(),
+        format!("0xd0e30db0  deposit()  (topic {:?})", topic_of("deposit()"))
+    );
+}
@@ -0,0 +1,1376 @@
+//! Interprets decoded transactions as human readable actions.
+//!
+//! Recognizers are applied to each transaction in order, the first one
+//! that recognises a pattern in the events or calldata provides the headline
+//! for that transaction. E.g., "Sent 1.5 WETH to 0xabcd…1234".
+use std::{
+    collections::{HashMap, HashSet},
+    fmt::{Debug, Display},
+    sync::Arc,
+};
+
+use serde::{Deserialize, Serialize};
+use web3::types::{H160, U256};
+
+use crate::{
+    abi::{data_word, dynamic_bytes, topic_address, topic_of, word_address},
+    chains::MAINNET,
+    data::{Contract, LoggedEvent, TxInfo},
+    interfaces::Erc,
+    labels::UserLabels,
+    safe::safe_rule,
+    user_operation::user_operation_rule,
+};
+
+/// ERC-20 and ERC-721 token transfer.
+pub const TRANSFER: &str = "Transfer(address,address,uint256)";
+/// ERC-20 and ERC-721 spending approval.
+pub const APPROVAL: &str = "Approval(address,address,uint256)";
+/// WETH9 wrap of ether.
+pub const WETH_DEPOSIT: &str = "Deposit(address,uint256)";
+/// WETH9 unwrap, also emitted before a router forwards ether.
+pub const WETH_WITHDRAWAL: &str = "Withdrawal(address,uint256)";
+/// Optimism (and OP stack) L1 standard bridge ether deposit.
+pub const OP_ETH_DEPOSIT: &str = "ETHDepositInitiated(address,address,uint256,bytes)";
+/// Optimism L1 standard bridge token deposit.
+pub const OP_ERC20_DEPOSIT: &str =
+    "ERC20DepositInitiated(address,address,address,address,uint256,bytes)";
+/// Optimism L1 standard bridge ether withdrawal.
+pub const OP_ETH_WITHDRAWAL: &str = "ETHWithdrawalFinalized(address,address,uint256,bytes)";
+/// Optimism L1 standard bridge token withdrawal.
+pub const OP_ERC20_WITHDRAWAL: &str =
+    "ERC20WithdrawalFinalized(address,address,address,address,uint256,bytes)";
+/// Arbitrum inbox message, emitted for ether deposits.
+pub const ARB_INBOX_MESSAGE: &str = "InboxMessageDelivered(uint256,bytes)";
+/// Arbitrum gateway token deposit.
+pub const ARB_ERC20_DEPOSIT: &str = "DepositInitiated(address,address,address,uint256,uint256)";
+/// Arbitrum outbox execution of a withdrawal.
+pub const ARB_OUTBOX_EXECUTED: &str = "OutBoxTransactionExecuted(address,address,uint256,uint256)";
+/// Polygon PoS ether predicate deposit.
+pub const POLYGON_LOCKED_ETHER: &str = "LockedEther(address,address,uint256)";
+/// Polygon PoS ERC-20 predicate deposit.
+pub const POLYGON_LOCKED_ERC20: &str = "LockedERC20(address,address,address,uint256)";
+/// Polygon PoS ether predicate withdrawal.
+pub const POLYGON_EXITED_ETHER: &str = "ExitedEther(address,uint256)";
+/// Polygon PoS ERC-20 predicate withdrawal.
+pub const POLYGON_EXITED_ERC20: &str = "ExitedERC20(address,address,uint256)";
+/// Uniswap V2 pair (and forks) swap.
+pub const UNISWAP_V2_SWAP: &str = "Swap(address,uint256,uint256,uint256,uint256,address)";
+/// Uniswap V3 pool swap.
+pub const UNISWAP_V3_SWAP: &str = "Swap(address,address,int256,int256,uint160,uint128,int24)";
+/// Curve pool exchange.
+pub const CURVE_TOKEN_EXCHANGE: &str = "TokenExchange(address,int128,uint256,int128,uint256)";
+/// Beacon chain deposit contract, emitted for each validator deposit.
+pub const BEACON_DEPOSIT: &str = "DepositEvent(bytes,bytes,bytes,bytes,bytes)";
+/// Lido stETH minted for deposited ether.
+pub const LIDO_SUBMITTED: &str = "Submitted(address,uint256,address)";
+/// Lido withdrawal queue request to redeem stETH.
+pub const LIDO_WITHDRAWAL_REQUESTED: &str =
+    "WithdrawalRequested(uint256,address,address,uint256,uint256)";
+/// Lido withdrawal queue payout of ether.
+pub const LIDO_WITHDRAWAL_CLAIMED: &str = "WithdrawalClaimed(uint256,address,address,uint256)";
+/// Rocket Pool rETH minted for deposited ether.
+pub const ROCKET_TOKENS_MINTED: &str = "TokensMinted(address,uint256,uint256,uint256)";
+/// Rocket Pool rETH burned for ether.
+pub const ROCKET_TOKENS_BURNED: &str = "TokensBurned(address,uint256,uint256,uint256)";
+
+/// Beacon chain deposit contract (lowercase hex without "0x", as are the
+/// contracts below).
+const BEACON_DEPOSIT_CONTRACT: &str = "00000000219ab540356cbb839cbe05303d7705fa";
+/// Lido stETH, which emits Submitted.
+const LIDO_STETH: &str = "ae7ab96520de3a18e5e111b5eaab095312d7fe84";
+/// Lido withdrawal queue (unstETH).
+const LIDO_WITHDRAWAL_QUEUE: &str = "889edc2edab5f40e902b864ad4d7ade8e412f9b1";
+/// Rocket Pool rETH, which emits TokensMinted and TokensBurned.
+const ROCKET_RETH: &str = "ae78736cd615f374d3085123a210448e74fc6393";
+
+/// WETH9 on each chain, by the name of the chain.
+const WETH_CONTRACTS: [(&str, &str); 5] = [
+    (MAINNET, "c02aaa39b223fe8d0a0e5c4f27ead9083c756cc2"),
+    ("optimism", "4200000000000000000000000000000000000006"),
+    ("base", "4200000000000000000000000000000000000006"),
+    ("arbitrum", "82af49447d8a07e3bd95bd0d56f35241523fbab1"),
+    ("sepolia", "fff9976782d46cc05630d1f6ebab18b2324d6b14"),
+];
+/// Optimism L1 standard bridge.
+const OPTIMISM_BRIDGE: &str = "99c9fc46f92e8a1c0dec1b1747d010903e884be1";
+/// Arbitrum One delayed inbox.
+const ARBITRUM_INBOX: &str = "4dbd4fc535ac27206064b68ffcf827b0a60bab3f";
+/// Arbitrum L1 token gateways (standard, custom and WETH).
+const ARBITRUM_GATEWAYS: [&str; 3] = [
+    "a3a7b6f88361f48403514059f1f16c8e78d60eec",
+    "cee284f754e854890e311e3280b767f80797180d",
+    "d92023e9d9911199a6711321d1277285e6d4e2db",
+];
+/// Arbitrum outboxes (Nitro and classic).
+const ARBITRUM_OUTBOXES: [&str; 2] = [
+    "0b9857ae2d4a3dbe74ffe1d7df045bb7f96e4840",
+    "760723cd2e632826c38fef8cd438a4cc7e7e1a40",
+];
+/// Polygon PoS ether predicate.
+const POLYGON_ETHER_PREDICATE: &str = "8484ef722627bf18ca5ae6bcf031c23e6e922b30";
+/// Polygon PoS ERC-20 predicate.
+const POLYGON_ERC20_PREDICATE: &str = "40ec5b33f54e0e8a33a975908c5ba1c14e5bbbdf";
+
+/// Selector of the ERC-20 transfer(address,uint256) function.
+pub const ERC20_TRANSFER: [u8; 4] = [0xa9, 0x05, 0x9c, 0xbb];
+
+/// A recognised action performed in a transaction.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
+pub enum Action {
+    /// A contract was deployed.
+    Deploy { contract: String },
+    /// Ether was deposited to the beacon chain deposit contract to fund a validator.
+    ValidatorDeposit { amount: Amount, pubkey: String },
+    /// Ether was deposited with a liquid staking protocol.
+    Stake { amount: Amount, protocol: String },
+    /// A liquid staking token was redeemed (or queued for redemption).
+    Unstake { amount: Amount, protocol: String },
+    /// Ether was paid out by a staking protocol.
+    StakingWithdrawal { amount: Amount, protocol: String },
+    /// Ether was wrapped into WETH.
+    Wrap { amount: Amount },
+    /// WETH was unwrapped into ether.
+    Unwrap { amount: Amount },
+    /// Assets moved between mainnet and another network through a canonical bridge.
+    Bridge {
+        amount: Option<Amount>,
+        network: String,
+        outgoing: bool,
+    },
+    /// One asset was exchanged for another at a decentralised exchange.
+    Swap {
+        sold: Option<Amount>,
+        bought: Option<Amount>,
+        venue: String,
+    },
+    /// Ether was sent without calldata.
+    SendEther { to: String, amount: Amount },
+    /// A non-fungible token was minted to the address.
+    MintNft { collection: String, token_id: U256 },
+    /// Fungible tokens moved into or out of the address.
+    TokenTransfer {
+        token: String,
+        /// Quantity moved, if the decimals of the token are known.
+        amount: Option<Amount>,
+        counterparty: String,
+        outgoing: bool,
+    },
+    /// The address allowed another address to spend tokens.
+    Approve { token: String, spender: String },
+    /// A Safe multisig executed a transaction, or tried to.
+    SafeTransaction {
+        safe: String,
+        /// Safe nonce, if recorded by the Safe.
+        nonce: Option<U256>,
+        success: bool,
+        /// Descriptions of the calls the Safe made, if known.
+        calls: Vec<String>,
+    },
+    /// A smart account performed an ERC-4337 user operation through a bundler.
+    UserOperation {
+        account: String,
+        /// Paymaster that paid the gas instead of the account, if any.
+        paymaster: Option<String>,
+        success: bool,
+        /// Gas cost charged for the operation.
+        fee: Amount,
+        /// Descriptions of the calls the account made, if known.
+        calls: Vec<String>,
+    },
+    /// A contract function was called, but nothing specific was recognised.
+    Call { contract: String, function: String },
+}
+
+/// A token quantity with the information required to display it.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
+pub struct Amount {
+    /// Raw integer value.
+    pub value: U256,
+    /// Decimal places used by the token.
+    pub decimals: u32,
+    /// Symbol or name of the asset.
+    pub symbol: String,
+}
+
+/// Information available to a rule about a single transaction.
+pub struct TxContext<'a> {
+    /// The address whose history is being explored, lowercase hex without "0x".
+    pub owner: &'a str,
+    /// The transaction being interpreted.
+    pub tx: &'a TxInfo,
+    /// Address labels supplied by the user.
+    pub labels: &'a UserLabels,
+    /// Decimals read from token contracts. Amounts of other tokens are omitted.
+    pub decimals: &'a HashMap<H160, u32>,
+}
+
+/// Recognises a pattern in a transaction and describes it as an action.
+///
+/// Implement this to add support for a protocol and add it to the
+/// `RecognizerRegistry` in `Config`.
+pub trait Recognizer: Send + Sync {
+    /// Unique name, used to disable the recognizer.
+    fn name(&self) -> &str;
+    /// Returns an action if the transaction matches the pattern.
+    fn recognize(&self, context: &TxContext) -> Option<Action>;
+}
+
+/// A rule produces an action if it recognises the transaction.
+type Rule = fn(&TxContext) -> Option<Action>;
+
+/// A built in recognizer backed by a function.
+struct RuleRecognizer {
+    name: &'static str,
+    rule: Rule,
+}
+
+impl Recognizer for RuleRecognizer {
+    fn name(&self) -> &str {
+        self.name
+    }
+    fn recognize(&self, context: &TxContext) -> Option<Action> {
+        (self.rule)(context)
+    }
+}
+
+/// Ordered collection of recognizers. The first to match a transaction provides its action.
+#[derive(Clone)]
+pub struct RecognizerRegistry {
+    /// Recognizers in order of precedence.
+    recognizers: Vec<Arc<dyn Recognizer>>,
+    /// Names of recognizers that are skipped.
+    disabled: HashSet<String>,
+}
+
+impl Default for RecognizerRegistry {
+    /// Built in recognizers, more specific ones first.
+    fn default() -> Self {
+        let rules: [(&'static str, Rule); 13] = [
+            ("deployment", deployment_rule),
+            ("user-operation", user_operation_rule),
+            ("safe", safe_rule),
+            ("beacon-deposit", beacon_deposit_rule),
+            ("liquid-staking", liquid_staking_rule),
+            ("bridge", bridge_rule),
+            ("swap", swap_rule),
+            ("weth", weth_rule),
+            ("nft-mint", nft_mint_rule),
+            ("token-transfer", token_transfer_rule),
+            ("approval", approval_rule),
+            ("ether-transfer", ether_transfer_rule),
+            ("contract-call", contract_call_rule),
+        ];
+        let recognizers = rules
+            .into_iter()
+            .map(|(name, rule)| Arc::new(RuleRecognizer { name, rule }) as Arc<dyn Recognizer>)
+            .collect();
+        RecognizerRegistry {
+            recognizers,
+            disabled: HashSet::new(),
+        }
+    }
+}
+
+impl RecognizerRegistry {
+    /// Adds a recognizer ahead of the built in ones, so it takes precedence.
+    pub fn register(&mut self, recognizer: Arc<dyn Recognizer>) -> &mut Self {
+        self.recognizers.insert(0, recognizer);
+        self
+    }
+    /// Skips the recognizer with the given name.
+    pub fn disable(&mut self, name: &str) -> &mut Self {
+        self.disabled.insert(name.to_string());
+        self
+    }
+    /// Names of the recognizers in order, with whether each is enabled.
+    pub fn names(&self) -> Vec<(String, bool)> {
+        self.recognizers
+            .iter()
+            .map(|r| (r.name().to_string(), !self.disabled.contains(r.name())))
+            .collect()
+    }
+    /// Returns the first action recognised for a transaction.
+    pub fn describe(
+        &self,
+        owner: &str,
+        tx: &TxInfo,
+        labels: &UserLabels,
+        decimals: &HashMap<H160, u32>,
+    ) -> Option<Action> {
+        let context = TxContext {
+            owner: owner.trim_start_matches("0x"),
+            tx,
+            labels,
+            decimals,
+        };
+        self.recognizers
+            .iter()
+            .filter(|r| !self.disabled.contains(r.name()))
+            .find_map(|r| r.recognize(&context))
+    }
+}
+
+impl Debug for RecognizerRegistry {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_list().entries(self.names()).finish()
+    }
+}
+
+impl PartialEq for RecognizerRegistry {
+    fn eq(&self, other: &Self) -> bool {
+        self.names() == other.names()
+    }
+}
+
+impl TxContext<'_> {
+    /// Events emitted during the transaction.
+    pub fn events(&self) -> &[LoggedEvent] {
+        self.tx.events.as_deref().unwrap_or_default()
+    }
+    /// Events with the given text signature, e.g., "Transfer(address,address,uint256)".
+    pub fn events_with_signature(&self, signature: &str) -> Vec<&LoggedEvent> {
+        let topic = topic_of(signature);
+        self.events()
+            .iter()
+            .filter(|e| e.raw.topics.first() == Some(&topic))
+            .collect()
+    }
+    /// Events with the given signature emitted by one of the contracts
+    /// (lowercase hex without "0x"). Others may emit the same event.
+    pub fn events_from(&self, signature: &str, contracts: &[&str]) -> Vec<&LoggedEvent> {
+        self.events_with_signature(signature)
+            .into_iter()
+            .filter(|e| contracts.contains(&hex::encode(e.raw.address).as_str()))
+            .collect()
+    }
+    /// WETH9 on the chain of the transaction, if known.
+    pub fn weth(&self) -> Option<&'static str> {
+        let chain = match self.tx.chain.is_empty() {
+            true => MAINNET,
+            false => &self.tx.chain,
+        };
+        WETH_CONTRACTS
+            .iter()
+            .find(|(name, _)| *name == chain)
+            .map(|(_, contract)| *contract)
+    }
+    /// True if the address is the owner of the history.
+    pub fn is_owner(&self, address: &H160) -> bool {
+        hex::encode(address) == self.owner
+    }
+    /// A readable name for an address using user labels, then nametags seen
+    /// in the transaction.
+    pub fn label(&self, address: &H160) -> String {
+        if self.is_owner(address) {
+            return String::from("Self");
+        }
+        if let Some(label) = self.labels.get(address).and_then(|l| l.first()) {
+            return label.to_owned();
+        }
+        let hex_address = hex::encode(address);
+        self.events()
+            .iter()
+            .find(|e| e.contract.address == hex_address)
+            .and_then(|e| e.nametags.as_ref())
+            .and_then(|tags| tags.first())
+            .filter(|tag| !tag.is_empty())
+            .map(|tag| tag.to_owned())
+            .unwrap_or_else(|| short_address(address))
+    }
+    /// Amount of a token named by its label, if the decimals of the token
+    /// are known.
+    pub fn token_amount(&self, value: U256, token: &H160) -> Option<Amount> {
+        Some(Amount {
+            value,
+            decimals: *self.decimals.get(token)?,
+            symbol: self.label(token),
+        })
+    }
+}
+
+/// Describes a call made by a contract on behalf of the address, e.g.,
+/// "transfer 10 ETH to 0xabcd…1234".
+pub fn describe_call(context: &TxContext, to: &H160, value: U256, data: &[u8]) -> String {
+    let target = context.label(to);
+    let Some(selector) = data.get(..4) else {
+        return format!("transfer {} to {}", ether_amount(value), target);
+    };
+    let args = &data[4..];
+    if selector == ERC20_TRANSFER {
+        if let (Some(recipient), Some(amount)) = (word_address(args, 0), data_word(args, 1)) {
+            let sent = match context.token_amount(amount, to) {
+                Some(amount) => amount.to_string(),
+                None => target,
+            };
+            return format!("transfer {} to {}", sent, context.label(&recipient));
+        }
+    }
+    let mut description = format!("call {} on {}", hex::encode(selector), target);
+    if !value.is_zero() {
+        description.push_str(&format!(" with {}", ether_amount(value)));
+    }
+    description
+}
+
+/// True if the contract is known to be ERC-721 and not also a fungible
+/// token (ERC-20 or ERC-777).
+///
+/// These standards use the same Transfer event.
+pub fn is_nft_only(contract: &Contract) -> bool {
+    let fungible = [Erc::Erc20, Erc::Erc777];
+    contract.interfaces.contains(&Erc::Erc721)
+        && !fungible.iter().any(|erc| contract.interfaces.contains(erc))
+}
+
+/// "0xabcd…1234" form of an address.
+pub fn short_address(address: &H160) -> String {
+    let a = hex::encode(address);
+    format!("0x{}…{}", &a[..4], &a[36..])
+}
+
+/// Amount of a liquid staking token, all of which use 18 decimals like ether.
+fn staked_amount(value: U256, symbol: &str) -> Amount {
+    Amount {
+        value,
+        decimals: 18,
+        symbol: symbol.to_string(),
+    }
+}
+
+/// Amount for a quantity of wei.
+pub fn ether_amount(value: U256) -> Amount {
+    Amount {
+        value,
+        decimals: 18,
+        symbol: String::from("ETH"),
+    }
+}
+
+fn deployment_rule(context: &TxContext) -> Option<Action> {
+    let contract = context.tx.receipt.as_ref()?.contract_address?;
+    Some(Action::Deploy {
+        contract: short_address(&contract),
+    })
+}
+
+/// Recognises deposits to the beacon chain deposit contract.
+///
+/// The event data holds five dynamic byte arrays: pubkey, withdrawal credentials,
+/// amount (little endian gwei), signature and index.
+fn beacon_deposit_rule(context: &TxContext) -> Option<Action> {
+    let deposits = context.events_from(BEACON_DEPOSIT, &[BEACON_DEPOSIT_CONTRACT]);
+    let mut total_gwei: u64 = 0;
+    let mut pubkey = None;
+    for e in &deposits {
+        let data = &e.raw.data.0;
+        let pubkey_bytes = dynamic_bytes(data, 0)?;
+        let amount_bytes: [u8; 8] = dynamic_bytes(data, 2)?.try_into().ok()?;
+        total_gwei += u64::from_le_bytes(amount_bytes);
+        pubkey.get_or_insert_with(|| {
+            let key = hex::encode(pubkey_bytes);
+            format!("0x{}…", &key[..key.len().min(8)])
+        });
+    }
+    let pubkey = match deposits.len() {
+        0 => return None,
+        1 => pubkey?,
+        n => format!("{} validators", n),
+    };
+    Some(Action::ValidatorDeposit {
+        amount: ether_amount(U256::from(total_gwei) * U256::exp10(9)),
+        pubkey,
+    })
+}
+
+/// Recognises Lido and Rocket Pool deposits and redemptions by the address.
+fn liquid_staking_rule(context: &TxContext) -> Option<Action> {
+    let owned_by_user = |e: &&LoggedEvent, topic: usize| {
+        e.raw
+            .topics
+            .get(topic)
+            .map(|t| context.is_owner(&topic_address(t)))
+            .unwrap_or(false)
+    };
+    let first_word = |signature: &str, contract: &str, topic: usize, word: usize| {
+        context
+            .events_from(signature, &[contract])
+            .into_iter()
+            .find(|e| owned_by_user(e, topic))
+            .and_then(|e| data_word(&e.raw.data.0, word))
+    };
+    let lido = String::from("Lido");
+    let rocket_pool = String::from("Rocket Pool");
+    if let Some(value) = first_word(LIDO_SUBMITTED, LIDO_STETH, 1, 0) {
+        return Some(Action::Stake {
+            amount: ether_amount(value),
+            protocol: lido,
+        });
+    }
+    if let Some(value) = first_word(LIDO_WITHDRAWAL_REQUESTED, LIDO_WITHDRAWAL_QUEUE, 3, 0) {
+        return Some(Action::Unstake {
+            amount: staked_amount(value, "stETH"),
+            protocol: lido,
+        });
+    }
+    if let Some(value) = first_word(LIDO_WITHDRAWAL_CLAIMED, LIDO_WITHDRAWAL_QUEUE, 2, 0) {
+        return Some(Action::StakingWithdrawal {
+            amount: ether_amount(value),
+            protocol: lido,
+        });
+    }
+    // Rocket Pool events carry (rETH amount, ETH amount, time).
+    if let Some(value) = first_word(ROCKET_TOKENS_MINTED, ROCKET_RETH, 1, 1) {
+        return Some(Action::Stake {
+            amount: ether_amount(value),
+            protocol: rocket_pool,
+        });
+    }
+    if let Some(value) = first_word(ROCKET_TOKENS_BURNED, ROCKET_RETH, 1, 0) {
+        return Some(Action::Unstake {
+            amount: staked_amount(value, "rETH"),
+            protocol: rocket_pool,
+        });
+    }
+    None
+}
+
+/// Recognises canonical bridge deposits to and withdrawals from L2 networks.
+///
+/// Each entry is (signature, emitting contracts, network, outgoing, topic
+/// holding the user, amount).
+fn bridge_rule(context: &TxContext) -> Option<Action> {
+    // Where to find the amount and asset for each bridge event.
+    enum Value {
+        /// Ether amount in the nth data word.
+        Ether(usize),
+        /// Token (address in topic) amount in the nth data word.
+        TokenInTopic(usize, usize),
+        /// Token (address in data word) amount in the nth data word.
+        TokenInData(usize, usize),
+        /// Not available from the event.
+        Unknown,
+    }
+    let optimism: &[&str] = &[OPTIMISM_BRIDGE];
+    let bridges = [
+        (
+            OP_ETH_DEPOSIT,
+            optimism,
+            "Optimism",
+            true,
+            1,
+            Value::Ether(0),
+        ),
+        (
+            OP_ERC20_DEPOSIT,
+            optimism,
+            "Optimism",
+            true,
+            3,
+            Value::TokenInTopic(1, 1),
+        ),
+        (
+            OP_ETH_WITHDRAWAL,
+            optimism,
+            "Optimism",
+            false,
+            2,
+            Value::Ether(0),
+        ),
+        (
+            OP_ERC20_WITHDRAWAL,
+            optimism,
+            "Optimism",
+            false,
+            3,
+            Value::TokenInTopic(1, 1),
+        ),
+        (
+            ARB_ERC20_DEPOSIT,
+            &ARBITRUM_GATEWAYS,
+            "Arbitrum",
+            true,
+            1,
+            Value::TokenInData(0, 1),
+        ),
+        (
+            ARB_OUTBOX_EXECUTED,
+            &ARBITRUM_OUTBOXES,
+            "Arbitrum",
+            false,
+            1,
+            Value::Unknown,
+        ),
+        (
+            POLYGON_LOCKED_ETHER,
+            &[POLYGON_ETHER_PREDICATE],
+            "Polygon",
+            true,
+            1,
+            Value::Ether(0),
+        ),
+        (
+            POLYGON_LOCKED_ERC20,
+            &[POLYGON_ERC20_PREDICATE],
+            "Polygon",
+            true,
+            1,
+            Value::TokenInTopic(3, 0),
+        ),
+        (
+            POLYGON_EXITED_ETHER,
+            &[POLYGON_ETHER_PREDICATE],
+            "Polygon",
+            false,
+            1,
+            Value::Ether(0),
+        ),
+        (
+            POLYGON_EXITED_ERC20,
+            &[POLYGON_ERC20_PREDICATE],
+            "Polygon",
+            false,
+            1,
+            Value::TokenInTopic(2, 0),
+        ),
+    ];
+    for (signature, contracts, network, outgoing, owner_topic, value) in bridges {
+        let found = context
+            .events_from(signature, contracts)
+            .into_iter()
+            .find(|e| {
+                e.raw
+                    .topics
+                    .get(owner_topic)
+                    .map(|t| context.is_owner(&topic_address(t)))
+                    .unwrap_or(false)
+            });
+        let Some(e) = found else { continue };
+        let data = &e.raw.data.0;
+        let amount = match value {
+            Value::Ether(word) => data_word(data, word).map(ether_amount),
+            Value::TokenInTopic(topic, word) => {
+                let token = e.raw.topics.get(topic).map(topic_address);
+                token
+                    .zip(data_word(data, word))
+                    .and_then(|(token, amount)| context.token_amount(amount, &token))
+            }
+            Value::TokenInData(token_word, word) => {
+                let token = data.get(token_word * 32 + 12..(token_word + 1) * 32);
+                token
+                    .zip(data_word(data, word))
+                    .and_then(|(token, amount)| {
+                        context.token_amount(amount, &H160::from_slice(token))
+                    })
+            }
+            Value::Unknown => None,
+        };
+        return Some(Action::Bridge {
+            amount,
+            network: network.to_string(),
+            outgoing,
+        });
+    }
+    // Arbitrum ether deposits only identify the user through the transaction itself.
+    let desc = context.tx.description.as_ref()?;
+    let sent_by_owner = desc.from.map(|f| context.is_owner(&f)).unwrap_or(false);
+    let inbox_message = context.events_from(ARB_INBOX_MESSAGE, &[ARBITRUM_INBOX]);
+    if sent_by_owner && !inbox_message.is_empty() {
+        return Some(Action::Bridge {
+            amount: Some(ether_amount(desc.value)).filter(|a| !a.value.is_zero()),
+            network: String::from("Arbitrum"),
+            outgoing: true,
+        });
+    }
+    None
+}
+
+/// Recognises ether wrapped or unwrapped directly by the address, at the
+/// WETH9 contract of the chain.
+fn weth_rule(context: &TxContext) -> Option<Action> {
+    let weth = [context.weth()?];
+    let by_owner = |signature: &str| {
+        context
+            .events_from(signature, &weth)
+            .into_iter()
+            .find(|e| {
+                e.raw
+                    .topics
+                    .get(1)
+                    .map(|t| context.is_owner(&topic_address(t)))
+                    .unwrap_or(false)
+            })
+            .and_then(|e| data_word(&e.raw.data.0, 0))
+            .map(ether_amount)
+    };
+    if let Some(amount) = by_owner(WETH_DEPOSIT) {
+        return Some(Action::Wrap { amount });
+    }
+    by_owner(WETH_WITHDRAWAL).map(|amount| Action::Unwrap { amount })
+}
+
+/// Recognises DEX swap events and composes the assets that left and entered the address.
+///
+/// The swap events themselves do not name the tokens, so the amounts are taken from
+/// token transfers involving the address, the ether value of the transaction and
+/// WETH unwrapping (for swaps that return ether). Token amounts are omitted if
+/// the decimals of the token are not known.
+fn swap_rule(context: &TxContext) -> Option<Action> {
+    let venues = [
+        (UNISWAP_V2_SWAP, "Uniswap V2"),
+        (UNISWAP_V3_SWAP, "Uniswap V3"),
+        (CURVE_TOKEN_EXCHANGE, "Curve"),
+    ];
+    let venue = venues
+        .iter()
+        .find(|(signature, _)| !context.events_with_signature(signature).is_empty())
+        .map(|(_, name)| name.to_string())?;
+
+    // Outer None if no token moved, inner None if its decimals are unknown.
+    let mut sold = None;
+    let mut bought = None;
+    for e in context.events_with_signature(TRANSFER) {
+        if e.raw.topics.len() != 3 {
+            continue;
+        }
+        let Some(value) = data_word(&e.raw.data.0, 0) else {
+            continue;
+        };
+        let amount = context.token_amount(value, &e.raw.address);
+        if sold.is_none() && context.is_owner(&topic_address(&e.raw.topics[1])) {
+            sold = Some(amount);
+        } else if context.is_owner(&topic_address(&e.raw.topics[2])) {
+            // The last incoming transfer is the final output of a multi-hop route.
+            bought = Some(amount);
+        }
+    }
+    if sold.is_none() {
+        sold = context
+            .tx
+            .description
+            .as_ref()
+            .filter(|desc| !desc.value.is_zero())
+            .map(|desc| Some(ether_amount(desc.value)));
+    }
+    if bought.is_none() {
+        bought = context
+            .weth()
+            .and_then(|weth| {
+                context
+                    .events_from(WETH_WITHDRAWAL, &[weth])
+                    .last()
+                    .and_then(|e| data_word(&e.raw.data.0, 0))
+            })
+            .map(|value| Some(ether_amount(value)));
+    }
+    Some(Action::Swap {
+        sold: sold.flatten(),
+        bought: bought.flatten(),
+        venue,
+    })
+}
+
+/// Early ERC-721 contracts do not index the token id, so it is read from the
+/// data if the contract is known to be ERC-721.
+fn nft_mint_rule(context: &TxContext) -> Option<Action> {
+    context
+        .events_with_signature(TRANSFER)
+        .into_iter()
+        .filter(|e| {
+            e.raw.topics.len() == 4 || (e.raw.topics.len() == 3 && is_nft_only(&e.contract))
+        })
+        .find(|e| {
+            topic_address(&e.raw.topics[1]).is_zero()
+                && context.is_owner(&topic_address(&e.raw.topics[2]))
+        })
+        .and_then(|e| {
+            let token_id = match e.raw.topics.get(3) {
+                Some(topic) => U256::from_big_endian(topic.as_bytes()),
+                None => data_word(&e.raw.data.0, 0)?,
+            };
+            Some(Action::MintNft {
+                collection: context.label(&e.raw.address),
+                token_id,
+            })
+        })
+}
+
+fn token_transfer_rule(context: &TxContext) -> Option<Action> {
+    context
+        .events_with_signature(TRANSFER)
+        .into_iter()
+        .filter(|e| e.raw.topics.len() == 3 && !is_nft_only(&e.contract))
+        .find_map(|e| {
+            let from = topic_address(&e.raw.topics[1]);
+            let to = topic_address(&e.raw.topics[2]);
+            let value = data_word(&e.raw.data.0, 0)?;
+            let token = context.label(&e.raw.address);
+            let amount = context.token_amount(value, &e.raw.address);
+            if context.is_owner(&from) {
+                Some(Action::TokenTransfer {
+                    token,
+                    amount,
+                    counterparty: context.label(&to),
+                    outgoing: true,
+                })
+            } else if context.is_owner(&to) {
+                Some(Action::TokenTransfer {
+                    token,
+                    amount,
+                    counterparty: context.label(&from),
+                    outgoing: false,
+                })
+            } else {
+                None
+            }
+        })
+}
+
+fn approval_rule(context: &TxContext) -> Option<Action> {
+    context
+        .events_with_signature(APPROVAL)
+        .into_iter()
+        .find(|e| e.raw.topics.len() >= 3 && context.is_owner(&topic_address(&e.raw.topics[1])))
+        .map(|e| Action::Approve {
+            token: context.label(&e.raw.address),
+            spender: context.label(&topic_address(&e.raw.topics[2])),
+        })
+}
+
+fn ether_transfer_rule(context: &TxContext) -> Option<Action> {
+    let desc = context.tx.description.as_ref()?;
+    if !desc.input.0.is_empty() || desc.value.is_zero() {
+        return None;
+    }
+    Some(Action::SendEther {
+        to: context.label(&desc.to?),
+        amount: ether_amount(desc.value),
+    })
+}
+
+fn contract_call_rule(context: &TxContext) -> Option<Action> {
+    let desc = context.tx.description.as_ref()?;
+    let selector = desc.input.0.get(..4)?;
+    let function = match context.tx.call.as_ref().and_then(|c| c.signature.as_ref()) {
+        Some(signature) => signature.to_owned(),
+        None => hex::encode(selector),
+    };
+    Some(Action::Call {
+        contract: context.label(&desc.to?),
+        function,
+    })
+}
+
+impl Display for Amount {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let unit = U256::exp10(self.decimals as usize);
+        let whole = self.value / unit;
+        // Show up to 4 decimal places, without trailing zeros.
+        let fraction: U256 = (self.value % unit) * 10_000 / unit;
+        let fraction = format!("{:04}", fraction.as_u32());
+        let fraction = fraction.trim_end_matches('0');
+        if fraction.is_empty() {
+            write!(f, "{} {}", whole, self.symbol)
+        } else {
+            write!(f, "{}.{} {}", whole, fraction, self.symbol)
+        }
+    }
+}
+
+impl Display for Action {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Action::Deploy { contract } => write!(f, "Deployed contract {}", contract),
+            Action::ValidatorDeposit { amount, pubkey } => {
+                write!(f, "Deposited {} to validator {}", amount, pubkey)
+            }
+            Action::Stake { amount, protocol } => {
+                write!(f, "Staked {} with {}", amount, protocol)
+            }
+            Action::Unstake { amount, protocol } => {
+                write!(f, "Unstaked {} from {}", amount, protocol)
+            }
+            Action::Wrap { amount } => write!(f, "Wrapped {} into WETH", amount),
+            Action::Unwrap { amount } => write!(f, "Unwrapped {} from WETH", amount),
+            Action::Bridge {
+                amount,
+                network,
+                outgoing,
+            } => {
+                let amount = match amount {
+                    Some(a) => a.to_string(),
+                    None => String::from("assets"),
+                };
+                let direction = if *outgoing { "to" } else { "from" };
+                write!(f, "Bridged {} {} {}", amount, direction, network)
+            }
+            Action::StakingWithdrawal { amount, protocol } => {
+                write!(f, "Withdrew {} from {}", amount, protocol)
+            }
+            Action::Swap {
+                sold: Some(sold),
+                bought: Some(bought),
+                venue,
+            } => write!(f, "Swap {} → {} on {}", sold, bought, venue),
+            Action::Swap {
+                sold: Some(sold),
+                bought: None,
+                venue,
+            } => write!(f, "Swap {} on {}", sold, venue),
+            Action::Swap {
+                sold: None,
+                bought: Some(bought),
+                venue,
+            } => write!(f, "Swap for {} on {}", bought, venue),
+            Action::Swap { venue, .. } => write!(f, "Swap on {}", venue),
+            Action::SendEther { to, amount } => write!(f, "Sent {} to {}", amount, to),
+            Action::MintNft {
+                collection,
+                token_id,
+            } => write!(f, "Mint NFT #{} from {}", token_id, collection),
+            Action::TokenTransfer {
+                token,
+                amount,
+                counterparty,
+                outgoing,
+            } => {
+                let moved = match amount {
+                    Some(a) => a.to_string(),
+                    None => token.to_owned(),
+                };
+                match outgoing {
+                    true => write!(f, "Sent {} to {}", moved, counterparty),
+                    false => write!(f, "Received {} from {}", moved, counterparty),
+                }
+            }
+            Action::Approve { token, spender } => {
+                write!(f, "Approved {} to spend {}", spender, token)
+            }
+            Action::SafeTransaction {
+                safe,
+                nonce,
+                success,
+                calls,
+            } => {
+                let outcome = if *success { "Executed" } else { "Failed" };
+                write!(f, "{} Safe tx", outcome)?;
+                if let Some(nonce) = nonce {
+                    write!(f, " #{}", nonce)?;
+                }
+                write!(f, " on {}", safe)?;
+                if !calls.is_empty() {
+                    write!(f, ": {}", calls.join("; "))?;
+                }
+                Ok(())
+            }
+            Action::UserOperation {
+                account,
+                paymaster,
+                success,
+                fee,
+                calls,
+            } => {
+                if !*success {
+                    write!(f, "Failed ")?;
+                }
+                let noun = if *success { "User" } else { "user" };
+                write!(f, "{} operation from {}", noun, account)?;
+                if !calls.is_empty() {
+                    write!(f, ": {}", calls.join("; "))?;
+                }
+                match paymaster {
+                    Some(paymaster) => write!(f, " (fee {} paid by {})", fee, paymaster),
+                    None => write!(f, " (fee {})", fee),
+                }
+            }
+            Action::Call { contract, function } => {
+                write!(f, "Called {} (function {})", contract, function)
+            }
+        }
+    }
+}
+
+#[test]
+fn amount_display() {
+    let one_and_half = U256::from(15) * U256::exp10(17);
+    assert_eq!(
+        staked_amount(one_and_half, "stETH").to_string(),
+        "1.5 stETH"
+    );
+    let usdc = Amount {
+        value: U256::from(1_800_000_000u64),
+        decimals: 6,
+        symbol: String::from("USDC"),
+    };
+    assert_eq!(usdc.to_string(), "1800 USDC");
+    assert_eq!(ether_amount(U256::from(1)).to_string(), "0 ETH");
+}
+
+#[test]
+fn transfer_topic() {
+    assert_eq!(
+        hex::encode(topic_of(TRANSFER)),
+        "ddf252ad1be2c89b69c2b068fc378daa952ba7f163c4a11628f55a4df523b3ef"
+    );
+}
+
+/// Builds an event emitted by a contract for tests.
+#[cfg(test)]
+pub(crate) fn test_event(
+    contract: &str,
+    signature: &str,
+    topics: &[web3::types::H256],
+    data: Vec<u8>,
+) -> LoggedEvent {
+    let mut all_topics = vec![topic_of(signature)];
+    all_topics.extend_from_slice(topics);
+    LoggedEvent {
+        raw: Arc::new(web3::types::Log {
+            address: contract.parse().unwrap(),
+            topics: all_topics,
+            data: web3::types::Bytes(data),
+            block_hash: None,
+            block_number: None,
+            transaction_hash: None,
+            transaction_index: None,
+            log_index: None,
+            transaction_log_index: None,
+            log_type: None,
+            removed: None,
+        }),
+        topic_zero: String::new(),
+        contract: crate::data::Contract {
+            address: contract.to_string(),
+            ..Default::default()
+        },
+        name: None,
+        name_source: None,
+        nametags: None,
+        topic_nametags: Default::default(),
+        spam: false,
+        log_index: None,
+        transaction_index: None,
+        warnings: vec![],
+    }
+}
+
+#[test]
+fn uniswap_v2_swap_from_ether() {
+    use web3::types::H256;
+
+    let owner: H160 = "846be97d3bf1e3865f3caf55d749864d39e54cb9".parse().unwrap();
+    let pair: H160 = "1636a5dfcf7a21945c06d1bea40b52ce975ea614".parse().unwrap();
+    let mut amount = [0u8; 32];
+    (U256::from(5) * U256::exp10(18)).to_big_endian(&mut amount);
+    let events = vec![
+        test_event(
+            "106d3c66d22d2dd0446df23d7f5960752994d600",
+            TRANSFER,
+            &[H256::from(pair), H256::from(owner)],
+            amount.to_vec(),
+        ),
+        test_event(
+            "1636a5dfcf7a21945c06d1bea40b52ce975ea614",
+            UNISWAP_V2_SWAP,
+            &[H256::zero(), H256::from(owner)],
+            vec![0; 128],
+        ),
+    ];
+    let tx = TxInfo {
+        description: Some(web3::types::Transaction {
+            from: Some(owner),
+            value: U256::from(14) * U256::exp10(16),
+            input: web3::types::Bytes(vec![0x7f, 0xf3, 0x6a, 0xb5]),
+            ..Default::default()
+        }),
+        events: Some(events),
+        ..Default::default()
+    };
+    let describe = |decimals: &HashMap<H160, u32>| {
+        RecognizerRegistry::default()
+            .describe(
+                "0x846be97d3bf1e3865f3caf55d749864d39e54cb9",
+                &tx,
+                &UserLabels::default(),
+                decimals,
+            )
+            .unwrap()
+            .to_string()
+    };
+    let token: H160 = "106d3c66d22d2dd0446df23d7f5960752994d600".parse().unwrap();
+    assert_eq!(
+        describe(&HashMap::from([(token, 18)])),
+        "Swap 0.14 ETH → 5 0x106d…d600 on Uniswap V2"
+    );
+    // Without the decimals of the token its amount is left out.
+    assert_eq!(describe(&HashMap::new()), "Swap 0.14 ETH on Uniswap V2");
+}
+
+#[test]
+fn token_transfer_needs_decimals() {
+    use web3::types::H256;
+
+    let owner: H160 = "846be97d3bf1e3865f3caf55d749864d39e54cb9".parse().unwrap();
+    let friend = H160::repeat_byte(0xbb);
+    let token = H160::repeat_byte(0x11);
+    let mut amount = [0u8; 32];
+    U256::from(2_500_000u64).to_big_endian(&mut amount);
+    let tx = TxInfo {
+        events: Some(vec![test_event(
+            &hex::encode(token),
+            TRANSFER,
+            &[H256::from(owner), H256::from(friend)],
+            amount.to_vec(),
+        )]),
+        ..Default::default()
+    };
+    let describe = |decimals: &HashMap<H160, u32>| {
+        RecognizerRegistry::default()
+            .describe(&hex::encode(owner), &tx, &UserLabels::default(), decimals)
+            .unwrap()
+            .to_string()
+    };
+    assert_eq!(
+        describe(&HashMap::from([(token, 6)])),
+        "Sent 2.5 0x1111…1111 to 0xbbbb…bbbb"
+    );
+    assert_eq!(describe(&HashMap::new()), "Sent 0x1111…1111 to 0xbbbb…bbbb");
+}
+
+#[test]
+fn beacon_deposit() {
+    // Layout: 5 offsets, then pubkey (48), credentials (32), amount (8), signature (96), index (8).
+    let mut data = vec![];
+    let parts: [Vec<u8>; 5] = [
+        vec![0xab; 48],
+        vec![0x01; 32],
+        32_000_000_000u64.to_le_bytes().to_vec(),
+        vec![0x02; 96],
+        7u64.to_le_bytes().to_vec(),
+    ];
+    let mut offset = 5 * 32;
+    let mut tails = vec![];
+    for part in &parts {
+        let mut word = [0u8; 32];
+        U256::from(offset).to_big_endian(&mut word);
+        data.extend_from_slice(&word);
+        let mut tail = vec![];
+        U256::from(part.len()).to_big_endian(&mut word);
+        tail.extend_from_slice(&word);
+        tail.extend_from_slice(part);
+        tail.resize(32 + part.len().div_ceil(32) * 32, 0);
+        offset += tail.len();
+        tails.push(tail);
+    }
+    data.extend(tails.concat());
+    let tx = TxInfo {
+        events: Some(vec![test_event(
+            "00000000219ab540356cbb839cbe05303d7705fa",
+            BEACON_DEPOSIT,
+            &[],
+            data,
+        )]),
+        ..Default::default()
+    };
+    let action = RecognizerRegistry::default()
+        .describe(
+            "0x846be97d3bf1e3865f3caf55d749864d39e54cb9",
+            &tx,
+            &UserLabels::default(),
+            &HashMap::new(),
+        )
+        .unwrap();
+    assert_eq!(
+        action.to_string(),
+        "Deposited 32 ETH to validator 0xabababab…"
+    );
+}
+
+#[test]
+fn staking_events_from_protocol_contracts() {
+    use web3::types::H256;
+
+    let owner: H160 = "846be97d3bf1e3865f3caf55d749864d39e54cb9".parse().unwrap();
+    let mut amount = vec![0; 32];
+    U256::exp10(18).to_big_endian(&mut amount);
+    amount.extend([0; 32]);
+    let submitted = |contract: &str| TxInfo {
+        events: Some(vec![test_event(
+            contract,
+            LIDO_SUBMITTED,
+            &[H256::from(owner)],
+            amount.clone(),
+        )]),
+        ..Default::default()
+    };
+    let describe = |tx: &TxInfo| {
+        RecognizerRegistry::default().describe(
+            "846be97d3bf1e3865f3caf55d749864d39e54cb9",
+            tx,
+            &UserLabels::default(),
+            &HashMap::new(),
+        )
+    };
+    assert_eq!(
+        describe(&submitted(LIDO_STETH)),
+        Some(Action::Stake {
+            amount: ether_amount(U256::exp10(18)),
+            protocol: String::from("Lido"),
+        })
+    );
+    // The same event from another contract is not a Lido deposit.
+    assert_eq!(
+        describe(&submitted("1111111111111111111111111111111111111111")),
+        None
+    );
+}
+
+#[test]
+fn weth_events_from_weth() {
+    use web3::types::H256;
+
+    let owner: H160 = "846be97d3bf1e3865f3caf55d749864d39e54cb9".parse().unwrap();
+    let mut amount = vec![0; 32];
+    U256::exp10(18).to_big_endian(&mut amount);
+    let deposit = |chain: &str, contract: &str, signature: &str| TxInfo {
+        chain: chain.to_string(),
+        events: Some(vec![test_event(
+            contract,
+            signature,
+            &[H256::from(owner)],
+            amount.clone(),
+        )]),
+        ..Default::default()
+    };
+    let describe = |tx: &TxInfo| {
+        RecognizerRegistry::default().describe(
+            "846be97d3bf1e3865f3caf55d749864d39e54cb9",
+            tx,
+            &UserLabels::default(),
+            &HashMap::new(),
+        )
+    };
+    let wrap = Some(Action::Wrap {
+        amount: ether_amount(U256::exp10(18)),
+    });
+    let mainnet_weth = "c02aaa39b223fe8d0a0e5c4f27ead9083c756cc2";
+    let op_weth = "4200000000000000000000000000000000000006";
+    assert_eq!(describe(&deposit("", mainnet_weth, WETH_DEPOSIT)), wrap);
+    assert_eq!(describe(&deposit("optimism", op_weth, WETH_DEPOSIT)), wrap);
+    assert_eq!(
+        describe(&deposit(MAINNET, mainnet_weth, WETH_WITHDRAWAL)),
+        Some(Action::Unwrap {
+            amount: ether_amount(U256::exp10(18)),
+        })
+    );
+    // Other contracts emit Deposit(address,uint256) too (e.g., vaults).
+    let vault = "1111111111111111111111111111111111111111";
+    assert_eq!(describe(&deposit(MAINNET, vault, WETH_DEPOSIT)), None);
+    assert_eq!(
+        describe(&deposit("optimism", mainnet_weth, WETH_DEPOSIT)),
+        None
+    );
+}
+
+#[test]
+fn bridge_events_from_bridges() {
+    use web3::types::H256;
+
+    let owner: H160 = "846be97d3bf1e3865f3caf55d749864d39e54cb9".parse().unwrap();
+    let mut data = vec![0; 32];
+    U256::exp10(18).to_big_endian(&mut data);
+    data.extend([0; 64]);
+    let deposit = |contract: &str| TxInfo {
+        events: Some(vec![test_event(
+            contract,
+            OP_ETH_DEPOSIT,
+            &[H256::from(owner), H256::from(owner)],
+            data.clone(),
+        )]),
+        ..Default::default()
+    };
+    let describe = |tx: &TxInfo| {
+        RecognizerRegistry::default().describe(
+            "846be97d3bf1e3865f3caf55d749864d39e54cb9",
+            tx,
+            &UserLabels::default(),
+            &HashMap::new(),
+        )
+    };
+    assert_eq!(
+        describe(&deposit(OPTIMISM_BRIDGE)),
+        Some(Action::Bridge {
+            amount: Some(ether_amount(U256::exp10(18))),
+            network: String::from("Optimism"),
+            outgoing: true,
+        })
+    );
+    assert_eq!(
+        describe(&deposit("1111111111111111111111111111111111111111")),
+        None
+    );
+}
+
+#[test]
+fn disabled_recognizer_is_skipped() {
+    let owner: H160 = "846be97d3bf1e3865f3caf55d749864d39e54cb9".parse().unwrap();
+    let tx = TxInfo {
+        description: Some(web3::types::Transaction {
+            from: Some(owner),
+            to: Some(H160::repeat_byte(0x11)),
+            value: U256::exp10(18),
+            ..Default::default()
+        }),
+        ..Default::default()
+    };
+    let mut registry = RecognizerRegistry::default();
+    let action = registry.describe(
+        "846be97d3bf1e3865f3caf55d749864d39e54cb9",
+        &tx,
+        &UserLabels::default(),
+        &HashMap::new(),
+    );
+    assert_eq!(action.unwrap().to_string(), "Sent 1 ETH to 0x1111…1111");
+    registry.disable("ether-transfer");
+    assert!(registry
+        .describe(
+            "846be97d3bf1e3865f3caf55d749864d39e54cb9",
+            &tx,
+            &UserLabels::default(),
+            &HashMap::new()
+        )
+        .is_none());
+}
+
+#[test]
+fn legacy_nft_mint() {
+    use web3::types::H256;
+
+    let owner: H160 = "846be97d3bf1e3865f3caf55d749864d39e54cb9".parse().unwrap();
+    let mut token_id = vec![0; 32];
+    U256::from(42).to_big_endian(&mut token_id);
+    let mut event = test_event(
+        "06012c8cf97bead5deae237070f9587f8e7a266d",
+        TRANSFER,
+        &[H256::zero(), H256::from(owner)],
+        token_id,
+    );
+    event.contract.interfaces = vec![Erc::Erc721];
+    let tx = TxInfo {
+        events: Some(vec![event]),
+        ..Default::default()
+    };
+    let action = RecognizerRegistry::default().describe(
+        "846be97d3bf1e3865f3caf55d749864d39e54cb9",
+        &tx,
+        &UserLabels::default(),
+        &HashMap::new(),
+    );
+    assert_eq!(
+        action,
+        Some(Action::MintNft {
+            collection: String::from("0x0601…266d"),
+            token_id: U256::from(42),
+        })
+    );
+}
@@ -0,0 +1,375 @@
+//! JSON-RPC 2.0 service, so that wallets and other local tools can use the
+//! explorer as a decoding oracle.
+//!
+//! Each request (or batch of requests) is one line of JSON, and each response
+//! is written as one line. The service listens on a TCP address (e.g.,
+//! "127.0.0.1:8645") or, for any other value, a Unix socket at that path.
+//!
+//! ## Methods
+//! - `psr_getHistory(address)`: the decoded history of an address, with the
+//!   same transaction fields as the JSON export and the stages that failed.
+//! - `psr_decodeTx(hash)`: one decoded transaction, interpreted from the
+//!   point of view of its sender.
+//! - `psr_contractInfo(address)`: metadata, ABI (and where it came from),
+//!   interfaces and nametags of a contract.
+//! - `psr_simulateCall(address, function, args, from, value)`: the return
+//!   values and gas of a call at the latest block, without sending it (see
+//!   `simulate`). The sender and value (wei, decimal) are optional.
+//! - `psr_craftTransaction(address, function, args, from, value)`: an
+//!   unsigned transaction for the call, as the fields of
+//!   eth_sendTransaction with its EIP-681 link and raw RLP (see `craft`).
+//!
+//! Parameters are given by position or by name. Requests share one cache and
+//! are handled one at a time.
+use std::{net::SocketAddr, sync::Arc};
+
+use anyhow::Result;
+use log::{info, warn};
+use serde_json::{json, Value};
+use tokio::{
+    io::{AsyncBufReadExt, AsyncRead, AsyncWrite, AsyncWriteExt, BufReader},
+    net::TcpListener,
+    sync::Mutex,
+};
+use web3::types::{H160, H256};
+
+use crate::{
+    cache::Cache,
+    chains::MAINNET,
+    craft::craft_transaction,
+    explain::Report,
+    export::{failures_json, transactions_json, warnings_json},
+    history::{examine_contract, AddressHistory, Config, Mode},
+    redact::Pseudonyms,
+    simulate::{try_call, PlannedCall},
+};
+
+/// Invalid JSON.
+const PARSE_ERROR: i64 = -32700;
+/// JSON that is not a request object.
+const INVALID_REQUEST: i64 = -32600;
+const METHOD_NOT_FOUND: i64 = -32601;
+const INVALID_PARAMS: i64 = -32602;
+/// Any error while handling a valid request (e.g., the node is unreachable).
+const SERVER_ERROR: i64 = -32000;
+
+/// State shared by all connections.
+struct Service {
+    config: Config,
+    mode: Mode,
+    cache: Mutex<Cache>,
+}
+
+/// A JSON-RPC error object.
+#[derive(Debug, PartialEq)]
+struct RpcError {
+    code: i64,
+    message: String,
+}
+
+/// Serves requests until the process is stopped.
+pub async fn serve(listen: &str, config: Config, mode: Mode) -> Result<()> {
+    let mut cache = Cache::default();
+    if config.chain == MAINNET {
+        match config.store.resolved() {
+            Ok(resolved) => cache.add_resolved(resolved),
+            Err(e) => warn!("Could not reuse resolved lookups ({})", e),
+        }
+    }
+    let service = Arc::new(Service {
+        config,
+        mode,
+        cache: Mutex::new(cache),
+    });
+    if let Ok(address) = listen.parse::<SocketAddr>() {
+        if !address.ip().is_loopback() {
+            warn!("Serving on {}, which is not a local address", address);
+        }
+        let listener = TcpListener::bind(address).await?;
+        info!("Serving JSON-RPC on {}", address);
+        loop {
+            let (stream, _) = listener.accept().await?;
+            tokio::spawn(connection(service.clone(), stream));
+        }
+    }
+    serve_unix(service, listen).await
+}
+
+#[cfg(unix)]
+async fn serve_unix(service: Arc<Service>, path: &str) -> Result<()> {
+    use anyhow::Context;
+
+    let listener = tokio::net::UnixListener::bind(path)
+        .with_context(|| format!("Could not listen on socket {}", path))?;
+    info!("Serving JSON-RPC on {}", path);
+    loop {
+        let (stream, _) = listener.accept().await?;
+        tokio::spawn(connection(service.clone(), stream));
+    }
+}
+
+#[cfg(not(unix))]
+async fn serve_unix(_service: Arc<Service>, path: &str) -> Result<()> {
+    anyhow::bail!(
+        "Expected an address to listen on (e.g., 127.0.0.1:8645), got {}",
+        path
+    )
+}
+
+/// Answers each line of a connection until it is closed.
+async fn connection(service: Arc<Service>, stream: impl AsyncRead + AsyncWrite) {
+    let (reader, mut writer) = tokio::io::split(stream);
+    let mut lines = BufReader::new(reader).lines();
+    loop {
+        let line = match lines.next_line().await {
+            Ok(Some(line)) => line,
+            Ok(None) => return,
+            Err(e) => {
+                warn!("Closing connection ({})", e);
+                return;
+            }
+        };
+        if line.trim().is_empty() {
+            continue;
+        }
+        let Some(response) = service.handle_line(&line).await else {
+            continue;
+        };
+        let written = writer.write_all(format!("{}\n", response).as_bytes()).await;
+        if let Err(e) = written {
+            warn!("Closing connection ({})", e);
+            return;
+        }
+    }
+}
+
+impl Service {
+    /// Handles a request or batch, returning the response (none if every
+    /// request was a notification).
+    async fn handle_line(&self, line: &str) -> Option<Value> {
+        let request: Value = match serde_json::from_str(line) {
+            Ok(request) => request,
+            Err(e) => {
+                let error = RpcError::new(PARSE_ERROR, format!("Parse error ({})", e));
+                return Some(error.response(Value::Null));
+            }
+        };
+        match request {
+            Value::Array(batch) if !batch.is_empty() => {
+                let mut responses = vec![];
+                for request in batch {
+                    responses.extend(self.handle(request).await);
+                }
+                (!responses.is_empty()).then_some(Value::Array(responses))
+            }
+            request => self.handle(request).await,
+        }
+    }
+    /// Handles one request, returning the response unless it is a notification.
+    async fn handle(&self, request: Value) -> Option<Value> {
+        let id = request.get("id").cloned();
+        let version = request.get("jsonrpc").and_then(Value::as_str);
+        let method = request.get("method").and_then(Value::as_str);
+        let (Some("2.0"), Some(method)) = (version, method) else {
+            let error = RpcError::new(INVALID_REQUEST, "Invalid request");
+            return Some(error.response(id.unwrap_or_default()));
+        };
+        let params = request.get("params").unwrap_or(&Value::Null);
+        let result = self.call(method, params).await;
+        let id = id?;
+        Some(match result {
+            Ok(result) => json!({"jsonrpc": "2.0", "id": id, "result": result}),
+            Err(error) => error.response(id),
+        })
+    }
+    async fn call(&self, method: &str, params: &Value) -> Result<Value, RpcError> {
+        let result = match method {
+            "psr_getHistory" => self.history(param(params, 0, "address")?).await,
+            "psr_decodeTx" => {
+                let hash = param(params, 0, "hash")?;
+                let hash = hash
+                    .trim_start_matches("0x")
+                    .parse()
+                    .map_err(|_| RpcError::new(INVALID_PARAMS, "Invalid transaction hash"))?;
+                self.decode_tx(hash).await
+            }
+            "psr_contractInfo" => {
+                let address = param(params, 0, "address")?;
+                let address = address
+                    .trim_start_matches("0x")
+                    .parse()
+                    .map_err(|_| RpcError::new(INVALID_PARAMS, "Invalid address"))?;
+                self.contract_info(&address).await
+            }
+            "psr_simulateCall" => {
+                let call = PlannedCall::parse(
+                    param(params, 0, "address")?,
+                    param(params, 1, "function")?,
+                    &string_list(params, 2, "args")?,
+                    param(params, 3, "from").ok(),
+                    param(params, 4, "value").unwrap_or("0"),
+                )
+                .map_err(|e| RpcError::new(INVALID_PARAMS, e.to_string()))?;
+                self.simulate_call(&call).await
+            }
+            "psr_craftTransaction" => {
+                let call = PlannedCall::parse(
+                    param(params, 0, "address")?,
+                    param(params, 1, "function")?,
+                    &string_list(params, 2, "args")?,
+                    param(params, 3, "from").ok(),
+                    param(params, 4, "value").unwrap_or("0"),
+                )
+                .map_err(|e| RpcError::new(INVALID_PARAMS, e.to_string()))?;
+                self.craft_transaction(&call).await
+            }
+            _ => {
+                let message = format!("Method not found: {}", method);
+                return Err(RpcError::new(METHOD_NOT_FOUND, message));
+            }
+        };
+        let mut cache = self.cache.lock().await;
+        // Not kept between requests.
+        cache.take_warnings();
+        if let Err(e) = self.config.store.put_resolved(cache.resolved()) {
+            warn!("Could not keep resolved lookups ({})", e);
+        }
+        result.map_err(|e| RpcError::new(SERVER_ERROR, format!("{:#}", e)))
+    }
+    async fn history(&self, address: &str) -> Result<Value> {
+        let mut history = AddressHistory::new(address, self.config.clone())?;
+        let mut cache = self.cache.lock().await;
+        history.cache = std::mem::take(&mut *cache);
+        let result = history.run(self.mode).await.map(|_| ());
+        *cache = std::mem::take(&mut history.cache);
+        result?;
+        Ok(serde_json::to_value(Report::new(&history))?)
+    }
+    async fn decode_tx(&self, hash: H256) -> Result<Value> {
+        let mut history = AddressHistory::of_transaction(hash, self.config.clone()).await?;
+        let mut cache = self.cache.lock().await;
+        history.cache = std::mem::take(&mut *cache);
+        let result = history.decode_transaction(self.mode).await.map(|_| ());
+        *cache = std::mem::take(&mut history.cache);
+        result?;
+        Ok(json!({
+            "transaction": transactions_json(&history, &Pseudonyms::default()).pop(),
+            "failures": failures_json(&history.failures),
+        }))
+    }
+    async fn contract_info(&self, address: &H160) -> Result<Value> {
+        let mut cache = self.cache.lock().await;
+        let (mut contract, _) =
+            examine_contract(address, None, &self.mode, &self.config, &mut cache).await?;
+        // Wait for decompilation rather than answer without an ABI.
+        if self.config.decompiler.is_pending(address) {
+            cache.add_decompiled(self.config.decompiler.wait().await);
+            (contract, _) =
+                examine_contract(address, None, &self.mode, &self.config, &mut cache).await?;
+        }
+        let nametags = cache.try_nametags(address, &self.config);
+        let abi = contract
+            .abi
+            .as_ref()
+            .map(|abi| serde_json::from_str(abi).unwrap_or_else(|_| json!(abi)));
+        Ok(json!({
+            "address": format!("0x{}", contract.address),
+            "bytecode_size": contract.bytecode.len(),
+            "compiler": contract.compiler,
+            "metadata_link": contract.source_code_metadata_link,
+            "verification": contract.verification,
+            "decompiled": contract.decompiled,
+            "decompile_status": contract.decompile.to_string(),
+            "functions": contract.functions,
+            "abi": abi,
+            "interfaces": contract.interfaces,
+            "nametags": nametags,
+            "warnings": warnings_json(&cache.take_warnings(), &Pseudonyms::default()),
+        }))
+    }
+    async fn simulate_call(&self, call: &PlannedCall) -> Result<Value> {
+        let mut cache = self.cache.lock().await;
+        let simulation = try_call(call, &self.mode, &self.config, &mut cache).await?;
+        Ok(json!({
+            "signature": simulation.signature,
+            "calldata": format!("0x{}", hex::encode(&simulation.calldata)),
+            "returned": simulation.returned.map(|r| r.params),
+            "reverted": simulation.reverted,
+            "gas": simulation.gas,
+        }))
+    }
+    /// The transaction, with the link and RLP if they can be made.
+    async fn craft_transaction(&self, call: &PlannedCall) -> Result<Value> {
+        let mut cache = self.cache.lock().await;
+        let tx = craft_transaction(call, &self.mode, &self.config, &mut cache).await?;
+        Ok(json!({
+            "transaction": tx.to_json(),
+            "eip681": tx.eip681().ok(),
+            "rlp": tx.rlp().ok().map(|rlp| format!("0x{}", hex::encode(rlp))),
+        }))
+    }
+}
+
+impl RpcError {
+    fn new(code: i64, message: impl Into<String>) -> Self {
+        RpcError {
+            code,
+            message: message.into(),
+        }
+    }
+    fn response(&self, id: Value) -> Value {
+        json!({
+            "jsonrpc": "2.0",
+            "id": id,
+            "error": {"code": self.code, "message": self.message},
+        })
+    }
+}
+
+/// A parameter given by position or by name.
+fn param<'a>(params: &'a Value, position: usize, name: &str) -> Result<&'a str, RpcError> {
+    let value = match params {
+        Value::Array(values) => values.get(position),
+        Value::Object(values) => values.get(name),
+        _ => None,
+    };
+    value
+        .and_then(Value::as_str)
+        .ok_or_else(|| RpcError::new(INVALID_PARAMS, format!("Expected {} (string)", name)))
+}
+
+/// A parameter that is a list of strings (empty if absent).
+fn string_list(params: &Value, position: usize, name: &str) -> Result<Vec<String>, RpcError> {
+    let value = match params {
+        Value::Array(values) => values.get(position),
+        Value::Object(values) => values.get(name),
+        _ => None,
+    };
+    let Some(value) = value else {
+        return Ok(vec![]);
+    };
+    value
+        .as_array()
+        .and_then(|items| items.iter().map(|i| i.as_str().map(String::from)).collect())
+        .ok_or_else(|| RpcError::new(INVALID_PARAMS, format!("Expected {} (strings)", name)))
+}
+
+#[test]
+fn request_errors() {
+    let request = |line: &str| -> Value { serde_json::from_str(line).unwrap() };
+    assert_eq!(param(&request(r#"["0xab"]"#), 0, "hash"), Ok("0xab"));
+    assert_eq!(
+        param(&request(r#"{"hash": "0xab"}"#), 0, "hash"),
+        Ok("0xab")
+    );
+    assert_eq!(
+        param(&request("[1]"), 0, "hash").unwrap_err().code,
+        INVALID_PARAMS
+    );
+    assert_eq!(
+        RpcError::new(METHOD_NOT_FOUND, "Method not found: x").response(json!(7)),
+        request(
+            r#"{"jsonrpc": "2.0", "id": 7, "error": {"code": -32601, "message": "Method not found: x"}}"#
+        )
+    );
+}
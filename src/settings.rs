@@ -0,0 +1,98 @@
+//! Settings read from a TOML file (see `--config`), for what is rarely
+//! changed between runs.
+//!
+//! ```toml
+//! # Requests to the APIs (Sourcify, 4byte.directory and prices). The node
+//! # is reached directly, whatever is set here.
+//! [http]
+//! # Proxy for http and https requests, which may be a SOCKS5 proxy
+//! # ("socks5h://" resolves host names through the proxy). Without it, the
+//! # HTTP_PROXY and HTTPS_PROXY environment variables are used.
+//! proxy = "http://proxy.internal:3128"
+//! # Or route the requests through a local Tor daemon (see `apis::TOR_PROXY`).
+//! # tor = true
+//! # Certificates (PEM) to trust in addition to the system ones, e.g., for
+//! # a mirror signed by a company CA.
+//! ca_certificates = ["/etc/ssl/certs/company-ca.pem"]
+//!
+//! # Where verified contracts are looked up, e.g., a local Sourcify
+//! # repository or a pinned snapshot ("file:///data/sourcify/contracts/" or
+//! # an IPFS gateway URL). The chain id defaults to that of the node.
+//! [sourcify]
+//! repository = "http://localhost:10000/contracts/"
+//! chain_id = 1
+//!
+//! # Alerts in watch mode (see `alerts` for the rules and notifiers).
+//! [[alerts.rules]]
+//! kind = "outbound_value"
+//! min_ether = "1"
+//! ```
+use std::{fs, path::Path};
+
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+
+use crate::alerts::AlertSettings;
+
+/// Contents of the settings file. Missing sections have defaults.
+#[derive(Debug, Default, Clone, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Settings {
+    pub http: HttpSettings,
+    pub sourcify: SourcifySettings,
+    pub alerts: AlertSettings,
+}
+
+/// How requests to the APIs are made.
+#[derive(Debug, Default, Clone, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct HttpSettings {
+    /// Proxy URL for all requests (e.g., "http://proxy.internal:3128" or
+    /// "socks5h://127.0.0.1:1080").
+    pub proxy: Option<String>,
+    /// Send the requests through the SOCKS port of a local Tor daemon.
+    pub tor: bool,
+    /// Do not use a proxy, not even one from the environment.
+    pub no_proxy: bool,
+    /// PEM files of extra root certificates to trust.
+    pub ca_certificates: Vec<String>,
+    /// Accept any certificate (e.g., a self-signed mirror). Unsafe.
+    pub accept_invalid_certs: bool,
+}
+
+/// Where verified contracts are looked up (see `apis::SourcifyRepo`).
+#[derive(Debug, Default, Clone, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct SourcifySettings {
+    /// URL of the "contracts" directory of the repository (the public one
+    /// if None).
+    pub repository: Option<String>,
+    /// Chain id in the paths, if not that of the node.
+    pub chain_id: Option<u64>,
+}
+
+impl Settings {
+    pub fn load(path: &Path) -> Result<Self> {
+        let text = fs::read_to_string(path)
+            .with_context(|| format!("Could not read settings {}", path.display()))?;
+        toml::from_str(&text).with_context(|| format!("Invalid settings {}", path.display()))
+    }
+}
+
+#[test]
+fn settings_from_toml() {
+    let text = r#"
+        [http]
+        proxy = "http://proxy.internal:3128"
+        ca_certificates = ["/etc/ssl/certs/company-ca.pem"]
+    "#;
+    let settings: Settings = toml::from_str(text).unwrap();
+    assert_eq!(
+        settings.http.proxy.as_deref(),
+        Some("http://proxy.internal:3128")
+    );
+    assert_eq!(settings.http.ca_certificates.len(), 1);
+    assert!(!settings.http.accept_invalid_certs);
+    assert_eq!(toml::from_str::<Settings>("").unwrap(), Settings::default());
+    assert!(toml::from_str::<Settings>("[http]\nproxi = \"x\"").is_err());
+}
@@ -0,0 +1,376 @@
+//! Text signatures of the most common functions and events, built in so that
+//! they are known without a database or API.
+//!
+//! Selectors and topics are matched by their first four bytes (hex), the
+//! same key as the signatures database. Covers the token standards, WETH,
+//! the usual DEX, lending and bridge contracts, proxies, multisigs and
+//! account abstraction.
+//!
+//! Names found elsewhere are marked with their source (`NameSource`), as
+//! anyone can add a signature to 4byte.directory, so a name from there may be
+//! a collision chosen to mislead.
+use std::{collections::HashMap, fmt::Display, sync::OnceLock};
+
+use serde::{Deserialize, Serialize};
+
+use crate::abi::selector_of;
+
+/// Where the text signature of a selector or topic came from, most trusted
+/// first.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Deserialize, Serialize)]
+pub enum NameSource {
+    /// The verified ABI of the contract.
+    VerifiedAbi,
+    /// The common signatures built in to this crate.
+    BuiltIn,
+    /// The local signatures database.
+    Database,
+    /// 4byte.directory, where anyone can add a signature.
+    FourByte,
+    /// A lookup kept from an earlier run that did not record its source.
+    Earlier,
+}
+
+impl NameSource {
+    /// Confidence in a name from the source, out of 100.
+    pub fn confidence(&self) -> u8 {
+        match self {
+            NameSource::VerifiedAbi => 100,
+            NameSource::BuiltIn => 90,
+            NameSource::Database => 70,
+            NameSource::FourByte => 40,
+            NameSource::Earlier => 30,
+        }
+    }
+}
+
+impl Display for NameSource {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let source = match self {
+            NameSource::VerifiedAbi => "verified ABI",
+            NameSource::BuiltIn => "built in",
+            NameSource::Database => "signatures database",
+            NameSource::FourByte => "4byte.directory",
+            NameSource::Earlier => "earlier run",
+        };
+        write!(f, "{}, confidence {}", source, self.confidence())
+    }
+}
+
+/// Functions and events, grouped by where they are usually found. Earlier
+/// entries win if two share a selector.
+pub const COMMON_SIGNATURES: &[&str] = &[
+    // ERC-20
+    "transfer(address,uint256)",
+    "transferFrom(address,address,uint256)",
+    "approve(address,uint256)",
+    "allowance(address,address)",
+    "balanceOf(address)",
+    "totalSupply()",
+    "name()",
+    "symbol()",
+    "decimals()",
+    "increaseAllowance(address,uint256)",
+    "decreaseAllowance(address,uint256)",
+    "Transfer(address,address,uint256)",
+    "Approval(address,address,uint256)",
+    // EIP-2612 and DAI permits
+    "permit(address,address,uint256,uint256,uint8,bytes32,bytes32)",
+    "permit(address,address,uint256,uint256,bool,uint8,bytes32,bytes32)",
+    "nonces(address)",
+    "DOMAIN_SEPARATOR()",
+    // Mintable, burnable and pausable tokens
+    "mint(address,uint256)",
+    "burn(uint256)",
+    "burn(address,uint256)",
+    "burnFrom(address,uint256)",
+    "pause()",
+    "unpause()",
+    "paused()",
+    "Paused(address)",
+    "Unpaused(address)",
+    // ERC-721
+    "ownerOf(uint256)",
+    "safeTransferFrom(address,address,uint256)",
+    "safeTransferFrom(address,address,uint256,bytes)",
+    "setApprovalForAll(address,bool)",
+    "isApprovedForAll(address,address)",
+    "getApproved(uint256)",
+    "tokenURI(uint256)",
+    "tokenByIndex(uint256)",
+    "tokenOfOwnerByIndex(address,uint256)",
+    "onERC721Received(address,address,uint256,bytes)",
+    "ApprovalForAll(address,address,bool)",
+    // ERC-1155
+    "safeTransferFrom(address,address,uint256,uint256,bytes)",
+    "safeBatchTransferFrom(address,address,uint256[],uint256[],bytes)",
+    "balanceOf(address,uint256)",
+    "balanceOfBatch(address[],uint256[])",
+    "uri(uint256)",
+    "onERC1155Received(address,address,uint256,uint256,bytes)",
+    "onERC1155BatchReceived(address,address,uint256[],uint256[],bytes)",
+    "TransferSingle(address,address,address,uint256,uint256)",
+    "TransferBatch(address,address,address,uint256[],uint256[])",
+    "URI(string,uint256)",
+    // ERC-165, ERC-2981 and ERC-4906
+    "supportsInterface(bytes4)",
+    "royaltyInfo(uint256,uint256)",
+    "MetadataUpdate(uint256)",
+    "BatchMetadataUpdate(uint256,uint256)",
+    // ERC-4626
+    "asset()",
+    "totalAssets()",
+    "deposit(uint256,address)",
+    "mint(uint256,address)",
+    "withdraw(uint256,address,address)",
+    "redeem(uint256,address,address)",
+    "convertToShares(uint256)",
+    "convertToAssets(uint256)",
+    "previewDeposit(uint256)",
+    "previewRedeem(uint256)",
+    "maxWithdraw(address)",
+    "Deposit(address,address,uint256,uint256)",
+    "Withdraw(address,address,address,uint256,uint256)",
+    // ERC-777
+    "send(address,uint256,bytes)",
+    "authorizeOperator(address)",
+    "revokeOperator(address)",
+    "Sent(address,address,address,uint256,bytes,bytes)",
+    "Minted(address,address,uint256,bytes,bytes)",
+    "Burned(address,address,uint256,bytes,bytes)",
+    // WETH
+    "deposit()",
+    "withdraw(uint256)",
+    "Deposit(address,uint256)",
+    "Withdrawal(address,uint256)",
+    // Ownable and AccessControl
+    "owner()",
+    "transferOwnership(address)",
+    "renounceOwnership()",
+    "acceptOwnership()",
+    "pendingOwner()",
+    "OwnershipTransferred(address,address)",
+    "OwnershipTransferStarted(address,address)",
+    "hasRole(bytes32,address)",
+    "grantRole(bytes32,address)",
+    "revokeRole(bytes32,address)",
+    "renounceRole(bytes32,address)",
+    "getRoleAdmin(bytes32)",
+    "RoleGranted(bytes32,address,address)",
+    "RoleRevoked(bytes32,address,address)",
+    "RoleAdminChanged(bytes32,bytes32,bytes32)",
+    // Proxies (ERC-1967, UUPS and beacons) and initializers
+    "upgradeTo(address)",
+    "upgradeToAndCall(address,bytes)",
+    "implementation()",
+    "admin()",
+    "changeAdmin(address)",
+    "initialize()",
+    "Upgraded(address)",
+    "AdminChanged(address,address)",
+    "BeaconUpgraded(address)",
+    "Initialized(uint8)",
+    "Initialized(uint64)",
+    // Multicall
+    "multicall(bytes[])",
+    "multicall(uint256,bytes[])",
+    "aggregate((address,bytes)[])",
+    "tryAggregate(bool,(address,bytes)[])",
+    "aggregate3((address,bool,bytes)[])",
+    // Uniswap V2 (and forks)
+    "swapExactTokensForTokens(uint256,uint256,address[],address,uint256)",
+    "swapTokensForExactTokens(uint256,uint256,address[],address,uint256)",
+    "swapExactETHForTokens(uint256,address[],address,uint256)",
+    "swapTokensForExactETH(uint256,uint256,address[],address,uint256)",
+    "swapExactTokensForETH(uint256,uint256,address[],address,uint256)",
+    "swapETHForExactTokens(uint256,address[],address,uint256)",
+    "swapExactTokensForTokensSupportingFeeOnTransferTokens(uint256,uint256,address[],address,uint256)",
+    "swapExactETHForTokensSupportingFeeOnTransferTokens(uint256,address[],address,uint256)",
+    "swapExactTokensForETHSupportingFeeOnTransferTokens(uint256,uint256,address[],address,uint256)",
+    "addLiquidity(address,address,uint256,uint256,uint256,uint256,address,uint256)",
+    "addLiquidityETH(address,uint256,uint256,uint256,address,uint256)",
+    "removeLiquidity(address,address,uint256,uint256,uint256,address,uint256)",
+    "removeLiquidityETH(address,uint256,uint256,uint256,address,uint256)",
+    "getAmountsOut(uint256,address[])",
+    "getReserves()",
+    "token0()",
+    "token1()",
+    "swap(uint256,uint256,address,bytes)",
+    "sync()",
+    "skim(address)",
+    "createPair(address,address)",
+    "getPair(address,address)",
+    "Swap(address,uint256,uint256,uint256,uint256,address)",
+    "Sync(uint112,uint112)",
+    "Mint(address,uint256,uint256)",
+    "Burn(address,uint256,uint256,address)",
+    "PairCreated(address,address,address,uint256)",
+    // Uniswap V3
+    "exactInputSingle((address,address,uint24,address,uint256,uint256,uint256,uint160))",
+    "exactInput((bytes,address,uint256,uint256,uint256))",
+    "exactOutputSingle((address,address,uint24,address,uint256,uint256,uint256,uint160))",
+    "exactOutput((bytes,address,uint256,uint256,uint256))",
+    "exactInputSingle((address,address,uint24,address,uint256,uint256,uint160))",
+    "exactInput((bytes,address,uint256,uint256))",
+    "refundETH()",
+    "unwrapWETH9(uint256,address)",
+    "sweepToken(address,uint256,address)",
+    "slot0()",
+    "swap(address,bool,int256,uint160,bytes)",
+    "collect((uint256,address,uint128,uint128))",
+    "decreaseLiquidity((uint256,uint128,uint256,uint256,uint256))",
+    "increaseLiquidity((uint256,uint256,uint256,uint256,uint256,uint256))",
+    "mint((address,address,uint24,int24,int24,uint256,uint256,uint256,uint256,address,uint256))",
+    "positions(uint256)",
+    "Swap(address,address,int256,int256,uint160,uint128,int24)",
+    "Mint(address,address,int24,int24,uint128,uint256,uint256)",
+    "Burn(address,int24,int24,uint128,uint256,uint256)",
+    "Collect(address,address,int24,int24,uint128,uint128)",
+    "IncreaseLiquidity(uint256,uint128,uint256,uint256)",
+    "DecreaseLiquidity(uint256,uint128,uint256,uint256)",
+    "Collect(uint256,address,uint256,uint256)",
+    "PoolCreated(address,address,uint24,int24,address)",
+    // Uniswap universal router and Permit2
+    "execute(bytes,bytes[])",
+    "execute(bytes,bytes[],uint256)",
+    "permit(address,((address,uint160,uint48,uint48),address,uint256),bytes)",
+    "Permit(address,address,address,uint160,uint48,uint48)",
+    "Approval(address,address,address,uint160,uint48)",
+    // Aggregators
+    "swap(address,(address,address,address,address,uint256,uint256,uint256),bytes,bytes)",
+    "uniswapV3Swap(uint256,uint256,uint256[])",
+    "transformERC20(address,address,uint256,uint256,(uint32,bytes)[])",
+    // Curve
+    "exchange(int128,int128,uint256,uint256)",
+    "exchange_underlying(int128,int128,uint256,uint256)",
+    "exchange(uint256,uint256,uint256,uint256)",
+    "add_liquidity(uint256[2],uint256)",
+    "add_liquidity(uint256[3],uint256)",
+    "remove_liquidity(uint256,uint256[2])",
+    "remove_liquidity_one_coin(uint256,int128,uint256)",
+    "get_dy(int128,int128,uint256)",
+    "TokenExchange(address,int128,uint256,int128,uint256)",
+    "TokenExchangeUnderlying(address,int128,uint256,int128,uint256)",
+    // Balancer V2
+    "swap((bytes32,uint8,address,address,uint256,bytes),(address,bool,address,bool),uint256,uint256)",
+    "Swap(bytes32,address,address,uint256,uint256)",
+    // Aave V2 and V3
+    "deposit(address,uint256,address,uint16)",
+    "supply(address,uint256,address,uint16)",
+    "withdraw(address,uint256,address)",
+    "borrow(address,uint256,uint256,uint16,address)",
+    "repay(address,uint256,uint256,address)",
+    "Supply(address,address,address,uint256,uint16)",
+    "Withdraw(address,address,address,uint256)",
+    "Borrow(address,address,address,uint256,uint8,uint256,uint16)",
+    "Repay(address,address,address,uint256,bool)",
+    "LiquidationCall(address,address,address,uint256,uint256,address,bool)",
+    "FlashLoan(address,address,address,uint256,uint8,uint256,uint16)",
+    // Compound
+    "mint(uint256)",
+    "redeem(uint256)",
+    "redeemUnderlying(uint256)",
+    "borrow(uint256)",
+    "repayBorrow(uint256)",
+    "Redeem(address,uint256,uint256)",
+    // Staking and rewards
+    "stake(uint256)",
+    "getReward()",
+    "exit()",
+    "claim()",
+    "claim(uint256,address,uint256,bytes32[])",
+    "Staked(address,uint256)",
+    "Withdrawn(address,uint256)",
+    "RewardPaid(address,uint256)",
+    "Claimed(uint256,address,uint256)",
+    // Governance
+    "delegate(address)",
+    "castVote(uint256,uint8)",
+    "DelegateChanged(address,address,address)",
+    "DelegateVotesChanged(address,uint256,uint256)",
+    // Safe multisig
+    "execTransaction(address,uint256,bytes,uint8,uint256,uint256,uint256,address,address,bytes)",
+    "getOwners()",
+    "getThreshold()",
+    "nonce()",
+    "ExecutionSuccess(bytes32,uint256)",
+    "ExecutionFailure(bytes32,uint256)",
+    "SafeReceived(address,uint256)",
+    "SafeSetup(address,address[],uint256,address,address)",
+    "ProxyCreation(address,address)",
+    // ERC-4337 account abstraction
+    "handleOps((address,uint256,bytes,bytes,uint256,uint256,uint256,uint256,uint256,bytes,bytes)[],address)",
+    "UserOperationEvent(bytes32,address,address,uint256,bool,uint256,uint256)",
+    "AccountDeployed(bytes32,address,address,address)",
+    "BeforeExecution()",
+    "Deposited(address,uint256)",
+    // ENS
+    "setName(string)",
+    "setAddr(bytes32,address)",
+    "register(string,address,uint256,bytes32)",
+    "renew(string,uint256)",
+    "NameRegistered(string,bytes32,address,uint256,uint256)",
+    "NameRenewed(string,bytes32,uint256,uint256)",
+    "AddrChanged(bytes32,address)",
+    "NewOwner(bytes32,bytes32,address)",
+    // Seaport and OpenSea
+    "fulfillBasicOrder((address,uint256,uint256,address,address,address,uint256,uint256,uint8,uint256,uint256,bytes32,uint256,bytes32,bytes32,uint256,(uint256,address)[],bytes))",
+    "OrderFulfilled(bytes32,address,address,address,(uint8,address,uint256,uint256)[],(uint8,address,uint256,uint256,address)[])",
+    // Bridges
+    "depositETH(uint32,bytes)",
+    "depositERC20(address,address,uint256,uint32,bytes)",
+    "depositEth()",
+    "outboundTransfer(address,address,uint256,uint256,uint256,bytes)",
+    "ETHDepositInitiated(address,address,uint256,bytes)",
+    "ERC20DepositInitiated(address,address,address,address,uint256,bytes)",
+    "ETHWithdrawalFinalized(address,address,uint256,bytes)",
+    "ERC20WithdrawalFinalized(address,address,address,address,uint256,bytes)",
+    "InboxMessageDelivered(uint256,bytes)",
+    "DepositInitiated(address,address,address,uint256,uint256)",
+    "OutBoxTransactionExecuted(address,address,uint256,uint256)",
+    "LockedEther(address,address,uint256)",
+    "LockedERC20(address,address,address,uint256)",
+    "ExitedEther(address,uint256)",
+    "ExitedERC20(address,address,uint256)",
+];
+
+/// First four bytes (hex) of the hash of each common signature.
+fn table() -> &'static HashMap<String, &'static str> {
+    static TABLE: OnceLock<HashMap<String, &'static str>> = OnceLock::new();
+    TABLE.get_or_init(|| {
+        let mut table = HashMap::new();
+        for signature in COMMON_SIGNATURES.iter().rev() {
+            table.insert(hex::encode(selector_of(signature)), *signature);
+        }
+        table
+    })
+}
+
+/// The common signature of a selector or topic (hex, with or without "0x").
+/// Only the first four bytes are compared.
+pub fn common_signature(hash: &str) -> Option<&'static str> {
+    let hash = hash.trim_start_matches("0x");
+    let key = hash.get(..8)?.to_ascii_lowercase();
+    table().get(&key).copied()
+}
+
+#[test]
+fn common_signatures_found_by_hash() {
+    assert_eq!(
+        common_signature("a9059cbb"),
+        Some("transfer(address,uint256)")
+    );
+    assert_eq!(
+        common_signature("0xDDF252AD1be2c89b69c2b068fc378daa952ba7f163c4a11628f55a4df523b3ef"),
+        Some("Transfer(address,address,uint256)")
+    );
+    assert_eq!(
+        common_signature("d0e30db0"),
+        Some("deposit()"),
+        "WETH deposit"
+    );
+    assert_eq!(common_signature("12345678"), None);
+    assert_eq!(common_signature("a905"), None);
+    let unique: std::collections::HashSet<_> = COMMON_SIGNATURES.iter().collect();
+    assert_eq!(unique.len(), COMMON_SIGNATURES.len());
+}
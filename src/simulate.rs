@@ -0,0 +1,279 @@
+//! Trying a contract function before sending a transaction to it, to plan a
+//! future transaction from the functions found while exploring.
+//!
+//! The call is made with eth_call at the latest block, and its gas is
+//! estimated with eth_estimateGas. Nothing is signed or sent. Return values
+//! are decoded with the outputs of the function in a verified ABI, and
+//! guessed otherwise.
+//!
+//! A function is chosen by its text signature, by its name or by its
+//! selector. Names and selectors are looked up in the verified ABI and the
+//! functions recovered by decompiling the contract.
+use std::fmt::Display;
+
+use anyhow::{anyhow, bail, Result};
+use serde_json::Value;
+use web3::types::{Bytes, CallRequest, H160, U256};
+
+use crate::{
+    abi::{dynamic_bytes, selector_of},
+    cache::Cache,
+    data::Contract,
+    decode::{decode_return, encode_call, function_signatures, DecodedCall},
+    history::{examine_contract, Config, Mode},
+    provider::Provider,
+};
+
+/// Selector of `Error(string)`, the usual revert reason.
+const ERROR_SELECTOR: [u8; 4] = [0x08, 0xc3, 0x79, 0xa0];
+
+/// A call to a contract function that may later be sent as a transaction.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PlannedCall {
+    pub to: H160,
+    /// Sender, for functions that depend on who calls them.
+    pub from: Option<H160>,
+    /// Ether sent with the call, in wei.
+    pub value: U256,
+    /// Text signature, name or selector of the function (see `find_function`).
+    pub function: String,
+    /// One value per argument (see `encode_call`).
+    pub args: Vec<String>,
+}
+
+/// What a planned call did at the latest block.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Simulation {
+    /// Text signature of the function called.
+    pub signature: String,
+    pub calldata: Vec<u8>,
+    /// Decoded return values, if the call succeeded.
+    pub returned: Option<DecodedCall>,
+    /// Why the call reverted, if it did.
+    pub reverted: Option<String>,
+    /// Gas the transaction would use, if it could be estimated.
+    pub gas: Option<U256>,
+    /// RPC endpoint that made the call.
+    pub provider: Option<String>,
+}
+
+impl PlannedCall {
+    /// A call from text: addresses as hex and the value in wei (decimal).
+    pub fn parse(
+        to: &str,
+        function: &str,
+        args: &[String],
+        from: Option<&str>,
+        value: &str,
+    ) -> Result<Self> {
+        let address = |text: &str| -> Result<H160> {
+            text.trim_start_matches("0x")
+                .parse()
+                .map_err(|_| anyhow!("Invalid address {}", text))
+        };
+        Ok(PlannedCall {
+            to: address(to)?,
+            from: from.map(address).transpose()?,
+            value: U256::from_dec_str(value).map_err(|_| anyhow!("Invalid value {}", value))?,
+            function: function.to_string(),
+            args: args.to_vec(),
+        })
+    }
+    pub fn request(&self, calldata: Vec<u8>) -> CallRequest {
+        CallRequest {
+            from: self.from,
+            to: Some(self.to),
+            value: Some(self.value),
+            data: Some(Bytes(calldata)),
+            ..Default::default()
+        }
+    }
+}
+
+/// Finds the signature of a function of a contract from a text signature
+/// (used as given), a name or a selector (hex).
+pub fn find_function(contract: &Contract, function: &str) -> Result<String> {
+    if function.contains('(') {
+        return Ok(function.to_string());
+    }
+    let mut known: Vec<String> = contract
+        .abi
+        .as_deref()
+        .and_then(function_signatures)
+        .unwrap_or_default();
+    for f in &contract.functions {
+        match &f.signature {
+            Some(signature) if !known.contains(signature) => known.push(signature.clone()),
+            _ => {}
+        }
+    }
+    let selector = function.trim_start_matches("0x").to_ascii_lowercase();
+    let matching: Vec<&String> = known
+        .iter()
+        .filter(|s| {
+            s.strip_prefix(function)
+                .is_some_and(|rest| rest.starts_with('('))
+                || hex::encode(selector_of(s)) == selector
+        })
+        .collect();
+    match matching[..] {
+        [signature] => Ok(signature.clone()),
+        [] if known.is_empty() => bail!(
+            "No functions known for 0x{}, give the full signature instead",
+            contract.address
+        ),
+        [] => bail!("No function {} (known: {})", function, known.join(", ")),
+        _ => {
+            let matching: Vec<&str> = matching.iter().map(|s| s.as_str()).collect();
+            bail!("{} is ambiguous ({})", function, matching.join(", "))
+        }
+    }
+}
+
+/// Examines the contract to find the function, then simulates the call.
+pub async fn try_call(
+    call: &PlannedCall,
+    mode: &Mode,
+    config: &Config,
+    cache: &mut Cache,
+) -> Result<Simulation> {
+    let signature = resolve_function(call, mode, config, cache).await?;
+    let abi = cache.verified_abi(&call.to);
+    simulate(call, &signature, abi, &config.provider).await
+}
+
+/// Examines the contract to find the signature of the function of a call.
+///
+/// Waits for the contract to be decompiled if it has no verified ABI, so
+/// that recovered functions can be found by name.
+pub async fn resolve_function(
+    call: &PlannedCall,
+    mode: &Mode,
+    config: &Config,
+    cache: &mut Cache,
+) -> Result<String> {
+    if call.function.contains('(') {
+        return Ok(call.function.clone());
+    }
+    let (mut contract, _) = examine_contract(&call.to, None, mode, config, cache).await?;
+    if config.decompiler.is_pending(&call.to) {
+        cache.add_decompiled(config.decompiler.wait().await);
+        (contract, _) = examine_contract(&call.to, None, mode, config, cache).await?;
+    }
+    find_function(&contract, &call.function)
+}
+
+/// Calls a function at the latest block and estimates the gas of sending
+/// it as a transaction.
+///
+/// A call that the node reverts is a result, not an error. The ABI (JSON)
+/// is used to decode the return values.
+async fn simulate(
+    call: &PlannedCall,
+    signature: &str,
+    abi: Option<&str>,
+    provider: &Provider,
+) -> Result<Simulation> {
+    let calldata = encode_call(signature, &call.args)?;
+    let request = call.request(calldata.clone());
+    let mut simulation = Simulation {
+        signature: signature.to_string(),
+        calldata,
+        returned: None,
+        reverted: None,
+        gas: None,
+        provider: None,
+    };
+    match provider.simulate_call(request.clone()).await {
+        Ok((data, url)) => {
+            simulation.returned = Some(decode_return(signature, &data.0, abi));
+            simulation.provider = Some(url);
+        }
+        Err(e) => {
+            simulation.reverted = Some(revert_reason(&e).ok_or(e)?);
+            return Ok(simulation);
+        }
+    }
+    match provider.estimate_gas(request).await {
+        Ok((gas, _)) => simulation.gas = Some(gas),
+        Err(e) if revert_reason(&e).is_some() => {}
+        Err(e) => return Err(e),
+    }
+    Ok(simulation)
+}
+
+/// The reason given by the node for a call it executed and reverted, or None
+/// for other errors (e.g., the node could not be reached).
+fn revert_reason(error: &anyhow::Error) -> Option<String> {
+    match error.downcast_ref::<web3::Error>() {
+        Some(web3::Error::Rpc(e)) => Some(reason(&e.message, e.data.as_ref())),
+        _ => None,
+    }
+}
+
+/// The text of `Error(string)` revert data, otherwise the error message.
+fn reason(message: &str, data: Option<&Value>) -> String {
+    let data = data
+        .and_then(Value::as_str)
+        .and_then(|d| hex::decode(d.trim_start_matches("0x")).ok());
+    let text = data.as_deref().and_then(|d| {
+        let args = d.strip_prefix(&ERROR_SELECTOR[..])?;
+        Some(String::from_utf8_lossy(dynamic_bytes(args, 0)?).to_string())
+    });
+    text.unwrap_or_else(|| message.to_string())
+}
+
+impl Display for Simulation {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Function: {}", self.signature)?;
+        write!(f, "\nCalldata: 0x{}", hex::encode(&self.calldata))?;
+        if let Some(reason) = &self.reverted {
+            return write!(f, "\nReverted: {}", reason);
+        }
+        if let Some(returned) = &self.returned {
+            write!(f, "\nReturned: {}", returned)?;
+        }
+        match self.gas {
+            Some(gas) => write!(f, "\nEstimated gas: {}", gas)?,
+            None => write!(f, "\nEstimated gas: unknown (the transaction would revert)")?,
+        }
+        if let Some(provider) = &self.provider {
+            write!(f, "\nSimulated by {}", provider)?;
+        }
+        Ok(())
+    }
+}
+
+#[test]
+fn functions_found_by_name_or_selector() {
+    use crate::data::RecoveredFunction;
+
+    let contract = Contract {
+        address: "11".repeat(20),
+        abi: Some(String::from(
+            r#"[{"type":"function","name":"balanceOf","inputs":[{"type":"address"}]}]"#,
+        )),
+        functions: vec![RecoveredFunction {
+            selector: String::from("a9059cbb"),
+            signature: Some(String::from("transfer(address,uint256)")),
+        }],
+        ..Default::default()
+    };
+    let find = |f| find_function(&contract, f);
+    assert_eq!(find("balanceOf").unwrap(), "balanceOf(address)");
+    assert_eq!(find("0xa9059cbb").unwrap(), "transfer(address,uint256)");
+    assert_eq!(find("f(uint256)").unwrap(), "f(uint256)");
+    assert!(find("approve").is_err());
+    assert!(find("balance").is_err());
+
+    assert!(revert_reason(&anyhow::anyhow!("timed out")).is_none());
+    let data = format!(
+        "0x08c379a0{:0>64}{:0>64}{:0<64}",
+        "20",
+        "0d",
+        hex::encode("Not the owner")
+    );
+    let data = Value::String(data);
+    assert_eq!(reason("execution reverted", Some(&data)), "Not the owner");
+    assert_eq!(reason("execution reverted", None), "execution reverted");
+}
@@ -0,0 +1,311 @@
+//! Snapshots of the Sourcify metadata and 4byte.directory signatures that a
+//! history needs, downloaded once so that later runs can avoid the APIs.
+//!
+//! For the contracts and selectors seen in the history:
+//! - Sourcify metadata (and the ABI) is kept in the artifact store.
+//! - Text signatures are kept with the resolved lookups, and are written as
+//!   TODD raw data with `--contribute`.
+//!
+//! The snapshot is also written to a directory laid out as a Sourcify
+//! repository ("contracts/full_match/{chain id}/{address}/metadata.json"),
+//! with the signatures in "signatures.csv", so that it can be served as a
+//! local repository (see `SourcifyRepo`). The directory can be pinned to a
+//! local IPFS node (Kubo HTTP API), making an edition that does not change
+//! and that others can fetch by its CID.
+use std::{
+    collections::BTreeSet,
+    fmt::Display,
+    fs,
+    path::{Path, PathBuf},
+};
+
+use anyhow::{anyhow, Context, Result};
+use reqwest::{header::CONTENT_TYPE, Url};
+use serde_json::Value;
+use web3::types::H160;
+
+use crate::{
+    apis::{self, metadata_from_sourcify_api},
+    data::{VerificationStatus, Warning, WarningKind},
+    history::{AddressHistory, Mode},
+    parsing::{abi_from_metadata, as_checksummed},
+};
+
+const SIGNATURES_FILE: &str = "signatures.csv";
+
+/// What was downloaded for a history.
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct Snapshot {
+    /// Chain id of the Sourcify paths.
+    pub chain_id: u64,
+    /// Contracts with a Sourcify match, and their metadata.
+    pub metadata: Vec<(H160, VerificationStatus, Value)>,
+    /// Contracts without a Sourcify match.
+    pub unverified: Vec<H160>,
+    /// (hex selector, text signature), sorted.
+    pub signatures: Vec<(String, String)>,
+    /// Selectors without a known text signature.
+    pub unresolved: Vec<String>,
+    /// Files written, relative to the snapshot directory.
+    pub files: Vec<PathBuf>,
+    /// CID of the snapshot directory, if pinned.
+    pub cid: Option<String>,
+}
+
+/// Downloads the metadata of the contracts and the signatures of the
+/// selectors in a history. Metadata and ABIs are kept in the artifact store,
+/// signatures in the cache of the history.
+///
+/// Failed downloads are added to the warnings of the history.
+pub async fn download(history: &mut AddressHistory) -> Result<Snapshot> {
+    let config = &history.config;
+    let mut snapshot = Snapshot {
+        chain_id: config.sourcify.chain_id,
+        ..Default::default()
+    };
+    for address in contracts(history) {
+        match metadata_from_sourcify_api(&address, &config.sourcify).await {
+            Ok(Some((metadata, status))) => {
+                let text = metadata.to_string();
+                config.store.put_metadata(&address, text.as_bytes())?;
+                config
+                    .store
+                    .put_abi(&address, &abi_from_metadata(metadata.clone())?, status)?;
+                snapshot.metadata.push((address, status, metadata));
+            }
+            Ok(None) => snapshot.unverified.push(address),
+            Err(e) => {
+                let subject = format!("0x{}", hex::encode(address));
+                let warning = Warning::new(WarningKind::Abi, subject, format!("{:#}", e));
+                history.warnings.push(warning);
+                snapshot.unverified.push(address);
+            }
+        }
+    }
+    for selector in selectors(history) {
+        let text = history
+            .cache
+            .try_sig(&selector, &Mode::UseApis, &history.config)
+            .await;
+        match text {
+            Some(text) => snapshot.signatures.push((selector, text)),
+            None => snapshot.unresolved.push(selector),
+        }
+    }
+    let warnings = history.cache.take_warnings();
+    history.warnings.extend(warnings);
+    Ok(snapshot)
+}
+
+/// Contracts that emitted events or were called in a history.
+fn contracts(history: &AddressHistory) -> BTreeSet<H160> {
+    let mut contracts = BTreeSet::new();
+    for tx in &history.transactions {
+        contracts.extend(tx.events.iter().flatten().map(|e| e.raw.address));
+        let called = tx.description.as_ref().filter(|d| d.input.0.len() >= 4);
+        contracts.extend(called.and_then(|d| d.to));
+    }
+    contracts
+}
+
+/// Selectors (hex) of the events and calls in a history.
+fn selectors(history: &AddressHistory) -> BTreeSet<String> {
+    let mut selectors = BTreeSet::new();
+    for tx in &history.transactions {
+        let events = tx.events.iter().flatten();
+        selectors.extend(events.map(|e| e.topic_zero.clone()));
+        let input = tx.description.as_ref().map(|d| &d.input.0);
+        if let Some(selector) = input.and_then(|i| i.get(..4)) {
+            selectors.insert(hex::encode(selector));
+        }
+    }
+    selectors.retain(|s| !s.is_empty());
+    selectors
+}
+
+impl Snapshot {
+    /// Writes the metadata as a Sourcify repository and the signatures as
+    /// CSV lines to a directory.
+    pub fn write(&mut self, dir: &Path) -> Result<()> {
+        let mut files = vec![];
+        for (address, status, metadata) in &self.metadata {
+            let matched = match status {
+                VerificationStatus::SourcifyPartialMatch => "partial_match",
+                _ => "full_match",
+            };
+            let file = Path::new("contracts")
+                .join(matched)
+                .join(self.chain_id.to_string())
+                .join(as_checksummed(address))
+                .join("metadata.json");
+            write_file(&dir.join(&file), metadata.to_string().as_bytes())?;
+            files.push(file);
+        }
+        let csv: String = self
+            .signatures
+            .iter()
+            .map(|(selector, text)| format!("{},{}\n", selector, text))
+            .collect();
+        write_file(&dir.join(SIGNATURES_FILE), csv.as_bytes())?;
+        files.push(PathBuf::from(SIGNATURES_FILE));
+        self.files = files;
+        Ok(())
+    }
+    /// Adds the written files to an IPFS node (e.g., "http://127.0.0.1:5001")
+    /// and pins them. Returns the CID of the directory holding them.
+    pub async fn pin(&mut self, dir: &Path, api: &str) -> Result<String> {
+        let boundary = format!("psr-snapshot-{}", std::process::id());
+        let mut contents = vec![];
+        for file in &self.files {
+            let path = dir.join(file);
+            let content =
+                fs::read(&path).with_context(|| format!("Could not read {}", path.display()))?;
+            contents.push((file.clone(), content));
+        }
+        let body = multipart(&contents, &boundary);
+        let url = Url::parse(api)
+            .and_then(|base| base.join("api/v0/add"))
+            .with_context(|| format!("Invalid IPFS API URL {}", api))?;
+        let response = apis::client()
+            .post(url.clone())
+            .query(&[
+                ("pin", "true"),
+                ("wrap-with-directory", "true"),
+                ("cid-version", "1"),
+            ])
+            .header(
+                CONTENT_TYPE,
+                format!("multipart/form-data; boundary={}", boundary),
+            )
+            .body(body)
+            .send()
+            .await
+            .context("Could not reach the IPFS node")?
+            .error_for_status()?
+            .text()
+            .await?;
+        apis::record_request(&url, response.len());
+        let cid = root_cid(&response)?;
+        apis::record_learned(
+            &url,
+            format!("pinned {} files as {}", self.files.len(), cid),
+        );
+        self.cid = Some(cid.clone());
+        Ok(cid)
+    }
+}
+
+fn write_file(path: &Path, content: &[u8]) -> Result<()> {
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    fs::write(path, content).with_context(|| format!("Could not write {}", path.display()))
+}
+
+/// A multipart body for the IPFS add endpoint: each directory (before its
+/// contents), then each file, named by its relative path.
+fn multipart(files: &[(PathBuf, Vec<u8>)], boundary: &str) -> Vec<u8> {
+    // Ordered so that a directory comes before its subdirectories.
+    let dirs: BTreeSet<&Path> = files
+        .iter()
+        .flat_map(|(path, _)| path.ancestors().skip(1))
+        .filter(|dir| !dir.as_os_str().is_empty())
+        .collect();
+    let mut body = vec![];
+    let mut part = |path: &Path, kind: &str, content: &[u8]| {
+        let name: Vec<String> = path
+            .components()
+            .map(|c| c.as_os_str().to_string_lossy().into_owned())
+            .collect();
+        let header = format!(
+            "--{}\r\nContent-Disposition: form-data; name=\"file\"; filename=\"{}\"\r\nContent-Type: {}\r\n\r\n",
+            boundary,
+            name.join("/"),
+            kind
+        );
+        body.extend_from_slice(header.as_bytes());
+        body.extend_from_slice(content);
+        body.extend_from_slice(b"\r\n");
+    };
+    for dir in dirs {
+        part(dir, "application/x-directory", &[]);
+    }
+    for (path, content) in files {
+        part(path, "application/octet-stream", content);
+    }
+    body.extend_from_slice(format!("--{}--\r\n", boundary).as_bytes());
+    body
+}
+
+/// CID of the wrapping directory (the entry without a name) in the response
+/// of the IPFS add endpoint, which has one JSON object per line.
+fn root_cid(response: &str) -> Result<String> {
+    response
+        .lines()
+        .filter_map(|line| serde_json::from_str::<Value>(line).ok())
+        .find(|entry| entry["Name"] == "")
+        .and_then(|entry| entry["Hash"].as_str().map(String::from))
+        .ok_or_else(|| anyhow!("The IPFS node did not return a directory CID"))
+}
+
+impl Display for Snapshot {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "Sourcify metadata: {} contracts ({} without a match)",
+            self.metadata.len(),
+            self.unverified.len()
+        )?;
+        write!(
+            f,
+            "\nSignatures: {} ({} selectors unknown)",
+            self.signatures.len(),
+            self.unresolved.len()
+        )?;
+        if !self.files.is_empty() {
+            write!(f, "\nFiles: {}", self.files.len())?;
+        }
+        if let Some(cid) = &self.cid {
+            write!(f, "\nPinned as {}", cid)?;
+        }
+        Ok(())
+    }
+}
+
+#[test]
+fn snapshot_pinned_as_directory() {
+    let files = vec![
+        (
+            PathBuf::from("contracts/full_match/1/0xAb/metadata.json"),
+            b"{}".to_vec(),
+        ),
+        (PathBuf::from(SIGNATURES_FILE), b"a9059cbb,x\n".to_vec()),
+    ];
+    let body = String::from_utf8(multipart(&files, "b")).unwrap();
+    let names: Vec<&str> = body
+        .split("filename=\"")
+        .skip(1)
+        .map(|part| &part[..part.find('"').unwrap()])
+        .collect();
+    assert_eq!(
+        names,
+        [
+            "contracts",
+            "contracts/full_match",
+            "contracts/full_match/1",
+            "contracts/full_match/1/0xAb",
+            "contracts/full_match/1/0xAb/metadata.json",
+            SIGNATURES_FILE,
+        ]
+    );
+    assert!(body.ends_with("a9059cbb,x\n\r\n--b--\r\n"));
+
+    let response = concat!(
+        r#"{"Name":"signatures.csv","Hash":"bafyfile","Size":"20"}"#,
+        "\n",
+        r#"{"Name":"","Hash":"bafyroot","Size":"120"}"#,
+        "\n"
+    );
+    assert_eq!(root_cid(response).unwrap(), "bafyroot");
+    assert!(root_cid("").is_err());
+}
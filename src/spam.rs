@@ -0,0 +1,183 @@
+//! Flags token events that are likely unsolicited airdrops (spam).
+//!
+//! Spam tokens are sent to many addresses at once, often with a name that
+//! invites a visit to a scam site. A token transfer to the address is
+//! flagged when all of these hold:
+//! - Someone else sent the transaction.
+//! - The address never sent a transaction to the token, nor sent the token.
+//! - The token has no label or nametag.
+//! - The token was minted (sent from the zero address), or sent to at least
+//!   `MASS_TRANSFERS` addresses in the transaction.
+//!
+//! Every event of that token in the transaction is then flagged. Liquidity
+//! of the token is not checked, as that needs data beyond the history.
+use std::collections::{HashMap, HashSet};
+
+use clap::ValueEnum;
+use web3::types::H160;
+
+use crate::{
+    abi::{topic_address, topic_of},
+    data::TxInfo,
+    labels::UserLabels,
+    semantics::TRANSFER,
+};
+
+/// Transfers of one token in one transaction from which it is a mass
+/// distribution.
+const MASS_TRANSFERS: usize = 10;
+
+/// How events flagged as spam are shown.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum SpamFilter {
+    /// Show them, marked as likely spam.
+    #[default]
+    Show,
+    /// Replace them with a count in each transaction.
+    Collapse,
+    /// Leave them out, and transactions that only have spam.
+    Hide,
+}
+
+/// Flags events that are likely spam in transactions of an address ("0x"
+/// prefixed hex).
+pub fn detect(address: &str, transactions: &mut [TxInfo], labels: &UserLabels) {
+    let owner: H160 = address.trim_start_matches("0x").parse().unwrap_or_default();
+    let transfer = topic_of(TRANSFER);
+    // Contracts the address called and tokens it sent.
+    let mut interacted = HashSet::new();
+    for tx in transactions.iter() {
+        let Some(desc) = &tx.description else {
+            continue;
+        };
+        if desc.from == Some(owner) {
+            interacted.extend(desc.to);
+        }
+        for e in tx.events.iter().flatten() {
+            let topics = &e.raw.topics;
+            if topics.len() >= 3 && topics[0] == transfer && topic_address(&topics[1]) == owner {
+                interacted.insert(e.raw.address);
+            }
+        }
+    }
+    for tx in transactions.iter_mut() {
+        let sender = tx.description.as_ref().and_then(|d| d.from);
+        if sender == Some(owner) {
+            continue;
+        }
+        let Some(events) = tx.events.as_mut() else {
+            continue;
+        };
+        // Recipients of each token in the transaction.
+        let mut recipients: HashMap<H160, HashSet<H160>> = HashMap::new();
+        for e in events.iter() {
+            let topics = &e.raw.topics;
+            if topics.len() >= 3 && topics[0] == transfer {
+                let to = topic_address(&topics[2]);
+                recipients.entry(e.raw.address).or_default().insert(to);
+            }
+        }
+        let mut spam_tokens = HashSet::new();
+        for e in events.iter() {
+            let topics = &e.raw.topics;
+            let token = e.raw.address;
+            if topics.len() < 3 || topics[0] != transfer || topic_address(&topics[2]) != owner {
+                continue;
+            }
+            let named =
+                labels.get(&token).is_some() || e.nametags.iter().flatten().any(|t| !t.is_empty());
+            if interacted.contains(&token) || named {
+                continue;
+            }
+            let minted = topic_address(&topics[1]).is_zero();
+            let mass = recipients[&token].len() >= MASS_TRANSFERS;
+            if minted || mass {
+                spam_tokens.insert(token);
+            }
+        }
+        for e in events.iter_mut() {
+            e.spam = spam_tokens.contains(&e.raw.address);
+        }
+    }
+}
+
+/// Removes spam events, and transactions that someone else sent which only
+/// have spam events.
+pub fn hide(address: &str, transactions: &mut Vec<TxInfo>) {
+    let owner = address.trim_start_matches("0x");
+    transactions.retain(|tx| {
+        let sent = tx
+            .description
+            .as_ref()
+            .and_then(|d| d.from)
+            .is_some_and(|from| hex::encode(from) == owner);
+        let events = tx.events.as_deref().unwrap_or_default();
+        sent || events.is_empty() || events.iter().any(|e| !e.spam)
+    });
+    for tx in transactions.iter_mut() {
+        let Some(events) = tx.events.as_mut() else {
+            continue;
+        };
+        // Keep prices in line with the events that remain.
+        if let Some(fiat) = tx.fiat.as_mut() {
+            let mut spam = events.iter().map(|e| e.spam);
+            fiat.events.retain(|_| !spam.next().unwrap_or_default());
+        }
+        events.retain(|e| !e.spam);
+    }
+}
+
+#[test]
+fn airdrop_flagged() {
+    use web3::types::{Transaction, H256};
+
+    use crate::semantics::test_event;
+
+    let owner = H160::repeat_byte(0xaa);
+    let address = format!("0x{}", hex::encode(owner));
+    let airdrop = "0x1111111111111111111111111111111111111111";
+    let known = "0x2222222222222222222222222222222222222222";
+    let transfer = |token: &str, from: H160, to: H160| {
+        test_event(
+            token,
+            TRANSFER,
+            &[H256::from(from), H256::from(to)],
+            vec![0; 32],
+        )
+    };
+    let tx = |from: H160, events| TxInfo {
+        description: Some(Transaction {
+            from: Some(from),
+            ..Default::default()
+        }),
+        events: Some(events),
+        ..Default::default()
+    };
+    let spammer = H160::repeat_byte(0xee);
+    // To the address and 11 others.
+    let mut mass: Vec<_> = (1..=11u8)
+        .map(|n| transfer(airdrop, spammer, H160::repeat_byte(n)))
+        .collect();
+    mass.push(transfer(airdrop, spammer, owner));
+    let mut transactions = vec![
+        tx(spammer, mass),
+        // Minted to the address, but the address later sent the token.
+        tx(spammer, vec![transfer(known, H160::zero(), owner)]),
+        tx(owner, vec![transfer(known, owner, spammer)]),
+        // Minted to the address, with a nametag.
+        tx(spammer, {
+            let mut named = transfer(airdrop, H160::zero(), owner);
+            named.nametags = Some(vec![String::from("Airdrop")]);
+            vec![named]
+        }),
+    ];
+    detect(&address, &mut transactions, &UserLabels::default());
+    let flags = |tx: &TxInfo| -> Vec<bool> { tx.events.iter().flatten().map(|e| e.spam).collect() };
+    assert_eq!(flags(&transactions[0]), vec![true; 12]);
+    assert_eq!(flags(&transactions[1]), vec![false]);
+    assert_eq!(flags(&transactions[3]), vec![false]);
+
+    hide(&address, &mut transactions);
+    assert_eq!(transactions.len(), 3);
+    assert_eq!(flags(&transactions[0]), vec![false]);
+}
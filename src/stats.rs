@@ -0,0 +1,223 @@
+//! Summary statistics for the activity of an address.
+use std::{
+    collections::{BTreeMap, HashMap, HashSet},
+    fmt::Display,
+};
+
+use web3::types::U256;
+
+use crate::{
+    anomalies::{self, Anomaly},
+    fees::{FeeBreakdown, TxType},
+    history::AddressHistory,
+};
+
+/// Number of event types listed in the summary.
+const TOP_EVENTS: usize = 5;
+
+/// Aggregate information about an address history.
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct HistoryStats {
+    /// Block of the earliest appearance.
+    pub first_block: Option<u32>,
+    /// Block of the latest appearance.
+    pub last_block: Option<u32>,
+    /// Unix timestamp of the earliest appearance (if timestamps were fetched).
+    pub first_timestamp: Option<u64>,
+    /// Unix timestamp of the latest appearance (if timestamps were fetched).
+    pub last_timestamp: Option<u64>,
+    /// Number of transactions the address appears in.
+    pub tx_count: usize,
+    /// Transactions sent by the address.
+    pub sent: usize,
+    /// Transactions sent by others that involve the address.
+    pub received: usize,
+    /// Total fees (wei) paid for transactions sent by the address.
+    pub gas_spent: U256,
+    /// Part of the fees (wei) that was burned as base fee, where the base fee is known.
+    pub fees_burned: U256,
+    /// Part of the fees (wei) paid to block producers, where the base fee is known.
+    pub tips_paid: U256,
+    /// Fees (wei) for blob gas, included in `gas_spent`.
+    pub blob_fees: U256,
+    /// Number of transactions of each type, in type order.
+    pub tx_types: Vec<(TxType, usize)>,
+    /// Number of distinct senders/recipients other than the address.
+    pub unique_counterparties: usize,
+    /// Most frequent events, as (name or topic, count), most common first.
+    pub top_events: Vec<(String, usize)>,
+    /// Number of distinct contracts that emitted events.
+    pub unique_contracts: usize,
+    /// Unusual transactions or gaps in the history.
+    pub anomalies: Vec<Anomaly>,
+}
+
+impl HistoryStats {
+    /// Computes statistics from the data present in the history.
+    ///
+    /// Transactions missing descriptions or receipts are counted but otherwise skipped.
+    pub fn new(history: &AddressHistory) -> Self {
+        let owner = history.address.trim_start_matches("0x");
+        let mut stats = HistoryStats {
+            tx_count: history.transactions.len(),
+            ..Default::default()
+        };
+        let mut counterparties = HashSet::new();
+        let mut contracts = HashSet::new();
+        let mut event_counts: HashMap<String, usize> = HashMap::new();
+        let mut tx_types: BTreeMap<TxType, usize> = BTreeMap::new();
+
+        for tx in &history.transactions {
+            let block = tx.location.block;
+            stats.first_block = Some(stats.first_block.map_or(block, |b| b.min(block)));
+            stats.last_block = Some(stats.last_block.map_or(block, |b| b.max(block)));
+            if let Some(ts) = tx.timestamp {
+                stats.first_timestamp = Some(stats.first_timestamp.map_or(ts, |t| t.min(ts)));
+                stats.last_timestamp = Some(stats.last_timestamp.map_or(ts, |t| t.max(ts)));
+            }
+
+            if let Some(tx_type) = TxType::of(tx) {
+                *tx_types.entry(tx_type).or_default() += 1;
+            }
+            if let Some(desc) = &tx.description {
+                let from = desc.from.map(hex::encode);
+                if from.as_deref() == Some(owner) {
+                    stats.sent += 1;
+                    if let Some(to) = desc.to {
+                        counterparties.insert(hex::encode(to));
+                    }
+                    if let Some(receipt) = &tx.receipt {
+                        let price = receipt
+                            .effective_gas_price
+                            .or(desc.gas_price)
+                            .unwrap_or_default();
+                        stats.gas_spent += receipt.gas_used.unwrap_or_default() * price;
+                    }
+                    if let Some(fees) = FeeBreakdown::new(tx) {
+                        stats.fees_burned += fees.burned.unwrap_or_default();
+                        stats.tips_paid += fees.tip.unwrap_or_default();
+                    }
+                    if let Some(blob_fee) = tx.blob.as_ref().and_then(|b| b.fee()) {
+                        stats.gas_spent += blob_fee;
+                        stats.blob_fees += blob_fee;
+                    }
+                } else {
+                    stats.received += 1;
+                    if let Some(f) = from {
+                        counterparties.insert(f);
+                    }
+                }
+            }
+
+            for event in tx.events.iter().flatten() {
+                contracts.insert(event.contract.address.to_owned());
+                let key = match &event.name {
+                    Some(name) => name.to_owned(),
+                    None => event.topic_zero.to_owned(),
+                };
+                *event_counts.entry(key).or_default() += 1;
+            }
+        }
+        counterparties.remove(owner);
+        stats.unique_counterparties = counterparties.len();
+        stats.unique_contracts = contracts.len();
+
+        let mut top_events: Vec<(String, usize)> = event_counts.into_iter().collect();
+        top_events.sort_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0.cmp(&b.0)));
+        top_events.truncate(TOP_EVENTS);
+        stats.top_events = top_events;
+        stats.tx_types = tx_types.into_iter().collect();
+        if let Ok(owner) = owner.parse() {
+            stats.anomalies = anomalies::detect(&owner, &history.transactions);
+        }
+        stats
+    }
+}
+
+impl Display for HistoryStats {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let activity = |block: Option<u32>, ts: Option<u64>| match (block, ts) {
+            (Some(b), Some(t)) => format!("block {} ({})", b, date_from_unix(t)),
+            (Some(b), None) => format!("block {}", b),
+            _ => String::from("None"),
+        };
+        write!(
+            f,
+            "\n\tFirst activity: {}",
+            activity(self.first_block, self.first_timestamp)
+        )?;
+        write!(
+            f,
+            "\n\tLast activity: {}",
+            activity(self.last_block, self.last_timestamp)
+        )?;
+        write!(
+            f,
+            "\n\tTransactions: {} ({} sent, {} received)",
+            self.tx_count, self.sent, self.received
+        )?;
+        let milli_ether = |wei: U256| wei / 1_000_000_000 / 1_000_000;
+        write!(
+            f,
+            "\n\tGas spent: {} mETH (burned {} mETH, tips {} mETH",
+            milli_ether(self.gas_spent),
+            milli_ether(self.fees_burned),
+            milli_ether(self.tips_paid)
+        )?;
+        if !self.blob_fees.is_zero() {
+            write!(f, ", blob fees {} mETH", milli_ether(self.blob_fees))?;
+        }
+        write!(f, ")")?;
+        if !self.tx_types.is_empty() {
+            let types: Vec<String> = self
+                .tx_types
+                .iter()
+                .map(|(t, count)| format!("{} x{}", t, count))
+                .collect();
+            write!(f, "\n\tTransaction types: {}", types.join(", "))?;
+        }
+        write!(
+            f,
+            "\n\tUnique counterparties: {}",
+            self.unique_counterparties
+        )?;
+        write!(f, "\n\tUnique contracts touched: {}", self.unique_contracts)?;
+        if !self.top_events.is_empty() {
+            write!(f, "\n\tTop events:")?;
+            for (name, count) in &self.top_events {
+                write!(f, "\n\t\t{} x{}", name, count)?;
+            }
+        }
+        if !self.anomalies.is_empty() {
+            write!(f, "\n\tWarnings:")?;
+            for anomaly in &self.anomalies {
+                write!(f, "\n\t\t{}", anomaly)?;
+            }
+        }
+        write!(f, "")
+    }
+}
+
+/// Converts a unix timestamp to a UTC date "YYYY-MM-DD".
+pub fn date_from_unix(timestamp: u64) -> String {
+    // Days to civil date conversion from http://howardhinnant.github.io/date_algorithms.html
+    let days = (timestamp / 86_400) as i64 + 719_468;
+    let era = days.div_euclid(146_097);
+    let day_of_era = days.rem_euclid(146_097);
+    let year_of_era =
+        (day_of_era - day_of_era / 1460 + day_of_era / 36_524 - day_of_era / 146_096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let mp = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = year_of_era + era * 400 + i64::from(month <= 2);
+    format!("{:04}-{:02}-{:02}", year, month, day)
+}
+
+#[test]
+fn unix_dates() {
+    assert_eq!(date_from_unix(0), "1970-01-01");
+    // Ethereum genesis block.
+    assert_eq!(date_from_unix(1_438_269_973), "2015-07-30");
+    assert_eq!(date_from_unix(1_709_251_200), "2024-03-01");
+}
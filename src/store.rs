@@ -0,0 +1,703 @@
+//! On-disk store for artifacts that are expensive to obtain.
+//!
+//! Artifacts (bytecode, Sourcify metadata and ABIs) are stored once, named by
+//! the keccak hash of their content. An index maps each contract (chain and
+//! address) to the hashes of its artifacts, so contracts with the same code
+//! (e.g., one contract deployed on several networks) share a single copy.
+//! Decompiler output is named by the hash of the bytecode it was produced
+//! from, so identical code is decompiled once.
+//!
+//! The store has a size limit: when it is exceeded, the least recently used
+//! objects and decompiler outputs are removed. An index entry whose object
+//! was removed is treated as missing.
+//!
+//! Signatures and nametags resolved during a run are also kept, so that
+//! they are not looked up again. With `--incremental`, the decoded history
+//! of an address is kept too, so that the next run only fetches transactions
+//! in later blocks.
+//!
+//! ## Layout
+//! - `objects/<hash>`: artifact content.
+//! - `decompiled/<code hash>/`: decompiler output.
+//! - `index/<chain>/<address>.json`: hashes of the artifacts of a contract.
+//! - `histories/<chain>/<address>.json`: decoded transactions of an address.
+//! - `resolved.json`: signatures and nametags.
+//!
+//! ## Bundles
+//! The contents of a store can be exported to a single file (a tar archive
+//! of the layout above, without histories, compressed with zstd) and
+//! imported into another store, to share the work of API lookups and
+//! decompilation with another machine. Importing keeps existing entries and
+//! rejects objects whose content does not match their hash.
+use std::{
+    collections::{BTreeMap, BTreeSet, HashMap},
+    fmt::Display,
+    fs::{self, File},
+    io::{ErrorKind, Read},
+    path::{Component, Path, PathBuf},
+    time::SystemTime,
+};
+
+use anyhow::{anyhow, bail, Context, Result};
+use log::{debug, info};
+use serde::{Deserialize, Serialize};
+use web3::{
+    signing::keccak256,
+    types::{H160, H256},
+};
+
+use crate::{
+    chains::MAINNET,
+    data::{TxInfo, VerificationStatus},
+    signatures::NameSource,
+};
+
+/// Subdirectory for artifacts, one file per distinct content.
+const OBJECTS_DIR: &str = "objects";
+/// Subdirectory for decompiler output, one directory per distinct bytecode.
+const DECOMPILED_DIR: &str = "decompiled";
+/// Subdirectory for the artifacts of each contract, by chain.
+const INDEX_DIR: &str = "index";
+/// Subdirectory for the decoded histories of addresses, by chain.
+const HISTORIES_DIR: &str = "histories";
+/// File for lookups resolved in earlier runs.
+const RESOLVED_FILE: &str = "resolved.json";
+/// Subdirectory of bytecode by address, from before content addressing.
+const LEGACY_BYTECODE_DIR: &str = "bytecode";
+
+/// Default size limit of the store.
+pub const DEFAULT_MAX_BYTES: u64 = 512 * 1024 * 1024;
+
+/// Location and size limit of the artifact store.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ArtifactStore {
+    pub root: PathBuf,
+    pub max_bytes: u64,
+    /// Chain whose contracts are looked up in the index (see `on_chain`).
+    pub chain: String,
+}
+
+/// Contents of the store.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct StoreStats {
+    pub objects: usize,
+    pub decompiled_entries: usize,
+    pub indexed_contracts: usize,
+    pub total_bytes: u64,
+    pub max_bytes: u64,
+}
+
+/// Hashes of the stored artifacts of a contract.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ContractArtifacts {
+    /// Runtime bytecode.
+    pub bytecode: Option<H256>,
+    /// Sourcify metadata JSON.
+    pub metadata: Option<H256>,
+    /// JSON ABI, with where it came from.
+    pub abi: Option<(H256, VerificationStatus)>,
+}
+
+/// Signatures and nametags found in earlier runs.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Resolved {
+    /// Text signatures by 4 byte (or 32 byte) hex selector.
+    pub signatures: BTreeMap<String, String>,
+    /// Nametags by address (hex without "0x").
+    pub nametags: BTreeMap<String, Vec<String>>,
+    /// Where each signature came from, for those recorded.
+    #[serde(default)]
+    pub signature_sources: BTreeMap<String, NameSource>,
+}
+
+/// Transactions of an address decoded in an earlier run.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct StoredHistory {
+    /// Highest block up to which every transaction was processed.
+    pub watermark: u32,
+    /// The transactions up to the watermark, in index order.
+    pub transactions: Vec<TxInfo>,
+}
+
+/// A top level file or directory in one of the store subdirectories.
+struct Entry {
+    path: PathBuf,
+    bytes: u64,
+    last_used: SystemTime,
+}
+
+impl ArtifactStore {
+    pub fn new(root: PathBuf, max_bytes: u64) -> Self {
+        ArtifactStore {
+            root,
+            max_bytes,
+            chain: String::from(MAINNET),
+        }
+    }
+    /// The same store, indexing contracts on another chain.
+    pub fn on_chain(&self, chain: &str) -> Self {
+        ArtifactStore {
+            chain: chain.to_string(),
+            ..self.clone()
+        }
+    }
+    fn object_path(&self, hash: &H256) -> PathBuf {
+        self.root.join(OBJECTS_DIR).join(hex::encode(hash))
+    }
+    fn index_path(&self, chain: &str, address: &H160) -> PathBuf {
+        self.root
+            .join(INDEX_DIR)
+            .join(chain)
+            .join(format!("{}.json", hex::encode(address)))
+    }
+    fn history_path(&self, address: &H160) -> PathBuf {
+        self.root
+            .join(HISTORIES_DIR)
+            .join(&self.chain)
+            .join(format!("{}.json", hex::encode(address)))
+    }
+    /// Directory for the decompiler output of some bytecode.
+    pub fn decompiled_dir(&self, code: &[u8]) -> PathBuf {
+        self.root
+            .join(DECOMPILED_DIR)
+            .join(hex::encode(keccak256(code)))
+    }
+    /// Marks decompiler output as recently used.
+    pub fn touch_decompiled(&self, code: &[u8]) {
+        touch(&self.decompiled_dir(code));
+    }
+    /// Hashes of the stored artifacts of a contract.
+    pub fn artifacts(&self, address: &H160) -> ContractArtifacts {
+        fs::read_to_string(self.index_path(&self.chain, address))
+            .ok()
+            .and_then(|text| serde_json::from_str(&text).ok())
+            .unwrap_or_default()
+    }
+    fn put_artifacts(
+        &self,
+        address: &H160,
+        update: impl FnOnce(&mut ContractArtifacts),
+    ) -> Result<()> {
+        let mut artifacts = self.artifacts(address);
+        update(&mut artifacts);
+        let path = self.index_path(&self.chain, address);
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        serde_json::to_writer(File::create(path)?, &artifacts)?;
+        Ok(())
+    }
+    /// Content of an object, if present.
+    fn object(&self, hash: &H256) -> Option<Vec<u8>> {
+        let path = self.object_path(hash);
+        let content = fs::read(&path).ok()?;
+        touch(&path);
+        Some(content)
+    }
+    /// Stores content (once) and returns its hash.
+    fn put_object(&self, content: &[u8]) -> Result<H256> {
+        let hash = H256::from(keccak256(content));
+        let path = self.object_path(&hash);
+        if path.exists() {
+            touch(&path);
+            return Ok(hash);
+        }
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        fs::write(path, content)?;
+        Ok(hash)
+    }
+    /// Stored runtime bytecode for a contract, if present.
+    pub fn bytecode(&self, address: &H160) -> Option<Vec<u8>> {
+        let code = self.object(&self.artifacts(address).bytecode?)?;
+        debug!("Using stored bytecode for 0x{}", hex::encode(address));
+        Some(code)
+    }
+    /// Stores runtime bytecode for a contract.
+    pub fn put_bytecode(&self, address: &H160, code: &[u8]) -> Result<()> {
+        let hash = self.put_object(code)?;
+        self.put_artifacts(address, |a| a.bytecode = Some(hash))
+    }
+    /// Stored JSON ABI for a contract and where it came from, if present.
+    pub fn abi(&self, address: &H160) -> Option<(String, VerificationStatus)> {
+        let (hash, status) = self.artifacts(address).abi?;
+        let abi = String::from_utf8(self.object(&hash)?).ok()?;
+        debug!("Using stored ABI for 0x{}", hex::encode(address));
+        Some((abi, status))
+    }
+    /// Stores the JSON ABI for a contract.
+    pub fn put_abi(&self, address: &H160, abi: &str, status: VerificationStatus) -> Result<()> {
+        let hash = self.put_object(abi.as_bytes())?;
+        self.put_artifacts(address, |a| a.abi = Some((hash, status)))
+    }
+    /// Stores the Sourcify metadata for a contract.
+    pub fn put_metadata(&self, address: &H160, metadata: &[u8]) -> Result<()> {
+        let hash = self.put_object(metadata)?;
+        self.put_artifacts(address, |a| a.metadata = Some(hash))
+    }
+    /// Moves bytecode and decompiler output stored by address (before
+    /// content addressing) into the store, as mainnet contracts.
+    ///
+    /// Decompiler output is kept only if the bytecode of the contract is
+    /// stored. Returns the number of entries moved.
+    pub fn migrate(&self) -> Result<usize> {
+        let legacy = self.root.join(LEGACY_BYTECODE_DIR);
+        let mut moved = 0;
+        if legacy.exists() {
+            let mainnet = self.on_chain(MAINNET);
+            for item in fs::read_dir(&legacy)? {
+                let path = item?.path();
+                let name = path
+                    .file_stem()
+                    .and_then(|n| n.to_str())
+                    .unwrap_or_default();
+                let Ok(address) = name.parse::<H160>() else {
+                    continue;
+                };
+                mainnet.put_bytecode(&address, &fs::read(&path)?)?;
+                moved += 1;
+            }
+            remove(&legacy)?;
+        }
+        for path in list_dir(&self.root.join(DECOMPILED_DIR))? {
+            let name = path
+                .file_name()
+                .and_then(|n| n.to_str())
+                .unwrap_or_default();
+            // Bytecode hashes are 64 hex characters, addresses 40.
+            let Ok(address) = name.parse::<H160>() else {
+                continue;
+            };
+            match self.on_chain(MAINNET).bytecode(&address) {
+                Some(code) if !self.decompiled_dir(&code).exists() => {
+                    fs::rename(&path, self.decompiled_dir(&code))?;
+                    moved += 1;
+                }
+                _ => remove(&path)?,
+            }
+        }
+        if moved > 0 {
+            info!("Moved {} entries into the content addressed store", moved);
+        }
+        Ok(moved)
+    }
+    /// All entries, least recently used first.
+    fn entries(&self) -> Result<Vec<Entry>> {
+        let mut entries = vec![];
+        for subdir in [OBJECTS_DIR, DECOMPILED_DIR] {
+            for path in list_dir(&self.root.join(subdir))? {
+                let last_used = fs::metadata(&path)?.modified()?;
+                entries.push(Entry {
+                    bytes: size_of(&path)?,
+                    path,
+                    last_used,
+                });
+            }
+        }
+        entries.sort_by_key(|e| e.last_used);
+        Ok(entries)
+    }
+    /// Index files by "chain/address".
+    fn index_entries(&self) -> Result<Vec<(String, PathBuf)>> {
+        let mut entries = vec![];
+        for chain in list_dir(&self.root.join(INDEX_DIR))? {
+            let chain_name = chain.file_name().unwrap_or_default().to_string_lossy();
+            for path in list_dir(&chain)? {
+                let address = path.file_stem().unwrap_or_default().to_string_lossy();
+                entries.push((format!("{}/{}", chain_name, address), path));
+            }
+        }
+        Ok(entries)
+    }
+    /// Every distinct stored ABI, on any chain.
+    pub fn abis(&self) -> Result<Vec<String>> {
+        let mut hashes = BTreeSet::new();
+        for (_, path) in self.index_entries()? {
+            let artifacts: Option<ContractArtifacts> = fs::read_to_string(path)
+                .ok()
+                .and_then(|text| serde_json::from_str(&text).ok());
+            hashes.extend(artifacts.and_then(|a| a.abi).map(|(hash, _)| hash));
+        }
+        // Read without touching, as a search is not a use of the ABI.
+        Ok(hashes
+            .iter()
+            .filter_map(|hash| fs::read(self.object_path(hash)).ok())
+            .filter_map(|content| String::from_utf8(content).ok())
+            .collect())
+    }
+    pub fn stats(&self) -> Result<StoreStats> {
+        let mut stats = StoreStats {
+            max_bytes: self.max_bytes,
+            indexed_contracts: self.index_entries()?.len(),
+            ..Default::default()
+        };
+        for entry in self.entries()? {
+            stats.total_bytes += entry.bytes;
+            if entry.path.parent() == Some(&self.root.join(OBJECTS_DIR)) {
+                stats.objects += 1;
+            } else {
+                stats.decompiled_entries += 1;
+            }
+        }
+        Ok(stats)
+    }
+    /// Removes least recently used entries until the store is within its limit.
+    ///
+    /// Returns the number of entries removed.
+    pub fn evict(&self) -> Result<usize> {
+        let entries = self.entries()?;
+        let mut total: u64 = entries.iter().map(|e| e.bytes).sum();
+        let mut removed = 0;
+        for entry in entries {
+            if total <= self.max_bytes {
+                break;
+            }
+            remove(&entry.path)?;
+            total -= entry.bytes;
+            removed += 1;
+        }
+        if removed > 0 {
+            info!("Evicted {} entries from {}", removed, self.root.display());
+        }
+        Ok(removed)
+    }
+    /// Removes every entry, the index, stored histories and the lookups
+    /// resolved in earlier runs.
+    pub fn clear(&self) -> Result<usize> {
+        let entries = self.entries()?;
+        for entry in &entries {
+            remove(&entry.path)?;
+        }
+        let paths = [
+            self.root.join(INDEX_DIR),
+            self.root.join(HISTORIES_DIR),
+            self.root.join(RESOLVED_FILE),
+        ];
+        for path in paths {
+            if path.exists() {
+                remove(&path)?;
+            }
+        }
+        Ok(entries.len())
+    }
+    /// Lookups resolved in earlier runs.
+    pub fn resolved(&self) -> Result<Resolved> {
+        let path = self.root.join(RESOLVED_FILE);
+        match fs::read_to_string(&path) {
+            Ok(text) => serde_json::from_str(&text)
+                .with_context(|| format!("Invalid resolved lookups {}", path.display())),
+            Err(e) if e.kind() == ErrorKind::NotFound => Ok(Resolved::default()),
+            Err(e) => Err(e.into()),
+        }
+    }
+    /// Adds resolved lookups, replacing earlier values for the same keys.
+    pub fn put_resolved(&self, resolved: Resolved) -> Result<()> {
+        let mut stored = self.resolved()?;
+        stored.signatures.extend(resolved.signatures);
+        stored.nametags.extend(resolved.nametags);
+        stored.signature_sources.extend(resolved.signature_sources);
+        fs::create_dir_all(&self.root)?;
+        serde_json::to_writer(File::create(self.root.join(RESOLVED_FILE))?, &stored)?;
+        Ok(())
+    }
+    /// History of an address decoded in an earlier run, if stored.
+    pub fn history(&self, address: &H160) -> Result<Option<StoredHistory>> {
+        let path = self.history_path(address);
+        match fs::read_to_string(&path) {
+            Ok(text) => serde_json::from_str(&text)
+                .map(Some)
+                .with_context(|| format!("Invalid stored history {}", path.display())),
+            Err(e) if e.kind() == ErrorKind::NotFound => Ok(None),
+            Err(e) => Err(e.into()),
+        }
+    }
+    /// Stores the history of an address, replacing an earlier one.
+    pub fn put_history(&self, address: &H160, history: &StoredHistory) -> Result<()> {
+        let path = self.history_path(address);
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        serde_json::to_writer(File::create(path)?, history)?;
+        Ok(())
+    }
+    /// Writes the contents of the store to a bundle file.
+    ///
+    /// Files are streamed into the archive as they are. Returns the number of
+    /// objects and decompiler entries.
+    pub fn export_bundle(&self, path: &Path) -> Result<usize> {
+        let file = File::create(path)
+            .with_context(|| format!("Could not create bundle {}", path.display()))?;
+        let mut archive = tar::Builder::new(zstd::Encoder::new(file, 0)?);
+        let mut count = 0;
+        for entry in self.entries()? {
+            let Some(name) = entry.path.file_name().and_then(|n| n.to_str()) else {
+                continue;
+            };
+            if entry.path.parent() == Some(&self.root.join(OBJECTS_DIR)) {
+                archive.append_path_with_name(&entry.path, format!("{}/{}", OBJECTS_DIR, name))?;
+                count += 1;
+            } else if name.parse::<H256>().is_ok() {
+                // Output still being written has another name.
+                archive.append_dir_all(format!("{}/{}", DECOMPILED_DIR, name), &entry.path)?;
+                count += 1;
+            }
+        }
+        for (key, path) in self.index_entries()? {
+            archive.append_path_with_name(&path, format!("{}/{}.json", INDEX_DIR, key))?;
+        }
+        let resolved = self.root.join(RESOLVED_FILE);
+        if resolved.exists() {
+            archive.append_path_with_name(&resolved, RESOLVED_FILE)?;
+        }
+        archive.into_inner()?.finish()?;
+        Ok(count)
+    }
+    /// Adds the contents of a bundle file to the store, keeping existing entries.
+    ///
+    /// Returns the number of objects and decompiler entries added.
+    pub fn import_bundle(&self, path: &Path) -> Result<usize> {
+        let file = File::open(path)
+            .with_context(|| format!("Could not read bundle {}", path.display()))?;
+        let mut archive = tar::Archive::new(zstd::Decoder::new(file)?);
+        let invalid = || format!("Invalid bundle {}", path.display());
+        let mut added = 0;
+        // Decompiler entries by code hash, with whether they are imported.
+        let mut decompiled: HashMap<H256, bool> = HashMap::new();
+        for entry in archive.entries().with_context(invalid)? {
+            let mut entry = entry.with_context(invalid)?;
+            if entry.header().entry_type().is_dir() {
+                continue;
+            }
+            if !entry.header().entry_type().is_file() {
+                bail!("Unexpected entry in bundle: {}", entry.path()?.display());
+            }
+            let name = entry.path()?.to_string_lossy().replace('\\', "/");
+            if !is_relative(&name) {
+                bail!("Invalid file name in bundle: {}", name);
+            }
+            let parts: Vec<&str> = name.splitn(3, '/').collect();
+            match parts[..] {
+                [OBJECTS_DIR, hash] => {
+                    let hash = bundle_hash(hash)?;
+                    if self.object_path(&hash).exists() {
+                        continue;
+                    }
+                    let mut content = vec![];
+                    entry.read_to_end(&mut content)?;
+                    if H256::from(keccak256(&content)) != hash {
+                        bail!(
+                            "Content of object {:?} in bundle does not match its hash",
+                            hash
+                        );
+                    }
+                    self.put_object(&content)?;
+                    added += 1;
+                }
+                [DECOMPILED_DIR, code_hash, file] => {
+                    let code_hash = bundle_hash(code_hash)?;
+                    let dir = self.root.join(DECOMPILED_DIR).join(hex::encode(code_hash));
+                    let imported = *decompiled.entry(code_hash).or_insert_with(|| {
+                        let new = !dir.exists();
+                        added += usize::from(new);
+                        new
+                    });
+                    if !imported {
+                        continue;
+                    }
+                    let path = dir.join(file);
+                    if let Some(parent) = path.parent() {
+                        fs::create_dir_all(parent)?;
+                    }
+                    entry.unpack(&path)?;
+                }
+                [INDEX_DIR, chain, file] => {
+                    let address = file
+                        .strip_suffix(".json")
+                        .ok_or_else(|| anyhow!("Invalid index entry in bundle: {}", name))?;
+                    let address: H160 = address
+                        .parse()
+                        .with_context(|| format!("Invalid address in bundle: {}", address))?;
+                    let mut text = String::new();
+                    entry.read_to_string(&mut text)?;
+                    let artifacts: ContractArtifacts = serde_json::from_str(&text)
+                        .with_context(|| format!("Invalid index entry in bundle: {}", name))?;
+                    self.on_chain(chain).put_artifacts(&address, |stored| {
+                        stored.bytecode = stored.bytecode.or(artifacts.bytecode);
+                        stored.metadata = stored.metadata.or(artifacts.metadata);
+                        stored.abi = stored.abi.take().or(artifacts.abi);
+                    })?;
+                }
+                [RESOLVED_FILE] => {
+                    let mut text = String::new();
+                    entry.read_to_string(&mut text)?;
+                    let mut resolved: Resolved = serde_json::from_str(&text)
+                        .with_context(|| format!("Invalid resolved lookups in {}", name))?;
+                    let stored = self.resolved()?;
+                    resolved.signatures.extend(stored.signatures);
+                    resolved.nametags.extend(stored.nametags);
+                    self.put_resolved(resolved)?;
+                }
+                _ => bail!("Unexpected entry in bundle: {}", name),
+            }
+        }
+        Ok(added)
+    }
+}
+
+/// Reads a hash in a bundle.
+fn bundle_hash(hash: &str) -> Result<H256> {
+    hash.parse()
+        .with_context(|| format!("Invalid hash in bundle: {}", hash))
+}
+
+/// Whether a name from a bundle is a path within a directory (no root,
+/// `..` or empty name).
+fn is_relative(name: &str) -> bool {
+    !name.is_empty()
+        && Path::new(name)
+            .components()
+            .all(|c| matches!(c, Component::Normal(_)))
+}
+
+/// Files and directories in a directory (none if it does not exist).
+fn list_dir(dir: &Path) -> Result<Vec<PathBuf>> {
+    match fs::read_dir(dir) {
+        Ok(items) => Ok(items
+            .map(|item| item.map(|i| i.path()))
+            .collect::<std::io::Result<_>>()?),
+        Err(e) if e.kind() == ErrorKind::NotFound => Ok(vec![]),
+        Err(e) => Err(e.into()),
+    }
+}
+
+impl Default for ArtifactStore {
+    fn default() -> Self {
+        ArtifactStore::new(PathBuf::from("cache"), DEFAULT_MAX_BYTES)
+    }
+}
+
+/// Total size of a file, or of the files in a directory.
+fn size_of(path: &Path) -> Result<u64> {
+    let metadata = fs::metadata(path)?;
+    if !metadata.is_dir() {
+        return Ok(metadata.len());
+    }
+    let mut total = 0;
+    for item in fs::read_dir(path)? {
+        total += size_of(&item?.path())?;
+    }
+    Ok(total)
+}
+
+fn remove(path: &Path) -> Result<()> {
+    if path.is_dir() {
+        fs::remove_dir_all(path)?;
+    } else {
+        fs::remove_file(path)?;
+    }
+    Ok(())
+}
+
+/// Sets the modification time to now, which is used as the last use time.
+fn touch(path: &Path) {
+    if let Ok(file) = File::open(path) {
+        let _ = file.set_modified(SystemTime::now());
+    }
+}
+
+impl Display for StoreStats {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "Objects: {} artifacts\nIndexed: {} contracts\nDecompiled: {} bytecodes\nSize: {:.1} of {:.1} MiB",
+            self.objects,
+            self.indexed_contracts,
+            self.decompiled_entries,
+            self.total_bytes as f64 / 1024.0 / 1024.0,
+            self.max_bytes as f64 / 1024.0 / 1024.0
+        )
+    }
+}
+
+#[test]
+fn evicts_least_recently_used() {
+    let root = std::env::temp_dir().join(format!("psr-store-{}", std::process::id()));
+    let store = ArtifactStore::new(root.clone(), 250);
+    let (a, b, c) = (
+        H160::repeat_byte(1),
+        H160::repeat_byte(2),
+        H160::repeat_byte(3),
+    );
+    store.put_bytecode(&a, &[1; 100]).unwrap();
+    store.put_bytecode(&b, &[2; 100]).unwrap();
+    // Make `a` the most recently used, then exceed the limit.
+    let earlier = SystemTime::now() - std::time::Duration::from_secs(60);
+    File::open(store.object_path(&store.artifacts(&b).bytecode.unwrap()))
+        .unwrap()
+        .set_modified(earlier)
+        .unwrap();
+    assert!(store.bytecode(&a).is_some());
+    store.put_bytecode(&c, &[3; 100]).unwrap();
+
+    assert_eq!(store.evict().unwrap(), 1);
+    assert!(store.bytecode(&b).is_none());
+    assert!(store.bytecode(&a).is_some());
+    let stats = store.stats().unwrap();
+    assert_eq!(stats.objects, 2);
+    assert_eq!(stats.indexed_contracts, 3);
+    assert_eq!(stats.total_bytes, 200);
+
+    let history = StoredHistory {
+        watermark: 7,
+        transactions: vec![TxInfo::default()],
+    };
+    store.put_history(&a, &history).unwrap();
+    assert_eq!(store.history(&a).unwrap(), Some(history));
+    assert_eq!(store.history(&b).unwrap(), None);
+
+    assert_eq!(store.clear().unwrap(), 2);
+    assert_eq!(store.history(&a).unwrap(), None);
+    fs::remove_dir_all(root).unwrap();
+}
+
+#[test]
+fn bundle_round_trip() {
+    let dir = std::env::temp_dir().join(format!("psr-bundle-{}", std::process::id()));
+    let (from, to) = (
+        ArtifactStore::new(dir.join("from"), DEFAULT_MAX_BYTES),
+        ArtifactStore::new(dir.join("to"), DEFAULT_MAX_BYTES),
+    );
+    let (a, b) = (H160::repeat_byte(1), H160::repeat_byte(2));
+    let code = [0x60, 0x80];
+    from.put_bytecode(&a, &code).unwrap();
+    // The same code on another chain is stored once.
+    from.on_chain("optimism").put_bytecode(&b, &code).unwrap();
+    let stats = from.stats().unwrap();
+    assert_eq!((stats.objects, stats.indexed_contracts), (1, 2));
+    let output = from.decompiled_dir(&code).join("abi");
+    fs::create_dir_all(&output).unwrap();
+    fs::write(output.join("abi.json"), "[]").unwrap();
+    // Decompiler output is not always UTF-8.
+    fs::write(output.join("raw.bin"), [0xff, 0xfe]).unwrap();
+    let mut resolved = Resolved::default();
+    resolved.signatures.insert(
+        String::from("a9059cbb"),
+        String::from("transfer(address,uint256)"),
+    );
+    from.put_resolved(resolved.clone()).unwrap();
+
+    let bundle = dir.join("bundle.tar.zst");
+    assert_eq!(from.export_bundle(&bundle).unwrap(), 2);
+    to.put_bytecode(&a, &[0xfe]).unwrap();
+    assert_eq!(to.import_bundle(&bundle).unwrap(), 2);
+    // Existing entries are kept.
+    assert_eq!(to.bytecode(&a).unwrap(), [0xfe]);
+    assert_eq!(to.on_chain("optimism").bytecode(&b).unwrap(), code);
+    let imported = to.decompiled_dir(&code).join("abi").join("abi.json");
+    assert_eq!(fs::read_to_string(imported).unwrap(), "[]");
+    let raw = to.decompiled_dir(&code).join("abi").join("raw.bin");
+    assert_eq!(fs::read(raw).unwrap(), [0xff, 0xfe]);
+    assert_eq!(to.resolved().unwrap(), resolved);
+    fs::remove_dir_all(dir).unwrap();
+}
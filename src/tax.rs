@@ -0,0 +1,450 @@
+//! Capital gains from the history, by matching each disposal of an asset
+//! with earlier acquisitions (tax lots).
+//!
+//! Ether received or sent with a successful transaction, and ERC-20 tokens
+//! transferred to or from the address, are acquisitions and disposals at
+//! their value on the day (see `prices`). Lots are matched first in, first
+//! out or last in, first out. Quantities are kept in base units (e.g., wei)
+//! and only converted for output.
+//!
+//! Gas fees, internal ether transfers and NFTs are not included, nor are
+//! tokens whose decimals could not be read from the contract. A disposal
+//! of more than was acquired in the history (e.g., funds received before the
+//! first transaction decoded) has no cost basis for the remainder.
+use std::{
+    collections::{BTreeMap, VecDeque},
+    fmt::Display,
+};
+
+use clap::ValueEnum;
+use web3::types::{H160, U256};
+
+use crate::{
+    abi::{data_word, topic_address, topic_of},
+    cache::Cache,
+    data::TxInfo,
+    flows::Asset,
+    history::AddressHistory,
+    labels::UserLabels,
+    prices::units,
+    semantics::TRANSFER,
+    stats::date_from_unix,
+};
+
+/// Which lots a disposal is matched with.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum LotMethod {
+    /// The earliest acquisitions first.
+    #[default]
+    Fifo,
+    /// The latest acquisitions first.
+    Lifo,
+}
+
+/// Disposals matched with lots.
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct TaxReport {
+    pub currency: String,
+    pub disposals: Vec<Disposal>,
+}
+
+/// Part of a disposal matched with one lot (or with no lot).
+#[derive(Debug, Clone, PartialEq)]
+pub struct Disposal {
+    /// Symbol of the asset, or the token contract.
+    pub asset: String,
+    /// Quantity in base units.
+    pub quantity: U256,
+    pub decimals: u32,
+    /// Unix timestamp of the acquisition, if matched with a lot.
+    pub acquired: Option<u64>,
+    /// Unix timestamp of the disposal.
+    pub disposed: u64,
+    /// Value received, if priced.
+    pub proceeds: Option<f64>,
+    /// Value of the lot when acquired, if matched and priced.
+    pub cost: Option<f64>,
+}
+
+/// Asset acquired or disposed of by the address.
+#[derive(Debug, Clone, PartialEq)]
+struct Movement {
+    asset: Asset,
+    symbol: String,
+    decimals: u32,
+    timestamp: u64,
+    /// Quantity in base units.
+    quantity: U256,
+    /// Value of the whole quantity, if priced.
+    value: Option<f64>,
+    incoming: bool,
+}
+
+/// Part of an acquisition not yet disposed of.
+#[derive(Debug, Clone, PartialEq)]
+struct Lot {
+    timestamp: u64,
+    /// Quantity acquired.
+    acquired: U256,
+    /// Quantity left.
+    quantity: U256,
+    /// Value of the quantity acquired, if priced.
+    cost: Option<f64>,
+}
+
+impl TaxReport {
+    /// Report for a history that has been priced (see `AddressHistory::price`),
+    /// which reads the decimals of the tokens.
+    pub fn new(history: &AddressHistory, method: LotMethod) -> Self {
+        let owner = history
+            .address
+            .trim_start_matches("0x")
+            .parse()
+            .unwrap_or_default();
+        let movements = history
+            .transactions
+            .iter()
+            .flat_map(|tx| movements(owner, tx, &history.config.labels, &history.cache))
+            .collect();
+        let currency = history
+            .transactions
+            .iter()
+            .find_map(|tx| tx.fiat.as_ref().map(|f| f.currency.clone()))
+            .unwrap_or_default();
+        TaxReport::of_movements(movements, method, currency)
+    }
+    fn of_movements(mut movements: Vec<Movement>, method: LotMethod, currency: String) -> Self {
+        movements.sort_by_key(|m| m.timestamp);
+        let mut lots: BTreeMap<Asset, VecDeque<Lot>> = BTreeMap::new();
+        let mut disposals = vec![];
+        for m in movements {
+            let held = lots.entry(m.asset).or_default();
+            if m.incoming {
+                held.push_back(Lot {
+                    timestamp: m.timestamp,
+                    acquired: m.quantity,
+                    quantity: m.quantity,
+                    cost: m.value,
+                });
+                continue;
+            }
+            let mut remaining = m.quantity;
+            while !remaining.is_zero() {
+                let lot = match method {
+                    LotMethod::Fifo => held.front_mut(),
+                    LotMethod::Lifo => held.back_mut(),
+                };
+                let Some(lot) = lot else {
+                    disposals.push(Disposal {
+                        asset: m.symbol.clone(),
+                        quantity: remaining,
+                        decimals: m.decimals,
+                        acquired: None,
+                        disposed: m.timestamp,
+                        proceeds: m.value.map(|v| v * share(remaining, m.quantity)),
+                        cost: None,
+                    });
+                    break;
+                };
+                let quantity = remaining.min(lot.quantity);
+                disposals.push(Disposal {
+                    asset: m.symbol.clone(),
+                    quantity,
+                    decimals: m.decimals,
+                    acquired: Some(lot.timestamp),
+                    disposed: m.timestamp,
+                    proceeds: m.value.map(|v| v * share(quantity, m.quantity)),
+                    cost: lot.cost.map(|c| c * share(quantity, lot.acquired)),
+                });
+                lot.quantity -= quantity;
+                remaining -= quantity;
+                if lot.quantity.is_zero() {
+                    match method {
+                        LotMethod::Fifo => held.pop_front(),
+                        LotMethod::Lifo => held.pop_back(),
+                    };
+                }
+            }
+        }
+        TaxReport {
+            currency,
+            disposals,
+        }
+    }
+    /// One row per disposal with the columns of Form 8949 (description,
+    /// dates as MM/DD/YYYY, proceeds, cost basis and gain), which tax tools
+    /// commonly import. Unknown values are left empty.
+    pub fn to_csv(&self) -> String {
+        let mut csv =
+            String::from("Description,Date Acquired,Date Sold,Proceeds,Cost Basis,Gain or Loss\n");
+        for d in &self.disposals {
+            let money = |value: Option<f64>| value.map(|v| format!("{:.2}", v)).unwrap_or_default();
+            csv.push_str(&format!(
+                "{} {},{},{},{},{},{}\n",
+                quantity(d.quantity, d.decimals),
+                csv_field(&d.asset),
+                d.acquired.map(us_date).unwrap_or_default(),
+                us_date(d.disposed),
+                money(d.proceeds),
+                money(d.cost),
+                money(d.gain()),
+            ));
+        }
+        csv
+    }
+}
+
+impl Disposal {
+    pub fn gain(&self) -> Option<f64> {
+        Some(self.proceeds? - self.cost?)
+    }
+}
+
+/// Part of a whole quantity, as a fraction.
+fn share(part: U256, whole: U256) -> f64 {
+    units(part, 0) / units(whole, 0)
+}
+
+/// Assets the address acquired and disposed of in a transaction.
+fn movements(owner: H160, tx: &TxInfo, labels: &UserLabels, cache: &Cache) -> Vec<Movement> {
+    let mut movements = vec![];
+    let (Some(timestamp), Some(desc)) = (tx.timestamp, &tx.description) else {
+        return movements;
+    };
+    let failed = tx
+        .receipt
+        .as_ref()
+        .and_then(|r| r.status)
+        .is_some_and(|s| s.is_zero());
+    if failed {
+        return movements;
+    }
+    let fiat = tx.fiat.as_ref();
+    let incoming = desc.to == Some(owner);
+    let outgoing = desc.from == Some(owner);
+    if !desc.value.is_zero() && incoming != outgoing {
+        movements.push(Movement {
+            asset: Asset::Ether,
+            symbol: String::from("ETH"),
+            decimals: 18,
+            timestamp,
+            quantity: desc.value,
+            value: fiat.and_then(|f| f.value),
+            incoming,
+        });
+    }
+    let transfer = topic_of(TRANSFER);
+    for (n, event) in tx.events.iter().flatten().enumerate() {
+        let topics = &event.raw.topics;
+        if topics.len() != 3 || topics[0] != transfer {
+            continue;
+        }
+        let Some(amount) = data_word(&event.raw.data.0, 0) else {
+            continue;
+        };
+        let (from, to) = (topic_address(&topics[1]), topic_address(&topics[2]));
+        if amount.is_zero() || from == to || (from != owner && to != owner) {
+            continue;
+        }
+        let token = event.raw.address;
+        let Some(decimals) = cache.known_decimals(&token) else {
+            continue;
+        };
+        let symbol = labels
+            .merge(&token, event.nametags.clone())
+            .and_then(|names| names.into_iter().find(|n| !n.is_empty()))
+            .unwrap_or_else(|| format!("0x{}", hex::encode(token)));
+        movements.push(Movement {
+            asset: Asset::Token(token),
+            quantity: amount,
+            symbol,
+            decimals,
+            timestamp,
+            value: fiat.and_then(|f| f.events.get(n).copied().flatten()),
+            incoming: to == owner,
+        });
+    }
+    movements
+}
+
+/// A quantity in base units as a number with up to 8 decimal places
+/// (rounded), without trailing zeros.
+fn quantity(value: U256, decimals: u32) -> String {
+    let places = decimals.min(8);
+    let dropped = U256::exp10((decimals - places) as usize);
+    let value = value.saturating_add(dropped / 2) / dropped;
+    let unit = U256::exp10(places as usize);
+    let fraction = format!(
+        "{:0width$}",
+        (value % unit).as_u64(),
+        width = places as usize
+    );
+    match fraction.trim_end_matches('0') {
+        "" => (value / unit).to_string(),
+        fraction => format!("{}.{}", value / unit, fraction),
+    }
+}
+
+/// "MM/DD/YYYY" from a unix timestamp.
+fn us_date(timestamp: u64) -> String {
+    let date = date_from_unix(timestamp);
+    format!("{}/{}/{}", &date[5..7], &date[8..10], &date[..4])
+}
+
+/// A CSV field, quoted if needed.
+pub fn csv_field(text: &str) -> String {
+    match text.contains([',', '"', '\n']) {
+        true => format!("\"{}\"", text.replace('"', "\"\"")),
+        false => text.to_string(),
+    }
+}
+
+impl Display for TaxReport {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        // Totals of quantity, proceeds, cost and gain by asset.
+        let mut totals: BTreeMap<&str, (U256, u32, f64, f64, f64)> = BTreeMap::new();
+        let mut unknown = 0;
+        for d in &self.disposals {
+            let total = totals.entry(&d.asset).or_default();
+            total.0 = total.0.saturating_add(d.quantity);
+            total.1 = d.decimals;
+            total.2 += d.proceeds.unwrap_or_default();
+            total.3 += d.cost.unwrap_or_default();
+            total.4 += d.gain().unwrap_or_default();
+            if d.gain().is_none() {
+                unknown += 1;
+            }
+        }
+        write!(
+            f,
+            "Disposals by asset ({}): quantity, proceeds, cost basis, gain or loss",
+            self.currency
+        )?;
+        for (asset, (quantity_sold, decimals, proceeds, cost, gain)) in &totals {
+            write!(
+                f,
+                "\n{}: {}, {:.2}, {:.2}, {:.2}",
+                asset,
+                quantity(*quantity_sold, *decimals),
+                proceeds,
+                cost,
+                gain
+            )?;
+        }
+        if totals.is_empty() {
+            write!(f, "\nNo disposals.")?;
+        }
+        if unknown > 0 {
+            write!(
+                f,
+                "\n{} of {} disposals have no price or cost basis and are not in the gains.",
+                unknown,
+                self.disposals.len()
+            )?;
+        }
+        Ok(())
+    }
+}
+
+#[test]
+fn lots_matched_in_order() {
+    // Quantities in thousandths of an ether.
+    let milli = |n: u64| U256::from(n) * U256::exp10(15);
+    let ether = |timestamp: u64, quantity: u64, value: f64, incoming: bool| Movement {
+        asset: Asset::Ether,
+        symbol: String::from("ETH"),
+        decimals: 18,
+        timestamp,
+        quantity: milli(quantity),
+        value: Some(value),
+        incoming,
+    };
+    // 2023-11-14, 2023-11-15, 2023-11-16.
+    let day = |n: u64| 1_700_000_000 + n * 86_400;
+    let movements = vec![
+        ether(day(0), 1000, 1000.0, true),
+        ether(day(1), 1000, 3000.0, true),
+        ether(day(2), 1500, 6000.0, false),
+    ];
+
+    let fifo = TaxReport::of_movements(movements.clone(), LotMethod::Fifo, String::from("USD"));
+    assert_eq!(fifo.disposals.len(), 2);
+    assert_eq!(fifo.disposals[0].cost, Some(1000.0));
+    assert_eq!(fifo.disposals[0].gain(), Some(3000.0));
+    assert_eq!(fifo.disposals[1].quantity, milli(500));
+    assert_eq!(fifo.disposals[1].gain(), Some(500.0));
+    let csv = fifo.to_csv();
+    let rows: Vec<&str> = csv.lines().collect();
+    assert_eq!(
+        rows[1],
+        "1 ETH,11/14/2023,11/16/2023,4000.00,1000.00,3000.00"
+    );
+    assert_eq!(
+        rows[2],
+        "0.5 ETH,11/15/2023,11/16/2023,2000.00,1500.00,500.00"
+    );
+
+    let lifo = TaxReport::of_movements(movements.clone(), LotMethod::Lifo, String::from("USD"));
+    assert_eq!(lifo.disposals[0].cost, Some(3000.0));
+    assert_eq!(lifo.disposals[1].cost, Some(500.0));
+
+    // More sold than acquired leaves the remainder without a cost basis.
+    let mut oversold = movements;
+    oversold.push(ether(day(3), 1000, 4000.0, false));
+    let report = TaxReport::of_movements(oversold, LotMethod::Fifo, String::from("USD"));
+    let last = report.disposals.last().unwrap();
+    assert_eq!(
+        (last.quantity, last.acquired, last.cost),
+        (milli(500), None, None)
+    );
+    assert!(report.to_csv().ends_with("0.5 ETH,,11/17/2023,2000.00,,\n"));
+    assert!(report
+        .to_string()
+        .ends_with("1 of 4 disposals have no price or cost basis and are not in the gains."));
+}
+
+#[test]
+fn tokens_in_base_units() {
+    use web3::types::{Transaction, H256};
+
+    use crate::{history::VisitNote, semantics::test_event};
+
+    assert_eq!(quantity(U256::from(1_500_000u64), 6), "1.5");
+    assert_eq!(quantity(U256::from(123_456_789_012u64), 10), "12.3456789");
+    assert_eq!(quantity(U256::from(7u64), 0), "7");
+    assert_eq!(quantity(U256::exp10(17) * 2, 18), "0.2");
+
+    let owner = H160::repeat_byte(0xaa);
+    let mut topic = [0u8; 32];
+    topic[12..].copy_from_slice(owner.as_bytes());
+    let topics = [H256::zero(), H256(topic)];
+    let mut amount = [0u8; 32];
+    U256::from(2_000_000u64).to_big_endian(&mut amount);
+    let transfer = |contract: &str| test_event(contract, TRANSFER, &topics, amount.to_vec());
+    let (usdt, unknown) = (
+        "1111111111111111111111111111111111111111",
+        "2222222222222222222222222222222222222222",
+    );
+    let tx = TxInfo {
+        timestamp: Some(1_700_000_000),
+        description: Some(Transaction::default()),
+        events: Some(vec![transfer(usdt), transfer(unknown)]),
+        ..Default::default()
+    };
+    let mut cache = Cache::default();
+    cache
+        .token_decimals
+        .insert(String::from(usdt), (VisitNote::PriorSuccess, Some(6)));
+    cache
+        .token_decimals
+        .insert(String::from(unknown), (VisitNote::PriorFailure, None));
+    // Only the token with known decimals is included.
+    let found = movements(owner, &tx, &UserLabels::default(), &cache);
+    assert_eq!(found.len(), 1);
+    assert_eq!(found[0].asset, Asset::Token(H160::repeat_byte(0x11)));
+    assert_eq!(
+        (found[0].quantity, found[0].decimals),
+        (U256::from(2_000_000u64), 6)
+    );
+    assert!(found[0].incoming);
+}
@@ -0,0 +1,237 @@
+//! Activity of an address over time, as transactions per month.
+//!
+//! Months without activity between the first and last transaction are kept,
+//! so that quiet periods show as gaps. The timeline is rendered as an ASCII
+//! bar chart for the terminal, or as an HTML heatmap of years by months.
+//! Transactions without a timestamp (e.g., if blocks were not fetched) are
+//! counted separately.
+use std::{collections::BTreeMap, fmt::Display};
+
+use crate::{data::TxInfo, history::AddressHistory, stats::date_from_unix};
+
+/// Width of the longest bar in the ASCII chart.
+const BAR_WIDTH: usize = 50;
+
+/// Transactions per month.
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct Timeline {
+    /// Each month from the first to the last active month, in order.
+    pub months: Vec<MonthActivity>,
+    /// Transactions without a timestamp.
+    pub undated: usize,
+}
+
+/// Transactions in one month.
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct MonthActivity {
+    pub year: i64,
+    /// 1 to 12.
+    pub month: u32,
+    /// Transactions sent by the address.
+    pub sent: usize,
+    /// Transactions sent by others that involve the address.
+    pub received: usize,
+}
+
+impl Timeline {
+    pub fn new(history: &AddressHistory) -> Self {
+        Timeline::of_transactions(&history.address, &history.transactions)
+    }
+    /// Timeline of the transactions of an address ("0x" prefixed hex).
+    fn of_transactions(address: &str, transactions: &[TxInfo]) -> Self {
+        let owner = address.trim_start_matches("0x");
+        let mut counts: BTreeMap<(i64, u32), (usize, usize)> = BTreeMap::new();
+        let mut undated = 0;
+        for tx in transactions {
+            let Some(timestamp) = tx.timestamp else {
+                undated += 1;
+                continue;
+            };
+            let date = date_from_unix(timestamp);
+            let year = date[..4].parse().unwrap_or_default();
+            let month = date[5..7].parse().unwrap_or_default();
+            let entry = counts.entry((year, month)).or_default();
+            let sender = tx.description.as_ref().and_then(|d| d.from);
+            match sender.is_some_and(|s| hex::encode(s) == owner) {
+                true => entry.0 += 1,
+                false => entry.1 += 1,
+            }
+        }
+        let mut months = vec![];
+        if let (Some(first), Some(last)) = (counts.keys().next(), counts.keys().next_back()) {
+            let (mut year, mut month) = *first;
+            while (year, month) <= *last {
+                let (sent, received) = counts.get(&(year, month)).copied().unwrap_or_default();
+                months.push(MonthActivity {
+                    year,
+                    month,
+                    sent,
+                    received,
+                });
+                (year, month) = match month {
+                    12 => (year + 1, 1),
+                    _ => (year, month + 1),
+                };
+            }
+        }
+        Timeline { months, undated }
+    }
+    /// A standalone HTML page with a heatmap of the timeline (a row per
+    /// year, a column per month).
+    pub fn to_html(&self, address: &str) -> String {
+        let most = self.months.iter().map(|m| m.total()).max().unwrap_or(0);
+        let mut rows = String::new();
+        let mut years: Vec<i64> = self.months.iter().map(|m| m.year).collect();
+        years.dedup();
+        for year in years {
+            rows.push_str(&format!("<tr><th>{}</th>", year));
+            for month in 1..=12 {
+                let activity = self
+                    .months
+                    .iter()
+                    .find(|m| m.year == year && m.month == month);
+                let Some(m) = activity else {
+                    rows.push_str("<td class=\"none\"></td>");
+                    continue;
+                };
+                // Opacity from 0.1 (one transaction) to 1 (the busiest month).
+                let opacity = match m.total() {
+                    0 => 0.0,
+                    n => 0.1 + 0.9 * n as f64 / most as f64,
+                };
+                rows.push_str(&format!(
+                    "<td style=\"background: rgba(33, 110, 57, {:.2})\" title=\"{}: {} sent, {} received\">{}</td>",
+                    opacity,
+                    m.label(),
+                    m.sent,
+                    m.received,
+                    m.total()
+                ));
+            }
+            rows.push_str("</tr>\n");
+        }
+        let months: String = [
+            "Jan", "Feb", "Mar", "Apr", "May", "Jun", "Jul", "Aug", "Sep", "Oct", "Nov", "Dec",
+        ]
+        .iter()
+        .map(|m| format!("<th>{}</th>", m))
+        .collect();
+        format!(
+            r#"<!DOCTYPE html>
+<html>
+<head>
+<meta charset="utf-8">
+<title>Activity of {address}</title>
+<style>
+body {{ font-family: sans-serif; }}
+td {{ width: 3em; height: 2em; text-align: center; border: 1px solid #ddd; }}
+td.none {{ background: #f4f4f4; }}
+</style>
+</head>
+<body>
+<h1>Activity of {address}</h1>
+<p>Transactions per month (UTC). Hover over a month for sent and received counts.{undated}</p>
+<table>
+<tr><th></th>{months}</tr>
+{rows}</table>
+</body>
+</html>
+"#,
+            address = address,
+            undated = match self.undated {
+                0 => String::new(),
+                n => format!(" {} transactions without a timestamp are not shown.", n),
+            },
+            months = months,
+            rows = rows,
+        )
+    }
+}
+
+impl MonthActivity {
+    pub fn total(&self) -> usize {
+        self.sent + self.received
+    }
+    /// "YYYY-MM".
+    fn label(&self) -> String {
+        format!("{:04}-{:02}", self.year, self.month)
+    }
+}
+
+impl Display for Timeline {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "Transactions per month (UTC), sent by the address in brackets:"
+        )?;
+        let most = self.months.iter().map(|m| m.total()).max().unwrap_or(0);
+        for m in &self.months {
+            // Any activity shows at least one character.
+            let width = match m.total() {
+                0 => 0,
+                n => (n * BAR_WIDTH / most).max(1),
+            };
+            write!(
+                f,
+                "\n{}  {:<width$}  {}",
+                m.label(),
+                "#".repeat(width),
+                m.total(),
+                width = BAR_WIDTH
+            )?;
+            if m.sent > 0 {
+                write!(f, " ({})", m.sent)?;
+            }
+        }
+        if self.months.is_empty() {
+            write!(f, "\nNo transactions with a timestamp.")?;
+        }
+        if self.undated > 0 {
+            write!(f, "\nWithout a timestamp: {}", self.undated)?;
+        }
+        Ok(())
+    }
+}
+
+#[test]
+fn months_with_gaps() {
+    use web3::types::{Transaction, H160};
+
+    let owner = H160::repeat_byte(0xaa);
+    let tx = |timestamp: Option<u64>, from: H160| TxInfo {
+        timestamp,
+        description: Some(Transaction {
+            from: Some(from),
+            ..Default::default()
+        }),
+        ..Default::default()
+    };
+    let address = format!("0x{}", hex::encode(owner));
+    let transactions = vec![
+        // 2023-11-15, 2023-11-20, 2024-02-01
+        tx(Some(1_700_000_000), owner),
+        tx(Some(1_700_480_000), H160::zero()),
+        tx(Some(1_706_745_600), owner),
+        tx(None, owner),
+    ];
+    let timeline = Timeline::of_transactions(&address, &transactions);
+    let labels: Vec<String> = timeline.months.iter().map(|m| m.label()).collect();
+    assert_eq!(labels, ["2023-11", "2023-12", "2024-01", "2024-02"]);
+    assert_eq!(
+        (timeline.months[0].sent, timeline.months[0].received),
+        (1, 1)
+    );
+    assert_eq!(timeline.months[1].total(), 0);
+    assert_eq!(timeline.undated, 1);
+
+    let text = timeline.to_string();
+    let lines: Vec<&str> = text.lines().collect();
+    assert_eq!(lines[1], format!("2023-11  {}  2 (1)", "#".repeat(50)));
+    assert_eq!(lines[2], format!("2023-12  {}  0", " ".repeat(50)));
+    assert!(lines[4].starts_with(&format!("2024-02  {} ", "#".repeat(25))));
+    assert_eq!(lines[5], "Without a timestamp: 1");
+
+    let html = timeline.to_html(&address);
+    assert_eq!(html.matches("<tr><th>20").count(), 2);
+    assert!(html.contains("title=\"2023-11: 1 sent, 1 received\">2</td>"));
+}
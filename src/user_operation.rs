@@ -0,0 +1,246 @@
+//! Interprets ERC-4337 user operations, the transactions of smart accounts.
+//!
+//! A smart account never sends a transaction itself. A bundler calls
+//! `handleOps` on the EntryPoint with a batch of user operations, and the
+//! EntryPoint emits a `UserOperationEvent` for each one naming the account
+//! (sender) and the paymaster (if one paid the gas). The account appears in
+//! that event, so the transaction is in the history of the account, with the
+//! bundler as the sender.
+//!
+//! The operation is matched to the bundle by sender and nonce, and its
+//! calldata is decoded if the account uses the common `execute` or
+//! `executeBatch` functions.
+use web3::types::{H160, U256};
+
+use crate::{
+    abi::{data_word, dynamic_bytes, selector_of, topic_address, word_address},
+    semantics::{describe_call, ether_amount, Action, TxContext},
+};
+
+/// Emitted by the EntryPoint for each user operation in a bundle.
+pub const USER_OPERATION_EVENT: &str =
+    "UserOperationEvent(bytes32,address,address,uint256,bool,uint256,uint256)";
+
+/// handleOps of the v0.6 EntryPoint, with UserOperation structs.
+const HANDLE_OPS_V06: &str = "handleOps((address,uint256,bytes,bytes,uint256,uint256,uint256,uint256,uint256,bytes,bytes)[],address)";
+/// handleOps of the v0.7 EntryPoint, with PackedUserOperation structs.
+const HANDLE_OPS_V07: &str =
+    "handleOps((address,uint256,bytes,bytes,bytes32,uint256,bytes32,bytes,bytes)[],address)";
+/// Single call from a smart account.
+const EXECUTE: &str = "execute(address,uint256,bytes)";
+/// Batch of calls without value (e.g., SimpleAccount v0.6).
+const EXECUTE_BATCH: &str = "executeBatch(address[],bytes[])";
+/// Batch of calls with value (e.g., SimpleAccount v0.7).
+const EXECUTE_BATCH_WITH_VALUE: &str = "executeBatch(address[],uint256[],bytes[])";
+
+/// Recognises a user operation of the address (as the smart account) or
+/// paid for by the address (as the paymaster).
+pub fn user_operation_rule(context: &TxContext) -> Option<Action> {
+    let event = context
+        .events_with_signature(USER_OPERATION_EVENT)
+        .into_iter()
+        .find(|e| {
+            e.raw.topics.len() == 4
+                && (context.is_owner(&topic_address(&e.raw.topics[2]))
+                    || context.is_owner(&topic_address(&e.raw.topics[3])))
+        })?;
+    let sender = topic_address(&event.raw.topics[2]);
+    let paymaster = topic_address(&event.raw.topics[3]);
+    let data = &event.raw.data.0;
+    let nonce = data_word(data, 0)?;
+    let calls = context
+        .tx
+        .description
+        .as_ref()
+        .and_then(|desc| operation_calldata(&desc.input.0, &sender, nonce))
+        .map(|calldata| account_calls(context, calldata))
+        .unwrap_or_default();
+    Some(Action::UserOperation {
+        account: context.label(&sender),
+        paymaster: (!paymaster.is_zero()).then(|| context.label(&paymaster)),
+        success: data_word(data, 1)? == U256::one(),
+        fee: ether_amount(data_word(data, 2)?),
+        calls,
+    })
+}
+
+/// Finds the calldata of the user operation with the given sender and nonce
+/// in handleOps calldata.
+///
+/// Both struct versions start with sender, nonce, initCode and callData.
+fn operation_calldata<'a>(calldata: &'a [u8], sender: &H160, nonce: U256) -> Option<&'a [u8]> {
+    let selector = calldata.get(..4)?;
+    let args = &calldata[4..];
+    if selector != selector_of(HANDLE_OPS_V06) && selector != selector_of(HANDLE_OPS_V07) {
+        return None;
+    }
+    let (count, ops) = array(args, 0)?;
+    (0..count)
+        .filter_map(|i| ops.get(data_word(ops, i)?.try_into().ok()?..))
+        .find(|op| word_address(op, 0) == Some(*sender) && data_word(op, 1) == Some(nonce))
+        .and_then(|op| dynamic_bytes(op, 3))
+}
+
+/// Describes the calls made by a smart account, if it uses a common
+/// execute function.
+fn account_calls(context: &TxContext, calldata: &[u8]) -> Vec<String> {
+    let Some(selector) = calldata.get(..4) else {
+        return vec![];
+    };
+    let args = &calldata[4..];
+    let calls: Option<Vec<(H160, U256, &[u8])>> = if selector == selector_of(EXECUTE) {
+        execute(args)
+    } else if selector == selector_of(EXECUTE_BATCH) {
+        batch(args, None, 1)
+    } else if selector == selector_of(EXECUTE_BATCH_WITH_VALUE) {
+        batch(args, Some(1), 2)
+    } else {
+        None
+    };
+    match calls {
+        Some(calls) => calls
+            .iter()
+            .map(|(to, value, data)| describe_call(context, to, *value, data))
+            .collect(),
+        None => vec![format!("call {}", hex::encode(selector))],
+    }
+}
+
+/// Reads an execute call.
+fn execute(args: &[u8]) -> Option<Vec<(H160, U256, &[u8])>> {
+    Some(vec![(
+        word_address(args, 0)?,
+        data_word(args, 1)?,
+        dynamic_bytes(args, 2)?,
+    )])
+}
+
+/// Reads an executeBatch call from the arguments that hold the targets
+/// (always the first), values (if any) and calldata.
+fn batch(args: &[u8], values: Option<usize>, data: usize) -> Option<Vec<(H160, U256, &[u8])>> {
+    let (count, targets) = array(args, 0)?;
+    let (data_count, data) = array(args, data)?;
+    let values = match values {
+        Some(n) => Some(array(args, n)?),
+        None => None,
+    };
+    if data_count != count || values.is_some_and(|(n, _)| n != count) {
+        return None;
+    }
+    (0..count)
+        .map(|i| {
+            let value = match values {
+                Some((_, values)) => data_word(values, i)?,
+                None => U256::zero(),
+            };
+            Some((word_address(targets, i)?, value, dynamic_bytes(data, i)?))
+        })
+        .collect()
+}
+
+/// Reads the nth argument of ABI encoded data as a dynamic array, returning
+/// the number of items and the data that follows the length.
+fn array(data: &[u8], n: usize) -> Option<(usize, &[u8])> {
+    let offset: usize = data_word(data, n)?.try_into().ok()?;
+    let items = data.get(offset..)?;
+    let count: usize = data_word(items, 0)?.try_into().ok()?;
+    if count > items.len() / 32 {
+        return None;
+    }
+    Some((count, &items[32..]))
+}
+
+#[test]
+fn user_operation_with_execute() {
+    use web3::types::{Bytes, Transaction, H256};
+
+    use crate::{
+        data::TxInfo,
+        labels::UserLabels,
+        semantics::{short_address, test_event, RecognizerRegistry},
+    };
+
+    let bundler = H160::repeat_byte(0xbd);
+    let account = H160::repeat_byte(0xac);
+    let paymaster = H160::repeat_byte(0x9a);
+    let recipient = H160::repeat_byte(0xbb);
+    let entry_point = H160::repeat_byte(0xe4);
+    let word = |value: U256| {
+        let mut w = [0u8; 32];
+        value.to_big_endian(&mut w);
+        w.to_vec()
+    };
+    let address_word = |a: H160| H256::from(a).as_bytes().to_vec();
+    let padded = |bytes: &[u8]| {
+        let mut tail = word(bytes.len().into());
+        tail.extend(bytes);
+        tail.resize(tail.len() + (32 - bytes.len() % 32) % 32, 0);
+        tail
+    };
+    let nonce = U256::from(7);
+
+    // execute(recipient, 2 ETH, "")
+    let mut call_data = selector_of(EXECUTE).to_vec();
+    call_data.extend(address_word(recipient));
+    call_data.extend(word(U256::exp10(18) * 2));
+    call_data.extend(word((3 * 32).into()));
+    call_data.extend(padded(&[]));
+    // PackedUserOperation with empty initCode, paymasterAndData and signature.
+    let mut op = address_word(account);
+    op.extend(word(nonce));
+    let dynamic = [vec![], call_data, vec![], vec![]];
+    let mut tail = vec![];
+    let mut offsets = vec![];
+    for bytes in &dynamic {
+        offsets.push(9 * 32 + tail.len());
+        tail.extend(padded(bytes));
+    }
+    op.extend(word(offsets[0].into()));
+    op.extend(word(offsets[1].into()));
+    op.extend(vec![0; 3 * 32]);
+    op.extend(word(offsets[2].into()));
+    op.extend(word(offsets[3].into()));
+    op.extend(tail);
+    let mut input = selector_of(HANDLE_OPS_V07).to_vec();
+    input.extend(word((2 * 32).into()));
+    input.extend(address_word(bundler));
+    input.extend(word(1.into()));
+    input.extend(word(32.into()));
+    input.extend(op);
+
+    let mut event_data = word(nonce);
+    event_data.extend(word(1.into()));
+    event_data.extend(word(U256::exp10(15)));
+    event_data.extend(word(100_000.into()));
+    let tx = TxInfo {
+        description: Some(Transaction {
+            from: Some(bundler),
+            to: Some(entry_point),
+            input: Bytes(input),
+            ..Default::default()
+        }),
+        events: Some(vec![test_event(
+            &hex::encode(entry_point),
+            USER_OPERATION_EVENT,
+            &[H256::zero(), account.into(), paymaster.into()],
+            event_data,
+        )]),
+        ..Default::default()
+    };
+    let action = RecognizerRegistry::default()
+        .describe(
+            &hex::encode(account),
+            &tx,
+            &UserLabels::default(),
+            &Default::default(),
+        )
+        .unwrap();
+    assert_eq!(
+        action.to_string(),
+        format!(
+            "User operation from Self: transfer 2 ETH to {} (fee 0.001 ETH paid by {})",
+            short_address(&recipient),
+            short_address(&paymaster)
+        )
+    );
+}
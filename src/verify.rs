@@ -0,0 +1,322 @@
+//! Checks of data from a node that do not require trusting the node.
+//!
+//! A local-first explorer still depends on whatever node it is pointed at.
+//! These checks recompute values that the node also reports, so that a
+//! faulty or dishonest node is noticed rather than believed.
+//!
+//! ## Logs bloom
+//! A receipt includes a 2048 bit bloom filter of the addresses and topics of
+//! its logs. Recomputing it from the logs detects logs that were added,
+//! removed or altered after the receipt was created (unless the bloom was
+//! altered to match).
+//!
+//! ## Receipts root
+//! A block header commits to the receipts of all transactions in the block
+//! with the root of a Merkle Patricia trie keyed by transaction position.
+//! Fetching every receipt of the block and rebuilding the trie checks each
+//! receipt (and so each log) against the header. The header itself is
+//! taken from the node unless a header verifier is set (see `headers`).
+use std::fmt::Display;
+
+use anyhow::{anyhow, bail, Context, Result};
+use rlp::RlpStream;
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use web3::{
+    signing::keccak256,
+    types::{Block, Log, TransactionReceipt, H2048, H256, U256, U64},
+};
+
+use crate::{
+    headers::{header_hash, Verifier},
+    provider::Provider,
+};
+
+/// Whether the data of a transaction was checked against its block header.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
+pub enum Verification {
+    /// The receipt is part of the receipts root of the block.
+    Verified {
+        /// Source that confirmed the block hash, if any.
+        header: Option<String>,
+    },
+    /// The receipt could not be checked, or did not match (with the reason).
+    Unverified(String),
+}
+
+/// Computes the bloom filter of the addresses and topics of some logs.
+pub fn logs_bloom(logs: &[Log]) -> H2048 {
+    let mut bloom = H2048::zero();
+    for log in logs {
+        accrue(&mut bloom, log.address.as_bytes());
+        for topic in &log.topics {
+            accrue(&mut bloom, topic.as_bytes());
+        }
+    }
+    bloom
+}
+
+/// Sets the three bits selected by the hash of the item.
+///
+/// Each bit index is 11 bits from a pair of bytes of the hash, counted from
+/// the end of the filter.
+fn accrue(bloom: &mut H2048, item: &[u8]) {
+    let hash = keccak256(item);
+    let bytes = bloom.as_bytes_mut();
+    for pair in hash[..6].chunks(2) {
+        let bit = (usize::from(pair[0]) << 8 | usize::from(pair[1])) & 2047;
+        bytes[255 - bit / 8] |= 1 << (bit % 8);
+    }
+}
+
+/// Checks that the logs bloom of a receipt matches its logs.
+pub fn check_logs_bloom(receipt: &TransactionReceipt) -> Result<()> {
+    if logs_bloom(&receipt.logs) != receipt.logs_bloom {
+        bail!(
+            "Logs bloom of the receipt does not match its {} logs",
+            receipt.logs.len()
+        );
+    }
+    Ok(())
+}
+
+/// Gets every receipt of a block and checks them against the receipts root
+/// of the block header.
+///
+/// The header is checked against the block hash, and the block hash with the
+/// header verifier if there is one.
+///
+/// Uses eth_getBlockReceipts if the node supports it, otherwise the receipt
+/// of each transaction in the block.
+pub async fn verified_block_receipts(
+    provider: &Provider,
+    number: u64,
+    verifier: Option<&Verifier>,
+) -> Result<Vec<TransactionReceipt>> {
+    let (header, _) = provider
+        .request(
+            "eth_getBlockByNumber",
+            vec![json!(U64::from(number)), json!(false)],
+        )
+        .await?;
+    let block: Block<H256> =
+        serde_json::from_value(header.clone()).with_context(|| format!("No block {}", number))?;
+    let hash = block
+        .hash
+        .ok_or_else(|| anyhow!("Block {} has no hash", number))?;
+    if header_hash(&header)? != hash {
+        bail!("Header fields of block {} do not match its hash", number);
+    }
+    if let Some(verifier) = verifier {
+        verifier
+            .verify(number, hash)
+            .await
+            .with_context(|| format!("Header not confirmed by {}", verifier))?;
+    }
+    let fetched = provider.block_receipts(number).await.ok();
+    let receipts = match fetched.and_then(|(receipts, _)| receipts) {
+        Some(receipts) => receipts,
+        None => {
+            let mut receipts = vec![];
+            for hash in &block.transactions {
+                let (receipt, _) = provider.receipt(*hash).await?;
+                receipts.push(receipt.ok_or_else(|| anyhow!("No receipt for {:?}", hash))?);
+            }
+            receipts
+        }
+    };
+    let encoded = receipts
+        .iter()
+        .map(encode_receipt)
+        .collect::<Result<Vec<_>>>()?;
+    let root = ordered_trie_root(&encoded);
+    if root != block.receipts_root {
+        bail!(
+            "Receipts root {:?} of the {} receipts does not match block {} ({:?})",
+            root,
+            receipts.len(),
+            number,
+            block.receipts_root
+        );
+    }
+    Ok(receipts)
+}
+
+/// Encodes a receipt as it is stored in the receipts trie.
+///
+/// A typed receipt (EIP-2718) is the type byte followed by the RLP list.
+/// Receipts before Byzantium have a state root in place of the status.
+pub fn encode_receipt(receipt: &TransactionReceipt) -> Result<Vec<u8>> {
+    let mut stream = RlpStream::new_list(4);
+    match (receipt.status, receipt.root) {
+        (Some(status), _) => stream.append(&status.as_u64()),
+        (None, Some(root)) => stream.append(&root.as_bytes()),
+        (None, None) => bail!("Receipt has neither a status nor a state root"),
+    };
+    stream.append(&trimmed(receipt.cumulative_gas_used).as_slice());
+    stream.append(&receipt.logs_bloom.as_bytes());
+    stream.begin_list(receipt.logs.len());
+    for log in &receipt.logs {
+        stream.begin_list(3);
+        stream.append(&log.address.as_bytes());
+        stream.begin_list(log.topics.len());
+        for topic in &log.topics {
+            stream.append(&topic.as_bytes());
+        }
+        stream.append(&log.data.0);
+    }
+    let mut encoded = vec![];
+    if let Some(tx_type) = receipt.transaction_type.filter(|t| !t.is_zero()) {
+        encoded.push(tx_type.low_u64() as u8);
+    }
+    encoded.extend_from_slice(&stream.out());
+    Ok(encoded)
+}
+
+/// Big endian bytes of an integer without leading zeros, as RLP encodes it.
+pub fn trimmed(value: U256) -> Vec<u8> {
+    let mut bytes = [0u8; 32];
+    value.to_big_endian(&mut bytes);
+    let start = bytes.iter().position(|b| *b != 0).unwrap_or(32);
+    bytes[start..].to_vec()
+}
+
+/// Root of a Merkle Patricia trie keyed by the RLP encoded position of each
+/// value, as used for the transactions and receipts of a block.
+pub fn ordered_trie_root(values: &[Vec<u8>]) -> H256 {
+    let mut items: Vec<(Vec<u8>, &[u8])> = values
+        .iter()
+        .enumerate()
+        .map(|(i, value)| (nibbles(&rlp::encode(&i)), value.as_slice()))
+        .collect();
+    items.sort();
+    H256::from(keccak256(&trie_node(&items, 0)))
+}
+
+/// Splits bytes into 4 bit values.
+fn nibbles(bytes: &[u8]) -> Vec<u8> {
+    bytes.iter().flat_map(|b| [b >> 4, b & 0x0f]).collect()
+}
+
+/// Encodes the node for sorted (key nibbles, value) items whose keys share
+/// the first `depth` nibbles.
+fn trie_node(items: &[(Vec<u8>, &[u8])], depth: usize) -> Vec<u8> {
+    let [(first, value), rest @ ..] = items else {
+        return rlp::NULL_RLP.to_vec();
+    };
+    if rest.is_empty() {
+        let mut stream = RlpStream::new_list(2);
+        stream.append(&hex_prefix(&first[depth..], true));
+        stream.append(value);
+        return stream.out().to_vec();
+    }
+    // Length of the key suffix shared by all items.
+    let shared = rest
+        .iter()
+        .map(|(key, _)| {
+            first[depth..]
+                .iter()
+                .zip(&key[depth..])
+                .take_while(|(a, b)| a == b)
+                .count()
+        })
+        .min()
+        .unwrap_or_default();
+    if shared > 0 {
+        let mut stream = RlpStream::new_list(2);
+        stream.append(&hex_prefix(&first[depth..depth + shared], false));
+        append_child(&mut stream, trie_node(items, depth + shared));
+        return stream.out().to_vec();
+    }
+    let mut stream = RlpStream::new_list(17);
+    for nibble in 0..16 {
+        let branch: Vec<(Vec<u8>, &[u8])> = items
+            .iter()
+            .filter(|(key, _)| key.get(depth) == Some(&nibble))
+            .cloned()
+            .collect();
+        if branch.is_empty() {
+            stream.append_empty_data();
+        } else {
+            append_child(&mut stream, trie_node(&branch, depth + 1));
+        }
+    }
+    match items.iter().find(|(key, _)| key.len() == depth) {
+        Some((_, value)) => stream.append(value),
+        None => stream.append_empty_data(),
+    };
+    stream.out().to_vec()
+}
+
+/// Adds a child node, inline if it is shorter than a hash.
+fn append_child(stream: &mut RlpStream, node: Vec<u8>) {
+    match node.len() < 32 {
+        true => stream.append_raw(&node, 1),
+        false => stream.append(&keccak256(&node).as_slice()),
+    };
+}
+
+/// Compact encoding of a key path, with flags for odd length and for leaves.
+fn hex_prefix(path: &[u8], leaf: bool) -> Vec<u8> {
+    let flag = if leaf { 0x20 } else { 0x00 };
+    let (mut encoded, rest) = match path.len() % 2 {
+        1 => (vec![flag | 0x10 | path[0]], &path[1..]),
+        _ => (vec![flag], path),
+    };
+    encoded.extend(rest.chunks(2).map(|pair| pair[0] << 4 | pair[1]));
+    encoded
+}
+
+impl Display for Verification {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Verification::Verified { header: None } => {
+                write!(f, "verified against the block receipts root")
+            }
+            Verification::Verified {
+                header: Some(source),
+            } => write!(
+                f,
+                "verified against the block receipts root (header confirmed by {})",
+                source
+            ),
+            Verification::Unverified(reason) => write!(f, "unverified ({})", reason),
+        }
+    }
+}
+
+#[test]
+fn bloom_of_logs() {
+    use web3::types::H256;
+
+    let log = crate::semantics::test_event(&"11".repeat(20), "Ping()", &[], vec![]).raw;
+    let mut receipt = TransactionReceipt {
+        logs: vec![(*log).clone()],
+        ..Default::default()
+    };
+    assert!(check_logs_bloom(&receipt).is_err());
+    receipt.logs_bloom = logs_bloom(&receipt.logs);
+    // Three bits for each of the address and the topic (unless they collide).
+    let bits: u32 = receipt.logs_bloom.0.iter().map(|b| b.count_ones()).sum();
+    assert!((4..=6).contains(&bits));
+    assert_eq!(logs_bloom(&[]), H2048::zero());
+    assert!(check_logs_bloom(&receipt).is_ok());
+    receipt.logs[0].topics[0] = H256::repeat_byte(0x33);
+    assert!(check_logs_bloom(&receipt).is_err());
+}
+
+#[test]
+fn trie_roots() {
+    let root = |values: &[&str]| {
+        let values: Vec<Vec<u8>> = values.iter().map(|v| v.as_bytes().to_vec()).collect();
+        hex::encode(ordered_trie_root(&values))
+    };
+    assert_eq!(
+        root(&[]),
+        "56e81f171bcc55a6ff8345e692c0f86e5b48e01b996cadc001622fb5e363b421"
+    );
+    assert_eq!(
+        root(&["doe", "reindeer"]),
+        "e766d5d51b89dc39d981b41bda63248d7abce4f0225eefd023792a540bcffee3"
+    );
+}
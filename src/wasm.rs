@@ -0,0 +1,114 @@
+//! Bindings of the decoding core that take and return strings.
+//!
+//! Bytes are hex (with or without "0x") and results are JSON text, so that
+//! the functions can be called from JavaScript when built for wasm32.
+//! Errors are returned as a message.
+use serde_json::{json, Value};
+#[cfg(target_arch = "wasm32")]
+use wasm_bindgen::prelude::wasm_bindgen;
+
+use crate::{
+    abi::{matches_signature, selector_of, topic_of},
+    contract::{constructor_args, read_trailer, Trailer},
+    decode::{decode_constructor, CallShape, DecodedCall},
+};
+
+/// 4 byte selector of a function signature, as hex with "0x".
+#[cfg_attr(target_arch = "wasm32", wasm_bindgen)]
+pub fn selector(signature: &str) -> String {
+    format!("0x{}", hex::encode(selector_of(signature)))
+}
+
+/// Topic of an event signature, as hex with "0x".
+#[cfg_attr(target_arch = "wasm32", wasm_bindgen)]
+pub fn topic(signature: &str) -> String {
+    format!("{:?}", topic_of(signature))
+}
+
+/// Whether a text signature hashes to a selector or topic.
+#[cfg_attr(target_arch = "wasm32", wasm_bindgen)]
+pub fn match_signature(signature: &str, hash: &str) -> bool {
+    matches_signature(signature, hash)
+}
+
+/// Decodes calldata with a text signature if given (which must match the
+/// selector), otherwise by guessing the type of each argument.
+#[cfg_attr(target_arch = "wasm32", wasm_bindgen)]
+pub fn decode_calldata(calldata: &str, signature: Option<String>) -> Result<String, String> {
+    let calldata = bytes(calldata)?;
+    let Some(selector) = calldata.get(..4) else {
+        return Err(String::from("Calldata is shorter than a selector"));
+    };
+    if let Some(signature) = &signature {
+        if !matches_signature(signature, &hex::encode(selector)) {
+            return Err(format!(
+                "{} does not match selector 0x{}",
+                signature,
+                hex::encode(selector)
+            ));
+        }
+    }
+    let call = CallShape::new(signature, &calldata)
+        .decode(&calldata)
+        .ok_or_else(|| String::from("Could not decode calldata"))?;
+    Ok(call_json(&call).to_string())
+}
+
+/// Decodes the constructor arguments in the input of a contract creation,
+/// using the constructor in a JSON ABI if given.
+#[cfg_attr(target_arch = "wasm32", wasm_bindgen)]
+pub fn decode_creation(input: &str, abi: Option<String>) -> Result<String, String> {
+    let input = bytes(input)?;
+    let args = constructor_args(&input)
+        .ok_or_else(|| String::from("No metadata trailer in the creation input"))?;
+    Ok(call_json(&decode_constructor(args, abi.as_deref())).to_string())
+}
+
+/// Reads the metadata trailer of runtime bytecode: the compiler and where
+/// the source code metadata can be found.
+#[cfg_attr(target_arch = "wasm32", wasm_bindgen)]
+pub fn read_metadata(bytecode: &str) -> Result<String, String> {
+    let metadata = match read_trailer(&bytes(bytecode)?) {
+        Trailer::Metadata(metadata) => json!({
+            "source": metadata.source.as_ref().map(|s| s.to_string()),
+            "compiler": metadata.compiler_string(),
+            "experimental": metadata.experimental,
+        }),
+        Trailer::Absent => Value::Null,
+    };
+    Ok(metadata.to_string())
+}
+
+fn bytes(text: &str) -> Result<Vec<u8>, String> {
+    hex::decode(text.trim().trim_start_matches("0x")).map_err(|e| format!("Invalid hex ({})", e))
+}
+
+fn call_json(call: &DecodedCall) -> Value {
+    let params: Vec<Value> = call
+        .params
+        .iter()
+        .map(|p| json!({"type": p.kind.to_string(), "value": p.value}))
+        .collect();
+    json!({
+        "selector": call.selector,
+        "signature": call.signature,
+        "heuristic": call.heuristic,
+        "params": params,
+    })
+}
+
+#[test]
+fn decodes_from_hex() {
+    let recipient = "11".repeat(20);
+    let calldata = format!("0xa9059cbb{:0>64}{:0>64}", recipient, "0de0b6b3a7640000");
+    let signature = Some(String::from("transfer(address,uint256)"));
+    let decoded: Value =
+        serde_json::from_str(&decode_calldata(&calldata, signature).unwrap()).unwrap();
+    assert_eq!(decoded["signature"], "transfer(address,uint256)");
+    assert_eq!(decoded["params"][0]["type"], "address");
+    assert_eq!(decoded["params"].as_array().unwrap().len(), 2);
+    let wrong = Some(String::from("approve(address,uint256)"));
+    assert!(decode_calldata(&calldata, wrong).is_err());
+    assert!(decode_calldata("0xzz", None).is_err());
+    assert_eq!(read_metadata("0x6080").unwrap(), "null");
+}
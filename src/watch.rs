@@ -0,0 +1,410 @@
+//! Follows new blocks and decodes activity for an address as it happens.
+//!
+//! New blocks are found by polling the node. Each block is checked for
+//! transactions sent to or from the address, as well as logs that have the
+//! address as an indexed topic (e.g., token transfers received).
+//!
+//! Optionally, pending transactions in the node mempool are also checked so
+//! that inbound activity is flagged before it is included in a block.
+use std::{
+    collections::{BTreeSet, HashMap, HashSet},
+    fmt::Display,
+    path::PathBuf,
+    time::{Duration, Instant},
+};
+
+use anyhow::{anyhow, Result};
+use log::{debug, info, warn};
+use min_know::specs::address_appearance_index::AAIAppearanceTx;
+use web3::{
+    api::BaseFilter,
+    transports::Http,
+    types::{BlockNumber, FilterBuilder, Transaction, TransactionId, H160, H256},
+    Web3,
+};
+
+use crate::{
+    alerts::AlertRules,
+    data::TxInfo,
+    event_index::EventIndex,
+    export,
+    history::{AddressHistory, Mode, VisitNote},
+    provider::{unsupported, Capability, Provider},
+    redact::Pseudonyms,
+    render::{FormatOptions, TxView},
+    spam::SpamFilter,
+};
+
+/// Most blocks examined in one poll, to bound work after a long pause.
+const MAX_BLOCKS_PER_POLL: u64 = 100;
+
+/// Longest wait between polls when the node keeps failing.
+const MAX_BACKOFF: Duration = Duration::from_secs(300);
+
+/// How long a pending transaction that was alerted is remembered, so that it
+/// is not alerted again if announced again (e.g., by a new filter).
+const ALERT_MEMORY: Duration = Duration::from_secs(3600);
+
+/// Settings for watch mode.
+#[derive(Debug, Clone)]
+pub struct WatchOptions {
+    /// Time between checks for new blocks.
+    pub interval: Duration,
+    /// Mode used to decode new transactions.
+    pub mode: Mode,
+    /// SQLite database to append decoded transactions to.
+    pub sqlite: Option<PathBuf>,
+    /// Also check pending transactions (requires eth_newPendingTransactionFilter).
+    pub pending: bool,
+    /// How new transactions are printed.
+    pub format: FormatOptions,
+    /// Rules checked against each new transaction.
+    pub alerts: AlertRules,
+}
+
+/// How a pending transaction involves the watched address.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Involvement {
+    Sender,
+    Recipient,
+    /// The address appears somewhere in the calldata (e.g., a token transfer recipient).
+    Calldata,
+}
+
+/// A pending transaction that involves the watched address.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PendingAlert {
+    pub hash: H256,
+    pub involvement: Involvement,
+}
+
+/// Polls for new blocks until cancelled (Ctrl-C), printing decoded transactions that involve the address.
+///
+/// New transactions are appended to the history, sharing its cache.
+pub async fn watch(history: &mut AddressHistory, options: WatchOptions) -> Result<()> {
+    let provider = history.config.provider.clone();
+    if !provider.supports(Capability::Logs) {
+        return Err(unsupported(provider.name(), Capability::Logs)
+            .context("Watch mode needs to follow new blocks."));
+    }
+    let address: H160 = history.address.parse()?;
+    let (mut last_seen, _) = provider.head_block().await?;
+    info!(
+        "Watching {} for new activity from block {}",
+        history.address, last_seen
+    );
+    // Filters live on one node, so pending transactions are only checked there.
+    let mut pending_filter = if options.pending {
+        let (filter, url) = provider.pending_transactions_filter().await?;
+        info!("Checking pending transactions using {}", url);
+        Some(filter)
+    } else {
+        None
+    };
+    let mut alerted: HashMap<H256, Instant> = HashMap::new();
+    let cancel = history.config.cancel.clone();
+    // Polls in a row that failed, to back off while the node is unavailable.
+    let mut failures = 0;
+    loop {
+        tokio::select! {
+            _ = tokio::time::sleep(poll_delay(options.interval, failures)) => {}
+            _ = cancel.cancelled() => return Ok(()),
+        }
+        if let Some((web3, filter)) = &pending_filter {
+            alerted.retain(|_, at| at.elapsed() < ALERT_MEMORY);
+            match check_pending(web3, filter, &address, &mut alerted).await {
+                Ok(alerts) => {
+                    for alert in alerts {
+                        println!("\n{}", alert);
+                    }
+                }
+                // Nodes remove filters that are not polled for a while.
+                Err(e) => {
+                    warn!(
+                        "Could not check pending transactions, renewing the filter ({:#})",
+                        e
+                    );
+                    match provider.pending_transactions_filter().await {
+                        Ok((filter, _)) => pending_filter = Some(filter),
+                        Err(e) => warn!("Could not renew the filter, will retry ({:#})", e),
+                    }
+                }
+            }
+        }
+        let head = match provider.head_block().await {
+            Ok((head, _)) => head,
+            Err(e) => {
+                failures += 1;
+                warn!("Could not get the latest block, will retry ({:#})", e);
+                continue;
+            }
+        };
+        if head <= last_seen {
+            failures = 0;
+            continue;
+        }
+        let from = last_seen + 1;
+        let to = head.min(last_seen + MAX_BLOCKS_PER_POLL);
+        // The same blocks are tried again on the next poll.
+        let new_txs = match find_transactions(&provider, &address, from, to).await {
+            Ok(new_txs) => new_txs,
+            Err(e) => {
+                failures += 1;
+                warn!(
+                    "Could not check blocks {}-{}, will retry ({:#})",
+                    from, to, e
+                );
+                continue;
+            }
+        };
+        failures = 0;
+        last_seen = to;
+        if new_txs.is_empty() {
+            debug!("No activity in blocks {}-{}", from, to);
+            continue;
+        }
+        process_new(history, new_txs, &options).await?;
+    }
+}
+
+/// Time to wait before the next poll: the interval, doubled for each poll in
+/// a row that failed, up to `MAX_BACKOFF`.
+fn poll_delay(interval: Duration, failures: u32) -> Duration {
+    match failures {
+        0 => interval,
+        n => interval
+            .saturating_mul(2u32.saturating_pow(n.min(16)))
+            .min(MAX_BACKOFF.max(interval)),
+    }
+}
+
+/// Returns alerts for new pending transactions that involve the address.
+///
+/// Transactions that have already been alerted are skipped, and those
+/// alerted are noted with the time.
+async fn check_pending(
+    web3: &Web3<Http>,
+    filter: &BaseFilter<Http, H256>,
+    address: &H160,
+    alerted: &mut HashMap<H256, Instant>,
+) -> Result<Vec<PendingAlert>> {
+    let hashes = filter.poll().await?.unwrap_or_default();
+    let mut alerts = vec![];
+    for hash in hashes {
+        if alerted.contains_key(&hash) {
+            continue;
+        }
+        // The transaction may have been dropped or included since being announced.
+        let tx = match web3.eth().transaction(TransactionId::Hash(hash)).await {
+            Ok(Some(tx)) => tx,
+            Ok(None) => continue,
+            Err(e) => {
+                warn!("Could not fetch pending transaction {:?} ({})", hash, e);
+                continue;
+            }
+        };
+        if let Some(involvement) = involvement(&tx, address) {
+            alerted.insert(hash, Instant::now());
+            alerts.push(PendingAlert { hash, involvement });
+        }
+    }
+    Ok(alerts)
+}
+
+/// Determines if and how a transaction involves the address.
+fn involvement(tx: &Transaction, address: &H160) -> Option<Involvement> {
+    if tx.from == Some(*address) {
+        Some(Involvement::Sender)
+    } else if tx.to == Some(*address) {
+        Some(Involvement::Recipient)
+    } else if tx
+        .input
+        .0
+        .windows(20)
+        .any(|window| window == address.as_bytes())
+    {
+        Some(Involvement::Calldata)
+    } else {
+        None
+    }
+}
+
+/// Finds transactions in a block range that involve the address.
+async fn find_transactions(
+    provider: &Provider,
+    address: &H160,
+    from: u64,
+    to: u64,
+) -> Result<Vec<TxInfo>> {
+    // Logs with the address in any indexed position.
+    let address_topic = H256::from(*address);
+    let mut hashes: HashSet<H256> = HashSet::new();
+    for position in 1..=3 {
+        let mut topics = [None, None, None, None];
+        topics[position] = Some(vec![address_topic]);
+        let [t0, t1, t2, t3] = topics;
+        let filter = FilterBuilder::default()
+            .from_block(BlockNumber::Number(from.into()))
+            .to_block(BlockNumber::Number(to.into()))
+            .topics(t0, t1, t2, t3)
+            .build();
+        let (logs, _) = provider.logs(filter).await?;
+        for log in logs {
+            if let Some(hash) = log.transaction_hash {
+                hashes.insert(hash);
+            }
+        }
+    }
+
+    let mut found = vec![];
+    for number in from..=to {
+        let (block, url) = provider.block_with_txs(number).await?;
+        let block = block.ok_or_else(|| anyhow!("No block data for block {}.", number))?;
+        for (index, tx) in block.transactions.into_iter().enumerate() {
+            let involved =
+                tx.from == Some(*address) || tx.to == Some(*address) || hashes.contains(&tx.hash);
+            if !involved {
+                continue;
+            }
+            found.push(TxInfo {
+                location: AAIAppearanceTx {
+                    block: number as u32,
+                    index: index as u32,
+                },
+                description: Some(tx),
+                timestamp: Some(block.timestamp.as_u64()),
+                base_fee: block.base_fee_per_gas,
+                providers: BTreeSet::from([url.clone()]),
+                ..Default::default()
+            });
+        }
+    }
+    Ok(found)
+}
+
+/// Decodes new transactions, prints them and adds them to the history.
+async fn process_new(
+    history: &mut AddressHistory,
+    new_txs: Vec<TxInfo>,
+    options: &WatchOptions,
+) -> Result<()> {
+    let mut update = AddressHistory {
+        address: history.address.clone(),
+        transactions: new_txs,
+        config: history.config.clone(),
+        cache: std::mem::take(&mut history.cache),
+        failures: vec![],
+        warnings: vec![],
+        events: EventIndex::default(),
+        sources: None,
+    };
+    let result = async {
+        update
+            .get_receipts(None)
+            .await?
+            .decode_logs(None, options.mode)
+            .await?
+            .decode_calldata(None, options.mode)
+            .await
+            .interpret()
+            .await
+            .detect_spam();
+        anyhow::Ok(())
+    }
+    .await;
+    history.cache = std::mem::take(&mut update.cache);
+    result?;
+    if options.format.spam == SpamFilter::Hide {
+        update.hide_spam();
+    }
+
+    for tx in update.transactions.iter_mut() {
+        tx.chain = history.config.chain.clone();
+    }
+    let offset = history.transactions.len();
+    for (i, tx) in update.transactions.iter().enumerate() {
+        let view = TxView {
+            index: offset + i,
+            tx,
+            owner: &history.address,
+            labels: &history.config.labels,
+            explorer: history.config.explorer.as_ref(),
+            options: &options.format,
+        };
+        println!("\n{}", view);
+    }
+    if !options.alerts.is_empty() {
+        let owner: H160 = history.address.parse()?;
+        let names = |address: &H160| {
+            let tags = match history.cache.nametags.get(&hex::encode(address)) {
+                Some((VisitNote::PriorSuccess, tags)) => Some(tags.clone()),
+                _ => None,
+            };
+            let names = history.config.labels.merge(address, tags);
+            names
+                .unwrap_or_default()
+                .into_iter()
+                .filter(|n| !n.is_empty())
+                .collect()
+        };
+        for tx in &update.transactions {
+            for alert in options.alerts.check(tx, &owner, names) {
+                options.alerts.notify(&alert).await;
+            }
+        }
+    }
+    if let Some(path) = &options.sqlite {
+        export::to_sqlite(&update, path, &Pseudonyms::default())?;
+    }
+    history.events.extend(offset, &update.transactions);
+    history.transactions.append(&mut update.transactions);
+    history.failures.append(&mut update.failures);
+    history.warnings.append(&mut update.warnings);
+    Ok(())
+}
+
+impl Display for PendingAlert {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let role = match self.involvement {
+            Involvement::Sender => "sent by Self",
+            Involvement::Recipient => "sent to Self",
+            Involvement::Calldata => "mentions Self in calldata",
+        };
+        write!(
+            f,
+            "Pending transaction 0x{} {} (not yet included)",
+            hex::encode(self.hash),
+            role
+        )
+    }
+}
+
+#[test]
+fn pending_involvement() {
+    let address = H160::repeat_byte(0xaa);
+    let mut input = vec![0xa9, 0x05, 0x9c, 0xbb, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0];
+    input.extend_from_slice(address.as_bytes());
+    let tx = Transaction {
+        from: Some(H160::repeat_byte(0x11)),
+        to: Some(H160::repeat_byte(0x22)),
+        input: web3::types::Bytes(input),
+        ..Default::default()
+    };
+    assert_eq!(involvement(&tx, &address), Some(Involvement::Calldata));
+    assert_eq!(
+        involvement(&tx, &H160::repeat_byte(0x22)),
+        Some(Involvement::Recipient)
+    );
+    assert_eq!(involvement(&tx, &H160::repeat_byte(0x33)), None);
+}
+
+#[test]
+fn backoff_after_failed_polls() {
+    let interval = Duration::from_secs(12);
+    assert_eq!(poll_delay(interval, 0), interval);
+    assert_eq!(poll_delay(interval, 1), Duration::from_secs(24));
+    assert_eq!(poll_delay(interval, 3), Duration::from_secs(96));
+    assert_eq!(poll_delay(interval, 40), MAX_BACKOFF);
+    let slow = Duration::from_secs(600);
+    assert_eq!(poll_delay(slow, 2), slow);
+}